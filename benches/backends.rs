@@ -0,0 +1,85 @@
+//! Runs the standard workload of `oml_storage::run_workload` against the backends.
+//!
+//! `cargo bench --features bench-support`
+//!
+//! Set `OML_STORAGE_BENCH_DYNAMODB_TABLE` (and optionally `OML_STORAGE_BENCH_DYNAMODB_ENDPOINT`)
+//! to include DynamoDB, e.g. against a local DynamoDB.
+
+use color_eyre::eyre::Result;
+use oml_storage::run_workload;
+use oml_storage::BenchWorkload;
+use oml_storage::Storage;
+use oml_storage::StorageDisk;
+use oml_storage::StorageDiskPacked;
+use oml_storage::StorageItem;
+use oml_storage::StorageMemory;
+use serde::Deserialize;
+use serde::Serialize;
+use std::env;
+use std::path::Path;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct BenchItem {
+    round: usize,
+    payload: String,
+}
+
+impl StorageItem for BenchItem {
+    type ID = String;
+
+    fn serialize(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(&self)?)
+    }
+    fn deserialize(data: &[u8]) -> Result<Self> {
+        Ok(serde_json::from_slice(data)?)
+    }
+}
+
+fn make_item(round: usize) -> BenchItem {
+    BenchItem {
+        round,
+        payload: "x".repeat(1024),
+    }
+}
+
+async fn bench<S: Storage<BenchItem>>(name: &str, mut storage: S) -> Result<()> {
+    storage.ensure_storage_exists().await?;
+    let results = run_workload(&storage, &BenchWorkload::default(), make_item).await?;
+    println!("{name}");
+    for result in results {
+        println!("  {result}");
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let mut path = env::current_dir()?;
+    path.push("data");
+    path.push("bench");
+    let _ = std::fs::remove_dir_all(&path);
+
+    bench("memory", StorageMemory::default()).await?;
+    bench(
+        "disk",
+        StorageDisk::new(&path.join("disk"), Path::new("json")).await,
+    )
+    .await?;
+    bench(
+        "disk_packed",
+        StorageDiskPacked::new(&path.join("packed.log")).await,
+    )
+    .await?;
+
+    #[cfg(feature = "dynamo-db")]
+    if let Ok(table_name) = env::var("OML_STORAGE_BENCH_DYNAMODB_TABLE") {
+        let mut storage = oml_storage::StorageDynamoDb::new(&table_name).await;
+        if let Ok(url) = env::var("OML_STORAGE_BENCH_DYNAMODB_ENDPOINT") {
+            storage.set_endpoint_url(&url)?;
+        }
+        bench("dynamodb", storage).await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,14 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    grpc();
+}
+
+/// Generates the code for `proto/oml_storage.proto`, with the vendored `protoc`, so none needs to be installed.
+#[cfg(feature = "grpc")]
+fn grpc() {
+    let protoc = protoc_bin_vendored::protoc_bin_path().expect("vendored protoc");
+    std::env::set_var("PROTOC", protoc);
+    tonic_prost_build::configure()
+        .compile_protos(&["proto/oml_storage.proto"], &["proto"])
+        .expect("can compile proto/oml_storage.proto");
+}
@@ -0,0 +1,139 @@
+use crate::StorageError;
+use crate::StorageItem;
+use crate::StorageMiddleware;
+use crate::StorageOperation;
+use crate::StorageRequest;
+use async_trait::async_trait;
+use color_eyre::eyre::Result;
+
+/// The answer of an [AccessPolicy].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Allow,
+    Deny,
+}
+
+/// Decides which operations may run, e.g. to keep tenants of a server apart.
+///
+/// Enforced via [crate::StorageWithMiddleware::add_access_policy] before the operation
+/// reaches the backend. Denied operations fail with [StorageError::PermissionDenied].
+pub trait AccessPolicy<ITEM: StorageItem>: Send + Sync + std::fmt::Debug {
+    /// Called once per id of the operation,
+    /// and with `None` for operations on the whole storage, e.g. [crate::Storage::all_ids].
+    /// `who` is the lock holder, for operations that take or use a lock.
+    fn allow(&self, op: StorageOperation, id: Option<&ITEM::ID>, who: Option<&str>) -> Decision;
+}
+
+/// Runs an [AccessPolicy] as [StorageMiddleware].
+#[derive(Debug)]
+pub(crate) struct AccessPolicyMiddleware<P>(pub P);
+
+impl<P> AccessPolicyMiddleware<P> {
+    fn check<ITEM: StorageItem>(
+        &self,
+        request: &StorageRequest<'_, ITEM>,
+        id: Option<&ITEM::ID>,
+    ) -> Result<()>
+    where
+        P: AccessPolicy<ITEM>,
+    {
+        match self.0.allow(request.operation(), id, request.who()) {
+            Decision::Allow => Ok(()),
+            Decision::Deny => Err(StorageError::PermissionDenied {
+                operation: request.operation(),
+                id: id.map(|id| id.to_string()),
+                who: request.who().map(String::from),
+            }
+            .into()),
+        }
+    }
+}
+
+#[async_trait]
+impl<ITEM: StorageItem, P: AccessPolicy<ITEM>> StorageMiddleware<ITEM>
+    for AccessPolicyMiddleware<P>
+{
+    async fn before(&self, request: &StorageRequest<'_, ITEM>) -> Result<()> {
+        if request.ids().is_empty() {
+            return self.check(request, None);
+        }
+        for id in request.ids() {
+            self.check(request, Some(*id))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::AccessPolicy;
+    use crate::Decision;
+    use crate::Storage;
+    use crate::StorageError;
+    use crate::StorageItem;
+    use crate::StorageNull;
+    use crate::StorageOperation;
+    use crate::StorageWithMiddleware;
+    use color_eyre::Result;
+
+    #[derive(Default, Debug)]
+    struct TestItem {}
+
+    impl StorageItem for TestItem {
+        type ID = String;
+
+        fn serialize(&self) -> Result<Vec<u8>> {
+            Ok(Vec::new())
+        }
+        fn deserialize(_: &[u8]) -> Result<Self> {
+            Ok(Self {})
+        }
+    }
+
+    /// Only allows access to items of one tenant, with ids like `{tenant}/{item}`
+    #[derive(Debug)]
+    struct TenantPolicy {
+        tenant: &'static str,
+    }
+
+    impl AccessPolicy<TestItem> for TenantPolicy {
+        fn allow(&self, op: StorageOperation, id: Option<&String>, _who: Option<&str>) -> Decision {
+            match id {
+                Some(id) if op != StorageOperation::ForceUnlock => {
+                    if id.starts_with(&format!("{}/", self.tenant)) {
+                        Decision::Allow
+                    } else {
+                        Decision::Deny
+                    }
+                }
+                _ => Decision::Deny,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn it_enforces_access_policy() -> Result<()> {
+        let mut storage = StorageWithMiddleware::new(StorageNull::<TestItem>::default());
+        storage.add_access_policy(TenantPolicy { tenant: "a" })?;
+
+        let own = String::from("a/1");
+        let other = String::from("b/1");
+        let (lock, item) = storage.lock(&own, "handler").await?.success()?;
+        storage.save_and_unlock(&own, &item, lock).await?;
+
+        let e = storage.lock(&other, "handler").await.unwrap_err();
+        assert_eq!(
+            Some(&StorageError::PermissionDenied {
+                operation: StorageOperation::Lock,
+                id: Some(other.clone()),
+                who: Some(String::from("handler")),
+            }),
+            e.downcast_ref::<StorageError>()
+        );
+        assert!(storage.force_unlock(&own).await.is_err());
+        assert!(storage.all_ids().await.is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,236 @@
+use crate::RequestContext;
+use crate::StorageItem;
+use crate::StorageMiddleware;
+use crate::StorageOperation;
+use crate::StorageRequest;
+use async_trait::async_trait;
+use chrono::DateTime;
+use chrono::Utc;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Report;
+use color_eyre::eyre::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use std::path::Path;
+use std::path::PathBuf;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum AuditOutcome {
+    /// Recorded before the operation runs
+    Attempted,
+    Succeeded,
+    Failed {
+        error: String,
+    },
+}
+
+/// One entry of the audit log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub when: DateTime<Utc>,
+    /// The actor given to [crate::StorageWithMiddleware::add_audit_sink]
+    pub who: String,
+    /// The lock holder, for operations that use a lock
+    pub lock_who: Option<String>,
+    pub operation: StorageOperation,
+    pub ids: Vec<String>,
+    #[serde(flatten)]
+    pub outcome: AuditOutcome,
+    /// The holder of the lock removed by [crate::Storage::force_unlock]
+    #[serde(default)]
+    pub displaced_lock_who: Option<String>,
+    /// When the lock removed by [crate::Storage::force_unlock] was taken
+    #[serde(default)]
+    pub displaced_lock_when: Option<DateTime<Utc>>,
+    /// See [crate::RequestContext::scope]
+    #[serde(default)]
+    pub context: Option<RequestContext>,
+}
+
+/// Receives the [AuditRecord]s of destructive operations, see [StorageOperation::is_destructive].
+///
+/// Sinks must only ever append.
+#[async_trait]
+pub trait AuditSink: Send + Sync + std::fmt::Debug {
+    async fn record(&self, record: &AuditRecord) -> Result<()>;
+}
+
+/// Appends records as JSON lines to a file, synced after every record.
+#[derive(Debug)]
+pub struct FileAuditSink {
+    path: PathBuf,
+    write_lock: Mutex<()>,
+}
+
+impl FileAuditSink {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            write_lock: Mutex::new(()),
+        }
+    }
+}
+
+#[async_trait]
+impl AuditSink for FileAuditSink {
+    async fn record(&self, record: &AuditRecord) -> Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+
+        let _write_lock = self.write_lock.lock().await;
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .map_err(|e| eyre!("Can't open audit log {:?} -> {e:?}", &self.path))?;
+        file.write_all(&line).await?;
+        file.sync_data().await?;
+
+        Ok(())
+    }
+}
+
+/// Runs an [AuditSink] as [StorageMiddleware].
+///
+/// Destructive operations are vetoed if the attempt can't be recorded,
+/// failing to record the outcome is only logged.
+#[derive(Debug)]
+pub(crate) struct AuditMiddleware<S> {
+    pub sink: S,
+    pub who: String,
+}
+
+impl<S: AuditSink> AuditMiddleware<S> {
+    fn record<ITEM: StorageItem>(
+        &self,
+        request: &StorageRequest<'_, ITEM>,
+        outcome: AuditOutcome,
+    ) -> AuditRecord {
+        AuditRecord {
+            when: Utc::now(),
+            who: self.who.clone(),
+            lock_who: request.who().map(String::from),
+            operation: request.operation(),
+            ids: request.ids().iter().map(|id| id.to_string()).collect(),
+            outcome,
+            displaced_lock_who: request.displaced_lock().map(|l| l.who().to_string()),
+            displaced_lock_when: request.displaced_lock().map(|l| *l.when()),
+            context: request.context().cloned(),
+        }
+    }
+}
+
+#[async_trait]
+impl<ITEM: StorageItem, S: AuditSink> StorageMiddleware<ITEM> for AuditMiddleware<S> {
+    async fn before(&self, request: &StorageRequest<'_, ITEM>) -> Result<()> {
+        if !request.operation().is_destructive() {
+            return Ok(());
+        }
+        let record = self.record(request, AuditOutcome::Attempted);
+        self.sink
+            .record(&record)
+            .await
+            .map_err(|e| eyre!("Can't audit {} -> {e:?}", request.operation()))
+    }
+
+    async fn after(&self, request: &StorageRequest<'_, ITEM>, error: Option<&Report>) {
+        if !request.operation().is_destructive() {
+            return;
+        }
+        let outcome = match error {
+            Some(error) => AuditOutcome::Failed {
+                error: error.to_string(),
+            },
+            None => AuditOutcome::Succeeded,
+        };
+        let record = self.record(request, outcome);
+        if let Err(e) = self.sink.record(&record).await {
+            tracing::error!("Can't audit {record:?} -> {e:?}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::AuditOutcome;
+    use crate::AuditRecord;
+    use crate::FileAuditSink;
+    use crate::RequestContext;
+    use crate::Storage;
+    use crate::StorageItem;
+    use crate::StorageMemory;
+    use crate::StorageOperation;
+    use crate::StorageWithMiddleware;
+    use color_eyre::Result;
+    use std::env;
+
+    #[derive(Default, Debug)]
+    struct TestItem {}
+
+    impl StorageItem for TestItem {
+        type ID = String;
+
+        fn serialize(&self) -> Result<Vec<u8>> {
+            Ok(Vec::new())
+        }
+        fn deserialize(_: &[u8]) -> Result<Self> {
+            Ok(Self {})
+        }
+    }
+
+    #[tokio::test]
+    async fn it_audits_destructive_operations() -> Result<()> {
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_audit");
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path)?;
+        path.push("audit.jsonl");
+
+        let mut storage = StorageWithMiddleware::new(StorageMemory::<TestItem>::default());
+        storage.add_audit_sink(FileAuditSink::new(&path), "admin")?;
+
+        let id = String::from("1");
+        let (lock, item) = storage.lock(&id, "tester").await?.success()?;
+        storage.save(&id, &item, &lock).await?;
+        let context = RequestContext {
+            tenant_id: Some(String::from("tenant-a")),
+            trace_id: Some(String::from("trace-1")),
+            caller: Some(String::from("support-tool")),
+            ..Default::default()
+        };
+        let displaced = context
+            .clone()
+            .scope(storage.force_unlock(&id))
+            .await?
+            .expect("was locked");
+        assert_eq!(lock, displaced);
+        assert_eq!(None, RequestContext::current());
+
+        let records = std::fs::read_to_string(&path)?
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<Vec<AuditRecord>, _>>()?;
+        assert_eq!(2, records.len());
+        for (record, outcome) in records
+            .iter()
+            .zip([AuditOutcome::Attempted, AuditOutcome::Succeeded])
+        {
+            assert_eq!("admin", record.who);
+            assert_eq!(StorageOperation::ForceUnlock, record.operation);
+            assert_eq!(vec![id.clone()], record.ids);
+            assert_eq!(outcome, record.outcome);
+            assert_eq!(Some(&context), record.context.as_ref());
+        }
+        assert_eq!(None, records[0].displaced_lock_who);
+        assert_eq!(Some(String::from("tester")), records[1].displaced_lock_who);
+        assert_eq!(Some(*lock.when()), records[1].displaced_lock_when);
+
+        Ok(())
+    }
+}
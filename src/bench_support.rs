@@ -0,0 +1,191 @@
+use crate::Storage;
+use crate::StorageItem;
+use crate::StorageOperation;
+use color_eyre::eyre::Result;
+use std::time::Duration;
+use std::time::Instant;
+
+/// The standard workload of [run_workload].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BenchWorkload {
+    /// Number of items created
+    pub items: usize,
+    /// Every round locks, saves, and unlocks each item once, then loads each item once
+    pub rounds: usize,
+    /// Page size for the final [Storage::scan_ids]
+    pub scan_limit: usize,
+}
+
+impl Default for BenchWorkload {
+    fn default() -> Self {
+        Self {
+            items: 100,
+            rounds: 5,
+            scan_limit: 25,
+        }
+    }
+}
+
+/// The latencies of all calls of one operation.
+#[derive(Debug, Clone)]
+pub struct BenchResult {
+    pub operation: StorageOperation,
+    /// Sorted, shortest first
+    latencies: Vec<Duration>,
+}
+
+impl BenchResult {
+    fn new(operation: StorageOperation, mut latencies: Vec<Duration>) -> Self {
+        latencies.sort_unstable();
+        Self {
+            operation,
+            latencies,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.latencies.len()
+    }
+
+    pub fn total(&self) -> Duration {
+        self.latencies.iter().sum()
+    }
+
+    /// Calls per second, if they ran one after another.
+    pub fn throughput(&self) -> f64 {
+        self.count() as f64 / self.total().as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// The latency below which `percent` of the calls finished, e.g. `50.0` for the median.
+    pub fn percentile(&self, percent: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        let rank = (percent / 100.0 * self.count() as f64).ceil() as usize;
+        self.latencies[rank.clamp(1, self.count()) - 1]
+    }
+}
+
+impl std::fmt::Display for BenchResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:<8} {:>6} calls {:>10.0}/s  p50 {:>10.3?}  p99 {:>10.3?}",
+            self.operation.name(),
+            self.count(),
+            self.throughput(),
+            self.percentile(50.0),
+            self.percentile(99.0)
+        )
+    }
+}
+
+/// Runs the standard `workload` against `storage` and returns the results for
+/// lock, save, unlock, load, and scan.
+///
+/// `make_item` builds the item saved in round `n`.
+/// The storage must be ready, i.e. [Storage::ensure_storage_exists] was called.
+pub async fn run_workload<ITEM, S>(
+    storage: &S,
+    workload: &BenchWorkload,
+    make_item: impl Fn(usize) -> ITEM,
+) -> Result<Vec<BenchResult>>
+where
+    ITEM: StorageItem + Send,
+    S: Storage<ITEM>,
+{
+    let mut ids = Vec::with_capacity(workload.items);
+    for _ in 0..workload.items {
+        ids.push(storage.create().await?);
+    }
+
+    let mut lock_latencies = Vec::new();
+    let mut save_latencies = Vec::new();
+    let mut unlock_latencies = Vec::new();
+    let mut load_latencies = Vec::new();
+    for round in 0..workload.rounds {
+        let item = make_item(round);
+        for id in ids.iter() {
+            let start = Instant::now();
+            let (lock, _) = storage.lock(id, "bench").await?.success()?;
+            lock_latencies.push(start.elapsed());
+
+            let start = Instant::now();
+            storage.save(id, &item, &lock).await?;
+            save_latencies.push(start.elapsed());
+
+            let start = Instant::now();
+            storage.unlock(id, lock).await?;
+            unlock_latencies.push(start.elapsed());
+        }
+        for id in ids.iter() {
+            let start = Instant::now();
+            storage.load(id).await?;
+            load_latencies.push(start.elapsed());
+        }
+    }
+
+    let mut scan_latencies = Vec::new();
+    let mut scan_pos = None;
+    loop {
+        let start = Instant::now();
+        let page = storage
+            .scan_ids(scan_pos.as_deref(), Some(workload.scan_limit))
+            .await?;
+        scan_latencies.push(start.elapsed());
+        scan_pos = page.cursor;
+        if scan_pos.is_none() {
+            break;
+        }
+    }
+
+    Ok(vec![
+        BenchResult::new(StorageOperation::Lock, lock_latencies),
+        BenchResult::new(StorageOperation::Save, save_latencies),
+        BenchResult::new(StorageOperation::Unlock, unlock_latencies),
+        BenchResult::new(StorageOperation::Load, load_latencies),
+        BenchResult::new(StorageOperation::ScanIds, scan_latencies),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::run_workload;
+    use crate::BenchWorkload;
+    use crate::StorageItem;
+    use crate::StorageMemory;
+    use crate::StorageOperation;
+    use color_eyre::Result;
+
+    #[derive(Default, Debug)]
+    struct TestItem {}
+
+    impl StorageItem for TestItem {
+        type ID = String;
+
+        fn serialize(&self) -> Result<Vec<u8>> {
+            Ok(b"{}".to_vec())
+        }
+        fn deserialize(_: &[u8]) -> Result<Self> {
+            Ok(Self {})
+        }
+    }
+
+    #[tokio::test]
+    async fn it_runs_the_workload() -> Result<()> {
+        let storage = StorageMemory::<TestItem>::default();
+        let workload = BenchWorkload {
+            items: 10,
+            rounds: 2,
+            scan_limit: 4,
+        };
+        let results = run_workload(&storage, &workload, |_| TestItem {}).await?;
+
+        assert_eq!(StorageOperation::Lock, results[0].operation);
+        assert_eq!(20, results[0].count());
+        assert_eq!(3, results[4].count());
+        assert!(results[0].percentile(50.0) <= results[0].percentile(99.0));
+
+        Ok(())
+    }
+}
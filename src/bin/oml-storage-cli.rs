@@ -0,0 +1,721 @@
+//! Inspect and administrate storages from the command line, e.g.
+//!
+//! `oml-storage-cli --backend disk --path data/items list`
+//!
+//! All backend options can also come from the environment, e.g.
+//!
+//! `OML_STORAGE_BACKEND=dynamodb OML_STORAGE_TABLE=items oml-storage-cli bench --tasks 16`
+//!
+//! `oml-storage-cli list --query 'id prefix "guild:" AND modified > 2024-01-01'`
+//!
+//! Items are handled as raw serialized bytes, so any item type works.
+
+use clap::Parser;
+use clap::Subcommand;
+use clap::ValueEnum;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use futures_util::TryStreamExt;
+use oml_storage::export::decode_record;
+use oml_storage::export::encode_record;
+use oml_storage::export::ExportFormat;
+use oml_storage::paginate;
+use oml_storage::Condition;
+#[cfg(feature = "wipe")]
+use oml_storage::DeleteOptions;
+#[cfg(feature = "wipe")]
+use oml_storage::DeleteOutcome;
+use oml_storage::FileAuditSink;
+use oml_storage::FsckOptions;
+#[cfg(feature = "wipe")]
+use oml_storage::IdFilter;
+use oml_storage::LockResult;
+use oml_storage::Progress;
+use oml_storage::ProgressSink;
+use oml_storage::ProgressTracker;
+use oml_storage::Query;
+use oml_storage::Storage;
+use oml_storage::StorageDisk;
+use oml_storage::StorageDiskPacked;
+use oml_storage::StorageDynamoDb;
+use oml_storage::StorageItem;
+use oml_storage::StorageWithMiddleware;
+#[cfg(feature = "wipe")]
+use oml_storage::WipeOptions;
+use std::collections::HashSet;
+use std::io::BufRead;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::Instant;
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Backend {
+    Disk,
+    DiskPacked,
+    Dynamodb,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Format {
+    /// Our own JSON lines, with base64 encoded data
+    Jsonl,
+    /// DynamoDB JSON, one item per line
+    DynamodbJson,
+    /// The (decompressed) files of a DynamoDB table export to S3
+    S3Export,
+}
+
+impl From<Format> for ExportFormat {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Jsonl => ExportFormat::JsonLines,
+            Format::DynamodbJson => ExportFormat::DynamoDbJson,
+            Format::S3Export => ExportFormat::S3Export,
+        }
+    }
+}
+
+#[derive(Debug, Parser)]
+#[command(name = "oml-storage-cli", version, about)]
+struct Cli {
+    #[arg(long, env = "OML_STORAGE_BACKEND", value_enum, default_value_t = Backend::Disk)]
+    backend: Backend,
+    /// The folder for `disk`, the log file for `disk-packed`
+    #[arg(long, env = "OML_STORAGE_PATH")]
+    path: Option<PathBuf>,
+    /// The item file extension for `disk`
+    #[arg(long, env = "OML_STORAGE_EXTENSION", default_value = "json")]
+    extension: PathBuf,
+    /// The table for `dynamodb`
+    #[arg(long, env = "OML_STORAGE_TABLE")]
+    table: Option<String>,
+    /// e.g. for a local DynamoDB
+    #[arg(long, env = "OML_STORAGE_ENDPOINT_URL")]
+    endpoint_url: Option<String>,
+    #[arg(long, env = "OML_STORAGE_REGION")]
+    region: Option<String>,
+    /// Keeps items apart from others, a subfolder for `disk`, a key prefix for `dynamodb`
+    #[arg(long, env = "OML_STORAGE_NAMESPACE")]
+    namespace: Option<String>,
+    /// Used as lock holder and in the audit log
+    #[arg(long, env = "OML_STORAGE_WHO", default_value = "oml-storage-cli")]
+    who: String,
+    /// Appends force unlocks, wipes, and deletes to this file
+    #[arg(long)]
+    audit_log: Option<PathBuf>,
+    /// Prints the progress of `export`, `import`, and `wipe` to stderr
+    #[arg(long)]
+    progress: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Lists all ids, optionally only those starting with `prefix`
+    List {
+        prefix: Option<String>,
+        /// Only items matching this, e.g. `id prefix "guild:" AND modified > 2024-01-01`
+        #[arg(long)]
+        query: Option<Query>,
+    },
+    /// Prints the serialized item
+    Show { id: String },
+    /// Shows who holds the lock and since when
+    LockStatus { id: String },
+    /// Removes the lock, whoever holds it
+    ForceUnlock { id: String },
+    /// Prints the stored size of the item in bytes
+    Size { id: String },
+    /// Prints the item count, sizes, and capacity units of all items as JSON, visiting every item
+    Cost,
+    /// Checks all items and locks, prints the report as JSON, and fails if anomalies remain
+    Fsck {
+        /// Removes orphaned locks and dangling links
+        #[arg(long)]
+        repair: bool,
+        /// Locks older than this many seconds are stale
+        #[arg(long, default_value_t = 3600)]
+        max_lock_age: u64,
+        /// Checks the links via this relation for missing items, can be repeated
+        #[arg(long = "link-relation")]
+        link_relations: Vec<String>,
+    },
+    /// Writes all items as JSON lines, to stdout if no file is given
+    Export {
+        file: Option<PathBuf>,
+        #[arg(long, value_enum, default_value_t = Format::Jsonl)]
+        format: Format,
+        /// Only items matching this, see `list`
+        #[arg(long)]
+        query: Option<Query>,
+    },
+    /// Saves all items from a file written by `export` or by AWS tooling
+    Import {
+        file: PathBuf,
+        #[arg(long, value_enum, default_value_t = Format::Jsonl)]
+        format: Format,
+    },
+    /// Lets `tasks` tasks fight over the lock of one item, and prints a latency summary.
+    ///
+    /// Every successful lock increments a counter in the item, so lost updates fail the run,
+    /// e.g. as a smoke test for a new backend.
+    Bench {
+        #[arg(long, default_value_t = 8)]
+        tasks: usize,
+        /// Successful locks per task
+        #[arg(long, default_value_t = 10)]
+        rounds: usize,
+        /// The item fought over, overwritten by the run
+        #[arg(long, default_value = "oml-storage-cli-bench")]
+        id: String,
+    },
+    /// Lets `writers` tasks lock and increment counters in `ids` items, while `readers` tasks load them.
+    ///
+    /// Fails on lost updates and on two writers holding the same lock, and prints contention statistics,
+    /// e.g. as a regression test for locking. Counters going backwards are only counted,
+    /// eventually consistent reads can see them.
+    Stress {
+        #[arg(long, default_value_t = 8)]
+        writers: usize,
+        #[arg(long, default_value_t = 2)]
+        readers: usize,
+        /// How many items the writers spread over, 1 makes all of them fight over one lock
+        #[arg(long, default_value_t = 1)]
+        ids: usize,
+        /// Successful locks per writer
+        #[arg(long, default_value_t = 10)]
+        rounds: usize,
+        /// The items are this, followed by their index, overwritten by the run
+        #[arg(long, default_value = "oml-storage-cli-stress-")]
+        id_prefix: String,
+    },
+    #[cfg(feature = "wipe")]
+    /// Removes all items or only those with the given prefix
+    Wipe {
+        /// Must be "Yes, I know what I am doing!", unless this is a dry run
+        #[arg(long, required_unless_present = "dry_run")]
+        confirm: Option<String>,
+        /// Only show what would be removed
+        #[arg(long)]
+        dry_run: bool,
+        /// Only remove items whose id starts with this
+        #[arg(long)]
+        prefix: Option<String>,
+    },
+    #[cfg(feature = "wipe")]
+    /// Removes the given items and prints what happened to each
+    Delete {
+        #[arg(required = true)]
+        ids: Vec<String>,
+        /// Removes locked items too
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+/// Any item, as its serialized bytes, only used via [Storage::load_raw] and [Storage::save_raw]
+#[derive(Debug, Default)]
+struct RawItem(Vec<u8>);
+
+impl StorageItem for RawItem {
+    type ID = String;
+
+    fn serialize(&self) -> Result<Vec<u8>> {
+        Ok(self.0.clone())
+    }
+    fn deserialize(data: &[u8]) -> Result<Self> {
+        Ok(Self(data.to_vec()))
+    }
+}
+
+const SCAN_LIMIT: usize = 100;
+
+async fn all_ids<S: Storage<RawItem>>(storage: &S, prefix: &str) -> Result<Vec<String>> {
+    paginate(storage)
+        .with_prefix(prefix)
+        .with_page_size(SCAN_LIMIT)
+        .ids()
+        .try_collect()
+        .await
+}
+
+async fn matching_ids<S: Storage<RawItem>>(storage: &S, query: &Query) -> Result<Vec<String>> {
+    query
+        .stream(storage, SCAN_LIMIT)
+        .map_ok(|summary| summary.id)
+        .try_collect()
+        .await
+}
+
+/// The lock attempts of one bench task.
+#[derive(Debug, Default)]
+struct BenchTask {
+    latencies: Vec<Duration>,
+    contended: usize,
+}
+
+async fn bench_task<S: Storage<RawItem>>(
+    storage: &S,
+    id: &String,
+    who: &str,
+    rounds: usize,
+) -> Result<BenchTask> {
+    let mut task = BenchTask::default();
+    let mut locked = 0;
+    while locked < rounds {
+        let start = Instant::now();
+        let result = storage.lock(id, who).await?;
+        task.latencies.push(start.elapsed());
+        let LockResult::Success { lock, item } = result else {
+            task.contended += 1;
+            tokio::task::yield_now().await;
+            continue;
+        };
+        let count = bench_count(&item.0)?;
+        storage
+            .save_raw(id, (count + 1).to_string().as_bytes(), &lock)
+            .await?;
+        storage.unlock(id, lock).await?;
+        locked += 1;
+    }
+
+    Ok(task)
+}
+
+/// What the stress tasks share, to check the invariants while running.
+#[derive(Debug, Default)]
+struct StressState {
+    /// The items currently locked by a writer
+    held: std::sync::Mutex<HashSet<String>>,
+    double_locks: AtomicUsize,
+    writers_done: AtomicBool,
+}
+
+/// The lock attempts of one stress writer.
+#[derive(Debug, Default)]
+struct StressWriter {
+    bench: BenchTask,
+    elapsed: Duration,
+}
+
+async fn stress_writer<S: Storage<RawItem>>(
+    storage: &S,
+    state: &StressState,
+    ids: &[String],
+    who: &str,
+    rounds: usize,
+) -> Result<StressWriter> {
+    let start = Instant::now();
+    let mut writer = StressWriter::default();
+    let mut locked = 0;
+    while locked < rounds {
+        let id = &ids[rand::random::<usize>() % ids.len()];
+        let attempt = Instant::now();
+        let result = storage.lock(id, who).await?;
+        writer.bench.latencies.push(attempt.elapsed());
+        let LockResult::Success { lock, item } = result else {
+            writer.bench.contended += 1;
+            tokio::task::yield_now().await;
+            continue;
+        };
+        if !state.held.lock().expect("can lock").insert(id.clone()) {
+            state.double_locks.fetch_add(1, Ordering::Relaxed);
+        }
+        let count = bench_count(&item.0)?;
+        storage
+            .save_raw(id, (count + 1).to_string().as_bytes(), &lock)
+            .await?;
+        state.held.lock().expect("can lock").remove(id);
+        storage.unlock(id, lock).await?;
+        locked += 1;
+    }
+    writer.elapsed = start.elapsed();
+
+    Ok(writer)
+}
+
+/// Loads the items until all writers are done, and returns how often a counter went backwards.
+async fn stress_reader<S: Storage<RawItem>>(
+    storage: &S,
+    state: &StressState,
+    ids: &[String],
+) -> Result<(usize, usize)> {
+    let mut seen = vec![0; ids.len()];
+    let mut reads = 0;
+    let mut regressions = 0;
+    while !state.writers_done.load(Ordering::Relaxed) {
+        for (id, seen) in ids.iter().zip(seen.iter_mut()) {
+            let count = bench_count(&storage.load_raw(id).await?)?;
+            if count < *seen {
+                regressions += 1;
+            }
+            *seen = count.max(*seen);
+            reads += 1;
+        }
+        tokio::task::yield_now().await;
+    }
+
+    Ok((reads, regressions))
+}
+
+/// The counter of the bench item, an empty item counts as 0.
+fn bench_count(data: &[u8]) -> Result<usize> {
+    if data.is_empty() {
+        return Ok(0);
+    }
+    let count = String::from_utf8_lossy(data);
+    count
+        .trim()
+        .parse()
+        .map_err(|e| eyre!("Bench item holds {count:?}, not a counter -> {e}"))
+}
+
+/// The latency below which `percent` of the sorted `latencies` are.
+fn percentile(latencies: &[Duration], percent: f64) -> Duration {
+    if latencies.is_empty() {
+        return Duration::ZERO;
+    }
+    let index = ((latencies.len() - 1) as f64 * percent / 100.0).round() as usize;
+    latencies[index]
+}
+
+fn print_progress(progress: &Progress) {
+    let current_id = progress.current_id.as_deref().unwrap_or_default();
+    match progress.total_estimate {
+        Some(total) => eprintln!("{}/{total} {current_id}", progress.processed),
+        None => eprintln!("{} {current_id}", progress.processed),
+    }
+}
+
+async fn run<S: Storage<RawItem>>(storage: S, cli: Cli) -> Result<()> {
+    let mut storage = StorageWithMiddleware::new(storage);
+    if let Some(audit_log) = &cli.audit_log {
+        storage.add_audit_sink(FileAuditSink::new(audit_log), &cli.who)?;
+    }
+    storage.ensure_storage_exists().await?;
+    let progress = cli.progress.then_some(&print_progress as &dyn ProgressSink);
+
+    match cli.command {
+        Command::List { prefix, query } => {
+            let ids = match query {
+                Some(query) => {
+                    let query = match prefix {
+                        Some(prefix) => query.and(Condition::IdPrefix(prefix)),
+                        None => query,
+                    };
+                    matching_ids(&storage, &query).await?
+                }
+                None => all_ids(&storage, prefix.as_deref().unwrap_or_default()).await?,
+            };
+            for id in ids {
+                println!("{id}");
+            }
+        }
+        Command::Show { id } => {
+            let data = storage.load_raw(&id).await?;
+            println!("{}", String::from_utf8_lossy(&data));
+        }
+        Command::LockStatus { id } => {
+            let status = storage.display_lock(&id).await?;
+            if status.is_empty() {
+                println!("{id} is not locked");
+            } else {
+                println!("{status}");
+            }
+        }
+        Command::ForceUnlock { id } => match storage.force_unlock(&id).await? {
+            Some(lock) => println!(
+                "Unlocked {id}, locked by {} at {:?}",
+                lock.who(),
+                lock.when()
+            ),
+            None => println!("{id} is not locked"),
+        },
+        Command::Export {
+            file,
+            format,
+            query,
+        } => {
+            let mut out: Box<dyn Write> = match file {
+                Some(file) => Box::new(std::io::BufWriter::new(std::fs::File::create(file)?)),
+                None => Box::new(std::io::stdout().lock()),
+            };
+            let ids = match query {
+                Some(query) => matching_ids(&storage, &query).await?,
+                None => all_ids(&storage, "").await?,
+            };
+            let mut tracker = ProgressTracker::new(progress, Some(ids.len() as u64));
+            for id in ids {
+                let data = storage.load_raw(&id).await?;
+                tracker.advance(&id);
+                writeln!(out, "{}", encode_record(format.into(), &id, &data)?)?;
+            }
+            out.flush()?;
+            tracker.finish();
+        }
+        Command::Import { file, format } => {
+            let file = std::io::BufReader::new(std::fs::File::open(&file)?);
+            let mut tracker = ProgressTracker::new(progress, None);
+            let mut count = 0;
+            let mut skipped = 0;
+            for line in file.lines() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let Some((id, data)) = decode_record(format.into(), &line)? else {
+                    skipped += 1;
+                    continue;
+                };
+                let (lock, _) = storage.lock(&id, &cli.who).await?.success()?;
+                storage.save_raw(&id, &data, &lock).await?;
+                storage.unlock(&id, lock).await?;
+                tracker.advance(&id);
+                count += 1;
+            }
+            tracker.finish();
+            println!("Imported {count} items, skipped {skipped} without data");
+        }
+        Command::Bench { tasks, rounds, id } => {
+            let (lock, _) = storage.lock(&id, &cli.who).await?.success()?;
+            storage.save_raw(&id, b"0", &lock).await?;
+            storage.unlock(&id, lock).await?;
+
+            let start = Instant::now();
+            let results = futures_util::future::try_join_all((0..tasks).map(|task| {
+                let who = format!("{}-{task}", cli.who);
+                let storage = &storage;
+                let id = &id;
+                async move { bench_task(storage, id, &who, rounds).await }
+            }))
+            .await?;
+            let elapsed = start.elapsed();
+
+            let contended: usize = results.iter().map(|r| r.contended).sum();
+            let mut latencies: Vec<Duration> =
+                results.into_iter().flat_map(|r| r.latencies).collect();
+            latencies.sort_unstable();
+            println!(
+                "{} lock attempts by {tasks} tasks in {elapsed:?}, {contended} contended",
+                latencies.len()
+            );
+            println!(
+                "lock latency p50 {:?}, p90 {:?}, p99 {:?}, max {:?}",
+                percentile(&latencies, 50.0),
+                percentile(&latencies, 90.0),
+                percentile(&latencies, 99.0),
+                latencies.last().copied().unwrap_or_default()
+            );
+
+            let count = bench_count(&storage.load_raw(&id).await?)?;
+            if count != tasks * rounds {
+                return Err(eyre!(
+                    "Lost updates, {id} counted {count}, expected {}",
+                    tasks * rounds
+                ));
+            }
+            println!("{id} counted all {count} locks");
+        }
+        Command::Stress {
+            writers,
+            readers,
+            ids,
+            rounds,
+            id_prefix,
+        } => {
+            if ids == 0 {
+                return Err(eyre!("Stress needs at least one id"));
+            }
+            let ids: Vec<String> = (0..ids).map(|i| format!("{id_prefix}{i}")).collect();
+            for id in &ids {
+                let (lock, _) = storage.lock(id, &cli.who).await?.success()?;
+                storage.save_raw(id, b"0", &lock).await?;
+                storage.unlock(id, lock).await?;
+            }
+
+            let state = StressState::default();
+            let start = Instant::now();
+            let writing = async {
+                let results = futures_util::future::try_join_all((0..writers).map(|task| {
+                    let who = format!("{}-writer-{task}", cli.who);
+                    let (storage, state, ids) = (&storage, &state, &ids);
+                    async move { stress_writer(storage, state, ids, &who, rounds).await }
+                }))
+                .await;
+                state.writers_done.store(true, Ordering::Relaxed);
+                results
+            };
+            let reading = futures_util::future::try_join_all(
+                (0..readers).map(|_| stress_reader(&storage, &state, &ids)),
+            );
+            let (results, reads) = futures_util::future::try_join(writing, reading).await?;
+            let elapsed = start.elapsed();
+
+            let contended: Vec<usize> = results.iter().map(|r| r.bench.contended).collect();
+            let writer_elapsed: Vec<Duration> = results.iter().map(|r| r.elapsed).collect();
+            let mut latencies: Vec<Duration> = results
+                .into_iter()
+                .flat_map(|r| r.bench.latencies)
+                .collect();
+            latencies.sort_unstable();
+            println!(
+                "{} lock attempts by {writers} writers on {} ids in {elapsed:?}, {} contended",
+                latencies.len(),
+                ids.len(),
+                contended.iter().sum::<usize>()
+            );
+            println!(
+                "contended per writer min {}, max {}, writer done after min {:?}, max {:?}",
+                contended.iter().min().copied().unwrap_or_default(),
+                contended.iter().max().copied().unwrap_or_default(),
+                writer_elapsed.iter().min().copied().unwrap_or_default(),
+                writer_elapsed.iter().max().copied().unwrap_or_default(),
+            );
+            println!(
+                "lock latency p50 {:?}, p90 {:?}, p99 {:?}, max {:?}",
+                percentile(&latencies, 50.0),
+                percentile(&latencies, 90.0),
+                percentile(&latencies, 99.0),
+                latencies.last().copied().unwrap_or_default()
+            );
+            let regressions: usize = reads.iter().map(|(_, r)| r).sum();
+            println!(
+                "{} reads by {readers} readers, {regressions} saw a counter go backwards",
+                reads.iter().map(|(r, _)| r).sum::<usize>()
+            );
+
+            let mut count = 0;
+            for id in &ids {
+                count += bench_count(&storage.load_raw(id).await?)?;
+            }
+            let double_locks = state.double_locks.load(Ordering::Relaxed);
+            if double_locks > 0 {
+                return Err(eyre!("{double_locks} locks were held twice"));
+            }
+            if count != writers * rounds {
+                return Err(eyre!(
+                    "Lost updates, counted {count}, expected {}",
+                    writers * rounds
+                ));
+            }
+            println!("{} ids counted all {count} locks", ids.len());
+        }
+        #[cfg(feature = "wipe")]
+        Command::Wipe {
+            confirm,
+            dry_run,
+            prefix,
+        } => {
+            let options = match confirm {
+                Some(confirm) if !dry_run => WipeOptions::delete(&confirm),
+                _ => WipeOptions::DryRun,
+            };
+            let report = match prefix {
+                Some(prefix) => {
+                    storage
+                        .wipe_matching(&IdFilter::Prefix(prefix), &options, progress, None)
+                        .await?
+                }
+                None => storage.wipe(&options, progress, None).await?,
+            };
+            let verb = if report.dry_run {
+                "Would wipe"
+            } else {
+                "Wiped"
+            };
+            println!(
+                "{verb} {} items, e.g. {:?}",
+                report.count, report.sample_ids
+            );
+        }
+        Command::Size { id } => {
+            println!("{}", storage.item_size(&id).await?);
+        }
+        Command::Cost => {
+            let cost = storage.estimate_cost().await?;
+            println!("{}", serde_json::to_string_pretty(&cost)?);
+        }
+        Command::Fsck {
+            repair,
+            max_lock_age,
+            link_relations,
+        } => {
+            let options = FsckOptions {
+                max_lock_age: Duration::from_secs(max_lock_age),
+                repair,
+                link_relations,
+            };
+            let report = storage.fsck(&options).await?;
+            println!("{}", serde_json::to_string_pretty(&report)?);
+            if !report.is_clean() {
+                return Err(eyre!("Found anomalies"));
+            }
+        }
+        #[cfg(feature = "wipe")]
+        Command::Delete { ids, force } => {
+            let options = DeleteOptions::default().with_force(force);
+            let outcomes = storage.delete_many(&ids, &options).await?;
+            for (id, outcome) in ids.iter().zip(&outcomes) {
+                match outcome {
+                    DeleteOutcome::Deleted => println!("{id}: deleted"),
+                    DeleteOutcome::NotFound => println!("{id}: not found"),
+                    DeleteOutcome::Locked { who } => println!("{id}: locked by {who:?}"),
+                    DeleteOutcome::Failed { message } => println!("{id}: failed {message}"),
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    match cli.backend {
+        Backend::Disk => {
+            let mut path = cli.path.clone().ok_or_else(|| eyre!("disk needs --path"))?;
+            if let Some(namespace) = &cli.namespace {
+                path.push(namespace);
+            }
+            let storage = StorageDisk::<RawItem>::new(&path, &cli.extension).await;
+            run(storage, cli).await
+        }
+        Backend::DiskPacked => {
+            let path = cli
+                .path
+                .clone()
+                .ok_or_else(|| eyre!("disk-packed needs --path"))?;
+            if cli.namespace.is_some() {
+                return Err(eyre!(
+                    "disk-packed doesn't support --namespace, use another --path"
+                ));
+            }
+            let storage = StorageDiskPacked::<RawItem>::new(&path).await;
+            run(storage, cli).await
+        }
+        Backend::Dynamodb => {
+            let table = cli
+                .table
+                .clone()
+                .ok_or_else(|| eyre!("dynamodb needs --table"))?;
+            let mut storage = StorageDynamoDb::<RawItem>::new(&table).await;
+            if let Some(url) = &cli.endpoint_url {
+                storage.set_endpoint_url(url)?;
+            }
+            if let Some(region) = &cli.region {
+                storage.set_region(region)?;
+            }
+            if let Some(namespace) = &cli.namespace {
+                storage.set_key_prefix(namespace)?;
+            }
+            run(storage, cli).await
+        }
+    }
+}
@@ -0,0 +1,282 @@
+//! Running a closure over many items with bounded parallelism, e.g. for migrations.
+
+use crate::LockResult;
+use crate::Priority;
+use crate::RequestContext;
+use crate::Storage;
+use crate::StorageItem;
+use chrono::DateTime;
+use chrono::Utc;
+use color_eyre::eyre::Report;
+use color_eyre::eyre::Result;
+use futures_util::StreamExt;
+use std::future::Future;
+use tokio_util::sync::CancellationToken;
+
+/// Used as lock holder.
+const WHO: &str = "bulk";
+const SCAN_PAGE_SIZE: usize = 1000;
+
+/// The items visited by [for_each_item].
+#[derive(Debug, Clone)]
+pub enum BulkIds<ID> {
+    /// Ids that don't exist are skipped, see [BulkOutcome::Missing]
+    Ids(Vec<ID>),
+    /// All ids starting with `prefix`, an empty prefix visits all items
+    Scan { prefix: String },
+}
+
+impl<ID> From<Vec<ID>> for BulkIds<ID> {
+    fn from(ids: Vec<ID>) -> Self {
+        BulkIds::Ids(ids)
+    }
+}
+
+#[derive(Debug)]
+pub enum BulkOutcome {
+    /// The closure returned a changed item, and it was saved
+    Saved,
+    /// The closure returned `None`, and the item was only unlocked
+    Unchanged,
+    AlreadyLocked {
+        who: String,
+        when: DateTime<Utc>,
+    },
+    Missing,
+    /// Locking, the closure, saving, or unlocking failed
+    Failed(Report),
+}
+
+/// The outcome for a single item.
+#[derive(Debug)]
+pub struct BulkResult<ID> {
+    pub id: ID,
+    pub outcome: BulkOutcome,
+}
+
+/// Locks every item, passes it to `f`, then saves the item `f` returns, or just unlocks if it returns `None`.
+///
+/// At most `concurrency` items are in flight at the same time.
+/// Failures of single items are collected in the results, in no particular order,
+/// only a failing scan fails the whole run.
+///
+/// Once `cancel` is cancelled no further items are started, the ones in flight finish,
+/// and the results only contain the handled items.
+///
+/// Runs at [Priority::Background], unless the caller set a [RequestContext].
+pub async fn for_each_item<ITEM, S, F, Fut>(
+    storage: &S,
+    ids: impl Into<BulkIds<ITEM::ID>>,
+    concurrency: usize,
+    cancel: Option<&CancellationToken>,
+    f: F,
+) -> Result<Vec<BulkResult<ITEM::ID>>>
+where
+    ITEM: StorageItem + Send,
+    S: Storage<ITEM>,
+    F: Fn(ITEM::ID, ITEM) -> Fut + Sync,
+    Fut: Future<Output = Result<Option<ITEM>>> + Send,
+{
+    let concurrency = concurrency.max(1);
+    let ids = ids.into();
+    match RequestContext::current() {
+        Some(_) => run(storage, ids, concurrency, cancel, &f).await,
+        None => {
+            let context = RequestContext {
+                priority: Priority::Background,
+                ..Default::default()
+            };
+            context
+                .scope(run(storage, ids, concurrency, cancel, &f))
+                .await
+        }
+    }
+}
+
+async fn run<ITEM, S, F, Fut>(
+    storage: &S,
+    ids: BulkIds<ITEM::ID>,
+    concurrency: usize,
+    cancel: Option<&CancellationToken>,
+    f: &F,
+) -> Result<Vec<BulkResult<ITEM::ID>>>
+where
+    ITEM: StorageItem + Send,
+    S: Storage<ITEM>,
+    F: Fn(ITEM::ID, ITEM) -> Fut + Sync,
+    Fut: Future<Output = Result<Option<ITEM>>> + Send,
+{
+    match ids {
+        BulkIds::Ids(ids) => Ok(run_batch(storage, ids, true, concurrency, cancel, f).await),
+        BulkIds::Scan { prefix } => {
+            let mut results = Vec::new();
+            let mut scan_pos = None;
+            loop {
+                let page = storage
+                    .scan_ids_with_prefix(&prefix, scan_pos.as_deref(), Some(SCAN_PAGE_SIZE))
+                    .await?;
+                results.extend(run_batch(storage, page.items, false, concurrency, cancel, f).await);
+                scan_pos = page.cursor;
+                if scan_pos.is_none() || is_cancelled(cancel) {
+                    return Ok(results);
+                }
+            }
+        }
+    }
+}
+
+fn is_cancelled(cancel: Option<&CancellationToken>) -> bool {
+    cancel.is_some_and(|c| c.is_cancelled())
+}
+
+async fn run_batch<ITEM, S, F, Fut>(
+    storage: &S,
+    ids: Vec<ITEM::ID>,
+    check_exists: bool,
+    concurrency: usize,
+    cancel: Option<&CancellationToken>,
+    f: &F,
+) -> Vec<BulkResult<ITEM::ID>>
+where
+    ITEM: StorageItem + Send,
+    S: Storage<ITEM>,
+    F: Fn(ITEM::ID, ITEM) -> Fut + Sync,
+    Fut: Future<Output = Result<Option<ITEM>>> + Send,
+{
+    futures_util::stream::iter(ids)
+        // checked whenever a new item is started
+        .take_while(|_| std::future::ready(!is_cancelled(cancel)))
+        .map(|id| async move {
+            let outcome = run_one(storage, &id, check_exists, f).await;
+            BulkResult { id, outcome }
+        })
+        .buffer_unordered(concurrency)
+        .collect()
+        .await
+}
+
+async fn run_one<ITEM, S, F, Fut>(
+    storage: &S,
+    id: &ITEM::ID,
+    check_exists: bool,
+    f: &F,
+) -> BulkOutcome
+where
+    ITEM: StorageItem + Send,
+    S: Storage<ITEM>,
+    F: Fn(ITEM::ID, ITEM) -> Fut + Sync,
+    Fut: Future<Output = Result<Option<ITEM>>> + Send,
+{
+    // locking would create missing items
+    if check_exists {
+        match storage.exists(id).await {
+            Ok(true) => {}
+            Ok(false) => return BulkOutcome::Missing,
+            Err(e) => return BulkOutcome::Failed(e),
+        }
+    }
+    let (lock, item) = match storage.lock(id, WHO).await {
+        Ok(LockResult::Success { lock, item }) => (lock, item),
+        Ok(LockResult::AlreadyLocked { who, when }) => {
+            return BulkOutcome::AlreadyLocked { who, when }
+        }
+        Err(e) => return BulkOutcome::Failed(e),
+    };
+    match f(id.clone(), item).await {
+        Ok(Some(item)) => match storage.save_and_unlock(id, &item, lock).await {
+            Ok(()) => BulkOutcome::Saved,
+            Err(e) => BulkOutcome::Failed(e),
+        },
+        Ok(None) => match storage.unlock(id, lock).await {
+            Ok(()) => BulkOutcome::Unchanged,
+            Err(e) => BulkOutcome::Failed(e),
+        },
+        Err(e) => {
+            if let Err(unlock_e) = storage.unlock(id, lock).await {
+                tracing::warn!("Can't unlock {id} after failure -> {unlock_e:?}");
+            }
+            BulkOutcome::Failed(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bulk;
+    use crate::bulk::BulkIds;
+    use crate::bulk::BulkOutcome;
+    use crate::test_item::TestItem;
+    use crate::Storage;
+    use crate::StorageMemory;
+    use color_eyre::eyre::eyre;
+    use color_eyre::Result;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+
+    #[tokio::test(start_paused = true)]
+    async fn it_runs_bounded() -> Result<()> {
+        let storage = StorageMemory::<TestItem>::default();
+        for i in 0..10 {
+            let id = format!("item-{i}");
+            let (lock, _) = storage.lock(&id, "tester").await?.success()?;
+            storage
+                .save_and_unlock(&id, &TestItem { count: i }, lock)
+                .await?;
+        }
+        let (held, _) = storage
+            .lock(&String::from("item-9"), "tester")
+            .await?
+            .success()?;
+
+        let running = AtomicUsize::new(0);
+        let max_running = AtomicUsize::new(0);
+        let scan = BulkIds::Scan {
+            prefix: String::from("item-"),
+        };
+        let results = bulk::for_each_item(&storage, scan, 3, None, |_, mut item: TestItem| {
+            let running = &running;
+            let max_running = &max_running;
+            async move {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                max_running.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                match item.count {
+                    0 => Err(eyre!("broken")),
+                    v if v % 2 == 0 => {
+                        item.count *= 10;
+                        Ok(Some(item))
+                    }
+                    _ => Ok(None),
+                }
+            }
+        })
+        .await?;
+
+        assert_eq!(10, results.len());
+        assert_eq!(3, max_running.load(Ordering::SeqCst));
+        let count = |f: fn(&BulkOutcome) -> bool| results.iter().filter(|r| f(&r.outcome)).count();
+        assert_eq!(4, count(|o| matches!(o, BulkOutcome::Saved)));
+        assert_eq!(4, count(|o| matches!(o, BulkOutcome::Unchanged)));
+        assert_eq!(1, count(|o| matches!(o, BulkOutcome::Failed(_))));
+        assert_eq!(1, count(|o| matches!(o, BulkOutcome::AlreadyLocked { .. })));
+        assert_eq!(
+            TestItem { count: 40 },
+            storage.load(&String::from("item-4")).await?
+        );
+        assert!(storage
+            .display_lock(&String::from("item-0"))
+            .await?
+            .is_empty());
+        storage.unlock(&String::from("item-9"), held).await?;
+
+        let ids = vec![String::from("item-1"), String::from("missing")];
+        let results =
+            bulk::for_each_item(&storage, ids, 1, None, |_, _| async { Ok(None) }).await?;
+        assert!(matches!(results[1].outcome, BulkOutcome::Missing));
+        assert!(!storage.exists(&String::from("missing")).await?);
+
+        Ok(())
+    }
+}
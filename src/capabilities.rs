@@ -0,0 +1,227 @@
+/// What a [crate::Storage] supports, see [crate::Storage::capabilities].
+///
+/// Generic code and wrappers can check these up front,
+/// instead of handling "not supported" errors or panics of unfinished backends.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// [crate::Storage::scan_ids] and the scans and [crate::paginate] built on it
+    pub scan: bool,
+    /// [crate::Storage::list]
+    pub list: bool,
+    /// [crate::Storage::put_blob] and the other blob operations
+    pub blobs: bool,
+    /// [crate::Storage::put_meta] and [crate::Storage::get_meta]
+    pub meta: bool,
+    /// [crate::Storage::add_alias] and the other alias operations
+    pub aliases: bool,
+    /// [crate::Storage::add_link] and the other link operations
+    pub links: bool,
+    /// [crate::Storage::remove_orphaned_locks]
+    pub remove_orphaned_locks: bool,
+    /// [crate::Storage::load_stream] and [crate::Storage::save_stream] without holding the whole item in memory
+    pub streaming: bool,
+    /// Plain reads see the latest write, without asking for it via [crate::OpOptions]
+    pub consistent_reads: bool,
+    /// Items or locks expire on their own, e.g. via [crate::DynamoDbTtl]
+    pub ttl: bool,
+    /// Multiple items can be written atomically. No backend supports this yet.
+    pub transactions: bool,
+}
+
+impl Capabilities {
+    /// Only what both support, e.g. for wrappers spreading operations over multiple storages.
+    pub fn intersect(&self, other: &Capabilities) -> Capabilities {
+        Capabilities {
+            scan: self.scan && other.scan,
+            list: self.list && other.list,
+            blobs: self.blobs && other.blobs,
+            meta: self.meta && other.meta,
+            aliases: self.aliases && other.aliases,
+            links: self.links && other.links,
+            remove_orphaned_locks: self.remove_orphaned_locks && other.remove_orphaned_locks,
+            streaming: self.streaming && other.streaming,
+            consistent_reads: self.consistent_reads && other.consistent_reads,
+            ttl: self.ttl && other.ttl,
+            transactions: self.transactions && other.transactions,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Capabilities;
+    use crate::DynStorage;
+    use crate::LockResult;
+    use crate::Storage;
+    use crate::StorageError;
+    use crate::StorageItem;
+    use crate::StorageLock;
+    use crate::StorageMemory;
+    use crate::StorageMigrating;
+    use crate::StorageNull;
+    use crate::StorageOperation;
+    use async_trait::async_trait;
+    use chrono::DateTime;
+    use chrono::Utc;
+    use color_eyre::Result;
+    use std::sync::Arc;
+
+    #[derive(Default, Debug)]
+    struct TestItem;
+
+    impl StorageItem for TestItem {
+        type ID = String;
+
+        fn serialize(&self) -> Result<Vec<u8>> {
+            Ok(Vec::new())
+        }
+        fn deserialize(_data: &[u8]) -> Result<Self> {
+            Ok(Self)
+        }
+    }
+
+    #[test]
+    fn it_reports_capabilities() {
+        let memory = StorageMemory::<TestItem>::default();
+        let capabilities = memory.capabilities();
+        assert!(capabilities.scan && capabilities.meta && capabilities.consistent_reads);
+        assert!(!capabilities.blobs && !capabilities.transactions);
+
+        let shared: DynStorage<TestItem> = Arc::new(memory);
+        assert_eq!(capabilities, shared.capabilities());
+
+        let migrating = StorageMigrating::new(StorageNull::<TestItem>::default(), shared);
+        assert_eq!(
+            capabilities.intersect(&StorageNull::<TestItem>::default().capabilities()),
+            migrating.capabilities()
+        );
+        assert_eq!(
+            Capabilities::default(),
+            capabilities.intersect(&Capabilities::default())
+        );
+    }
+
+    /// Only the required operations, like a backend without scans.
+    #[derive(Debug, Default)]
+    struct Unscanned(StorageMemory<TestItem>);
+
+    #[async_trait]
+    impl Storage<TestItem> for Unscanned {
+        async fn ensure_storage_exists(&mut self) -> Result<()> {
+            self.0.ensure_storage_exists().await
+        }
+        async fn create(&self) -> Result<String> {
+            self.0.create().await
+        }
+        async fn exists(&self, id: &String) -> Result<bool> {
+            self.0.exists(id).await
+        }
+        async fn load(&self, id: &String) -> Result<TestItem> {
+            self.0.load(id).await
+        }
+        async fn save(&self, id: &String, item: &TestItem, lock: &StorageLock) -> Result<()> {
+            self.0.save(id, item, lock).await
+        }
+        async fn lock(&self, id: &String, who: &str) -> Result<LockResult<TestItem>> {
+            self.0.lock(id, who).await
+        }
+        async fn unlock(&self, id: &String, lock: StorageLock) -> Result<()> {
+            self.0.unlock(id, lock).await
+        }
+        async fn force_unlock(&self, id: &String) -> Result<Option<StorageLock>> {
+            self.0.force_unlock(id).await
+        }
+        async fn force_unlock_if_held(
+            &self,
+            id: &String,
+            who: &str,
+            when: DateTime<Utc>,
+        ) -> Result<bool> {
+            self.0.force_unlock_if_held(id, who, when).await
+        }
+        async fn verify_lock(&self, id: &String, lock: &StorageLock) -> Result<bool> {
+            self.0.verify_lock(id, lock).await
+        }
+        async fn all_ids(&self) -> Result<Vec<String>> {
+            self.0.all_ids().await
+        }
+        async fn display_lock(&self, id: &String) -> Result<String> {
+            self.0.display_lock(id).await
+        }
+        #[cfg(feature = "metadata")]
+        async fn metadata_highest_seen_id(&self) -> Option<String> {
+            self.0.metadata_highest_seen_id().await
+        }
+        #[cfg(feature = "metadata")]
+        async fn metadata_item_count(&self) -> Option<u64> {
+            self.0.metadata_item_count().await
+        }
+        #[cfg(feature = "metadata")]
+        async fn metadata_total_bytes(&self) -> Option<u64> {
+            self.0.metadata_total_bytes().await
+        }
+        #[cfg(feature = "metadata")]
+        async fn metadata_last_write(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+            self.0.metadata_last_write().await
+        }
+        #[cfg(feature = "metadata")]
+        async fn metadata_lock_stats(&self) -> crate::LockStats {
+            self.0.metadata_lock_stats().await
+        }
+        #[cfg(feature = "wipe")]
+        async fn wipe(
+            &self,
+            options: &crate::WipeOptions,
+            progress: Option<&dyn crate::ProgressSink>,
+            cancel: Option<&tokio_util::sync::CancellationToken>,
+        ) -> Result<crate::WipeReport> {
+            self.0.wipe(options, progress, cancel).await
+        }
+        #[cfg(feature = "wipe")]
+        async fn wipe_matching(
+            &self,
+            filter: &crate::IdFilter,
+            options: &crate::WipeOptions,
+            progress: Option<&dyn crate::ProgressSink>,
+            cancel: Option<&tokio_util::sync::CancellationToken>,
+        ) -> Result<crate::WipeReport> {
+            self.0
+                .wipe_matching(filter, options, progress, cancel)
+                .await
+        }
+        #[cfg(feature = "wipe")]
+        async fn delete_many(
+            &self,
+            ids: &[String],
+            options: &crate::DeleteOptions,
+        ) -> Result<Vec<crate::DeleteOutcome>> {
+            self.0.delete_many(ids, options).await
+        }
+    }
+
+    #[tokio::test]
+    async fn it_fails_unsupported_scans() -> Result<()> {
+        let storage = Unscanned::default();
+        assert!(!storage.capabilities().scan);
+        let unsupported = |e: color_eyre::Report| {
+            matches!(
+                e.downcast_ref(),
+                Some(StorageError::Unsupported {
+                    operation: StorageOperation::ScanIds
+                })
+            )
+        };
+        assert!(unsupported(storage.scan_ids(None, None).await.unwrap_err()));
+        assert!(unsupported(
+            storage
+                .scan_ids_with_prefix("a", None, None)
+                .await
+                .unwrap_err()
+        ));
+        assert!(unsupported(
+            storage.scan_items("", None, Some(10)).await.unwrap_err()
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+use crate::Redacted;
+use crate::StorageItem;
+use tokio::sync::mpsc;
+
+/// A change to an item, as observed by a storage backend.
+pub enum ChangeEvent<ITEM: StorageItem> {
+    /// The item was written.
+    /// `old` is `None` for new items or when the backend can't provide it.
+    Saved {
+        id: ITEM::ID,
+        old: Option<ITEM>,
+        new: ITEM,
+    },
+    Locked {
+        id: ITEM::ID,
+        who: String,
+    },
+    Unlocked {
+        id: ITEM::ID,
+    },
+    /// The item was removed.
+    /// `old` is `None` when the backend can't provide it.
+    Deleted {
+        id: ITEM::ID,
+        old: Option<ITEM>,
+    },
+}
+
+// items are only shown without redaction, see [crate::set_redact]
+impl<ITEM: StorageItem> std::fmt::Debug for ChangeEvent<ITEM> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChangeEvent::Saved { id, old, new } => f
+                .debug_struct("Saved")
+                .field("id", id)
+                .field("old", &Redacted(old))
+                .field("new", &Redacted(new))
+                .finish(),
+            ChangeEvent::Locked { id, who } => f
+                .debug_struct("Locked")
+                .field("id", id)
+                .field("who", who)
+                .finish(),
+            ChangeEvent::Unlocked { id } => f.debug_struct("Unlocked").field("id", id).finish(),
+            ChangeEvent::Deleted { id, old } => f
+                .debug_struct("Deleted")
+                .field("id", id)
+                .field("old", &Redacted(old))
+                .finish(),
+        }
+    }
+}
+
+impl<ITEM: StorageItem> ChangeEvent<ITEM> {
+    pub fn id(&self) -> &ITEM::ID {
+        match self {
+            ChangeEvent::Saved { id, .. } => id,
+            ChangeEvent::Locked { id, .. } => id,
+            ChangeEvent::Unlocked { id } => id,
+            ChangeEvent::Deleted { id, .. } => id,
+        }
+    }
+}
+
+/// The receiving end of a change feed. The feed stops when this is dropped.
+pub type ChangeEventReceiver<ITEM> = mpsc::Receiver<ChangeEvent<ITEM>>;
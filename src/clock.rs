@@ -0,0 +1,60 @@
+use chrono::DateTime;
+use chrono::Utc;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The source of the current time for lock creation, lock ages, and TTLs.
+///
+/// Backends use [SystemClock] unless told otherwise, e.g. via [crate::StorageDisk::set_clock].
+pub trait Clock: Send + Sync + std::fmt::Debug {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// [Utc::now]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that only moves when told to, e.g. for testing lock expiry.
+///
+/// Clones share the time, keep one to move the clock given to a backend.
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    now: Arc<Mutex<DateTime<Utc>>>,
+}
+
+impl MockClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            now: Arc::new(Mutex::new(now)),
+        }
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().expect("can lock") = now;
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().expect("can lock");
+        *now += chrono::Duration::from_std(duration).expect("duration fits");
+    }
+}
+
+impl Default for MockClock {
+    /// Starts at the current time.
+    fn default() -> Self {
+        Self::new(Utc::now())
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().expect("can lock")
+    }
+}
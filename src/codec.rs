@@ -0,0 +1,960 @@
+use crate::paginate;
+use crate::storage_item::corrupt;
+use crate::storage_item::deserialize_item_as;
+use crate::storage_item::serialize_valid_as;
+use crate::ItemFormat;
+use crate::LockResult;
+use crate::NativeFormat;
+use crate::OpOptions;
+use crate::Page;
+use crate::Storage;
+use crate::StorageItem;
+use crate::StorageLock;
+use async_trait::async_trait;
+use chrono::DateTime;
+use chrono::Utc;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use core::marker::PhantomData;
+use futures_util::TryStreamExt;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Starts every record written with a [Codec] other than the default, followed by the format version.
+const MAGIC: [u8; 4] = [0x00, b'o', b'c', 0x01];
+const FLAG_ZSTD: u8 = 0x01;
+const FLAG_AES_256_GCM: u8 = 0x02;
+#[cfg(feature = "encryption")]
+const NONCE_LEN: usize = 12;
+/// Used as lock holder.
+const WHO: &str = "codec";
+
+/// How a [StorageCodec] encodes items on save.
+///
+/// Both can be combined, items are compressed before they are encrypted.
+/// The default leaves the data as serialized, without a header.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Codec {
+    /// Compresses with zstd at the given level, e.g. `3`. Needs the `zstd` feature.
+    pub zstd_level: Option<i32>,
+    /// Encrypts with AES-256-GCM using the key added as [StorageCodec::add_key]. Needs the `encryption` feature.
+    pub key_id: Option<u8>,
+}
+
+/// How a stored record was encoded, read from its header, see [StorageCodec::stored_codec].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RecordCodec {
+    pub zstd: bool,
+    pub key_id: Option<u8>,
+}
+
+impl RecordCodec {
+    /// Whether saving with `codec` would write the record the same way.
+    pub fn matches(&self, codec: &Codec) -> bool {
+        self.zstd == codec.zstd_level.is_some() && self.key_id == codec.key_id
+    }
+
+    /// Returns the codec and the rest of the data after the header.
+    fn read(data: &[u8]) -> Result<(Self, &[u8])> {
+        let Some(rest) = data.strip_prefix(&MAGIC[..]) else {
+            return Ok((Self::default(), data));
+        };
+        let (&flags, rest) = rest
+            .split_first()
+            .ok_or_else(|| eyre!("Truncated codec header"))?;
+        if flags & !(FLAG_ZSTD | FLAG_AES_256_GCM) != 0 {
+            return Err(eyre!("Unknown codec flags {flags:#04x}"));
+        }
+        let zstd = flags & FLAG_ZSTD != 0;
+        if flags & FLAG_AES_256_GCM == 0 {
+            return Ok((Self { zstd, key_id: None }, rest));
+        }
+        let (&key_id, rest) = rest
+            .split_first()
+            .ok_or_else(|| eyre!("Truncated codec header"))?;
+
+        Ok((
+            Self {
+                zstd,
+                key_id: Some(key_id),
+            },
+            rest,
+        ))
+    }
+
+    fn header(&self) -> Vec<u8> {
+        let mut flags = 0;
+        if self.zstd {
+            flags |= FLAG_ZSTD;
+        }
+        if self.key_id.is_some() {
+            flags |= FLAG_AES_256_GCM;
+        }
+        let mut header = MAGIC.to_vec();
+        header.push(flags);
+        header.extend(self.key_id);
+        header
+    }
+}
+
+/// A 256 bit key, never shown in debug output.
+#[cfg_attr(not(feature = "encryption"), allow(dead_code))]
+struct Key([u8; 32]);
+
+impl std::fmt::Debug for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Key(..)")
+    }
+}
+
+/// The additional authenticated data, binding the ciphertext to its header and its item.
+#[cfg(feature = "encryption")]
+fn aad(header: &[u8], id: &str) -> Vec<u8> {
+    let mut aad = header.to_vec();
+    aad.extend_from_slice(id.as_bytes());
+    aad
+}
+
+#[cfg(feature = "encryption")]
+fn aead_key(key: &Key) -> Result<ring::aead::LessSafeKey> {
+    let key = ring::aead::UnboundKey::new(&ring::aead::AES_256_GCM, &key.0)
+        .map_err(|_| eyre!("Invalid AES-256-GCM key"))?;
+    Ok(ring::aead::LessSafeKey::new(key))
+}
+
+/// Returns the nonce, followed by the ciphertext and its tag.
+#[cfg(feature = "encryption")]
+fn seal(key: &Key, aad: &[u8], mut data: Vec<u8>) -> Result<Vec<u8>> {
+    let nonce: [u8; NONCE_LEN] = rand::random();
+    aead_key(key)?
+        .seal_in_place_append_tag(
+            ring::aead::Nonce::assume_unique_for_key(nonce),
+            ring::aead::Aad::from(aad),
+            &mut data,
+        )
+        .map_err(|_| eyre!("Can't encrypt"))?;
+    let mut sealed = nonce.to_vec();
+    sealed.extend(data);
+
+    Ok(sealed)
+}
+
+#[cfg(feature = "encryption")]
+fn open(key: &Key, aad: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    if data.len() < NONCE_LEN {
+        return Err(eyre!("Truncated nonce"));
+    }
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    let nonce =
+        ring::aead::Nonce::try_assume_unique_for_key(nonce).map_err(|_| eyre!("Invalid nonce"))?;
+    let mut in_out = ciphertext.to_vec();
+    let plain = aead_key(key)?
+        .open_in_place(nonce, ring::aead::Aad::from(aad), &mut in_out)
+        .map_err(|_| eyre!("Can't decrypt, wrong key, or tampered data"))?;
+
+    Ok(plain.to_vec())
+}
+
+/// The stored form of an item of a [StorageCodec], with its codec header.
+///
+/// `None` for new items, that were locked, but never saved.
+#[derive(Debug)]
+pub struct Encoded<ITEM> {
+    data: Option<Vec<u8>>,
+    item_type: PhantomData<ITEM>,
+}
+
+impl<ITEM> Default for Encoded<ITEM> {
+    fn default() -> Self {
+        Self {
+            data: None,
+            item_type: PhantomData,
+        }
+    }
+}
+
+impl<ITEM> Encoded<ITEM> {
+    fn new(data: Vec<u8>) -> Self {
+        Self {
+            data: Some(data),
+            item_type: PhantomData,
+        }
+    }
+
+    pub fn data(&self) -> Option<&[u8]> {
+        self.data.as_deref()
+    }
+}
+
+impl<ITEM: StorageItem> StorageItem for Encoded<ITEM> {
+    type ID = ITEM::ID;
+
+    fn serialize(&self) -> Result<Vec<u8>> {
+        Ok(self.data.clone().unwrap_or_default())
+    }
+
+    fn deserialize(data: &[u8]) -> Result<Self> {
+        Ok(Self::new(data.to_vec()))
+    }
+}
+
+/// Wraps a storage of [Encoded] items and compresses and encrypts them on save, see [Codec].
+///
+/// Every record carries a header naming its codec, so changing the codec or rotating keys
+/// only affects new saves, and loads decode whatever they find.
+/// Records without a header, e.g. written before the wrapper was added, are read as is.
+/// [StorageCodec::reencode] moves single items to the current codec.
+///
+/// Encrypted records are bound to their id and fail to decrypt when copied to another one.
+///
+/// The serialization format can be changed the same way, see [StorageCodec::set_formats].
+///
+/// Note: Old keys and formats have to stay added as long as records use them.
+#[derive(Debug)]
+pub struct StorageCodec<ITEM: StorageItem, S: Storage<Encoded<ITEM>>>
+where
+    ITEM: Send,
+{
+    storage: S,
+    codec: Codec,
+    keys: HashMap<u8, Key>,
+    formats: Vec<Box<dyn ItemFormat<ITEM>>>,
+    reencode_on_load: bool,
+    item_type: PhantomData<ITEM>,
+}
+
+impl<ITEM: StorageItem + Send, S: Storage<Encoded<ITEM>>> StorageCodec<ITEM, S> {
+    pub fn new(storage: S) -> Self {
+        Self {
+            storage,
+            codec: Codec::default(),
+            keys: HashMap::new(),
+            formats: vec![Box::new(NativeFormat)],
+            reencode_on_load: false,
+            item_type: PhantomData,
+        }
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    pub fn into_inner(self) -> S {
+        self.storage
+    }
+
+    /// Adds a key for encrypting and decrypting records, replacing one with the same id.
+    pub fn add_key(&mut self, key_id: u8, key: [u8; 32]) -> Result<()> {
+        self.keys.insert(key_id, Key(key));
+
+        Ok(())
+    }
+
+    /// The codec for saves, fails if it needs a missing feature or key.
+    pub fn set_codec(&mut self, codec: Codec) -> Result<()> {
+        if codec.zstd_level.is_some() && !cfg!(feature = "zstd") {
+            return Err(eyre!("Compression needs the zstd feature"));
+        }
+        if let Some(key_id) = codec.key_id {
+            if !cfg!(feature = "encryption") {
+                return Err(eyre!("Encryption needs the encryption feature"));
+            }
+            if !self.keys.contains_key(&key_id) {
+                return Err(eyre!("Key {key_id} wasn't added"));
+            }
+        }
+        self.codec = codec;
+
+        Ok(())
+    }
+
+    pub fn codec(&self) -> Codec {
+        self.codec
+    }
+
+    /// The serialization formats. Saves use the first one and loads try all of them in order,
+    /// e.g. `[binary, NativeFormat]` while moving from JSON to a binary format.
+    /// Defaults to [NativeFormat].
+    pub fn set_formats(&mut self, formats: Vec<Box<dyn ItemFormat<ITEM>>>) -> Result<()> {
+        if formats.is_empty() {
+            return Err(eyre!("At least one format is needed"));
+        }
+        self.formats = formats;
+
+        Ok(())
+    }
+
+    /// Loads [StorageCodec::reencode] items not using the current codec or first format, so records upgrade lazily.
+    /// Failures are only logged. Off by default.
+    pub fn set_reencode_on_load(&mut self, reencode_on_load: bool) -> Result<()> {
+        self.reencode_on_load = reencode_on_load;
+
+        Ok(())
+    }
+
+    /// Reads how the stored item was encoded, without decoding it.
+    pub async fn stored_codec(&self, id: &ITEM::ID) -> Result<RecordCodec> {
+        Ok(RecordCodec::read(&self.storage.load_raw(id).await?)?.0)
+    }
+
+    /// Saves the item again with the current codec, unless it already uses it or is locked.
+    ///
+    /// Returns whether it was saved, e.g. to move all items to a new key before removing the old one.
+    pub async fn reencode(&self, id: &ITEM::ID) -> Result<bool> {
+        let LockResult::Success { lock, item } = self.storage.lock(id, WHO).await? else {
+            return Ok(false);
+        };
+        let reencoded = match item.data() {
+            Some(data) => self.reencoded(id, data),
+            None => Ok(None),
+        };
+        match reencoded {
+            Ok(Some(data)) => {
+                self.storage
+                    .save_and_unlock(id, &Encoded::new(data), lock)
+                    .await?;
+                Ok(true)
+            }
+            Ok(None) => {
+                self.storage.unlock(id, lock).await?;
+                Ok(false)
+            }
+            Err(e) => {
+                if let Err(unlock_e) = self.storage.unlock(id, lock).await {
+                    tracing::warn!("Can't unlock {id} after failed reencoding -> {unlock_e:?}");
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// [StorageCodec::reencode] for all items, returns how many were saved.
+    ///
+    /// Locked items are skipped. Run it again or rely on [StorageCodec::set_reencode_on_load] to catch them.
+    pub async fn reencode_all(
+        &self,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<usize> {
+        let mut ids = paginate(&self.storage);
+        if let Some(cancel) = cancel {
+            ids = ids.with_cancellation(cancel);
+        }
+        let mut ids = std::pin::pin!(ids.ids());
+        let mut reencoded = 0;
+        while let Some(id) = ids.try_next().await? {
+            if self.reencode(&id).await? {
+                reencoded += 1;
+            }
+        }
+
+        Ok(reencoded)
+    }
+
+    /// The record in the current codec and first format, `None` if it already is.
+    fn reencoded(&self, id: &ITEM::ID, data: &[u8]) -> Result<Option<Vec<u8>>> {
+        let (codec, _) = RecordCodec::read(data)?;
+        if self.formats.len() == 1 {
+            if codec.matches(&self.codec) {
+                return Ok(None);
+            }
+            return self.encode(id, self.decode(id, data)?).map(Some);
+        }
+        let plain = self.decode(id, data)?;
+        let (item, format) = self.deserialize(id, &plain)?;
+        if format > 0 {
+            return self.encode(id, self.formats[0].serialize(&item)?).map(Some);
+        }
+        if codec.matches(&self.codec) {
+            return Ok(None);
+        }
+
+        self.encode(id, plain).map(Some)
+    }
+
+    fn key(&self, key_id: u8) -> Result<&Key> {
+        self.keys
+            .get(&key_id)
+            .ok_or_else(|| eyre!("Key {key_id} wasn't added"))
+    }
+
+    fn encode(&self, id: &ITEM::ID, data: Vec<u8>) -> Result<Vec<u8>> {
+        if self.codec == Codec::default() {
+            return Ok(data);
+        }
+        let record = RecordCodec {
+            zstd: self.codec.zstd_level.is_some(),
+            key_id: self.codec.key_id,
+        };
+        let header = record.header();
+        #[allow(unused_mut)]
+        let mut data = data;
+        if let Some(_level) = self.codec.zstd_level {
+            #[cfg(feature = "zstd")]
+            {
+                data = zstd::encode_all(data.as_slice(), _level)?;
+            }
+            #[cfg(not(feature = "zstd"))]
+            return Err(eyre!("Compression needs the zstd feature"));
+        }
+        if let Some(key_id) = self.codec.key_id {
+            let _key = self.key(key_id)?;
+            #[cfg(feature = "encryption")]
+            {
+                data = seal(_key, &aad(&header, &id.to_string()), data)?;
+            }
+            #[cfg(not(feature = "encryption"))]
+            return Err(eyre!("Encryption of {id} needs the encryption feature"));
+        }
+        let mut encoded = header;
+        encoded.extend(data);
+
+        Ok(encoded)
+    }
+
+    fn decode(&self, id: &ITEM::ID, data: &[u8]) -> Result<Vec<u8>> {
+        let (record, rest) = RecordCodec::read(data)?;
+        #[allow(unused_mut)]
+        let mut data = rest.to_vec();
+        if let Some(key_id) = record.key_id {
+            let _key = self.key(key_id)?;
+            #[cfg(feature = "encryption")]
+            {
+                data = open(_key, &aad(&record.header(), &id.to_string()), rest)?;
+            }
+            #[cfg(not(feature = "encryption"))]
+            return Err(eyre!("{id} is encrypted, enable the encryption feature"));
+        }
+        if record.zstd {
+            #[cfg(feature = "zstd")]
+            {
+                data = zstd::decode_all(data.as_slice())?;
+            }
+            #[cfg(not(feature = "zstd"))]
+            return Err(eyre!("{id} is zstd compressed, enable the zstd feature"));
+        }
+
+        Ok(data)
+    }
+
+    /// The item and the index of the first format that could read it.
+    /// Fails with [crate::StorageError::Corrupt] if none can.
+    fn deserialize(&self, id: &ITEM::ID, data: &[u8]) -> Result<(ITEM, usize)> {
+        let mut first_error = None;
+        for (index, format) in self.formats.iter().enumerate() {
+            match deserialize_item_as(data, format.as_ref()) {
+                Ok(item) => return Ok((item, index)),
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        let e = first_error.unwrap_or_else(|| eyre!("No formats"));
+
+        let e = e.wrap_err(format!(
+            "None of {} formats can read it",
+            self.formats.len()
+        ));
+
+        Err(corrupt(id, e))
+    }
+
+    fn decode_item(&self, id: &ITEM::ID, encoded: Encoded<ITEM>) -> Result<ITEM> {
+        match encoded.data {
+            Some(data) => Ok(self.deserialize(id, &self.decode(id, &data)?)?.0),
+            None => Ok(ITEM::default()),
+        }
+    }
+
+    /// Like [StorageCodec::decode_item], but reencodes outdated records, see [StorageCodec::set_reencode_on_load].
+    async fn decode_loaded(&self, id: &ITEM::ID, encoded: Encoded<ITEM>) -> Result<ITEM> {
+        let Some(data) = encoded.data else {
+            return Ok(ITEM::default());
+        };
+        let (codec, _) = RecordCodec::read(&data)?;
+        let (item, format) = self.deserialize(id, &self.decode(id, &data)?)?;
+        if self.reencode_on_load && (format > 0 || !codec.matches(&self.codec)) {
+            if let Err(e) = self.reencode(id).await {
+                tracing::warn!("Can't reencode {id} on load -> {e:?}");
+            }
+        }
+
+        Ok(item)
+    }
+
+    /// Validates and serializes the item in the first format.
+    fn serialize(&self, id: &ITEM::ID, item: &ITEM) -> Result<Vec<u8>> {
+        serialize_valid_as(id, item, self.formats[0].as_ref())
+    }
+}
+
+#[async_trait]
+impl<ITEM: StorageItem + Send, S: Storage<Encoded<ITEM>>> Storage<ITEM> for StorageCodec<ITEM, S> {
+    async fn ensure_storage_exists(&mut self) -> Result<()> {
+        self.storage.ensure_storage_exists().await
+    }
+
+    fn capabilities(&self) -> crate::Capabilities {
+        self.storage.capabilities()
+    }
+
+    async fn create(&self) -> Result<ITEM::ID> {
+        self.storage.create().await
+    }
+
+    async fn exists(&self, id: &ITEM::ID) -> Result<bool> {
+        self.storage.exists(id).await
+    }
+
+    async fn exists_with(&self, id: &ITEM::ID, options: &OpOptions) -> Result<bool> {
+        self.storage.exists_with(id, options).await
+    }
+
+    async fn load(&self, id: &ITEM::ID) -> Result<ITEM> {
+        self.decode_loaded(id, self.storage.load(id).await?).await
+    }
+
+    async fn load_with(&self, id: &ITEM::ID, options: &OpOptions) -> Result<ITEM> {
+        self.decode_loaded(id, self.storage.load_with(id, options).await?)
+            .await
+    }
+
+    async fn load_many(&self, ids: &[ITEM::ID]) -> Result<Vec<Option<ITEM>>> {
+        let encoded = self.storage.load_many(ids).await?;
+        ids.iter()
+            .zip(encoded)
+            .map(|(id, e)| e.map(|e| self.decode_item(id, e)).transpose())
+            .collect()
+    }
+
+    async fn prefetch(&self, ids: &[ITEM::ID]) -> Result<()> {
+        self.storage.prefetch(ids).await
+    }
+
+    async fn save(&self, id: &ITEM::ID, item: &ITEM, lock: &StorageLock) -> Result<()> {
+        let data = self.encode(id, self.serialize(id, item)?)?;
+        self.storage.save(id, &Encoded::new(data), lock).await
+    }
+
+    async fn lock(&self, id: &ITEM::ID, who: &str) -> Result<LockResult<ITEM>> {
+        match self.storage.lock(id, who).await? {
+            LockResult::Success { lock, item } => match self.decode_item(id, item) {
+                Ok(item) => Ok(LockResult::Success { lock, item }),
+                Err(e) => {
+                    if let Err(unlock_e) = self.storage.unlock(id, lock).await {
+                        tracing::warn!("Can't unlock {id} after failed decoding -> {unlock_e:?}");
+                    }
+                    Err(e)
+                }
+            },
+            LockResult::AlreadyLocked { who, when } => Ok(LockResult::AlreadyLocked { who, when }),
+        }
+    }
+
+    async fn unlock(&self, id: &ITEM::ID, lock: StorageLock) -> Result<()> {
+        self.storage.unlock(id, lock).await
+    }
+
+    async fn handoff_lock(
+        &self,
+        id: &ITEM::ID,
+        lock: StorageLock,
+        new_who: &str,
+    ) -> Result<StorageLock> {
+        self.storage.handoff_lock(id, lock, new_who).await
+    }
+
+    async fn save_and_unlock(&self, id: &ITEM::ID, item: &ITEM, lock: StorageLock) -> Result<()> {
+        let data = self.encode(id, self.serialize(id, item)?)?;
+        self.storage
+            .save_and_unlock(id, &Encoded::new(data), lock)
+            .await
+    }
+
+    async fn load_raw(&self, id: &ITEM::ID) -> Result<Vec<u8>> {
+        self.decode(id, &self.storage.load_raw(id).await?)
+    }
+
+    async fn save_raw(&self, id: &ITEM::ID, data: &[u8], lock: &StorageLock) -> Result<()> {
+        let data = self.encode(id, data.to_vec())?;
+        self.storage.save_raw(id, &data, lock).await
+    }
+
+    async fn put_blob(
+        &self,
+        id: &ITEM::ID,
+        name: &str,
+        data: &[u8],
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.storage.put_blob(id, name, data, lock).await
+    }
+
+    async fn get_blob(&self, id: &ITEM::ID, name: &str) -> Result<Option<Vec<u8>>> {
+        self.storage.get_blob(id, name).await
+    }
+
+    async fn list_blobs(&self, id: &ITEM::ID) -> Result<Vec<String>> {
+        self.storage.list_blobs(id).await
+    }
+
+    async fn put_meta(&self, key: &str, value: &[u8]) -> Result<()> {
+        self.storage.put_meta(key, value).await
+    }
+
+    async fn get_meta(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.storage.get_meta(key).await
+    }
+
+    async fn add_alias(&self, alias: &ITEM::ID, canonical: &ITEM::ID) -> Result<()> {
+        self.storage.add_alias(alias, canonical).await
+    }
+
+    async fn remove_alias(&self, alias: &ITEM::ID) -> Result<()> {
+        self.storage.remove_alias(alias).await
+    }
+
+    async fn resolve_alias(&self, id: &ITEM::ID) -> Result<ITEM::ID> {
+        self.storage.resolve_alias(id).await
+    }
+
+    async fn add_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.storage.add_link(from, relation, to, lock).await
+    }
+
+    async fn remove_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.storage.remove_link(from, relation, to, lock).await
+    }
+
+    async fn links_of(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        self.storage.links_of(id, relation).await
+    }
+
+    async fn linked_from(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        self.storage.linked_from(id, relation).await
+    }
+
+    async fn force_unlock(&self, id: &ITEM::ID) -> Result<Option<StorageLock>> {
+        self.storage.force_unlock(id).await
+    }
+
+    async fn force_unlock_if_held(
+        &self,
+        id: &ITEM::ID,
+        who: &str,
+        when: DateTime<Utc>,
+    ) -> Result<bool> {
+        self.storage.force_unlock_if_held(id, who, when).await
+    }
+
+    async fn remove_orphaned_locks(&self, max_age: Duration) -> Result<usize> {
+        self.storage.remove_orphaned_locks(max_age).await
+    }
+
+    async fn verify_lock(&self, id: &ITEM::ID, lock: &StorageLock) -> Result<bool> {
+        self.storage.verify_lock(id, lock).await
+    }
+
+    async fn verify_lock_with(
+        &self,
+        id: &ITEM::ID,
+        lock: &StorageLock,
+        options: &OpOptions,
+    ) -> Result<bool> {
+        self.storage.verify_lock_with(id, lock, options).await
+    }
+
+    async fn all_ids(&self) -> Result<Vec<ITEM::ID>> {
+        self.storage.all_ids().await
+    }
+
+    async fn scan_ids(&self, start: Option<&str>, limit: Option<usize>) -> Result<Page<ITEM::ID>> {
+        self.storage.scan_ids(start, limit).await
+    }
+
+    async fn scan_ids_with_prefix(
+        &self,
+        prefix: &str,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.storage
+            .scan_ids_with_prefix(prefix, start, limit)
+            .await
+    }
+
+    async fn scan_ids_filtered(
+        &self,
+        filter: &crate::IdFilter,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.storage.scan_ids_filtered(filter, start, limit).await
+    }
+
+    async fn scan_ids_between(
+        &self,
+        from: &ITEM::ID,
+        to: &ITEM::ID,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.storage.scan_ids_between(from, to, start, limit).await
+    }
+
+    async fn list(
+        &self,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<crate::ItemSummary<ITEM::ID>>> {
+        self.storage.list(start, limit).await
+    }
+
+    async fn item_size(&self, id: &ITEM::ID) -> Result<u64> {
+        self.storage.item_size(id).await
+    }
+
+    async fn estimate_cost(&self) -> Result<crate::StorageCost> {
+        self.storage.estimate_cost().await
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        self.storage.shutdown().await
+    }
+
+    async fn fsck(&self, options: &crate::FsckOptions) -> Result<crate::FsckReport> {
+        self.storage.fsck(options).await
+    }
+
+    async fn display_lock(&self, id: &ITEM::ID) -> Result<String> {
+        self.storage.display_lock(id).await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_highest_seen_id(&self) -> Option<ITEM::ID> {
+        self.storage.metadata_highest_seen_id().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_item_count(&self) -> Option<u64> {
+        self.storage.metadata_item_count().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_total_bytes(&self) -> Option<u64> {
+        self.storage.metadata_total_bytes().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_last_write(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.storage.metadata_last_write().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_lock_stats(&self) -> crate::LockStats {
+        self.storage.metadata_lock_stats().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_snapshot(&self) -> crate::MetadataSnapshot {
+        self.storage.metadata_snapshot().await
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn wipe(
+        &self,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        self.storage.wipe(options, progress, cancel).await
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn wipe_matching(
+        &self,
+        filter: &crate::IdFilter,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        self.storage
+            .wipe_matching(filter, options, progress, cancel)
+            .await
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn delete_many(
+        &self,
+        ids: &[ITEM::ID],
+        options: &crate::DeleteOptions,
+    ) -> Result<Vec<crate::DeleteOutcome>> {
+        self.storage.delete_many(ids, options).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_item::TestItem;
+    use crate::Codec;
+    use crate::Encoded;
+    use crate::ItemFormat;
+    use crate::NativeFormat;
+    use crate::RecordCodec;
+    use crate::Storage;
+    use crate::StorageCodec;
+    use crate::StorageMemory;
+    use color_eyre::eyre::eyre;
+    use color_eyre::Result;
+
+    async fn save(
+        storage: &StorageCodec<TestItem, StorageMemory<Encoded<TestItem>>>,
+        id: &str,
+        count: u32,
+    ) -> Result<()> {
+        let id = id.to_string();
+        let (lock, _) = storage.lock(&id, "test").await?.success()?;
+        storage
+            .save_and_unlock(&id, &TestItem { count }, lock)
+            .await
+    }
+
+    #[tokio::test]
+    async fn it_keeps_plain_records_readable() -> Result<()> {
+        let mut storage = StorageCodec::new(StorageMemory::default());
+        let id = String::from("plain");
+        save(&storage, &id, 1).await?;
+        assert_eq!(
+            br#"{"count":1}"#.to_vec(),
+            storage.storage().load_raw(&id).await?
+        );
+        assert_eq!(RecordCodec::default(), storage.stored_codec(&id).await?);
+        assert_eq!(TestItem { count: 1 }, storage.load(&id).await?);
+
+        let unknown = Codec {
+            zstd_level: None,
+            key_id: Some(7),
+        };
+        assert!(storage.set_codec(unknown).is_err());
+        assert!(!storage.reencode(&id).await?);
+
+        Ok(())
+    }
+
+    /// The count as 4 little endian bytes, after a `b` marker.
+    #[derive(Debug)]
+    struct BinaryFormat;
+
+    impl ItemFormat<TestItem> for BinaryFormat {
+        fn serialize(&self, item: &TestItem) -> Result<Vec<u8>> {
+            let mut data = vec![b'b'];
+            data.extend(item.count.to_le_bytes());
+            Ok(data)
+        }
+        fn deserialize(&self, data: &[u8]) -> Result<TestItem> {
+            match data {
+                [b'b', count @ ..] => Ok(TestItem {
+                    count: u32::from_le_bytes(count.try_into()?),
+                }),
+                _ => Err(eyre!("Not binary")),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn it_migrates_formats() -> Result<()> {
+        let mut storage = StorageCodec::new(StorageMemory::default());
+        save(&storage, "a", 1).await?;
+        save(&storage, "b", 2).await?;
+        assert!(storage.set_formats(Vec::new()).is_err());
+        storage.set_formats(vec![Box::new(BinaryFormat), Box::new(NativeFormat)])?;
+        storage.set_reencode_on_load(true)?;
+        save(&storage, "c", 3).await?;
+        let binary = |count: u32| [&[b'b'][..], &count.to_le_bytes()].concat();
+        let c = String::from("c");
+        assert_eq!(binary(3), storage.storage().load_raw(&c).await?);
+
+        // lazily
+        let a = String::from("a");
+        assert_eq!(TestItem { count: 1 }, storage.load(&a).await?);
+        assert_eq!(binary(1), storage.storage().load_raw(&a).await?);
+        // in bulk
+        assert_eq!(1, storage.reencode_all(None).await?);
+        let b = String::from("b");
+        assert_eq!(binary(2), storage.storage().load_raw(&b).await?);
+        assert_eq!(TestItem { count: 2 }, storage.load(&b).await?);
+        assert_eq!(0, storage.reencode_all(None).await?);
+
+        Ok(())
+    }
+
+    #[cfg(all(feature = "zstd", feature = "encryption"))]
+    #[tokio::test]
+    async fn it_rotates_codecs() -> Result<()> {
+        let mut storage = StorageCodec::new(StorageMemory::default());
+        save(&storage, "a", 1).await?;
+        storage.set_codec(Codec {
+            zstd_level: Some(3),
+            key_id: None,
+        })?;
+        save(&storage, "b", 2).await?;
+        storage.add_key(1, [1; 32])?;
+        storage.set_codec(Codec {
+            zstd_level: Some(3),
+            key_id: Some(1),
+        })?;
+        save(&storage, "c", 3).await?;
+
+        let stored = |zstd, key_id| RecordCodec { zstd, key_id };
+        for (id, count, codec) in [
+            ("a", 1, stored(false, None)),
+            ("b", 2, stored(true, None)),
+            ("c", 3, stored(true, Some(1))),
+        ] {
+            let id = id.to_string();
+            assert_eq!(TestItem { count }, storage.load(&id).await?);
+            assert_eq!(codec, storage.stored_codec(&id).await?);
+        }
+        let c = String::from("c");
+        assert!(!storage
+            .storage()
+            .load_raw(&c)
+            .await?
+            .windows(9)
+            .any(|w| w == br#""count":3"#));
+
+        // rotate
+        storage.add_key(2, [2; 32])?;
+        storage.set_codec(Codec {
+            zstd_level: None,
+            key_id: Some(2),
+        })?;
+        for id in ["a", "b", "c"] {
+            assert!(storage.reencode(&id.to_string()).await?);
+        }
+        assert!(!storage.reencode(&c).await?);
+        assert_eq!(stored(false, Some(2)), storage.stored_codec(&c).await?);
+        assert_eq!(TestItem { count: 3 }, storage.load(&c).await?);
+
+        // records are bound to their id
+        let d = String::from("d");
+        let data = storage.storage().load_raw(&c).await?;
+        let (lock, _) = storage.storage().lock(&d, "test").await?.success()?;
+        storage.storage().save_raw(&d, &data, &lock).await?;
+        storage.storage().unlock(&d, lock).await?;
+        assert!(storage.load(&d).await.is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,111 @@
+use crate::StorageId;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+
+const SEPARATOR: char = '/';
+
+/// A hierarchical id made of a parent and a child id, e.g. `player:123/inventory:7`.
+///
+/// Stored as `{parent}/{child}`. The child must not contain a `/`, the parent may,
+/// so composite ids can be nested.
+/// All children of a parent can be scanned via [crate::Storage::scan_ids_with_prefix]
+/// with [CompositeId::prefix].
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CompositeId<P: StorageId, C: StorageId> {
+    parent: P,
+    child: C,
+}
+
+impl<P: StorageId, C: StorageId> CompositeId<P, C> {
+    pub fn new(parent: P, child: C) -> Result<Self> {
+        let child_s = child.to_string();
+        if child_s.contains(SEPARATOR) {
+            return Err(eyre!("Child id {child_s} must not contain '{SEPARATOR}'"));
+        }
+
+        Ok(Self { parent, child })
+    }
+
+    pub fn parent(&self) -> &P {
+        &self.parent
+    }
+
+    pub fn child(&self) -> &C {
+        &self.child
+    }
+
+    /// The prefix shared by all ids with the given parent.
+    pub fn prefix(parent: &P) -> String {
+        format!("{parent}{SEPARATOR}")
+    }
+}
+
+impl<P: StorageId, C: StorageId> std::fmt::Display for CompositeId<P, C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{SEPARATOR}{}", self.parent, self.child)
+    }
+}
+
+impl<P: StorageId, C: StorageId> StorageId for CompositeId<P, C> {
+    fn from_string(id: &str) -> Result<Self> {
+        let Some((parent, child)) = id.rsplit_once(SEPARATOR) else {
+            return Err(eyre!("Invalid composite id {id}, missing '{SEPARATOR}'"));
+        };
+        let parent = P::from_string(parent)?;
+        let child = C::from_string(child)?;
+
+        Ok(Self { parent, child })
+    }
+
+    /// Keeps the parent of `previous` and generates a new child.
+    fn generate_new(previous: Option<&Self>) -> Self {
+        match previous {
+            Some(previous) => Self {
+                parent: previous.parent.clone(),
+                child: C::generate_new(Some(&previous.child)),
+            },
+            None => Self {
+                parent: P::generate_new(None),
+                child: C::generate_new(None),
+            },
+        }
+    }
+
+    fn is_valid_format(id: &str) -> bool {
+        match id.rsplit_once(SEPARATOR) {
+            Some((parent, child)) => P::is_valid_format(parent) && C::is_valid_format(child),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::CompositeId;
+    use crate::SequentialId;
+    use crate::StorageId;
+
+    type InventoryId = CompositeId<String, SequentialId>;
+
+    #[test]
+    fn it_parses_and_formats_composite_ids() -> color_eyre::Result<()> {
+        let id = InventoryId::from_string("player:123/7")?;
+        assert_eq!("player:123", id.parent());
+        assert_eq!(7, id.child().value());
+        assert_eq!("player:123/7", id.to_string());
+        assert_eq!("player:123/", InventoryId::prefix(id.parent()));
+
+        let next = InventoryId::generate_new(Some(&id));
+        assert_eq!("player:123/8", next.to_string());
+
+        let nested = CompositeId::<InventoryId, SequentialId>::from_string("player:123/7/2")?;
+        assert_eq!(&id, nested.parent());
+
+        assert!(!InventoryId::is_valid_format("player:123"));
+        assert!(!InventoryId::is_valid_format("player:123/seven"));
+        assert!(InventoryId::new(String::from("player:123"), SequentialId::from(1)).is_ok());
+        assert!(CompositeId::new(String::from("player"), String::from("a/b")).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+use crate::StorageId;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use sha2::Digest;
+use sha2::Sha256;
+
+const LEN: usize = 32;
+
+/// A content addressed id, the SHA-256 of the serialized item, stored as lowercase hex.
+///
+/// Use [crate::StorageItem::content_id] to get the id for an item,
+/// identical items end up with the same id, so they are only stored once.
+///
+/// Note: [StorageId::generate_new] has no content to hash and returns a random id.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ContentId([u8; LEN]);
+
+impl ContentId {
+    pub fn from_data(data: &[u8]) -> Self {
+        Self(Sha256::digest(data).into())
+    }
+
+    pub fn bytes(&self) -> &[u8; LEN] {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for ContentId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for b in self.0 {
+            write!(f, "{b:02x}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl StorageId for ContentId {
+    fn from_string(id: &str) -> Result<Self> {
+        if !Self::is_valid_format(id) {
+            return Err(eyre!("Invalid content id {id}"));
+        }
+        let mut bytes = [0u8; LEN];
+        for (i, b) in bytes.iter_mut().enumerate() {
+            *b = u8::from_str_radix(&id[i * 2..i * 2 + 2], 16)?;
+        }
+
+        Ok(Self(bytes))
+    }
+
+    fn generate_new(_previous: Option<&Self>) -> Self {
+        Self(rand::random())
+    }
+
+    fn is_valid_format(id: &str) -> bool {
+        id.len() == LEN * 2 && id.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ContentId;
+    use crate::StorageId;
+
+    #[test]
+    fn it_hashes_content() -> color_eyre::Result<()> {
+        let id = ContentId::from_data(b"replay");
+        assert_eq!(id, ContentId::from_data(b"replay"));
+        assert_ne!(id, ContentId::from_data(b"replay2"));
+
+        let empty = ContentId::from_data(b"");
+        assert_eq!(
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            empty.to_string()
+        );
+        assert_eq!(id, ContentId::from_string(&id.to_string())?);
+        assert!(!ContentId::is_valid_format(&id.to_string().to_uppercase()));
+        assert!(!ContentId::is_valid_format("e3b0"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,231 @@
+use crate::storage_disk::decode_file_name;
+use crate::storage_disk::decompress_item_data;
+use crate::storage_item::deserialize_item;
+use crate::ChangeEvent;
+use crate::ChangeEventReceiver;
+use crate::StorageDisk;
+use crate::StorageDiskLayout;
+use crate::StorageId;
+use crate::StorageItem;
+use crate::StorageLock;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use core::marker::PhantomData;
+use notify::EventKind;
+use notify::RecursiveMode;
+use notify::Watcher;
+use std::path::Path;
+use std::path::PathBuf;
+use tokio::fs;
+use tokio::sync::mpsc;
+
+/// Watches the folder of a [StorageDisk] and turns file changes into [ChangeEvent]s.
+///
+/// This also reports changes made by other processes sharing the folder.
+/// File systems report changes, not content, so `old` is always `None`,
+/// and a single save can be reported more than once.
+#[derive(Debug)]
+pub struct DiskChangeFeed<ITEM: StorageItem> {
+    base_path: PathBuf,
+    extension: String,
+    layout: StorageDiskLayout,
+    buffer_size: usize,
+    item_type: PhantomData<ITEM>,
+}
+
+impl<ITEM: StorageItem + Send + 'static> DiskChangeFeed<ITEM> {
+    pub fn new(storage: &StorageDisk<ITEM>) -> Self {
+        Self {
+            base_path: storage.base_path().to_path_buf(),
+            extension: storage.extension().to_string_lossy().to_string(),
+            layout: storage.layout(),
+            buffer_size: 1024,
+            item_type: PhantomData,
+        }
+    }
+
+    /// How many events can be buffered before watching waits for the receiver.
+    pub fn set_buffer_size(&mut self, buffer_size: usize) -> Result<()> {
+        self.buffer_size = buffer_size.max(1);
+
+        Ok(())
+    }
+
+    /// Starts watching in a background task.
+    /// The task stops when the returned receiver is dropped.
+    pub async fn start(self) -> Result<ChangeEventReceiver<ITEM>> {
+        let (raw_tx, mut raw_rx) = mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            // only fails when the task is gone
+            let _ = raw_tx.send(event);
+        })
+        .map_err(|e| eyre!("Can't create watcher -> {e:?}"))?;
+
+        let recursive_mode = match self.layout {
+            StorageDiskLayout::Flat => RecursiveMode::NonRecursive,
+            StorageDiskLayout::Sharded => RecursiveMode::Recursive,
+        };
+        watcher
+            .watch(&self.base_path, recursive_mode)
+            .map_err(|e| eyre!("Can't watch {:?} -> {e:?}", &self.base_path))?;
+
+        let (tx, rx) = mpsc::channel(self.buffer_size);
+        tokio::spawn(async move {
+            // keep the watcher alive as long as the task runs
+            let _watcher = watcher;
+            loop {
+                let event = tokio::select! {
+                    event = raw_rx.recv() => event,
+                    _ = tx.closed() => None,
+                };
+                let event: notify::Event = match event {
+                    Some(Ok(event)) => event,
+                    Some(Err(e)) => {
+                        tracing::warn!("Watch error for {:?} -> {e:?}", &self.base_path);
+                        continue;
+                    }
+                    None => break,
+                };
+                if matches!(event.kind, EventKind::Access(_)) {
+                    continue;
+                }
+                for path in event.paths.iter() {
+                    let event = match self.event_from_path(path).await {
+                        Ok(Some(event)) => event,
+                        Ok(None) => continue,
+                        Err(e) => {
+                            tracing::warn!("Can't handle change of {path:?} -> {e:?}");
+                            continue;
+                        }
+                    };
+                    if tx.send(event).await.is_err() {
+                        break;
+                    }
+                }
+            }
+            tracing::info!("Change feed for {:?} stopped", &self.base_path);
+        });
+
+        Ok(rx)
+    }
+
+    /// Turns the *current* state of the file at `path` into an event.
+    /// Returns `None` for unrelated files, e.g. temporary files.
+    async fn event_from_path(&self, path: &Path) -> Result<Option<ChangeEvent<ITEM>>> {
+        let Some(file_name) = path.file_name() else {
+            return Ok(None);
+        };
+        let file_name = file_name.to_string_lossy();
+        let exists = fs::metadata(path).await.is_ok();
+
+        if let Some(name) = file_name.strip_suffix(&format!(".{}", self.extension)) {
+            let Some(id) = decode_file_name(name) else {
+                return Ok(None);
+            };
+            let id = ITEM::ID::from_string(&id)?;
+            if !exists {
+                return Ok(Some(ChangeEvent::Deleted { id, old: None }));
+            }
+            let new = match fs::read(path).await {
+                Ok(data) => deserialize_item::<ITEM>(&decompress_item_data(data)?)?,
+                // removed in the meantime
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    return Ok(Some(ChangeEvent::Deleted { id, old: None }));
+                }
+                Err(e) => return Err(eyre!("Can't read {path:?} -> {e:?}")),
+            };
+            Ok(Some(ChangeEvent::Saved { id, old: None, new }))
+        } else if let Some(name) = file_name.strip_suffix(".lock") {
+            let Some(id) = decode_file_name(name) else {
+                return Ok(None);
+            };
+            let id = ITEM::ID::from_string(&id)?;
+            if !exists {
+                return Ok(Some(ChangeEvent::Unlocked { id }));
+            }
+            let who = match fs::read(path).await {
+                Ok(lock_json) => serde_json::from_slice::<StorageLock>(&lock_json)
+                    .map(|lock| lock.who().to_string())
+                    .unwrap_or_default(),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    return Ok(Some(ChangeEvent::Unlocked { id }));
+                }
+                Err(e) => return Err(eyre!("Can't read {path:?} -> {e:?}")),
+            };
+            Ok(Some(ChangeEvent::Locked { id, who }))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ChangeEvent;
+    use crate::DiskChangeFeed;
+    use crate::Storage;
+    use crate::StorageDisk;
+    use crate::StorageItem;
+    use color_eyre::Result;
+    use serde::Deserialize;
+    use serde::Serialize;
+    use std::env;
+    use std::path::Path;
+    use std::time::Duration;
+
+    #[derive(Default, Debug, Serialize, Deserialize, PartialEq)]
+    struct TestItem {
+        count: u32,
+    }
+
+    impl StorageItem for TestItem {
+        type ID = String;
+
+        fn serialize(&self) -> Result<Vec<u8>> {
+            let json = serde_json::to_string_pretty(&self)?;
+
+            Ok(json.into())
+        }
+        fn deserialize(data: &[u8]) -> Result<Self> {
+            let i = serde_json::from_slice(data)?;
+
+            Ok(i)
+        }
+    }
+
+    #[tokio::test]
+    async fn it_reports_external_saves() -> Result<()> {
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_items_watch");
+        let extension = Path::new("test_item");
+
+        let mut storage = StorageDisk::<TestItem>::new(&path, extension).await;
+        storage.ensure_storage_exists().await?;
+        let mut events = DiskChangeFeed::new(&storage).start().await?;
+
+        // a separate instance, like another process
+        let mut other = StorageDisk::<TestItem>::new(&path, extension).await;
+        other.ensure_storage_exists().await?;
+        let item_id = other.create().await?;
+        let (lock, _) = other.lock(&item_id, "OTHER").await?.success()?;
+        other
+            .save_and_unlock(&item_id, &TestItem { count: 42 }, lock)
+            .await?;
+
+        let saved = tokio::time::timeout(Duration::from_secs(5), async {
+            while let Some(event) = events.recv().await {
+                if let ChangeEvent::Saved { id, new, .. } = event {
+                    if id == item_id {
+                        return Some(new);
+                    }
+                }
+            }
+            None
+        })
+        .await?;
+        assert_eq!(Some(TestItem { count: 42 }), saved);
+
+        Ok(())
+    }
+}
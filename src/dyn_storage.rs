@@ -0,0 +1,753 @@
+use crate::LockResult;
+use crate::Page;
+use crate::PayloadReader;
+use crate::Storage;
+use crate::StorageItem;
+use crate::StorageLock;
+use async_trait::async_trait;
+use chrono::DateTime;
+use chrono::Utc;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncRead;
+
+/// A shared storage of any backend, e.g. built via [crate::StorageConfig] or [crate::from_url].
+///
+/// [Storage] is kept object safe on purpose, so boxed and shared storages work everywhere a backend does,
+/// e.g. inside [crate::StorageCached] or any other wrapper.
+/// New methods must not be generic, futures are boxed via `async_trait`, streams are returned boxed, e.g. [PayloadReader].
+/// Generic helpers, e.g. [crate::paginate], take `S: Storage<ITEM> + ?Sized` instead.
+pub type DynStorage<ITEM> = Arc<dyn Storage<ITEM>>;
+
+#[async_trait]
+impl<ITEM, S> Storage<ITEM> for Box<S>
+where
+    ITEM: StorageItem + Send,
+    S: Storage<ITEM> + ?Sized,
+{
+    async fn ensure_storage_exists(&mut self) -> Result<()> {
+        (**self).ensure_storage_exists().await
+    }
+
+    fn capabilities(&self) -> crate::Capabilities {
+        (**self).capabilities()
+    }
+
+    async fn create(&self) -> Result<ITEM::ID> {
+        (**self).create().await
+    }
+
+    async fn exists(&self, id: &ITEM::ID) -> Result<bool> {
+        (**self).exists(id).await
+    }
+
+    async fn load(&self, id: &ITEM::ID) -> Result<ITEM> {
+        (**self).load(id).await
+    }
+
+    async fn try_load(&self, id: &ITEM::ID) -> Result<Option<ITEM>> {
+        (**self).try_load(id).await
+    }
+
+    async fn exists_with(&self, id: &ITEM::ID, options: &crate::OpOptions) -> Result<bool> {
+        (**self).exists_with(id, options).await
+    }
+
+    async fn load_with(&self, id: &ITEM::ID, options: &crate::OpOptions) -> Result<ITEM> {
+        (**self).load_with(id, options).await
+    }
+
+    async fn load_many(&self, ids: &[ITEM::ID]) -> Result<Vec<Option<ITEM>>> {
+        (**self).load_many(ids).await
+    }
+
+    async fn prefetch(&self, ids: &[ITEM::ID]) -> Result<()> {
+        (**self).prefetch(ids).await
+    }
+
+    async fn save(&self, id: &ITEM::ID, item: &ITEM, lock: &StorageLock) -> Result<()> {
+        (**self).save(id, item, lock).await
+    }
+
+    async fn lock(&self, id: &ITEM::ID, who: &str) -> Result<LockResult<ITEM>> {
+        (**self).lock(id, who).await
+    }
+
+    async fn lock_and_load_many(
+        &self,
+        ids: &[ITEM::ID],
+        who: &str,
+    ) -> Result<Vec<crate::LockManyOutcome<ITEM>>> {
+        (**self).lock_and_load_many(ids, who).await
+    }
+
+    async fn unlock(&self, id: &ITEM::ID, lock: StorageLock) -> Result<()> {
+        (**self).unlock(id, lock).await
+    }
+
+    async fn save_and_unlock(&self, id: &ITEM::ID, item: &ITEM, lock: StorageLock) -> Result<()> {
+        (**self).save_and_unlock(id, item, lock).await
+    }
+
+    async fn handoff_lock(
+        &self,
+        id: &ITEM::ID,
+        lock: StorageLock,
+        new_who: &str,
+    ) -> Result<StorageLock> {
+        (**self).handoff_lock(id, lock, new_who).await
+    }
+
+    async fn load_raw(&self, id: &ITEM::ID) -> Result<Vec<u8>> {
+        (**self).load_raw(id).await
+    }
+
+    async fn save_raw(&self, id: &ITEM::ID, data: &[u8], lock: &StorageLock) -> Result<()> {
+        (**self).save_raw(id, data, lock).await
+    }
+
+    async fn load_stream(&self, id: &ITEM::ID) -> Result<PayloadReader> {
+        (**self).load_stream(id).await
+    }
+
+    async fn save_stream(
+        &self,
+        id: &ITEM::ID,
+        reader: &mut (dyn AsyncRead + Send + Unpin),
+        lock: &StorageLock,
+    ) -> Result<()> {
+        (**self).save_stream(id, reader, lock).await
+    }
+
+    async fn put_blob(
+        &self,
+        id: &ITEM::ID,
+        name: &str,
+        data: &[u8],
+        lock: &StorageLock,
+    ) -> Result<()> {
+        (**self).put_blob(id, name, data, lock).await
+    }
+
+    async fn get_blob(&self, id: &ITEM::ID, name: &str) -> Result<Option<Vec<u8>>> {
+        (**self).get_blob(id, name).await
+    }
+
+    async fn list_blobs(&self, id: &ITEM::ID) -> Result<Vec<String>> {
+        (**self).list_blobs(id).await
+    }
+
+    async fn put_meta(&self, key: &str, value: &[u8]) -> Result<()> {
+        (**self).put_meta(key, value).await
+    }
+
+    async fn get_meta(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        (**self).get_meta(key).await
+    }
+
+    async fn add_alias(&self, alias: &ITEM::ID, canonical: &ITEM::ID) -> Result<()> {
+        (**self).add_alias(alias, canonical).await
+    }
+
+    async fn remove_alias(&self, alias: &ITEM::ID) -> Result<()> {
+        (**self).remove_alias(alias).await
+    }
+
+    async fn resolve_alias(&self, id: &ITEM::ID) -> Result<ITEM::ID> {
+        (**self).resolve_alias(id).await
+    }
+
+    async fn add_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        (**self).add_link(from, relation, to, lock).await
+    }
+
+    async fn remove_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        (**self).remove_link(from, relation, to, lock).await
+    }
+
+    async fn links_of(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        (**self).links_of(id, relation).await
+    }
+
+    async fn linked_from(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        (**self).linked_from(id, relation).await
+    }
+
+    async fn force_unlock(&self, id: &ITEM::ID) -> Result<Option<StorageLock>> {
+        (**self).force_unlock(id).await
+    }
+
+    async fn force_unlock_if_held(
+        &self,
+        id: &ITEM::ID,
+        who: &str,
+        when: DateTime<Utc>,
+    ) -> Result<bool> {
+        (**self).force_unlock_if_held(id, who, when).await
+    }
+
+    async fn remove_orphaned_locks(&self, max_age: Duration) -> Result<usize> {
+        (**self).remove_orphaned_locks(max_age).await
+    }
+
+    async fn force_unlock_all(&self, who_prefix: &str) -> Result<Vec<ITEM::ID>> {
+        (**self).force_unlock_all(who_prefix).await
+    }
+
+    async fn verify_lock(&self, id: &ITEM::ID, lock: &StorageLock) -> Result<bool> {
+        (**self).verify_lock(id, lock).await
+    }
+
+    async fn verify_lock_with(
+        &self,
+        id: &ITEM::ID,
+        lock: &StorageLock,
+        options: &crate::OpOptions,
+    ) -> Result<bool> {
+        (**self).verify_lock_with(id, lock, options).await
+    }
+
+    async fn all_ids(&self) -> Result<Vec<ITEM::ID>> {
+        (**self).all_ids().await
+    }
+
+    async fn scan_ids(&self, start: Option<&str>, limit: Option<usize>) -> Result<Page<ITEM::ID>> {
+        (**self).scan_ids(start, limit).await
+    }
+
+    async fn scan_ids_with_prefix(
+        &self,
+        prefix: &str,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        (**self).scan_ids_with_prefix(prefix, start, limit).await
+    }
+
+    async fn scan_ids_filtered(
+        &self,
+        filter: &crate::IdFilter,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        (**self).scan_ids_filtered(filter, start, limit).await
+    }
+
+    async fn scan_ids_between(
+        &self,
+        from: &ITEM::ID,
+        to: &ITEM::ID,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        (**self).scan_ids_between(from, to, start, limit).await
+    }
+
+    async fn list(
+        &self,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<crate::ItemSummary<ITEM::ID>>> {
+        (**self).list(start, limit).await
+    }
+
+    async fn item_size(&self, id: &ITEM::ID) -> Result<u64> {
+        (**self).item_size(id).await
+    }
+
+    async fn estimate_cost(&self) -> Result<crate::StorageCost> {
+        (**self).estimate_cost().await
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        (**self).shutdown().await
+    }
+
+    async fn export_item(&self, id: &ITEM::ID) -> Result<crate::ItemArchive> {
+        (**self).export_item(id).await
+    }
+
+    async fn import_item(&self, archive: &crate::ItemArchive) -> Result<ITEM::ID> {
+        (**self).import_item(archive).await
+    }
+
+    async fn fsck(&self, options: &crate::FsckOptions) -> Result<crate::FsckReport> {
+        (**self).fsck(options).await
+    }
+
+    async fn scan_items(
+        &self,
+        prefix: &str,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<(ITEM::ID, ITEM)>> {
+        (**self).scan_items(prefix, start, limit).await
+    }
+
+    async fn display_lock(&self, id: &ITEM::ID) -> Result<String> {
+        (**self).display_lock(id).await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_highest_seen_id(&self) -> Option<ITEM::ID> {
+        (**self).metadata_highest_seen_id().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_item_count(&self) -> Option<u64> {
+        (**self).metadata_item_count().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_total_bytes(&self) -> Option<u64> {
+        (**self).metadata_total_bytes().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_last_write(&self) -> Option<DateTime<Utc>> {
+        (**self).metadata_last_write().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_lock_stats(&self) -> crate::LockStats {
+        (**self).metadata_lock_stats().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_snapshot(&self) -> crate::MetadataSnapshot {
+        (**self).metadata_snapshot().await
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn wipe(
+        &self,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        (**self).wipe(options, progress, cancel).await
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn wipe_matching(
+        &self,
+        filter: &crate::IdFilter,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        (**self)
+            .wipe_matching(filter, options, progress, cancel)
+            .await
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn delete_many(
+        &self,
+        ids: &[ITEM::ID],
+        options: &crate::DeleteOptions,
+    ) -> Result<Vec<crate::DeleteOutcome>> {
+        (**self).delete_many(ids, options).await
+    }
+}
+
+/// [Storage::ensure_storage_exists] needs the only reference, all other methods work on shared storages.
+#[async_trait]
+impl<ITEM, S> Storage<ITEM> for Arc<S>
+where
+    ITEM: StorageItem + Send,
+    S: Storage<ITEM> + ?Sized,
+{
+    async fn ensure_storage_exists(&mut self) -> Result<()> {
+        match Arc::get_mut(self) {
+            Some(storage) => storage.ensure_storage_exists().await,
+            None => Err(eyre!(
+                "Can't ensure a shared storage exists, call it before sharing"
+            )),
+        }
+    }
+
+    fn capabilities(&self) -> crate::Capabilities {
+        (**self).capabilities()
+    }
+
+    async fn create(&self) -> Result<ITEM::ID> {
+        (**self).create().await
+    }
+
+    async fn exists(&self, id: &ITEM::ID) -> Result<bool> {
+        (**self).exists(id).await
+    }
+
+    async fn load(&self, id: &ITEM::ID) -> Result<ITEM> {
+        (**self).load(id).await
+    }
+
+    async fn try_load(&self, id: &ITEM::ID) -> Result<Option<ITEM>> {
+        (**self).try_load(id).await
+    }
+
+    async fn exists_with(&self, id: &ITEM::ID, options: &crate::OpOptions) -> Result<bool> {
+        (**self).exists_with(id, options).await
+    }
+
+    async fn load_with(&self, id: &ITEM::ID, options: &crate::OpOptions) -> Result<ITEM> {
+        (**self).load_with(id, options).await
+    }
+
+    async fn load_many(&self, ids: &[ITEM::ID]) -> Result<Vec<Option<ITEM>>> {
+        (**self).load_many(ids).await
+    }
+
+    async fn prefetch(&self, ids: &[ITEM::ID]) -> Result<()> {
+        (**self).prefetch(ids).await
+    }
+
+    async fn save(&self, id: &ITEM::ID, item: &ITEM, lock: &StorageLock) -> Result<()> {
+        (**self).save(id, item, lock).await
+    }
+
+    async fn lock(&self, id: &ITEM::ID, who: &str) -> Result<LockResult<ITEM>> {
+        (**self).lock(id, who).await
+    }
+
+    async fn lock_and_load_many(
+        &self,
+        ids: &[ITEM::ID],
+        who: &str,
+    ) -> Result<Vec<crate::LockManyOutcome<ITEM>>> {
+        (**self).lock_and_load_many(ids, who).await
+    }
+
+    async fn unlock(&self, id: &ITEM::ID, lock: StorageLock) -> Result<()> {
+        (**self).unlock(id, lock).await
+    }
+
+    async fn save_and_unlock(&self, id: &ITEM::ID, item: &ITEM, lock: StorageLock) -> Result<()> {
+        (**self).save_and_unlock(id, item, lock).await
+    }
+
+    async fn handoff_lock(
+        &self,
+        id: &ITEM::ID,
+        lock: StorageLock,
+        new_who: &str,
+    ) -> Result<StorageLock> {
+        (**self).handoff_lock(id, lock, new_who).await
+    }
+
+    async fn load_raw(&self, id: &ITEM::ID) -> Result<Vec<u8>> {
+        (**self).load_raw(id).await
+    }
+
+    async fn save_raw(&self, id: &ITEM::ID, data: &[u8], lock: &StorageLock) -> Result<()> {
+        (**self).save_raw(id, data, lock).await
+    }
+
+    async fn load_stream(&self, id: &ITEM::ID) -> Result<PayloadReader> {
+        (**self).load_stream(id).await
+    }
+
+    async fn save_stream(
+        &self,
+        id: &ITEM::ID,
+        reader: &mut (dyn AsyncRead + Send + Unpin),
+        lock: &StorageLock,
+    ) -> Result<()> {
+        (**self).save_stream(id, reader, lock).await
+    }
+
+    async fn put_blob(
+        &self,
+        id: &ITEM::ID,
+        name: &str,
+        data: &[u8],
+        lock: &StorageLock,
+    ) -> Result<()> {
+        (**self).put_blob(id, name, data, lock).await
+    }
+
+    async fn get_blob(&self, id: &ITEM::ID, name: &str) -> Result<Option<Vec<u8>>> {
+        (**self).get_blob(id, name).await
+    }
+
+    async fn list_blobs(&self, id: &ITEM::ID) -> Result<Vec<String>> {
+        (**self).list_blobs(id).await
+    }
+
+    async fn put_meta(&self, key: &str, value: &[u8]) -> Result<()> {
+        (**self).put_meta(key, value).await
+    }
+
+    async fn get_meta(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        (**self).get_meta(key).await
+    }
+
+    async fn add_alias(&self, alias: &ITEM::ID, canonical: &ITEM::ID) -> Result<()> {
+        (**self).add_alias(alias, canonical).await
+    }
+
+    async fn remove_alias(&self, alias: &ITEM::ID) -> Result<()> {
+        (**self).remove_alias(alias).await
+    }
+
+    async fn resolve_alias(&self, id: &ITEM::ID) -> Result<ITEM::ID> {
+        (**self).resolve_alias(id).await
+    }
+
+    async fn add_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        (**self).add_link(from, relation, to, lock).await
+    }
+
+    async fn remove_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        (**self).remove_link(from, relation, to, lock).await
+    }
+
+    async fn links_of(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        (**self).links_of(id, relation).await
+    }
+
+    async fn linked_from(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        (**self).linked_from(id, relation).await
+    }
+
+    async fn force_unlock(&self, id: &ITEM::ID) -> Result<Option<StorageLock>> {
+        (**self).force_unlock(id).await
+    }
+
+    async fn force_unlock_if_held(
+        &self,
+        id: &ITEM::ID,
+        who: &str,
+        when: DateTime<Utc>,
+    ) -> Result<bool> {
+        (**self).force_unlock_if_held(id, who, when).await
+    }
+
+    async fn remove_orphaned_locks(&self, max_age: Duration) -> Result<usize> {
+        (**self).remove_orphaned_locks(max_age).await
+    }
+
+    async fn force_unlock_all(&self, who_prefix: &str) -> Result<Vec<ITEM::ID>> {
+        (**self).force_unlock_all(who_prefix).await
+    }
+
+    async fn verify_lock(&self, id: &ITEM::ID, lock: &StorageLock) -> Result<bool> {
+        (**self).verify_lock(id, lock).await
+    }
+
+    async fn verify_lock_with(
+        &self,
+        id: &ITEM::ID,
+        lock: &StorageLock,
+        options: &crate::OpOptions,
+    ) -> Result<bool> {
+        (**self).verify_lock_with(id, lock, options).await
+    }
+
+    async fn all_ids(&self) -> Result<Vec<ITEM::ID>> {
+        (**self).all_ids().await
+    }
+
+    async fn scan_ids(&self, start: Option<&str>, limit: Option<usize>) -> Result<Page<ITEM::ID>> {
+        (**self).scan_ids(start, limit).await
+    }
+
+    async fn scan_ids_with_prefix(
+        &self,
+        prefix: &str,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        (**self).scan_ids_with_prefix(prefix, start, limit).await
+    }
+
+    async fn scan_ids_filtered(
+        &self,
+        filter: &crate::IdFilter,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        (**self).scan_ids_filtered(filter, start, limit).await
+    }
+
+    async fn scan_ids_between(
+        &self,
+        from: &ITEM::ID,
+        to: &ITEM::ID,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        (**self).scan_ids_between(from, to, start, limit).await
+    }
+
+    async fn list(
+        &self,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<crate::ItemSummary<ITEM::ID>>> {
+        (**self).list(start, limit).await
+    }
+
+    async fn item_size(&self, id: &ITEM::ID) -> Result<u64> {
+        (**self).item_size(id).await
+    }
+
+    async fn estimate_cost(&self) -> Result<crate::StorageCost> {
+        (**self).estimate_cost().await
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        (**self).shutdown().await
+    }
+
+    async fn export_item(&self, id: &ITEM::ID) -> Result<crate::ItemArchive> {
+        (**self).export_item(id).await
+    }
+
+    async fn import_item(&self, archive: &crate::ItemArchive) -> Result<ITEM::ID> {
+        (**self).import_item(archive).await
+    }
+
+    async fn fsck(&self, options: &crate::FsckOptions) -> Result<crate::FsckReport> {
+        (**self).fsck(options).await
+    }
+
+    async fn scan_items(
+        &self,
+        prefix: &str,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<(ITEM::ID, ITEM)>> {
+        (**self).scan_items(prefix, start, limit).await
+    }
+
+    async fn display_lock(&self, id: &ITEM::ID) -> Result<String> {
+        (**self).display_lock(id).await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_highest_seen_id(&self) -> Option<ITEM::ID> {
+        (**self).metadata_highest_seen_id().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_item_count(&self) -> Option<u64> {
+        (**self).metadata_item_count().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_total_bytes(&self) -> Option<u64> {
+        (**self).metadata_total_bytes().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_last_write(&self) -> Option<DateTime<Utc>> {
+        (**self).metadata_last_write().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_lock_stats(&self) -> crate::LockStats {
+        (**self).metadata_lock_stats().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_snapshot(&self) -> crate::MetadataSnapshot {
+        (**self).metadata_snapshot().await
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn wipe(
+        &self,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        (**self).wipe(options, progress, cancel).await
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn wipe_matching(
+        &self,
+        filter: &crate::IdFilter,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        (**self)
+            .wipe_matching(filter, options, progress, cancel)
+            .await
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn delete_many(
+        &self,
+        ids: &[ITEM::ID],
+        options: &crate::DeleteOptions,
+    ) -> Result<Vec<crate::DeleteOutcome>> {
+        (**self).delete_many(ids, options).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_item::TestItem;
+    use crate::DynStorage;
+    use crate::Storage;
+    use crate::StorageCached;
+    use crate::StorageMemory;
+    use color_eyre::Result;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn it_wraps_boxed_and_shared_storages() -> Result<()> {
+        let boxed: Box<dyn Storage<TestItem>> = Box::new(StorageMemory::default());
+        let mut cached = StorageCached::new(boxed);
+        cached.ensure_storage_exists().await?;
+        let id = cached.create().await?;
+        let (lock, _) = cached.lock(&id, "test").await?.success()?;
+        cached
+            .save_and_unlock(&id, &TestItem { count: 3 }, lock)
+            .await?;
+        assert_eq!(TestItem { count: 3 }, cached.load(&id).await?);
+
+        let mut shared: DynStorage<TestItem> = Arc::new(StorageMemory::default());
+        shared.ensure_storage_exists().await?;
+        let other = shared.clone();
+        assert!(shared.ensure_storage_exists().await.is_err());
+        let id = other.create().await?;
+        let (lock, _) = other.lock(&id, "test").await?.success()?;
+        other
+            .save_and_unlock(&id, &TestItem { count: 4 }, lock)
+            .await?;
+        assert_eq!(TestItem { count: 4 }, shared.load(&id).await?);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,425 @@
+use crate::storage_dynamodb::id_from_key;
+use crate::storage_item::deserialize_item;
+use crate::ChangeEvent;
+use crate::ChangeEventReceiver;
+use crate::DynamoDbAttributeNames;
+use crate::DynamoDbKeyHasher;
+use crate::IdCodec;
+use crate::Redacted;
+use crate::StorageDynamoDb;
+use crate::StorageId;
+use crate::StorageItem;
+use crate::StorageLock;
+use aws_config::SdkConfig;
+use aws_sdk_dynamodb::types::StreamSpecification;
+use aws_sdk_dynamodb::types::StreamViewType;
+use aws_sdk_dynamodbstreams::types::AttributeValue;
+use aws_sdk_dynamodbstreams::types::OperationType;
+use aws_sdk_dynamodbstreams::types::Record;
+use aws_sdk_dynamodbstreams::types::ShardIteratorType;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use core::marker::PhantomData;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+type Image = HashMap<String, AttributeValue>;
+
+/// Consumes the DynamoDB Stream of a [StorageDynamoDb] table and turns it into [ChangeEvent]s.
+///
+/// The stream must include old and new images, see [DynamoDbChangeFeed::ensure_stream_enabled].
+/// Only changes made after [DynamoDbChangeFeed::start] are reported.
+#[derive(Debug)]
+pub struct DynamoDbChangeFeed<ITEM: StorageItem> {
+    table_name: String,
+    endpoint_url: Option<String>,
+    sdk_config: SdkConfig,
+    poll_interval: Duration,
+    buffer_size: usize,
+    attribute_names: DynamoDbAttributeNames,
+    key_prefix: String,
+    key_hasher: Option<Arc<dyn DynamoDbKeyHasher>>,
+    id_codec: Option<Arc<dyn IdCodec>>,
+    item_type: PhantomData<ITEM>,
+}
+
+impl<ITEM: StorageItem + Send + 'static> DynamoDbChangeFeed<ITEM> {
+    pub async fn new(storage: &StorageDynamoDb<ITEM>) -> Self {
+        Self {
+            table_name: storage.table_name().to_string(),
+            endpoint_url: storage.endpoint_url().map(String::from),
+            sdk_config: storage.sdk_config().await,
+            poll_interval: Duration::from_secs(1),
+            buffer_size: 1024,
+            attribute_names: storage.attribute_names().clone(),
+            key_prefix: storage.key_prefix().to_string(),
+            key_hasher: storage.key_hasher(),
+            id_codec: storage.id_codec(),
+            item_type: PhantomData,
+        }
+    }
+
+    /// How long to wait between polls when a shard had no new records.
+    pub fn set_poll_interval(&mut self, poll_interval: Duration) -> Result<()> {
+        self.poll_interval = poll_interval;
+
+        Ok(())
+    }
+
+    /// How many events can be buffered before polling waits for the receiver.
+    pub fn set_buffer_size(&mut self, buffer_size: usize) -> Result<()> {
+        self.buffer_size = buffer_size.max(1);
+
+        Ok(())
+    }
+
+    fn dynamodb_client(&self) -> aws_sdk_dynamodb::Client {
+        let config = aws_sdk_dynamodb::config::Builder::from(&self.sdk_config);
+        let config = if let Some(endpoint_url) = &self.endpoint_url {
+            config.endpoint_url(endpoint_url)
+        } else {
+            config
+        };
+        #[cfg(feature = "otel")]
+        let config = config.interceptor(crate::otel::OtelContextInterceptor);
+        aws_sdk_dynamodb::Client::from_conf(config.build())
+    }
+
+    fn streams_client(&self) -> aws_sdk_dynamodbstreams::Client {
+        let config = aws_sdk_dynamodbstreams::config::Builder::from(&self.sdk_config);
+        let config = if let Some(endpoint_url) = &self.endpoint_url {
+            config.endpoint_url(endpoint_url)
+        } else {
+            config
+        };
+        #[cfg(feature = "otel")]
+        let config = config.interceptor(crate::otel::OtelContextInterceptor);
+        aws_sdk_dynamodbstreams::Client::from_conf(config.build())
+    }
+
+    /// Enables the table's stream with old and new images, if needed.
+    pub async fn ensure_stream_enabled(&self) -> Result<()> {
+        let client = self.dynamodb_client();
+        let o = client
+            .describe_table()
+            .table_name(&self.table_name)
+            .send()
+            .await
+            .map_err(|e| eyre!("Can't describe table {} -> {e:?}", &self.table_name))?;
+        let specification = o.table().and_then(|t| t.stream_specification());
+        match specification {
+            Some(s)
+                if s.stream_enabled()
+                    && s.stream_view_type() == Some(&StreamViewType::NewAndOldImages) =>
+            {
+                tracing::info!("Table {} has stream enabled", &self.table_name);
+                Ok(())
+            }
+            Some(s) if s.stream_enabled() => Err(eyre!(
+                "Table {} streams {:?}, but the change feed needs NEW_AND_OLD_IMAGES",
+                &self.table_name,
+                s.stream_view_type()
+            )),
+            _ => {
+                tracing::info!("Enabling stream for table {}", &self.table_name);
+                let specification = StreamSpecification::builder()
+                    .stream_enabled(true)
+                    .stream_view_type(StreamViewType::NewAndOldImages)
+                    .build()?;
+                client
+                    .update_table()
+                    .table_name(&self.table_name)
+                    .stream_specification(specification)
+                    .send()
+                    .await
+                    .map_err(|e| eyre!("Can't enable stream for {} -> {e:?}", &self.table_name))?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Starts polling the stream in a background task.
+    /// The task stops when the returned receiver is dropped.
+    pub async fn start(self) -> Result<ChangeEventReceiver<ITEM>> {
+        let o = self
+            .dynamodb_client()
+            .describe_table()
+            .table_name(&self.table_name)
+            .send()
+            .await
+            .map_err(|e| eyre!("Can't describe table {} -> {e:?}", &self.table_name))?;
+        let Some(stream_arn) = o.table().and_then(|t| t.latest_stream_arn()) else {
+            return Err(eyre!("Table {} has no stream", &self.table_name));
+        };
+        let stream_arn = stream_arn.to_string();
+
+        let (tx, rx) = mpsc::channel(self.buffer_size);
+        tokio::spawn(async move {
+            if let Err(e) = self.run(&stream_arn, tx).await {
+                tracing::error!("Change feed for {} stopped -> {e:?}", &self.table_name);
+            }
+        });
+
+        Ok(rx)
+    }
+
+    async fn run(&self, stream_arn: &str, tx: mpsc::Sender<ChangeEvent<ITEM>>) -> Result<()> {
+        let client = self.streams_client();
+        // shard id -> iterator
+        let mut iterators: HashMap<String, String> = HashMap::default();
+        let mut finished_shards: HashSet<String> = HashSet::default();
+        let mut first_discovery = true;
+
+        loop {
+            // discover (new) shards
+            let mut exclusive_start_shard_id = None;
+            loop {
+                let o = client
+                    .describe_stream()
+                    .stream_arn(stream_arn)
+                    .set_exclusive_start_shard_id(exclusive_start_shard_id)
+                    .send()
+                    .await
+                    .map_err(|e| eyre!("Can't describe stream {stream_arn} -> {e:?}"))?;
+                let Some(description) = o.stream_description else {
+                    break;
+                };
+                for shard in description.shards() {
+                    let Some(shard_id) = shard.shard_id() else {
+                        continue;
+                    };
+                    if iterators.contains_key(shard_id) || finished_shards.contains(shard_id) {
+                        continue;
+                    }
+                    // shards existing on startup are followed from now on, new ones from the start
+                    let iterator_type = if first_discovery {
+                        ShardIteratorType::Latest
+                    } else {
+                        ShardIteratorType::TrimHorizon
+                    };
+                    let o = client
+                        .get_shard_iterator()
+                        .stream_arn(stream_arn)
+                        .shard_id(shard_id)
+                        .shard_iterator_type(iterator_type)
+                        .send()
+                        .await
+                        .map_err(|e| eyre!("Can't get iterator for shard {shard_id} -> {e:?}"))?;
+                    if let Some(iterator) = o.shard_iterator {
+                        iterators.insert(shard_id.to_string(), iterator);
+                    }
+                }
+                exclusive_start_shard_id = description.last_evaluated_shard_id;
+                if exclusive_start_shard_id.is_none() {
+                    break;
+                }
+            }
+            first_discovery = false;
+
+            // poll all shards
+            let mut got_records = false;
+            for (shard_id, iterator) in std::mem::take(&mut iterators) {
+                let o = client
+                    .get_records()
+                    .shard_iterator(iterator)
+                    .send()
+                    .await
+                    .map_err(|e| eyre!("Can't get records for shard {shard_id} -> {e:?}"))?;
+                for record in o.records() {
+                    got_records = true;
+                    for event in Self::events_from_record(
+                        record,
+                        &self.attribute_names,
+                        &self.key_prefix,
+                        self.key_hasher.as_deref(),
+                        self.id_codec.as_deref(),
+                    )? {
+                        if tx.send(event).await.is_err() {
+                            tracing::info!("Change feed receiver dropped, stopping");
+                            return Ok(());
+                        }
+                    }
+                }
+                match o.next_shard_iterator {
+                    Some(next) => {
+                        iterators.insert(shard_id, next);
+                    }
+                    None => {
+                        // shard is closed, and fully consumed
+                        finished_shards.insert(shard_id);
+                    }
+                }
+            }
+
+            if tx.is_closed() {
+                return Ok(());
+            }
+            if !got_records {
+                tokio::time::sleep(self.poll_interval).await;
+            }
+        }
+    }
+
+    fn item_from_data(data: &AttributeValue) -> Result<ITEM> {
+        match data {
+            AttributeValue::S(data) => deserialize_item::<ITEM>(data.as_bytes()),
+            AttributeValue::M(_) => {
+                let json: serde_json::Value = serde_dynamo::from_attribute_value(data.clone())?;
+                let data = serde_json::to_vec(&json)?;
+                deserialize_item::<ITEM>(&data)
+            }
+            o => Err(eyre!("Unsupported data attribute {:?}", Redacted(&o))),
+        }
+    }
+
+    fn lock_from_image(image: &Image, lock_attribute: &str) -> Option<StorageLock> {
+        let lock_json = image.get(lock_attribute)?.as_s().ok()?;
+        serde_json::from_str(lock_json).ok()
+    }
+
+    fn events_from_record(
+        record: &Record,
+        attribute_names: &DynamoDbAttributeNames,
+        key_prefix: &str,
+        key_hasher: Option<&dyn DynamoDbKeyHasher>,
+        id_codec: Option<&dyn IdCodec>,
+    ) -> Result<Vec<ChangeEvent<ITEM>>> {
+        let Some(stream_record) = record.dynamodb() else {
+            return Ok(Vec::default());
+        };
+        let Some(id) = stream_record
+            .keys()
+            .and_then(|k| k.get(&attribute_names.id))
+            .and_then(|id| id.as_s().ok())
+        else {
+            tracing::warn!("Stream record without id {:?}", Redacted(&record));
+            return Ok(Vec::default());
+        };
+        let Some(id) = id_from_key(id, key_prefix, key_hasher, id_codec) else {
+            // another storage sharing the table
+            return Ok(Vec::default());
+        };
+        let id = ITEM::ID::from_string(&id)?;
+        let empty = Image::default();
+        let old_image = stream_record.old_image().unwrap_or(&empty);
+        let new_image = stream_record.new_image().unwrap_or(&empty);
+
+        let mut events = Vec::default();
+        match record.event_name() {
+            Some(OperationType::Remove) => {
+                let old = old_image
+                    .get(&attribute_names.data)
+                    .map(Self::item_from_data)
+                    .transpose()?;
+                events.push(ChangeEvent::Deleted { id, old });
+            }
+            Some(OperationType::Insert) | Some(OperationType::Modify) => {
+                let old_data = old_image.get(&attribute_names.data);
+                if let Some(new_data) = new_image.get(&attribute_names.data) {
+                    if old_data != Some(new_data) {
+                        let old = old_data.map(Self::item_from_data).transpose()?;
+                        let new = Self::item_from_data(new_data)?;
+                        events.push(ChangeEvent::Saved {
+                            id: id.clone(),
+                            old,
+                            new,
+                        });
+                    }
+                }
+                let old_lock = old_image.get(&attribute_names.lock);
+                let new_lock = new_image.get(&attribute_names.lock);
+                if old_lock != new_lock {
+                    if new_lock.is_some() {
+                        let who = Self::lock_from_image(new_image, &attribute_names.lock)
+                            .map(|l| l.who().to_string())
+                            .unwrap_or_default();
+                        events.push(ChangeEvent::Locked { id, who });
+                    } else {
+                        events.push(ChangeEvent::Unlocked { id });
+                    }
+                }
+            }
+            o => {
+                tracing::warn!("Unhandled stream event {o:?}");
+            }
+        }
+
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ChangeEvent;
+    use crate::DynamoDbAttributeNames;
+    use crate::DynamoDbChangeFeed;
+    use crate::StorageItem;
+    use crate::StorageLock;
+    use aws_sdk_dynamodbstreams::types::AttributeValue;
+    use aws_sdk_dynamodbstreams::types::OperationType;
+    use aws_sdk_dynamodbstreams::types::Record;
+    use aws_sdk_dynamodbstreams::types::StreamRecord;
+    use color_eyre::Result;
+    use serde::Deserialize;
+    use serde::Serialize;
+
+    #[derive(Default, Debug, Serialize, Deserialize, PartialEq)]
+    struct TestItem {
+        count: u32,
+    }
+
+    impl StorageItem for TestItem {
+        type ID = String;
+
+        fn serialize(&self) -> Result<Vec<u8>> {
+            let json = serde_json::to_string_pretty(&self)?;
+
+            Ok(json.into())
+        }
+        fn deserialize(data: &[u8]) -> Result<Self> {
+            let i = serde_json::from_slice(data)?;
+
+            Ok(i)
+        }
+    }
+
+    #[test]
+    fn it_turns_save_and_unlock_into_events() -> Result<()> {
+        let lock = serde_json::to_string(&StorageLock::new("TEST"))?;
+        let stream_record = StreamRecord::builder()
+            .keys("id", AttributeValue::S(String::from("item")))
+            .old_image("id", AttributeValue::S(String::from("item")))
+            .old_image("lock", AttributeValue::S(lock))
+            .old_image("data", AttributeValue::S(String::from(r#"{"count":1}"#)))
+            .new_image("id", AttributeValue::S(String::from("item")))
+            .new_image("data", AttributeValue::S(String::from(r#"{"count":2}"#)))
+            .build();
+        let record = Record::builder()
+            .event_name(OperationType::Modify)
+            .dynamodb(stream_record)
+            .build();
+
+        let events = DynamoDbChangeFeed::<TestItem>::events_from_record(
+            &record,
+            &DynamoDbAttributeNames::default(),
+            "",
+            None,
+            None,
+        )?;
+        assert_eq!(2, events.len());
+        match &events[0] {
+            ChangeEvent::Saved { id, old, new } => {
+                assert_eq!("item", id);
+                assert_eq!(Some(TestItem { count: 1 }), *old);
+                assert_eq!(TestItem { count: 2 }, *new);
+            }
+            o => panic!("Expected Saved, got {o:?}"),
+        }
+        assert!(matches!(events[1], ChangeEvent::Unlocked { .. }));
+
+        Ok(())
+    }
+}
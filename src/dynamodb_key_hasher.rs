@@ -0,0 +1,71 @@
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+
+/// Separates the shard from the id in DynamoDB keys, shards must not contain it.
+pub(crate) const KEY_SHARD_SEPARATOR: char = '#';
+
+/// Picks the shard prepended to the DynamoDB key of an item, see [crate::StorageDynamoDb::set_key_hasher].
+///
+/// Spreading e.g. [crate::SequentialId]s over many partition keys avoids a hot partition.
+pub trait DynamoDbKeyHasher: Send + Sync + std::fmt::Debug {
+    /// The shard of `id`. Must only depend on `id` and never contain `#`.
+    fn shard(&self, id: &str) -> String;
+}
+
+/// Spreads ids over a fixed number of shards by a stable hash, e.g. `"07"` of `"00"`..`"15"`.
+///
+/// The hash never changes between releases, but changing the number of shards moves all items.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DynamoDbHashShards {
+    shards: u16,
+}
+
+impl DynamoDbHashShards {
+    pub fn new(shards: u16) -> Result<Self> {
+        if shards < 2 {
+            return Err(eyre!("Sharding needs at least 2 shards, got {shards}"));
+        }
+
+        Ok(Self { shards })
+    }
+
+    pub fn shards(&self) -> u16 {
+        self.shards
+    }
+}
+
+impl DynamoDbKeyHasher for DynamoDbHashShards {
+    fn shard(&self, id: &str) -> String {
+        let shard = fnv1a(id.as_bytes()) % self.shards as u64;
+        let width = (self.shards - 1).to_string().len();
+        format!("{shard:0width$}")
+    }
+}
+
+/// 64 bit FNV-1a, unlike the std hashers it is stable across Rust versions.
+pub(crate) fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::DynamoDbHashShards;
+    use crate::DynamoDbKeyHasher;
+    use color_eyre::Result;
+    use std::collections::HashSet;
+
+    #[test]
+    fn it_spreads_sequential_ids() -> Result<()> {
+        assert!(DynamoDbHashShards::new(1).is_err());
+
+        let hasher = DynamoDbHashShards::new(16)?;
+        assert_eq!(hasher.shard("42"), hasher.shard("42"));
+        let shards: HashSet<String> = (0..100).map(|i| hasher.shard(&i.to_string())).collect();
+        assert_eq!(16, shards.len());
+        assert!(shards.iter().all(|s| s.len() == 2));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,173 @@
+use crate::StorageDynamoDb;
+use crate::StorageItem;
+use aws_config::SdkConfig;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use std::any::type_name;
+use std::any::TypeId;
+use std::collections::HashMap;
+
+/// Hands out [StorageDynamoDb]s for many item types,
+/// all sharing one [SdkConfig] and client, and with it connection pool and credentials.
+///
+/// Register a table per item type once, then get a storage via [DynamoDbRegistry::storage].
+#[derive(Debug)]
+pub struct DynamoDbRegistry {
+    sdk_config: SdkConfig,
+    endpoint_url: Option<String>,
+    client: aws_sdk_dynamodb::Client,
+    tables: HashMap<TypeId, (&'static str, String)>,
+}
+
+impl DynamoDbRegistry {
+    /// Uses the configuration from the environment.
+    pub async fn new() -> Self {
+        let sdk_config = aws_config::defaults(aws_config::BehaviorVersion::latest())
+            .load()
+            .await;
+
+        Self::from_sdk_config(sdk_config)
+    }
+
+    pub fn from_sdk_config(sdk_config: SdkConfig) -> Self {
+        let client = aws_sdk_dynamodb::Client::new(&sdk_config);
+        Self {
+            sdk_config,
+            endpoint_url: None,
+            client,
+            tables: HashMap::default(),
+        }
+    }
+
+    pub fn endpoint_url(&self) -> Option<&str> {
+        self.endpoint_url.as_deref()
+    }
+
+    /// Talk to the given endpoint, e.g. a local DynamoDB, for all tables.
+    pub fn set_endpoint_url(&mut self, url: &str) -> Result<()> {
+        let config = aws_sdk_dynamodb::config::Builder::from(&self.sdk_config).endpoint_url(url);
+        self.client = aws_sdk_dynamodb::Client::from_conf(config.build());
+        self.endpoint_url = Some(String::from(url));
+
+        Ok(())
+    }
+
+    /// Routes `ITEM` to `table_name`. Item types and tables can only be registered once.
+    pub fn register<ITEM: StorageItem + 'static>(&mut self, table_name: &str) -> Result<()> {
+        let item_type = type_name::<ITEM>();
+        if let Some((_, existing)) = self.tables.get(&TypeId::of::<ITEM>()) {
+            return Err(eyre!(
+                "{item_type} is already registered for table {existing}"
+            ));
+        }
+        if let Some((other_type, _)) = self.tables.values().find(|(_, t)| t == table_name) {
+            return Err(eyre!("Table {table_name} is already used by {other_type}"));
+        }
+        self.tables
+            .insert(TypeId::of::<ITEM>(), (item_type, String::from(table_name)));
+
+        Ok(())
+    }
+
+    /// The table registered for `ITEM`.
+    pub fn table_name<ITEM: StorageItem + 'static>(&self) -> Option<&str> {
+        self.tables
+            .get(&TypeId::of::<ITEM>())
+            .map(|(_, table_name)| table_name.as_str())
+    }
+
+    /// All registered tables, e.g. to ensure they exist on startup.
+    pub fn table_names(&self) -> Vec<&str> {
+        let mut table_names: Vec<&str> = self.tables.values().map(|(_, t)| t.as_str()).collect();
+        table_names.sort_unstable();
+        table_names
+    }
+
+    /// A storage for the table registered for `ITEM`.
+    pub async fn storage<ITEM: StorageItem + 'static>(&self) -> Result<StorageDynamoDb<ITEM>> {
+        let Some(table_name) = self.table_name::<ITEM>() else {
+            return Err(eyre!("No table registered for {}", type_name::<ITEM>()));
+        };
+
+        self.storage_for_table(table_name).await
+    }
+
+    /// A storage for `table_name`, without registering it.
+    pub async fn storage_for_table<ITEM: StorageItem>(
+        &self,
+        table_name: &str,
+    ) -> Result<StorageDynamoDb<ITEM>> {
+        let mut storage = StorageDynamoDb::new(table_name).await;
+        storage.set_sdk_config(self.sdk_config.clone())?;
+        if let Some(endpoint_url) = &self.endpoint_url {
+            storage.set_endpoint_url(endpoint_url)?;
+        }
+        // the client is cheap to clone, and shares the connection pool
+        storage.set_client(self.client.clone())?;
+
+        Ok(storage)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::DynamoDbRegistry;
+    use crate::StorageItem;
+    use aws_config::BehaviorVersion;
+    use aws_config::Region;
+    use aws_config::SdkConfig;
+    use color_eyre::Result;
+
+    #[derive(Default, Debug)]
+    struct Player {}
+
+    #[derive(Default, Debug)]
+    struct Guild {}
+
+    impl StorageItem for Player {
+        type ID = String;
+
+        fn serialize(&self) -> Result<Vec<u8>> {
+            Ok(Vec::default())
+        }
+        fn deserialize(_data: &[u8]) -> Result<Self> {
+            Ok(Self::default())
+        }
+    }
+
+    impl StorageItem for Guild {
+        type ID = String;
+
+        fn serialize(&self) -> Result<Vec<u8>> {
+            Ok(Vec::default())
+        }
+        fn deserialize(_data: &[u8]) -> Result<Self> {
+            Ok(Self::default())
+        }
+    }
+
+    #[tokio::test]
+    async fn it_routes_item_types_to_tables() -> Result<()> {
+        let sdk_config = SdkConfig::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("eu-west-1"))
+            .build();
+        let mut registry = DynamoDbRegistry::from_sdk_config(sdk_config);
+        registry.set_endpoint_url("http://localhost:8000")?;
+
+        assert!(registry.storage::<Player>().await.is_err());
+
+        registry.register::<Player>("players")?;
+        registry.register::<Guild>("guilds")?;
+        assert!(registry.register::<Player>("other_players").is_err());
+        assert_eq!(vec!["guilds", "players"], registry.table_names());
+
+        let players = registry.storage::<Player>().await?;
+        assert_eq!("players", players.table_name());
+        assert_eq!(Some("http://localhost:8000"), players.endpoint_url());
+        let guilds = registry.storage::<Guild>().await?;
+        assert_eq!("guilds", guilds.table_name());
+
+        Ok(())
+    }
+}
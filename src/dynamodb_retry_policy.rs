@@ -0,0 +1,239 @@
+use crate::operation_metrics;
+use crate::RetryBudget;
+use aws_sdk_dynamodb::error::ProvideErrorMetadata;
+use aws_sdk_dynamodb::error::SdkError;
+use rand::Rng;
+use std::future::Future;
+use std::time::Duration;
+use tracing::Instrument;
+
+/// Error codes DynamoDB uses to signal throttling
+const THROTTLING_ERROR_CODES: &[&str] = &[
+    "ProvisionedThroughputExceededException",
+    "ThrottlingException",
+    "RequestLimitExceeded",
+];
+
+/// Error codes DynamoDB uses for (probably) transient server side problems
+const TRANSIENT_ERROR_CODES: &[&str] = &["InternalServerError", "ServiceUnavailable"];
+
+/// Rough classification of errors returned by the DynamoDB SDK
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DynamoDbErrorClass {
+    /// The request was throttled, e.g. `ProvisionedThroughputExceededException`
+    Throttling,
+    /// Timeouts, dispatch failures, and internal server errors
+    Transient,
+    /// Everything else, including failed conditions and configuration problems
+    Other,
+}
+
+impl DynamoDbErrorClass {
+    /// Whether the same request might succeed later.
+    pub fn is_transient(&self) -> bool {
+        !matches!(self, Self::Other)
+    }
+
+    /// `throttling`, `transient`, or `other`, e.g. as a metrics label.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Throttling => "throttling",
+            Self::Transient => "transient",
+            Self::Other => "other",
+        }
+    }
+
+    pub fn classify<E: ProvideErrorMetadata, R>(e: &SdkError<E, R>) -> Self {
+        match e {
+            SdkError::TimeoutError(_) | SdkError::DispatchFailure(_) => Self::Transient,
+            SdkError::ResponseError(_) => Self::Transient,
+            e => match e.code() {
+                Some(code) if THROTTLING_ERROR_CODES.contains(&code) => Self::Throttling,
+                Some(code) if TRANSIENT_ERROR_CODES.contains(&code) => Self::Transient,
+                _ => Self::Other,
+            },
+        }
+    }
+}
+
+/// Retry policy applied around all DynamoDB calls made by [crate::StorageDynamoDb].
+///
+/// Uses exponential backoff with full jitter.
+#[derive(Debug, Clone)]
+pub struct DynamoDbRetryPolicy {
+    /// Maximum number of attempts, including the first one
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled for every further retry
+    pub base_delay: Duration,
+    /// Upper limit for the delay between two attempts
+    pub max_delay: Duration,
+    /// Retry when DynamoDB throttles requests
+    pub retry_throttling: bool,
+    /// Retry on timeouts, dispatch failures, and internal server errors.
+    /// Conditional writes, e.g. saves, are not retried on these, they might have been applied already.
+    pub retry_transient: bool,
+    /// Shared limit for retries, e.g. of all storages on a table, `None` for no limit.
+    /// Without tokens the last error is returned.
+    pub budget: Option<RetryBudget>,
+}
+
+impl Default for DynamoDbRetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(25),
+            max_delay: Duration::from_secs(2),
+            retry_throttling: true,
+            retry_transient: true,
+            budget: None,
+        }
+    }
+}
+
+impl DynamoDbRetryPolicy {
+    /// A policy that never retries
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_retryable<E: ProvideErrorMetadata, R>(&self, e: &SdkError<E, R>) -> bool {
+        match DynamoDbErrorClass::classify(e) {
+            DynamoDbErrorClass::Throttling => self.retry_throttling,
+            DynamoDbErrorClass::Transient => self.retry_transient,
+            DynamoDbErrorClass::Other => false,
+        }
+    }
+
+    /// The (jittered) delay before the given retry, starting with `1` for the first retry
+    pub fn delay(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        let delay = self.base_delay.saturating_mul(factor).min(self.max_delay);
+        let millis = delay.as_millis() as u64;
+        if millis == 0 {
+            return delay;
+        }
+        Duration::from_millis(rand::thread_rng().gen_range(0..=millis))
+    }
+
+    /// Whether the request might have been applied although it failed,
+    /// e.g. a timeout after DynamoDB received it, or an internal server error.
+    pub fn is_ambiguous<E: ProvideErrorMetadata, R>(e: &SdkError<E, R>) -> bool {
+        DynamoDbErrorClass::classify(e) == DynamoDbErrorClass::Transient
+    }
+
+    /// Runs `f` until it succeeds, fails with a non retryable error, or `max_attempts` is reached.
+    /// Every attempt runs in a `dynamodb.request` span, with `db.operation` set to the DynamoDB operation, e.g. `GetItem`.
+    pub(crate) async fn run<T, E, R, F, Fut>(
+        &self,
+        operation: &str,
+        f: F,
+    ) -> Result<T, SdkError<E, R>>
+    where
+        E: ProvideErrorMetadata + std::fmt::Debug,
+        R: std::fmt::Debug,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, SdkError<E, R>>>,
+    {
+        self.run_with(operation, true, f).await
+    }
+
+    /// Like [Self::run], but for conditional writes, which are not retried after [Self::is_ambiguous] failures.
+    /// If the first attempt was applied, a retry fails its condition and reports a conflict for a successful write.
+    pub(crate) async fn run_conditional<T, E, R, F, Fut>(
+        &self,
+        operation: &str,
+        f: F,
+    ) -> Result<T, SdkError<E, R>>
+    where
+        E: ProvideErrorMetadata + std::fmt::Debug,
+        R: std::fmt::Debug,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, SdkError<E, R>>>,
+    {
+        self.run_with(operation, false, f).await
+    }
+
+    async fn run_with<T, E, R, F, Fut>(
+        &self,
+        operation: &str,
+        retry_ambiguous: bool,
+        mut f: F,
+    ) -> Result<T, SdkError<E, R>>
+    where
+        E: ProvideErrorMetadata + std::fmt::Debug,
+        R: std::fmt::Debug,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, SdkError<E, R>>>,
+    {
+        // e.g. `GetItem` for `Load - GetItem`
+        let db_operation = operation.rsplit(" - ").next().unwrap_or(operation);
+        let mut attempt = 1;
+        loop {
+            let span = tracing::info_span!(
+                "dynamodb.request",
+                otel.name = db_operation,
+                db.system = "dynamodb",
+                db.operation = db_operation,
+                attempt,
+            );
+            match f().instrument(span).await {
+                Ok(o) => return Ok(o),
+                Err(e)
+                    if attempt < self.max_attempts
+                        && self.is_retryable(&e)
+                        && (retry_ambiguous || !Self::is_ambiguous(&e)) =>
+                {
+                    let class = DynamoDbErrorClass::classify(&e).name();
+                    if let Some(budget) = self.budget.as_ref().filter(|b| !b.try_retry()) {
+                        tracing::warn!(
+                            "{operation} failed, retry budget exhausted ({} refused) -> {e:?}",
+                            budget.exhausted()
+                        );
+                        operation_metrics::record_error("dynamodb", operation, class);
+                        return Err(e);
+                    }
+                    operation_metrics::record_retry("dynamodb", operation, class);
+                    let delay = self.delay(attempt);
+                    tracing::warn!(
+                        "{operation} failed (attempt {attempt}/{}), retrying in {delay:?} -> {e:?}",
+                        self.max_attempts
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(e) => {
+                    // failed conditions, e.g. contended locks, are expected, not errors
+                    if e.code() != Some("ConditionalCheckFailedException") {
+                        let class = DynamoDbErrorClass::classify(&e).name();
+                        operation_metrics::record_error("dynamodb", operation, class);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::DynamoDbRetryPolicy;
+    use std::time::Duration;
+
+    #[test]
+    fn it_caps_delays() {
+        let policy = DynamoDbRetryPolicy {
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(300),
+            ..Default::default()
+        };
+
+        for retry in 1..20 {
+            let delay = policy.delay(retry);
+            assert!(delay <= Duration::from_millis(300), "{retry} -> {delay:?}");
+        }
+        assert!(policy.delay(1) <= Duration::from_millis(100));
+    }
+}
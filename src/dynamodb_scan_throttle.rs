@@ -0,0 +1,155 @@
+use aws_sdk_dynamodb::types::ConsumedCapacity;
+use aws_sdk_dynamodb::types::ProvisionedThroughputDescription;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// How much of the table throughput the scans and wipes of [crate::StorageDynamoDb] may use,
+/// so bulk work, e.g. a nightly export, doesn't starve other traffic.
+///
+/// The consumed capacity DynamoDB reports is paced, i.e. the next request waits until the capacity
+/// used so far fits into the budget. Single requests still burst, one scan page reads up to 1MB.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DynamoDbScanThrottle {
+    /// No throttling, the default
+    #[default]
+    Off,
+    /// A share, in `(0, 1]`, of the provisioned capacity of the table,
+    /// read by [crate::Storage::ensure_storage_exists]. On-demand tables have none, use [Self::UnitsPerSecond].
+    Share(f64),
+    /// Fixed capacity units per second, reads for scans and writes for deletes
+    UnitsPerSecond { read: f64, write: f64 },
+}
+
+impl DynamoDbScanThrottle {
+    pub(crate) fn validate(&self) -> Result<()> {
+        let valid = match *self {
+            Self::Off => true,
+            Self::Share(share) => share > 0.0 && share <= 1.0,
+            Self::UnitsPerSecond { read, write } => {
+                read.is_finite() && read > 0.0 && write.is_finite() && write > 0.0
+            }
+        };
+        if !valid {
+            return Err(eyre!("Invalid scan throttle {self:?}"));
+        }
+
+        Ok(())
+    }
+
+    /// The pacers for this throttle, `None` if it is off or needs the provisioned capacity.
+    pub(crate) fn pacers(
+        &self,
+        provisioned: Option<&ProvisionedThroughputDescription>,
+    ) -> Result<Option<ScanPacers>> {
+        let (read, write) = match *self {
+            Self::Off => return Ok(None),
+            Self::UnitsPerSecond { read, write } => (read, write),
+            Self::Share(share) => {
+                let Some(provisioned) = provisioned else {
+                    return Ok(None);
+                };
+                let read = provisioned.read_capacity_units().unwrap_or_default();
+                let write = provisioned.write_capacity_units().unwrap_or_default();
+                if read <= 0 || write <= 0 {
+                    return Err(eyre!(
+                        "Scan throttle {self:?} needs provisioned capacity, use DynamoDbScanThrottle::UnitsPerSecond for on-demand tables"
+                    ));
+                }
+                (read as f64 * share, write as f64 * share)
+            }
+        };
+
+        Ok(Some(ScanPacers {
+            read: CapacityPacer::new(read),
+            write: CapacityPacer::new(write),
+        }))
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct ScanPacers {
+    pub read: CapacityPacer,
+    /// Only wipes delete in bulk
+    #[cfg_attr(not(feature = "wipe"), allow(dead_code))]
+    pub write: CapacityPacer,
+}
+
+/// Delays requests, so the consumed capacity stays under `per_second` on average.
+#[derive(Debug)]
+pub(crate) struct CapacityPacer {
+    per_second: f64,
+    next: Mutex<Option<Instant>>,
+}
+
+impl CapacityPacer {
+    fn new(per_second: f64) -> Self {
+        Self {
+            per_second,
+            next: Mutex::new(None),
+        }
+    }
+
+    /// Waits until the capacity consumed so far is paid off.
+    pub async fn wait(&self) {
+        let next = *self.next.lock().expect("can lock");
+        if let Some(next) = next {
+            tokio::time::sleep_until(next).await;
+        }
+    }
+
+    pub fn consumed(&self, capacity: &ConsumedCapacity) {
+        let units = capacity.capacity_units().unwrap_or_default();
+        if units <= 0.0 {
+            return;
+        }
+        let mut next = self.next.lock().expect("can lock");
+        let now = Instant::now();
+        let start = next.map_or(now, |next| next.max(now));
+        *next = Some(start + Duration::from_secs_f64(units / self.per_second));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::DynamoDbScanThrottle;
+    use aws_sdk_dynamodb::types::ConsumedCapacity;
+    use aws_sdk_dynamodb::types::ProvisionedThroughputDescription;
+    use color_eyre::Result;
+    use std::time::Duration;
+    use tokio::time::Instant;
+
+    #[tokio::test(start_paused = true)]
+    async fn it_paces_consumed_capacity() -> Result<()> {
+        let provisioned = ProvisionedThroughputDescription::builder()
+            .read_capacity_units(100)
+            .write_capacity_units(10)
+            .build();
+        let pacers = DynamoDbScanThrottle::Share(0.25)
+            .pacers(Some(&provisioned))?
+            .expect("pacers");
+
+        let start = Instant::now();
+        pacers.read.wait().await;
+        assert_eq!(start, Instant::now());
+
+        // 25 read units per second
+        let page = ConsumedCapacity::builder().capacity_units(50.0).build();
+        pacers.read.consumed(&page);
+        pacers.read.consumed(&page);
+        pacers.read.wait().await;
+        assert_eq!(Duration::from_secs(4), start.elapsed());
+        pacers.write.wait().await;
+        assert_eq!(Duration::from_secs(4), start.elapsed());
+
+        let on_demand = ProvisionedThroughputDescription::builder().build();
+        assert!(DynamoDbScanThrottle::Share(0.25)
+            .pacers(Some(&on_demand))
+            .is_err());
+        assert!(DynamoDbScanThrottle::Share(1.5).validate().is_err());
+
+        Ok(())
+    }
+}
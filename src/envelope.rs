@@ -0,0 +1,533 @@
+use crate::storage_item::deserialize_item;
+use crate::storage_item::serialize_valid;
+use crate::LockResult;
+use crate::OpOptions;
+use crate::Page;
+use crate::Storage;
+use crate::StorageItem;
+use crate::StorageLock;
+use async_trait::async_trait;
+use chrono::DateTime;
+use chrono::Utc;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use core::marker::PhantomData;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// When an item was created, and when and by whom it was last saved.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EnvelopeHeader {
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// The lock holder of the last save, empty for items never saved via [StorageEnveloped]
+    pub updated_by: String,
+}
+
+impl Default for EnvelopeHeader {
+    fn default() -> Self {
+        let now = Utc::now();
+        Self {
+            created_at: now,
+            updated_at: now,
+            updated_by: String::new(),
+        }
+    }
+}
+
+impl EnvelopeHeader {
+    /// Reads only the header of serialized [Envelope] data, without deserializing the item.
+    pub fn from_data(data: &[u8]) -> Result<Self> {
+        Ok(split(data)?.0)
+    }
+}
+
+/// An item and its [EnvelopeHeader].
+///
+/// Stored as a single line of JSON for the header, followed by the serialized item,
+/// so the header can be read without deserializing the item.
+/// Use [StorageEnveloped] to have the header stamped on every save.
+#[derive(Debug, Default)]
+pub struct Envelope<ITEM> {
+    pub header: EnvelopeHeader,
+    pub item: ITEM,
+}
+
+pub(crate) fn join(header: &EnvelopeHeader, item_data: &[u8]) -> Result<Vec<u8>> {
+    let mut data = serde_json::to_vec(header)?;
+    data.push(b'\n');
+    data.extend_from_slice(item_data);
+
+    Ok(data)
+}
+
+pub(crate) fn split(data: &[u8]) -> Result<(EnvelopeHeader, &[u8])> {
+    let newline = data
+        .iter()
+        .position(|b| *b == b'\n')
+        .ok_or_else(|| eyre!("Missing envelope header"))?;
+    let header = serde_json::from_slice(&data[..newline])?;
+
+    Ok((header, &data[newline + 1..]))
+}
+
+impl<ITEM: StorageItem> StorageItem for Envelope<ITEM> {
+    type ID = ITEM::ID;
+
+    fn serialize(&self) -> Result<Vec<u8>> {
+        join(&self.header, &self.item.serialize()?)
+    }
+
+    fn deserialize(data: &[u8]) -> Result<Self> {
+        let (header, item_data) = split(data)?;
+        Ok(Self {
+            header,
+            item: deserialize_item::<ITEM>(item_data)?,
+        })
+    }
+}
+
+/// Wraps a storage of [Envelope]s and stamps their header on every save,
+/// while callers only see the items.
+///
+/// `created_at` is kept from the stored item, `updated_at` and `updated_by` come from the save and its lock.
+#[derive(Debug)]
+pub struct StorageEnveloped<ITEM: StorageItem, S: Storage<Envelope<ITEM>>>
+where
+    ITEM: Send,
+{
+    storage: S,
+    /// `created_at` of items locked via this instance, saves don't need to read it again
+    created: Mutex<HashMap<String, DateTime<Utc>>>,
+    item_type: PhantomData<ITEM>,
+}
+
+impl<ITEM: StorageItem + Send, S: Storage<Envelope<ITEM>>> StorageEnveloped<ITEM, S> {
+    pub fn new(storage: S) -> Self {
+        Self {
+            storage,
+            created: Mutex::new(HashMap::new()),
+            item_type: PhantomData,
+        }
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    pub fn into_inner(self) -> S {
+        self.storage
+    }
+
+    /// Loads the item with its header.
+    pub async fn load_envelope(&self, id: &ITEM::ID) -> Result<Envelope<ITEM>> {
+        self.storage.load(id).await
+    }
+
+    /// Loads only the header, the item is not deserialized.
+    pub async fn load_header(&self, id: &ITEM::ID) -> Result<EnvelopeHeader> {
+        EnvelopeHeader::from_data(&self.storage.load_raw(id).await?)
+    }
+
+    fn forget_created(&self, id: &ITEM::ID) {
+        self.created
+            .lock()
+            .expect("can lock")
+            .remove(&id.to_string());
+    }
+
+    async fn stamp(&self, id: &ITEM::ID, item_data: &[u8], who: &str) -> Result<Vec<u8>> {
+        let created_at = self
+            .created
+            .lock()
+            .expect("can lock")
+            .get(&id.to_string())
+            .copied();
+        let created_at = match created_at {
+            Some(created_at) => created_at,
+            None => self.load_header(id).await?.created_at,
+        };
+        let header = EnvelopeHeader {
+            created_at,
+            updated_at: Utc::now(),
+            updated_by: who.to_string(),
+        };
+
+        join(&header, item_data)
+    }
+}
+
+#[async_trait]
+impl<ITEM: StorageItem + Send, S: Storage<Envelope<ITEM>>> Storage<ITEM>
+    for StorageEnveloped<ITEM, S>
+{
+    async fn ensure_storage_exists(&mut self) -> Result<()> {
+        self.storage.ensure_storage_exists().await
+    }
+
+    fn capabilities(&self) -> crate::Capabilities {
+        self.storage.capabilities()
+    }
+
+    async fn create(&self) -> Result<ITEM::ID> {
+        self.storage.create().await
+    }
+
+    async fn exists(&self, id: &ITEM::ID) -> Result<bool> {
+        self.storage.exists(id).await
+    }
+
+    async fn exists_with(&self, id: &ITEM::ID, options: &OpOptions) -> Result<bool> {
+        self.storage.exists_with(id, options).await
+    }
+
+    async fn load(&self, id: &ITEM::ID) -> Result<ITEM> {
+        Ok(self.storage.load(id).await?.item)
+    }
+
+    async fn load_with(&self, id: &ITEM::ID, options: &OpOptions) -> Result<ITEM> {
+        Ok(self.storage.load_with(id, options).await?.item)
+    }
+
+    async fn load_many(&self, ids: &[ITEM::ID]) -> Result<Vec<Option<ITEM>>> {
+        let envelopes = self.storage.load_many(ids).await?;
+        Ok(envelopes.into_iter().map(|e| e.map(|e| e.item)).collect())
+    }
+
+    async fn prefetch(&self, ids: &[ITEM::ID]) -> Result<()> {
+        self.storage.prefetch(ids).await
+    }
+
+    async fn save(&self, id: &ITEM::ID, item: &ITEM, lock: &StorageLock) -> Result<()> {
+        let data = self
+            .stamp(id, &serialize_valid(id, item)?, lock.who())
+            .await?;
+        self.storage.save_raw(id, &data, lock).await
+    }
+
+    async fn lock(&self, id: &ITEM::ID, who: &str) -> Result<LockResult<ITEM>> {
+        match self.storage.lock(id, who).await? {
+            LockResult::Success { lock, item } => {
+                self.created
+                    .lock()
+                    .expect("can lock")
+                    .insert(id.to_string(), item.header.created_at);
+                Ok(LockResult::Success {
+                    lock,
+                    item: item.item,
+                })
+            }
+            LockResult::AlreadyLocked { who, when } => Ok(LockResult::AlreadyLocked { who, when }),
+        }
+    }
+
+    async fn unlock(&self, id: &ITEM::ID, lock: StorageLock) -> Result<()> {
+        self.forget_created(id);
+        self.storage.unlock(id, lock).await
+    }
+
+    async fn handoff_lock(
+        &self,
+        id: &ITEM::ID,
+        lock: StorageLock,
+        new_who: &str,
+    ) -> Result<StorageLock> {
+        self.storage.handoff_lock(id, lock, new_who).await
+    }
+
+    async fn save_and_unlock(&self, id: &ITEM::ID, item: &ITEM, lock: StorageLock) -> Result<()> {
+        self.save(id, item, &lock).await?;
+        self.unlock(id, lock).await
+    }
+
+    async fn load_raw(&self, id: &ITEM::ID) -> Result<Vec<u8>> {
+        let data = self.storage.load_raw(id).await?;
+        Ok(split(&data)?.1.to_vec())
+    }
+
+    async fn save_raw(&self, id: &ITEM::ID, data: &[u8], lock: &StorageLock) -> Result<()> {
+        let data = self.stamp(id, data, lock.who()).await?;
+        self.storage.save_raw(id, &data, lock).await
+    }
+
+    async fn put_blob(
+        &self,
+        id: &ITEM::ID,
+        name: &str,
+        data: &[u8],
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.storage.put_blob(id, name, data, lock).await
+    }
+
+    async fn get_blob(&self, id: &ITEM::ID, name: &str) -> Result<Option<Vec<u8>>> {
+        self.storage.get_blob(id, name).await
+    }
+
+    async fn list_blobs(&self, id: &ITEM::ID) -> Result<Vec<String>> {
+        self.storage.list_blobs(id).await
+    }
+
+    async fn put_meta(&self, key: &str, value: &[u8]) -> Result<()> {
+        self.storage.put_meta(key, value).await
+    }
+
+    async fn get_meta(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.storage.get_meta(key).await
+    }
+
+    async fn add_alias(&self, alias: &ITEM::ID, canonical: &ITEM::ID) -> Result<()> {
+        self.storage.add_alias(alias, canonical).await
+    }
+
+    async fn remove_alias(&self, alias: &ITEM::ID) -> Result<()> {
+        self.storage.remove_alias(alias).await
+    }
+
+    async fn resolve_alias(&self, id: &ITEM::ID) -> Result<ITEM::ID> {
+        self.storage.resolve_alias(id).await
+    }
+
+    async fn add_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.storage.add_link(from, relation, to, lock).await
+    }
+
+    async fn remove_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.storage.remove_link(from, relation, to, lock).await
+    }
+
+    async fn links_of(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        self.storage.links_of(id, relation).await
+    }
+
+    async fn linked_from(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        self.storage.linked_from(id, relation).await
+    }
+
+    async fn force_unlock(&self, id: &ITEM::ID) -> Result<Option<StorageLock>> {
+        self.forget_created(id);
+        self.storage.force_unlock(id).await
+    }
+
+    async fn force_unlock_if_held(
+        &self,
+        id: &ITEM::ID,
+        who: &str,
+        when: DateTime<Utc>,
+    ) -> Result<bool> {
+        self.forget_created(id);
+        self.storage.force_unlock_if_held(id, who, when).await
+    }
+
+    async fn remove_orphaned_locks(&self, max_age: Duration) -> Result<usize> {
+        self.storage.remove_orphaned_locks(max_age).await
+    }
+
+    async fn verify_lock(&self, id: &ITEM::ID, lock: &StorageLock) -> Result<bool> {
+        self.storage.verify_lock(id, lock).await
+    }
+
+    async fn verify_lock_with(
+        &self,
+        id: &ITEM::ID,
+        lock: &StorageLock,
+        options: &OpOptions,
+    ) -> Result<bool> {
+        self.storage.verify_lock_with(id, lock, options).await
+    }
+
+    async fn all_ids(&self) -> Result<Vec<ITEM::ID>> {
+        self.storage.all_ids().await
+    }
+
+    async fn scan_ids(&self, start: Option<&str>, limit: Option<usize>) -> Result<Page<ITEM::ID>> {
+        self.storage.scan_ids(start, limit).await
+    }
+
+    async fn scan_ids_with_prefix(
+        &self,
+        prefix: &str,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.storage
+            .scan_ids_with_prefix(prefix, start, limit)
+            .await
+    }
+
+    async fn scan_ids_filtered(
+        &self,
+        filter: &crate::IdFilter,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.storage.scan_ids_filtered(filter, start, limit).await
+    }
+
+    async fn scan_ids_between(
+        &self,
+        from: &ITEM::ID,
+        to: &ITEM::ID,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.storage.scan_ids_between(from, to, start, limit).await
+    }
+
+    async fn list(
+        &self,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<crate::ItemSummary<ITEM::ID>>> {
+        self.storage.list(start, limit).await
+    }
+
+    async fn item_size(&self, id: &ITEM::ID) -> Result<u64> {
+        self.storage.item_size(id).await
+    }
+
+    async fn estimate_cost(&self) -> Result<crate::StorageCost> {
+        self.storage.estimate_cost().await
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        self.storage.shutdown().await
+    }
+
+    async fn fsck(&self, options: &crate::FsckOptions) -> Result<crate::FsckReport> {
+        self.storage.fsck(options).await
+    }
+
+    async fn display_lock(&self, id: &ITEM::ID) -> Result<String> {
+        self.storage.display_lock(id).await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_highest_seen_id(&self) -> Option<ITEM::ID> {
+        self.storage.metadata_highest_seen_id().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_item_count(&self) -> Option<u64> {
+        self.storage.metadata_item_count().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_total_bytes(&self) -> Option<u64> {
+        self.storage.metadata_total_bytes().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_last_write(&self) -> Option<DateTime<Utc>> {
+        self.storage.metadata_last_write().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_lock_stats(&self) -> crate::LockStats {
+        self.storage.metadata_lock_stats().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_snapshot(&self) -> crate::MetadataSnapshot {
+        self.storage.metadata_snapshot().await
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn wipe(
+        &self,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        let report = self.storage.wipe(options, progress, cancel).await?;
+        if !options.is_dry_run() {
+            self.created.lock().expect("can lock").clear();
+        }
+
+        Ok(report)
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn wipe_matching(
+        &self,
+        filter: &crate::IdFilter,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        let report = self
+            .storage
+            .wipe_matching(filter, options, progress, cancel)
+            .await?;
+        if !options.is_dry_run() {
+            self.created.lock().expect("can lock").clear();
+        }
+
+        Ok(report)
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn delete_many(
+        &self,
+        ids: &[ITEM::ID],
+        options: &crate::DeleteOptions,
+    ) -> Result<Vec<crate::DeleteOutcome>> {
+        let outcomes = self.storage.delete_many(ids, options).await?;
+        for (id, outcome) in ids.iter().zip(&outcomes) {
+            if *outcome == crate::DeleteOutcome::Deleted {
+                self.forget_created(id);
+            }
+        }
+
+        Ok(outcomes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_item::TestItem;
+    use crate::Storage;
+    use crate::StorageEnveloped;
+    use crate::StorageMemory;
+    use color_eyre::Result;
+
+    #[tokio::test]
+    async fn it_stamps_saves() -> Result<()> {
+        let storage = StorageEnveloped::new(StorageMemory::default());
+        let id = String::from("item");
+
+        let (lock, mut item): (_, TestItem) = storage.lock(&id, "alice").await?.success()?;
+        item.count = 1;
+        storage.save_and_unlock(&id, &item, lock).await?;
+        let created = storage.load_header(&id).await?;
+        assert_eq!("alice", created.updated_by);
+        assert!(created.created_at <= created.updated_at);
+
+        let (lock, _) = storage.lock(&id, "bob").await?.success()?;
+        storage.save_raw(&id, br#"{"count":2}"#, &lock).await?;
+        storage.unlock(&id, lock).await?;
+        let envelope = storage.load_envelope(&id).await?;
+        assert_eq!(TestItem { count: 2 }, envelope.item);
+        assert_eq!("bob", envelope.header.updated_by);
+        assert_eq!(created.created_at, envelope.header.created_at);
+        assert!(created.updated_at <= envelope.header.updated_at);
+        assert_eq!(br#"{"count":2}"#.to_vec(), storage.load_raw(&id).await?);
+
+        Ok(())
+    }
+}
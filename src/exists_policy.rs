@@ -0,0 +1,36 @@
+use crate::StorageLock;
+use chrono::DateTime;
+use chrono::Utc;
+use std::time::Duration;
+
+/// Whether items that are locked but were never saved exist, e.g. for [crate::StorageDisk::set_exists_policy].
+///
+/// A lock without an item is usually an item being created,
+/// but can also be left behind by a crash during creation, see [crate::Storage::remove_orphaned_locks].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ExistsPolicy {
+    /// Locked items exist, so their ids are never handed out twice. This is the default.
+    #[default]
+    IncludeLocked,
+    /// Locked items stop existing once the lock is older than this, they are considered orphans.
+    IgnoreOrphansOlderThan(Duration),
+}
+
+impl ExistsPolicy {
+    /// Whether an item without data exists. `lock` is `None` if it can't be read.
+    pub fn lock_only_exists(&self, lock: Option<&StorageLock>, now: DateTime<Utc>) -> bool {
+        match self {
+            ExistsPolicy::IncludeLocked => true,
+            ExistsPolicy::IgnoreOrphansOlderThan(max_age) => {
+                lock.is_some_and(|lock| !is_orphan(lock, *max_age, now))
+            }
+        }
+    }
+}
+
+/// Whether the lock of an item without data is older than `max_age`.
+pub(crate) fn is_orphan(lock: &StorageLock, max_age: Duration, now: DateTime<Utc>) -> bool {
+    now.signed_duration_since(*lock.when())
+        .to_std()
+        .is_ok_and(|age| age > max_age)
+}
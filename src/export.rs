@@ -0,0 +1,181 @@
+//! The line formats of `oml-storage-cli export` and `import`, one item per line.
+//!
+//! Besides our own format, items can be read and written as DynamoDB JSON,
+//! so data can be moved between e.g. [crate::StorageDisk] and DynamoDB with AWS's own tooling.
+
+use crate::Redacted;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::json;
+use serde_json::Map;
+use serde_json::Value;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// `{"id": "...", "data": "<base64>"}`, this is the default
+    #[default]
+    JsonLines,
+    /// DynamoDB JSON as used by e.g. `aws dynamodb put-item`, `{"id": {"S": "..."}, "data": {"S": "..."}}`
+    DynamoDbJson,
+    /// DynamoDB JSON wrapped in `{"Item": ...}`, as in the files of a DynamoDB table export to S3.
+    ///
+    /// Note: The exported files are gzipped, decompress them first.
+    S3Export,
+}
+
+/// One line of [ExportFormat::JsonLines]
+#[derive(Debug, Serialize, Deserialize)]
+struct ExportRecord {
+    id: String,
+    /// base64 encoded
+    data: String,
+}
+
+/// Encodes one item as a line, without the newline.
+///
+/// For DynamoDB JSON, data is written as a string if it is UTF-8 and as binary otherwise.
+pub fn encode_record(format: ExportFormat, id: &str, data: &[u8]) -> Result<String> {
+    let line = match format {
+        ExportFormat::JsonLines => serde_json::to_string(&ExportRecord {
+            id: id.to_string(),
+            data: BASE64.encode(data),
+        })?,
+        ExportFormat::DynamoDbJson => serde_json::to_string(&dynamodb_item(id, data))?,
+        ExportFormat::S3Export => {
+            serde_json::to_string(&json!({ "Item": dynamodb_item(id, data) }))?
+        }
+    };
+
+    Ok(line)
+}
+
+/// Decodes one line into id and data.
+///
+/// `None` for DynamoDB items without data, e.g. locked but never saved items, or aliases.
+/// Data stored as a DynamoDB map, e.g. with `DynamoDbDataFormat::Map`, is returned as JSON.
+pub fn decode_record(format: ExportFormat, line: &str) -> Result<Option<(String, Vec<u8>)>> {
+    let item = match format {
+        ExportFormat::JsonLines => {
+            let record: ExportRecord = serde_json::from_str(line)?;
+            return Ok(Some((record.id, BASE64.decode(&record.data)?)));
+        }
+        ExportFormat::DynamoDbJson => serde_json::from_str::<Value>(line)?,
+        ExportFormat::S3Export => serde_json::from_str::<Value>(line)?
+            .get_mut("Item")
+            .map(Value::take)
+            .ok_or_else(|| eyre!("Missing Item in {line}"))?,
+    };
+    let Some(id) = item.pointer("/id/S").and_then(Value::as_str) else {
+        return Err(eyre!("Missing string id in {line}"));
+    };
+    let data = match item.get("data").map(typed_entry).transpose()? {
+        None => return Ok(None),
+        Some(("S", Value::String(data))) => data.as_bytes().to_vec(),
+        Some(("B", Value::String(data))) => BASE64.decode(data)?,
+        Some(("M", _)) => serde_json::to_vec(&plain_from_typed(&item["data"])?)?,
+        Some((kind, _)) => return Err(eyre!("Unsupported data type {kind} for {id}")),
+    };
+
+    Ok(Some((id.to_string(), data)))
+}
+
+fn dynamodb_item(id: &str, data: &[u8]) -> Value {
+    let data = match std::str::from_utf8(data) {
+        Ok(data) => json!({ "S": data }),
+        Err(_) => json!({ "B": BASE64.encode(data) }),
+    };
+    json!({ "id": { "S": id }, "data": data })
+}
+
+/// Splits e.g. `{"S": "text"}` into `("S", "text")`.
+fn typed_entry(value: &Value) -> Result<(&str, &Value)> {
+    match value.as_object() {
+        Some(typed) if typed.len() == 1 => {
+            let (kind, value) = typed.iter().next().expect("one entry");
+            Ok((kind.as_str(), value))
+        }
+        _ => Err(eyre!("Not a DynamoDB JSON value: {}", Redacted(value))),
+    }
+}
+
+/// Converts a DynamoDB JSON value into plain JSON, binaries stay base64.
+fn plain_from_typed(value: &Value) -> Result<Value> {
+    let number = |n: &Value| -> Result<Value> {
+        let n = n
+            .as_str()
+            .ok_or_else(|| eyre!("Invalid number {}", Redacted(n)))?;
+        Ok(serde_json::from_str(n)?)
+    };
+    let (kind, inner) = typed_entry(value)?;
+    match (kind, inner) {
+        ("S" | "B", Value::String(_))
+        | ("BOOL", Value::Bool(_))
+        | ("SS" | "BS", Value::Array(_)) => Ok(inner.clone()),
+        ("NULL", _) => Ok(Value::Null),
+        ("N", n) => number(n),
+        ("NS", Value::Array(ns)) => ns
+            .iter()
+            .map(number)
+            .collect::<Result<_>>()
+            .map(Value::Array),
+        ("L", Value::Array(l)) => l
+            .iter()
+            .map(plain_from_typed)
+            .collect::<Result<_>>()
+            .map(Value::Array),
+        ("M", Value::Object(m)) => m
+            .iter()
+            .map(|(k, v)| Ok((k.clone(), plain_from_typed(v)?)))
+            .collect::<Result<Map<_, _>>>()
+            .map(Value::Object),
+        _ => Err(eyre!("Invalid DynamoDB JSON value of type {kind}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::decode_record;
+    use crate::export::encode_record;
+    use crate::export::ExportFormat;
+    use color_eyre::Result;
+
+    #[test]
+    fn it_round_trips_all_formats() -> Result<()> {
+        for format in [
+            ExportFormat::JsonLines,
+            ExportFormat::DynamoDbJson,
+            ExportFormat::S3Export,
+        ] {
+            for data in [&b"{\"a\":1}"[..], &[0xff, 0x00]] {
+                let line = encode_record(format, "id-1", data)?;
+                assert_eq!(
+                    Some((String::from("id-1"), data.to_vec())),
+                    decode_record(format, &line)?,
+                    "{format:?} {line}"
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_reads_s3_exports() -> Result<()> {
+        let line = r#"{"Item":{"id":{"S":"a"},"data":{"M":{"n":{"N":"1.5"},"l":{"L":[{"BOOL":true},{"NULL":true}]}}},"lock":{"S":"{}"}}}"#;
+        let (id, data) = decode_record(ExportFormat::S3Export, line)?.expect("has data");
+        assert_eq!("a", id);
+        assert_eq!(
+            serde_json::json!({ "n": 1.5, "l": [true, null] }),
+            serde_json::from_slice::<serde_json::Value>(&data)?
+        );
+
+        let unsaved = r#"{"Item":{"id":{"S":"b"},"lock":{"S":"{}"}}}"#;
+        assert_eq!(None, decode_record(ExportFormat::S3Export, unsaved)?);
+
+        Ok(())
+    }
+}
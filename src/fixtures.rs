@@ -0,0 +1,110 @@
+//! Seeding storages from and dumping them to a directory of `<id>.json` files,
+//! e.g. for test fixtures.
+//!
+//! Every file holds one item as written by [StorageItem::serialize].
+
+use crate::Storage;
+use crate::StorageId;
+use crate::StorageItem;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use std::path::Path;
+use tokio::fs;
+
+const EXTENSION: &str = "json";
+const WHO: &str = "fixtures";
+
+/// Inserts every `<id>.json` file in `path` into `storage`, via lock, save, and unlock.
+///
+/// Other files are ignored. Existing items are overwritten, locked ones fail.
+/// Returns the number of items inserted.
+pub async fn load_dir<ITEM, S>(storage: &S, path: &Path) -> Result<usize>
+where
+    ITEM: StorageItem + Send,
+    S: Storage<ITEM>,
+{
+    let mut files = Vec::new();
+    let mut entries = fs::read_dir(path).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let file = entry.path();
+        if file.extension().is_some_and(|e| e == EXTENSION) && entry.file_type().await?.is_file() {
+            files.push(file);
+        }
+    }
+    files.sort();
+
+    for file in files.iter() {
+        let stem = file
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .ok_or_else(|| eyre!("Invalid fixture file name {file:?}"))?;
+        let id = ITEM::ID::from_string(stem)?;
+        let data = fs::read(file).await?;
+        let item = ITEM::deserialize(&data).map_err(|e| eyre!("Invalid fixture {file:?}: {e}"))?;
+        let (lock, _) = storage.lock(&id, WHO).await?.success()?;
+        storage.save_and_unlock(&id, &item, lock).await?;
+    }
+
+    Ok(files.len())
+}
+
+/// Writes every item in `storage` to `path` as `<id>.json`, creating `path` if needed.
+///
+/// The counterpart of [load_dir]. Returns the number of items written.
+pub async fn dump_dir<ITEM, S>(storage: &S, path: &Path) -> Result<usize>
+where
+    ITEM: StorageItem + Send,
+    S: Storage<ITEM>,
+{
+    fs::create_dir_all(path).await?;
+    let ids = storage.all_ids().await?;
+    for id in ids.iter() {
+        let item = storage.load(id).await?;
+        let file = path.join(format!("{id}.{EXTENSION}"));
+        fs::write(file, item.serialize()?).await?;
+    }
+
+    Ok(ids.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures;
+    use crate::test_item::TestItem;
+    use crate::Storage;
+    use crate::StorageMemory;
+    use color_eyre::Result;
+
+    #[tokio::test]
+    async fn it_loads_and_dumps_fixtures() -> Result<()> {
+        let mut path = std::env::current_dir()?;
+        path.push("data");
+        path.push("test_fixtures");
+        let _ = std::fs::remove_dir_all(&path);
+        let source = path.join("source");
+        std::fs::create_dir_all(&source)?;
+        std::fs::write(source.join("first.json"), br#"{"count":1}"#)?;
+        std::fs::write(source.join("second.json"), br#"{"count":2}"#)?;
+        std::fs::write(source.join("README.md"), b"not a fixture")?;
+
+        let storage = StorageMemory::<TestItem>::default();
+        assert_eq!(2, fixtures::load_dir(&storage, &source).await?);
+        assert_eq!(
+            TestItem { count: 2 },
+            storage.load(&"second".to_string()).await?
+        );
+        assert!(storage.display_lock(&"first".to_string()).await?.is_empty());
+
+        let dump = path.join("dump");
+        assert_eq!(2, fixtures::dump_dir(&storage, &dump).await?);
+        let reloaded = StorageMemory::<TestItem>::default();
+        assert_eq!(2, fixtures::load_dir(&reloaded, &dump).await?);
+        assert_eq!(
+            TestItem { count: 1 },
+            reloaded.load(&"first".to_string()).await?
+        );
+
+        let _ = std::fs::remove_dir_all(&path);
+        Ok(())
+    }
+}
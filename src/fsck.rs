@@ -0,0 +1,361 @@
+use crate::links::find_dangling_links;
+use crate::links::DanglingLink;
+use crate::storage_item::deserialize_item;
+use crate::LockResult;
+use crate::Storage;
+use crate::StorageError;
+use crate::StorageItem;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+const LIST_PAGE_SIZE: usize = 1000;
+/// Who holds the locks taken to remove dangling links
+const FSCK_WHO: &str = "fsck";
+
+/// What [Storage::fsck] checks and whether it repairs what is safe to repair.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FsckOptions {
+    /// Locks older than this are reported as stale,
+    /// and items locked this long but never saved are orphaned
+    pub max_lock_age: Duration,
+    /// Removes orphaned locks via [Storage::remove_orphaned_locks] and dangling links.
+    /// Stale locks of saved items are only reported, their holder might still be working
+    pub repair: bool,
+    /// Relations whose links are checked for missing targets, see [find_dangling_links].
+    /// Every relation scans all items again, and backends without links fail the check
+    pub link_relations: Vec<String>,
+}
+
+impl Default for FsckOptions {
+    fn default() -> Self {
+        Self {
+            max_lock_age: Duration::from_secs(60 * 60),
+            repair: false,
+            link_relations: Vec::new(),
+        }
+    }
+}
+
+impl FsckOptions {
+    pub fn with_repair(mut self, repair: bool) -> Self {
+        self.repair = repair;
+        self
+    }
+
+    pub fn with_link_relation(mut self, relation: &str) -> Self {
+        self.link_relations.push(relation.to_string());
+        self
+    }
+}
+
+/// The item count from the metadata and the items counted while checking.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct MetadataDrift {
+    pub recorded: u64,
+    pub counted: u64,
+}
+
+/// The anomalies found by [Storage::fsck], ids are strings so reports can be logged or stored as is.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct FsckReport {
+    pub items_checked: u64,
+    /// Items whose stored data can't be read, e.g. because of corrupt compression or failed checksums
+    pub unreadable: Vec<String>,
+    /// Items that can be read but fail to deserialize or [StorageItem::validate]
+    pub invalid: Vec<String>,
+    /// Saved items locked for longer than [FsckOptions::max_lock_age]
+    pub stale_locks: Vec<String>,
+    /// How many orphaned locks were removed, `None` unless repairing on a backend that supports it
+    pub orphaned_locks_removed: Option<usize>,
+    /// `None` if the metadata matches, or the backend doesn't track an item count
+    pub metadata_drift: Option<MetadataDrift>,
+    /// Links pointing to missing items, by relation, without the removed ones
+    pub dangling_links: BTreeMap<String, Vec<DanglingLink<String>>>,
+    /// How many dangling links were removed, `None` unless repairing
+    pub dangling_links_removed: Option<usize>,
+}
+
+impl FsckReport {
+    /// No anomalies found, repaired ones don't count.
+    pub fn is_clean(&self) -> bool {
+        self.unreadable.is_empty()
+            && self.invalid.is_empty()
+            && self.stale_locks.is_empty()
+            && self.metadata_drift.is_none()
+            && self.dangling_links.is_empty()
+    }
+}
+
+/// Loads every item [Storage::list] reports, compares the count with the metadata, and checks the links.
+/// Only reads, unless [FsckOptions::repair] is set.
+pub(crate) async fn fsck<ITEM, S>(
+    storage: &S,
+    options: &FsckOptions,
+) -> color_eyre::eyre::Result<FsckReport>
+where
+    ITEM: StorageItem + Send,
+    S: Storage<ITEM> + ?Sized,
+{
+    let mut report = FsckReport::default();
+    if options.repair {
+        match storage.remove_orphaned_locks(options.max_lock_age).await {
+            Ok(removed) => report.orphaned_locks_removed = Some(removed),
+            Err(e) => tracing::warn!("Fsck - can't remove orphaned locks -> {e:?}"),
+        }
+    }
+
+    let now = chrono::Utc::now();
+    let mut start = None;
+    loop {
+        let page = storage.list(start.as_deref(), Some(LIST_PAGE_SIZE)).await?;
+        for summary in page.items {
+            report.items_checked += 1;
+            let id = summary.id.to_string();
+            let data = match storage.load_raw(&summary.id).await {
+                Ok(data) => data,
+                // removed while checking
+                Err(e) if matches!(e.downcast_ref(), Some(StorageError::NotFound { .. })) => {
+                    report.items_checked -= 1;
+                    continue;
+                }
+                Err(e) => {
+                    tracing::warn!("Fsck - {id} is unreadable -> {e:?}");
+                    report.unreadable.push(id);
+                    continue;
+                }
+            };
+            if summary
+                .lock_age(now)
+                .is_some_and(|age| age > options.max_lock_age)
+            {
+                report.stale_locks.push(id.clone());
+            }
+            if let Err(e) = deserialize_item::<ITEM>(&data).and_then(|item| item.validate()) {
+                tracing::warn!("Fsck - {id} is invalid -> {e:?}");
+                report.invalid.push(id);
+            }
+        }
+        start = page.cursor;
+        if start.is_none() {
+            break;
+        }
+    }
+
+    for relation in &options.link_relations {
+        for link in find_dangling_links(storage, relation).await? {
+            if options.repair {
+                let removed = report.dangling_links_removed.get_or_insert(0);
+                match remove_dangling_link(storage, relation, &link).await {
+                    Ok(true) => {
+                        *removed += 1;
+                        continue;
+                    }
+                    Ok(false) => {}
+                    Err(e) => tracing::warn!(
+                        "Fsck - can't remove link {} -> {} via {relation} -> {e:?}",
+                        link.from,
+                        link.to
+                    ),
+                }
+            }
+            report
+                .dangling_links
+                .entry(relation.clone())
+                .or_default()
+                .push(DanglingLink {
+                    from: link.from.to_string(),
+                    to: link.to.to_string(),
+                });
+        }
+    }
+
+    #[cfg(feature = "metadata")]
+    if let Some(recorded) = storage.metadata_item_count().await {
+        if recorded != report.items_checked {
+            report.metadata_drift = Some(MetadataDrift {
+                recorded,
+                counted: report.items_checked,
+            });
+        }
+    }
+
+    Ok(report)
+}
+
+/// Removes the link under a lock of `from`, unless `from` is locked or `to` exists again.
+async fn remove_dangling_link<ITEM, S>(
+    storage: &S,
+    relation: &str,
+    link: &DanglingLink<ITEM::ID>,
+) -> color_eyre::eyre::Result<bool>
+where
+    ITEM: StorageItem + Send,
+    S: Storage<ITEM> + ?Sized,
+{
+    let lock = match storage.lock(&link.from, FSCK_WHO).await? {
+        LockResult::Success { lock, .. } => lock,
+        LockResult::AlreadyLocked { who, .. } => {
+            tracing::warn!(
+                "Fsck - {} is locked by {who:?}, keeping its link",
+                link.from
+            );
+            return Ok(false);
+        }
+    };
+    let removed = match storage.exists(&link.to).await {
+        // recreated while checking
+        Ok(true) => Ok(false),
+        Ok(false) => storage
+            .remove_link(&link.from, relation, &link.to, &lock)
+            .await
+            .map(|_| true),
+        Err(e) => Err(e),
+    };
+    storage.unlock(&link.from, lock).await?;
+
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::links::DanglingLink;
+    use crate::test_item::TestItem;
+    use crate::FsckOptions;
+    use crate::MockResponse;
+    use crate::Storage;
+    use crate::StorageDisk;
+    use crate::StorageMemory;
+    use crate::StorageMock;
+    use crate::StorageOperation;
+    use color_eyre::eyre::eyre;
+    use color_eyre::Result;
+    use std::env;
+    use std::path::Path;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn it_removes_orphaned_locks_only_when_repairing() -> Result<()> {
+        let mut storage = StorageMemory::<TestItem>::default();
+        let clock = crate::MockClock::new(chrono::Utc::now() - chrono::Duration::hours(2));
+        storage.set_clock(clock.clone())?;
+        let orphan = String::from("orphan");
+        storage.lock(&orphan, "crashed").await?.success()?;
+        clock.advance(Duration::from_secs(2 * 60 * 60));
+
+        let report = storage.fsck(&FsckOptions::default()).await?;
+        assert_eq!(0, report.items_checked);
+        assert_eq!(None, report.orphaned_locks_removed);
+        assert!(storage.exists(&orphan).await?);
+
+        let options = FsckOptions::default().with_repair(true);
+        let report = storage.fsck(&options).await?;
+        assert_eq!(Some(1), report.orphaned_locks_removed);
+        assert!(!storage.exists(&orphan).await?);
+        assert!(report.is_clean());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_reports_corrupt_items() -> Result<()> {
+        let storage = StorageMock::<TestItem>::default();
+        let ids = ["unreadable", "removed", "fine"].map(String::from).to_vec();
+        storage.script(StorageOperation::List, MockResponse::Ids(ids));
+        storage.script(
+            StorageOperation::Load,
+            MockResponse::Err(eyre!("Checksum mismatch")),
+        );
+        storage.script(
+            StorageOperation::Load,
+            MockResponse::Err(crate::StorageError::NotFound { id: String::new() }.into()),
+        );
+
+        let report = storage.fsck(&FsckOptions::default()).await?;
+        assert_eq!(2, report.items_checked);
+        assert_eq!(vec![String::from("unreadable")], report.unreadable);
+        assert!(report.invalid.is_empty());
+        assert!(!report.is_clean());
+
+        let storage = StorageMemory::<TestItem>::default();
+        let invalid = String::from("invalid");
+        let (lock, _) = storage.lock(&invalid, "TEST").await?.success()?;
+        storage.save_raw(&invalid, b"{\"count\":-1}", &lock).await?;
+        storage.unlock(&invalid, lock).await?;
+
+        let report = storage.fsck(&FsckOptions::default()).await?;
+        assert_eq!(vec![invalid], report.invalid);
+        assert!(!report.is_clean());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_removes_dangling_links_when_repairing() -> Result<()> {
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_items_fsck_links");
+        let _ = std::fs::remove_dir_all(&path);
+        let mut storage = StorageDisk::<TestItem>::new(&path, Path::new("test_item")).await;
+        storage.ensure_storage_exists().await?;
+
+        let guild = String::from("guild");
+        let members = ["m1", "m2"].map(String::from);
+        for id in members.iter().chain([&guild]) {
+            let (lock, item) = storage.lock(id, "TEST").await?.success()?;
+            storage.save_and_unlock(id, &item, lock).await?;
+        }
+        let (lock, _) = storage.lock(&guild, "TEST").await?.success()?;
+        for member in members.iter() {
+            storage.add_link(&guild, "member", member, &lock).await?;
+        }
+        storage.unlock(&guild, lock).await?;
+        std::fs::remove_file(path.join("m1.test_item"))?;
+
+        let options = FsckOptions::default().with_link_relation("member");
+        let report = storage.fsck(&options).await?;
+        assert_eq!(
+            Some(&vec![DanglingLink {
+                from: guild.clone(),
+                to: members[0].clone(),
+            }]),
+            report.dangling_links.get("member")
+        );
+        assert_eq!(None, report.dangling_links_removed);
+        assert!(!report.is_clean());
+
+        // a locked item keeps its links
+        let (lock, _) = storage.lock(&guild, "worker").await?.success()?;
+        let report = storage.fsck(&options.clone().with_repair(true)).await?;
+        assert_eq!(Some(0), report.dangling_links_removed);
+        assert_eq!(1, report.dangling_links.len());
+        storage.unlock(&guild, lock).await?;
+
+        let report = storage.fsck(&options.with_repair(true)).await?;
+        assert_eq!(Some(1), report.dangling_links_removed);
+        assert!(report.dangling_links.is_empty());
+        assert!(report.is_clean());
+        assert_eq!(
+            vec![members[1].clone()],
+            storage.links_of(&guild, "member").await?
+        );
+        // the repair released its lock
+        storage.lock(&guild, "TEST").await?.success()?;
+
+        let _ = std::fs::remove_dir_all(&path);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_fails_link_checks_without_links() -> Result<()> {
+        let storage = StorageMemory::<TestItem>::default();
+        let id = String::from("a");
+        let (lock, item) = storage.lock(&id, "TEST").await?.success()?;
+        storage.save_and_unlock(&id, &item, lock).await?;
+
+        let options = FsckOptions::default().with_link_relation("member");
+        assert!(storage.fsck(&options).await.is_err());
+
+        Ok(())
+    }
+}
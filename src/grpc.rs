@@ -0,0 +1,884 @@
+//! gRPC access to any [Storage], behind the `grpc` feature, see `proto/oml_storage.proto`,
+//! e.g. for a storage daemon holding the disk backend, with many workers connecting remotely.
+//!
+//! [StorageGrpcService] serves a backend, [StorageGrpc] is the backend of the workers.
+//! Locks are taken and checked by the daemon, so they work across all its clients.
+
+use crate::LockResult;
+#[cfg(feature = "metadata")]
+use crate::Metadata;
+use crate::Page;
+use crate::Storage;
+use crate::StorageError;
+use crate::StorageId;
+use crate::StorageItem;
+use crate::StorageLock;
+use crate::StorageOperation;
+use async_trait::async_trait;
+use chrono::DateTime;
+use chrono::SecondsFormat;
+use chrono::Utc;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use core::marker::PhantomData;
+use proto::lock_response;
+use proto::storage_client::StorageClient;
+use proto::storage_server::StorageServer;
+use std::sync::Arc;
+use tonic::transport::Channel;
+use tonic::Code;
+use tonic::Request;
+use tonic::Response;
+use tonic::Status;
+
+/// The code generated from `proto/oml_storage.proto`.
+#[allow(clippy::all)]
+pub mod proto {
+    tonic::include_proto!("oml_storage.v1");
+}
+
+fn format_when(when: &DateTime<Utc>) -> String {
+    when.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+fn parse_when(when: &str) -> Result<DateTime<Utc>> {
+    Ok(DateTime::parse_from_rfc3339(when)?.with_timezone(&Utc))
+}
+
+fn lock_to_proto(lock: &StorageLock) -> proto::Lock {
+    proto::Lock {
+        who: lock.who().to_string(),
+        when: format_when(lock.when()),
+        token: lock.token().unwrap_or_default().to_string(),
+    }
+}
+
+fn lock_from_proto(lock: Option<proto::Lock>) -> Result<StorageLock> {
+    let lock = lock.ok_or_else(|| eyre!("Missing lock"))?;
+    let when = parse_when(&lock.when)?;
+
+    Ok(StorageLock::observed(&lock.who, when).with_token(&lock.token))
+}
+
+fn parse_id<ID: StorageId>(id: &str) -> Result<ID, Status> {
+    ID::from_string(id).map_err(|e| Status::invalid_argument(format!("Invalid id {id:?} -> {e}")))
+}
+
+fn invalid_argument(e: color_eyre::Report) -> Status {
+    Status::invalid_argument(e.to_string())
+}
+
+/// The status for a failed operation of the served backend.
+fn to_status(e: color_eyre::Report) -> Status {
+    let code = match e.downcast_ref::<StorageError>() {
+        Some(StorageError::NotFound { .. }) => Code::NotFound,
+        Some(StorageError::InvalidId { .. } | StorageError::InvalidItem { .. }) => {
+            Code::InvalidArgument
+        }
+        Some(StorageError::PermissionDenied { .. }) => Code::PermissionDenied,
+        Some(
+            StorageError::AlreadyLocked { .. }
+            | StorageError::LockMismatch { .. }
+            | StorageError::ReadOnly,
+        ) => Code::FailedPrecondition,
+        Some(StorageError::Immutable { .. }) => Code::AlreadyExists,
+        Some(
+            StorageError::ItemTooLarge { .. }
+            | StorageError::QuotaExceeded { .. }
+            | StorageError::NamespaceQuotaExceeded { .. },
+        ) => Code::ResourceExhausted,
+        Some(StorageError::Unsupported { .. }) => Code::Unimplemented,
+        Some(StorageError::Corrupt { .. }) => Code::DataLoss,
+        Some(e) if e.is_transient() => Code::Unavailable,
+        _ => Code::Internal,
+    };
+    if code == Code::Internal {
+        tracing::warn!("Request failed -> {e:?}");
+    }
+    Status::new(code, e.to_string())
+}
+
+/// The error for a failed request of [StorageGrpc], [StorageError]s where the code allows.
+fn from_status(
+    status: Status,
+    operation: StorageOperation,
+    id: Option<&dyn std::fmt::Display>,
+) -> color_eyre::Report {
+    let error = match (status.code(), id) {
+        (Code::NotFound, Some(id)) => StorageError::NotFound { id: id.to_string() },
+        (Code::DataLoss, Some(id)) => StorageError::Corrupt {
+            id: id.to_string(),
+            preserved_at: None,
+        },
+        (Code::Unimplemented, _) => StorageError::Unsupported { operation },
+        (Code::Unavailable, _) => StorageError::Backend {
+            operation,
+            transient: true,
+            message: status.message().to_string(),
+        },
+        (code, _) => {
+            return eyre!(
+                "{operation} failed remotely, {code:?} -> {}",
+                status.message()
+            );
+        }
+    };
+
+    eyre!(status).wrap_err(error)
+}
+
+/// Serves a [Storage] via gRPC, see the [module](crate::grpc).
+#[derive(Debug)]
+pub struct StorageGrpcService<ITEM, S> {
+    storage: Arc<S>,
+    item_type: PhantomData<fn() -> ITEM>,
+}
+
+impl<ITEM, S> StorageGrpcService<ITEM, S>
+where
+    ITEM: StorageItem + Send + 'static,
+    S: Storage<ITEM> + 'static,
+{
+    pub fn new(storage: Arc<S>) -> Self {
+        Self {
+            storage,
+            item_type: PhantomData,
+        }
+    }
+
+    /// The tonic service, e.g. to add it to a larger server.
+    pub fn into_server(self) -> StorageServer<Self> {
+        StorageServer::new(self)
+    }
+
+    /// Serves until the listener fails.
+    pub async fn serve(self, listener: tokio::net::TcpListener) -> Result<()> {
+        tonic::transport::Server::builder()
+            .add_service(self.into_server())
+            .serve_with_incoming(tonic::transport::server::TcpIncoming::from(listener))
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<ITEM, S> proto::storage_server::Storage for StorageGrpcService<ITEM, S>
+where
+    ITEM: StorageItem + Send + 'static,
+    S: Storage<ITEM> + 'static,
+{
+    async fn create(
+        &self,
+        _request: Request<proto::CreateRequest>,
+    ) -> Result<Response<proto::IdResponse>, Status> {
+        let id = self.storage.create().await.map_err(to_status)?;
+
+        Ok(Response::new(proto::IdResponse { id: id.to_string() }))
+    }
+
+    async fn exists(
+        &self,
+        request: Request<proto::IdRequest>,
+    ) -> Result<Response<proto::BoolResponse>, Status> {
+        let id = parse_id::<ITEM::ID>(&request.into_inner().id)?;
+        let value = self.storage.exists(&id).await.map_err(to_status)?;
+
+        Ok(Response::new(proto::BoolResponse { value }))
+    }
+
+    async fn load(
+        &self,
+        request: Request<proto::IdRequest>,
+    ) -> Result<Response<proto::ItemResponse>, Status> {
+        let id = parse_id::<ITEM::ID>(&request.into_inner().id)?;
+        let data = self.storage.load_raw(&id).await.map_err(to_status)?;
+
+        Ok(Response::new(proto::ItemResponse { data }))
+    }
+
+    async fn save(
+        &self,
+        request: Request<proto::SaveRequest>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        let request = request.into_inner();
+        let id = parse_id::<ITEM::ID>(&request.id)?;
+        let lock = lock_from_proto(request.lock).map_err(invalid_argument)?;
+        self.storage
+            .save_raw(&id, &request.data, &lock)
+            .await
+            .map_err(to_status)?;
+
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    async fn save_and_unlock(
+        &self,
+        request: Request<proto::SaveRequest>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        let request = request.into_inner();
+        let id = parse_id::<ITEM::ID>(&request.id)?;
+        let lock = lock_from_proto(request.lock).map_err(invalid_argument)?;
+        self.storage
+            .save_raw(&id, &request.data, &lock)
+            .await
+            .map_err(to_status)?;
+        self.storage.unlock(&id, lock).await.map_err(to_status)?;
+
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    async fn lock(
+        &self,
+        request: Request<proto::LockRequest>,
+    ) -> Result<Response<proto::LockResponse>, Status> {
+        let request = request.into_inner();
+        let id = parse_id::<ITEM::ID>(&request.id)?;
+        let result = match self
+            .storage
+            .lock(&id, &request.who)
+            .await
+            .map_err(to_status)?
+        {
+            LockResult::Success { lock, item } => {
+                let data = item.serialize().map_err(to_status)?;
+                lock_response::Result::Success(proto::LockSuccess {
+                    lock: Some(lock_to_proto(&lock)),
+                    data,
+                })
+            }
+            LockResult::AlreadyLocked { who, when } => {
+                lock_response::Result::AlreadyLocked(proto::LockHolder {
+                    who,
+                    when: format_when(&when),
+                })
+            }
+        };
+
+        Ok(Response::new(proto::LockResponse {
+            result: Some(result),
+        }))
+    }
+
+    async fn unlock(
+        &self,
+        request: Request<proto::UnlockRequest>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        let request = request.into_inner();
+        let id = parse_id::<ITEM::ID>(&request.id)?;
+        let lock = lock_from_proto(request.lock).map_err(invalid_argument)?;
+        self.storage.unlock(&id, lock).await.map_err(to_status)?;
+
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    async fn force_unlock(
+        &self,
+        request: Request<proto::IdRequest>,
+    ) -> Result<Response<proto::ForceUnlockResponse>, Status> {
+        let id = parse_id::<ITEM::ID>(&request.into_inner().id)?;
+        let lock = self.storage.force_unlock(&id).await.map_err(to_status)?;
+
+        Ok(Response::new(proto::ForceUnlockResponse {
+            lock: lock.as_ref().map(lock_to_proto),
+        }))
+    }
+
+    async fn force_unlock_if_held(
+        &self,
+        request: Request<proto::ForceUnlockIfHeldRequest>,
+    ) -> Result<Response<proto::BoolResponse>, Status> {
+        let request = request.into_inner();
+        let id = parse_id::<ITEM::ID>(&request.id)?;
+        let when = parse_when(&request.when).map_err(invalid_argument)?;
+        let value = self
+            .storage
+            .force_unlock_if_held(&id, &request.who, when)
+            .await
+            .map_err(to_status)?;
+
+        Ok(Response::new(proto::BoolResponse { value }))
+    }
+
+    async fn verify_lock(
+        &self,
+        request: Request<proto::UnlockRequest>,
+    ) -> Result<Response<proto::BoolResponse>, Status> {
+        let request = request.into_inner();
+        let id = parse_id::<ITEM::ID>(&request.id)?;
+        let lock = lock_from_proto(request.lock).map_err(invalid_argument)?;
+        let value = self
+            .storage
+            .verify_lock(&id, &lock)
+            .await
+            .map_err(to_status)?;
+
+        Ok(Response::new(proto::BoolResponse { value }))
+    }
+
+    async fn all_ids(
+        &self,
+        _request: Request<proto::AllIdsRequest>,
+    ) -> Result<Response<proto::ScanIdsResponse>, Status> {
+        let ids = self.storage.all_ids().await.map_err(to_status)?;
+
+        Ok(Response::new(proto::ScanIdsResponse {
+            ids: ids.iter().map(|id| id.to_string()).collect(),
+            cursor: None,
+        }))
+    }
+
+    async fn scan_ids(
+        &self,
+        request: Request<proto::ScanIdsRequest>,
+    ) -> Result<Response<proto::ScanIdsResponse>, Status> {
+        let request = request.into_inner();
+        let limit = request
+            .limit
+            .map(usize::try_from)
+            .transpose()
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let page = self
+            .storage
+            .scan_ids_with_prefix(&request.prefix, request.start.as_deref(), limit)
+            .await
+            .map_err(to_status)?;
+
+        Ok(Response::new(proto::ScanIdsResponse {
+            ids: page.items.iter().map(|id| id.to_string()).collect(),
+            cursor: page.cursor,
+        }))
+    }
+
+    async fn display_lock(
+        &self,
+        request: Request<proto::IdRequest>,
+    ) -> Result<Response<proto::DisplayLockResponse>, Status> {
+        let id = parse_id::<ITEM::ID>(&request.into_inner().id)?;
+        let status = self.storage.display_lock(&id).await.map_err(to_status)?;
+
+        Ok(Response::new(proto::DisplayLockResponse { status }))
+    }
+}
+
+/// A backend using a remote [StorageGrpcService], e.g. a storage daemon.
+///
+/// Metadata only covers what this client saw, e.g. its own locks.
+#[derive(Debug)]
+pub struct StorageGrpc<ITEM: StorageItem> {
+    client: StorageClient<Channel>,
+    item_type: PhantomData<ITEM>,
+    #[cfg(feature = "metadata")]
+    metadata: Metadata<ITEM>,
+}
+
+impl<ITEM: StorageItem> StorageGrpc<ITEM> {
+    /// Connects to `endpoint`, e.g. `http://storage-daemon:50051`, lazily, on the first request.
+    pub fn new(endpoint: &str) -> Result<Self> {
+        let channel = tonic::transport::Endpoint::from_shared(endpoint.to_string())?.connect_lazy();
+
+        Ok(Self::from_channel(channel))
+    }
+
+    /// E.g. for a channel with TLS or timeouts configured.
+    pub fn from_channel(channel: Channel) -> Self {
+        Self {
+            client: StorageClient::new(channel),
+            item_type: PhantomData,
+            #[cfg(feature = "metadata")]
+            metadata: Metadata::default(),
+        }
+    }
+
+    /// The generated client is cheap to clone and needs `&mut` per request.
+    fn client(&self) -> StorageClient<Channel> {
+        self.client.clone()
+    }
+}
+
+#[cfg(feature = "metadata")]
+impl<ITEM: StorageItem> StorageGrpc<ITEM> {
+    fn update_highest_seen_id(&self, id: &ITEM::ID) {
+        self.metadata.update_highest_seen_id(id);
+    }
+    fn record_lock_acquired(&self, id: &ITEM::ID, lock: &StorageLock) {
+        self.metadata.record_lock_acquired(id, lock);
+    }
+    fn record_lock_contended(&self) {
+        self.metadata.record_lock_contended();
+    }
+    fn record_lock_released(&self, id: &ITEM::ID, lock: &StorageLock) {
+        self.metadata.record_lock_released(id, lock, Utc::now());
+    }
+}
+
+#[cfg(not(feature = "metadata"))]
+impl<ITEM: StorageItem> StorageGrpc<ITEM> {
+    fn update_highest_seen_id(&self, _id: &ITEM::ID) {}
+    fn record_lock_acquired(&self, _id: &ITEM::ID, _lock: &StorageLock) {}
+    fn record_lock_contended(&self) {}
+    fn record_lock_released(&self, _id: &ITEM::ID, _lock: &StorageLock) {}
+}
+
+#[async_trait]
+impl<ITEM: StorageItem + std::marker::Send> Storage<ITEM> for StorageGrpc<ITEM> {
+    /// The daemon ensures its own storage exists, this only checks it can be reached.
+    #[tracing::instrument(
+        name = "storage.ensure_storage_exists",
+        skip_all,
+        fields(backend = "grpc", db.system = "grpc", db.operation = "ScanIds")
+    )]
+    async fn ensure_storage_exists(&mut self) -> Result<()> {
+        self.client()
+            .scan_ids(proto::ScanIdsRequest {
+                limit: Some(1),
+                ..Default::default()
+            })
+            .await
+            .map_err(|s| from_status(s, StorageOperation::EnsureStorageExists, None))?;
+
+        Ok(())
+    }
+
+    fn capabilities(&self) -> crate::Capabilities {
+        crate::Capabilities {
+            scan: true,
+            ..Default::default()
+        }
+    }
+
+    #[tracing::instrument(
+        name = "storage.create",
+        skip_all,
+        fields(backend = "grpc", db.system = "grpc", db.operation = "Create")
+    )]
+    async fn create(&self) -> Result<ITEM::ID> {
+        let response = self
+            .client()
+            .create(proto::CreateRequest {})
+            .await
+            .map_err(|s| from_status(s, StorageOperation::Create, None))?;
+        let id = ITEM::ID::from_string(&response.into_inner().id)?;
+        self.update_highest_seen_id(&id);
+
+        Ok(id)
+    }
+
+    #[tracing::instrument(
+        name = "storage.exists",
+        skip_all,
+        fields(backend = "grpc", db.system = "grpc", db.operation = "Exists", id = %id)
+    )]
+    async fn exists(&self, id: &ITEM::ID) -> Result<bool> {
+        let response = self
+            .client()
+            .exists(proto::IdRequest { id: id.to_string() })
+            .await
+            .map_err(|s| from_status(s, StorageOperation::Exists, Some(id)))?;
+
+        Ok(response.into_inner().value)
+    }
+
+    #[tracing::instrument(
+        name = "storage.load",
+        skip_all,
+        fields(backend = "grpc", db.system = "grpc", db.operation = "Load", id = %id)
+    )]
+    async fn load(&self, id: &ITEM::ID) -> Result<ITEM> {
+        let data = self.load_raw(id).await?;
+        let item = crate::storage_item::deserialize_stored(id, &data)?;
+        self.update_highest_seen_id(id);
+
+        Ok(item)
+    }
+
+    #[tracing::instrument(
+        name = "storage.load_raw",
+        skip_all,
+        fields(backend = "grpc", db.system = "grpc", db.operation = "Load", id = %id)
+    )]
+    async fn load_raw(&self, id: &ITEM::ID) -> Result<Vec<u8>> {
+        let response = self
+            .client()
+            .load(proto::IdRequest { id: id.to_string() })
+            .await
+            .map_err(|s| from_status(s, StorageOperation::LoadRaw, Some(id)))?;
+
+        Ok(response.into_inner().data)
+    }
+
+    #[tracing::instrument(
+        name = "storage.save",
+        skip_all,
+        fields(backend = "grpc", db.system = "grpc", db.operation = "Save", id = %id)
+    )]
+    async fn save(&self, id: &ITEM::ID, item: &ITEM, lock: &StorageLock) -> Result<()> {
+        let data = crate::storage_item::serialize_valid(id, item)?;
+        self.save_raw(id, &data, lock).await
+    }
+
+    #[tracing::instrument(
+        name = "storage.save_raw",
+        skip_all,
+        fields(backend = "grpc", db.system = "grpc", db.operation = "Save", id = %id)
+    )]
+    async fn save_raw(&self, id: &ITEM::ID, data: &[u8], lock: &StorageLock) -> Result<()> {
+        self.client()
+            .save(proto::SaveRequest {
+                id: id.to_string(),
+                data: data.to_vec(),
+                lock: Some(lock_to_proto(lock)),
+            })
+            .await
+            .map_err(|s| from_status(s, StorageOperation::SaveRaw, Some(id)))?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(
+        name = "storage.save_and_unlock",
+        skip_all,
+        fields(backend = "grpc", db.system = "grpc", db.operation = "SaveAndUnlock", id = %id)
+    )]
+    async fn save_and_unlock(&self, id: &ITEM::ID, item: &ITEM, lock: StorageLock) -> Result<()> {
+        let data = crate::storage_item::serialize_valid(id, item)?;
+        self.client()
+            .save_and_unlock(proto::SaveRequest {
+                id: id.to_string(),
+                data,
+                lock: Some(lock_to_proto(&lock)),
+            })
+            .await
+            .map_err(|s| from_status(s, StorageOperation::SaveAndUnlock, Some(id)))?;
+        self.record_lock_released(id, &lock);
+
+        Ok(())
+    }
+
+    #[tracing::instrument(
+        name = "storage.lock",
+        skip_all,
+        fields(backend = "grpc", db.system = "grpc", db.operation = "Lock", id = %id, who = %who)
+    )]
+    async fn lock(&self, id: &ITEM::ID, who: &str) -> Result<LockResult<ITEM>> {
+        let response = self
+            .client()
+            .lock(proto::LockRequest {
+                id: id.to_string(),
+                who: who.to_string(),
+            })
+            .await
+            .map_err(|s| from_status(s, StorageOperation::Lock, Some(id)))?;
+        match response.into_inner().result {
+            Some(lock_response::Result::Success(success)) => {
+                let lock = lock_from_proto(success.lock)?;
+                let item = crate::storage_item::deserialize_stored(id, &success.data)?;
+                self.update_highest_seen_id(id);
+                self.record_lock_acquired(id, &lock);
+                Ok(LockResult::Success { lock, item })
+            }
+            Some(lock_response::Result::AlreadyLocked(holder)) => {
+                self.record_lock_contended();
+                Ok(LockResult::AlreadyLocked {
+                    who: holder.who,
+                    when: parse_when(&holder.when)?,
+                })
+            }
+            None => Err(eyre!("Locking {id} returned no result")),
+        }
+    }
+
+    #[tracing::instrument(
+        name = "storage.unlock",
+        skip_all,
+        fields(backend = "grpc", db.system = "grpc", db.operation = "Unlock", id = %id)
+    )]
+    async fn unlock(&self, id: &ITEM::ID, lock: StorageLock) -> Result<()> {
+        self.client()
+            .unlock(proto::UnlockRequest {
+                id: id.to_string(),
+                lock: Some(lock_to_proto(&lock)),
+            })
+            .await
+            .map_err(|s| from_status(s, StorageOperation::Unlock, Some(id)))?;
+        self.record_lock_released(id, &lock);
+
+        Ok(())
+    }
+
+    #[tracing::instrument(
+        name = "storage.force_unlock",
+        skip_all,
+        fields(backend = "grpc", db.system = "grpc", db.operation = "ForceUnlock", id = %id)
+    )]
+    async fn force_unlock(&self, id: &ITEM::ID) -> Result<Option<StorageLock>> {
+        let response = self
+            .client()
+            .force_unlock(proto::IdRequest { id: id.to_string() })
+            .await
+            .map_err(|s| from_status(s, StorageOperation::ForceUnlock, Some(id)))?;
+
+        response
+            .into_inner()
+            .lock
+            .map(|lock| lock_from_proto(Some(lock)))
+            .transpose()
+    }
+
+    #[tracing::instrument(
+        name = "storage.force_unlock_if_held",
+        skip_all,
+        fields(
+            backend = "grpc",
+            db.system = "grpc",
+            db.operation = "ForceUnlockIfHeld",
+            id = %id,
+            who = %who,
+        )
+    )]
+    async fn force_unlock_if_held(
+        &self,
+        id: &ITEM::ID,
+        who: &str,
+        when: DateTime<Utc>,
+    ) -> Result<bool> {
+        let response = self
+            .client()
+            .force_unlock_if_held(proto::ForceUnlockIfHeldRequest {
+                id: id.to_string(),
+                who: who.to_string(),
+                when: format_when(&when),
+            })
+            .await
+            .map_err(|s| from_status(s, StorageOperation::ForceUnlock, Some(id)))?;
+
+        Ok(response.into_inner().value)
+    }
+
+    #[tracing::instrument(
+        name = "storage.verify_lock",
+        skip_all,
+        fields(backend = "grpc", db.system = "grpc", db.operation = "VerifyLock", id = %id)
+    )]
+    async fn verify_lock(&self, id: &ITEM::ID, lock: &StorageLock) -> Result<bool> {
+        let response = self
+            .client()
+            .verify_lock(proto::UnlockRequest {
+                id: id.to_string(),
+                lock: Some(lock_to_proto(lock)),
+            })
+            .await
+            .map_err(|s| from_status(s, StorageOperation::VerifyLock, Some(id)))?;
+
+        Ok(response.into_inner().value)
+    }
+
+    #[tracing::instrument(
+        name = "storage.all_ids",
+        skip_all,
+        fields(backend = "grpc", db.system = "grpc", db.operation = "AllIds")
+    )]
+    async fn all_ids(&self) -> Result<Vec<ITEM::ID>> {
+        let response = self
+            .client()
+            .all_ids(proto::AllIdsRequest {})
+            .await
+            .map_err(|s| from_status(s, StorageOperation::AllIds, None))?;
+
+        response
+            .into_inner()
+            .ids
+            .iter()
+            .map(|id| ITEM::ID::from_string(id))
+            .collect()
+    }
+
+    #[tracing::instrument(
+        name = "storage.scan_ids",
+        skip_all,
+        fields(backend = "grpc", db.system = "grpc", db.operation = "ScanIds")
+    )]
+    async fn scan_ids(&self, start: Option<&str>, limit: Option<usize>) -> Result<Page<ITEM::ID>> {
+        self.scan_ids_with_prefix("", start, limit).await
+    }
+
+    /// Filtered by the daemon.
+    #[tracing::instrument(
+        name = "storage.scan_ids_with_prefix",
+        skip_all,
+        fields(backend = "grpc", db.system = "grpc", db.operation = "ScanIds")
+    )]
+    async fn scan_ids_with_prefix(
+        &self,
+        prefix: &str,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        let response = self
+            .client()
+            .scan_ids(proto::ScanIdsRequest {
+                prefix: prefix.to_string(),
+                start: start.map(str::to_string),
+                limit: limit.map(|limit| limit as u64),
+            })
+            .await
+            .map_err(|s| from_status(s, StorageOperation::ScanIds, None))?
+            .into_inner();
+        let ids = response
+            .ids
+            .iter()
+            .map(|id| ITEM::ID::from_string(id))
+            .collect::<Result<_>>()?;
+
+        Ok(Page::new(ids, response.cursor))
+    }
+
+    #[tracing::instrument(
+        name = "storage.display_lock",
+        skip_all,
+        fields(backend = "grpc", db.system = "grpc", db.operation = "DisplayLock", id = %id)
+    )]
+    async fn display_lock(&self, id: &ITEM::ID) -> Result<String> {
+        let response = self
+            .client()
+            .display_lock(proto::IdRequest { id: id.to_string() })
+            .await
+            .map_err(|s| from_status(s, StorageOperation::DisplayLock, Some(id)))?;
+
+        Ok(response.into_inner().status)
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_highest_seen_id(&self) -> Option<ITEM::ID> {
+        self.metadata.highest_seen_id()
+    }
+    #[cfg(feature = "metadata")]
+    async fn metadata_item_count(&self) -> Option<u64> {
+        self.metadata.item_count()
+    }
+    #[cfg(feature = "metadata")]
+    async fn metadata_total_bytes(&self) -> Option<u64> {
+        self.metadata.total_bytes()
+    }
+    #[cfg(feature = "metadata")]
+    async fn metadata_last_write(&self) -> Option<DateTime<Utc>> {
+        self.metadata.last_write()
+    }
+    #[cfg(feature = "metadata")]
+    async fn metadata_lock_stats(&self) -> crate::LockStats {
+        self.metadata.lock_stats()
+    }
+
+    /// Not exposed by the daemon, wipe its storage directly.
+    #[cfg(feature = "wipe")]
+    async fn wipe(
+        &self,
+        _options: &crate::WipeOptions,
+        _progress: Option<&dyn crate::ProgressSink>,
+        _cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        Err(StorageError::Unsupported {
+            operation: StorageOperation::Wipe,
+        }
+        .into())
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn wipe_matching(
+        &self,
+        _filter: &crate::IdFilter,
+        _options: &crate::WipeOptions,
+        _progress: Option<&dyn crate::ProgressSink>,
+        _cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        Err(StorageError::Unsupported {
+            operation: StorageOperation::WipeMatching,
+        }
+        .into())
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn delete_many(
+        &self,
+        _ids: &[ITEM::ID],
+        _options: &crate::DeleteOptions,
+    ) -> Result<Vec<crate::DeleteOutcome>> {
+        Err(StorageError::Unsupported {
+            operation: StorageOperation::DeleteMany,
+        }
+        .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StorageMemory;
+    use serde::Deserialize;
+    use serde::Serialize;
+
+    #[derive(Default, Debug, PartialEq, Serialize, Deserialize)]
+    struct TestItem {
+        name: String,
+    }
+
+    impl StorageItem for TestItem {
+        type ID = String;
+
+        fn serialize(&self) -> Result<Vec<u8>> {
+            Ok(serde_json::to_vec(&self)?)
+        }
+        fn deserialize(data: &[u8]) -> Result<Self> {
+            Ok(serde_json::from_slice(data)?)
+        }
+    }
+
+    #[tokio::test]
+    async fn it_shares_locks_via_grpc() -> Result<()> {
+        let daemon = Arc::new(StorageMemory::<TestItem>::default());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let endpoint = format!("http://{}", listener.local_addr()?);
+        let service = StorageGrpcService::new(daemon.clone());
+        tokio::spawn(service.serve(listener));
+
+        let mut worker = StorageGrpc::<TestItem>::new(&endpoint)?;
+        worker.ensure_storage_exists().await?;
+        let other_worker = StorageGrpc::<TestItem>::new(&endpoint)?;
+        let id = String::from("guild:1");
+        assert!(matches!(
+            worker.load(&id).await.unwrap_err().downcast_ref(),
+            Some(StorageError::NotFound { .. })
+        ));
+
+        let (lock, _) = worker.lock(&id, "worker").await?.success()?;
+        assert!(worker.verify_lock(&id, &lock).await?);
+        assert!(matches!(
+            other_worker.lock(&id, "other").await?,
+            LockResult::AlreadyLocked { who, .. } if who == "worker"
+        ));
+        let item = TestItem {
+            name: String::from("remote"),
+        };
+        worker.save(&id, &item, &lock).await?;
+        assert!(other_worker
+            .save(&id, &item, &StorageLock::new("other"))
+            .await
+            .is_err());
+        worker.save_and_unlock(&id, &item, lock).await?;
+
+        assert_eq!(item, other_worker.load(&id).await?);
+        assert_eq!(item, daemon.load(&id).await?);
+        assert_eq!(vec![id.clone()], other_worker.all_ids().await?);
+        let page = other_worker
+            .scan_ids_with_prefix("guild:", None, None)
+            .await?;
+        assert_eq!(vec![id.clone()], page.items);
+
+        let (lock, _) = other_worker.lock(&id, "other").await?.success()?;
+        let forced = worker.force_unlock(&id).await?.expect("was locked");
+        assert_eq!(lock, forced);
+        assert!(!other_worker.verify_lock(&id, &lock).await?);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,102 @@
+use crate::SequentialId;
+use async_trait::async_trait;
+use color_eyre::eyre::Result;
+use tokio::sync::Mutex;
+
+/// A counter persisted in a storage backend, shared by all instances using it.
+///
+/// See e.g. [crate::StorageDisk::id_counter] and [crate::StorageDynamoDb::id_counter].
+#[async_trait]
+pub trait IdCounter: Send + Sync + std::fmt::Debug {
+    /// Atomically reserves `count` consecutive values and returns the first one.
+    /// The first value ever reserved is 1.
+    async fn reserve(&self, count: u64) -> Result<u64>;
+}
+
+/// Hands out [SequentialId]s that are unique across all instances sharing the counter.
+///
+/// Ids are reserved from the counter in batches, so ids are only roughly ordered across instances,
+/// and unused ids of a batch are lost when the allocator is dropped.
+#[derive(Debug)]
+pub struct IdAllocator {
+    counter: Box<dyn IdCounter>,
+    batch_size: u64,
+    /// The next id and the end of the reserved range
+    range: Mutex<(u64, u64)>,
+}
+
+impl IdAllocator {
+    pub fn new(counter: Box<dyn IdCounter>) -> Self {
+        Self {
+            counter,
+            batch_size: 1,
+            range: Mutex::new((0, 0)),
+        }
+    }
+
+    /// How many ids are reserved from the counter at once.
+    /// Larger batches need fewer round trips, but waste more ids on restarts.
+    pub fn set_batch_size(&mut self, batch_size: u64) -> Result<()> {
+        self.batch_size = batch_size.max(1);
+
+        Ok(())
+    }
+
+    pub async fn next(&self) -> Result<SequentialId> {
+        let mut range = self.range.lock().await;
+        if range.0 >= range.1 {
+            let first = self.counter.reserve(self.batch_size).await?;
+            *range = (first, first + self.batch_size);
+        }
+        let id = range.0;
+        range.0 += 1;
+
+        Ok(SequentialId::from(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::IdAllocator;
+    use crate::IdCounter;
+    use async_trait::async_trait;
+    use color_eyre::Result;
+    use std::sync::atomic::AtomicU64;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+
+    #[derive(Debug, Default)]
+    struct MemoryCounter {
+        value: AtomicU64,
+        reserves: AtomicU64,
+    }
+
+    #[async_trait]
+    impl IdCounter for Arc<MemoryCounter> {
+        async fn reserve(&self, count: u64) -> Result<u64> {
+            self.reserves.fetch_add(1, Ordering::Relaxed);
+            Ok(self.value.fetch_add(count, Ordering::Relaxed) + 1)
+        }
+    }
+
+    #[tokio::test]
+    async fn it_allocates_in_batches() -> Result<()> {
+        let counter = Arc::new(MemoryCounter::default());
+        let mut a = IdAllocator::new(Box::new(counter.clone()));
+        a.set_batch_size(10)?;
+        let mut b = IdAllocator::new(Box::new(counter.clone()));
+        b.set_batch_size(10)?;
+
+        let mut ids = Vec::default();
+        for _ in 0..15 {
+            ids.push(a.next().await?.value());
+            ids.push(b.next().await?.value());
+        }
+        ids.sort();
+        ids.dedup();
+        assert_eq!(30, ids.len());
+        assert_eq!(4, counter.reserves.load(Ordering::Relaxed));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+#[cfg(feature = "encryption")]
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+#[cfg(feature = "encryption")]
+use base64::Engine;
+#[cfg(feature = "encryption")]
+use color_eyre::eyre::eyre;
+#[cfg(feature = "encryption")]
+use color_eyre::eyre::Result;
+
+/// Maps ids to the keys items are stored under and back, see [crate::StorageDynamoDb::set_id_codec],
+/// e.g. so sequential player numbers never show up as raw keys.
+///
+/// Applied to every key of items, so loads, saves, locks, and scans all see the original ids.
+pub trait IdCodec: Send + Sync + std::fmt::Debug {
+    /// The key of `id`. Must only depend on `id` and never start with `#`, internal keys do.
+    fn encode(&self, id: &str) -> String;
+    /// The id of `key`, `None` for keys not encoded by this codec, e.g. of items saved before.
+    ///
+    /// Must invert [IdCodec::encode], one-way hashes can't be scanned.
+    fn decode(&self, key: &str) -> Option<String>;
+}
+
+/// Length of the synthetic nonce, the AES-GCM nonce size.
+#[cfg(feature = "encryption")]
+const NONCE_LEN: usize = 12;
+
+/// Encrypts ids deterministically with AES-256-GCM, with the nonce derived from the id via HMAC-SHA256,
+/// and stores them URL safe base64 encoded. Needs the `encryption` feature.
+///
+/// Equal ids always get the same key, which is needed to find items, but also means
+/// equal ids can be recognised. Changing the key makes all existing items unreachable.
+#[cfg(feature = "encryption")]
+pub struct EncryptedIdCodec {
+    key: ring::aead::LessSafeKey,
+    nonce_key: ring::hmac::Key,
+}
+
+#[cfg(feature = "encryption")]
+impl std::fmt::Debug for EncryptedIdCodec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EncryptedIdCodec").finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "encryption")]
+impl EncryptedIdCodec {
+    /// Derives separate keys for the encryption and the nonces from `key`.
+    pub fn new(key: [u8; 32]) -> Result<Self> {
+        let master = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, &key);
+        let derive = |label: &[u8]| ring::hmac::sign(&master, label);
+        let aead_key = derive(b"oml-storage id codec key");
+        let key = ring::aead::UnboundKey::new(&ring::aead::AES_256_GCM, aead_key.as_ref())
+            .map_err(|_| eyre!("Invalid id codec key"))?;
+        let nonce_key = derive(b"oml-storage id codec nonce");
+
+        Ok(Self {
+            key: ring::aead::LessSafeKey::new(key),
+            nonce_key: ring::hmac::Key::new(ring::hmac::HMAC_SHA256, nonce_key.as_ref()),
+        })
+    }
+
+    fn nonce(&self, id: &[u8]) -> [u8; NONCE_LEN] {
+        let tag = ring::hmac::sign(&self.nonce_key, id);
+        let mut nonce = [0; NONCE_LEN];
+        nonce.copy_from_slice(&tag.as_ref()[..NONCE_LEN]);
+        nonce
+    }
+}
+
+#[cfg(feature = "encryption")]
+impl IdCodec for EncryptedIdCodec {
+    fn encode(&self, id: &str) -> String {
+        let nonce = self.nonce(id.as_bytes());
+        let mut data = id.as_bytes().to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                ring::aead::Nonce::assume_unique_for_key(nonce),
+                ring::aead::Aad::empty(),
+                &mut data,
+            )
+            .expect("ids fit into AES-GCM");
+        let mut key = nonce.to_vec();
+        key.extend_from_slice(&data);
+        BASE64.encode(key)
+    }
+
+    fn decode(&self, key: &str) -> Option<String> {
+        let data = BASE64.decode(key).ok()?;
+        if data.len() < NONCE_LEN {
+            return None;
+        }
+        let (nonce, data) = data.split_at(NONCE_LEN);
+        let nonce: [u8; NONCE_LEN] = nonce.try_into().ok()?;
+        let mut data = data.to_vec();
+        let id = self
+            .key
+            .open_in_place(
+                ring::aead::Nonce::assume_unique_for_key(nonce),
+                ring::aead::Aad::empty(),
+                &mut data,
+            )
+            .ok()?;
+        // only the key encode returns, so every id has exactly one key
+        if self.nonce(id) != nonce {
+            return None;
+        }
+        String::from_utf8(id.to_vec()).ok()
+    }
+}
+
+#[cfg(all(test, feature = "encryption"))]
+mod tests {
+    use crate::EncryptedIdCodec;
+    use crate::IdCodec;
+    use color_eyre::Result;
+
+    #[test]
+    fn it_encrypts_ids() -> Result<()> {
+        let codec = EncryptedIdCodec::new([7; 32])?;
+        let key = codec.encode("1234");
+        assert_eq!(key, codec.encode("1234"));
+        assert_ne!(key, codec.encode("1235"));
+        assert!(!key.contains("1234"));
+        assert!(!key.starts_with('#'));
+        assert_eq!(Some(String::from("1234")), codec.decode(&key));
+
+        assert_eq!(None, codec.decode("1234"));
+        assert_eq!(None, EncryptedIdCodec::new([8; 32])?.decode(&key));
+
+        Ok(())
+    }
+}
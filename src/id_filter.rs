@@ -0,0 +1,64 @@
+use chrono::DateTime;
+use chrono::Utc;
+
+/// Selects items by their id or by when they were last saved, e.g. for [crate::Storage::wipe_matching].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdFilter {
+    /// Ids starting with the prefix, e.g. all items of one tenant
+    Prefix(String),
+    /// Ids from `start`, inclusive, to `end`, exclusive, compared as strings. `None` is unbounded.
+    Range {
+        start: Option<String>,
+        end: Option<String>,
+    },
+    /// Items last saved before the given time, items without a known save time never match
+    ModifiedBefore(DateTime<Utc>),
+}
+
+impl IdFilter {
+    /// `modified` is only used for [IdFilter::ModifiedBefore].
+    pub fn matches(&self, id: &str, modified: Option<DateTime<Utc>>) -> bool {
+        match self {
+            IdFilter::Prefix(prefix) => id.starts_with(prefix.as_str()),
+            IdFilter::Range { start, end } => {
+                start.as_deref().is_none_or(|start| id >= start)
+                    && end.as_deref().is_none_or(|end| id < end)
+            }
+            IdFilter::ModifiedBefore(before) => modified.is_some_and(|m| m < *before),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::IdFilter;
+    use chrono::Duration;
+    use chrono::Utc;
+
+    #[test]
+    fn it_matches() {
+        let prefix = IdFilter::Prefix(String::from("tenant-a/"));
+        assert!(prefix.matches("tenant-a/1", None));
+        assert!(!prefix.matches("tenant-b/1", None));
+
+        let range = IdFilter::Range {
+            start: Some(String::from("b")),
+            end: Some(String::from("d")),
+        };
+        assert!(!range.matches("a", None));
+        assert!(range.matches("b", None));
+        assert!(range.matches("c9", None));
+        assert!(!range.matches("d", None));
+        let open = IdFilter::Range {
+            start: None,
+            end: Some(String::from("b")),
+        };
+        assert!(open.matches("a", None));
+
+        let now = Utc::now();
+        let modified_before = IdFilter::ModifiedBefore(now);
+        assert!(modified_before.matches("a", Some(now - Duration::seconds(1))));
+        assert!(!modified_before.matches("a", Some(now)));
+        assert!(!modified_before.matches("a", None));
+    }
+}
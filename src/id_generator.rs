@@ -0,0 +1,168 @@
+use crate::RandomId;
+use crate::RandomIdAlphabet;
+use crate::StorageId;
+use crate::UrlSafeAlphabet;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+/// The source of new ids for [crate::Storage::create].
+///
+/// Backends use [DefaultIdGenerator] unless told otherwise, e.g. via [crate::StorageMemory::set_id_generator].
+pub trait IdGenerator<ID>: Send + Sync + std::fmt::Debug {
+    /// `previous` is the id of the last try if it collided, see [StorageId::generate_new].
+    fn generate(&self, previous: Option<&ID>) -> ID;
+}
+
+/// [StorageId::generate_new]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DefaultIdGenerator;
+
+impl<ID: StorageId> IdGenerator<ID> for DefaultIdGenerator {
+    fn generate(&self, previous: Option<&ID>) -> ID {
+        ID::generate_new(previous)
+    }
+}
+
+/// Random looking ids, that are the same for the same seed, e.g. for reproducible tests.
+/// Generates [RandomId]s and nanoid like [String]s.
+///
+/// Clones share the sequence.
+#[derive(Debug, Clone)]
+pub struct SeededIdGenerator {
+    state: Arc<Mutex<u64>>,
+}
+
+impl SeededIdGenerator {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(seed)),
+        }
+    }
+
+    /// SplitMix64
+    fn next_u64(&self) -> u64 {
+        let mut state = self.state.lock().expect("can lock");
+        *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = *state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn chars(&self, alphabet: &[char], len: usize) -> String {
+        (0..len)
+            .map(|_| alphabet[(self.next_u64() % alphabet.len() as u64) as usize])
+            .collect()
+    }
+}
+
+impl IdGenerator<String> for SeededIdGenerator {
+    fn generate(&self, _previous: Option<&String>) -> String {
+        self.chars(UrlSafeAlphabet::CHARS, 21)
+    }
+}
+
+impl<A: RandomIdAlphabet, const LEN: usize> IdGenerator<RandomId<A, LEN>> for SeededIdGenerator {
+    fn generate(&self, _previous: Option<&RandomId<A, LEN>>) -> RandomId<A, LEN> {
+        RandomId::from_string(&self.chars(A::CHARS, LEN)).expect("only uses the alphabet")
+    }
+}
+
+/// Hands out the given ids in order, e.g. to test collisions.
+///
+/// Clones share the ids, keep one to add more to the generator given to a backend.
+///
+/// Note: Panics when it runs out of ids.
+#[derive(Debug, Clone)]
+pub struct MockIdGenerator<ID> {
+    ids: Arc<Mutex<VecDeque<ID>>>,
+}
+
+impl<ID> MockIdGenerator<ID> {
+    pub fn new(ids: impl IntoIterator<Item = ID>) -> Self {
+        Self {
+            ids: Arc::new(Mutex::new(ids.into_iter().collect())),
+        }
+    }
+
+    pub fn push(&self, id: ID) {
+        self.ids.lock().expect("can lock").push_back(id);
+    }
+}
+
+impl<ID: Send + std::fmt::Debug> IdGenerator<ID> for MockIdGenerator<ID> {
+    fn generate(&self, _previous: Option<&ID>) -> ID {
+        self.ids
+            .lock()
+            .expect("can lock")
+            .pop_front()
+            .expect("MockIdGenerator has ids left")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::IdGenerator;
+    use crate::LowercaseAlphabet;
+    use crate::MockIdGenerator;
+    use crate::RandomId;
+    use crate::SeededIdGenerator;
+    use crate::Storage;
+    use crate::StorageError;
+    use crate::StorageItem;
+    use crate::StorageMemory;
+    use color_eyre::Result;
+
+    #[derive(Default, Debug)]
+    struct TestItem;
+
+    impl StorageItem for TestItem {
+        type ID = String;
+
+        fn serialize(&self) -> Result<Vec<u8>> {
+            Ok(Vec::new())
+        }
+        fn deserialize(_data: &[u8]) -> Result<Self> {
+            Ok(Self)
+        }
+    }
+
+    #[test]
+    fn it_generates_seeded_ids() {
+        let generate = |seed| {
+            let generator = SeededIdGenerator::new(seed);
+            let ids: Vec<String> = (0..3).map(|_| generator.generate(None)).collect();
+            ids
+        };
+        assert_eq!(generate(42), generate(42));
+        assert_ne!(generate(42), generate(43));
+        assert_eq!(21, generate(42)[0].len());
+
+        let id: RandomId<LowercaseAlphabet, 16> = SeededIdGenerator::new(1).generate(None);
+        assert_eq!(16, id.as_str().len());
+    }
+
+    #[tokio::test]
+    async fn it_creates_injected_ids() -> Result<()> {
+        let ids = MockIdGenerator::new(["a", "a", "a"].map(String::from));
+        let mut storage = StorageMemory::<TestItem>::default();
+        storage.set_id_generator(ids.clone())?;
+        storage.set_create_tries(2)?;
+
+        let a = storage.create().await?;
+        assert_eq!("a", a);
+        let (lock, item) = storage.lock(&a, "test").await?.success()?;
+        storage.save_and_unlock(&a, &item, lock).await?;
+
+        let e = storage.create().await.expect_err("both tries collide");
+        assert_eq!(
+            Some(&StorageError::IdExhausted { tries: 2 }),
+            e.downcast_ref::<StorageError>()
+        );
+        ids.push(String::from("b"));
+        assert_eq!("b", storage.create().await?);
+
+        Ok(())
+    }
+}
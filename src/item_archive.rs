@@ -0,0 +1,267 @@
+use crate::dynamodb_key_hasher::fnv1a;
+use crate::LockResult;
+use crate::Storage;
+use crate::StorageId;
+use crate::StorageItem;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::DateTime;
+use chrono::Utc;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serialize;
+use serde::Serializer;
+use std::collections::BTreeMap;
+
+/// The format of [ItemArchive], bumped on incompatible changes.
+pub const ITEM_ARCHIVE_FORMAT: u32 = 1;
+
+/// Who [Storage::export_item] and [Storage::import_item] lock items as.
+const ARCHIVE_LOCK_HOLDER: &str = "oml-storage item archive";
+
+/// Stored bytes, base64 encoded, with their checksum.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchivedData {
+    #[serde(serialize_with = "to_base64", deserialize_with = "from_base64")]
+    pub data: Vec<u8>,
+    /// `fnv1a64:<hex>`, a stable 64 bit FNV-1a of `data`, catches damage, not tampering.
+    pub checksum: String,
+}
+
+fn to_base64<S: Serializer>(data: &[u8], serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(&BASE64.encode(data))
+}
+
+fn from_base64<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Vec<u8>, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    BASE64.decode(s).map_err(serde::de::Error::custom)
+}
+
+fn checksum(data: &[u8]) -> String {
+    format!("fnv1a64:{:016x}", fnv1a(data))
+}
+
+impl ArchivedData {
+    pub fn new(data: Vec<u8>) -> Self {
+        let checksum = checksum(&data);
+        Self { data, checksum }
+    }
+
+    pub fn verify(&self) -> Result<()> {
+        let actual = checksum(&self.data);
+        if actual != self.checksum {
+            return Err(eyre!(
+                "Checksum mismatch, expected {}, got {actual}",
+                self.checksum
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// A complete, portable copy of one item, e.g. to move it between environments for a support ticket,
+/// see [Storage::export_item] and [Storage::import_item].
+///
+/// Serializes to JSON or any other serde format, with all bytes base64 encoded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItemArchive {
+    /// [ITEM_ARCHIVE_FORMAT] at the time of the export.
+    pub format: u32,
+    pub id: String,
+    pub exported_at: DateTime<Utc>,
+    /// The item as returned by [Storage::load_raw].
+    pub payload: ArchivedData,
+    /// The last save, if the backend knows it.
+    #[serde(default)]
+    pub modified: Option<DateTime<Utc>>,
+    /// The holder of the lock at the time of the export, `None` if the item wasn't locked.
+    #[serde(default)]
+    pub locked_by: Option<String>,
+    #[serde(default)]
+    pub locked_at: Option<DateTime<Utc>>,
+    /// The attachments, by name, empty if the backend doesn't support blobs.
+    #[serde(default)]
+    pub blobs: BTreeMap<String, ArchivedData>,
+    /// Previous versions, newest first, only for backends keeping them, e.g. [crate::StorageDisk::set_versions].
+    #[serde(default)]
+    pub versions: Vec<ArchivedData>,
+}
+
+impl ItemArchive {
+    /// Checks the format and all checksums.
+    pub fn verify(&self) -> Result<()> {
+        if self.format > ITEM_ARCHIVE_FORMAT {
+            return Err(eyre!(
+                "Archive of {} has format {}, only up to {ITEM_ARCHIVE_FORMAT} is supported",
+                self.id,
+                self.format
+            ));
+        }
+        let parts = std::iter::once(("payload".to_string(), &self.payload))
+            .chain(self.blobs.iter().map(|(n, b)| (format!("blob {n}"), b)))
+            .chain(
+                self.versions
+                    .iter()
+                    .enumerate()
+                    .map(|(n, v)| (format!("version {}", n + 1), v)),
+            );
+        for (part, data) in parts {
+            data.verify()
+                .map_err(|e| eyre!("Archive of {} has a damaged {part} -> {e}", self.id))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+}
+
+/// See [Storage::export_item].
+///
+/// The item is locked while reading, so payload and blobs match. If someone holds the lock,
+/// it is read anyway and the holder recorded.
+pub(crate) async fn export_item<ITEM, S>(storage: &S, id: &ITEM::ID) -> Result<ItemArchive>
+where
+    ITEM: StorageItem + Send,
+    S: Storage<ITEM> + ?Sized,
+{
+    // locking would create the item
+    if !storage.exists(id).await? {
+        return Err(crate::StorageError::NotFound { id: id.to_string() }.into());
+    }
+    let (lock, locked_by, locked_at) = match storage.lock(id, ARCHIVE_LOCK_HOLDER).await? {
+        LockResult::Success { lock, .. } => (Some(lock), None, None),
+        LockResult::AlreadyLocked { who, when } => (None, Some(who), Some(when)),
+    };
+    let archive = read_archive(storage, id, locked_by, locked_at).await;
+    if let Some(lock) = lock {
+        storage.unlock(id, lock).await?;
+    }
+
+    archive
+}
+
+async fn read_archive<ITEM, S>(
+    storage: &S,
+    id: &ITEM::ID,
+    locked_by: Option<String>,
+    locked_at: Option<DateTime<Utc>>,
+) -> Result<ItemArchive>
+where
+    ITEM: StorageItem + Send,
+    S: Storage<ITEM> + ?Sized,
+{
+    let payload = ArchivedData::new(storage.load_raw(id).await?);
+    let mut blobs = BTreeMap::new();
+    // backends without blobs fail here
+    if let Ok(names) = storage.list_blobs(id).await {
+        for name in names {
+            if let Some(data) = storage.get_blob(id, &name).await? {
+                blobs.insert(name, ArchivedData::new(data));
+            }
+        }
+    }
+
+    Ok(ItemArchive {
+        format: ITEM_ARCHIVE_FORMAT,
+        id: id.to_string(),
+        exported_at: Utc::now(),
+        payload,
+        modified: None,
+        locked_by,
+        locked_at,
+        blobs,
+        versions: Vec::new(),
+    })
+}
+
+/// See [Storage::import_item].
+pub(crate) async fn import_item<ITEM, S>(storage: &S, archive: &ItemArchive) -> Result<ITEM::ID>
+where
+    ITEM: StorageItem + Send,
+    S: Storage<ITEM> + ?Sized,
+{
+    archive.verify()?;
+    let id = ITEM::ID::from_string(&archive.id)?;
+    let (lock, _) = storage.lock(&id, ARCHIVE_LOCK_HOLDER).await?.success()?;
+    let result = async {
+        storage.save_raw(&id, &archive.payload.data, &lock).await?;
+        for (name, blob) in &archive.blobs {
+            storage.put_blob(&id, name, &blob.data, &lock).await?;
+        }
+        Result::<()>::Ok(())
+    }
+    .await;
+    storage.unlock(&id, lock).await?;
+    result?;
+
+    Ok(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ItemArchive;
+    use crate::Storage;
+    use crate::StorageItem;
+    use crate::StorageMemory;
+    use color_eyre::Result;
+    use serde::Deserialize;
+    use serde::Serialize;
+
+    #[derive(Default, Debug, PartialEq, Serialize, Deserialize)]
+    struct TestItem {
+        name: String,
+    }
+
+    impl StorageItem for TestItem {
+        type ID = String;
+
+        fn serialize(&self) -> Result<Vec<u8>> {
+            Ok(serde_json::to_vec(&self)?)
+        }
+        fn deserialize(data: &[u8]) -> Result<Self> {
+            Ok(serde_json::from_slice(data)?)
+        }
+    }
+
+    #[tokio::test]
+    async fn it_round_trips_item_archives() -> Result<()> {
+        let source = StorageMemory::<TestItem>::default();
+        let id = String::from("ticket-1234");
+        let (lock, _) = source.lock(&id, "support").await?.success()?;
+        let item = TestItem {
+            name: String::from("broken"),
+        };
+        source.save(&id, &item, &lock).await?;
+
+        let archive = source.export_item(&id).await?;
+        assert_eq!(Some("support"), archive.locked_by.as_deref());
+        assert!(source.export_item(&String::from("missing")).await.is_err());
+
+        let json = archive.to_json()?;
+        let archive = ItemArchive::from_json(&json)?;
+        archive.verify()?;
+        let target = StorageMemory::<TestItem>::default();
+        assert_eq!(id, target.import_item(&archive).await?);
+        assert_eq!(item, target.load(&id).await?);
+        assert!(target.lock(&id, "other").await?.success().is_ok());
+
+        let mut damaged = archive.clone();
+        damaged.payload.data.push(b' ');
+        assert!(damaged.verify().is_err());
+        assert!(target.import_item(&damaged).await.is_err());
+
+        Ok(())
+    }
+}
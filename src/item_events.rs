@@ -0,0 +1,741 @@
+use crate::LockResult;
+use crate::OpOptions;
+use crate::Page;
+use crate::Storage;
+use crate::StorageItem;
+use crate::StorageLock;
+use async_trait::async_trait;
+use chrono::DateTime;
+use chrono::Utc;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use core::marker::PhantomData;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::task::AbortHandle;
+
+/// A change in the life of an item, see [StorageWithEvents].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ItemEvent<ID> {
+    /// The first save of an item that didn't exist when it was locked
+    Created {
+        id: ID,
+        who: String,
+    },
+    Saved {
+        id: ID,
+        who: String,
+    },
+    /// The lock `who` holds will be considered stale soon, see [StorageWithEvents::set_lock_expiry_notice].
+    /// Renew it, e.g. via [Storage::handoff_lock], or finish up.
+    LockExpiring {
+        id: ID,
+        who: String,
+    },
+    /// `who` held the removed lock
+    ForceUnlocked {
+        id: ID,
+        who: String,
+    },
+    /// Items were removed by a wipe, `filter` is `None` for [Storage::wipe].
+    /// Wipes don't tell which ids they removed.
+    #[cfg(feature = "wipe")]
+    Deleted {
+        filter: Option<crate::IdFilter>,
+        count: usize,
+    },
+    /// An item removed by [Storage::delete_many]
+    #[cfg(feature = "wipe")]
+    Removed {
+        id: ID,
+    },
+}
+
+/// Receives the [ItemEvent]s of a [StorageWithEvents].
+///
+/// Implemented for [broadcast::Sender], to fan events out to many receivers.
+#[async_trait]
+pub trait EventSink<ID>: Send + Sync + std::fmt::Debug {
+    async fn emit(&self, event: &ItemEvent<ID>) -> Result<()>;
+}
+
+#[async_trait]
+impl<ID: Clone + Send + Sync + std::fmt::Debug> EventSink<ID> for broadcast::Sender<ItemEvent<ID>> {
+    async fn emit(&self, event: &ItemEvent<ID>) -> Result<()> {
+        // nobody listening is fine
+        let _ = self.send(event.clone());
+
+        Ok(())
+    }
+}
+
+/// Wraps any [Storage] and emits an [ItemEvent] to every [EventSink] after each successful change.
+///
+/// Sinks run in the order they were added, before the operation returns.
+/// Failing sinks are only logged, the change already happened.
+///
+/// With sinks, locking checks if the item exists first, to tell creations from saves.
+#[derive(Debug)]
+pub struct StorageWithEvents<ITEM: StorageItem, S: Storage<ITEM>>
+where
+    ITEM: Send,
+{
+    storage: S,
+    sinks: Vec<Arc<dyn EventSink<ITEM::ID>>>,
+    /// Locked items that didn't exist before, until their first save
+    new_ids: Mutex<HashSet<String>>,
+    expiry_notice: Option<ExpiryNotice<ITEM::ID>>,
+    /// Pending [ItemEvent::LockExpiring] of the locks taken via this wrapper
+    expiry_timers: ExpiryTimers,
+    item_type: PhantomData<ITEM>,
+}
+
+type Sinks<ID> = Vec<Arc<dyn EventSink<ID>>>;
+
+/// Spawns the timer emitting [ItemEvent::LockExpiring],
+/// boxed to keep the `'static` bound, spawning needs, off the wrapper.
+struct ExpiryNotice<ID> {
+    schedule: Box<dyn Fn(ID, String, Sinks<ID>) -> AbortHandle + Send + Sync>,
+}
+
+impl<ID> std::fmt::Debug for ExpiryNotice<ID> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ExpiryNotice").finish_non_exhaustive()
+    }
+}
+
+/// Aborts the pending notices when dropped
+#[derive(Debug, Default)]
+struct ExpiryTimers(Mutex<HashMap<String, AbortHandle>>);
+
+impl Drop for ExpiryTimers {
+    fn drop(&mut self) {
+        if let Ok(timers) = self.0.get_mut() {
+            for (_, timer) in timers.drain() {
+                timer.abort();
+            }
+        }
+    }
+}
+
+impl<ITEM: StorageItem + Send, S: Storage<ITEM>> StorageWithEvents<ITEM, S> {
+    pub fn new(storage: S) -> Self {
+        Self {
+            storage,
+            sinks: Vec::new(),
+            new_ids: Mutex::new(HashSet::new()),
+            expiry_notice: None,
+            expiry_timers: ExpiryTimers::default(),
+            item_type: PhantomData,
+        }
+    }
+
+    pub fn add_event_sink(&mut self, sink: impl EventSink<ITEM::ID> + 'static) -> Result<()> {
+        self.sinks.push(Arc::new(sink));
+
+        Ok(())
+    }
+
+    /// Emits [ItemEvent::LockExpiring] `lead` before a lock, taken or handed off via this wrapper,
+    /// is `lock_ttl` old, unless it was released before.
+    ///
+    /// `lock_ttl` is the age after which locks are considered stale,
+    /// e.g. the `max_age` given to [Storage::remove_orphaned_locks] or [crate::DynamoDbTtl::unsaved_lock_ttl].
+    ///
+    /// Note: Needs a running tokio runtime when locking.
+    pub fn set_lock_expiry_notice(&mut self, lock_ttl: Duration, lead: Duration) -> Result<()>
+    where
+        ITEM: 'static,
+    {
+        if lead >= lock_ttl {
+            return Err(eyre!(
+                "Lock expiry lead {lead:?} must be shorter than the lock ttl {lock_ttl:?}"
+            ));
+        }
+        let delay = lock_ttl - lead;
+        let schedule = move |id: ITEM::ID, who: String, sinks: Sinks<ITEM::ID>| {
+            tokio::spawn(async move {
+                tokio::time::sleep(delay).await;
+                let event = ItemEvent::LockExpiring { id, who };
+                for sink in sinks.iter() {
+                    if let Err(e) = sink.emit(&event).await {
+                        tracing::error!("Can't emit {event:?} to {sink:?} -> {e:?}");
+                    }
+                }
+            })
+            .abort_handle()
+        };
+        self.expiry_notice = Some(ExpiryNotice {
+            schedule: Box::new(schedule),
+        });
+
+        Ok(())
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    pub fn into_inner(self) -> S {
+        self.storage
+    }
+
+    async fn emit(&self, event: ItemEvent<ITEM::ID>) {
+        for sink in self.sinks.iter() {
+            if let Err(e) = sink.emit(&event).await {
+                tracing::error!("Can't emit {event:?} to {sink:?} -> {e:?}");
+            }
+        }
+    }
+
+    async fn emit_saved(&self, id: &ITEM::ID, who: &str) {
+        if self.sinks.is_empty() {
+            return;
+        }
+        let created = self
+            .new_ids
+            .lock()
+            .expect("can lock")
+            .remove(&id.to_string());
+        let id = id.clone();
+        let who = who.to_string();
+        let event = if created {
+            ItemEvent::Created { id, who }
+        } else {
+            ItemEvent::Saved { id, who }
+        };
+        self.emit(event).await;
+    }
+
+    fn schedule_expiry(&self, id: &ITEM::ID, lock: &StorageLock) {
+        let Some(notice) = &self.expiry_notice else {
+            return;
+        };
+        if self.sinks.is_empty() {
+            return;
+        }
+        let timer = (notice.schedule)(id.clone(), lock.who().to_string(), self.sinks.clone());
+        let previous = self
+            .expiry_timers
+            .0
+            .lock()
+            .expect("can lock")
+            .insert(id.to_string(), timer);
+        if let Some(previous) = previous {
+            previous.abort();
+        }
+    }
+
+    fn cancel_expiry(&self, id: &ITEM::ID) {
+        let timer = self
+            .expiry_timers
+            .0
+            .lock()
+            .expect("can lock")
+            .remove(&id.to_string());
+        if let Some(timer) = timer {
+            timer.abort();
+        }
+    }
+
+    /// Wiping doesn't tell which ids it removed, `wiped` tells which ones it could have.
+    #[cfg(feature = "wipe")]
+    fn cancel_wiped_expiries(&self, wiped: impl Fn(&str) -> bool) {
+        self.expiry_timers
+            .0
+            .lock()
+            .expect("can lock")
+            .retain(|id, timer| {
+                if wiped(id) {
+                    timer.abort();
+                    false
+                } else {
+                    true
+                }
+            });
+    }
+}
+
+#[async_trait]
+impl<ITEM: StorageItem + Send, S: Storage<ITEM>> Storage<ITEM> for StorageWithEvents<ITEM, S> {
+    async fn ensure_storage_exists(&mut self) -> Result<()> {
+        self.storage.ensure_storage_exists().await
+    }
+
+    fn capabilities(&self) -> crate::Capabilities {
+        self.storage.capabilities()
+    }
+
+    async fn create(&self) -> Result<ITEM::ID> {
+        self.storage.create().await
+    }
+
+    async fn exists(&self, id: &ITEM::ID) -> Result<bool> {
+        self.storage.exists(id).await
+    }
+
+    async fn exists_with(&self, id: &ITEM::ID, options: &OpOptions) -> Result<bool> {
+        self.storage.exists_with(id, options).await
+    }
+
+    async fn load(&self, id: &ITEM::ID) -> Result<ITEM> {
+        self.storage.load(id).await
+    }
+
+    async fn load_with(&self, id: &ITEM::ID, options: &OpOptions) -> Result<ITEM> {
+        self.storage.load_with(id, options).await
+    }
+
+    async fn load_many(&self, ids: &[ITEM::ID]) -> Result<Vec<Option<ITEM>>> {
+        self.storage.load_many(ids).await
+    }
+
+    async fn prefetch(&self, ids: &[ITEM::ID]) -> Result<()> {
+        self.storage.prefetch(ids).await
+    }
+
+    async fn save(&self, id: &ITEM::ID, item: &ITEM, lock: &StorageLock) -> Result<()> {
+        self.storage.save(id, item, lock).await?;
+        self.emit_saved(id, lock.who()).await;
+
+        Ok(())
+    }
+
+    async fn lock(&self, id: &ITEM::ID, who: &str) -> Result<LockResult<ITEM>> {
+        if self.sinks.is_empty() {
+            return self.storage.lock(id, who).await;
+        }
+        // locked, but unsaved items exist, too
+        let existed = self.storage.exists(id).await?;
+        let result = self.storage.lock(id, who).await?;
+        if let LockResult::Success { lock, .. } = &result {
+            if !existed {
+                self.new_ids
+                    .lock()
+                    .expect("can lock")
+                    .insert(id.to_string());
+            }
+            self.schedule_expiry(id, lock);
+        }
+
+        Ok(result)
+    }
+
+    async fn unlock(&self, id: &ITEM::ID, lock: StorageLock) -> Result<()> {
+        self.storage.unlock(id, lock).await?;
+        self.cancel_expiry(id);
+        self.new_ids
+            .lock()
+            .expect("can lock")
+            .remove(&id.to_string());
+
+        Ok(())
+    }
+
+    async fn handoff_lock(
+        &self,
+        id: &ITEM::ID,
+        lock: StorageLock,
+        new_who: &str,
+    ) -> Result<StorageLock> {
+        let lock = self.storage.handoff_lock(id, lock, new_who).await?;
+        self.schedule_expiry(id, &lock);
+
+        Ok(lock)
+    }
+
+    async fn save_and_unlock(&self, id: &ITEM::ID, item: &ITEM, lock: StorageLock) -> Result<()> {
+        let who = lock.who().to_string();
+        self.storage.save_and_unlock(id, item, lock).await?;
+        self.cancel_expiry(id);
+        self.emit_saved(id, &who).await;
+
+        Ok(())
+    }
+
+    async fn load_raw(&self, id: &ITEM::ID) -> Result<Vec<u8>> {
+        self.storage.load_raw(id).await
+    }
+
+    async fn save_raw(&self, id: &ITEM::ID, data: &[u8], lock: &StorageLock) -> Result<()> {
+        self.storage.save_raw(id, data, lock).await?;
+        self.emit_saved(id, lock.who()).await;
+
+        Ok(())
+    }
+
+    async fn put_blob(
+        &self,
+        id: &ITEM::ID,
+        name: &str,
+        data: &[u8],
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.storage.put_blob(id, name, data, lock).await
+    }
+
+    async fn get_blob(&self, id: &ITEM::ID, name: &str) -> Result<Option<Vec<u8>>> {
+        self.storage.get_blob(id, name).await
+    }
+
+    async fn list_blobs(&self, id: &ITEM::ID) -> Result<Vec<String>> {
+        self.storage.list_blobs(id).await
+    }
+
+    async fn put_meta(&self, key: &str, value: &[u8]) -> Result<()> {
+        self.storage.put_meta(key, value).await
+    }
+
+    async fn get_meta(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.storage.get_meta(key).await
+    }
+
+    async fn add_alias(&self, alias: &ITEM::ID, canonical: &ITEM::ID) -> Result<()> {
+        self.storage.add_alias(alias, canonical).await
+    }
+
+    async fn remove_alias(&self, alias: &ITEM::ID) -> Result<()> {
+        self.storage.remove_alias(alias).await
+    }
+
+    async fn resolve_alias(&self, id: &ITEM::ID) -> Result<ITEM::ID> {
+        self.storage.resolve_alias(id).await
+    }
+
+    async fn add_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.storage.add_link(from, relation, to, lock).await
+    }
+
+    async fn remove_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.storage.remove_link(from, relation, to, lock).await
+    }
+
+    async fn links_of(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        self.storage.links_of(id, relation).await
+    }
+
+    async fn linked_from(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        self.storage.linked_from(id, relation).await
+    }
+
+    async fn force_unlock(&self, id: &ITEM::ID) -> Result<Option<StorageLock>> {
+        let lock = self.storage.force_unlock(id).await?;
+        self.cancel_expiry(id);
+        if let Some(lock) = &lock {
+            self.new_ids
+                .lock()
+                .expect("can lock")
+                .remove(&id.to_string());
+            self.emit(ItemEvent::ForceUnlocked {
+                id: id.clone(),
+                who: lock.who().to_string(),
+            })
+            .await;
+        }
+
+        Ok(lock)
+    }
+
+    async fn force_unlock_if_held(
+        &self,
+        id: &ITEM::ID,
+        who: &str,
+        when: DateTime<Utc>,
+    ) -> Result<bool> {
+        let unlocked = self.storage.force_unlock_if_held(id, who, when).await?;
+        if unlocked {
+            self.cancel_expiry(id);
+            self.new_ids
+                .lock()
+                .expect("can lock")
+                .remove(&id.to_string());
+            self.emit(ItemEvent::ForceUnlocked {
+                id: id.clone(),
+                who: who.to_string(),
+            })
+            .await;
+        }
+
+        Ok(unlocked)
+    }
+
+    async fn remove_orphaned_locks(&self, max_age: Duration) -> Result<usize> {
+        self.storage.remove_orphaned_locks(max_age).await
+    }
+
+    async fn verify_lock(&self, id: &ITEM::ID, lock: &StorageLock) -> Result<bool> {
+        self.storage.verify_lock(id, lock).await
+    }
+
+    async fn verify_lock_with(
+        &self,
+        id: &ITEM::ID,
+        lock: &StorageLock,
+        options: &OpOptions,
+    ) -> Result<bool> {
+        self.storage.verify_lock_with(id, lock, options).await
+    }
+
+    async fn all_ids(&self) -> Result<Vec<ITEM::ID>> {
+        self.storage.all_ids().await
+    }
+
+    async fn scan_ids(&self, start: Option<&str>, limit: Option<usize>) -> Result<Page<ITEM::ID>> {
+        self.storage.scan_ids(start, limit).await
+    }
+
+    async fn scan_ids_with_prefix(
+        &self,
+        prefix: &str,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.storage
+            .scan_ids_with_prefix(prefix, start, limit)
+            .await
+    }
+
+    async fn scan_ids_filtered(
+        &self,
+        filter: &crate::IdFilter,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.storage.scan_ids_filtered(filter, start, limit).await
+    }
+
+    async fn scan_ids_between(
+        &self,
+        from: &ITEM::ID,
+        to: &ITEM::ID,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.storage.scan_ids_between(from, to, start, limit).await
+    }
+
+    async fn list(
+        &self,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<crate::ItemSummary<ITEM::ID>>> {
+        self.storage.list(start, limit).await
+    }
+
+    async fn item_size(&self, id: &ITEM::ID) -> Result<u64> {
+        self.storage.item_size(id).await
+    }
+
+    async fn estimate_cost(&self) -> Result<crate::StorageCost> {
+        self.storage.estimate_cost().await
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        self.storage.shutdown().await
+    }
+
+    async fn fsck(&self, options: &crate::FsckOptions) -> Result<crate::FsckReport> {
+        self.storage.fsck(options).await
+    }
+
+    async fn display_lock(&self, id: &ITEM::ID) -> Result<String> {
+        self.storage.display_lock(id).await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_highest_seen_id(&self) -> Option<ITEM::ID> {
+        self.storage.metadata_highest_seen_id().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_item_count(&self) -> Option<u64> {
+        self.storage.metadata_item_count().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_total_bytes(&self) -> Option<u64> {
+        self.storage.metadata_total_bytes().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_last_write(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.storage.metadata_last_write().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_lock_stats(&self) -> crate::LockStats {
+        self.storage.metadata_lock_stats().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_snapshot(&self) -> crate::MetadataSnapshot {
+        self.storage.metadata_snapshot().await
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn wipe(
+        &self,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        let report = self.storage.wipe(options, progress, cancel).await?;
+        if !options.is_dry_run() {
+            self.cancel_wiped_expiries(|_| true);
+            self.emit(ItemEvent::Deleted {
+                filter: None,
+                count: report.count,
+            })
+            .await;
+        }
+
+        Ok(report)
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn wipe_matching(
+        &self,
+        filter: &crate::IdFilter,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        let report = self
+            .storage
+            .wipe_matching(filter, options, progress, cancel)
+            .await?;
+        if !options.is_dry_run() {
+            // the modification time of locked items isn't known here, they keep their notice
+            self.cancel_wiped_expiries(|id| filter.matches(id, None));
+            self.emit(ItemEvent::Deleted {
+                filter: Some(filter.clone()),
+                count: report.count,
+            })
+            .await;
+        }
+
+        Ok(report)
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn delete_many(
+        &self,
+        ids: &[ITEM::ID],
+        options: &crate::DeleteOptions,
+    ) -> Result<Vec<crate::DeleteOutcome>> {
+        let outcomes = self.storage.delete_many(ids, options).await?;
+        for (id, outcome) in ids.iter().zip(&outcomes) {
+            if *outcome == crate::DeleteOutcome::Deleted {
+                self.cancel_expiry(id);
+                self.new_ids
+                    .lock()
+                    .expect("can lock")
+                    .remove(&id.to_string());
+                self.emit(ItemEvent::Removed { id: id.clone() }).await;
+            }
+        }
+
+        Ok(outcomes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_item::TestItem;
+    use crate::ItemEvent;
+    use crate::Storage;
+    use crate::StorageMemory;
+    use crate::StorageWithEvents;
+    use color_eyre::Result;
+    use std::time::Duration;
+    use tokio::sync::broadcast;
+
+    #[tokio::test]
+    async fn it_emits_item_events() -> Result<()> {
+        let (sender, mut receiver) = broadcast::channel(16);
+        let mut storage = StorageWithEvents::new(StorageMemory::<TestItem>::default());
+        storage.add_event_sink(sender)?;
+
+        let id = String::from("player-1");
+        let (lock, item) = storage.lock(&id, "login").await?.success()?;
+        storage.save(&id, &item, &lock).await?;
+        storage.save_and_unlock(&id, &item, lock).await?;
+        let (_lock, _item) = storage.lock(&id, "crashed").await?.success()?;
+        storage.force_unlock(&id).await?;
+        // locked, but never saved
+        let other = String::from("player-2");
+        let (lock, _item) = storage.lock(&other, "login").await?.success()?;
+        storage.unlock(&other, lock).await?;
+
+        let expected = [
+            ItemEvent::Created {
+                id: id.clone(),
+                who: String::from("login"),
+            },
+            ItemEvent::Saved {
+                id: id.clone(),
+                who: String::from("login"),
+            },
+            ItemEvent::ForceUnlocked {
+                id: id.clone(),
+                who: String::from("crashed"),
+            },
+        ];
+        for event in expected {
+            assert_eq!(event, receiver.try_recv()?);
+        }
+        assert!(receiver.try_recv().is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn it_emits_lock_expiry_notices() -> Result<()> {
+        let (sender, mut receiver) = broadcast::channel(16);
+        let mut storage = StorageWithEvents::new(StorageMemory::<TestItem>::default());
+        storage.add_event_sink(sender)?;
+        assert!(storage
+            .set_lock_expiry_notice(Duration::from_secs(10), Duration::from_secs(10))
+            .is_err());
+        storage.set_lock_expiry_notice(Duration::from_secs(60), Duration::from_secs(10))?;
+
+        let id = String::from("player-1");
+        let (lock, _item) = storage.lock(&id, "worker-1").await?.success()?;
+        tokio::time::sleep(Duration::from_secs(40)).await;
+        let lock = storage.handoff_lock(&id, lock, "worker-2").await?;
+        tokio::time::sleep(Duration::from_secs(40)).await;
+        assert!(receiver.try_recv().is_err(), "handoff renews the notice");
+        tokio::time::sleep(Duration::from_secs(11)).await;
+        assert_eq!(
+            ItemEvent::LockExpiring {
+                id: id.clone(),
+                who: String::from("worker-2"),
+            },
+            receiver.try_recv()?
+        );
+        storage.unlock(&id, lock).await?;
+
+        let (lock, _item) = storage.lock(&id, "worker-3").await?.success()?;
+        storage.unlock(&id, lock).await?;
+        tokio::time::sleep(Duration::from_secs(120)).await;
+        assert!(receiver.try_recv().is_err(), "unlocking cancels the notice");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,23 @@
+use crate::StorageItem;
+use color_eyre::eyre::Result;
+
+/// A serialization format of items, e.g. JSON or a binary format, see [crate::StorageCodec::set_formats].
+pub trait ItemFormat<ITEM>: Send + Sync + std::fmt::Debug {
+    fn serialize(&self, item: &ITEM) -> Result<Vec<u8>>;
+    /// Fails if `data` isn't in this format, so the next format can be tried.
+    fn deserialize(&self, data: &[u8]) -> Result<ITEM>;
+}
+
+/// The format of [StorageItem::serialize] and [StorageItem::deserialize], the default.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NativeFormat;
+
+impl<ITEM: StorageItem> ItemFormat<ITEM> for NativeFormat {
+    fn serialize(&self, item: &ITEM) -> Result<Vec<u8>> {
+        item.serialize()
+    }
+
+    fn deserialize(&self, data: &[u8]) -> Result<ITEM> {
+        ITEM::deserialize(data)
+    }
+}
@@ -0,0 +1,46 @@
+use crate::StorageLock;
+use chrono::DateTime;
+use chrono::Utc;
+use std::time::Duration;
+
+/// One row of [crate::Storage::list], e.g. for an admin screen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ItemSummary<ID> {
+    pub id: ID,
+    /// The holder of the lock, `None` if not locked
+    pub locked_by: Option<String>,
+    /// When the lock was taken
+    pub locked_at: Option<DateTime<Utc>>,
+    /// The stored size in bytes, e.g. after compression, `None` if unknown
+    pub size: Option<u64>,
+    /// When the item was last saved, `None` for backends that don't track it
+    pub modified: Option<DateTime<Utc>>,
+}
+
+impl<ID> ItemSummary<ID> {
+    pub fn new(id: ID) -> Self {
+        Self {
+            id,
+            locked_by: None,
+            locked_at: None,
+            size: None,
+            modified: None,
+        }
+    }
+
+    pub(crate) fn with_lock(mut self, lock: Option<&StorageLock>) -> Self {
+        self.locked_by = lock.map(|lock| lock.who().to_string());
+        self.locked_at = lock.map(|lock| *lock.when());
+        self
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked_by.is_some()
+    }
+
+    /// How long the item has been locked at `now`, `None` if it isn't locked.
+    pub fn lock_age(&self, now: DateTime<Utc>) -> Option<Duration> {
+        self.locked_at
+            .map(|at| now.signed_duration_since(at).to_std().unwrap_or_default())
+    }
+}
@@ -9,21 +9,322 @@
 //! The documentation is still work-in-progress.
 
 mod storage;
+pub use storage::LockManyOutcome;
 pub use storage::LockResult;
+pub use storage::PayloadReader;
 pub use storage::Storage;
 pub use storage::StorageLock;
+pub use storage::LOCK_VERSION;
+mod dyn_storage;
+pub use dyn_storage::DynStorage;
+mod capabilities;
+pub use capabilities::Capabilities;
+
+mod change_event;
+pub use change_event::ChangeEvent;
+pub use change_event::ChangeEventReceiver;
+
+mod operation_metrics;
+mod slow_op;
+mod storage_middleware;
+pub use storage_middleware::StorageMiddleware;
+pub use storage_middleware::StorageOperation;
+pub use storage_middleware::StorageRequest;
+pub use storage_middleware::StorageWithMiddleware;
+mod rate_limit;
+pub use rate_limit::RateLimit;
+mod storage_cached;
+pub use storage_cached::CacheStats;
+pub use storage_cached::StorageCached;
+mod storage_write_behind;
+pub use storage_write_behind::StorageWriteBehind;
+mod access_policy;
+pub use access_policy::AccessPolicy;
+pub use access_policy::Decision;
+mod audit;
+pub use audit::AuditOutcome;
+pub use audit::AuditRecord;
+pub use audit::AuditSink;
+pub use audit::FileAuditSink;
+mod quarantine;
+pub use quarantine::QuarantineReason;
+pub use quarantine::QuarantineRecord;
+pub use quarantine::QuarantineSink;
+pub use quarantine::StorageQuarantineSink;
+
+mod request_context;
+pub use request_context::Priority;
+pub use request_context::RequestContext;
+
+mod clock;
+pub use clock::Clock;
+pub use clock::MockClock;
+pub use clock::SystemClock;
+
+mod op_options;
+pub use op_options::Consistency;
+pub use op_options::OpOptions;
+
+mod storage_cost;
+pub use storage_cost::CapacityUnits;
+pub use storage_cost::StorageCost;
+mod fsck;
+pub use fsck::FsckOptions;
+pub use fsck::FsckReport;
+pub use fsck::MetadataDrift;
+mod storage_error;
+pub use storage_error::StorageError;
 
 mod storage_item;
 pub use storage_item::StorageItem;
 
+mod storage_id;
+pub use storage_id::StorageId;
+mod random_id;
+pub use random_id::LowercaseAlphabet;
+pub use random_id::RandomId;
+pub use random_id::RandomIdAlphabet;
+pub use random_id::UrlSafeAlphabet;
+mod snowflake_id;
+pub use snowflake_id::SnowflakeId;
+mod sequential_id;
+pub use sequential_id::SequentialId;
+mod id_allocator;
+pub use id_allocator::IdAllocator;
+pub use id_allocator::IdCounter;
+mod id_generator;
+pub use id_generator::DefaultIdGenerator;
+pub use id_generator::IdGenerator;
+pub use id_generator::MockIdGenerator;
+pub use id_generator::SeededIdGenerator;
+mod composite_id;
+pub use composite_id::CompositeId;
+mod page;
+pub use page::paginate;
+pub use page::Page;
+pub use page::Paginate;
+mod query;
+pub use query::CompareOp;
+pub use query::Condition;
+pub use query::Query;
+mod id_filter;
+pub use id_filter::IdFilter;
+mod exists_policy;
+pub use exists_policy::ExistsPolicy;
+mod redact;
+pub use redact::is_redacted;
+pub use redact::set_redact;
+pub use redact::Redacted;
+mod progress;
+pub use progress::Progress;
+pub use progress::ProgressSink;
+pub use progress::ProgressTracker;
+#[cfg(feature = "content-id")]
+mod content_id;
+#[cfg(feature = "content-id")]
+pub use content_id::ContentId;
+#[cfg(feature = "uuid")]
+mod uuid_id;
+#[cfg(feature = "uuid")]
+pub use uuid_id::UuidId;
+#[cfg(feature = "uuid")]
+pub use uuid_id::UuidV4Id;
+#[cfg(feature = "uuid")]
+pub use uuid_id::UuidV7Id;
+
 mod storage_disk;
+pub use storage_disk::DiskIdCounter;
 pub use storage_disk::StorageDisk;
+pub use storage_disk::StorageDiskBackup;
+pub use storage_disk::StorageDiskCompression;
+pub use storage_disk::StorageDiskDurability;
+pub use storage_disk::StorageDiskFileNames;
+pub use storage_disk::StorageDiskLayout;
+pub use storage_disk::StorageDiskPermissions;
+pub use storage_disk::StorageDiskQuota;
+pub use storage_disk::StorageDiskQuotaAction;
+pub use storage_disk::StorageDiskStaleLocks;
+mod storage_disk_packed;
+pub use storage_disk_packed::StorageDiskPacked;
+mod storage_memory;
+pub use storage_memory::MemoryLatency;
+pub use storage_memory::StorageMemory;
+#[cfg(feature = "disk-watch")]
+mod disk_change_feed;
+#[cfg(feature = "disk-watch")]
+pub use disk_change_feed::DiskChangeFeed;
+mod dynamodb_retry_policy;
+#[cfg(feature = "otel")]
+mod otel;
+pub use dynamodb_retry_policy::DynamoDbErrorClass;
+pub use dynamodb_retry_policy::DynamoDbRetryPolicy;
+mod dynamodb_scan_throttle;
+pub use dynamodb_scan_throttle::DynamoDbScanThrottle;
+mod dynamodb_key_hasher;
+pub use dynamodb_key_hasher::DynamoDbHashShards;
+pub use dynamodb_key_hasher::DynamoDbKeyHasher;
+mod id_codec;
+#[cfg(feature = "encryption")]
+pub use id_codec::EncryptedIdCodec;
+pub use id_codec::IdCodec;
 mod storage_dynamodb;
+pub use storage_dynamodb::DynamoDbAttributeNames;
+pub use storage_dynamodb::DynamoDbDataFormat;
+pub use storage_dynamodb::DynamoDbIdCounter;
+pub use storage_dynamodb::DynamoDbTtl;
 pub use storage_dynamodb::StorageDynamoDb;
+mod dynamodb_registry;
+pub use dynamodb_registry::DynamoDbRegistry;
+#[cfg(feature = "dynamodb-streams")]
+mod dynamodb_change_feed;
+#[cfg(feature = "dynamodb-streams")]
+pub use dynamodb_change_feed::DynamoDbChangeFeed;
 mod storage_null;
+pub use storage_null::NullBehavior;
+pub use storage_null::NullProfile;
 pub use storage_null::StorageNull;
+mod storage_session;
+pub use storage_session::StorageSession;
+mod retry_budget;
+pub use retry_budget::RetryBudget;
+mod item_archive;
+pub use item_archive::ArchivedData;
+pub use item_archive::ItemArchive;
+pub use item_archive::ITEM_ARCHIVE_FORMAT;
+mod serialization_stats;
+pub use serialization_stats::SerializationCost;
+pub use serialization_stats::SerializationStats;
+mod repository;
+pub use repository::ItemHandle;
+pub use repository::LockRetryPolicy;
+pub use repository::Repository;
+mod saga;
+pub use saga::Saga;
+mod envelope;
+pub use envelope::Envelope;
+pub use envelope::EnvelopeHeader;
+pub use envelope::StorageEnveloped;
+mod item_format;
+pub use item_format::ItemFormat;
+pub use item_format::NativeFormat;
+mod codec;
+pub use codec::Codec;
+pub use codec::Encoded;
+pub use codec::RecordCodec;
+pub use codec::StorageCodec;
+mod storage_quorum;
+pub use storage_quorum::StorageQuorum;
+mod replication;
+pub use replication::ReplicationChange;
+pub use replication::ReplicationEntry;
+pub use replication::ReplicationFollower;
+pub use replication::ReplicationLag;
+pub use replication::ReplicationLog;
+pub use replication::StorageReplicated;
+mod item_events;
+pub use item_events::EventSink;
+pub use item_events::ItemEvent;
+pub use item_events::StorageWithEvents;
+mod item_summary;
+pub use item_summary::ItemSummary;
+mod lock_queue;
+pub use lock_queue::StorageLockQueue;
+mod single_flight;
+pub use single_flight::StorageSingleFlight;
+mod namespace_quotas;
+pub use namespace_quotas::NamespaceQuota;
+pub use namespace_quotas::NamespaceUsage;
+pub use namespace_quotas::StorageNamespaceQuotas;
+mod skip_unchanged;
+pub use skip_unchanged::StorageSkipUnchanged;
+mod read_replica;
+pub use read_replica::ReadSource;
+pub use read_replica::ReplicaLag;
+pub use read_replica::ReplicaRead;
+pub use read_replica::StorageReadReplica;
+mod storage_tiered;
+pub use storage_tiered::StorageTiered;
+mod storage_migrating;
+pub use storage_migrating::MigrationReport;
+pub use storage_migrating::StorageMigrating;
+mod storage_resilient;
+pub use storage_resilient::CircuitBreakerPolicy;
+pub use storage_resilient::CircuitState;
+pub use storage_resilient::ResilientErrorClass;
+pub use storage_resilient::ResilientRetryPolicy;
+pub use storage_resilient::StorageResilient;
+mod storage_config;
+pub use storage_config::StorageConfig;
+mod storage_url;
+pub use storage_url::from_url;
+#[cfg(feature = "wipe")]
+mod wipe;
+#[cfg(feature = "wipe")]
+pub use wipe::DeleteOptions;
+#[cfg(feature = "wipe")]
+pub use wipe::DeleteOutcome;
+#[cfg(feature = "wipe")]
+pub use wipe::WipeOptions;
+#[cfg(feature = "wipe")]
+pub use wipe::WipeReport;
+#[cfg(feature = "wipe")]
+pub use wipe::WIPE_CONFIRMATION;
+#[cfg(feature = "bench-support")]
+mod bench_support;
+#[cfg(feature = "bench-support")]
+pub use bench_support::run_workload;
+#[cfg(feature = "bench-support")]
+pub use bench_support::BenchResult;
+#[cfg(feature = "bench-support")]
+pub use bench_support::BenchWorkload;
+#[cfg(any(test, feature = "test-support"))]
+mod test_support;
+#[cfg(any(test, feature = "test-support"))]
+pub use test_support::run_conformance;
+#[cfg(any(test, feature = "test-support"))]
+pub use test_support::DynamoDbLocal;
+#[cfg(any(test, feature = "test-support"))]
+pub use test_support::TestTable;
+#[cfg(any(test, feature = "test-support"))]
+pub use test_support::DYNAMODB_ENDPOINT_ENV;
+mod storage_mock;
+pub use storage_mock::MockCall;
+pub use storage_mock::MockResponse;
+pub use storage_mock::StorageMock;
+pub mod bulk;
+pub mod export;
+pub mod fixtures;
+pub mod links;
+pub mod maintenance;
 
 #[cfg(feature = "metadata")]
 mod metadata;
 #[cfg(feature = "metadata")]
+pub use metadata::LockHoldTimes;
+#[cfg(feature = "metadata")]
+pub use metadata::LockStats;
+#[cfg(feature = "metadata")]
 pub(crate) use metadata::Metadata;
+#[cfg(feature = "metadata")]
+pub use metadata::MetadataSnapshot;
+#[cfg(feature = "metadata")]
+pub use metadata::LOCK_HOLD_BUCKETS;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(feature = "server")]
+pub use server::StorageServer;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "grpc")]
+pub use grpc::StorageGrpc;
+#[cfg(feature = "grpc")]
+pub use grpc::StorageGrpcService;
+#[cfg(feature = "prometheus")]
+mod lock_metrics;
+#[cfg(feature = "prometheus")]
+pub use lock_metrics::StorageMetrics;
+#[cfg(feature = "prometheus")]
+pub use lock_metrics::StorageMetricsMiddleware;
+
+#[cfg(test)]
+mod test_item;
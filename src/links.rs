@@ -0,0 +1,59 @@
+//! Checking links between items, see [Storage::add_link].
+//!
+//! Links are removed with the item they are stored with, but not with the item they point to.
+//! After deleting items, e.g. via `wipe_matching`, [find_dangling_links] finds the links left behind.
+
+use crate::paginate;
+use crate::Storage;
+use crate::StorageItem;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use futures_util::TryStreamExt;
+use serde::Serialize;
+
+/// A link whose target doesn't exist anymore.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DanglingLink<ID> {
+    pub from: ID,
+    pub to: ID,
+}
+
+/// Returns all links via `relation` pointing to missing items.
+///
+/// Note: This scans all items and checks every link.
+pub async fn find_dangling_links<ITEM, S>(
+    storage: &S,
+    relation: &str,
+) -> Result<Vec<DanglingLink<ITEM::ID>>>
+where
+    ITEM: StorageItem + Send,
+    S: Storage<ITEM> + ?Sized,
+{
+    let mut dangling = Vec::new();
+    let mut ids = std::pin::pin!(paginate(storage).ids());
+    while let Some(from) = ids.try_next().await? {
+        for to in storage.links_of(&from, relation).await? {
+            if !storage.exists(&to).await? {
+                dangling.push(DanglingLink {
+                    from: from.clone(),
+                    to,
+                });
+            }
+        }
+    }
+
+    Ok(dangling)
+}
+
+/// Relations become part of file and attribute names, so only `[A-Za-z0-9_-]` is allowed.
+pub(crate) fn ensure_valid_relation(relation: &str) -> Result<()> {
+    if relation.is_empty()
+        || !relation
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(eyre!("Invalid relation {relation:?}"));
+    }
+
+    Ok(())
+}
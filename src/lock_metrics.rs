@@ -0,0 +1,434 @@
+use crate::Clock;
+use crate::DynStorage;
+use crate::LockStats;
+use crate::RetryBudget;
+use crate::SerializationCost;
+use crate::SerializationStats;
+use crate::StorageItem;
+use crate::StorageMiddleware;
+use crate::StorageRequest;
+use crate::SystemClock;
+use async_trait::async_trait;
+use color_eyre::eyre::Report;
+use color_eyre::eyre::Result;
+use prometheus::core::Collector;
+use prometheus::core::Desc;
+use prometheus::proto::MetricFamily;
+use prometheus::CounterVec;
+use prometheus::GaugeVec;
+use prometheus::IntCounterVec;
+use prometheus::IntGaugeVec;
+use prometheus::Opts;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::Weak;
+use std::time::Duration;
+
+/// Prometheus metrics for the locks and errors of many storages, labelled by `backend`:
+///
+/// - `oml_storage_locks_held`, the locks currently held by the storages
+/// - `oml_storage_oldest_lock_age_seconds`, how long the oldest of them is held already, 0 without locks
+/// - `oml_storage_operations_total` and `oml_storage_errors_total`, also labelled by `operation`
+/// - `oml_storage_retry_budget_tokens` and `oml_storage_retry_budget_exhausted_total`
+///   of the added [RetryBudget]s, labelled by `budget` instead
+/// - `oml_storage_serializations_total`, `oml_storage_serialization_seconds_total`, and `oml_storage_serialized_bytes_total`,
+///   from [SerializationStats], labelled by `item_type` and `direction`, i.e. `serialize` or `deserialize`, instead
+/// - `oml_storage_serialized_over_soft_limit_total`, labelled by `item_type`
+///
+/// Register it with a [prometheus::Registry].
+/// The lock gauges are updated from [crate::Storage::metadata_lock_stats] by [StorageMetrics::refresh],
+/// e.g. via [StorageMetrics::spawn_refresh].
+/// Operations and errors are counted by the [StorageMetrics::middleware] of a [crate::StorageWithMiddleware].
+///
+/// Clones share the metrics.
+#[derive(Clone)]
+pub struct StorageMetrics {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    sources: Mutex<Vec<(String, Arc<dyn LockStatsSource>)>>,
+    budgets: Mutex<Vec<(String, RetryBudget)>>,
+    clock: Mutex<Arc<dyn Clock>>,
+    locks_held: IntGaugeVec,
+    oldest_lock_age: GaugeVec,
+    operations: IntCounterVec,
+    errors: IntCounterVec,
+    budget_tokens: GaugeVec,
+    budget_exhausted: IntCounterVec,
+    serializations: IntCounterVec,
+    serialization_seconds: CounterVec,
+    serialized_bytes: IntCounterVec,
+    over_soft_limit: IntCounterVec,
+}
+
+impl std::fmt::Debug for StorageMetrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let backends: Vec<String> = self
+            .inner
+            .sources
+            .lock()
+            .expect("can lock")
+            .iter()
+            .map(|(backend, _)| backend.clone())
+            .collect();
+        f.debug_struct("StorageMetrics")
+            .field("backends", &backends)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Type erases the item type of the added storages.
+#[async_trait]
+trait LockStatsSource: Send + Sync {
+    async fn lock_stats(&self) -> LockStats;
+}
+
+struct Source<ITEM: StorageItem + Send>(DynStorage<ITEM>);
+
+#[async_trait]
+impl<ITEM: StorageItem + Send> LockStatsSource for Source<ITEM> {
+    async fn lock_stats(&self) -> LockStats {
+        self.0.metadata_lock_stats().await
+    }
+}
+
+impl StorageMetrics {
+    pub fn new() -> Result<Self> {
+        let locks_held = IntGaugeVec::new(
+            Opts::new("oml_storage_locks_held", "Locks currently held"),
+            &["backend"],
+        )?;
+        let oldest_lock_age = GaugeVec::new(
+            Opts::new(
+                "oml_storage_oldest_lock_age_seconds",
+                "Age of the oldest lock currently held",
+            ),
+            &["backend"],
+        )?;
+        let operations = IntCounterVec::new(
+            Opts::new("oml_storage_operations_total", "Storage operations"),
+            &["backend", "operation"],
+        )?;
+        let errors = IntCounterVec::new(
+            Opts::new("oml_storage_errors_total", "Failed storage operations"),
+            &["backend", "operation"],
+        )?;
+        let budget_tokens = GaugeVec::new(
+            Opts::new(
+                "oml_storage_retry_budget_tokens",
+                "Retries left in the retry budget",
+            ),
+            &["budget"],
+        )?;
+        let budget_exhausted = IntCounterVec::new(
+            Opts::new(
+                "oml_storage_retry_budget_exhausted_total",
+                "Retries refused, because the retry budget was exhausted",
+            ),
+            &["budget"],
+        )?;
+        let serializations = IntCounterVec::new(
+            Opts::new(
+                "oml_storage_serializations_total",
+                "Items serialized, or deserialized",
+            ),
+            &["item_type", "direction"],
+        )?;
+        let serialization_seconds = CounterVec::new(
+            Opts::new(
+                "oml_storage_serialization_seconds_total",
+                "Time spent serializing, or deserializing, items",
+            ),
+            &["item_type", "direction"],
+        )?;
+        let serialized_bytes = IntCounterVec::new(
+            Opts::new(
+                "oml_storage_serialized_bytes_total",
+                "Bytes of items serialized, or deserialized",
+            ),
+            &["item_type", "direction"],
+        )?;
+        let over_soft_limit = IntCounterVec::new(
+            Opts::new(
+                "oml_storage_serialized_over_soft_limit_total",
+                "Items serialized to more than the soft limit",
+            ),
+            &["item_type"],
+        )?;
+
+        Ok(Self {
+            inner: Arc::new(Inner {
+                sources: Mutex::new(Vec::new()),
+                budgets: Mutex::new(Vec::new()),
+                clock: Mutex::new(Arc::new(SystemClock)),
+                locks_held,
+                oldest_lock_age,
+                operations,
+                errors,
+                budget_tokens,
+                budget_exhausted,
+                serializations,
+                serialization_seconds,
+                serialized_bytes,
+                over_soft_limit,
+            }),
+        })
+    }
+
+    /// The clock the lock ages are measured with, e.g. a [crate::MockClock] shared with the storages.
+    pub fn set_clock(&self, clock: impl Clock + 'static) -> Result<()> {
+        *self.inner.clock.lock().expect("can lock") = Arc::new(clock);
+
+        Ok(())
+    }
+
+    /// Adds the locks of `storage` to the gauges of `backend`, e.g. `dynamodb`.
+    /// Storages with the same `backend` are summed up.
+    pub fn add_storage<ITEM: StorageItem + Send + 'static>(
+        &self,
+        backend: &str,
+        storage: DynStorage<ITEM>,
+    ) -> Result<()> {
+        self.inner
+            .sources
+            .lock()
+            .expect("can lock")
+            .push((String::from(backend), Arc::new(Source(storage))));
+
+        Ok(())
+    }
+
+    /// Exports the tokens left in `budget` and the retries it refused, labelled `name`, see [StorageMetrics::refresh].
+    pub fn add_retry_budget(&self, name: &str, budget: &RetryBudget) -> Result<()> {
+        self.inner
+            .budgets
+            .lock()
+            .expect("can lock")
+            .push((String::from(name), budget.clone()));
+
+        Ok(())
+    }
+
+    /// Counts the operations and errors of a [crate::StorageWithMiddleware] for `backend`.
+    pub fn middleware(&self, backend: &str) -> StorageMetricsMiddleware {
+        StorageMetricsMiddleware {
+            metrics: self.clone(),
+            backend: String::from(backend),
+        }
+    }
+
+    /// Updates the lock gauges from the added storages, the retry budget, and the serialization metrics.
+    pub async fn refresh(&self) {
+        for (item_type, stats) in SerializationStats::all() {
+            self.refresh_serialization(&item_type, "serialize", &stats.serialize);
+            self.refresh_serialization(&item_type, "deserialize", &stats.deserialize);
+            let over = self.inner.over_soft_limit.with_label_values(&[&item_type]);
+            over.inc_by(stats.over_soft_limit.saturating_sub(over.get()));
+        }
+        for (name, budget) in self.inner.budgets.lock().expect("can lock").iter() {
+            let labels = [name.as_str()];
+            self.inner
+                .budget_tokens
+                .with_label_values(&labels)
+                .set(budget.available());
+            let exhausted = self.inner.budget_exhausted.with_label_values(&labels);
+            exhausted.inc_by(budget.exhausted().saturating_sub(exhausted.get()));
+        }
+        let sources = self.inner.sources.lock().expect("can lock").clone();
+        let mut backends: BTreeMap<String, (u64, Option<chrono::DateTime<chrono::Utc>>)> =
+            BTreeMap::new();
+        for (backend, source) in sources {
+            let stats = source.lock_stats().await;
+            let (held, oldest) = backends.entry(backend).or_default();
+            *held += stats.held;
+            *oldest = match (*oldest, stats.oldest_held_since) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+        }
+        let now = self.inner.clock.lock().expect("can lock").now();
+        for (backend, (held, oldest)) in backends {
+            self.inner
+                .locks_held
+                .with_label_values(&[&backend])
+                .set(held as i64);
+            let age = oldest
+                .and_then(|oldest| (now - oldest).to_std().ok())
+                .unwrap_or_default();
+            self.inner
+                .oldest_lock_age
+                .with_label_values(&[&backend])
+                .set(age.as_secs_f64());
+        }
+    }
+
+    /// Calls [StorageMetrics::refresh] every `interval`, until all clones are dropped.
+    pub fn spawn_refresh(&self, interval: Duration) {
+        let inner = Arc::downgrade(&self.inner);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            loop {
+                interval.tick().await;
+                let Some(inner) = Weak::upgrade(&inner) else {
+                    break;
+                };
+                StorageMetrics { inner }.refresh().await;
+            }
+        });
+    }
+
+    fn refresh_serialization(&self, item_type: &str, direction: &str, cost: &SerializationCost) {
+        let labels = [item_type, direction];
+        let count = self.inner.serializations.with_label_values(&labels);
+        count.inc_by(cost.count.saturating_sub(count.get()));
+        let seconds = self.inner.serialization_seconds.with_label_values(&labels);
+        seconds.inc_by((cost.time.as_secs_f64() - seconds.get()).max(0.0));
+        let bytes = self.inner.serialized_bytes.with_label_values(&labels);
+        bytes.inc_by(cost.bytes.saturating_sub(bytes.get()));
+    }
+
+    fn record(&self, backend: &str, operation: &str, failed: bool) {
+        let labels = [backend, operation];
+        self.inner.operations.with_label_values(&labels).inc();
+        if failed {
+            self.inner.errors.with_label_values(&labels).inc();
+        }
+    }
+}
+
+impl Collector for StorageMetrics {
+    fn desc(&self) -> Vec<&Desc> {
+        let inner = &*self.inner;
+        let mut desc = inner.locks_held.desc();
+        desc.extend(inner.oldest_lock_age.desc());
+        desc.extend(inner.operations.desc());
+        desc.extend(inner.errors.desc());
+        desc.extend(inner.budget_tokens.desc());
+        desc.extend(inner.budget_exhausted.desc());
+        desc.extend(inner.serializations.desc());
+        desc.extend(inner.serialization_seconds.desc());
+        desc.extend(inner.serialized_bytes.desc());
+        desc.extend(inner.over_soft_limit.desc());
+
+        desc
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        let inner = &*self.inner;
+        let mut families = inner.locks_held.collect();
+        families.extend(inner.oldest_lock_age.collect());
+        families.extend(inner.operations.collect());
+        families.extend(inner.errors.collect());
+        families.extend(inner.budget_tokens.collect());
+        families.extend(inner.budget_exhausted.collect());
+        families.extend(inner.serializations.collect());
+        families.extend(inner.serialization_seconds.collect());
+        families.extend(inner.serialized_bytes.collect());
+        families.extend(inner.over_soft_limit.collect());
+
+        families
+    }
+}
+
+/// Counts operations and errors, see [StorageMetrics::middleware].
+#[derive(Debug)]
+pub struct StorageMetricsMiddleware {
+    metrics: StorageMetrics,
+    backend: String,
+}
+
+#[async_trait]
+impl<ITEM: StorageItem> StorageMiddleware<ITEM> for StorageMetricsMiddleware {
+    async fn after(&self, request: &StorageRequest<'_, ITEM>, error: Option<&Report>) {
+        self.metrics
+            .record(&self.backend, request.operation().name(), error.is_some());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::DynStorage;
+    use crate::MockClock;
+    use crate::RetryBudget;
+    use crate::Storage;
+    use crate::StorageItem;
+    use crate::StorageMemory;
+    use crate::StorageMetrics;
+    use crate::StorageWithMiddleware;
+    use chrono::Utc;
+    use color_eyre::Result;
+    use std::sync::Arc;
+
+    #[derive(Default, Debug)]
+    struct TestItem;
+
+    impl StorageItem for TestItem {
+        type ID = String;
+
+        fn serialize(&self) -> Result<Vec<u8>> {
+            Ok(Vec::new())
+        }
+        fn deserialize(_data: &[u8]) -> Result<Self> {
+            Ok(Self)
+        }
+    }
+
+    #[tokio::test]
+    async fn it_exports_lock_metrics() -> Result<()> {
+        let clock = MockClock::new(Utc::now());
+        let mut memory = StorageMemory::<TestItem>::default();
+        memory.set_clock(clock.clone())?;
+        let memory: DynStorage<TestItem> = Arc::new(memory);
+        let metrics = StorageMetrics::new()?;
+        metrics.set_clock(clock.clone())?;
+        metrics.add_storage("memory", memory.clone())?;
+        let registry = prometheus::Registry::new();
+        registry.register(Box::new(metrics.clone()))?;
+
+        let mut storage = StorageWithMiddleware::new(memory);
+        storage.add_middleware(metrics.middleware("memory"))?;
+        let id = String::from("stuck");
+        let (lock, _item) = storage.lock(&id, "worker").await?.success()?;
+        storage.save(&id, &TestItem, &lock).await?;
+        assert!(storage.load(&String::from("missing")).await.is_err());
+        let budget = RetryBudget::new(1.0, 1)?;
+        metrics.add_retry_budget("locks", &budget)?;
+        assert!(budget.try_retry());
+        assert!(!budget.try_retry());
+        clock.advance(std::time::Duration::from_secs(90));
+        metrics.refresh().await;
+
+        let value = |name: &str, operation: Option<&str>| {
+            registry
+                .gather()
+                .iter()
+                .filter(|f| f.name() == name)
+                .flat_map(|f| f.get_metric().to_vec())
+                .find(|m| {
+                    operation.is_none_or(|operation| {
+                        m.get_label().iter().any(|l| l.value() == operation)
+                    })
+                })
+                .map(|m| m.get_counter().get_value() + m.get_gauge().get_value())
+        };
+        assert_eq!(Some(1.0), value("oml_storage_locks_held", None));
+        assert_eq!(
+            Some(90.0),
+            value("oml_storage_oldest_lock_age_seconds", None)
+        );
+        assert_eq!(
+            Some(1.0),
+            value("oml_storage_operations_total", Some("lock"))
+        );
+        assert_eq!(Some(1.0), value("oml_storage_errors_total", Some("load")));
+        assert_eq!(
+            Some(1.0),
+            value("oml_storage_retry_budget_exhausted_total", Some("locks"))
+        );
+        assert!(value("oml_storage_serializations_total", Some("serialize")).is_some());
+
+        Ok(())
+    }
+}
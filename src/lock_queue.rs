@@ -0,0 +1,537 @@
+use crate::LockResult;
+use crate::OpOptions;
+use crate::Page;
+use crate::Storage;
+use crate::StorageItem;
+use crate::StorageLock;
+use async_trait::async_trait;
+use chrono::DateTime;
+use chrono::Utc;
+use color_eyre::eyre::Result;
+use core::marker::PhantomData;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::OwnedSemaphorePermit;
+use tokio::sync::Semaphore;
+
+#[derive(Debug)]
+struct Queue {
+    /// A single permit, tokio hands it to waiters in order
+    turn: Arc<Semaphore>,
+    /// Taken while the lock is held via this wrapper
+    permit: Option<OwnedSemaphorePermit>,
+    holder: Option<StorageLock>,
+}
+
+/// Wraps any [Storage] and lets callers wait for locks in order via [StorageLockQueue::lock_queued].
+///
+/// Waiters in this process are woken by `unlock`, in the order they started waiting.
+/// Locks held elsewhere, e.g. by other processes, are polled for,
+/// while the other waiters keep their place in the queue.
+///
+/// Note: Plain `lock` calls don't wait, but don't jump the queue either.
+#[derive(Debug)]
+pub struct StorageLockQueue<ITEM: StorageItem, S: Storage<ITEM>>
+where
+    ITEM: Send,
+{
+    storage: S,
+    poll_interval: Duration,
+    queues: Mutex<HashMap<String, Queue>>,
+    item_type: PhantomData<ITEM>,
+}
+
+impl<ITEM: StorageItem + Send, S: Storage<ITEM>> StorageLockQueue<ITEM, S> {
+    pub fn new(storage: S) -> Self {
+        Self {
+            storage,
+            poll_interval: Duration::from_millis(100),
+            queues: Mutex::new(HashMap::new()),
+            item_type: PhantomData,
+        }
+    }
+
+    /// How long to wait between attempts while the lock is held outside of this wrapper.
+    pub fn set_poll_interval(&mut self, poll_interval: Duration) -> Result<()> {
+        self.poll_interval = poll_interval;
+
+        Ok(())
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    pub fn into_inner(self) -> S {
+        self.storage
+    }
+
+    /// Waits until it's our turn and the lock can be taken.
+    ///
+    /// Dropping the future gives up the place in the queue, wrap it in [tokio::time::timeout] to limit the wait.
+    pub async fn lock_queued(&self, id: &ITEM::ID, who: &str) -> Result<(StorageLock, ITEM)> {
+        let turn = self.turn(id);
+        let permit = turn.acquire_owned().await?;
+        loop {
+            match self.storage.lock(id, who).await {
+                Ok(LockResult::Success { lock, item }) => {
+                    self.hold(id, permit, &lock);
+                    return Ok((lock, item));
+                }
+                Ok(LockResult::AlreadyLocked { who: holder, .. }) => {
+                    tracing::debug!("{id} is locked by {holder:?} elsewhere, polling");
+                    tokio::time::sleep(self.poll_interval).await;
+                }
+                Err(e) => {
+                    drop(permit);
+                    self.release(id);
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    fn turn(&self, id: &ITEM::ID) -> Arc<Semaphore> {
+        let mut queues = self.queues.lock().expect("can lock");
+        let queue = queues.entry(id.to_string()).or_insert_with(|| Queue {
+            turn: Arc::new(Semaphore::new(1)),
+            permit: None,
+            holder: None,
+        });
+        queue.turn.clone()
+    }
+
+    fn hold(&self, id: &ITEM::ID, permit: OwnedSemaphorePermit, lock: &StorageLock) {
+        let mut queues = self.queues.lock().expect("can lock");
+        if let Some(queue) = queues.get_mut(&id.to_string()) {
+            queue.permit = Some(permit);
+            queue.holder = Some(lock.duplicate());
+        }
+    }
+
+    /// Hands the turn to the next waiter and forgets queues nobody waits in.
+    fn release(&self, id: &ITEM::ID) {
+        let mut queues = self.queues.lock().expect("can lock");
+        let id = id.to_string();
+        let Some(queue) = queues.get_mut(&id) else {
+            return;
+        };
+        queue.holder = None;
+        drop(queue.permit.take());
+        // the map holds the only reference if nobody waits
+        if Arc::strong_count(&queue.turn) == 1 {
+            queues.remove(&id);
+        }
+    }
+}
+
+#[async_trait]
+impl<ITEM: StorageItem + Send, S: Storage<ITEM>> Storage<ITEM> for StorageLockQueue<ITEM, S> {
+    async fn ensure_storage_exists(&mut self) -> Result<()> {
+        self.storage.ensure_storage_exists().await
+    }
+
+    fn capabilities(&self) -> crate::Capabilities {
+        self.storage.capabilities()
+    }
+
+    async fn create(&self) -> Result<ITEM::ID> {
+        self.storage.create().await
+    }
+
+    async fn exists(&self, id: &ITEM::ID) -> Result<bool> {
+        self.storage.exists(id).await
+    }
+
+    async fn exists_with(&self, id: &ITEM::ID, options: &OpOptions) -> Result<bool> {
+        self.storage.exists_with(id, options).await
+    }
+
+    async fn load(&self, id: &ITEM::ID) -> Result<ITEM> {
+        self.storage.load(id).await
+    }
+
+    async fn load_with(&self, id: &ITEM::ID, options: &OpOptions) -> Result<ITEM> {
+        self.storage.load_with(id, options).await
+    }
+
+    async fn load_many(&self, ids: &[ITEM::ID]) -> Result<Vec<Option<ITEM>>> {
+        self.storage.load_many(ids).await
+    }
+
+    async fn prefetch(&self, ids: &[ITEM::ID]) -> Result<()> {
+        self.storage.prefetch(ids).await
+    }
+
+    async fn save(&self, id: &ITEM::ID, item: &ITEM, lock: &StorageLock) -> Result<()> {
+        self.storage.save(id, item, lock).await
+    }
+
+    async fn lock(&self, id: &ITEM::ID, who: &str) -> Result<LockResult<ITEM>> {
+        let turn = self.turn(id);
+        let Ok(permit) = turn.try_acquire_owned() else {
+            let held = {
+                let queues = self.queues.lock().expect("can lock");
+                queues
+                    .get(&id.to_string())
+                    .and_then(|q| q.holder.as_ref())
+                    .map(LockResult::already_locked)
+            };
+            if let Some(held) = held {
+                return Ok(held);
+            }
+            // a waiter is polling for a lock held elsewhere, which most likely still is
+            return self.storage.lock(id, who).await;
+        };
+        let result = self.storage.lock(id, who).await;
+        match &result {
+            Ok(LockResult::Success { lock, .. }) => self.hold(id, permit, lock),
+            _ => {
+                drop(permit);
+                self.release(id);
+            }
+        }
+
+        result
+    }
+
+    async fn unlock(&self, id: &ITEM::ID, lock: StorageLock) -> Result<()> {
+        self.storage.unlock(id, lock).await?;
+        self.release(id);
+
+        Ok(())
+    }
+
+    async fn handoff_lock(
+        &self,
+        id: &ITEM::ID,
+        lock: StorageLock,
+        new_who: &str,
+    ) -> Result<StorageLock> {
+        self.storage.handoff_lock(id, lock, new_who).await
+    }
+
+    async fn save_and_unlock(&self, id: &ITEM::ID, item: &ITEM, lock: StorageLock) -> Result<()> {
+        self.storage.save_and_unlock(id, item, lock).await?;
+        self.release(id);
+
+        Ok(())
+    }
+
+    async fn load_raw(&self, id: &ITEM::ID) -> Result<Vec<u8>> {
+        self.storage.load_raw(id).await
+    }
+
+    async fn save_raw(&self, id: &ITEM::ID, data: &[u8], lock: &StorageLock) -> Result<()> {
+        self.storage.save_raw(id, data, lock).await
+    }
+
+    async fn put_blob(
+        &self,
+        id: &ITEM::ID,
+        name: &str,
+        data: &[u8],
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.storage.put_blob(id, name, data, lock).await
+    }
+
+    async fn get_blob(&self, id: &ITEM::ID, name: &str) -> Result<Option<Vec<u8>>> {
+        self.storage.get_blob(id, name).await
+    }
+
+    async fn list_blobs(&self, id: &ITEM::ID) -> Result<Vec<String>> {
+        self.storage.list_blobs(id).await
+    }
+
+    async fn put_meta(&self, key: &str, value: &[u8]) -> Result<()> {
+        self.storage.put_meta(key, value).await
+    }
+
+    async fn get_meta(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.storage.get_meta(key).await
+    }
+
+    async fn add_alias(&self, alias: &ITEM::ID, canonical: &ITEM::ID) -> Result<()> {
+        self.storage.add_alias(alias, canonical).await
+    }
+
+    async fn remove_alias(&self, alias: &ITEM::ID) -> Result<()> {
+        self.storage.remove_alias(alias).await
+    }
+
+    async fn resolve_alias(&self, id: &ITEM::ID) -> Result<ITEM::ID> {
+        self.storage.resolve_alias(id).await
+    }
+
+    async fn add_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.storage.add_link(from, relation, to, lock).await
+    }
+
+    async fn remove_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.storage.remove_link(from, relation, to, lock).await
+    }
+
+    async fn links_of(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        self.storage.links_of(id, relation).await
+    }
+
+    async fn linked_from(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        self.storage.linked_from(id, relation).await
+    }
+
+    async fn force_unlock(&self, id: &ITEM::ID) -> Result<Option<StorageLock>> {
+        let lock = self.storage.force_unlock(id).await?;
+        self.release(id);
+
+        Ok(lock)
+    }
+
+    async fn force_unlock_if_held(
+        &self,
+        id: &ITEM::ID,
+        who: &str,
+        when: DateTime<Utc>,
+    ) -> Result<bool> {
+        let unlocked = self.storage.force_unlock_if_held(id, who, when).await?;
+        if unlocked {
+            self.release(id);
+        }
+
+        Ok(unlocked)
+    }
+
+    async fn remove_orphaned_locks(&self, max_age: Duration) -> Result<usize> {
+        self.storage.remove_orphaned_locks(max_age).await
+    }
+
+    async fn verify_lock(&self, id: &ITEM::ID, lock: &StorageLock) -> Result<bool> {
+        self.storage.verify_lock(id, lock).await
+    }
+
+    async fn verify_lock_with(
+        &self,
+        id: &ITEM::ID,
+        lock: &StorageLock,
+        options: &OpOptions,
+    ) -> Result<bool> {
+        self.storage.verify_lock_with(id, lock, options).await
+    }
+
+    async fn all_ids(&self) -> Result<Vec<ITEM::ID>> {
+        self.storage.all_ids().await
+    }
+
+    async fn scan_ids(&self, start: Option<&str>, limit: Option<usize>) -> Result<Page<ITEM::ID>> {
+        self.storage.scan_ids(start, limit).await
+    }
+
+    async fn scan_ids_with_prefix(
+        &self,
+        prefix: &str,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.storage
+            .scan_ids_with_prefix(prefix, start, limit)
+            .await
+    }
+
+    async fn scan_ids_filtered(
+        &self,
+        filter: &crate::IdFilter,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.storage.scan_ids_filtered(filter, start, limit).await
+    }
+
+    async fn scan_ids_between(
+        &self,
+        from: &ITEM::ID,
+        to: &ITEM::ID,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.storage.scan_ids_between(from, to, start, limit).await
+    }
+
+    async fn list(
+        &self,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<crate::ItemSummary<ITEM::ID>>> {
+        self.storage.list(start, limit).await
+    }
+
+    async fn item_size(&self, id: &ITEM::ID) -> Result<u64> {
+        self.storage.item_size(id).await
+    }
+
+    async fn estimate_cost(&self) -> Result<crate::StorageCost> {
+        self.storage.estimate_cost().await
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        self.storage.shutdown().await
+    }
+
+    async fn fsck(&self, options: &crate::FsckOptions) -> Result<crate::FsckReport> {
+        self.storage.fsck(options).await
+    }
+
+    async fn display_lock(&self, id: &ITEM::ID) -> Result<String> {
+        self.storage.display_lock(id).await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_highest_seen_id(&self) -> Option<ITEM::ID> {
+        self.storage.metadata_highest_seen_id().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_item_count(&self) -> Option<u64> {
+        self.storage.metadata_item_count().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_total_bytes(&self) -> Option<u64> {
+        self.storage.metadata_total_bytes().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_last_write(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.storage.metadata_last_write().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_lock_stats(&self) -> crate::LockStats {
+        self.storage.metadata_lock_stats().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_snapshot(&self) -> crate::MetadataSnapshot {
+        self.storage.metadata_snapshot().await
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn wipe(
+        &self,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        self.storage.wipe(options, progress, cancel).await
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn wipe_matching(
+        &self,
+        filter: &crate::IdFilter,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        self.storage
+            .wipe_matching(filter, options, progress, cancel)
+            .await
+    }
+
+    /// Forced deletes release the queues of the deleted items, like [Storage::force_unlock].
+    #[cfg(feature = "wipe")]
+    async fn delete_many(
+        &self,
+        ids: &[ITEM::ID],
+        options: &crate::DeleteOptions,
+    ) -> Result<Vec<crate::DeleteOutcome>> {
+        let outcomes = self.storage.delete_many(ids, options).await?;
+        if options.force() {
+            for id in ids {
+                self.release(id);
+            }
+        }
+
+        Ok(outcomes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_item::TestItem;
+    use crate::LockResult;
+    use crate::Storage;
+    use crate::StorageLockQueue;
+    use crate::StorageMemory;
+    use color_eyre::Result;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::sync::Mutex;
+
+    #[tokio::test]
+    async fn it_hands_locks_to_waiters_in_order() -> Result<()> {
+        let storage = Arc::new(StorageLockQueue::new(StorageMemory::<TestItem>::default()));
+        let id = String::from("busy");
+        let (lock, _) = storage.lock(&id, "first").await?.success()?;
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut waiters = Vec::new();
+        for who in ["second", "third", "fourth"] {
+            let storage = storage.clone();
+            let order = order.clone();
+            let id = id.clone();
+            waiters.push(tokio::spawn(async move {
+                let (lock, _) = storage.lock_queued(&id, who).await?;
+                order.lock().await.push(lock.who().to_string());
+                storage.unlock(&id, lock).await
+            }));
+            // let the waiter queue up
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // no barging while others wait
+        assert!(matches!(
+            storage.lock(&id, "late").await?,
+            LockResult::AlreadyLocked { .. }
+        ));
+        storage.unlock(&id, lock).await?;
+        for waiter in waiters {
+            waiter.await??;
+        }
+        assert_eq!(vec!["second", "third", "fourth"], *order.lock().await);
+        assert!(storage.queues.lock().expect("can lock").is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_polls_for_locks_held_elsewhere() -> Result<()> {
+        let mut storage = StorageLockQueue::new(StorageMemory::<TestItem>::default());
+        storage.set_poll_interval(Duration::from_millis(5))?;
+        let id = String::from("remote");
+        let (lock, _) = storage.storage().lock(&id, "elsewhere").await?.success()?;
+
+        let (queued, unlocked) = tokio::join!(storage.lock_queued(&id, "here"), async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            storage.storage().unlock(&id, lock).await
+        });
+        unlocked?;
+        let (queued, _) = queued?;
+        assert_eq!("here", queued.who());
+
+        Ok(())
+    }
+}
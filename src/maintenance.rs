@@ -0,0 +1,231 @@
+//! Running periodic jobs against a storage, e.g. stale lock cleanup or metadata flushes.
+//!
+//! ```no_run
+//! # use color_eyre::eyre::Result;
+//! # use oml_storage::maintenance::Maintenance;
+//! # use oml_storage::{StorageDisk, StorageDiskStaleLocks, StorageItem};
+//! # use std::sync::Arc;
+//! # use std::time::Duration;
+//! # async fn example<ITEM: StorageItem + Send + 'static>(storage: Arc<StorageDisk<ITEM>>) -> Result<()> {
+//! let mut maintenance = Maintenance::default();
+//! maintenance.set_jitter(Duration::from_secs(5))?;
+//! let s = storage.clone();
+//! maintenance.add_fn("stale_locks", Duration::from_secs(60), move || {
+//!     let s = s.clone();
+//!     async move {
+//!         let stale_locks = StorageDiskStaleLocks {
+//!             max_age: Some(Duration::from_secs(3600)),
+//!             ..Default::default()
+//!         };
+//!         s.cleanup_stale_locks(&stale_locks).await.map(|_| ())
+//!     }
+//! })?;
+//! let handle = maintenance.start();
+//! // ...
+//! handle.shutdown().await;
+//! # Ok(())
+//! # }
+//! ```
+
+use async_trait::async_trait;
+use color_eyre::eyre::Result;
+use rand::Rng;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+/// A job run periodically by [Maintenance].
+#[async_trait]
+pub trait MaintenanceJob: Send + Sync {
+    /// Used in logs.
+    fn name(&self) -> &str;
+
+    /// Errors are logged, and the job runs again after the next interval.
+    async fn run(&self) -> Result<()>;
+}
+
+struct FnJob<F> {
+    name: String,
+    f: F,
+}
+
+#[async_trait]
+impl<F, Fut> MaintenanceJob for FnJob<F>
+where
+    F: Fn() -> Fut + Send + Sync,
+    Fut: Future<Output = Result<()>> + Send,
+{
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn run(&self) -> Result<()> {
+        (self.f)().await
+    }
+}
+
+struct Scheduled {
+    job: Arc<dyn MaintenanceJob>,
+    interval: Duration,
+}
+
+/// The jobs to run and how often.
+#[derive(Default)]
+pub struct Maintenance {
+    jobs: Vec<Scheduled>,
+    jitter: Duration,
+}
+
+impl std::fmt::Debug for Maintenance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let jobs: Vec<_> = self
+            .jobs
+            .iter()
+            .map(|s| (s.job.name(), s.interval))
+            .collect();
+        f.debug_struct("Maintenance")
+            .field("jobs", &jobs)
+            .field("jitter", &self.jitter)
+            .finish()
+    }
+}
+
+impl Maintenance {
+    /// Runs `job` every `interval`, the first run is after one interval.
+    pub fn add_job(
+        &mut self,
+        job: impl MaintenanceJob + 'static,
+        interval: Duration,
+    ) -> Result<()> {
+        self.jobs.push(Scheduled {
+            job: Arc::new(job),
+            interval,
+        });
+
+        Ok(())
+    }
+
+    /// Like [Maintenance::add_job], for a closure.
+    pub fn add_fn<F, Fut>(&mut self, name: &str, interval: Duration, f: F) -> Result<()>
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        self.add_job(
+            FnJob {
+                name: name.to_string(),
+                f,
+            },
+            interval,
+        )
+    }
+
+    /// Adds a random delay up to `jitter` to every interval,
+    /// so instances started together don't run their jobs at the same time.
+    pub fn set_jitter(&mut self, jitter: Duration) -> Result<()> {
+        self.jitter = jitter;
+
+        Ok(())
+    }
+
+    /// Starts every job in its own task.
+    pub fn start(self) -> MaintenanceHandle {
+        let (shutdown, _) = watch::channel(false);
+        let tasks = self
+            .jobs
+            .into_iter()
+            .map(|scheduled| {
+                let mut shutdown = shutdown.subscribe();
+                let jitter = self.jitter;
+                tokio::spawn(async move {
+                    loop {
+                        let delay = scheduled.interval + random_jitter(jitter);
+                        tokio::select! {
+                            _ = tokio::time::sleep(delay) => {}
+                            // also when the handle was dropped
+                            _ = shutdown.changed() => break,
+                        }
+                        let name = scheduled.job.name();
+                        tracing::debug!("Running maintenance job {name}");
+                        if let Err(e) = scheduled.job.run().await {
+                            tracing::warn!("Maintenance job {name} failed -> {e:?}");
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        MaintenanceHandle { shutdown, tasks }
+    }
+}
+
+fn random_jitter(jitter: Duration) -> Duration {
+    if jitter.is_zero() {
+        return Duration::ZERO;
+    }
+    let millis = jitter.as_millis() as u64;
+    Duration::from_millis(rand::thread_rng().gen_range(0..=millis))
+}
+
+/// Stops the jobs when shut down or dropped.
+#[derive(Debug)]
+pub struct MaintenanceHandle {
+    shutdown: watch::Sender<bool>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl MaintenanceHandle {
+    /// Stops scheduling and waits for running jobs to finish.
+    pub async fn shutdown(self) {
+        let _ = self.shutdown.send(true);
+        for task in self.tasks {
+            if let Err(e) = task.await {
+                tracing::warn!("Maintenance task failed -> {e:?}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::maintenance::Maintenance;
+    use color_eyre::eyre::eyre;
+    use color_eyre::Result;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test(start_paused = true)]
+    async fn it_runs_jobs_until_shutdown() -> Result<()> {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let mut maintenance = Maintenance::default();
+        let r = runs.clone();
+        maintenance.add_fn("count", Duration::from_secs(10), move || {
+            let r = r.clone();
+            async move {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                r.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            }
+        })?;
+        maintenance.add_fn("fail", Duration::from_secs(1), || async {
+            Err(eyre!("failing"))
+        })?;
+
+        let handle = maintenance.start();
+        tokio::time::sleep(Duration::from_secs(35)).await;
+        assert_eq!(3, runs.load(Ordering::SeqCst));
+
+        // the fourth run is in progress, and finishes
+        tokio::time::sleep(Duration::from_millis(8500)).await;
+        handle.shutdown().await;
+        assert_eq!(4, runs.load(Ordering::SeqCst));
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        assert_eq!(4, runs.load(Ordering::SeqCst));
+
+        Ok(())
+    }
+}
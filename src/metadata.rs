@@ -1,13 +1,132 @@
+use crate::StorageId;
 use crate::StorageItem;
+use crate::StorageLock;
+use chrono::DateTime;
+use chrono::Utc;
+use color_eyre::eyre::Result;
 use core::marker::PhantomData;
+use serde::Deserialize;
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::future::Future;
 use std::sync::Arc;
+use std::sync::Mutex;
 use std::sync::RwLock;
+use std::time::Duration;
+
+/// How often changed metadata is written to the backend
+#[cfg(feature = "metadata")]
+pub(crate) const METADATA_FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The metadata as stored in the backend, ids are kept as strings.
+#[cfg(feature = "metadata")]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+struct PersistedMetadata {
+    highest_seen_id: Option<String>,
+    /// Unknown until the backend counted all items once
+    #[serde(default)]
+    item_count: Option<u64>,
+    /// Unknown until the backend counted all items once
+    #[serde(default)]
+    total_bytes: Option<u64>,
+    #[serde(default)]
+    last_write: Option<DateTime<Utc>>,
+}
+
+#[cfg(feature = "metadata")]
+#[derive(Debug, Default)]
+struct Persisted {
+    metadata: PersistedMetadata,
+    dirty: bool,
+    flushing: bool,
+}
+
+/// All tracked metadata at one point in time, e.g. to dump via an admin endpoint.
+///
+/// See [crate::Storage::metadata_snapshot].
+#[cfg(feature = "metadata")]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MetadataSnapshot {
+    pub highest_seen_id: Option<String>,
+    pub item_count: Option<u64>,
+    pub total_bytes: Option<u64>,
+    pub last_write: Option<DateTime<Utc>>,
+    pub lock_stats: LockStats,
+}
+
+/// Lock statistics of a single storage instance, since it was created.
+#[cfg(feature = "metadata")]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockStats {
+    /// Locks acquired and not yet released by this instance
+    pub held: u64,
+    pub acquisitions: u64,
+    /// Lock attempts that failed, because the item was already locked
+    pub contentions: u64,
+    /// The longest time a lock was held before it was released
+    pub longest_held: Option<Duration>,
+    /// The item with the longest held lock
+    pub longest_held_id: Option<String>,
+    /// How long locks were held before they were released
+    #[serde(default)]
+    pub hold_times: LockHoldTimes,
+    /// When the oldest lock, still held by this instance, was taken, e.g. to alert on stuck locks
+    #[serde(default)]
+    pub oldest_held_since: Option<DateTime<Utc>>,
+}
+
+/// Upper bounds of the buckets of [LockHoldTimes], longer holds go into one more bucket.
+#[cfg(feature = "metadata")]
+pub const LOCK_HOLD_BUCKETS: [Duration; 5] = [
+    Duration::from_millis(10),
+    Duration::from_millis(100),
+    Duration::from_secs(1),
+    Duration::from_secs(10),
+    Duration::from_secs(60),
+];
+
+/// The distribution of lock hold times, from acquisition to release, e.g. to find code holding locks across slow calls.
+#[cfg(feature = "metadata")]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockHoldTimes {
+    /// Released locks
+    pub count: u64,
+    pub total: Duration,
+    /// Released locks per bucket of [LOCK_HOLD_BUCKETS], the last bucket counts longer holds
+    pub buckets: [u64; LOCK_HOLD_BUCKETS.len() + 1],
+    /// Locks held longer than the maximum hold time, see e.g. [crate::StorageDisk::set_max_lock_hold_time]
+    pub too_long: u64,
+}
+
+#[cfg(feature = "metadata")]
+impl LockHoldTimes {
+    pub fn mean(&self) -> Option<Duration> {
+        let count = u32::try_from(self.count).ok().filter(|c| *c > 0)?;
+        Some(self.total / count)
+    }
+
+    fn record(&mut self, held_for: Duration) {
+        self.count += 1;
+        self.total = self.total.saturating_add(held_for);
+        let bucket = LOCK_HOLD_BUCKETS
+            .iter()
+            .position(|bound| held_for < *bound)
+            .unwrap_or(LOCK_HOLD_BUCKETS.len());
+        self.buckets[bucket] += 1;
+    }
+}
 
 #[cfg(feature = "metadata")]
 #[derive(Debug, Default)]
 pub(crate) struct Metadata<ITEM: StorageItem> {
     item_type: PhantomData<ITEM>,
     highest_seen_id: Arc<RwLock<Option<ITEM::ID>>>,
+    persisted: Arc<Mutex<Persisted>>,
+    lock_stats: Mutex<LockStats>,
+    /// When the locks, held by this instance, were taken, by id
+    held_since: Mutex<HashMap<String, DateTime<Utc>>>,
+    max_lock_hold_time: Option<Duration>,
 }
 #[cfg(feature = "metadata")]
 impl<ITEM: StorageItem> Metadata<ITEM> {
@@ -16,28 +135,237 @@ impl<ITEM: StorageItem> Metadata<ITEM> {
     }
 
     pub fn update_highest_seen_id(&self, id: &ITEM::ID) {
+        if self.raise_highest_seen_id(id) {
+            let mut persisted = self.persisted.lock().expect("can lock");
+            persisted.metadata.highest_seen_id = Some(id.to_string());
+            persisted.dirty = true;
+        }
+    }
+
+    pub fn item_count(&self) -> Option<u64> {
+        self.persisted.lock().expect("can lock").metadata.item_count
+    }
+
+    pub fn total_bytes(&self) -> Option<u64> {
+        self.persisted
+            .lock()
+            .expect("can lock")
+            .metadata
+            .total_bytes
+    }
+
+    pub fn last_write(&self) -> Option<DateTime<Utc>> {
+        self.persisted.lock().expect("can lock").metadata.last_write
+    }
+
+    /// Records a save of `len` bytes, replacing `previous_len` bytes, or a new item for `None`.
+    /// Totals are only adjusted once they are known, see [Metadata::record_totals].
+    pub fn record_save(&self, previous_len: Option<u64>, len: u64) {
+        let mut persisted = self.persisted.lock().expect("can lock");
+        let metadata = &mut persisted.metadata;
+        metadata.last_write = Some(Utc::now());
+        if previous_len.is_none() {
+            metadata.item_count = metadata.item_count.map(|c| c + 1);
+        }
+        let previous_len = previous_len.unwrap_or_default();
+        metadata.total_bytes = metadata
+            .total_bytes
+            .map(|b| (b + len).saturating_sub(previous_len));
+        persisted.dirty = true;
+    }
+
+    /// Records a write with unknown size, e.g. when the backend doesn't return the previous size.
+    pub fn record_write(&self) {
+        let mut persisted = self.persisted.lock().expect("can lock");
+        persisted.metadata.last_write = Some(Utc::now());
+        persisted.dirty = true;
+    }
+
+    /// Records the removal of an item of `len` bytes.
+    pub fn record_remove(&self, len: u64) {
+        let mut persisted = self.persisted.lock().expect("can lock");
+        let metadata = &mut persisted.metadata;
+        metadata.last_write = Some(Utc::now());
+        metadata.item_count = metadata.item_count.map(|c| c.saturating_sub(1));
+        metadata.total_bytes = metadata.total_bytes.map(|b| b.saturating_sub(len));
+        persisted.dirty = true;
+    }
+
+    /// Records exact totals, e.g. after the backend counted all items.
+    pub fn record_totals(&self, item_count: u64, total_bytes: Option<u64>) {
+        let mut persisted = self.persisted.lock().expect("can lock");
+        let metadata = &mut persisted.metadata;
+        if metadata.item_count != Some(item_count)
+            || (total_bytes.is_some() && metadata.total_bytes != total_bytes)
+        {
+            metadata.item_count = Some(item_count);
+            metadata.total_bytes = total_bytes.or(metadata.total_bytes);
+            persisted.dirty = true;
+        }
+    }
+
+    pub fn lock_stats(&self) -> LockStats {
+        let oldest_held_since = self
+            .held_since
+            .lock()
+            .expect("can lock")
+            .values()
+            .min()
+            .copied();
+        LockStats {
+            oldest_held_since,
+            ..self.lock_stats.lock().expect("can lock").clone()
+        }
+    }
+
+    pub fn record_lock_acquired(&self, id: &ITEM::ID, lock: &StorageLock) {
+        self.held_since
+            .lock()
+            .expect("can lock")
+            .insert(id.to_string(), *lock.when());
+        let mut lock_stats = self.lock_stats.lock().expect("can lock");
+        lock_stats.held += 1;
+        lock_stats.acquisitions += 1;
+    }
+
+    pub fn record_lock_contended(&self) {
+        self.lock_stats.lock().expect("can lock").contentions += 1;
+    }
+
+    /// Locks held longer than this are logged and counted in [LockHoldTimes::too_long].
+    pub fn set_max_lock_hold_time(&mut self, max_lock_hold_time: Option<Duration>) {
+        self.max_lock_hold_time = max_lock_hold_time;
+    }
+
+    pub fn record_lock_released(&self, id: &ITEM::ID, lock: &StorageLock, now: DateTime<Utc>) {
+        let held_for = now
+            .signed_duration_since(*lock.when())
+            .to_std()
+            .unwrap_or_default();
+        self.held_since
+            .lock()
+            .expect("can lock")
+            .remove(&id.to_string());
+        let too_long = self.max_lock_hold_time.is_some_and(|max| held_for > max);
+        if too_long {
+            tracing::warn!(
+                "{id} was locked by {} for {held_for:?}, longer than {:?}",
+                lock.who(),
+                self.max_lock_hold_time.unwrap_or_default()
+            );
+        }
+        let mut lock_stats = self.lock_stats.lock().expect("can lock");
+        lock_stats.held = lock_stats.held.saturating_sub(1);
+        lock_stats.hold_times.record(held_for);
+        if too_long {
+            lock_stats.hold_times.too_long += 1;
+        }
+        if lock_stats
+            .longest_held
+            .is_none_or(|longest| held_for > longest)
+        {
+            lock_stats.longest_held = Some(held_for);
+            lock_stats.longest_held_id = Some(id.to_string());
+        }
+    }
+
+    /// Returns true if `id` is the new highest seen id
+    fn raise_highest_seen_id(&self, id: &ITEM::ID) -> bool {
         let highest_seen_id = self.highest_seen_id.read().expect("can read lock");
         tracing::debug!("update_highest_seen_id: '{id}' >? '{highest_seen_id:?}'");
-        let higher = if let Some(highest_seen_id) = &*highest_seen_id {
-            // :HACK to ensure we compare numbers correctly
-            let higher = *id > *highest_seen_id;
-            /*
-            let higher = match (id.parse::<u64>(), highest_seen_id.parse::<u64>()) {
-                (Ok(a), Ok(b)) => a > b,
-                _ => *id > **highest_seen_id,
-            };
-            */
-            higher
-        } else {
-            true
+        let higher = match &*highest_seen_id {
+            Some(highest_seen_id) => id.id_cmp(highest_seen_id) == Ordering::Greater,
+            None => true,
         };
 
-        if higher {
-            drop(highest_seen_id);
-            tracing::debug!("Updating to {id}");
-            let mut highest_seen_id = self.highest_seen_id.write().expect("can write lock");
-            //*highest_seen_id = id.to_string();
-            *highest_seen_id = Some(id.to_owned());
+        if !higher {
+            return false;
+        }
+        drop(highest_seen_id);
+        let mut highest_seen_id = self.highest_seen_id.write().expect("can write lock");
+        // somebody else might have raised it in the meantime
+        if highest_seen_id
+            .as_ref()
+            .is_some_and(|highest_seen_id| id.id_cmp(highest_seen_id) != Ordering::Greater)
+        {
+            return false;
+        }
+        tracing::debug!("Updating to {id}");
+        *highest_seen_id = Some(id.to_owned());
+
+        true
+    }
+
+    /// Merges metadata previously written by [Metadata::take_dirty], e.g. on start.
+    pub fn restore(&self, data: &[u8]) -> Result<()> {
+        let restored: PersistedMetadata = serde_json::from_slice(data)?;
+        let mut persisted = self.persisted.lock().expect("can lock");
+        if let Some(id) = &restored.highest_seen_id {
+            let id = ITEM::ID::from_string(id)?;
+            if self.raise_highest_seen_id(&id) {
+                persisted.metadata.highest_seen_id = restored.highest_seen_id;
+            }
+        }
+        // anything recorded since the start is newer
+        let metadata = &mut persisted.metadata;
+        metadata.item_count = metadata.item_count.or(restored.item_count);
+        metadata.total_bytes = metadata.total_bytes.or(restored.total_bytes);
+        metadata.last_write = metadata.last_write.max(restored.last_write);
+
+        Ok(())
+    }
+
+    /// Returns the serialized metadata if it changed since the last call.
+    pub fn take_dirty(&self) -> Result<Option<Vec<u8>>> {
+        take_dirty(&self.persisted)
+    }
+
+    /// Writes changed metadata via `flush` every [METADATA_FLUSH_INTERVAL], starting one interval from now,
+    /// until the metadata is dropped. Only the first call starts flushing.
+    pub fn spawn_flush<F, Fut>(&self, flush: F)
+    where
+        F: Fn(Vec<u8>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send,
+    {
+        {
+            let mut persisted = self.persisted.lock().expect("can lock");
+            if persisted.flushing {
+                return;
+            }
+            persisted.flushing = true;
         }
+        let persisted = Arc::downgrade(&self.persisted);
+        tokio::spawn(async move {
+            let start = tokio::time::Instant::now() + METADATA_FLUSH_INTERVAL;
+            let mut interval = tokio::time::interval_at(start, METADATA_FLUSH_INTERVAL);
+            loop {
+                interval.tick().await;
+                let Some(persisted) = persisted.upgrade() else {
+                    break;
+                };
+                match take_dirty(&persisted) {
+                    Ok(Some(data)) => {
+                        if let Err(e) = flush(data).await {
+                            tracing::warn!("Flushing metadata failed, retrying later -> {e:?}");
+                            persisted.lock().expect("can lock").dirty = true;
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(e) => tracing::warn!("Serializing metadata failed -> {e:?}"),
+                }
+            }
+        });
     }
 }
+
+#[cfg(feature = "metadata")]
+fn take_dirty(persisted: &Mutex<Persisted>) -> Result<Option<Vec<u8>>> {
+    let mut persisted = persisted.lock().expect("can lock");
+    if !persisted.dirty {
+        return Ok(None);
+    }
+    let data = serde_json::to_vec_pretty(&persisted.metadata)?;
+    persisted.dirty = false;
+
+    Ok(Some(data))
+}
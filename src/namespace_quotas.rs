@@ -0,0 +1,712 @@
+use crate::LockResult;
+use crate::OpOptions;
+use crate::Page;
+use crate::Storage;
+use crate::StorageError;
+use crate::StorageItem;
+use crate::StorageLock;
+use async_trait::async_trait;
+use chrono::DateTime;
+use chrono::Utc;
+use color_eyre::eyre::Result;
+use core::marker::PhantomData;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+const LIST_PAGE_SIZE: usize = 1000;
+
+/// Limits for the items of one namespace, see [StorageNamespaceQuotas::set_quota].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct NamespaceQuota {
+    pub max_bytes: Option<u64>,
+    pub max_items: Option<usize>,
+}
+
+impl NamespaceQuota {
+    fn is_exceeded(&self, usage: &NamespaceUsage) -> bool {
+        self.max_bytes
+            .is_some_and(|max_bytes| usage.bytes > max_bytes)
+            || self
+                .max_items
+                .is_some_and(|max_items| usage.items > max_items)
+    }
+}
+
+/// What the items of one namespace take up, in serialized bytes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct NamespaceUsage {
+    pub items: usize,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Default)]
+struct Tracked {
+    sizes: HashMap<String, u64>,
+    usage: BTreeMap<String, NamespaceUsage>,
+}
+
+impl Tracked {
+    fn set(&mut self, namespace: &str, id: String, size: Option<u64>) {
+        let previous = match size {
+            Some(size) => self.sizes.insert(id, size),
+            None => self.sizes.remove(&id),
+        };
+        let usage = self.usage.entry(namespace.to_string()).or_default();
+        if let Some(previous) = previous {
+            usage.items -= 1;
+            usage.bytes -= previous;
+        }
+        if let Some(size) = size {
+            usage.items += 1;
+            usage.bytes += size;
+        }
+        if usage.items == 0 {
+            self.usage.remove(namespace);
+        }
+    }
+}
+
+/// Wraps any [Storage] and tracks item count and bytes per namespace, e.g. per tenant,
+/// enforcing per namespace quotas at save time.
+///
+/// The namespace is the part of the id before the first separator, `/` by default, matching [crate::CompositeId].
+/// Ids without the separator belong to the `""` namespace.
+///
+/// Saves that would exceed the quota fail with [StorageError::NamespaceQuotaExceeded], before reaching the backend.
+///
+/// Note: Usage only covers items saved via this wrapper, call [StorageNamespaceQuotas::refresh_usage] to include existing ones.
+/// Sizes are the serialized size, see [StorageItem::serialize], not what the backend stores, e.g. after compression.
+#[derive(Debug)]
+pub struct StorageNamespaceQuotas<ITEM: StorageItem, S: Storage<ITEM>>
+where
+    ITEM: Send,
+{
+    storage: S,
+    separator: char,
+    default_quota: NamespaceQuota,
+    quotas: HashMap<String, NamespaceQuota>,
+    tracked: Mutex<Tracked>,
+    item_type: PhantomData<ITEM>,
+}
+
+impl<ITEM: StorageItem + Send, S: Storage<ITEM>> StorageNamespaceQuotas<ITEM, S> {
+    pub fn new(storage: S) -> Self {
+        Self {
+            storage,
+            separator: '/',
+            default_quota: NamespaceQuota::default(),
+            quotas: HashMap::new(),
+            tracked: Mutex::new(Tracked::default()),
+            item_type: PhantomData,
+        }
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    pub fn into_inner(self) -> S {
+        self.storage
+    }
+
+    pub fn set_separator(&mut self, separator: char) -> Result<()> {
+        self.separator = separator;
+        Ok(())
+    }
+
+    /// The quota for namespaces without their own, unlimited by default.
+    pub fn set_default_quota(&mut self, quota: NamespaceQuota) -> Result<()> {
+        self.default_quota = quota;
+        Ok(())
+    }
+
+    pub fn set_quota(&mut self, namespace: &str, quota: NamespaceQuota) -> Result<()> {
+        self.quotas.insert(namespace.to_string(), quota);
+        Ok(())
+    }
+
+    pub fn quota(&self, namespace: &str) -> NamespaceQuota {
+        self.quotas
+            .get(namespace)
+            .copied()
+            .unwrap_or(self.default_quota)
+    }
+
+    pub fn namespace_of<'a>(&self, id: &'a str) -> &'a str {
+        id.split_once(self.separator)
+            .map(|(namespace, _)| namespace)
+            .unwrap_or("")
+    }
+
+    pub fn usage(&self, namespace: &str) -> NamespaceUsage {
+        self.tracked
+            .lock()
+            .expect("can lock")
+            .usage
+            .get(namespace)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// The usage of every namespace with items, ordered by namespace.
+    pub fn usages(&self) -> BTreeMap<String, NamespaceUsage> {
+        self.tracked.lock().expect("can lock").usage.clone()
+    }
+
+    /// Replaces the tracked usage with what the backend has, via [Storage::list].
+    ///
+    /// Sizes come from [Storage::list] or [Storage::item_size] for backends that don't report them.
+    pub async fn refresh_usage(&self) -> Result<()> {
+        let mut tracked = Tracked::default();
+        let mut start = None;
+        loop {
+            let page = self
+                .storage
+                .list(start.as_deref(), Some(LIST_PAGE_SIZE))
+                .await?;
+            for summary in page.items {
+                let size = match summary.size {
+                    Some(size) => size,
+                    None => self.storage.item_size(&summary.id).await?,
+                };
+                let id = summary.id.to_string();
+                tracked.set(self.namespace_of(&id), id.clone(), Some(size));
+            }
+            start = page.cursor;
+            if start.is_none() {
+                break;
+            }
+        }
+        *self.tracked.lock().expect("can lock") = tracked;
+
+        Ok(())
+    }
+
+    /// Records `size` for `id` if it fits the quota of its namespace,
+    /// returning the previous size, to [Self::restore] it if the save fails.
+    fn reserve(&self, id: &ITEM::ID, size: u64) -> Result<Option<u64>> {
+        let id = id.to_string();
+        let namespace = self.namespace_of(&id);
+        let quota = self.quota(namespace);
+        let mut tracked = self.tracked.lock().expect("can lock");
+        let previous = tracked.sizes.get(&id).copied();
+        let mut usage = tracked.usage.get(namespace).copied().unwrap_or_default();
+        match previous {
+            Some(previous) => usage.bytes = usage.bytes - previous + size,
+            None => {
+                usage.items += 1;
+                usage.bytes += size;
+            }
+        }
+        if quota.is_exceeded(&usage) {
+            return Err(StorageError::NamespaceQuotaExceeded {
+                namespace: namespace.to_string(),
+                bytes: usage.bytes,
+                max_bytes: quota.max_bytes,
+                items: usage.items,
+                max_items: quota.max_items,
+            }
+            .into());
+        }
+        tracked.set(namespace, id.clone(), Some(size));
+
+        Ok(previous)
+    }
+
+    fn restore(&self, id: &ITEM::ID, previous: Option<u64>) {
+        let id = id.to_string();
+        let namespace = self.namespace_of(&id).to_string();
+        self.tracked
+            .lock()
+            .expect("can lock")
+            .set(&namespace, id, previous);
+    }
+
+    async fn saving<F>(&self, id: &ITEM::ID, size: usize, save: F) -> Result<()>
+    where
+        F: std::future::Future<Output = Result<()>>,
+    {
+        let previous = self.reserve(id, size as u64)?;
+        let result = save.await;
+        if result.is_err() {
+            self.restore(id, previous);
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl<ITEM: StorageItem + Send, S: Storage<ITEM>> Storage<ITEM> for StorageNamespaceQuotas<ITEM, S> {
+    async fn ensure_storage_exists(&mut self) -> Result<()> {
+        self.storage.ensure_storage_exists().await
+    }
+
+    fn capabilities(&self) -> crate::Capabilities {
+        self.storage.capabilities()
+    }
+
+    async fn create(&self) -> Result<ITEM::ID> {
+        self.storage.create().await
+    }
+
+    async fn exists(&self, id: &ITEM::ID) -> Result<bool> {
+        self.storage.exists(id).await
+    }
+
+    async fn exists_with(&self, id: &ITEM::ID, options: &OpOptions) -> Result<bool> {
+        self.storage.exists_with(id, options).await
+    }
+
+    async fn load(&self, id: &ITEM::ID) -> Result<ITEM> {
+        self.storage.load(id).await
+    }
+
+    async fn load_with(&self, id: &ITEM::ID, options: &OpOptions) -> Result<ITEM> {
+        self.storage.load_with(id, options).await
+    }
+
+    async fn load_many(&self, ids: &[ITEM::ID]) -> Result<Vec<Option<ITEM>>> {
+        self.storage.load_many(ids).await
+    }
+
+    async fn prefetch(&self, ids: &[ITEM::ID]) -> Result<()> {
+        self.storage.prefetch(ids).await
+    }
+
+    async fn save(&self, id: &ITEM::ID, item: &ITEM, lock: &StorageLock) -> Result<()> {
+        let size = item.serialize()?.len();
+        self.saving(id, size, self.storage.save(id, item, lock))
+            .await
+    }
+
+    async fn lock(&self, id: &ITEM::ID, who: &str) -> Result<LockResult<ITEM>> {
+        self.storage.lock(id, who).await
+    }
+
+    async fn lock_and_load_many(
+        &self,
+        ids: &[ITEM::ID],
+        who: &str,
+    ) -> Result<Vec<crate::LockManyOutcome<ITEM>>> {
+        self.storage.lock_and_load_many(ids, who).await
+    }
+
+    async fn unlock(&self, id: &ITEM::ID, lock: StorageLock) -> Result<()> {
+        self.storage.unlock(id, lock).await
+    }
+
+    async fn handoff_lock(
+        &self,
+        id: &ITEM::ID,
+        lock: StorageLock,
+        new_who: &str,
+    ) -> Result<StorageLock> {
+        self.storage.handoff_lock(id, lock, new_who).await
+    }
+
+    async fn save_and_unlock(&self, id: &ITEM::ID, item: &ITEM, lock: StorageLock) -> Result<()> {
+        let size = item.serialize()?.len();
+        self.saving(id, size, self.storage.save_and_unlock(id, item, lock))
+            .await
+    }
+
+    async fn load_raw(&self, id: &ITEM::ID) -> Result<Vec<u8>> {
+        self.storage.load_raw(id).await
+    }
+
+    async fn save_raw(&self, id: &ITEM::ID, data: &[u8], lock: &StorageLock) -> Result<()> {
+        self.saving(id, data.len(), self.storage.save_raw(id, data, lock))
+            .await
+    }
+
+    async fn put_blob(
+        &self,
+        id: &ITEM::ID,
+        name: &str,
+        data: &[u8],
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.storage.put_blob(id, name, data, lock).await
+    }
+
+    async fn get_blob(&self, id: &ITEM::ID, name: &str) -> Result<Option<Vec<u8>>> {
+        self.storage.get_blob(id, name).await
+    }
+
+    async fn list_blobs(&self, id: &ITEM::ID) -> Result<Vec<String>> {
+        self.storage.list_blobs(id).await
+    }
+
+    async fn put_meta(&self, key: &str, value: &[u8]) -> Result<()> {
+        self.storage.put_meta(key, value).await
+    }
+
+    async fn get_meta(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.storage.get_meta(key).await
+    }
+
+    async fn add_alias(&self, alias: &ITEM::ID, canonical: &ITEM::ID) -> Result<()> {
+        self.storage.add_alias(alias, canonical).await
+    }
+
+    async fn remove_alias(&self, alias: &ITEM::ID) -> Result<()> {
+        self.storage.remove_alias(alias).await
+    }
+
+    async fn resolve_alias(&self, id: &ITEM::ID) -> Result<ITEM::ID> {
+        self.storage.resolve_alias(id).await
+    }
+
+    async fn add_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.storage.add_link(from, relation, to, lock).await
+    }
+
+    async fn remove_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.storage.remove_link(from, relation, to, lock).await
+    }
+
+    async fn links_of(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        self.storage.links_of(id, relation).await
+    }
+
+    async fn linked_from(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        self.storage.linked_from(id, relation).await
+    }
+
+    async fn force_unlock(&self, id: &ITEM::ID) -> Result<Option<StorageLock>> {
+        self.storage.force_unlock(id).await
+    }
+
+    async fn force_unlock_if_held(
+        &self,
+        id: &ITEM::ID,
+        who: &str,
+        when: DateTime<Utc>,
+    ) -> Result<bool> {
+        self.storage.force_unlock_if_held(id, who, when).await
+    }
+
+    async fn remove_orphaned_locks(&self, max_age: Duration) -> Result<usize> {
+        self.storage.remove_orphaned_locks(max_age).await
+    }
+
+    async fn verify_lock(&self, id: &ITEM::ID, lock: &StorageLock) -> Result<bool> {
+        self.storage.verify_lock(id, lock).await
+    }
+
+    async fn verify_lock_with(
+        &self,
+        id: &ITEM::ID,
+        lock: &StorageLock,
+        options: &OpOptions,
+    ) -> Result<bool> {
+        self.storage.verify_lock_with(id, lock, options).await
+    }
+
+    async fn all_ids(&self) -> Result<Vec<ITEM::ID>> {
+        self.storage.all_ids().await
+    }
+
+    async fn scan_ids(&self, start: Option<&str>, limit: Option<usize>) -> Result<Page<ITEM::ID>> {
+        self.storage.scan_ids(start, limit).await
+    }
+
+    async fn scan_ids_with_prefix(
+        &self,
+        prefix: &str,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.storage
+            .scan_ids_with_prefix(prefix, start, limit)
+            .await
+    }
+
+    async fn scan_ids_filtered(
+        &self,
+        filter: &crate::IdFilter,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.storage.scan_ids_filtered(filter, start, limit).await
+    }
+
+    async fn scan_ids_between(
+        &self,
+        from: &ITEM::ID,
+        to: &ITEM::ID,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.storage.scan_ids_between(from, to, start, limit).await
+    }
+
+    async fn list(
+        &self,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<crate::ItemSummary<ITEM::ID>>> {
+        self.storage.list(start, limit).await
+    }
+
+    async fn item_size(&self, id: &ITEM::ID) -> Result<u64> {
+        self.storage.item_size(id).await
+    }
+
+    async fn estimate_cost(&self) -> Result<crate::StorageCost> {
+        self.storage.estimate_cost().await
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        self.storage.shutdown().await
+    }
+
+    async fn fsck(&self, options: &crate::FsckOptions) -> Result<crate::FsckReport> {
+        self.storage.fsck(options).await
+    }
+
+    async fn display_lock(&self, id: &ITEM::ID) -> Result<String> {
+        self.storage.display_lock(id).await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_highest_seen_id(&self) -> Option<ITEM::ID> {
+        self.storage.metadata_highest_seen_id().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_item_count(&self) -> Option<u64> {
+        self.storage.metadata_item_count().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_total_bytes(&self) -> Option<u64> {
+        self.storage.metadata_total_bytes().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_last_write(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.storage.metadata_last_write().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_lock_stats(&self) -> crate::LockStats {
+        self.storage.metadata_lock_stats().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_snapshot(&self) -> crate::MetadataSnapshot {
+        self.storage.metadata_snapshot().await
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn wipe(
+        &self,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        let report = self.storage.wipe(options, progress, cancel).await?;
+        if !options.is_dry_run() {
+            *self.tracked.lock().expect("can lock") = Tracked::default();
+        }
+
+        Ok(report)
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn wipe_matching(
+        &self,
+        filter: &crate::IdFilter,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        let report = self
+            .storage
+            .wipe_matching(filter, options, progress, cancel)
+            .await?;
+        if !options.is_dry_run() {
+            // filters can depend on when items were saved, which isn't tracked
+            self.refresh_usage().await?;
+        }
+
+        Ok(report)
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn delete_many(
+        &self,
+        ids: &[ITEM::ID],
+        options: &crate::DeleteOptions,
+    ) -> Result<Vec<crate::DeleteOutcome>> {
+        let outcomes = self.storage.delete_many(ids, options).await?;
+        for (id, outcome) in ids.iter().zip(outcomes.iter()) {
+            if *outcome == crate::DeleteOutcome::Deleted {
+                self.restore(id, None);
+            }
+        }
+
+        Ok(outcomes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_item::TestItem;
+    use crate::MockResponse;
+    use crate::NamespaceQuota;
+    use crate::NamespaceUsage;
+    use crate::Storage;
+    use crate::StorageError;
+    use crate::StorageMemory;
+    use crate::StorageMock;
+    use crate::StorageNamespaceQuotas;
+    use crate::StorageOperation;
+    use color_eyre::eyre::eyre;
+    use color_eyre::Result;
+
+    /// Saved as `{"count":N}`, i.e. 11 bytes for counts below 10, 12 below 100, and so on.
+    async fn save(
+        storage: &StorageNamespaceQuotas<TestItem, StorageMemory<TestItem>>,
+        id: &str,
+        count: u32,
+    ) -> Result<()> {
+        let id = id.to_string();
+        let (lock, _) = storage.lock(&id, "test").await?.success()?;
+        storage
+            .save_and_unlock(&id, &TestItem { count }, lock)
+            .await
+    }
+
+    #[tokio::test]
+    async fn it_enforces_namespace_quotas() -> Result<()> {
+        let mut storage = StorageNamespaceQuotas::new(StorageMemory::<TestItem>::default());
+        storage.set_quota(
+            "a",
+            NamespaceQuota {
+                max_bytes: None,
+                max_items: Some(2),
+            },
+        )?;
+        storage.set_default_quota(NamespaceQuota {
+            max_bytes: Some(20),
+            max_items: None,
+        })?;
+
+        save(&storage, "a/1", 100).await?;
+        save(&storage, "a/2", 100).await?;
+        let e = save(&storage, "a/3", 1).await.expect_err("a is full");
+        assert_eq!(
+            Some(&StorageError::NamespaceQuotaExceeded {
+                namespace: String::from("a"),
+                bytes: 37,
+                max_bytes: None,
+                items: 3,
+                max_items: Some(2),
+            }),
+            e.downcast_ref::<StorageError>()
+        );
+        // replacing an item doesn't add one
+        save(&storage, "a/2", 50).await?;
+        assert_eq!(
+            NamespaceUsage {
+                items: 2,
+                bytes: 25
+            },
+            storage.usage("a")
+        );
+
+        save(&storage, "b/1", 1).await?;
+        let e = save(&storage, "b/2", 1).await.expect_err("b is full");
+        assert!(matches!(
+            e.downcast_ref::<StorageError>(),
+            Some(StorageError::NamespaceQuotaExceeded { bytes: 22, .. })
+        ));
+        assert_eq!(
+            NamespaceUsage {
+                items: 1,
+                bytes: 11
+            },
+            storage.usage("b")
+        );
+
+        save(&storage, "1", 10).await?;
+        assert_eq!(3, storage.usages().len());
+
+        // existing items are picked up
+        let mut storage = StorageNamespaceQuotas::new(storage.into_inner());
+        assert_eq!(NamespaceUsage::default(), storage.usage("a"));
+        storage.refresh_usage().await?;
+        assert_eq!(
+            NamespaceUsage {
+                items: 2,
+                bytes: 25
+            },
+            storage.usage("a")
+        );
+        assert_eq!(
+            NamespaceUsage {
+                items: 1,
+                bytes: 11
+            },
+            storage.usage("b")
+        );
+        assert_eq!(
+            NamespaceUsage {
+                items: 1,
+                bytes: 12
+            },
+            storage.usage("")
+        );
+        storage.set_separator('-')?;
+        assert_eq!("a/1", storage.namespace_of("a/1-2"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_releases_usage_of_failed_saves() -> Result<()> {
+        let mut storage = StorageNamespaceQuotas::new(StorageMock::<TestItem>::default());
+        storage.set_quota(
+            "a",
+            NamespaceQuota {
+                max_bytes: None,
+                max_items: Some(1),
+            },
+        )?;
+        let id = String::from("a/1");
+        let (lock, _) = storage.lock(&id, "test").await?.success()?;
+
+        storage
+            .storage()
+            .script(StorageOperation::Save, MockResponse::Err(eyre!("down")));
+        let item = TestItem { count: 1 };
+        assert!(storage.save(&id, &item, &lock).await.is_err());
+        assert_eq!(NamespaceUsage::default(), storage.usage("a"));
+        storage.save(&id, &item, &lock).await?;
+        assert_eq!(
+            NamespaceUsage {
+                items: 1,
+                bytes: 11
+            },
+            storage.usage("a")
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,52 @@
+/// How fresh the data read by an operation has to be, see [OpOptions].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Consistency {
+    /// Whatever the storage is configured for
+    #[default]
+    Default,
+    /// The latest write, e.g. a consistent read on DynamoDB, caches are bypassed
+    Strong,
+    /// Stale data is fine, e.g. eventually consistent reads or expired cache entries
+    Eventual,
+}
+
+/// Per call options for [crate::Storage::load_with], [crate::Storage::exists_with],
+/// and [crate::Storage::verify_lock_with], e.g. `OpOptions::default().with_consistency(Consistency::Strong)`.
+///
+/// Backends ignore what doesn't apply to them, e.g. the disk is always consistent.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct OpOptions {
+    consistency: Consistency,
+    bypass_cache: bool,
+}
+
+impl OpOptions {
+    pub fn with_consistency(mut self, consistency: Consistency) -> Self {
+        self.consistency = consistency;
+        self
+    }
+
+    /// Skips caches, e.g. [crate::StorageCached], without asking for strong consistency.
+    pub fn with_bypass_cache(mut self, bypass_cache: bool) -> Self {
+        self.bypass_cache = bypass_cache;
+        self
+    }
+
+    pub fn consistency(&self) -> Consistency {
+        self.consistency
+    }
+
+    /// Also true for [Consistency::Strong].
+    pub fn bypass_cache(&self) -> bool {
+        self.bypass_cache || self.consistency == Consistency::Strong
+    }
+
+    /// Resolves [Consistency::Default] to the storage's own setting.
+    pub fn consistent_read(&self, default: bool) -> bool {
+        match self.consistency {
+            Consistency::Default => default,
+            Consistency::Strong => true,
+            Consistency::Eventual => false,
+        }
+    }
+}
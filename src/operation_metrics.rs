@@ -0,0 +1,179 @@
+//! Counters and histograms recorded from inside the backends via the [metrics] facade,
+//! if the `metrics` feature is enabled, so they include retries and client creation a wrapper can't see.
+//!
+//! All are labelled by `backend`, e.g. `disk` or `dynamodb`:
+//!
+//! - `oml_storage_backend_operations_total` and `oml_storage_backend_operation_seconds`, also by `op`, e.g. `load`
+//! - `oml_storage_backend_lock_contentions_total`
+//! - `oml_storage_backend_retries_total` and `oml_storage_backend_errors_total`, also by `op`,
+//!   the request, e.g. `Load - GetItem`, and by `class`, see [crate::DynamoDbErrorClass::name]
+//! - `oml_storage_backend_client_creation_seconds`
+//!
+//! Install any `metrics` recorder, e.g. an exporter, to collect them. Without the feature nothing is recorded.
+
+#[cfg(feature = "metrics")]
+use std::time::Duration;
+
+#[cfg(feature = "metrics")]
+pub(crate) fn record_operation(backend: &'static str, op: &'static str, duration: Duration) {
+    metrics::counter!("oml_storage_backend_operations_total", "backend" => backend, "op" => op)
+        .increment(1);
+    metrics::histogram!("oml_storage_backend_operation_seconds", "backend" => backend, "op" => op)
+        .record(duration.as_secs_f64());
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn record_lock_contended(backend: &'static str) {
+    metrics::counter!("oml_storage_backend_lock_contentions_total", "backend" => backend)
+        .increment(1);
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn record_retry(backend: &'static str, op: &str, class: &'static str) {
+    let op = op.to_string();
+    metrics::counter!("oml_storage_backend_retries_total", "backend" => backend, "op" => op, "class" => class)
+        .increment(1);
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn record_error(backend: &'static str, op: &str, class: &'static str) {
+    let op = op.to_string();
+    metrics::counter!("oml_storage_backend_errors_total", "backend" => backend, "op" => op, "class" => class)
+        .increment(1);
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn record_client_creation(backend: &'static str, duration: Duration) {
+    metrics::histogram!("oml_storage_backend_client_creation_seconds", "backend" => backend)
+        .record(duration.as_secs_f64());
+}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn record_lock_contended(_backend: &'static str) {}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn record_retry(_backend: &'static str, _op: &str, _class: &'static str) {}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn record_error(_backend: &'static str, _op: &str, _class: &'static str) {}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use crate::Storage;
+    use crate::StorageItem;
+    use crate::StorageMemory;
+    use color_eyre::Result;
+    use metrics::Counter;
+    use metrics::CounterFn;
+    use metrics::Gauge;
+    use metrics::Histogram;
+    use metrics::Key;
+    use metrics::KeyName;
+    use metrics::Metadata;
+    use metrics::Recorder;
+    use metrics::SharedString;
+    use metrics::Unit;
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    #[derive(Default, Debug)]
+    struct TestItem;
+
+    impl StorageItem for TestItem {
+        type ID = String;
+
+        fn serialize(&self) -> Result<Vec<u8>> {
+            Ok(Vec::new())
+        }
+        fn deserialize(_data: &[u8]) -> Result<Self> {
+            Ok(Self)
+        }
+    }
+
+    /// Counts by name and labels, e.g. `oml_storage_backend_operations_total{backend=memory,op=load}`.
+    #[derive(Default)]
+    struct CountingRecorder {
+        counts: Arc<Mutex<BTreeMap<String, u64>>>,
+    }
+
+    struct CountingCounter {
+        name: String,
+        counts: Arc<Mutex<BTreeMap<String, u64>>>,
+    }
+
+    impl CounterFn for CountingCounter {
+        fn increment(&self, value: u64) {
+            *self
+                .counts
+                .lock()
+                .expect("can lock")
+                .entry(self.name.clone())
+                .or_default() += value;
+        }
+        fn absolute(&self, value: u64) {
+            self.counts
+                .lock()
+                .expect("can lock")
+                .insert(self.name.clone(), value);
+        }
+    }
+
+    impl Recorder for CountingRecorder {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+            let labels: Vec<_> = key
+                .labels()
+                .map(|l| format!("{}={}", l.key(), l.value()))
+                .collect();
+            let name = format!("{}{{{}}}", key.name(), labels.join(","));
+            Counter::from_arc(Arc::new(CountingCounter {
+                name,
+                counts: self.counts.clone(),
+            }))
+        }
+        fn register_gauge(&self, _: &Key, _: &Metadata<'_>) -> Gauge {
+            Gauge::noop()
+        }
+        fn register_histogram(&self, _: &Key, _: &Metadata<'_>) -> Histogram {
+            Histogram::noop()
+        }
+    }
+
+    #[test]
+    fn it_records_backend_metrics() -> Result<()> {
+        let recorder = CountingRecorder::default();
+        let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+        metrics::with_local_recorder(&recorder, || {
+            runtime.block_on(async {
+                let storage = StorageMemory::<TestItem>::default();
+                let id = String::from("counted");
+                let (lock, _) = storage.lock(&id, "first").await?.success()?;
+                assert!(storage.lock(&id, "second").await?.success().is_err());
+                storage.save(&id, &TestItem, &lock).await?;
+                storage.unlock(&id, lock).await?;
+                storage.load(&id).await?;
+                Result::<()>::Ok(())
+            })
+        })?;
+
+        let counts = recorder.counts.lock().expect("can lock");
+        let count = |name: &str| counts.get(name).copied().unwrap_or_default();
+        assert_eq!(
+            2,
+            count("oml_storage_backend_operations_total{backend=memory,op=lock}")
+        );
+        assert_eq!(
+            1,
+            count("oml_storage_backend_operations_total{backend=memory,op=load}")
+        );
+        assert_eq!(
+            1,
+            count("oml_storage_backend_lock_contentions_total{backend=memory}")
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,73 @@
+//! Propagates the OpenTelemetry context of the current span into AWS SDK requests, if the `otel` feature is enabled,
+//! so traces continue into DynamoDB, e.g. AWS X-Ray, instead of ending at the storage.
+//!
+//! The context is taken from the `tracing` span via [tracing_opentelemetry],
+//! and injected with the global propagator, see [opentelemetry::global::set_text_map_propagator].
+//! Without a propagator or a `tracing-opentelemetry` layer nothing is injected.
+
+use aws_sdk_dynamodb::config::interceptors::BeforeTransmitInterceptorContextMut;
+use aws_sdk_dynamodb::config::ConfigBag;
+use aws_sdk_dynamodb::config::Intercept;
+use aws_sdk_dynamodb::config::RuntimeComponents;
+use aws_sdk_dynamodb::error::BoxError;
+use opentelemetry::propagation::Injector;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Injects the current OpenTelemetry context into the headers of every request.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct OtelContextInterceptor;
+
+impl Intercept for OtelContextInterceptor {
+    fn name(&self) -> &'static str {
+        "OtelContextInterceptor"
+    }
+
+    fn modify_before_transmit(
+        &self,
+        context: &mut BeforeTransmitInterceptorContextMut<'_>,
+        _runtime_components: &RuntimeComponents,
+        _cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        let otel_context = tracing::Span::current().context();
+        let mut injector = HeaderInjector(context.request_mut().headers_mut());
+        opentelemetry::global::get_text_map_propagator(|propagator| {
+            propagator.inject_context(&otel_context, &mut injector)
+        });
+
+        Ok(())
+    }
+}
+
+struct HeaderInjector<'a>(&'a mut aws_smithy_runtime_api::http::Headers);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        // propagators only use valid header names, and values
+        if let Err(e) = self.0.try_insert(key.to_string(), value) {
+            tracing::warn!("Can't propagate {key} -> {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HeaderInjector;
+    use opentelemetry::propagation::Injector;
+
+    #[test]
+    fn it_skips_invalid_headers() {
+        let mut headers = aws_smithy_runtime_api::http::Headers::new();
+        let mut injector = HeaderInjector(&mut headers);
+        injector.set(
+            "traceparent",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01".to_string(),
+        );
+        injector.set("tracestate", "broken\nvalue".to_string());
+
+        assert_eq!(
+            Some("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"),
+            headers.get("traceparent")
+        );
+        assert_eq!(None, headers.get("tracestate"));
+    }
+}
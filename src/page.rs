@@ -0,0 +1,297 @@
+use crate::Storage;
+use crate::StorageError;
+use crate::StorageId;
+use crate::StorageItem;
+use color_eyre::eyre::Result;
+use core::marker::PhantomData;
+use futures_util::stream;
+use futures_util::Stream;
+use futures_util::TryStreamExt;
+use std::cmp::Ordering;
+use std::future::Future;
+use tokio_util::sync::CancellationToken;
+
+const DEFAULT_PAGE_SIZE: usize = 1000;
+
+/// One page of a scan, see [Storage::scan_ids].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Where the next page starts, `None` after the last page
+    pub cursor: Option<String>,
+    /// How many items the whole scan returns, e.g. for page controls,
+    /// `None` for backends that can't tell cheaply. Approximate, items change while scanning.
+    pub approximate_total: Option<u64>,
+}
+
+impl<T> Page<T> {
+    pub fn new(items: Vec<T>, cursor: Option<String>) -> Self {
+        Self {
+            items,
+            cursor,
+            approximate_total: None,
+        }
+    }
+
+    pub fn with_approximate_total(mut self, approximate_total: u64) -> Self {
+        self.approximate_total = Some(approximate_total);
+        self
+    }
+
+    /// Pages may be empty while the scan continues, only the missing cursor ends it.
+    pub fn is_last(&self) -> bool {
+        self.cursor.is_none()
+    }
+}
+
+/// Follows the cursors of a scan, see [paginate].
+#[derive(Debug)]
+pub struct Paginate<'a, ITEM, S: ?Sized> {
+    storage: &'a S,
+    prefix: String,
+    page_size: usize,
+    cancel: Option<CancellationToken>,
+    item_type: PhantomData<ITEM>,
+}
+
+/// Streams all ids or items of `storage`, e.g. `paginate(&storage).with_prefix("guild/").ids()`.
+pub fn paginate<ITEM, S>(storage: &S) -> Paginate<'_, ITEM, S>
+where
+    ITEM: StorageItem + Send,
+    S: Storage<ITEM> + ?Sized,
+{
+    Paginate {
+        storage,
+        prefix: String::new(),
+        page_size: DEFAULT_PAGE_SIZE,
+        cancel: None,
+        item_type: PhantomData,
+    }
+}
+
+impl<'a, ITEM, S> Paginate<'a, ITEM, S>
+where
+    ITEM: StorageItem + Send + 'a,
+    S: Storage<ITEM> + ?Sized,
+{
+    /// Only ids starting with `prefix`, see [Storage::scan_ids_with_prefix].
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    /// The `limit` of each scan, defaults to 1000.
+    pub fn with_page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size.max(1);
+        self
+    }
+
+    /// Ends the stream with [StorageError::Cancelled] before the next page once `cancel` is cancelled,
+    /// its cursor continues the scan.
+    pub fn with_cancellation(mut self, cancel: &CancellationToken) -> Self {
+        self.cancel = Some(cancel.clone());
+        self
+    }
+
+    pub fn pages(self) -> impl Stream<Item = Result<Page<ITEM::ID>>> + 'a {
+        let Paginate {
+            storage,
+            prefix,
+            page_size,
+            cancel,
+            ..
+        } = self;
+        follow(cancel, move |start| {
+            let prefix = prefix.clone();
+            async move {
+                storage
+                    .scan_ids_with_prefix(&prefix, start.as_deref(), Some(page_size))
+                    .await
+            }
+        })
+    }
+
+    pub fn ids(self) -> impl Stream<Item = Result<ITEM::ID>> + 'a {
+        self.pages()
+            .map_ok(|page| stream::iter(page.items.into_iter().map(Ok)))
+            .try_flatten()
+    }
+
+    /// Loads the items via [Storage::scan_items].
+    pub fn items(self) -> impl Stream<Item = Result<(ITEM::ID, ITEM)>> + 'a {
+        let Paginate {
+            storage,
+            prefix,
+            page_size,
+            cancel,
+            ..
+        } = self;
+        follow(cancel, move |start| {
+            let prefix = prefix.clone();
+            async move {
+                storage
+                    .scan_items(&prefix, start.as_deref(), Some(page_size))
+                    .await
+            }
+        })
+        .map_ok(|page| stream::iter(page.items.into_iter().map(Ok)))
+        .try_flatten()
+    }
+}
+
+/// `id` is from `from`, inclusive, to `to`, exclusive, by [StorageId::id_cmp].
+pub(crate) fn is_between<ID: StorageId>(id: &ID, from: &ID, to: &ID) -> bool {
+    id.id_cmp(from) != Ordering::Less && id.id_cmp(to) == Ordering::Less
+}
+
+/// The page after `start` of the `ids` between `from` and `to`, ordered by [StorageId::id_cmp],
+/// for backends that can list all ids cheaply, see [Storage::scan_ids_between].
+pub(crate) fn page_between<ID: StorageId>(
+    mut ids: Vec<ID>,
+    from: &ID,
+    to: &ID,
+    start: Option<&str>,
+    limit: Option<usize>,
+) -> Result<Page<ID>> {
+    let start = start.map(ID::from_string).transpose()?;
+    ids.retain(|id| is_between(id, from, to));
+    let total = ids.len() as u64;
+    ids.retain(|id| {
+        start
+            .as_ref()
+            .is_none_or(|start| id.id_cmp(start) == Ordering::Greater)
+    });
+    ids.sort_by(|a, b| a.id_cmp(b));
+    let cursor = match limit {
+        Some(limit) if ids.len() > limit => {
+            ids.truncate(limit);
+            ids.last().map(ToString::to_string)
+        }
+        _ => None,
+    };
+
+    Ok(Page::new(ids, cursor).with_approximate_total(total))
+}
+
+/// Calls `fetch` with the cursor of the previous page, until a page has none or `cancel` is cancelled.
+pub(crate) fn follow<'a, T, F, Fut>(
+    cancel: Option<CancellationToken>,
+    fetch: F,
+) -> impl Stream<Item = Result<Page<T>>> + 'a
+where
+    T: 'a,
+    F: FnMut(Option<String>) -> Fut + 'a,
+    Fut: Future<Output = Result<Page<T>>> + 'a,
+{
+    stream::try_unfold(
+        (fetch, Some(None), 0),
+        move |(mut fetch, start, processed)| {
+            let cancelled = cancel.as_ref().is_some_and(|c| c.is_cancelled());
+            async move {
+                let Some(start) = start else {
+                    return Ok(None);
+                };
+                if cancelled {
+                    return Err(StorageError::Cancelled {
+                        processed,
+                        cursor: start,
+                    }
+                    .into());
+                }
+                let page = fetch(start).await?;
+                let processed = processed + page.items.len() as u64;
+                let next = page.cursor.clone().map(Some);
+                Ok(Some((page, (fetch, next, processed))))
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::paginate;
+    use crate::Storage;
+    use crate::StorageError;
+    use crate::StorageItem;
+    use crate::StorageMemory;
+    use color_eyre::Result;
+    use futures_util::TryStreamExt;
+    use tokio_util::sync::CancellationToken;
+
+    #[derive(Default, Debug)]
+    struct TestItem {}
+
+    impl StorageItem for TestItem {
+        type ID = String;
+
+        fn serialize(&self) -> Result<Vec<u8>> {
+            Ok(Vec::new())
+        }
+        fn deserialize(_: &[u8]) -> Result<Self> {
+            Ok(Self {})
+        }
+    }
+
+    #[tokio::test]
+    async fn it_follows_cursors() -> Result<()> {
+        let storage = StorageMemory::<TestItem>::default();
+        let mut expected = Vec::new();
+        for i in 0..5 {
+            let id = format!("a/{i}");
+            let (lock, item) = storage.lock(&id, "TEST").await?.success()?;
+            storage.save_and_unlock(&id, &item, lock).await?;
+            expected.push(id);
+        }
+        let id = String::from("b/0");
+        let (lock, item) = storage.lock(&id, "TEST").await?.success()?;
+        storage.save_and_unlock(&id, &item, lock).await?;
+
+        let ids: Vec<String> = paginate(&storage)
+            .with_prefix("a/")
+            .with_page_size(2)
+            .ids()
+            .try_collect()
+            .await?;
+        assert_eq!(expected, ids);
+
+        let items: Vec<(String, TestItem)> = paginate(&storage)
+            .with_page_size(4)
+            .items()
+            .try_collect()
+            .await?;
+        assert_eq!(6, items.len());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_stops_when_cancelled() -> Result<()> {
+        let storage = StorageMemory::<TestItem>::default();
+        for i in 0..5 {
+            let id = format!("a/{i}");
+            let (lock, item) = storage.lock(&id, "TEST").await?.success()?;
+            storage.save_and_unlock(&id, &item, lock).await?;
+        }
+
+        let cancel = CancellationToken::new();
+        let mut pages = Box::pin(
+            paginate(&storage)
+                .with_page_size(2)
+                .with_cancellation(&cancel)
+                .pages(),
+        );
+        let first = pages.try_next().await?.expect("has a first page");
+        assert_eq!(2, first.items.len());
+        cancel.cancel();
+        let e = pages.try_next().await.expect_err("is cancelled");
+        assert_eq!(
+            Some(&StorageError::Cancelled {
+                processed: 2,
+                cursor: first.cursor,
+            }),
+            e.downcast_ref::<StorageError>()
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+use crate::StorageError;
+use color_eyre::eyre::Result;
+use std::time::Duration;
+use std::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+/// How often a [ProgressTracker] reports to its sink, at most
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A snapshot of a long running operation, e.g. [crate::Storage::wipe].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Progress {
+    /// Items handled so far
+    pub processed: u64,
+    /// `None` if the backend can't tell cheaply, e.g. while scanning DynamoDB
+    pub total_estimate: Option<u64>,
+    /// The last handled item
+    pub current_id: Option<String>,
+}
+
+/// Receives periodic [Progress] updates and a final one when the operation is done.
+///
+/// Implemented for closures, e.g. `&|p: &Progress| println!("{}", p.processed)`.
+pub trait ProgressSink: Send + Sync {
+    fn report(&self, progress: &Progress);
+}
+
+impl<F: Fn(&Progress) + Send + Sync> ProgressSink for F {
+    fn report(&self, progress: &Progress) {
+        self(progress)
+    }
+}
+
+/// Counts handled items and forwards the [Progress] to an optional sink every [REPORT_INTERVAL].
+pub struct ProgressTracker<'a> {
+    sink: Option<&'a dyn ProgressSink>,
+    cancel: Option<&'a CancellationToken>,
+    progress: Progress,
+    last_report: Instant,
+}
+
+impl<'a> ProgressTracker<'a> {
+    pub fn new(sink: Option<&'a dyn ProgressSink>, total_estimate: Option<u64>) -> Self {
+        Self {
+            sink,
+            cancel: None,
+            progress: Progress {
+                total_estimate,
+                ..Default::default()
+            },
+            last_report: Instant::now(),
+        }
+    }
+
+    /// Lets [ProgressTracker::ensure_not_cancelled] fail once `cancel` is cancelled.
+    pub fn with_cancellation(mut self, cancel: Option<&'a CancellationToken>) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    pub fn advance(&mut self, id: &impl ToString) {
+        self.progress.processed += 1;
+        let Some(sink) = self.sink else {
+            return;
+        };
+        self.progress.current_id = Some(id.to_string());
+        if self.last_report.elapsed() >= REPORT_INTERVAL {
+            sink.report(&self.progress);
+            self.last_report = Instant::now();
+        }
+    }
+
+    /// Fails with [StorageError::Cancelled] if cancelled, call it between items.
+    pub fn ensure_not_cancelled(&self) -> Result<()> {
+        if self.cancel.is_some_and(|c| c.is_cancelled()) {
+            if let Some(sink) = self.sink {
+                sink.report(&self.progress);
+            }
+            return Err(StorageError::Cancelled {
+                processed: self.progress.processed,
+                cursor: None,
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Reports the final progress.
+    pub fn finish(self) {
+        if let Some(sink) = self.sink {
+            sink.report(&self.progress);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Progress;
+    use crate::ProgressTracker;
+    use std::sync::Mutex;
+
+    #[test]
+    fn it_reports_the_final_progress() {
+        let reports = Mutex::new(Vec::new());
+        let sink = |p: &Progress| reports.lock().expect("can lock").push(p.clone());
+        let mut tracker = ProgressTracker::new(Some(&sink), Some(2));
+        tracker.advance(&"a");
+        tracker.advance(&"b");
+        tracker.finish();
+
+        let reports = reports.into_inner().expect("can lock");
+        assert_eq!(
+            Some(&Progress {
+                processed: 2,
+                total_estimate: Some(2),
+                current_id: Some(String::from("b")),
+            }),
+            reports.last()
+        );
+    }
+}
@@ -0,0 +1,284 @@
+use crate::Storage;
+use crate::StorageItem;
+use crate::StorageLock;
+use async_trait::async_trait;
+use chrono::DateTime;
+use chrono::Utc;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Why a [QuarantineRecord] was taken.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QuarantineReason {
+    /// Before [Storage::force_unlock] removed the lock
+    #[default]
+    ForceUnlock,
+    /// The stored data failed to deserialize,
+    /// see [crate::StorageWithMiddleware::set_quarantine_corrupt_items]
+    Corrupt,
+}
+
+/// A copy of an item, taken before its lock was removed by [Storage::force_unlock],
+/// in case the writes of the original holder get lost, or of an item that failed to deserialize.
+///
+/// See [crate::StorageWithMiddleware::set_quarantine_sink].
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuarantineRecord {
+    pub when: DateTime<Utc>,
+    pub id: String,
+    /// Records from before reasons were recorded are [QuarantineReason::ForceUnlock]
+    #[serde(default)]
+    pub reason: QuarantineReason,
+    /// The holder of the removed lock, empty for [QuarantineReason::Corrupt]
+    pub displaced_lock_who: String,
+    /// When the removed lock was taken, the epoch for [QuarantineReason::Corrupt]
+    pub displaced_lock_when: DateTime<Utc>,
+    /// The serialized item, `None` for items that were never saved
+    #[serde(with = "base64_data")]
+    pub data: Option<Vec<u8>>,
+}
+
+impl QuarantineRecord {
+    pub fn new(id: &str, displaced_lock: &StorageLock, data: Option<Vec<u8>>) -> Self {
+        Self {
+            when: Utc::now(),
+            id: id.to_string(),
+            reason: QuarantineReason::ForceUnlock,
+            displaced_lock_who: displaced_lock.who().to_string(),
+            displaced_lock_when: *displaced_lock.when(),
+            data,
+        }
+    }
+
+    /// A copy of stored `data` that failed to deserialize.
+    pub fn corrupt(id: &str, data: Vec<u8>) -> Self {
+        Self {
+            when: Utc::now(),
+            id: id.to_string(),
+            reason: QuarantineReason::Corrupt,
+            data: Some(data),
+            ..Default::default()
+        }
+    }
+
+    /// The quarantined item, `None` if it was never saved.
+    pub fn item<ITEM: StorageItem>(&self) -> Result<Option<ITEM>> {
+        self.data.as_deref().map(ITEM::deserialize).transpose()
+    }
+}
+
+impl StorageItem for QuarantineRecord {
+    type ID = String;
+
+    fn serialize(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec_pretty(self)?)
+    }
+
+    fn deserialize(data: &[u8]) -> Result<Self> {
+        Ok(serde_json::from_slice(data)?)
+    }
+}
+
+/// The data of a [QuarantineRecord] is stored as base64.
+mod base64_data {
+    use base64::engine::general_purpose::STANDARD as BASE64;
+    use base64::Engine;
+    use serde::Deserialize;
+    use serde::Deserializer;
+    use serde::Serialize;
+    use serde::Serializer;
+
+    pub fn serialize<S: Serializer>(data: &Option<Vec<u8>>, s: S) -> Result<S::Ok, S::Error> {
+        data.as_ref().map(|data| BASE64.encode(data)).serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Vec<u8>>, D::Error> {
+        Option::<String>::deserialize(d)?
+            .map(|data| BASE64.decode(data).map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
+
+/// Receives a [QuarantineRecord] for every forced unlock and corrupt item if enabled.
+#[async_trait]
+pub trait QuarantineSink: Send + Sync + std::fmt::Debug {
+    async fn quarantine(&self, record: &QuarantineRecord) -> Result<()>;
+}
+
+/// Saves records as items of a separate storage, e.g. a [crate::StorageDisk] folder or a DynamoDB table.
+///
+/// Record ids are the prefix, the item id, and the time, e.g. `{prefix}{id}@20261017T093000.123456Z`.
+#[derive(Debug)]
+pub struct StorageQuarantineSink<S> {
+    storage: S,
+    prefix: String,
+}
+
+impl<S: Storage<QuarantineRecord>> StorageQuarantineSink<S> {
+    pub fn new(storage: S) -> Self {
+        Self {
+            storage,
+            prefix: String::new(),
+        }
+    }
+
+    /// Prepended to all record ids, e.g. to share a storage with other items.
+    pub fn set_prefix(&mut self, prefix: &str) -> Result<()> {
+        self.prefix = prefix.to_string();
+
+        Ok(())
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    pub fn into_inner(self) -> S {
+        self.storage
+    }
+}
+
+#[async_trait]
+impl<S: Storage<QuarantineRecord>> QuarantineSink for StorageQuarantineSink<S> {
+    async fn quarantine(&self, record: &QuarantineRecord) -> Result<()> {
+        let id = format!(
+            "{}{}@{}",
+            self.prefix,
+            record.id,
+            record.when.format("%Y%m%dT%H%M%S%.6fZ")
+        );
+        let (lock, _) = self
+            .storage
+            .lock(&id, "quarantine")
+            .await?
+            .success()
+            .map_err(|e| eyre!("Can't lock quarantine record {id} -> {e:?}"))?;
+        self.storage.save_and_unlock(&id, record, lock).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_item::TestItem;
+    use crate::QuarantineReason;
+    use crate::QuarantineRecord;
+    use crate::Storage;
+    use crate::StorageDisk;
+    use crate::StorageError;
+    use crate::StorageMemory;
+    use crate::StorageQuarantineSink;
+    use crate::StorageWithMiddleware;
+    use color_eyre::Result;
+    use std::env;
+    use std::path::Path;
+
+    #[tokio::test]
+    async fn it_quarantines_force_unlocked_items() -> Result<()> {
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_quarantine");
+        let _ = std::fs::remove_dir_all(&path);
+        let extension = Path::new("json");
+
+        let mut quarantine = StorageDisk::<QuarantineRecord>::new(&path, extension).await;
+        quarantine.ensure_storage_exists().await?;
+        let mut storage = StorageWithMiddleware::new(StorageMemory::<TestItem>::default());
+        storage.set_quarantine_sink(StorageQuarantineSink::new(quarantine))?;
+        storage.ensure_storage_exists().await?;
+
+        let id = String::from("stuck");
+        let (lock, _) = storage.lock(&id, "first").await?.success()?;
+        storage
+            .save_and_unlock(&id, &TestItem { count: 7 }, lock)
+            .await?;
+        let _lock = storage.lock(&id, "crashed").await?.success()?;
+        assert!(storage.force_unlock(&id).await?.is_some());
+        // not locked, nothing to quarantine
+        assert!(storage.force_unlock(&id).await?.is_none());
+
+        let quarantine = StorageDisk::<QuarantineRecord>::new(&path, extension).await;
+        let ids = quarantine.all_ids().await?;
+        assert_eq!(1, ids.len());
+        let record = quarantine.load(&ids[0]).await?;
+        assert_eq!("stuck", record.id);
+        assert_eq!("crashed", record.displaced_lock_who);
+        assert_eq!(Some(TestItem { count: 7 }), record.item()?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_quarantines_corrupt_items() -> Result<()> {
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_quarantine_corrupt");
+        let _ = std::fs::remove_dir_all(&path);
+        let extension = Path::new("json");
+
+        let mut quarantine = StorageDisk::<QuarantineRecord>::new(&path, extension).await;
+        quarantine.ensure_storage_exists().await?;
+        let mut storage = StorageWithMiddleware::new(StorageMemory::<TestItem>::default());
+        storage.set_quarantine_sink(StorageQuarantineSink::new(quarantine))?;
+        storage.ensure_storage_exists().await?;
+
+        let (lock, _) = storage
+            .lock(&String::from("good"), "test")
+            .await?
+            .success()?;
+        storage
+            .save_and_unlock(&String::from("good"), &TestItem { count: 7 }, lock)
+            .await?;
+        let id = String::from("bad");
+        let (lock, _) = storage.lock(&id, "test").await?.success()?;
+        storage.save_raw(&id, b"{ broken", &lock).await?;
+        storage.unlock(&id, lock).await?;
+
+        // not preserved by default
+        let e = storage.load(&id).await.unwrap_err();
+        assert_eq!(
+            Some(&StorageError::Corrupt {
+                id: id.clone(),
+                preserved_at: None,
+            }),
+            e.downcast_ref::<StorageError>()
+        );
+        assert!(storage.lock(&id, "test").await.is_err());
+
+        storage.set_quarantine_corrupt_items(true)?;
+        let e = storage.load(&id).await.unwrap_err();
+        let Some(StorageError::Corrupt {
+            id: corrupt_id,
+            preserved_at,
+        }) = e.downcast_ref()
+        else {
+            panic!("not corrupt -> {e:?}");
+        };
+        assert_eq!(&id, corrupt_id);
+        assert!(preserved_at.is_some());
+        assert_eq!(
+            TestItem { count: 7 },
+            storage.load(&String::from("good")).await?
+        );
+        assert!(matches!(
+            storage
+                .load(&String::from("missing"))
+                .await
+                .unwrap_err()
+                .downcast_ref(),
+            Some(StorageError::NotFound { .. })
+        ));
+
+        let quarantine = StorageDisk::<QuarantineRecord>::new(&path, extension).await;
+        let ids = quarantine.all_ids().await?;
+        assert_eq!(1, ids.len());
+        let record = quarantine.load(&ids[0]).await?;
+        assert_eq!("bad", record.id);
+        assert_eq!(QuarantineReason::Corrupt, record.reason);
+        assert_eq!(Some(b"{ broken".to_vec()), record.data);
+        assert_eq!(Some(record.when), *preserved_at);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,368 @@
+use crate::ItemSummary;
+use crate::Page;
+use crate::Storage;
+use crate::StorageItem;
+use chrono::DateTime;
+use chrono::NaiveDate;
+use chrono::Utc;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use futures_util::stream;
+use futures_util::Stream;
+use futures_util::TryStreamExt;
+use std::cmp::Ordering;
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// A comparison in a [Condition].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CompareOp {
+    fn parse(s: &str) -> Option<Self> {
+        Some(match s {
+            "=" | "==" => Self::Eq,
+            "!=" => Self::Ne,
+            "<" => Self::Lt,
+            "<=" => Self::Le,
+            ">" => Self::Gt,
+            ">=" => Self::Ge,
+            _ => return None,
+        })
+    }
+
+    fn holds(&self, ordering: Ordering) -> bool {
+        match self {
+            Self::Eq => ordering.is_eq(),
+            Self::Ne => ordering.is_ne(),
+            Self::Lt => ordering.is_lt(),
+            Self::Le => ordering.is_le(),
+            Self::Gt => ordering.is_gt(),
+            Self::Ge => ordering.is_ge(),
+        }
+    }
+}
+
+/// One condition of a [Query].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Condition {
+    /// `id prefix "guild:"`
+    IdPrefix(String),
+    /// `id contains "guild"`
+    IdContains(String),
+    /// `id >= "b"`, compared as strings
+    Id(CompareOp, String),
+    /// `modified > 2024-01-01` or an RFC 3339 time, items without a known save time never match
+    Modified(CompareOp, DateTime<Utc>),
+    /// `size > 1024`, the stored size in bytes, items without a known size never match
+    Size(CompareOp, u64),
+    /// `locked = true`
+    Locked(bool),
+}
+
+impl Condition {
+    /// Needs more than the id, i.e. [Storage::list].
+    fn needs_summary(&self) -> bool {
+        matches!(self, Self::Modified(..) | Self::Size(..) | Self::Locked(_))
+    }
+
+    fn matches<ID: Display>(&self, summary: &ItemSummary<ID>) -> bool {
+        match self {
+            Self::IdPrefix(prefix) => summary.id.to_string().starts_with(prefix.as_str()),
+            Self::IdContains(part) => summary.id.to_string().contains(part.as_str()),
+            Self::Id(op, value) => op.holds(summary.id.to_string().as_str().cmp(value)),
+            Self::Modified(op, time) => summary.modified.is_some_and(|m| op.holds(m.cmp(time))),
+            Self::Size(op, size) => summary.size.is_some_and(|s| op.holds(s.cmp(size))),
+            Self::Locked(locked) => summary.is_locked() == *locked,
+        }
+    }
+}
+
+/// A filter for admin tooling, e.g. `id prefix "guild:" AND modified > 2024-01-01`.
+///
+/// Conditions on `id` (`prefix`, `contains`, or compared), `modified`, `size`, and `locked`,
+/// joined by `AND`. Values are quoted strings or single words.
+///
+/// Runs as scans, see [Query::scan], with the longest id prefix pushed down to [Storage::scan_ids_with_prefix],
+/// all other conditions are applied to each page. The default query matches everything.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Query {
+    conditions: Vec<Condition>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Op(CompareOp),
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            let mut s = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => s.extend(chars.next()),
+                    Some(c) => s.push(c),
+                    None => return Err(eyre!("Unterminated string in query {input:?}")),
+                }
+            }
+            tokens.push(Token::Quoted(s));
+        } else if "=!<>".contains(c) {
+            let mut s = String::new();
+            while let Some(&c) = chars.peek().filter(|c| "=!<>".contains(**c)) {
+                s.push(c);
+                chars.next();
+            }
+            let op = CompareOp::parse(&s)
+                .ok_or_else(|| eyre!("Unknown operator {s:?} in query {input:?}"))?;
+            tokens.push(Token::Op(op));
+        } else {
+            let mut s = String::new();
+            while let Some(&c) = chars
+                .peek()
+                .filter(|c| !c.is_whitespace() && !"\"=!<>".contains(**c))
+            {
+                s.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Word(s));
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn parse_time(value: &str) -> Result<DateTime<Utc>> {
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc());
+    }
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|e| eyre!("Invalid time {value:?}, expected e.g. 2024-01-01 -> {e}"))
+}
+
+impl FromStr for Query {
+    type Err = color_eyre::eyre::Report;
+
+    fn from_str(input: &str) -> Result<Self> {
+        let mut tokens = tokenize(input)?.into_iter();
+        let mut query = Query::default();
+        loop {
+            let Some(Token::Word(field)) = tokens.next() else {
+                return Err(eyre!("Expected a field in query {input:?}"));
+            };
+            let (op, value) = match (tokens.next(), tokens.next()) {
+                (Some(op), Some(Token::Word(value) | Token::Quoted(value))) => (op, value),
+                _ => return Err(eyre!("Incomplete condition on {field} in query {input:?}")),
+            };
+            let condition = match (field.to_lowercase().as_str(), op) {
+                ("id", Token::Word(op)) if op.eq_ignore_ascii_case("prefix") => {
+                    Condition::IdPrefix(value)
+                }
+                ("id", Token::Word(op)) if op.eq_ignore_ascii_case("contains") => {
+                    Condition::IdContains(value)
+                }
+                ("id", Token::Op(op)) => Condition::Id(op, value),
+                ("modified", Token::Op(op)) => Condition::Modified(op, parse_time(&value)?),
+                ("size", Token::Op(op)) => Condition::Size(
+                    op,
+                    value
+                        .parse()
+                        .map_err(|e| eyre!("Invalid size {value:?} -> {e}"))?,
+                ),
+                ("locked", Token::Op(CompareOp::Eq)) => Condition::Locked(
+                    value
+                        .parse()
+                        .map_err(|_| eyre!("Expected true, or false, for locked, got {value:?}"))?,
+                ),
+                (field, op) => {
+                    return Err(eyre!(
+                        "Unsupported condition {field} {op:?} in query {input:?}"
+                    ))
+                }
+            };
+            query.conditions.push(condition);
+
+            match tokens.next() {
+                None => break,
+                Some(Token::Word(and)) if and.eq_ignore_ascii_case("and") => {}
+                Some(token) => return Err(eyre!("Expected AND, got {token:?} in query {input:?}")),
+            }
+        }
+
+        Ok(query)
+    }
+}
+
+impl Query {
+    /// Adds a condition, all of them have to match.
+    pub fn and(mut self, condition: Condition) -> Self {
+        self.conditions.push(condition);
+        self
+    }
+
+    pub fn conditions(&self) -> &[Condition] {
+        &self.conditions
+    }
+
+    pub fn matches<ID: Display>(&self, summary: &ItemSummary<ID>) -> bool {
+        self.conditions.iter().all(|c| c.matches(summary))
+    }
+
+    /// The longest id prefix, all matching ids start with it.
+    fn prefix(&self) -> &str {
+        self.conditions
+            .iter()
+            .filter_map(|c| match c {
+                Condition::IdPrefix(prefix) => Some(prefix.as_str()),
+                _ => None,
+            })
+            .max_by_key(|prefix| prefix.len())
+            .unwrap_or_default()
+    }
+
+    /// Like [Storage::scan_ids], but only the matching items.
+    ///
+    /// Queries only on ids scan ids, others [Storage::list], with only the id or all fields set.
+    /// Pages may be short or even empty. Keep scanning until the cursor is `None`.
+    pub async fn scan<ITEM, S>(
+        &self,
+        storage: &S,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ItemSummary<ITEM::ID>>>
+    where
+        ITEM: StorageItem + Send,
+        S: Storage<ITEM> + ?Sized,
+    {
+        let mut page = if self.conditions.iter().any(Condition::needs_summary) {
+            storage.list(start, limit).await?
+        } else {
+            let page = storage
+                .scan_ids_with_prefix(self.prefix(), start, limit)
+                .await?;
+            Page::new(
+                page.items.into_iter().map(ItemSummary::new).collect(),
+                page.cursor,
+            )
+        };
+        page.items.retain(|summary| self.matches(summary));
+
+        Ok(page)
+    }
+
+    /// Streams all matching items, following the cursors of [Query::scan].
+    pub fn stream<'a, ITEM, S>(
+        &'a self,
+        storage: &'a S,
+        page_size: usize,
+    ) -> impl Stream<Item = Result<ItemSummary<ITEM::ID>>> + 'a
+    where
+        ITEM: StorageItem + Send + 'a,
+        S: Storage<ITEM> + ?Sized,
+    {
+        crate::page::follow(None, move |start| async move {
+            self.scan(storage, start.as_deref(), Some(page_size.max(1)))
+                .await
+        })
+        .map_ok(|page| stream::iter(page.items.into_iter().map(Ok)))
+        .try_flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::CompareOp;
+    use crate::Condition;
+    use crate::Query;
+    use crate::Storage;
+    use crate::StorageItem;
+    use crate::StorageMemory;
+    use color_eyre::Result;
+    use futures_util::TryStreamExt;
+
+    #[derive(Default, Debug)]
+    struct TestItem;
+
+    impl StorageItem for TestItem {
+        type ID = String;
+
+        fn serialize(&self) -> Result<Vec<u8>> {
+            Ok(b"{}".to_vec())
+        }
+        fn deserialize(_data: &[u8]) -> Result<Self> {
+            Ok(Self)
+        }
+    }
+
+    #[tokio::test]
+    async fn it_filters_by_query() -> Result<()> {
+        let query: Query =
+            r#"id prefix "guild:" AND modified > 2024-01-01 and size<=1024"#.parse()?;
+        assert_eq!(
+            &[
+                Condition::IdPrefix(String::from("guild:")),
+                Condition::Modified(CompareOp::Gt, "2024-01-01T00:00:00Z".parse()?),
+                Condition::Size(CompareOp::Le, 1024),
+            ],
+            query.conditions()
+        );
+        assert!("id prefix".parse::<Query>().is_err());
+        assert!("id > a OR id < b".parse::<Query>().is_err());
+        assert!("modified > yesterday".parse::<Query>().is_err());
+
+        let storage = StorageMemory::<TestItem>::default();
+        for id in ["guild:a", "guild:b", "user:a"] {
+            let id = id.to_string();
+            let (lock, _) = storage.lock(&id, "query").await?.success()?;
+            storage.save(&id, &TestItem, &lock).await?;
+            if id != "guild:b" {
+                storage.unlock(&id, lock).await?;
+            }
+        }
+
+        let ids = |query: &'static str| {
+            let storage = &storage;
+            async move {
+                let query: Query = query.parse()?;
+                let mut ids: Vec<String> = query
+                    .stream(storage, 1)
+                    .map_ok(|summary| summary.id)
+                    .try_collect()
+                    .await?;
+                ids.sort();
+                Result::<_>::Ok(ids)
+            }
+        };
+        assert_eq!(
+            vec!["guild:a", "guild:b"],
+            ids(r#"id prefix "guild:""#).await?
+        );
+        assert_eq!(vec!["guild:b", "user:a"], ids("id >= guild:b").await?);
+        assert_eq!(
+            vec!["guild:a"],
+            ids("id prefix guild: AND locked = false").await?
+        );
+        assert_eq!(
+            vec!["guild:a", "user:a"],
+            ids("id contains a AND modified > 2024-01-01").await?
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+use crate::StorageId;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use core::marker::PhantomData;
+
+/// The characters a [RandomId] is made of.
+pub trait RandomIdAlphabet: Send + Sync + std::fmt::Debug + Clone + PartialOrd {
+    const CHARS: &'static [char];
+
+    fn contains(c: char) -> bool {
+        Self::CHARS.contains(&c)
+    }
+}
+
+/// `A-Za-z0-9_-`, the nanoid default
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UrlSafeAlphabet;
+
+impl RandomIdAlphabet for UrlSafeAlphabet {
+    const CHARS: &'static [char] = &[
+        '_', '-', '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', 'a', 'b', 'c', 'd', 'e', 'f',
+        'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o', 'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x',
+        'y', 'z', 'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M', 'N', 'O', 'P',
+        'Q', 'R', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z',
+    ];
+
+    fn contains(c: char) -> bool {
+        c.is_ascii_alphanumeric() || c == '_' || c == '-'
+    }
+}
+
+/// `0-9a-z`, for case insensitive backends
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LowercaseAlphabet;
+
+impl RandomIdAlphabet for LowercaseAlphabet {
+    const CHARS: &'static [char] = &[
+        '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', 'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h',
+        'i', 'j', 'k', 'l', 'm', 'n', 'o', 'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z',
+    ];
+
+    fn contains(c: char) -> bool {
+        c.is_ascii_digit() || c.is_ascii_lowercase()
+    }
+}
+
+/// A random nanoid of `LEN` characters from the alphabet `A`.
+///
+/// Parsing rejects ids of the wrong length or with characters outside the alphabet,
+/// so corrupted or hostile ids never reach the backend.
+///
+/// e.g. `RandomId<LowercaseAlphabet, 16>`
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RandomId<A: RandomIdAlphabet = UrlSafeAlphabet, const LEN: usize = 21> {
+    id: String,
+    alphabet: PhantomData<A>,
+}
+
+impl<A: RandomIdAlphabet, const LEN: usize> RandomId<A, LEN> {
+    pub fn as_str(&self) -> &str {
+        &self.id
+    }
+}
+
+/// The default is all zeros, the first character of the alphabet.
+impl<A: RandomIdAlphabet, const LEN: usize> Default for RandomId<A, LEN> {
+    fn default() -> Self {
+        Self {
+            id: std::iter::repeat_n(A::CHARS[0], LEN).collect(),
+            alphabet: PhantomData,
+        }
+    }
+}
+
+impl<A: RandomIdAlphabet, const LEN: usize> std::fmt::Display for RandomId<A, LEN> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.id.fmt(f)
+    }
+}
+
+impl<A: RandomIdAlphabet, const LEN: usize> StorageId for RandomId<A, LEN> {
+    fn from_string(id: &str) -> Result<Self> {
+        if !Self::is_valid_format(id) {
+            return Err(eyre!("Invalid random id {id}"));
+        }
+
+        Ok(Self {
+            id: id.to_string(),
+            alphabet: PhantomData,
+        })
+    }
+
+    fn generate_new(_previous: Option<&Self>) -> Self {
+        const {
+            assert!(LEN > 0, "Random ids need at least one character");
+        }
+        Self {
+            id: nanoid::nanoid!(LEN, A::CHARS),
+            alphabet: PhantomData,
+        }
+    }
+
+    fn is_valid_format(id: &str) -> bool {
+        id.chars().count() == LEN && id.chars().all(A::contains)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::LowercaseAlphabet;
+    use crate::RandomId;
+    use crate::StorageId;
+
+    #[test]
+    fn it_validates_random_ids() -> color_eyre::Result<()> {
+        let id = RandomId::<LowercaseAlphabet, 16>::generate_new(None);
+        assert_eq!(16, id.as_str().len());
+        assert_eq!(id, RandomId::from_string(id.as_str())?);
+        assert!(!RandomId::<LowercaseAlphabet, 16>::is_valid_format(
+            "0123456789ABCDEF"
+        ));
+        assert!(!RandomId::<LowercaseAlphabet, 16>::is_valid_format("0123"));
+
+        let id: RandomId = RandomId::generate_new(None);
+        assert_eq!(21, id.to_string().len());
+        assert!(RandomId::<LowercaseAlphabet, 16>::from_string(id.as_str()).is_err());
+        assert!(!<RandomId>::is_valid_format("../../etc/passwd_____"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,188 @@
+use crate::Priority;
+use crate::StorageItem;
+use crate::StorageMiddleware;
+use crate::StorageRequest;
+use async_trait::async_trait;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+const DEFAULT_FOREGROUND_RESERVE: f64 = 0.2;
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+/// [StorageMiddleware] that delays operations beyond `per_second`, allowing bursts of up to `burst`.
+///
+/// Operations wait instead of failing, and [Priority::Background] operations wait longer:
+/// they leave a share of the burst to foreground operations and never overtake a waiting one.
+#[derive(Debug)]
+pub struct RateLimit {
+    per_second: f64,
+    burst: f64,
+    foreground_reserve: f64,
+    bucket: Mutex<Bucket>,
+    waiting_foreground: AtomicUsize,
+}
+
+impl RateLimit {
+    pub fn new(per_second: f64, burst: u32) -> Result<Self> {
+        if !per_second.is_finite() || per_second <= 0.0 || burst == 0 {
+            return Err(eyre!(
+                "Rate limit needs a positive rate and burst, got {per_second}/s and {burst}"
+            ));
+        }
+
+        Ok(Self {
+            per_second,
+            burst: burst as f64,
+            foreground_reserve: DEFAULT_FOREGROUND_RESERVE,
+            bucket: Mutex::new(Bucket {
+                tokens: burst as f64,
+                refilled: Instant::now(),
+            }),
+            waiting_foreground: AtomicUsize::new(0),
+        })
+    }
+
+    /// The share of the burst only foreground operations may use, defaults to 0.2.
+    pub fn set_foreground_reserve(&mut self, foreground_reserve: f64) -> Result<()> {
+        if !(0.0..1.0).contains(&foreground_reserve) {
+            return Err(eyre!(
+                "Foreground reserve must be in [0, 1), got {foreground_reserve}"
+            ));
+        }
+        self.foreground_reserve = foreground_reserve;
+
+        Ok(())
+    }
+
+    /// Takes a token or returns how long to wait before trying again.
+    fn try_acquire(&self, priority: Priority) -> Option<Duration> {
+        let mut bucket = self.bucket.lock().expect("can lock");
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.refilled).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.per_second).min(self.burst);
+        bucket.refilled = now;
+
+        let floor = match priority {
+            Priority::Foreground => 0.0,
+            Priority::Background if self.waiting_foreground.load(Ordering::Relaxed) > 0 => {
+                return Some(Duration::from_secs_f64(1.0 / self.per_second));
+            }
+            Priority::Background => (self.burst * self.foreground_reserve).floor(),
+        };
+        if bucket.tokens >= floor + 1.0 {
+            bucket.tokens -= 1.0;
+            return None;
+        }
+
+        Some(Duration::from_secs_f64(
+            (floor + 1.0 - bucket.tokens) / self.per_second,
+        ))
+    }
+
+    async fn acquire(&self, priority: Priority) {
+        let _waiting = (priority == Priority::Foreground).then(|| WaitingGuard::new(self));
+        while let Some(wait) = self.try_acquire(priority) {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Counts a waiting foreground operation, until dropped.
+struct WaitingGuard<'a>(&'a AtomicUsize);
+
+impl<'a> WaitingGuard<'a> {
+    fn new(rate_limit: &'a RateLimit) -> Self {
+        rate_limit
+            .waiting_foreground
+            .fetch_add(1, Ordering::Relaxed);
+        Self(&rate_limit.waiting_foreground)
+    }
+}
+
+impl Drop for WaitingGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[async_trait]
+impl<ITEM: StorageItem> StorageMiddleware<ITEM> for RateLimit {
+    async fn before(&self, request: &StorageRequest<'_, ITEM>) -> Result<()> {
+        let priority = request.context().map(|c| c.priority).unwrap_or_default();
+        self.acquire(priority).await;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Priority;
+    use crate::RateLimit;
+    use crate::RequestContext;
+    use crate::Storage;
+    use crate::StorageItem;
+    use crate::StorageMemory;
+    use crate::StorageWithMiddleware;
+    use color_eyre::Result;
+    use std::time::Duration;
+    use tokio::time::Instant;
+
+    #[derive(Default, Debug)]
+    struct TestItem {}
+
+    impl StorageItem for TestItem {
+        type ID = String;
+
+        fn serialize(&self) -> Result<Vec<u8>> {
+            Ok(Vec::new())
+        }
+        fn deserialize(_data: &[u8]) -> Result<Self> {
+            Ok(TestItem {})
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn it_serves_foreground_first() -> Result<()> {
+        assert!(RateLimit::new(0.0, 1).is_err());
+
+        let mut rate_limit = RateLimit::new(10.0, 4)?;
+        rate_limit.set_foreground_reserve(0.5)?;
+        let mut storage = StorageWithMiddleware::new(StorageMemory::<TestItem>::default());
+        storage.add_middleware(rate_limit)?;
+        let id = String::from("1");
+        let background = RequestContext {
+            priority: Priority::Background,
+            ..Default::default()
+        };
+
+        // background work only gets the unreserved half of the burst
+        let start = Instant::now();
+        for _ in 0..3 {
+            background.clone().scope(storage.exists(&id)).await?;
+        }
+        assert_eq!(Duration::from_millis(100), start.elapsed());
+
+        // foreground work uses the reserve
+        let start = Instant::now();
+        storage.exists(&id).await?;
+        storage.exists(&id).await?;
+        assert_eq!(Duration::ZERO, start.elapsed());
+
+        let start = Instant::now();
+        storage.exists(&id).await?;
+        assert_eq!(Duration::from_millis(100), start.elapsed());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,585 @@
+use crate::Consistency;
+use crate::LockResult;
+use crate::OpOptions;
+use crate::Page;
+use crate::ReplicationFollower;
+use crate::Storage;
+use crate::StorageItem;
+use crate::StorageLock;
+use async_trait::async_trait;
+use chrono::DateTime;
+use chrono::Utc;
+use color_eyre::eyre::Result;
+use core::marker::PhantomData;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Reports how far a read replica is behind its primary, see [StorageReadReplica::set_lag_source].
+pub trait ReplicaLag: Send + Sync + std::fmt::Debug {
+    /// `None` if unknown, e.g. while the replica needs to catch up.
+    fn lag(&self) -> Option<Duration>;
+}
+
+impl<ITEM: StorageItem + Send + 'static, S: Storage<ITEM> + 'static> ReplicaLag
+    for ReplicationFollower<ITEM, S>
+{
+    fn lag(&self) -> Option<Duration> {
+        let lag = ReplicationFollower::lag(self);
+        match lag {
+            _ if lag.needs_catch_up => None,
+            _ if lag.entries == 0 => Some(Duration::ZERO),
+            _ => lag.behind,
+        }
+    }
+}
+
+/// Where a read of a [StorageReadReplica] was served from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadSource {
+    Primary,
+    /// `lag` is how far the replica was behind when read, `None` if unknown
+    Replica {
+        lag: Option<Duration>,
+    },
+}
+
+/// A value read via [StorageReadReplica], annotated with where it came from.
+#[derive(Debug)]
+pub struct ReplicaRead<T> {
+    pub value: T,
+    pub source: ReadSource,
+}
+
+impl<T> ReplicaRead<T> {
+    /// Whether the value might not include the latest writes.
+    pub fn is_stale(&self) -> bool {
+        match self.source {
+            ReadSource::Primary => false,
+            ReadSource::Replica { lag } => lag != Some(Duration::ZERO),
+        }
+    }
+}
+
+/// Sends reads, i.e. loads, `exists`, blobs, links, and scans, to a `replica`,
+/// e.g. a DynamoDB global table replica or a disk snapshot,
+/// and everything else, including all locking and saving, to the `primary`.
+///
+/// Reads with [Consistency::Strong] go to the primary,
+/// so do all reads while the replica is further behind than [StorageReadReplica::set_max_lag].
+/// Use [StorageReadReplica::load_annotated] to know how stale a loaded item might be.
+///
+/// Note: `lock` returns the item from the primary, load it this way before modifying it.
+/// Only the primary is set up by `ensure_storage_exists`.
+#[derive(Debug)]
+pub struct StorageReadReplica<ITEM: StorageItem, P: Storage<ITEM>, R: Storage<ITEM>>
+where
+    ITEM: Send,
+{
+    primary: P,
+    replica: R,
+    lag_source: Option<Arc<dyn ReplicaLag>>,
+    max_lag: Option<Duration>,
+    item_type: PhantomData<ITEM>,
+}
+
+impl<ITEM: StorageItem + Send, P: Storage<ITEM>, R: Storage<ITEM>> StorageReadReplica<ITEM, P, R> {
+    pub fn new(primary: P, replica: R) -> Self {
+        Self {
+            primary,
+            replica,
+            lag_source: None,
+            max_lag: None,
+            item_type: PhantomData,
+        }
+    }
+
+    /// Where the lag of the replica comes from, e.g. the [ReplicationFollower] applying changes to it.
+    /// Without one the lag is unknown.
+    pub fn set_lag_source(&mut self, lag_source: Arc<dyn ReplicaLag>) -> Result<()> {
+        self.lag_source = Some(lag_source);
+
+        Ok(())
+    }
+
+    /// Reads go to the primary while the replica is further behind than `max_lag`, or its lag is unknown.
+    /// `None`, the default, always reads from the replica.
+    pub fn set_max_lag(&mut self, max_lag: Option<Duration>) -> Result<()> {
+        self.max_lag = max_lag;
+
+        Ok(())
+    }
+
+    pub fn primary(&self) -> &P {
+        &self.primary
+    }
+
+    pub fn replica(&self) -> &R {
+        &self.replica
+    }
+
+    pub fn into_inner(self) -> (P, R) {
+        (self.primary, self.replica)
+    }
+
+    /// How far the replica is behind right now, `None` if unknown.
+    pub fn lag(&self) -> Option<Duration> {
+        self.lag_source.as_ref().and_then(|s| s.lag())
+    }
+
+    /// Where a read with `consistency` goes.
+    pub fn read_source(&self, consistency: Consistency) -> ReadSource {
+        if consistency == Consistency::Strong {
+            return ReadSource::Primary;
+        }
+        let lag = self.lag();
+        match (self.max_lag, lag) {
+            (Some(max_lag), Some(lag)) if lag <= max_lag => ReadSource::Replica { lag: Some(lag) },
+            (Some(_), _) => ReadSource::Primary,
+            (None, lag) => ReadSource::Replica { lag },
+        }
+    }
+
+    /// Like [Storage::load_with], annotated with where the item was loaded from.
+    pub async fn load_annotated(
+        &self,
+        id: &ITEM::ID,
+        options: &OpOptions,
+    ) -> Result<ReplicaRead<ITEM>> {
+        let source = self.read_source(options.consistency());
+        let value = match source {
+            ReadSource::Primary => self.primary.load_with(id, options).await?,
+            ReadSource::Replica { .. } => self.replica.load_with(id, options).await?,
+        };
+
+        Ok(ReplicaRead { value, source })
+    }
+
+    /// Like [Storage::exists_with], annotated with where the answer came from.
+    pub async fn exists_annotated(
+        &self,
+        id: &ITEM::ID,
+        options: &OpOptions,
+    ) -> Result<ReplicaRead<bool>> {
+        let source = self.read_source(options.consistency());
+        let value = match source {
+            ReadSource::Primary => self.primary.exists_with(id, options).await?,
+            ReadSource::Replica { .. } => self.replica.exists_with(id, options).await?,
+        };
+
+        Ok(ReplicaRead { value, source })
+    }
+
+    /// The storage reads without [OpOptions] go to.
+    fn reader(&self) -> &dyn Storage<ITEM> {
+        match self.read_source(Consistency::Default) {
+            ReadSource::Primary => &self.primary,
+            ReadSource::Replica { .. } => &self.replica,
+        }
+    }
+}
+
+#[async_trait]
+impl<ITEM: StorageItem + Send, P: Storage<ITEM>, R: Storage<ITEM>> Storage<ITEM>
+    for StorageReadReplica<ITEM, P, R>
+{
+    async fn ensure_storage_exists(&mut self) -> Result<()> {
+        self.primary.ensure_storage_exists().await
+    }
+
+    /// Replica reads can lag behind the primary.
+    fn capabilities(&self) -> crate::Capabilities {
+        crate::Capabilities {
+            consistent_reads: false,
+            ..self
+                .primary
+                .capabilities()
+                .intersect(&self.replica.capabilities())
+        }
+    }
+
+    async fn create(&self) -> Result<ITEM::ID> {
+        self.primary.create().await
+    }
+
+    async fn exists(&self, id: &ITEM::ID) -> Result<bool> {
+        self.reader().exists(id).await
+    }
+
+    async fn exists_with(&self, id: &ITEM::ID, options: &OpOptions) -> Result<bool> {
+        Ok(self.exists_annotated(id, options).await?.value)
+    }
+
+    async fn load(&self, id: &ITEM::ID) -> Result<ITEM> {
+        self.reader().load(id).await
+    }
+
+    async fn load_with(&self, id: &ITEM::ID, options: &OpOptions) -> Result<ITEM> {
+        Ok(self.load_annotated(id, options).await?.value)
+    }
+
+    async fn load_many(&self, ids: &[ITEM::ID]) -> Result<Vec<Option<ITEM>>> {
+        self.reader().load_many(ids).await
+    }
+
+    async fn prefetch(&self, ids: &[ITEM::ID]) -> Result<()> {
+        self.reader().prefetch(ids).await
+    }
+
+    async fn save(&self, id: &ITEM::ID, item: &ITEM, lock: &StorageLock) -> Result<()> {
+        self.primary.save(id, item, lock).await
+    }
+
+    async fn lock(&self, id: &ITEM::ID, who: &str) -> Result<LockResult<ITEM>> {
+        self.primary.lock(id, who).await
+    }
+
+    async fn lock_and_load_many(
+        &self,
+        ids: &[ITEM::ID],
+        who: &str,
+    ) -> Result<Vec<crate::LockManyOutcome<ITEM>>> {
+        self.primary.lock_and_load_many(ids, who).await
+    }
+
+    async fn unlock(&self, id: &ITEM::ID, lock: StorageLock) -> Result<()> {
+        self.primary.unlock(id, lock).await
+    }
+
+    async fn handoff_lock(
+        &self,
+        id: &ITEM::ID,
+        lock: StorageLock,
+        new_who: &str,
+    ) -> Result<StorageLock> {
+        self.primary.handoff_lock(id, lock, new_who).await
+    }
+
+    async fn save_and_unlock(&self, id: &ITEM::ID, item: &ITEM, lock: StorageLock) -> Result<()> {
+        self.primary.save_and_unlock(id, item, lock).await
+    }
+
+    async fn load_raw(&self, id: &ITEM::ID) -> Result<Vec<u8>> {
+        self.reader().load_raw(id).await
+    }
+
+    async fn save_raw(&self, id: &ITEM::ID, data: &[u8], lock: &StorageLock) -> Result<()> {
+        self.primary.save_raw(id, data, lock).await
+    }
+
+    async fn put_blob(
+        &self,
+        id: &ITEM::ID,
+        name: &str,
+        data: &[u8],
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.primary.put_blob(id, name, data, lock).await
+    }
+
+    async fn get_blob(&self, id: &ITEM::ID, name: &str) -> Result<Option<Vec<u8>>> {
+        self.reader().get_blob(id, name).await
+    }
+
+    async fn list_blobs(&self, id: &ITEM::ID) -> Result<Vec<String>> {
+        self.reader().list_blobs(id).await
+    }
+
+    /// Meta entries are used for coordination, e.g. id counters, so they always come from the primary.
+    async fn put_meta(&self, key: &str, value: &[u8]) -> Result<()> {
+        self.primary.put_meta(key, value).await
+    }
+
+    async fn get_meta(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.primary.get_meta(key).await
+    }
+
+    async fn add_alias(&self, alias: &ITEM::ID, canonical: &ITEM::ID) -> Result<()> {
+        self.primary.add_alias(alias, canonical).await
+    }
+
+    async fn remove_alias(&self, alias: &ITEM::ID) -> Result<()> {
+        self.primary.remove_alias(alias).await
+    }
+
+    async fn resolve_alias(&self, id: &ITEM::ID) -> Result<ITEM::ID> {
+        self.reader().resolve_alias(id).await
+    }
+
+    async fn add_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.primary.add_link(from, relation, to, lock).await
+    }
+
+    async fn remove_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.primary.remove_link(from, relation, to, lock).await
+    }
+
+    async fn links_of(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        self.reader().links_of(id, relation).await
+    }
+
+    async fn linked_from(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        self.reader().linked_from(id, relation).await
+    }
+
+    async fn force_unlock(&self, id: &ITEM::ID) -> Result<Option<StorageLock>> {
+        self.primary.force_unlock(id).await
+    }
+
+    async fn force_unlock_if_held(
+        &self,
+        id: &ITEM::ID,
+        who: &str,
+        when: DateTime<Utc>,
+    ) -> Result<bool> {
+        self.primary.force_unlock_if_held(id, who, when).await
+    }
+
+    async fn remove_orphaned_locks(&self, max_age: Duration) -> Result<usize> {
+        self.primary.remove_orphaned_locks(max_age).await
+    }
+
+    async fn verify_lock(&self, id: &ITEM::ID, lock: &StorageLock) -> Result<bool> {
+        self.primary.verify_lock(id, lock).await
+    }
+
+    async fn verify_lock_with(
+        &self,
+        id: &ITEM::ID,
+        lock: &StorageLock,
+        options: &OpOptions,
+    ) -> Result<bool> {
+        self.primary.verify_lock_with(id, lock, options).await
+    }
+
+    async fn all_ids(&self) -> Result<Vec<ITEM::ID>> {
+        self.reader().all_ids().await
+    }
+
+    async fn scan_ids(&self, start: Option<&str>, limit: Option<usize>) -> Result<Page<ITEM::ID>> {
+        self.reader().scan_ids(start, limit).await
+    }
+
+    async fn scan_ids_with_prefix(
+        &self,
+        prefix: &str,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.reader()
+            .scan_ids_with_prefix(prefix, start, limit)
+            .await
+    }
+
+    async fn scan_ids_filtered(
+        &self,
+        filter: &crate::IdFilter,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.reader().scan_ids_filtered(filter, start, limit).await
+    }
+
+    async fn scan_ids_between(
+        &self,
+        from: &ITEM::ID,
+        to: &ITEM::ID,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.reader().scan_ids_between(from, to, start, limit).await
+    }
+
+    async fn list(
+        &self,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<crate::ItemSummary<ITEM::ID>>> {
+        self.reader().list(start, limit).await
+    }
+
+    async fn item_size(&self, id: &ITEM::ID) -> Result<u64> {
+        self.reader().item_size(id).await
+    }
+
+    async fn estimate_cost(&self) -> Result<crate::StorageCost> {
+        self.reader().estimate_cost().await
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        self.primary.shutdown().await?;
+        self.replica.shutdown().await
+    }
+
+    async fn fsck(&self, options: &crate::FsckOptions) -> Result<crate::FsckReport> {
+        self.primary.fsck(options).await
+    }
+
+    async fn display_lock(&self, id: &ITEM::ID) -> Result<String> {
+        self.primary.display_lock(id).await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_highest_seen_id(&self) -> Option<ITEM::ID> {
+        self.primary.metadata_highest_seen_id().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_item_count(&self) -> Option<u64> {
+        self.primary.metadata_item_count().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_total_bytes(&self) -> Option<u64> {
+        self.primary.metadata_total_bytes().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_last_write(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.primary.metadata_last_write().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_lock_stats(&self) -> crate::LockStats {
+        self.primary.metadata_lock_stats().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_snapshot(&self) -> crate::MetadataSnapshot {
+        self.primary.metadata_snapshot().await
+    }
+
+    /// Wipes the primary, the replica follows via replication.
+    #[cfg(feature = "wipe")]
+    async fn wipe(
+        &self,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        self.primary.wipe(options, progress, cancel).await
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn wipe_matching(
+        &self,
+        filter: &crate::IdFilter,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        self.primary
+            .wipe_matching(filter, options, progress, cancel)
+            .await
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn delete_many(
+        &self,
+        ids: &[ITEM::ID],
+        options: &crate::DeleteOptions,
+    ) -> Result<Vec<crate::DeleteOutcome>> {
+        self.primary.delete_many(ids, options).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_item::TestItem;
+    use crate::Consistency;
+    use crate::MockResponse;
+    use crate::OpOptions;
+    use crate::ReadSource;
+    use crate::ReplicaLag;
+    use crate::Storage;
+    use crate::StorageMemory;
+    use crate::StorageMock;
+    use crate::StorageOperation;
+    use crate::StorageReadReplica;
+    use color_eyre::eyre::eyre;
+    use color_eyre::Result;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[derive(Debug)]
+    struct FixedLag(Option<Duration>);
+
+    impl ReplicaLag for FixedLag {
+        fn lag(&self) -> Option<Duration> {
+            self.0
+        }
+    }
+
+    #[tokio::test]
+    async fn it_reads_from_the_replica() -> Result<()> {
+        let mut storage = StorageReadReplica::new(
+            StorageMemory::<TestItem>::default(),
+            StorageMemory::<TestItem>::default(),
+        );
+        let id = String::from("1");
+        let (lock, item) = storage.lock(&id, "test").await?.success()?;
+        storage.save_and_unlock(&id, &item, lock).await?;
+
+        // the replica hasn't seen the save
+        assert!(!storage.exists(&id).await?);
+        let strong = OpOptions::default().with_consistency(Consistency::Strong);
+        assert!(storage.exists_with(&id, &strong).await?);
+        let read = storage.exists_annotated(&id, &OpOptions::default()).await?;
+        assert_eq!(ReadSource::Replica { lag: None }, read.source);
+        assert!(read.is_stale());
+
+        // too far behind
+        storage.set_lag_source(Arc::new(FixedLag(Some(Duration::from_secs(5)))))?;
+        storage.set_max_lag(Some(Duration::from_secs(1)))?;
+        let read = storage.load_annotated(&id, &OpOptions::default()).await?;
+        assert_eq!(ReadSource::Primary, read.source);
+        assert!(!read.is_stale());
+        assert!(storage.exists(&id).await?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_keeps_strong_reads_and_writes_while_the_replica_is_down() -> Result<()> {
+        let mut storage = StorageReadReplica::new(
+            StorageMemory::<TestItem>::default(),
+            StorageMock::<TestItem>::default(),
+        );
+        let id = String::from("1");
+        let (lock, _) = storage.lock(&id, "test").await?.success()?;
+        storage
+            .save_and_unlock(&id, &TestItem { count: 1 }, lock)
+            .await?;
+
+        // replica failures are not hidden
+        storage
+            .replica()
+            .script(StorageOperation::Load, MockResponse::Err(eyre!("down")));
+        assert!(storage.load(&id).await.is_err());
+        let strong = OpOptions::default().with_consistency(Consistency::Strong);
+        assert_eq!(
+            TestItem { count: 1 },
+            storage.load_with(&id, &strong).await?
+        );
+
+        // e.g. the follower stopped, so the lag is unknown
+        storage.set_lag_source(Arc::new(FixedLag(None)))?;
+        storage.set_max_lag(Some(Duration::from_secs(1)))?;
+        let read = storage.load_annotated(&id, &OpOptions::default()).await?;
+        assert_eq!(ReadSource::Primary, read.source);
+        assert_eq!(TestItem { count: 1 }, read.value);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,58 @@
+use std::fmt;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+static REDACT: AtomicBool = AtomicBool::new(false);
+
+/// Keeps item data out of the Debug output, logs, and errors of all storages in this process,
+/// e.g. for items holding personal data. Off by default.
+///
+/// Only data the storages handle is covered, [crate::StorageItem] implementations
+/// need to take care of their own Debug output.
+pub fn set_redact(redact: bool) {
+    REDACT.store(redact, Ordering::Relaxed);
+}
+
+pub fn is_redacted() -> bool {
+    REDACT.load(Ordering::Relaxed)
+}
+
+/// Formats like the wrapped value or as `<redacted>` if [set_redact] is on.
+///
+/// Wrap anything that might contain item data before logging it, e.g. `tracing::debug!("{:?}", Redacted(&output))`.
+pub struct Redacted<T>(pub T);
+
+impl<T: fmt::Debug> fmt::Debug for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if is_redacted() {
+            return f.write_str("<redacted>");
+        }
+        self.0.fmt(f)
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if is_redacted() {
+            return f.write_str("<redacted>");
+        }
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::set_redact;
+    use crate::Redacted;
+
+    #[test]
+    fn it_redacts_when_enabled() {
+        let data = vec![1u8, 2, 3];
+        assert_eq!("[1, 2, 3]", format!("{:?}", Redacted(&data)));
+
+        set_redact(true);
+        let redacted = format!("{:?} {}", Redacted(&data), Redacted("secret"));
+        set_redact(false);
+        assert_eq!("<redacted> <redacted>", redacted);
+    }
+}
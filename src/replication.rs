@@ -0,0 +1,965 @@
+use crate::storage_item::serialize_valid;
+use crate::LockResult;
+use crate::OpOptions;
+use crate::Page;
+use crate::Storage;
+use crate::StorageId;
+use crate::StorageItem;
+use crate::StorageLock;
+use async_trait::async_trait;
+use chrono::DateTime;
+use chrono::Utc;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use core::marker::PhantomData;
+use std::collections::VecDeque;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+/// Used as lock holder on followers.
+const WHO: &str = "replication";
+const DEFAULT_LOG_CAPACITY: usize = 10_000;
+/// Entries applied per round, so catching up doesn't starve other tasks
+const APPLY_BATCH_SIZE: usize = 100;
+const SCAN_PAGE_SIZE: usize = 1000;
+/// How long a follower waits after a failed round
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// A change to the primary, replayed on every follower.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplicationChange {
+    Saved {
+        id: String,
+        data: Vec<u8>,
+    },
+    BlobPut {
+        id: String,
+        name: String,
+        data: Vec<u8>,
+    },
+    MetaPut {
+        key: String,
+        value: Vec<u8>,
+    },
+    AliasAdded {
+        alias: String,
+        canonical: String,
+    },
+    AliasRemoved {
+        alias: String,
+    },
+    LinkAdded {
+        from: String,
+        relation: String,
+        to: String,
+    },
+    LinkRemoved {
+        from: String,
+        relation: String,
+        to: String,
+    },
+    #[cfg(feature = "wipe")]
+    Wiped,
+    #[cfg(feature = "wipe")]
+    WipedMatching(crate::IdFilter),
+    #[cfg(feature = "wipe")]
+    Removed {
+        ids: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplicationEntry {
+    /// Increases by one for every entry, starting with `1`
+    pub seq: u64,
+    pub at: DateTime<Utc>,
+    pub change: ReplicationChange,
+}
+
+#[derive(Debug)]
+struct LogState {
+    entries: VecDeque<ReplicationEntry>,
+    capacity: usize,
+}
+
+/// The ordered change log of a [StorageReplicated].
+///
+/// Only the latest entries are kept in memory,
+/// followers that fall further behind or start late need [ReplicationFollower::catch_up].
+#[derive(Debug)]
+pub struct ReplicationLog {
+    state: Mutex<LogState>,
+    /// The seq of the latest entry, `0` before the first one
+    head: watch::Sender<u64>,
+}
+
+impl ReplicationLog {
+    fn new(capacity: usize) -> Self {
+        Self {
+            state: Mutex::new(LogState {
+                entries: VecDeque::new(),
+                capacity: capacity.max(1),
+            }),
+            head: watch::Sender::new(0),
+        }
+    }
+
+    /// The seq of the latest entry, `0` before the first one.
+    pub fn head_seq(&self) -> u64 {
+        *self.head.borrow()
+    }
+
+    /// Up to `limit` entries following `seq`.
+    /// `None` if some of them were already dropped from the log.
+    pub fn entries_after(&self, seq: u64, limit: usize) -> Option<Vec<ReplicationEntry>> {
+        let state = self.state.lock().expect("can lock");
+        match state.entries.front() {
+            Some(first) if first.seq > seq + 1 => None,
+            None if self.head_seq() > seq => None,
+            _ => Some(
+                state
+                    .entries
+                    .iter()
+                    .skip_while(|e| e.seq <= seq)
+                    .take(limit)
+                    .cloned()
+                    .collect(),
+            ),
+        }
+    }
+
+    fn append(&self, change: ReplicationChange) {
+        let mut state = self.state.lock().expect("can lock");
+        let seq = self.head_seq() + 1;
+        state.entries.push_back(ReplicationEntry {
+            seq,
+            at: Utc::now(),
+            change,
+        });
+        while state.entries.len() > state.capacity {
+            state.entries.pop_front();
+        }
+        // still holding the state lock, so seqs stay in order
+        self.head.send_replace(seq);
+    }
+
+    fn set_capacity(&self, capacity: usize) {
+        let mut state = self.state.lock().expect("can lock");
+        state.capacity = capacity.max(1);
+        while state.entries.len() > state.capacity {
+            state.entries.pop_front();
+        }
+    }
+
+    fn entry_time(&self, seq: u64) -> Option<DateTime<Utc>> {
+        let state = self.state.lock().expect("can lock");
+        let first = state.entries.front()?.seq;
+        state
+            .entries
+            .get(seq.checked_sub(first)? as usize)
+            .map(|e| e.at)
+    }
+}
+
+/// Wraps the primary [Storage] and records every successful change in a [ReplicationLog].
+///
+/// Locks are not replicated, neither are items that were only created or locked.
+/// The log is not persisted, followers need [ReplicationFollower::catch_up] after a restart.
+#[derive(Debug)]
+pub struct StorageReplicated<ITEM: StorageItem, S: Storage<ITEM>>
+where
+    ITEM: Send,
+{
+    storage: S,
+    log: Arc<ReplicationLog>,
+    item_type: PhantomData<ITEM>,
+}
+
+impl<ITEM: StorageItem + Send, S: Storage<ITEM>> StorageReplicated<ITEM, S> {
+    pub fn new(storage: S) -> Self {
+        Self {
+            storage,
+            log: Arc::new(ReplicationLog::new(DEFAULT_LOG_CAPACITY)),
+            item_type: PhantomData,
+        }
+    }
+
+    /// How many entries the log keeps, defaults to 10000.
+    pub fn set_log_capacity(&mut self, capacity: usize) -> Result<()> {
+        self.log.set_capacity(capacity);
+
+        Ok(())
+    }
+
+    pub fn log(&self) -> Arc<ReplicationLog> {
+        self.log.clone()
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    pub fn into_inner(self) -> S {
+        self.storage
+    }
+
+    fn record_save(&self, id: &ITEM::ID, data: Vec<u8>) {
+        self.log.append(ReplicationChange::Saved {
+            id: id.to_string(),
+            data,
+        });
+    }
+}
+
+#[async_trait]
+impl<ITEM: StorageItem + Send, S: Storage<ITEM>> Storage<ITEM> for StorageReplicated<ITEM, S> {
+    async fn ensure_storage_exists(&mut self) -> Result<()> {
+        self.storage.ensure_storage_exists().await
+    }
+
+    fn capabilities(&self) -> crate::Capabilities {
+        self.storage.capabilities()
+    }
+
+    async fn create(&self) -> Result<ITEM::ID> {
+        self.storage.create().await
+    }
+
+    async fn exists(&self, id: &ITEM::ID) -> Result<bool> {
+        self.storage.exists(id).await
+    }
+
+    async fn exists_with(&self, id: &ITEM::ID, options: &OpOptions) -> Result<bool> {
+        self.storage.exists_with(id, options).await
+    }
+
+    async fn load(&self, id: &ITEM::ID) -> Result<ITEM> {
+        self.storage.load(id).await
+    }
+
+    async fn load_with(&self, id: &ITEM::ID, options: &OpOptions) -> Result<ITEM> {
+        self.storage.load_with(id, options).await
+    }
+
+    async fn load_many(&self, ids: &[ITEM::ID]) -> Result<Vec<Option<ITEM>>> {
+        self.storage.load_many(ids).await
+    }
+
+    async fn prefetch(&self, ids: &[ITEM::ID]) -> Result<()> {
+        self.storage.prefetch(ids).await
+    }
+
+    async fn save(&self, id: &ITEM::ID, item: &ITEM, lock: &StorageLock) -> Result<()> {
+        let data = serialize_valid(id, item)?;
+        self.storage.save_raw(id, &data, lock).await?;
+        self.record_save(id, data);
+
+        Ok(())
+    }
+
+    async fn lock(&self, id: &ITEM::ID, who: &str) -> Result<LockResult<ITEM>> {
+        self.storage.lock(id, who).await
+    }
+
+    async fn lock_and_load_many(
+        &self,
+        ids: &[ITEM::ID],
+        who: &str,
+    ) -> Result<Vec<crate::LockManyOutcome<ITEM>>> {
+        self.storage.lock_and_load_many(ids, who).await
+    }
+
+    async fn unlock(&self, id: &ITEM::ID, lock: StorageLock) -> Result<()> {
+        self.storage.unlock(id, lock).await
+    }
+
+    async fn handoff_lock(
+        &self,
+        id: &ITEM::ID,
+        lock: StorageLock,
+        new_who: &str,
+    ) -> Result<StorageLock> {
+        self.storage.handoff_lock(id, lock, new_who).await
+    }
+
+    async fn save_and_unlock(&self, id: &ITEM::ID, item: &ITEM, lock: StorageLock) -> Result<()> {
+        self.save(id, item, &lock).await?;
+        self.storage.unlock(id, lock).await
+    }
+
+    async fn load_raw(&self, id: &ITEM::ID) -> Result<Vec<u8>> {
+        self.storage.load_raw(id).await
+    }
+
+    async fn save_raw(&self, id: &ITEM::ID, data: &[u8], lock: &StorageLock) -> Result<()> {
+        self.storage.save_raw(id, data, lock).await?;
+        self.record_save(id, data.to_vec());
+
+        Ok(())
+    }
+
+    async fn put_blob(
+        &self,
+        id: &ITEM::ID,
+        name: &str,
+        data: &[u8],
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.storage.put_blob(id, name, data, lock).await?;
+        self.log.append(ReplicationChange::BlobPut {
+            id: id.to_string(),
+            name: name.to_string(),
+            data: data.to_vec(),
+        });
+
+        Ok(())
+    }
+
+    async fn get_blob(&self, id: &ITEM::ID, name: &str) -> Result<Option<Vec<u8>>> {
+        self.storage.get_blob(id, name).await
+    }
+
+    async fn list_blobs(&self, id: &ITEM::ID) -> Result<Vec<String>> {
+        self.storage.list_blobs(id).await
+    }
+
+    async fn put_meta(&self, key: &str, value: &[u8]) -> Result<()> {
+        self.storage.put_meta(key, value).await?;
+        self.log.append(ReplicationChange::MetaPut {
+            key: key.to_string(),
+            value: value.to_vec(),
+        });
+
+        Ok(())
+    }
+
+    async fn get_meta(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.storage.get_meta(key).await
+    }
+
+    async fn add_alias(&self, alias: &ITEM::ID, canonical: &ITEM::ID) -> Result<()> {
+        self.storage.add_alias(alias, canonical).await?;
+        self.log.append(ReplicationChange::AliasAdded {
+            alias: alias.to_string(),
+            canonical: canonical.to_string(),
+        });
+
+        Ok(())
+    }
+
+    async fn remove_alias(&self, alias: &ITEM::ID) -> Result<()> {
+        self.storage.remove_alias(alias).await?;
+        self.log.append(ReplicationChange::AliasRemoved {
+            alias: alias.to_string(),
+        });
+
+        Ok(())
+    }
+
+    async fn resolve_alias(&self, id: &ITEM::ID) -> Result<ITEM::ID> {
+        self.storage.resolve_alias(id).await
+    }
+
+    async fn add_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.storage.add_link(from, relation, to, lock).await?;
+        self.log.append(ReplicationChange::LinkAdded {
+            from: from.to_string(),
+            relation: relation.to_string(),
+            to: to.to_string(),
+        });
+
+        Ok(())
+    }
+
+    async fn remove_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.storage.remove_link(from, relation, to, lock).await?;
+        self.log.append(ReplicationChange::LinkRemoved {
+            from: from.to_string(),
+            relation: relation.to_string(),
+            to: to.to_string(),
+        });
+
+        Ok(())
+    }
+
+    async fn links_of(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        self.storage.links_of(id, relation).await
+    }
+
+    async fn linked_from(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        self.storage.linked_from(id, relation).await
+    }
+
+    async fn force_unlock(&self, id: &ITEM::ID) -> Result<Option<StorageLock>> {
+        self.storage.force_unlock(id).await
+    }
+
+    async fn force_unlock_if_held(
+        &self,
+        id: &ITEM::ID,
+        who: &str,
+        when: DateTime<Utc>,
+    ) -> Result<bool> {
+        self.storage.force_unlock_if_held(id, who, when).await
+    }
+
+    async fn remove_orphaned_locks(&self, max_age: Duration) -> Result<usize> {
+        self.storage.remove_orphaned_locks(max_age).await
+    }
+
+    async fn verify_lock(&self, id: &ITEM::ID, lock: &StorageLock) -> Result<bool> {
+        self.storage.verify_lock(id, lock).await
+    }
+
+    async fn verify_lock_with(
+        &self,
+        id: &ITEM::ID,
+        lock: &StorageLock,
+        options: &OpOptions,
+    ) -> Result<bool> {
+        self.storage.verify_lock_with(id, lock, options).await
+    }
+
+    async fn all_ids(&self) -> Result<Vec<ITEM::ID>> {
+        self.storage.all_ids().await
+    }
+
+    async fn scan_ids(&self, start: Option<&str>, limit: Option<usize>) -> Result<Page<ITEM::ID>> {
+        self.storage.scan_ids(start, limit).await
+    }
+
+    async fn scan_ids_with_prefix(
+        &self,
+        prefix: &str,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.storage
+            .scan_ids_with_prefix(prefix, start, limit)
+            .await
+    }
+
+    async fn scan_ids_filtered(
+        &self,
+        filter: &crate::IdFilter,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.storage.scan_ids_filtered(filter, start, limit).await
+    }
+
+    async fn scan_ids_between(
+        &self,
+        from: &ITEM::ID,
+        to: &ITEM::ID,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.storage.scan_ids_between(from, to, start, limit).await
+    }
+
+    async fn list(
+        &self,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<crate::ItemSummary<ITEM::ID>>> {
+        self.storage.list(start, limit).await
+    }
+
+    async fn item_size(&self, id: &ITEM::ID) -> Result<u64> {
+        self.storage.item_size(id).await
+    }
+
+    async fn estimate_cost(&self) -> Result<crate::StorageCost> {
+        self.storage.estimate_cost().await
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        self.storage.shutdown().await
+    }
+
+    async fn fsck(&self, options: &crate::FsckOptions) -> Result<crate::FsckReport> {
+        self.storage.fsck(options).await
+    }
+
+    async fn display_lock(&self, id: &ITEM::ID) -> Result<String> {
+        self.storage.display_lock(id).await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_highest_seen_id(&self) -> Option<ITEM::ID> {
+        self.storage.metadata_highest_seen_id().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_item_count(&self) -> Option<u64> {
+        self.storage.metadata_item_count().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_total_bytes(&self) -> Option<u64> {
+        self.storage.metadata_total_bytes().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_last_write(&self) -> Option<DateTime<Utc>> {
+        self.storage.metadata_last_write().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_lock_stats(&self) -> crate::LockStats {
+        self.storage.metadata_lock_stats().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_snapshot(&self) -> crate::MetadataSnapshot {
+        self.storage.metadata_snapshot().await
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn wipe(
+        &self,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        let report = self.storage.wipe(options, progress, cancel).await?;
+        if !options.is_dry_run() {
+            self.log.append(ReplicationChange::Wiped);
+        }
+
+        Ok(report)
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn wipe_matching(
+        &self,
+        filter: &crate::IdFilter,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        let report = self
+            .storage
+            .wipe_matching(filter, options, progress, cancel)
+            .await?;
+        if !options.is_dry_run() {
+            self.log
+                .append(ReplicationChange::WipedMatching(filter.clone()));
+        }
+
+        Ok(report)
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn delete_many(
+        &self,
+        ids: &[ITEM::ID],
+        options: &crate::DeleteOptions,
+    ) -> Result<Vec<crate::DeleteOutcome>> {
+        let outcomes = self.storage.delete_many(ids, options).await?;
+        let removed: Vec<String> = ids
+            .iter()
+            .zip(&outcomes)
+            .filter(|(_, o)| **o == crate::DeleteOutcome::Deleted)
+            .map(|(id, _)| id.to_string())
+            .collect();
+        if !removed.is_empty() {
+            self.log.append(ReplicationChange::Removed { ids: removed });
+        }
+
+        Ok(outcomes)
+    }
+}
+
+/// How far a [ReplicationFollower] is behind its primary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplicationLag {
+    /// Log entries not applied yet
+    pub entries: u64,
+    /// Age of the oldest entry not applied yet, `None` when caught up, or the entry was dropped
+    pub behind: Option<Duration>,
+    /// Entries were dropped before the follower applied them, see [ReplicationFollower::catch_up]
+    pub needs_catch_up: bool,
+}
+
+#[derive(Debug)]
+struct FollowerShared<ITEM: StorageItem, S> {
+    storage: S,
+    log: Arc<ReplicationLog>,
+    applied_seq: AtomicU64,
+    needs_catch_up: AtomicBool,
+    /// Held while applying entries or catching up
+    applying: tokio::sync::Mutex<()>,
+    item_type: PhantomData<ITEM>,
+}
+
+impl<ITEM: StorageItem + Send, S: Storage<ITEM>> FollowerShared<ITEM, S> {
+    async fn apply_pending(&self) -> Result<usize> {
+        let _applying = self.applying.lock().await;
+        let mut count = 0;
+        loop {
+            let applied_seq = self.applied_seq.load(Ordering::Acquire);
+            let Some(entries) = self.log.entries_after(applied_seq, APPLY_BATCH_SIZE) else {
+                self.needs_catch_up.store(true, Ordering::Release);
+                return Err(eyre!(
+                    "Follower at {applied_seq} fell behind the replication log, catch up first"
+                ));
+            };
+            if entries.is_empty() {
+                return Ok(count);
+            }
+            for entry in entries {
+                self.apply(&entry.change).await?;
+                self.applied_seq.store(entry.seq, Ordering::Release);
+                count += 1;
+            }
+        }
+    }
+
+    async fn apply(&self, change: &ReplicationChange) -> Result<()> {
+        match change {
+            ReplicationChange::Saved { id, data } => {
+                let id = ITEM::ID::from_string(id)?;
+                let lock = self.lock(&id).await?;
+                let saved = self.storage.save_raw(&id, data, &lock).await;
+                self.storage.unlock(&id, lock).await?;
+                saved
+            }
+            ReplicationChange::BlobPut { id, name, data } => {
+                let id = ITEM::ID::from_string(id)?;
+                let lock = self.lock(&id).await?;
+                let put = self.storage.put_blob(&id, name, data, &lock).await;
+                self.storage.unlock(&id, lock).await?;
+                put
+            }
+            ReplicationChange::MetaPut { key, value } => self.storage.put_meta(key, value).await,
+            ReplicationChange::AliasAdded { alias, canonical } => {
+                self.storage
+                    .add_alias(
+                        &ITEM::ID::from_string(alias)?,
+                        &ITEM::ID::from_string(canonical)?,
+                    )
+                    .await
+            }
+            ReplicationChange::AliasRemoved { alias } => {
+                self.storage
+                    .remove_alias(&ITEM::ID::from_string(alias)?)
+                    .await
+            }
+            ReplicationChange::LinkAdded { from, relation, to } => {
+                let from = ITEM::ID::from_string(from)?;
+                let lock = self.lock(&from).await?;
+                let added = self
+                    .storage
+                    .add_link(&from, relation, &ITEM::ID::from_string(to)?, &lock)
+                    .await;
+                self.storage.unlock(&from, lock).await?;
+                added
+            }
+            ReplicationChange::LinkRemoved { from, relation, to } => {
+                let from = ITEM::ID::from_string(from)?;
+                let lock = self.lock(&from).await?;
+                let removed = self
+                    .storage
+                    .remove_link(&from, relation, &ITEM::ID::from_string(to)?, &lock)
+                    .await;
+                self.storage.unlock(&from, lock).await?;
+                removed
+            }
+            #[cfg(feature = "wipe")]
+            ReplicationChange::Wiped => {
+                let options = crate::WipeOptions::delete(crate::WIPE_CONFIRMATION);
+                self.storage.wipe(&options, None, None).await.map(|_| ())
+            }
+            #[cfg(feature = "wipe")]
+            ReplicationChange::WipedMatching(filter) => {
+                let options = crate::WipeOptions::delete(crate::WIPE_CONFIRMATION);
+                self.storage
+                    .wipe_matching(filter, &options, None, None)
+                    .await
+                    .map(|_| ())
+            }
+            #[cfg(feature = "wipe")]
+            ReplicationChange::Removed { ids } => {
+                let ids = ids
+                    .iter()
+                    .map(|id| ITEM::ID::from_string(id))
+                    .collect::<Result<Vec<_>>>()?;
+                let options = crate::DeleteOptions::default().with_force(true);
+                self.storage.delete_many(&ids, &options).await.map(|_| ())
+            }
+        }
+    }
+
+    async fn lock(&self, id: &ITEM::ID) -> Result<StorageLock> {
+        match self.storage.lock(id, WHO).await? {
+            LockResult::Success { lock, .. } => Ok(lock),
+            LockResult::AlreadyLocked { who, .. } => {
+                Err(eyre!("{id} is locked by {who:?} on the follower"))
+            }
+        }
+    }
+}
+
+/// Applies the [ReplicationLog] of a [StorageReplicated] to another storage, e.g. a warm standby on disk.
+///
+/// Entries are applied in order, either explicitly via [ReplicationFollower::apply_pending],
+/// or in the background after [ReplicationFollower::start].
+/// A new follower starts at the beginning of the log,
+/// call [ReplicationFollower::catch_up] first if the primary already has items.
+#[derive(Debug)]
+pub struct ReplicationFollower<ITEM: StorageItem, S: Storage<ITEM>>
+where
+    ITEM: Send,
+{
+    shared: Arc<FollowerShared<ITEM, S>>,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl<ITEM: StorageItem + Send + 'static, S: Storage<ITEM> + 'static> ReplicationFollower<ITEM, S> {
+    pub fn new(storage: S, log: Arc<ReplicationLog>) -> Self {
+        Self {
+            shared: Arc::new(FollowerShared {
+                storage,
+                log,
+                applied_seq: AtomicU64::new(0),
+                needs_catch_up: AtomicBool::new(false),
+                applying: tokio::sync::Mutex::new(()),
+                item_type: PhantomData,
+            }),
+            task: Mutex::new(None),
+        }
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.shared.storage
+    }
+
+    /// The seq of the last applied entry.
+    pub fn applied_seq(&self) -> u64 {
+        self.shared.applied_seq.load(Ordering::Acquire)
+    }
+
+    pub fn lag(&self) -> ReplicationLag {
+        let applied_seq = self.applied_seq();
+        let entries = self.shared.log.head_seq().saturating_sub(applied_seq);
+        let behind = if entries > 0 {
+            self.shared
+                .log
+                .entry_time(applied_seq + 1)
+                .and_then(|at| (Utc::now() - at).to_std().ok())
+        } else {
+            None
+        };
+
+        ReplicationLag {
+            entries,
+            behind,
+            needs_catch_up: self.shared.needs_catch_up.load(Ordering::Acquire),
+        }
+    }
+
+    /// Applies all entries not applied yet and returns their number.
+    /// Stops at the first failure, the failed entry is retried next time.
+    pub async fn apply_pending(&self) -> Result<usize> {
+        self.shared.apply_pending().await
+    }
+
+    /// Copies all items and their blobs from the `primary`, then applies the log from there.
+    /// Links are not copied, their relations are unknown to the follower.
+    /// Returns the number of copied items.
+    ///
+    /// Items removed from the primary are not removed from the follower.
+    pub async fn catch_up(&self, primary: &dyn Storage<ITEM>) -> Result<usize> {
+        let shared = &self.shared;
+        let count = {
+            let _applying = shared.applying.lock().await;
+            // changes during the copy are applied again afterwards
+            let start_seq = shared.log.head_seq();
+            let mut count = 0;
+            let mut scan_pos = None;
+            loop {
+                let page = primary
+                    .scan_ids(scan_pos.as_deref(), Some(SCAN_PAGE_SIZE))
+                    .await?;
+                for id in page.items {
+                    let data = primary.load_raw(&id).await?;
+                    let lock = shared.lock(&id).await?;
+                    let copied = self.copy_item(primary, &id, &data, &lock).await;
+                    shared.storage.unlock(&id, lock).await?;
+                    copied?;
+                    count += 1;
+                }
+                scan_pos = page.cursor;
+                if scan_pos.is_none() {
+                    break;
+                }
+            }
+            shared.applied_seq.store(start_seq, Ordering::Release);
+            shared.needs_catch_up.store(false, Ordering::Release);
+            tracing::info!("Replication follower copied {count} items, continuing at {start_seq}");
+            count
+        };
+        shared.apply_pending().await?;
+
+        Ok(count)
+    }
+
+    async fn copy_item(
+        &self,
+        primary: &dyn Storage<ITEM>,
+        id: &ITEM::ID,
+        data: &[u8],
+        lock: &StorageLock,
+    ) -> Result<()> {
+        let storage = &self.shared.storage;
+        storage.save_raw(id, data, lock).await?;
+        // backends without blob support fail to list them
+        for name in primary.list_blobs(id).await.unwrap_or_default() {
+            if let Some(blob) = primary.get_blob(id, &name).await? {
+                storage.put_blob(id, &name, &blob, lock).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies new entries in the background, as they are appended to the log.
+    pub fn start(&self) {
+        let mut task = self.task.lock().expect("can lock");
+        if task.is_some() {
+            return;
+        }
+        let shared = Arc::downgrade(&self.shared);
+        let mut head = self.shared.log.head.subscribe();
+        *task = Some(tokio::spawn(async move {
+            loop {
+                let Some(shared) = shared.upgrade() else {
+                    break;
+                };
+                if shared.needs_catch_up.load(Ordering::Acquire) {
+                    drop(shared);
+                    tokio::time::sleep(RETRY_DELAY).await;
+                    continue;
+                }
+                head.mark_unchanged();
+                if let Err(e) = shared.apply_pending().await {
+                    tracing::warn!("Replication follower failed, retrying -> {e:?}");
+                    drop(shared);
+                    tokio::time::sleep(RETRY_DELAY).await;
+                    continue;
+                }
+                drop(shared);
+                if head.changed().await.is_err() {
+                    break;
+                }
+            }
+        }));
+    }
+
+    /// Stops applying entries in the background.
+    pub async fn stop(&self) {
+        let task = self.task.lock().expect("can lock").take();
+        if let Some(task) = task {
+            task.abort();
+            // only fails with the cancellation
+            let _ = task.await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_item::TestItem;
+    use crate::MockResponse;
+    use crate::ReplicationFollower;
+    use crate::Storage;
+    use crate::StorageMemory;
+    use crate::StorageMock;
+    use crate::StorageOperation;
+    use crate::StorageReplicated;
+    use color_eyre::eyre::eyre;
+    use color_eyre::Result;
+
+    async fn save(storage: &dyn Storage<TestItem>, id: &str, count: u32) -> Result<()> {
+        let id = String::from(id);
+        let (lock, _) = storage.lock(&id, "TEST").await?.success()?;
+        storage
+            .save_and_unlock(&id, &TestItem { count }, lock)
+            .await
+    }
+
+    #[tokio::test]
+    async fn it_replicates_to_followers() -> Result<()> {
+        let mut primary = StorageReplicated::new(StorageMemory::<TestItem>::default());
+        primary.set_log_capacity(2)?;
+        save(&primary, "early", 1).await?;
+
+        let follower = ReplicationFollower::new(StorageMemory::default(), primary.log());
+        save(&primary, "a", 1).await?;
+        save(&primary, "a", 2).await?;
+        assert_eq!(3, follower.lag().entries);
+        assert!(follower.apply_pending().await.is_err());
+        assert!(follower.lag().needs_catch_up);
+
+        assert_eq!(2, follower.catch_up(&primary).await?);
+        assert_eq!(0, follower.lag().entries);
+        assert!(!follower.lag().needs_catch_up);
+        assert_eq!(
+            TestItem { count: 2 },
+            follower.storage().load(&String::from("a")).await?
+        );
+
+        save(&primary, "b", 3).await?;
+        assert_eq!(1, follower.apply_pending().await?);
+        assert_eq!(
+            TestItem { count: 3 },
+            follower.storage().load(&String::from("b")).await?
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_retries_entries_while_the_replica_is_down() -> Result<()> {
+        let primary = StorageReplicated::new(StorageMemory::<TestItem>::default());
+        let follower = ReplicationFollower::new(StorageMock::<TestItem>::default(), primary.log());
+        save(&primary, "a", 1).await?;
+
+        follower
+            .storage()
+            .script(StorageOperation::Lock, MockResponse::Err(eyre!("down")));
+        assert!(follower.apply_pending().await.is_err());
+        assert_eq!(0, follower.applied_seq());
+        assert_eq!(1, follower.lag().entries);
+
+        assert_eq!(1, follower.apply_pending().await?);
+        assert_eq!(0, follower.lag().entries);
+        assert!(follower
+            .storage()
+            .calls()
+            .iter()
+            .any(|c| c.operation == StorageOperation::Save));
+
+        Ok(())
+    }
+}
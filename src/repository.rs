@@ -0,0 +1,515 @@
+use crate::LockResult;
+use crate::RetryBudget;
+use crate::Storage;
+use crate::StorageError;
+use crate::StorageItem;
+use crate::StorageLock;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Report;
+use color_eyre::eyre::Result;
+use rand::Rng;
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The default of [Repository::set_lock_heartbeat].
+const DEFAULT_LOCK_HEARTBEAT: Duration = Duration::from_secs(60);
+
+/// How [Repository] retries locking items that are already locked.
+///
+/// Uses exponential backoff with full jitter.
+#[derive(Debug, Clone)]
+pub struct LockRetryPolicy {
+    /// Maximum number of attempts, including the first one
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled for every further retry
+    pub base_delay: Duration,
+    /// Upper limit for the delay between two attempts
+    pub max_delay: Duration,
+    /// Shared limit for retries, e.g. of all repositories of a storage, `None` for no limit
+    pub budget: Option<RetryBudget>,
+}
+
+impl Default for LockRetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(50),
+            max_delay: Duration::from_secs(2),
+            budget: None,
+        }
+    }
+}
+
+impl LockRetryPolicy {
+    /// A policy that never retries
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// The (jittered) delay before the given retry, starting with `1` for the first retry
+    pub fn delay(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        let delay = self.base_delay.saturating_mul(factor).min(self.max_delay);
+        let millis = delay.as_millis() as u64;
+        if millis == 0 {
+            return delay;
+        }
+        Duration::from_millis(rand::thread_rng().gen_range(0..=millis))
+    }
+}
+
+/// A convenience layer over any [Storage] for application code,
+/// locking, saving, and unlocking as a single `who`.
+///
+/// Cheap to clone, all clones share the storage.
+#[derive(Debug)]
+pub struct Repository<ITEM: StorageItem + Send> {
+    storage: Arc<Box<dyn Storage<ITEM>>>,
+    who: String,
+    lock_retry_policy: LockRetryPolicy,
+    lock_heartbeat: Duration,
+}
+
+impl<ITEM: StorageItem + Send> Clone for Repository<ITEM> {
+    fn clone(&self) -> Self {
+        Self {
+            storage: self.storage.clone(),
+            who: self.who.clone(),
+            lock_retry_policy: self.lock_retry_policy.clone(),
+            lock_heartbeat: self.lock_heartbeat,
+        }
+    }
+}
+
+impl<ITEM: StorageItem + Send> Repository<ITEM> {
+    /// `storage` must already exist, see [Storage::ensure_storage_exists].
+    pub fn new(storage: Box<dyn Storage<ITEM>>, who: &str) -> Self {
+        Self {
+            storage: Arc::new(storage),
+            who: who.to_string(),
+            lock_retry_policy: LockRetryPolicy::default(),
+            lock_heartbeat: DEFAULT_LOCK_HEARTBEAT,
+        }
+    }
+
+    /// Use the storage directly for everything not covered here.
+    pub fn storage(&self) -> &dyn Storage<ITEM> {
+        self.storage.as_ref().as_ref()
+    }
+
+    /// The lock holder for all locks taken by this repository.
+    pub fn who(&self) -> &str {
+        &self.who
+    }
+
+    pub fn set_lock_retry_policy(&mut self, lock_retry_policy: LockRetryPolicy) -> Result<()> {
+        self.lock_retry_policy = lock_retry_policy;
+
+        Ok(())
+    }
+
+    /// How often [Repository::with_lock] refreshes the lock, via [Storage::handoff_lock] to itself,
+    /// so it doesn't look stale or orphaned while work is still in progress.
+    pub fn set_lock_heartbeat(&mut self, lock_heartbeat: Duration) -> Result<()> {
+        if lock_heartbeat.is_zero() {
+            return Err(eyre!("Lock heartbeat must not be zero"));
+        }
+        self.lock_heartbeat = lock_heartbeat;
+
+        Ok(())
+    }
+
+    pub async fn get(&self, id: &ITEM::ID) -> Result<ITEM> {
+        self.storage.load(id).await
+    }
+
+    /// Locks the item, passes it to `f`, and saves it if `f` succeeds.
+    /// When `f` fails the item is only unlocked and the error returned.
+    ///
+    /// Like [Storage::lock] this creates missing items.
+    /// Fails with [StorageError::AlreadyLocked] when the item stays locked for all attempts of the [LockRetryPolicy].
+    pub async fn modify<R>(
+        &self,
+        id: &ITEM::ID,
+        f: impl FnOnce(&mut ITEM) -> Result<R> + Send,
+    ) -> Result<R> {
+        let (lock, mut item) = self.lock(id).await?;
+        match f(&mut item) {
+            Ok(r) => {
+                self.storage.save_and_unlock(id, &item, lock).await?;
+                Ok(r)
+            }
+            Err(e) => {
+                if let Err(unlock_e) = self.storage.unlock(id, lock).await {
+                    tracing::warn!("Can't unlock {id} after failure -> {unlock_e:?}");
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Locks the item and runs the future returned by `f`, keeping the lock alive while it runs.
+    /// The item is saved if the future succeeds and only unlocked if it fails.
+    ///
+    /// If the future panics or this call is cancelled, the lock is released in the background.
+    /// Backends without [Storage::handoff_lock] keep the lock, just without heartbeat.
+    pub async fn with_lock<R, F, Fut>(&self, id: &ITEM::ID, f: F) -> Result<R>
+    where
+        ITEM: 'static,
+        F: FnOnce(ItemHandle<ITEM>) -> Fut + Send,
+        Fut: Future<Output = Result<R>> + Send,
+        R: Send,
+    {
+        let (lock, item) = self.lock(id).await?;
+        let mut held = HeldLock {
+            storage: self.storage.clone(),
+            id: id.clone(),
+            lock: Some(lock),
+        };
+        let handle = ItemHandle {
+            item: Arc::new(Mutex::new(item)),
+        };
+        let work = f(handle.clone());
+        tokio::pin!(work);
+
+        let start = tokio::time::Instant::now() + self.lock_heartbeat;
+        let mut heartbeat = tokio::time::interval_at(start, self.lock_heartbeat);
+        let mut beating = true;
+        let result = loop {
+            tokio::select! {
+                result = &mut work => break result,
+                _ = heartbeat.tick(), if beating => beating = held.heartbeat(&self.who).await,
+            }
+        };
+
+        match result {
+            Ok(r) => {
+                let item = handle.take();
+                self.storage
+                    .save_and_unlock(id, &item, held.duplicate())
+                    .await?;
+                held.released();
+                Ok(r)
+            }
+            Err(e) => {
+                held.unlock().await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Like [Storage::upsert], but retries locking according to the [LockRetryPolicy].
+    pub async fn upsert(
+        &self,
+        id: &ITEM::ID,
+        f: impl FnOnce(Option<ITEM>) -> Result<ITEM> + Send,
+    ) -> Result<bool> {
+        let (lock, item) = self.lock(id).await?;
+        crate::storage::upsert_locked(&**self.storage, id, lock, item, f).await
+    }
+
+    /// Creates a new item and initialises it via `f`, see [Repository::modify].
+    pub async fn create_with(
+        &self,
+        f: impl FnOnce(&mut ITEM) -> Result<()> + Send,
+    ) -> Result<ITEM::ID> {
+        let id = self.storage.create().await?;
+        self.modify(&id, f).await?;
+
+        Ok(id)
+    }
+
+    async fn lock(&self, id: &ITEM::ID) -> Result<(StorageLock, ITEM)> {
+        let mut attempt = 1;
+        loop {
+            match self.storage.lock(id, &self.who).await? {
+                LockResult::Success { lock, item } => return Ok((lock, item)),
+                LockResult::AlreadyLocked { who, .. } => {
+                    let locked = StorageError::AlreadyLocked {
+                        id: id.to_string(),
+                        who,
+                    };
+                    if attempt >= self.lock_retry_policy.max_attempts {
+                        return Err(locked.into());
+                    }
+                    if let Some(budget) = &self.lock_retry_policy.budget {
+                        if !budget.try_retry() {
+                            return Err(Report::new(locked).wrap_err(
+                                StorageError::RetryBudgetExhausted {
+                                    operation: String::from("lock"),
+                                },
+                            ));
+                        }
+                    }
+                    let delay = self.lock_retry_policy.delay(attempt);
+                    tracing::debug!("{locked}, retrying in {delay:?}");
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Shared access to the item locked by [Repository::with_lock].
+///
+/// Changes made after the future completed are lost.
+#[derive(Debug)]
+pub struct ItemHandle<ITEM> {
+    item: Arc<Mutex<ITEM>>,
+}
+
+impl<ITEM> Clone for ItemHandle<ITEM> {
+    fn clone(&self) -> Self {
+        Self {
+            item: self.item.clone(),
+        }
+    }
+}
+
+impl<ITEM: Default> ItemHandle<ITEM> {
+    pub fn read<R>(&self, f: impl FnOnce(&ITEM) -> R) -> R {
+        f(&self.item.lock().expect("can lock"))
+    }
+
+    pub fn modify<R>(&self, f: impl FnOnce(&mut ITEM) -> R) -> R {
+        f(&mut self.item.lock().expect("can lock"))
+    }
+
+    fn take(&self) -> ITEM {
+        std::mem::take(&mut *self.item.lock().expect("can lock"))
+    }
+}
+
+/// The lock held by [Repository::with_lock], released in the background if dropped while held.
+struct HeldLock<ITEM: StorageItem + Send + 'static> {
+    storage: Arc<Box<dyn Storage<ITEM>>>,
+    id: ITEM::ID,
+    lock: Option<StorageLock>,
+}
+
+impl<ITEM: StorageItem + Send + 'static> HeldLock<ITEM> {
+    fn duplicate(&self) -> StorageLock {
+        self.lock
+            .as_ref()
+            .expect("lock is held until released")
+            .duplicate()
+    }
+
+    /// Refreshes the lock, returns `false` once heartbeats should stop.
+    async fn heartbeat(&mut self, who: &str) -> bool {
+        match self
+            .storage
+            .handoff_lock(&self.id, self.duplicate(), who)
+            .await
+        {
+            Ok(lock) => {
+                self.lock = Some(lock);
+                true
+            }
+            Err(e) => {
+                tracing::warn!(
+                    "Can't refresh lock for {}, stopping heartbeat -> {e:?}",
+                    self.id
+                );
+                false
+            }
+        }
+    }
+
+    /// Marks the lock as released, e.g. by a save.
+    fn released(mut self) {
+        self.lock = None;
+    }
+
+    async fn unlock(mut self) {
+        let Some(lock) = self.lock.take() else {
+            return;
+        };
+        if let Err(e) = self.storage.unlock(&self.id, lock).await {
+            tracing::warn!("Can't unlock {} after failure -> {e:?}", self.id);
+        }
+    }
+}
+
+impl<ITEM: StorageItem + Send + 'static> Drop for HeldLock<ITEM> {
+    fn drop(&mut self) {
+        let Some(lock) = self.lock.take() else {
+            return;
+        };
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            tracing::warn!("Can't unlock {} without a runtime", self.id);
+            return;
+        };
+        let storage = self.storage.clone();
+        let id = self.id.clone();
+        runtime.spawn(async move {
+            if let Err(e) = storage.unlock(&id, lock).await {
+                tracing::warn!("Can't unlock {id} after cancel -> {e:?}");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_item::TestItem;
+    use crate::LockRetryPolicy;
+    use crate::MockClock;
+    use crate::MockResponse;
+    use crate::Repository;
+    use crate::Storage;
+    use crate::StorageError;
+    use crate::StorageMemory;
+    use crate::StorageMock;
+    use crate::StorageOperation;
+    use color_eyre::eyre::eyre;
+    use color_eyre::Result;
+    use std::time::Duration;
+
+    #[tokio::test(start_paused = true)]
+    async fn it_modifies_items() -> Result<()> {
+        let mut repository = Repository::new(Box::new(StorageMemory::default()), "repository");
+        let id = repository
+            .create_with(|item: &mut TestItem| {
+                item.count = 1;
+                Ok(())
+            })
+            .await?;
+        let doubled = repository
+            .modify(&id, |item| {
+                item.count *= 2;
+                Ok(item.count)
+            })
+            .await?;
+        assert_eq!(2, doubled);
+        let failed = repository
+            .modify(&id, |item| -> Result<()> {
+                item.count = 100;
+                Err(eyre!("broken"))
+            })
+            .await;
+        assert!(failed.is_err());
+        assert_eq!(TestItem { count: 2 }, repository.get(&id).await?);
+
+        repository.set_lock_retry_policy(LockRetryPolicy {
+            max_attempts: 3,
+            ..Default::default()
+        })?;
+        let (lock, _) = repository.storage().lock(&id, "other").await?.success()?;
+        let e = repository.modify(&id, |_| Ok(())).await.unwrap_err();
+        assert_eq!(
+            Some(&StorageError::AlreadyLocked {
+                id: id.clone(),
+                who: String::from("other"),
+            }),
+            e.downcast_ref::<StorageError>()
+        );
+        repository.storage().unlock(&id, lock).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_upserts_items() -> Result<()> {
+        let storage = StorageMemory::<TestItem>::default();
+        let id = String::from("counter");
+        let increment = |item: Option<TestItem>| {
+            Ok(TestItem {
+                count: item.map_or(1, |item| item.count + 1),
+            })
+        };
+        assert!(storage.upsert(&id, "upsert", increment).await?);
+        assert!(!storage.upsert(&id, "upsert", increment).await?);
+        assert_eq!(TestItem { count: 2 }, storage.load(&id).await?);
+
+        let failed = storage
+            .upsert(&id, "upsert", |_| Err(eyre!("broken")))
+            .await;
+        assert!(failed.is_err());
+
+        let repository = Repository::new(Box::new(storage), "repository");
+        assert!(!repository.upsert(&id, increment).await?);
+        assert!(repository.upsert(&String::from("new"), increment).await?);
+        assert_eq!(TestItem { count: 3 }, repository.get(&id).await?);
+
+        // a failed save releases the lock
+        let storage = StorageMock::<TestItem>::default();
+        storage.script(StorageOperation::Save, MockResponse::Err(eyre!("down")));
+        assert!(storage.upsert(&id, "upsert", increment).await.is_err());
+        let operations: Vec<_> = storage.calls().iter().map(|c| c.operation).collect();
+        assert_eq!(Some(&StorageOperation::Unlock), operations.last());
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn it_keeps_locks_alive_while_working() -> Result<()> {
+        let clock = MockClock::default();
+        let mut storage = StorageMemory::default();
+        storage.set_clock(clock.clone())?;
+        let mut repository = Repository::new(Box::new(storage), "repository");
+        repository.set_lock_heartbeat(Duration::from_secs(10))?;
+        let id = repository.create_with(|_: &mut TestItem| Ok(())).await?;
+
+        let storage = repository.clone();
+        let locked_at = repository
+            .with_lock(&id, |item| async move {
+                let before = storage.storage().list(None, None).await?.items[0].locked_at;
+                clock.advance(Duration::from_secs(60 * 60));
+                tokio::time::sleep(Duration::from_secs(15)).await;
+                let after = storage.storage().list(None, None).await?.items[0].locked_at;
+                item.modify(|item| item.count = 7);
+                Ok((before, after))
+            })
+            .await?;
+        assert!(locked_at.0 < locked_at.1);
+        assert_eq!(TestItem { count: 7 }, repository.get(&id).await?);
+
+        let failed = repository
+            .with_lock(&id, |item| async move {
+                item.modify(|item| item.count = 100);
+                Err::<(), _>(eyre!("broken"))
+            })
+            .await;
+        assert!(failed.is_err());
+        assert_eq!(TestItem { count: 7 }, repository.get(&id).await?);
+        assert_eq!("", repository.storage().display_lock(&id).await?);
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn it_unlocks_after_cancel_and_panic() -> Result<()> {
+        let repository = Repository::new(Box::new(StorageMemory::default()), "repository");
+        let id = repository.create_with(|_: &mut TestItem| Ok(())).await?;
+
+        let cancelled = tokio::time::timeout(
+            Duration::from_secs(1),
+            repository.with_lock(&id, |_| std::future::pending::<Result<()>>()),
+        )
+        .await;
+        assert!(cancelled.is_err());
+        tokio::task::yield_now().await;
+        assert_eq!("", repository.storage().display_lock(&id).await?);
+
+        let panicking = repository.clone();
+        let panic_id = id.clone();
+        let panicked = tokio::spawn(async move {
+            panicking
+                .with_lock::<(), _, _>(&panic_id, |_| async { panic!("broken") })
+                .await
+        })
+        .await;
+        assert!(panicked.is_err());
+        tokio::task::yield_now().await;
+        assert_eq!("", repository.storage().display_lock(&id).await?);
+
+        Ok(())
+    }
+}
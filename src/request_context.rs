@@ -0,0 +1,67 @@
+use serde::Deserialize;
+use serde::Serialize;
+use std::future::Future;
+use tracing::Instrument;
+
+tokio::task_local! {
+    static CONTEXT: RequestContext;
+}
+
+/// How urgent the operations of a [RequestContext] are.
+///
+/// Under load [crate::RateLimit] and [crate::StorageWriteBehind] serve foreground work first,
+/// e.g. player saves before analytics writes.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    /// Work nobody waits for, e.g. analytics, migrations, and [crate::bulk::for_each_item]
+    Background,
+    /// Work someone waits for
+    #[default]
+    Foreground,
+}
+
+impl Priority {
+    /// The priority of the current task's [RequestContext], [Priority::Foreground] outside of one.
+    pub fn current() -> Self {
+        CONTEXT.try_with(|c| c.priority).unwrap_or_default()
+    }
+}
+
+/// For whom and on whose behalf storage operations run, e.g. taken from an incoming request.
+///
+/// Set for a task with [RequestContext::scope]. Middleware sees it via [crate::StorageRequest::context],
+/// and [crate::AuditRecord]s include it.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RequestContext {
+    pub tenant_id: Option<String>,
+    pub trace_id: Option<String>,
+    /// The identity of the caller, unlike the lock holder, e.g. a user or service name
+    pub caller: Option<String>,
+    #[serde(default)]
+    pub priority: Priority,
+}
+
+impl RequestContext {
+    /// Runs `f` with this context, inside a `storage.context` span carrying its fields,
+    /// so the spans of all operations in `f` are its children.
+    ///
+    /// Note: Tasks spawned by `f`, e.g. write-behind flushes, don't inherit the context.
+    pub async fn scope<F: Future>(self, f: F) -> F::Output {
+        let span = tracing::info_span!(
+            "storage.context",
+            tenant_id = self.tenant_id.as_deref(),
+            trace_id = self.trace_id.as_deref(),
+            caller = self.caller.as_deref(),
+            priority = ?self.priority,
+        );
+        CONTEXT.scope(self, f).instrument(span).await
+    }
+
+    /// The context of the current task, `None` outside of [RequestContext::scope].
+    pub fn current() -> Option<Self> {
+        CONTEXT.try_with(Clone::clone).ok()
+    }
+}
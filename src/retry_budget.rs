@@ -0,0 +1,151 @@
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use tokio::time::Instant;
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+#[derive(Debug)]
+struct Inner {
+    per_second: f64,
+    burst: f64,
+    bucket: Mutex<Bucket>,
+    retries: AtomicU64,
+    exhausted: AtomicU64,
+}
+
+/// Limits retries across all callers sharing it to `per_second`, with bursts of up to `burst`,
+/// so contention or a struggling backend doesn't turn into a retry storm.
+///
+/// Every retry takes a token, first attempts are free. Without tokens retries are skipped,
+/// and the caller fails, e.g. with [crate::StorageError::RetryBudgetExhausted].
+/// Put it into the `budget` of a [crate::LockRetryPolicy], [crate::ResilientRetryPolicy],
+/// or [crate::DynamoDbRetryPolicy].
+///
+/// Clones share the budget.
+#[derive(Debug, Clone)]
+pub struct RetryBudget {
+    inner: Arc<Inner>,
+}
+
+impl RetryBudget {
+    pub fn new(per_second: f64, burst: u32) -> Result<Self> {
+        if !per_second.is_finite() || per_second <= 0.0 || burst == 0 {
+            return Err(eyre!(
+                "Retry budget needs a positive rate and burst, got {per_second}/s and {burst}"
+            ));
+        }
+
+        Ok(Self {
+            inner: Arc::new(Inner {
+                per_second,
+                burst: burst as f64,
+                bucket: Mutex::new(Bucket {
+                    tokens: burst as f64,
+                    refilled: Instant::now(),
+                }),
+                retries: AtomicU64::new(0),
+                exhausted: AtomicU64::new(0),
+            }),
+        })
+    }
+
+    /// Takes a token for one retry, `false` if there is none left.
+    pub fn try_retry(&self) -> bool {
+        let inner = &*self.inner;
+        let mut bucket = inner.bucket.lock().expect("can lock");
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.refilled).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * inner.per_second).min(inner.burst);
+        bucket.refilled = now;
+        if bucket.tokens < 1.0 {
+            inner.exhausted.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        inner.retries.fetch_add(1, Ordering::Relaxed);
+
+        true
+    }
+
+    /// The tokens left, as of the last retry.
+    pub fn available(&self) -> f64 {
+        self.inner.bucket.lock().expect("can lock").tokens
+    }
+
+    /// Retries granted so far.
+    pub fn retries(&self) -> u64 {
+        self.inner.retries.load(Ordering::Relaxed)
+    }
+
+    /// Retries refused so far, because the budget was exhausted.
+    pub fn exhausted(&self) -> u64 {
+        self.inner.exhausted.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::LockRetryPolicy;
+    use crate::Repository;
+    use crate::RetryBudget;
+    use crate::Storage;
+    use crate::StorageError;
+    use crate::StorageItem;
+    use crate::StorageMemory;
+    use color_eyre::Result;
+    use std::time::Duration;
+
+    #[derive(Default, Debug)]
+    struct TestItem;
+
+    impl StorageItem for TestItem {
+        type ID = String;
+
+        fn serialize(&self) -> Result<Vec<u8>> {
+            Ok(b"{}".to_vec())
+        }
+        fn deserialize(_data: &[u8]) -> Result<Self> {
+            Ok(Self)
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn it_limits_retries() -> Result<()> {
+        assert!(RetryBudget::new(0.0, 1).is_err());
+
+        let budget = RetryBudget::new(1.0, 2)?;
+        assert!(budget.try_retry());
+        assert!(budget.try_retry());
+        assert!(!budget.try_retry());
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert!(budget.try_retry());
+        assert_eq!((3, 1), (budget.retries(), budget.exhausted()));
+
+        // two repositories contending for one item share the budget
+        let storage = StorageMemory::<TestItem>::default();
+        let id = String::from("contended");
+        let _ = storage.lock(&id, "holder").await?.success()?;
+        let mut repository = Repository::new(Box::new(storage), "repository");
+        repository.set_lock_retry_policy(LockRetryPolicy {
+            max_attempts: 10,
+            budget: Some(budget.clone()),
+            ..Default::default()
+        })?;
+        let e = repository.modify(&id, |_| Ok(())).await.unwrap_err();
+        assert!(matches!(
+            e.downcast_ref(),
+            Some(StorageError::RetryBudgetExhausted { .. })
+        ));
+        assert_eq!((3, 2), (budget.retries(), budget.exhausted()));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,251 @@
+use crate::Repository;
+use crate::StorageItem;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Report;
+use color_eyre::eyre::Result;
+
+type Compensation<ITEM> = Box<dyn FnOnce(&mut ITEM) -> Result<()> + Send>;
+
+/// Applies changes to several items, one step per item, and undoes the applied steps if a later one fails.
+///
+/// Every step locks, modifies, and saves its item via [Repository::modify] and registers a compensation.
+/// When a step fails or [Saga::abort] is called, the compensations of all applied steps run in reverse order,
+/// each locking its item again. [Saga::commit] finishes the saga and drops the compensations.
+///
+/// Note: Other writers can see the intermediate state, this isn't a transaction.
+pub struct Saga<ITEM: StorageItem + Send> {
+    repository: Repository<ITEM>,
+    compensations: Vec<(ITEM::ID, Compensation<ITEM>)>,
+}
+
+impl<ITEM: StorageItem + Send> std::fmt::Debug for Saga<ITEM> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Saga")
+            .field("repository", &self.repository)
+            .field(
+                "compensations",
+                &self
+                    .compensations
+                    .iter()
+                    .map(|(id, _)| id)
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl<ITEM: StorageItem + Send> Saga<ITEM> {
+    pub fn new(repository: &Repository<ITEM>) -> Self {
+        Self {
+            repository: repository.clone(),
+            compensations: Vec::new(),
+        }
+    }
+
+    /// Modifies the item via `f` and registers `compensate` to undo it.
+    ///
+    /// If `f` or saving fails, all previous steps are compensated and the error is returned.
+    pub async fn step<R>(
+        &mut self,
+        id: &ITEM::ID,
+        f: impl FnOnce(&mut ITEM) -> Result<R> + Send,
+        compensate: impl FnOnce(&mut ITEM) -> Result<()> + Send + 'static,
+    ) -> Result<R> {
+        match self.repository.modify(id, f).await {
+            Ok(r) => {
+                self.compensations.push((id.clone(), Box::new(compensate)));
+                Ok(r)
+            }
+            Err(e) => Err(self.compensate(e).await),
+        }
+    }
+
+    /// The number of applied steps, that would be compensated.
+    pub fn len(&self) -> usize {
+        self.compensations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.compensations.is_empty()
+    }
+
+    /// Keeps all applied steps.
+    pub fn commit(mut self) {
+        self.compensations.clear();
+    }
+
+    /// Compensates all applied steps, e.g. after a failure outside of the saga.
+    ///
+    /// Fails if any compensation failed, after trying all of them.
+    pub async fn abort(mut self) -> Result<()> {
+        let failed = self.run_compensations().await;
+        if !failed.is_empty() {
+            return Err(eyre!("Saga compensation failed for {failed:?}"));
+        }
+
+        Ok(())
+    }
+
+    async fn compensate(&mut self, e: Report) -> Report {
+        let failed = self.run_compensations().await;
+        if failed.is_empty() {
+            return e;
+        }
+        e.wrap_err(format!("Saga compensation failed for {failed:?}"))
+    }
+
+    /// Runs the compensations in reverse order and returns the ids of the failed ones.
+    async fn run_compensations(&mut self) -> Vec<String> {
+        let mut failed = Vec::new();
+        while let Some((id, compensate)) = self.compensations.pop() {
+            if let Err(e) = self.repository.modify(&id, compensate).await {
+                tracing::warn!("Can't compensate {id} -> {e:?}");
+                failed.push(id.to_string());
+            }
+        }
+        failed
+    }
+}
+
+impl<ITEM: StorageItem + Send> Drop for Saga<ITEM> {
+    fn drop(&mut self) {
+        if !self.compensations.is_empty() {
+            tracing::warn!(
+                "Saga dropped with {} uncompensated steps, use commit, or abort",
+                self.compensations.len()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_item::TestItem;
+    use crate::Repository;
+    use crate::Saga;
+    use crate::StorageMemory;
+    use color_eyre::eyre::eyre;
+    use color_eyre::Result;
+
+    async fn transfer(
+        repository: &Repository<TestItem>,
+        from: &String,
+        to: &String,
+        amount: u32,
+    ) -> Result<()> {
+        let mut saga = Saga::new(repository);
+        saga.step(
+            to,
+            |a: &mut TestItem| {
+                a.count += amount;
+                Ok(())
+            },
+            move |a| {
+                a.count -= amount;
+                Ok(())
+            },
+        )
+        .await?;
+        saga.step(
+            from,
+            |a| {
+                a.count = a
+                    .count
+                    .checked_sub(amount)
+                    .ok_or_else(|| eyre!("Insufficient count"))?;
+                Ok(())
+            },
+            move |a| {
+                a.count += amount;
+                Ok(())
+            },
+        )
+        .await?;
+        saga.commit();
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_compensates_applied_steps() -> Result<()> {
+        let repository = Repository::new(Box::new(StorageMemory::default()), "saga");
+        let from = repository
+            .create_with(|a: &mut TestItem| {
+                a.count = 10;
+                Ok(())
+            })
+            .await?;
+        let to = repository.create_with(|_| Ok(())).await?;
+
+        transfer(&repository, &from, &to, 7).await?;
+        assert_eq!(3, repository.get(&from).await?.count);
+        assert_eq!(7, repository.get(&to).await?.count);
+
+        // the credit to `to` is undone
+        assert!(transfer(&repository, &from, &to, 5).await.is_err());
+        assert_eq!(3, repository.get(&from).await?.count);
+        assert_eq!(7, repository.get(&to).await?.count);
+
+        let mut saga = Saga::new(&repository);
+        saga.step(
+            &to,
+            |a| {
+                a.count += 1;
+                Ok(())
+            },
+            |a| {
+                a.count -= 1;
+                Ok(())
+            },
+        )
+        .await?;
+        assert_eq!(1, saga.len());
+        saga.abort().await?;
+        assert_eq!(7, repository.get(&to).await?.count);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_reports_failed_compensations() -> Result<()> {
+        let repository = Repository::new(Box::new(StorageMemory::default()), "saga");
+        let a = repository.create_with(|_: &mut TestItem| Ok(())).await?;
+        let b = repository.create_with(|_| Ok(())).await?;
+
+        let mut saga = Saga::new(&repository);
+        for id in [&a, &b] {
+            let failing = *id == b;
+            saga.step(
+                id,
+                |i| {
+                    i.count += 1;
+                    Ok(())
+                },
+                move |i| {
+                    if failing {
+                        return Err(eyre!("Can't undo"));
+                    }
+                    i.count -= 1;
+                    Ok(())
+                },
+            )
+            .await?;
+        }
+        let e = saga
+            .step(&a, |_| Err::<(), _>(eyre!("Step failed")), |_| Ok(()))
+            .await
+            .expect_err("step fails");
+
+        // the other compensations still run
+        assert_eq!(
+            format!("Saga compensation failed for [{b:?}]"),
+            e.to_string()
+        );
+        assert_eq!("Step failed", e.root_cause().to_string());
+        assert_eq!(0, repository.get(&a).await?.count);
+        assert_eq!(1, repository.get(&b).await?.count);
+        assert!(saga.is_empty());
+
+        Ok(())
+    }
+}
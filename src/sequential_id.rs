@@ -0,0 +1,48 @@
+use crate::StorageId;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+
+/// A numeric id, counting up from 1.
+///
+/// Note: [StorageId::generate_new] just increments the previous id,
+/// so concurrent creates can collide. Use an [crate::IdAllocator] to hand out ids safely.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SequentialId(u64);
+
+impl SequentialId {
+    pub fn value(&self) -> u64 {
+        self.0
+    }
+}
+
+impl From<u64> for SequentialId {
+    fn from(value: u64) -> Self {
+        Self(value)
+    }
+}
+
+impl std::fmt::Display for SequentialId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl StorageId for SequentialId {
+    fn from_string(id: &str) -> Result<Self> {
+        if !Self::is_valid_format(id) {
+            return Err(eyre!("Invalid sequential id {id}"));
+        }
+        Ok(Self(id.parse()?))
+    }
+
+    fn generate_new(previous: Option<&Self>) -> Self {
+        match previous {
+            Some(previous) => Self(previous.0 + 1),
+            None => Self(1),
+        }
+    }
+
+    fn is_valid_format(id: &str) -> bool {
+        !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit()) && id.parse::<u64>().is_ok()
+    }
+}
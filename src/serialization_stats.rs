@@ -0,0 +1,171 @@
+use crate::StorageItem;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Serialization costs of one direction, see [SerializationStats].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SerializationCost {
+    pub count: u64,
+    pub time: Duration,
+    pub bytes: u64,
+    pub largest: u64,
+    pub slowest: Duration,
+}
+
+impl SerializationCost {
+    pub fn mean_time(&self) -> Option<Duration> {
+        let count = u32::try_from(self.count).ok().filter(|c| *c > 0)?;
+        Some(self.time / count)
+    }
+
+    pub fn mean_bytes(&self) -> Option<u64> {
+        self.bytes.checked_div(self.count)
+    }
+
+    fn record(&mut self, time: Duration, bytes: u64) {
+        self.count += 1;
+        self.time += time;
+        self.bytes += bytes;
+        self.largest = self.largest.max(bytes);
+        self.slowest = self.slowest.max(time);
+    }
+}
+
+/// How much time and how many bytes [StorageItem::serialize] and [StorageItem::deserialize] cost per item type,
+/// counted by all storages of the process, e.g. to find items that became a CPU hotspot.
+///
+/// Exported by [crate::StorageMetrics] if the `prometheus` feature is enabled.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SerializationStats {
+    pub serialize: SerializationCost,
+    pub deserialize: SerializationCost,
+    /// Serialized items larger than the soft limit, see [SerializationStats::set_soft_limit].
+    pub over_soft_limit: u64,
+}
+
+#[derive(Debug, Default)]
+struct Entry {
+    stats: SerializationStats,
+    soft_limit: Option<u64>,
+}
+
+/// By [std::any::type_name] of the item.
+static REGISTRY: Mutex<BTreeMap<&'static str, Entry>> = Mutex::new(BTreeMap::new());
+
+impl SerializationStats {
+    /// The stats of `ITEM`, all zero until one was serialized or deserialized.
+    pub fn of<ITEM: StorageItem>() -> Self {
+        REGISTRY
+            .lock()
+            .expect("can lock")
+            .get(std::any::type_name::<ITEM>())
+            .map(|entry| entry.stats)
+            .unwrap_or_default()
+    }
+
+    /// The stats of all item types seen so far, by type name.
+    pub fn all() -> BTreeMap<String, Self> {
+        REGISTRY
+            .lock()
+            .expect("can lock")
+            .iter()
+            .map(|(name, entry)| (name.to_string(), entry.stats))
+            .collect()
+    }
+
+    /// Logs a warning and counts it in [SerializationStats::over_soft_limit]
+    /// whenever an `ITEM` serializes to more than `bytes`. Saves still succeed. Off by default.
+    pub fn set_soft_limit<ITEM: StorageItem>(bytes: Option<u64>) -> Result<()> {
+        if bytes == Some(0) {
+            return Err(eyre!("Serialization soft limit must not be zero"));
+        }
+        REGISTRY
+            .lock()
+            .expect("can lock")
+            .entry(std::any::type_name::<ITEM>())
+            .or_default()
+            .soft_limit = bytes;
+
+        Ok(())
+    }
+}
+
+pub(crate) fn record_serialize<ITEM: StorageItem>(id: &ITEM::ID, time: Duration, bytes: u64) {
+    let name = std::any::type_name::<ITEM>();
+    let mut registry = REGISTRY.lock().expect("can lock");
+    let entry = registry.entry(name).or_default();
+    entry.stats.serialize.record(time, bytes);
+    if let Some(limit) = entry.soft_limit.filter(|limit| bytes > *limit) {
+        entry.stats.over_soft_limit += 1;
+        drop(registry);
+        tracing::warn!("{name} {id} serialized to {bytes} bytes, over the soft limit of {limit}");
+    }
+}
+
+pub(crate) fn record_deserialize<ITEM: StorageItem>(time: Duration, bytes: u64) {
+    REGISTRY
+        .lock()
+        .expect("can lock")
+        .entry(std::any::type_name::<ITEM>())
+        .or_default()
+        .stats
+        .deserialize
+        .record(time, bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::SerializationStats;
+    use crate::Storage;
+    use crate::StorageItem;
+    use crate::StorageMemory;
+    use color_eyre::Result;
+
+    #[derive(Default, Debug)]
+    struct MeasuredItem {
+        size: usize,
+    }
+
+    impl StorageItem for MeasuredItem {
+        type ID = String;
+
+        fn serialize(&self) -> Result<Vec<u8>> {
+            Ok(vec![b' '; self.size])
+        }
+        fn deserialize(data: &[u8]) -> Result<Self> {
+            Ok(Self { size: data.len() })
+        }
+    }
+
+    #[tokio::test]
+    async fn it_measures_serialization() -> Result<()> {
+        assert!(SerializationStats::set_soft_limit::<MeasuredItem>(Some(0)).is_err());
+        SerializationStats::set_soft_limit::<MeasuredItem>(Some(100))?;
+        let storage = StorageMemory::<MeasuredItem>::default();
+        let id = String::from("large");
+        let (lock, _) = storage.lock(&id, "stats").await?.success()?;
+        storage.save(&id, &MeasuredItem { size: 10 }, &lock).await?;
+        storage
+            .save(&id, &MeasuredItem { size: 1000 }, &lock)
+            .await?;
+        storage.unlock(&id, lock).await?;
+        assert_eq!(1000, storage.load(&id).await?.size);
+
+        let stats = SerializationStats::of::<MeasuredItem>();
+        assert_eq!(2, stats.serialize.count);
+        assert_eq!(
+            (1010, 1000),
+            (stats.serialize.bytes, stats.serialize.largest)
+        );
+        assert_eq!(Some(505), stats.serialize.mean_bytes());
+        assert!(stats.deserialize.count >= 1);
+        assert_eq!(1000, stats.deserialize.largest);
+        assert_eq!(1, stats.over_soft_limit);
+        assert!(SerializationStats::all().contains_key(std::any::type_name::<MeasuredItem>()));
+
+        Ok(())
+    }
+}
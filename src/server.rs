@@ -0,0 +1,390 @@
+//! HTTP access to any [Storage], behind the `server` feature,
+//! e.g. for services not written in Rust or debugging tools sharing the locks of Rust services.
+//!
+//! - `GET /items?prefix=&start=&limit=` scans ids, returning `{"ids": [...], "cursor": ...}`
+//! - `GET /items/{id}` returns the item as stored, see [Storage::load_raw]
+//! - `PUT /items/{id}` saves the body as is, see [Storage::save_raw], requires [LOCK_HEADER]
+//! - `POST /items/{id}/lock` locks as [WHO_HEADER], returning the item and the lock in [LOCK_HEADER],
+//!   or `409` with `{"who": ..., "when": ...}` of the holder
+//! - `DELETE /items/{id}/lock` unlocks, requires [LOCK_HEADER]
+//!
+//! Lock headers are opaque to clients, send back what the lock returned.
+//! [StorageError]s map to status codes, e.g. `404` for [StorageError::NotFound], other errors are `500`.
+
+use crate::LockResult;
+use crate::Storage;
+use crate::StorageError;
+use crate::StorageId;
+use crate::StorageItem;
+use crate::StorageLock;
+use axum::body::Bytes;
+use axum::extract::Path;
+use axum::extract::Query;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::http::HeaderValue;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::response::Response;
+use axum::routing::get;
+use axum::routing::post;
+use axum::Json;
+use axum::Router;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::DateTime;
+use chrono::Utc;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+/// Carries the lock, base64 encoded JSON of the [StorageLock].
+pub const LOCK_HEADER: &str = "x-oml-storage-lock";
+
+/// Who locks, for `POST /items/{id}/lock`.
+pub const WHO_HEADER: &str = "x-oml-storage-who";
+
+/// Serves a [Storage] via HTTP, see the [module](crate::server) for the endpoints.
+#[derive(Debug)]
+pub struct StorageServer<ITEM, S> {
+    storage: Arc<S>,
+    item_type: PhantomData<fn() -> ITEM>,
+}
+
+impl<ITEM, S> StorageServer<ITEM, S>
+where
+    ITEM: StorageItem + Send + 'static,
+    S: Storage<ITEM> + 'static,
+{
+    pub fn new(storage: Arc<S>) -> Self {
+        Self {
+            storage,
+            item_type: PhantomData,
+        }
+    }
+
+    /// The routes, e.g. to nest them into a larger service.
+    pub fn router(&self) -> Router {
+        Router::new()
+            .route("/items", get(scan_ids::<ITEM, S>))
+            .route(
+                "/items/{id}",
+                get(load_item::<ITEM, S>).put(save_item::<ITEM, S>),
+            )
+            .route(
+                "/items/{id}/lock",
+                post(lock_item::<ITEM, S>).delete(unlock_item::<ITEM, S>),
+            )
+            .with_state(self.storage.clone())
+    }
+
+    /// Serves until the listener fails.
+    pub async fn serve(&self, listener: tokio::net::TcpListener) -> Result<()> {
+        axum::serve(listener, self.router()).await?;
+
+        Ok(())
+    }
+}
+
+/// A failed request, with the status derived from the [StorageError], if any.
+struct ServerError {
+    status: StatusCode,
+    report: color_eyre::Report,
+}
+
+impl From<color_eyre::Report> for ServerError {
+    fn from(report: color_eyre::Report) -> Self {
+        let status = report
+            .downcast_ref::<StorageError>()
+            .map(status_of)
+            .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        Self { status, report }
+    }
+}
+
+fn status_of(e: &StorageError) -> StatusCode {
+    match e {
+        StorageError::NotFound { .. } => StatusCode::NOT_FOUND,
+        StorageError::InvalidId { .. } | StorageError::InvalidItem { .. } => {
+            StatusCode::BAD_REQUEST
+        }
+        StorageError::PermissionDenied { .. } => StatusCode::FORBIDDEN,
+        StorageError::AlreadyLocked { .. }
+        | StorageError::LockMismatch { .. }
+        | StorageError::Immutable { .. } => StatusCode::CONFLICT,
+        StorageError::ReadOnly => StatusCode::METHOD_NOT_ALLOWED,
+        StorageError::ItemTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+        StorageError::QuotaExceeded { .. } | StorageError::NamespaceQuotaExceeded { .. } => {
+            StatusCode::INSUFFICIENT_STORAGE
+        }
+        StorageError::Unsupported { .. } => StatusCode::NOT_IMPLEMENTED,
+        e if e.is_transient() => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+impl IntoResponse for ServerError {
+    fn into_response(self) -> Response {
+        if self.status.is_server_error() {
+            tracing::warn!("Request failed with {} -> {:?}", self.status, self.report);
+        }
+        (self.status, self.report.to_string()).into_response()
+    }
+}
+
+fn parse_id<ID: StorageId>(id: &str) -> Result<ID> {
+    ID::from_string(id).map_err(|e| e.wrap_err(StorageError::InvalidId { id: id.to_string() }))
+}
+
+fn bad_request(message: String) -> ServerError {
+    ServerError {
+        status: StatusCode::BAD_REQUEST,
+        report: eyre!(message),
+    }
+}
+
+fn encode_lock(lock: &StorageLock) -> Result<HeaderValue> {
+    Ok(HeaderValue::from_str(
+        &BASE64.encode(serde_json::to_vec(lock)?),
+    )?)
+}
+
+fn header<'h>(headers: &'h HeaderMap, name: &str) -> Result<&'h str, ServerError> {
+    headers
+        .get(name)
+        .ok_or_else(|| bad_request(format!("Missing {name} header")))?
+        .to_str()
+        .map_err(|e| bad_request(format!("Invalid {name} header -> {e}")))
+}
+
+fn decode_lock(headers: &HeaderMap) -> Result<StorageLock, ServerError> {
+    let value = header(headers, LOCK_HEADER)?;
+    BASE64
+        .decode(value)
+        .ok()
+        .and_then(|json| serde_json::from_slice(&json).ok())
+        .ok_or_else(|| bad_request(format!("Invalid {LOCK_HEADER} header")))
+}
+
+#[derive(Debug, Deserialize)]
+struct ScanQuery {
+    #[serde(default)]
+    prefix: String,
+    start: Option<String>,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ScanResponse {
+    ids: Vec<String>,
+    cursor: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LockedResponse {
+    who: String,
+    when: DateTime<Utc>,
+}
+
+async fn scan_ids<ITEM, S>(
+    State(storage): State<Arc<S>>,
+    Query(query): Query<ScanQuery>,
+) -> Result<Json<ScanResponse>, ServerError>
+where
+    ITEM: StorageItem + Send,
+    S: Storage<ITEM>,
+{
+    let page = storage
+        .scan_ids_with_prefix(&query.prefix, query.start.as_deref(), query.limit)
+        .await?;
+
+    Ok(Json(ScanResponse {
+        ids: page.items.iter().map(|id| id.to_string()).collect(),
+        cursor: page.cursor,
+    }))
+}
+
+async fn load_item<ITEM, S>(
+    State(storage): State<Arc<S>>,
+    Path(id): Path<String>,
+) -> Result<Vec<u8>, ServerError>
+where
+    ITEM: StorageItem + Send,
+    S: Storage<ITEM>,
+{
+    let id = parse_id::<ITEM::ID>(&id)?;
+
+    Ok(storage.load_raw(&id).await?)
+}
+
+async fn save_item<ITEM, S>(
+    State(storage): State<Arc<S>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    data: Bytes,
+) -> Result<StatusCode, ServerError>
+where
+    ITEM: StorageItem + Send,
+    S: Storage<ITEM>,
+{
+    let id = parse_id::<ITEM::ID>(&id)?;
+    let lock = decode_lock(&headers)?;
+    storage.save_raw(&id, &data, &lock).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn lock_item<ITEM, S>(
+    State(storage): State<Arc<S>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, ServerError>
+where
+    ITEM: StorageItem + Send,
+    S: Storage<ITEM>,
+{
+    let id = parse_id::<ITEM::ID>(&id)?;
+    let who = header(&headers, WHO_HEADER)?;
+    match storage.lock(&id, who).await? {
+        LockResult::Success { lock, item } => {
+            let data = item.serialize()?;
+            Ok(([(LOCK_HEADER, encode_lock(&lock)?)], data).into_response())
+        }
+        LockResult::AlreadyLocked { who, when } => {
+            Ok((StatusCode::CONFLICT, Json(LockedResponse { who, when })).into_response())
+        }
+    }
+}
+
+async fn unlock_item<ITEM, S>(
+    State(storage): State<Arc<S>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<StatusCode, ServerError>
+where
+    ITEM: StorageItem + Send,
+    S: Storage<ITEM>,
+{
+    let id = parse_id::<ITEM::ID>(&id)?;
+    let lock = decode_lock(&headers)?;
+    storage.unlock(&id, lock).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StorageMemory;
+    use axum::body::Body;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    #[derive(Default, Debug, PartialEq, Serialize, Deserialize)]
+    struct TestItem {
+        name: String,
+    }
+
+    impl StorageItem for TestItem {
+        type ID = String;
+
+        fn serialize(&self) -> Result<Vec<u8>> {
+            Ok(serde_json::to_vec(&self)?)
+        }
+        fn deserialize(data: &[u8]) -> Result<Self> {
+            Ok(serde_json::from_slice(data)?)
+        }
+    }
+
+    async fn send(
+        router: &Router,
+        method: &str,
+        uri: &str,
+        headers: &[(&str, &HeaderValue)],
+        body: &[u8],
+    ) -> Result<(StatusCode, HeaderMap, Bytes)> {
+        let mut request = Request::builder().method(method).uri(uri);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let response = router
+            .clone()
+            .oneshot(request.body(Body::from(body.to_vec()))?)
+            .await?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+
+        Ok((status, headers, body))
+    }
+
+    #[tokio::test]
+    async fn it_serves_locked_items() -> Result<()> {
+        let storage = Arc::new(StorageMemory::<TestItem>::default());
+        let router = StorageServer::new(storage.clone()).router();
+        let who = HeaderValue::from_static("http-client");
+
+        let (status, _, _) = send(&router, "GET", "/items/guild:1", &[], b"").await?;
+        assert_eq!(StatusCode::NOT_FOUND, status);
+
+        let (status, headers, _) = send(
+            &router,
+            "POST",
+            "/items/guild:1/lock",
+            &[(WHO_HEADER, &who)],
+            b"",
+        )
+        .await?;
+        assert_eq!(StatusCode::OK, status);
+        let lock = headers.get(LOCK_HEADER).expect("lock header").clone();
+
+        let (status, _, body) = send(
+            &router,
+            "POST",
+            "/items/guild:1/lock",
+            &[(WHO_HEADER, &who)],
+            b"",
+        )
+        .await?;
+        assert_eq!(StatusCode::CONFLICT, status);
+        let locked: LockedResponse = serde_json::from_slice(&body)?;
+        assert_eq!("http-client", locked.who);
+
+        let data = br#"{"name":"remote"}"#;
+        let (status, _, _) = send(&router, "PUT", "/items/guild:1", &[], data).await?;
+        assert_eq!(StatusCode::BAD_REQUEST, status);
+        let (status, _, _) = send(
+            &router,
+            "PUT",
+            "/items/guild:1",
+            &[(LOCK_HEADER, &lock)],
+            data,
+        )
+        .await?;
+        assert_eq!(StatusCode::NO_CONTENT, status);
+        let (status, _, _) = send(
+            &router,
+            "DELETE",
+            "/items/guild:1/lock",
+            &[(LOCK_HEADER, &lock)],
+            b"",
+        )
+        .await?;
+        assert_eq!(StatusCode::NO_CONTENT, status);
+
+        let (status, _, body) = send(&router, "GET", "/items/guild:1", &[], b"").await?;
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(data.as_slice(), &body[..]);
+        assert_eq!("remote", storage.load(&String::from("guild:1")).await?.name);
+
+        let (status, _, body) = send(&router, "GET", "/items?prefix=guild:", &[], b"").await?;
+        assert_eq!(StatusCode::OK, status);
+        let page: ScanResponse = serde_json::from_slice(&body)?;
+        assert_eq!(vec![String::from("guild:1")], page.ids);
+
+        Ok(())
+    }
+}
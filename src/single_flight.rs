@@ -0,0 +1,747 @@
+use crate::storage_item::deserialize_item;
+use crate::LockResult;
+use crate::OpOptions;
+use crate::Page;
+use crate::Storage;
+use crate::StorageError;
+use crate::StorageItem;
+use crate::StorageLock;
+use async_trait::async_trait;
+use chrono::DateTime;
+use chrono::Utc;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Report;
+use color_eyre::eyre::Result;
+use core::marker::PhantomData;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+use tokio::sync::watch;
+
+/// An error shared with the callers that joined a flight, [StorageError]s stay available via `downcast_ref`.
+#[derive(Debug, Clone)]
+struct SharedError {
+    storage_error: Option<StorageError>,
+    message: String,
+}
+
+impl SharedError {
+    fn new(e: &Report) -> Self {
+        Self {
+            storage_error: e.downcast_ref::<StorageError>().cloned(),
+            message: format!("{e:?}"),
+        }
+    }
+
+    fn report(&self) -> Report {
+        match &self.storage_error {
+            Some(e) => e.clone().into(),
+            None => eyre!("{}", self.message),
+        }
+    }
+}
+
+type FlightResult<T> = std::result::Result<T, SharedError>;
+
+/// The calls in flight, per id, callers for the same id wait for the first one.
+#[derive(Debug)]
+struct Flights<T> {
+    flights: Mutex<HashMap<String, watch::Receiver<Option<FlightResult<T>>>>>,
+    coalesced: AtomicU64,
+}
+
+impl<T> Default for Flights<T> {
+    fn default() -> Self {
+        Self {
+            flights: Mutex::new(HashMap::new()),
+            coalesced: AtomicU64::new(0),
+        }
+    }
+}
+
+/// Ends the flight when the leading call finishes or is dropped.
+/// Followers of a dropped call see the closed channel and call the backend themselves.
+struct Leader<'a, T> {
+    flights: &'a Flights<T>,
+    key: String,
+    sender: watch::Sender<Option<FlightResult<T>>>,
+}
+
+impl<T> Drop for Leader<'_, T> {
+    fn drop(&mut self) {
+        let mut flights = self.flights.flights.lock().expect("can lock");
+        // the flight might have been forgotten, and replaced by a newer one
+        if flights
+            .get(&self.key)
+            .is_some_and(|r| r.same_channel(&self.sender.subscribe()))
+        {
+            flights.remove(&self.key);
+        }
+    }
+}
+
+impl<T> Leader<'_, T> {
+    async fn lead<R>(
+        self,
+        call: impl Future<Output = Result<R>>,
+        share: impl FnOnce(&R) -> Result<T>,
+    ) -> Result<R> {
+        let result = call.await;
+        let shared = match &result {
+            Ok(r) => share(r).map_err(|e| SharedError::new(&e)),
+            Err(e) => Err(SharedError::new(e)),
+        };
+        self.sender.send_replace(Some(shared));
+
+        result
+    }
+}
+
+enum Flight<'a, T> {
+    Leader(Leader<'a, T>),
+    Follower(watch::Receiver<Option<FlightResult<T>>>),
+}
+
+impl<T: Clone> Flights<T> {
+    fn join(&self, key: String) -> Flight<'_, T> {
+        let mut flights = self.flights.lock().expect("can lock");
+        if let Some(receiver) = flights.get(&key) {
+            return Flight::Follower(receiver.clone());
+        }
+        let (sender, receiver) = watch::channel(None);
+        flights.insert(key.clone(), receiver);
+        Flight::Leader(Leader {
+            flights: self,
+            key,
+            sender,
+        })
+    }
+
+    /// Runs `call`, unless a call for `key` is in flight already, then waits for its result.
+    ///
+    /// `share` turns the result into something every waiting caller can have, `unshare` turns it back.
+    async fn run<R, Fut>(
+        &self,
+        key: String,
+        call: Fut,
+        share: impl FnOnce(&R) -> Result<T>,
+        unshare: impl FnOnce(T) -> Result<R>,
+    ) -> Result<R>
+    where
+        Fut: Future<Output = Result<R>>,
+    {
+        let mut receiver = match self.join(key) {
+            Flight::Leader(leader) => return leader.lead(call, share).await,
+            Flight::Follower(receiver) => receiver,
+        };
+        let shared = match receiver.wait_for(Option::is_some).await {
+            Ok(shared) => shared.clone(),
+            Err(_) => None,
+        };
+        match shared {
+            Some(Ok(t)) => {
+                self.coalesced.fetch_add(1, Ordering::Relaxed);
+                unshare(t)
+            }
+            Some(Err(e)) => {
+                self.coalesced.fetch_add(1, Ordering::Relaxed);
+                Err(e.report())
+            }
+            // the leading call was dropped
+            None => call.await,
+        }
+    }
+
+    /// Calls for `key` started after this don't join calls in flight, e.g. after a save.
+    fn forget(&self, key: &str) {
+        self.flights.lock().expect("can lock").remove(key);
+    }
+
+    fn forget_all(&self) {
+        self.flights.lock().expect("can lock").clear();
+    }
+
+    fn coalesced(&self) -> u64 {
+        self.coalesced.load(Ordering::Relaxed)
+    }
+}
+
+/// Wraps any [Storage] and coalesces concurrent calls for the same id into one backend call,
+/// e.g. against thundering herds on popular items.
+///
+/// Concurrent [Storage::load]s and [Storage::load_raw]s of an id wait for the first one and share its result,
+/// concurrent [Storage::exists] checks too. Optionally [Storage::exists] results are reused for a while,
+/// see [StorageSingleFlight::set_exists_ttl].
+///
+/// Saves, locks, and removals via this instance make later calls start a new flight,
+/// so they never see data from before. Calls with [OpOptions] other than the default are not coalesced.
+/// Items are shared serialized and must survive a round trip through [StorageItem::serialize].
+#[derive(Debug)]
+pub struct StorageSingleFlight<ITEM: StorageItem, S: Storage<ITEM>>
+where
+    ITEM: Send,
+{
+    storage: S,
+    loads: Flights<Vec<u8>>,
+    raw_loads: Flights<Vec<u8>>,
+    exists: Flights<bool>,
+    exists_ttl: Option<Duration>,
+    /// Recent [Storage::exists] results, only with an `exists_ttl`
+    recent_exists: Mutex<HashMap<String, (bool, Instant)>>,
+    exists_reused: AtomicU64,
+    item_type: PhantomData<ITEM>,
+}
+
+impl<ITEM: StorageItem + Send, S: Storage<ITEM>> StorageSingleFlight<ITEM, S> {
+    pub fn new(storage: S) -> Self {
+        Self {
+            storage,
+            loads: Flights::default(),
+            raw_loads: Flights::default(),
+            exists: Flights::default(),
+            exists_ttl: None,
+            recent_exists: Mutex::new(HashMap::new()),
+            exists_reused: AtomicU64::new(0),
+            item_type: PhantomData,
+        }
+    }
+
+    /// Reuses [Storage::exists] results for `exists_ttl`, by default they are only shared while in flight.
+    ///
+    /// Only changes via this instance are seen, keep it short if other instances create or remove items.
+    pub fn set_exists_ttl(&mut self, exists_ttl: Option<Duration>) -> Result<()> {
+        self.exists_ttl = exists_ttl;
+
+        Ok(())
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    pub fn into_inner(self) -> S {
+        self.storage
+    }
+
+    /// How many loads, raw or not, got their result from a call in flight.
+    pub fn coalesced_loads(&self) -> u64 {
+        self.loads.coalesced() + self.raw_loads.coalesced()
+    }
+
+    /// How many [Storage::exists] checks got their result from a call in flight or a recent one.
+    pub fn coalesced_exists(&self) -> u64 {
+        self.exists.coalesced() + self.exists_reused.load(Ordering::Relaxed)
+    }
+
+    fn recent_exists(&self, key: &str) -> Option<bool> {
+        let exists_ttl = self.exists_ttl?;
+        let recent = self.recent_exists.lock().expect("can lock");
+        let (exists, since) = recent.get(key)?;
+        (since.elapsed() <= exists_ttl).then_some(*exists)
+    }
+
+    fn remember_exists(&self, key: String, exists: bool) {
+        if self.exists_ttl.is_none() {
+            return;
+        }
+        let mut recent = self.recent_exists.lock().expect("can lock");
+        recent.retain(|_, (_, since)| self.exists_ttl.is_some_and(|ttl| since.elapsed() <= ttl));
+        recent.insert(key, (exists, Instant::now()));
+    }
+
+    fn forget(&self, id: &ITEM::ID) {
+        let key = id.to_string();
+        self.loads.forget(&key);
+        self.raw_loads.forget(&key);
+        self.exists.forget(&key);
+        self.recent_exists.lock().expect("can lock").remove(&key);
+    }
+
+    #[cfg(feature = "wipe")]
+    fn forget_all(&self) {
+        self.loads.forget_all();
+        self.raw_loads.forget_all();
+        self.exists.forget_all();
+        self.recent_exists.lock().expect("can lock").clear();
+    }
+}
+
+#[async_trait]
+impl<ITEM: StorageItem + Send, S: Storage<ITEM>> Storage<ITEM> for StorageSingleFlight<ITEM, S> {
+    async fn ensure_storage_exists(&mut self) -> Result<()> {
+        self.storage.ensure_storage_exists().await
+    }
+
+    fn capabilities(&self) -> crate::Capabilities {
+        self.storage.capabilities()
+    }
+
+    async fn create(&self) -> Result<ITEM::ID> {
+        let id = self.storage.create().await?;
+        self.forget(&id);
+
+        Ok(id)
+    }
+
+    async fn exists(&self, id: &ITEM::ID) -> Result<bool> {
+        let key = id.to_string();
+        if let Some(exists) = self.recent_exists(&key) {
+            self.exists_reused.fetch_add(1, Ordering::Relaxed);
+            return Ok(exists);
+        }
+        let exists = self
+            .exists
+            .run(key.clone(), self.storage.exists(id), |e| Ok(*e), Ok)
+            .await?;
+        self.remember_exists(key, exists);
+
+        Ok(exists)
+    }
+
+    async fn exists_with(&self, id: &ITEM::ID, options: &OpOptions) -> Result<bool> {
+        if *options == OpOptions::default() {
+            return self.exists(id).await;
+        }
+        self.storage.exists_with(id, options).await
+    }
+
+    async fn load(&self, id: &ITEM::ID) -> Result<ITEM> {
+        self.loads
+            .run(
+                id.to_string(),
+                self.storage.load(id),
+                |item| item.serialize(),
+                |data| deserialize_item::<ITEM>(&data),
+            )
+            .await
+    }
+
+    async fn load_with(&self, id: &ITEM::ID, options: &OpOptions) -> Result<ITEM> {
+        if *options == OpOptions::default() {
+            return self.load(id).await;
+        }
+        self.storage.load_with(id, options).await
+    }
+
+    async fn load_many(&self, ids: &[ITEM::ID]) -> Result<Vec<Option<ITEM>>> {
+        self.storage.load_many(ids).await
+    }
+
+    async fn prefetch(&self, ids: &[ITEM::ID]) -> Result<()> {
+        self.storage.prefetch(ids).await
+    }
+
+    async fn save(&self, id: &ITEM::ID, item: &ITEM, lock: &StorageLock) -> Result<()> {
+        let result = self.storage.save(id, item, lock).await;
+        self.forget(id);
+
+        result
+    }
+
+    async fn lock(&self, id: &ITEM::ID, who: &str) -> Result<LockResult<ITEM>> {
+        let result = self.storage.lock(id, who).await;
+        // locking creates new items
+        self.forget(id);
+
+        result
+    }
+
+    async fn unlock(&self, id: &ITEM::ID, lock: StorageLock) -> Result<()> {
+        let result = self.storage.unlock(id, lock).await;
+        // unlocking removes items that were never saved
+        self.forget(id);
+
+        result
+    }
+
+    async fn handoff_lock(
+        &self,
+        id: &ITEM::ID,
+        lock: StorageLock,
+        new_who: &str,
+    ) -> Result<StorageLock> {
+        self.storage.handoff_lock(id, lock, new_who).await
+    }
+
+    async fn save_and_unlock(&self, id: &ITEM::ID, item: &ITEM, lock: StorageLock) -> Result<()> {
+        let result = self.storage.save_and_unlock(id, item, lock).await;
+        self.forget(id);
+
+        result
+    }
+
+    async fn load_raw(&self, id: &ITEM::ID) -> Result<Vec<u8>> {
+        self.raw_loads
+            .run(
+                id.to_string(),
+                self.storage.load_raw(id),
+                |data| Ok(data.clone()),
+                Ok,
+            )
+            .await
+    }
+
+    async fn save_raw(&self, id: &ITEM::ID, data: &[u8], lock: &StorageLock) -> Result<()> {
+        let result = self.storage.save_raw(id, data, lock).await;
+        self.forget(id);
+
+        result
+    }
+
+    async fn put_blob(
+        &self,
+        id: &ITEM::ID,
+        name: &str,
+        data: &[u8],
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.storage.put_blob(id, name, data, lock).await
+    }
+
+    async fn get_blob(&self, id: &ITEM::ID, name: &str) -> Result<Option<Vec<u8>>> {
+        self.storage.get_blob(id, name).await
+    }
+
+    async fn list_blobs(&self, id: &ITEM::ID) -> Result<Vec<String>> {
+        self.storage.list_blobs(id).await
+    }
+
+    async fn put_meta(&self, key: &str, value: &[u8]) -> Result<()> {
+        self.storage.put_meta(key, value).await
+    }
+
+    async fn get_meta(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.storage.get_meta(key).await
+    }
+
+    async fn add_alias(&self, alias: &ITEM::ID, canonical: &ITEM::ID) -> Result<()> {
+        self.storage.add_alias(alias, canonical).await
+    }
+
+    async fn remove_alias(&self, alias: &ITEM::ID) -> Result<()> {
+        self.storage.remove_alias(alias).await
+    }
+
+    async fn resolve_alias(&self, id: &ITEM::ID) -> Result<ITEM::ID> {
+        self.storage.resolve_alias(id).await
+    }
+
+    async fn add_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.storage.add_link(from, relation, to, lock).await
+    }
+
+    async fn remove_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.storage.remove_link(from, relation, to, lock).await
+    }
+
+    async fn links_of(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        self.storage.links_of(id, relation).await
+    }
+
+    async fn linked_from(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        self.storage.linked_from(id, relation).await
+    }
+
+    async fn force_unlock(&self, id: &ITEM::ID) -> Result<Option<StorageLock>> {
+        self.storage.force_unlock(id).await
+    }
+
+    async fn force_unlock_if_held(
+        &self,
+        id: &ITEM::ID,
+        who: &str,
+        when: DateTime<Utc>,
+    ) -> Result<bool> {
+        self.storage.force_unlock_if_held(id, who, when).await
+    }
+
+    async fn remove_orphaned_locks(&self, max_age: Duration) -> Result<usize> {
+        let result = self.storage.remove_orphaned_locks(max_age).await;
+        self.exists.forget_all();
+        self.recent_exists.lock().expect("can lock").clear();
+
+        result
+    }
+
+    async fn verify_lock(&self, id: &ITEM::ID, lock: &StorageLock) -> Result<bool> {
+        self.storage.verify_lock(id, lock).await
+    }
+
+    async fn verify_lock_with(
+        &self,
+        id: &ITEM::ID,
+        lock: &StorageLock,
+        options: &OpOptions,
+    ) -> Result<bool> {
+        self.storage.verify_lock_with(id, lock, options).await
+    }
+
+    async fn all_ids(&self) -> Result<Vec<ITEM::ID>> {
+        self.storage.all_ids().await
+    }
+
+    async fn scan_ids(&self, start: Option<&str>, limit: Option<usize>) -> Result<Page<ITEM::ID>> {
+        self.storage.scan_ids(start, limit).await
+    }
+
+    async fn scan_ids_with_prefix(
+        &self,
+        prefix: &str,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.storage
+            .scan_ids_with_prefix(prefix, start, limit)
+            .await
+    }
+
+    async fn scan_ids_filtered(
+        &self,
+        filter: &crate::IdFilter,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.storage.scan_ids_filtered(filter, start, limit).await
+    }
+
+    async fn scan_ids_between(
+        &self,
+        from: &ITEM::ID,
+        to: &ITEM::ID,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.storage.scan_ids_between(from, to, start, limit).await
+    }
+
+    async fn list(
+        &self,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<crate::ItemSummary<ITEM::ID>>> {
+        self.storage.list(start, limit).await
+    }
+
+    async fn item_size(&self, id: &ITEM::ID) -> Result<u64> {
+        self.storage.item_size(id).await
+    }
+
+    async fn estimate_cost(&self) -> Result<crate::StorageCost> {
+        self.storage.estimate_cost().await
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        self.storage.shutdown().await
+    }
+
+    async fn fsck(&self, options: &crate::FsckOptions) -> Result<crate::FsckReport> {
+        self.storage.fsck(options).await
+    }
+
+    async fn display_lock(&self, id: &ITEM::ID) -> Result<String> {
+        self.storage.display_lock(id).await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_highest_seen_id(&self) -> Option<ITEM::ID> {
+        self.storage.metadata_highest_seen_id().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_item_count(&self) -> Option<u64> {
+        self.storage.metadata_item_count().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_total_bytes(&self) -> Option<u64> {
+        self.storage.metadata_total_bytes().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_last_write(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.storage.metadata_last_write().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_lock_stats(&self) -> crate::LockStats {
+        self.storage.metadata_lock_stats().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_snapshot(&self) -> crate::MetadataSnapshot {
+        self.storage.metadata_snapshot().await
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn wipe(
+        &self,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        let result = self.storage.wipe(options, progress, cancel).await;
+        self.forget_all();
+
+        result
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn wipe_matching(
+        &self,
+        filter: &crate::IdFilter,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        let result = self
+            .storage
+            .wipe_matching(filter, options, progress, cancel)
+            .await;
+        self.forget_all();
+
+        result
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn delete_many(
+        &self,
+        ids: &[ITEM::ID],
+        options: &crate::DeleteOptions,
+    ) -> Result<Vec<crate::DeleteOutcome>> {
+        let result = self.storage.delete_many(ids, options).await;
+        for id in ids {
+            self.forget(id);
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_item::TestItem;
+    use crate::MockResponse;
+    use crate::Storage;
+    use crate::StorageError;
+    use crate::StorageMock;
+    use crate::StorageOperation;
+    use crate::StorageSingleFlight;
+    use color_eyre::Result;
+    use std::time::Duration;
+
+    fn calls(
+        storage: &StorageSingleFlight<TestItem, StorageMock<TestItem>>,
+        operation: StorageOperation,
+    ) -> usize {
+        storage
+            .storage()
+            .calls()
+            .iter()
+            .filter(|c| c.operation == operation)
+            .count()
+    }
+
+    #[tokio::test]
+    async fn it_coalesces_concurrent_loads() -> Result<()> {
+        let storage = StorageSingleFlight::new(StorageMock::<TestItem>::default());
+        let id = String::from("1");
+        let delay = Some(Duration::from_millis(50));
+        let item = TestItem { count: 7 };
+        let mock = storage.storage();
+        mock.script_delayed(StorageOperation::Load, MockResponse::Item(item), delay);
+
+        let items = futures_util::future::try_join_all((0..4).map(|_| storage.load(&id))).await?;
+        assert!(items.iter().all(|i| *i == TestItem { count: 7 }));
+        assert_eq!(1, calls(&storage, StorageOperation::Load));
+        assert_eq!(3, storage.coalesced_loads());
+
+        // errors are shared too, and stay matchable
+        let not_found = StorageError::NotFound { id: id.clone() };
+        let mock = storage.storage();
+        mock.script_delayed(
+            StorageOperation::Load,
+            MockResponse::Err(not_found.into()),
+            delay,
+        );
+        let (a, b) = tokio::join!(storage.load(&id), storage.load(&id));
+        for e in [a.unwrap_err(), b.unwrap_err()] {
+            assert!(matches!(
+                e.downcast_ref(),
+                Some(StorageError::NotFound { .. })
+            ));
+        }
+        assert_eq!(2, calls(&storage, StorageOperation::Load));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_starts_a_new_flight_after_saves() -> Result<()> {
+        let storage = StorageSingleFlight::new(StorageMock::<TestItem>::default());
+        let id = String::from("1");
+        let mock = storage.storage();
+        mock.script_delayed(
+            StorageOperation::Load,
+            MockResponse::Item(TestItem { count: 1 }),
+            Some(Duration::from_millis(50)),
+        );
+        mock.script(
+            StorageOperation::Load,
+            MockResponse::Item(TestItem { count: 2 }),
+        );
+
+        let early = storage.load(&id);
+        let late = async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            let (lock, _) = storage.lock(&id, "test").await?.success()?;
+            storage
+                .save_and_unlock(&id, &TestItem { count: 2 }, lock)
+                .await?;
+            storage.load(&id).await
+        };
+        let (early, late) = tokio::join!(early, late);
+        assert_eq!(TestItem { count: 1 }, early?);
+        assert_eq!(TestItem { count: 2 }, late?);
+        assert_eq!(0, storage.coalesced_loads());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_reuses_recent_exists_results() -> Result<()> {
+        let mut storage = StorageSingleFlight::new(StorageMock::<TestItem>::default());
+        storage.set_exists_ttl(Some(Duration::from_secs(60)))?;
+        let id = String::from("1");
+        storage
+            .storage()
+            .script(StorageOperation::Exists, MockResponse::Bool(true));
+
+        assert!(storage.exists(&id).await?);
+        assert!(storage.exists(&id).await?);
+        assert_eq!(1, calls(&storage, StorageOperation::Exists));
+        assert_eq!(1, storage.coalesced_exists());
+
+        let (lock, _) = storage.lock(&id, "test").await?.success()?;
+        storage.unlock(&id, lock).await?;
+        assert!(!storage.exists(&id).await?);
+        assert_eq!(2, calls(&storage, StorageOperation::Exists));
+
+        Ok(())
+    }
+}
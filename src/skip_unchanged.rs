@@ -0,0 +1,492 @@
+use crate::LockResult;
+use crate::OpOptions;
+use crate::Page;
+use crate::Storage;
+use crate::StorageItem;
+use crate::StorageLock;
+use async_trait::async_trait;
+use chrono::DateTime;
+use chrono::Utc;
+use color_eyre::eyre::Result;
+use core::marker::PhantomData;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Wraps any [Storage] and skips saves that wouldn't change the item.
+///
+/// Locking an existing item remembers the hash of its content, see [StorageItem::content_hash],
+/// and saves under that lock only reach the backend if the hash changed since.
+/// `save_and_unlock` of an unchanged item only unlocks it.
+///
+/// Note: Every successful lock also checks whether the item exists, to never skip the first save of a new item.
+/// Skipped saves don't check the lock, use [Storage::verify_lock] if it might have been force unlocked elsewhere.
+#[derive(Debug)]
+pub struct StorageSkipUnchanged<ITEM: StorageItem, S: Storage<ITEM>>
+where
+    ITEM: Send,
+{
+    storage: S,
+    /// The lock and the content hash of what is stored, for items locked via this wrapper
+    hashes: Mutex<HashMap<String, (StorageLock, u64)>>,
+    /// Keyed per instance, so colliding content can't be crafted
+    hasher: RandomState,
+    skipped_saves: AtomicU64,
+    item_type: PhantomData<ITEM>,
+}
+
+impl<ITEM: StorageItem + Send, S: Storage<ITEM>> StorageSkipUnchanged<ITEM, S> {
+    pub fn new(storage: S) -> Self {
+        Self {
+            storage,
+            hashes: Mutex::new(HashMap::new()),
+            hasher: RandomState::new(),
+            skipped_saves: AtomicU64::new(0),
+            item_type: PhantomData,
+        }
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    pub fn into_inner(self) -> S {
+        self.storage
+    }
+
+    /// How many saves were skipped so far.
+    pub fn skipped_saves(&self) -> u64 {
+        self.skipped_saves.load(Ordering::Relaxed)
+    }
+
+    fn hash(&self, item: &ITEM) -> Result<u64> {
+        match item.content_hash() {
+            Some(hash) => Ok(hash),
+            None => Ok(self.hasher.hash_one(item.serialize()?)),
+        }
+    }
+
+    fn is_unchanged(&self, id: &ITEM::ID, lock: &StorageLock, hash: u64) -> bool {
+        let hashes = self.hashes.lock().expect("can lock");
+        hashes
+            .get(&id.to_string())
+            .is_some_and(|(tracked, tracked_hash)| tracked == lock && *tracked_hash == hash)
+    }
+
+    fn track(&self, id: &ITEM::ID, lock: &StorageLock, hash: u64) {
+        self.hashes
+            .lock()
+            .expect("can lock")
+            .insert(id.to_string(), (lock.duplicate(), hash));
+    }
+
+    fn forget(&self, id: &ITEM::ID) {
+        self.hashes
+            .lock()
+            .expect("can lock")
+            .remove(&id.to_string());
+    }
+}
+
+#[async_trait]
+impl<ITEM: StorageItem + Send, S: Storage<ITEM>> Storage<ITEM> for StorageSkipUnchanged<ITEM, S> {
+    async fn ensure_storage_exists(&mut self) -> Result<()> {
+        self.storage.ensure_storage_exists().await
+    }
+
+    fn capabilities(&self) -> crate::Capabilities {
+        self.storage.capabilities()
+    }
+
+    async fn create(&self) -> Result<ITEM::ID> {
+        self.storage.create().await
+    }
+
+    async fn exists(&self, id: &ITEM::ID) -> Result<bool> {
+        self.storage.exists(id).await
+    }
+
+    async fn exists_with(&self, id: &ITEM::ID, options: &OpOptions) -> Result<bool> {
+        self.storage.exists_with(id, options).await
+    }
+
+    async fn load(&self, id: &ITEM::ID) -> Result<ITEM> {
+        self.storage.load(id).await
+    }
+
+    async fn load_with(&self, id: &ITEM::ID, options: &OpOptions) -> Result<ITEM> {
+        self.storage.load_with(id, options).await
+    }
+
+    async fn load_many(&self, ids: &[ITEM::ID]) -> Result<Vec<Option<ITEM>>> {
+        self.storage.load_many(ids).await
+    }
+
+    async fn prefetch(&self, ids: &[ITEM::ID]) -> Result<()> {
+        self.storage.prefetch(ids).await
+    }
+
+    async fn save(&self, id: &ITEM::ID, item: &ITEM, lock: &StorageLock) -> Result<()> {
+        let hash = self.hash(item)?;
+        if self.is_unchanged(id, lock, hash) {
+            self.skipped_saves.fetch_add(1, Ordering::Relaxed);
+            return Ok(());
+        }
+        self.storage.save(id, item, lock).await?;
+        self.track(id, lock, hash);
+
+        Ok(())
+    }
+
+    async fn lock(&self, id: &ITEM::ID, who: &str) -> Result<LockResult<ITEM>> {
+        let result = self.storage.lock(id, who).await?;
+        if let LockResult::Success { lock, item } = &result {
+            // the default item of a new one still needs its first save
+            if self.storage.exists(id).await? {
+                self.track(id, lock, self.hash(item)?);
+            }
+        }
+
+        Ok(result)
+    }
+
+    async fn unlock(&self, id: &ITEM::ID, lock: StorageLock) -> Result<()> {
+        self.forget(id);
+        self.storage.unlock(id, lock).await
+    }
+
+    async fn handoff_lock(
+        &self,
+        id: &ITEM::ID,
+        lock: StorageLock,
+        new_who: &str,
+    ) -> Result<StorageLock> {
+        self.storage.handoff_lock(id, lock, new_who).await
+    }
+
+    async fn save_and_unlock(&self, id: &ITEM::ID, item: &ITEM, lock: StorageLock) -> Result<()> {
+        let hash = self.hash(item)?;
+        if self.is_unchanged(id, &lock, hash) {
+            self.skipped_saves.fetch_add(1, Ordering::Relaxed);
+            return self.unlock(id, lock).await;
+        }
+        self.forget(id);
+        self.storage.save_and_unlock(id, item, lock).await
+    }
+
+    async fn load_raw(&self, id: &ITEM::ID) -> Result<Vec<u8>> {
+        self.storage.load_raw(id).await
+    }
+
+    async fn save_raw(&self, id: &ITEM::ID, data: &[u8], lock: &StorageLock) -> Result<()> {
+        self.forget(id);
+        self.storage.save_raw(id, data, lock).await
+    }
+
+    async fn put_blob(
+        &self,
+        id: &ITEM::ID,
+        name: &str,
+        data: &[u8],
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.storage.put_blob(id, name, data, lock).await
+    }
+
+    async fn get_blob(&self, id: &ITEM::ID, name: &str) -> Result<Option<Vec<u8>>> {
+        self.storage.get_blob(id, name).await
+    }
+
+    async fn list_blobs(&self, id: &ITEM::ID) -> Result<Vec<String>> {
+        self.storage.list_blobs(id).await
+    }
+
+    async fn put_meta(&self, key: &str, value: &[u8]) -> Result<()> {
+        self.storage.put_meta(key, value).await
+    }
+
+    async fn get_meta(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.storage.get_meta(key).await
+    }
+
+    async fn add_alias(&self, alias: &ITEM::ID, canonical: &ITEM::ID) -> Result<()> {
+        self.storage.add_alias(alias, canonical).await
+    }
+
+    async fn remove_alias(&self, alias: &ITEM::ID) -> Result<()> {
+        self.storage.remove_alias(alias).await
+    }
+
+    async fn resolve_alias(&self, id: &ITEM::ID) -> Result<ITEM::ID> {
+        self.storage.resolve_alias(id).await
+    }
+
+    async fn add_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.storage.add_link(from, relation, to, lock).await
+    }
+
+    async fn remove_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.storage.remove_link(from, relation, to, lock).await
+    }
+
+    async fn links_of(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        self.storage.links_of(id, relation).await
+    }
+
+    async fn linked_from(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        self.storage.linked_from(id, relation).await
+    }
+
+    async fn force_unlock(&self, id: &ITEM::ID) -> Result<Option<StorageLock>> {
+        self.forget(id);
+        self.storage.force_unlock(id).await
+    }
+
+    async fn force_unlock_if_held(
+        &self,
+        id: &ITEM::ID,
+        who: &str,
+        when: DateTime<Utc>,
+    ) -> Result<bool> {
+        self.forget(id);
+        self.storage.force_unlock_if_held(id, who, when).await
+    }
+
+    async fn remove_orphaned_locks(&self, max_age: Duration) -> Result<usize> {
+        self.storage.remove_orphaned_locks(max_age).await
+    }
+
+    async fn verify_lock(&self, id: &ITEM::ID, lock: &StorageLock) -> Result<bool> {
+        self.storage.verify_lock(id, lock).await
+    }
+
+    async fn verify_lock_with(
+        &self,
+        id: &ITEM::ID,
+        lock: &StorageLock,
+        options: &OpOptions,
+    ) -> Result<bool> {
+        self.storage.verify_lock_with(id, lock, options).await
+    }
+
+    async fn all_ids(&self) -> Result<Vec<ITEM::ID>> {
+        self.storage.all_ids().await
+    }
+
+    async fn scan_ids(&self, start: Option<&str>, limit: Option<usize>) -> Result<Page<ITEM::ID>> {
+        self.storage.scan_ids(start, limit).await
+    }
+
+    async fn scan_ids_with_prefix(
+        &self,
+        prefix: &str,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.storage
+            .scan_ids_with_prefix(prefix, start, limit)
+            .await
+    }
+
+    async fn scan_ids_filtered(
+        &self,
+        filter: &crate::IdFilter,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.storage.scan_ids_filtered(filter, start, limit).await
+    }
+
+    async fn scan_ids_between(
+        &self,
+        from: &ITEM::ID,
+        to: &ITEM::ID,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.storage.scan_ids_between(from, to, start, limit).await
+    }
+
+    async fn list(
+        &self,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<crate::ItemSummary<ITEM::ID>>> {
+        self.storage.list(start, limit).await
+    }
+
+    async fn item_size(&self, id: &ITEM::ID) -> Result<u64> {
+        self.storage.item_size(id).await
+    }
+
+    async fn estimate_cost(&self) -> Result<crate::StorageCost> {
+        self.storage.estimate_cost().await
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        self.storage.shutdown().await
+    }
+
+    async fn fsck(&self, options: &crate::FsckOptions) -> Result<crate::FsckReport> {
+        self.storage.fsck(options).await
+    }
+
+    async fn display_lock(&self, id: &ITEM::ID) -> Result<String> {
+        self.storage.display_lock(id).await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_highest_seen_id(&self) -> Option<ITEM::ID> {
+        self.storage.metadata_highest_seen_id().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_item_count(&self) -> Option<u64> {
+        self.storage.metadata_item_count().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_total_bytes(&self) -> Option<u64> {
+        self.storage.metadata_total_bytes().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_last_write(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.storage.metadata_last_write().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_lock_stats(&self) -> crate::LockStats {
+        self.storage.metadata_lock_stats().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_snapshot(&self) -> crate::MetadataSnapshot {
+        self.storage.metadata_snapshot().await
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn wipe(
+        &self,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        if !options.is_dry_run() {
+            self.hashes.lock().expect("can lock").clear();
+        }
+        self.storage.wipe(options, progress, cancel).await
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn wipe_matching(
+        &self,
+        filter: &crate::IdFilter,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        if !options.is_dry_run() {
+            self.hashes.lock().expect("can lock").clear();
+        }
+        self.storage
+            .wipe_matching(filter, options, progress, cancel)
+            .await
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn delete_many(
+        &self,
+        ids: &[ITEM::ID],
+        options: &crate::DeleteOptions,
+    ) -> Result<Vec<crate::DeleteOutcome>> {
+        for id in ids {
+            self.forget(id);
+        }
+        self.storage.delete_many(ids, options).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_item::TestItem;
+    use crate::MockResponse;
+    use crate::Storage;
+    use crate::StorageMock;
+    use crate::StorageOperation;
+    use crate::StorageSkipUnchanged;
+    use color_eyre::eyre::eyre;
+    use color_eyre::Result;
+
+    fn saves(storage: &StorageSkipUnchanged<TestItem, StorageMock<TestItem>>) -> usize {
+        storage
+            .storage()
+            .calls()
+            .iter()
+            .filter(|c| c.operation == StorageOperation::Save)
+            .count()
+    }
+
+    #[tokio::test]
+    async fn it_skips_unchanged_saves() -> Result<()> {
+        let storage = StorageSkipUnchanged::new(StorageMock::<TestItem>::default());
+        let id = String::from("1");
+        storage
+            .storage()
+            .script(StorageOperation::Exists, MockResponse::Bool(true));
+        let (lock, mut item) = storage.lock(&id, "test").await?.success()?;
+
+        storage.save(&id, &item, &lock).await?;
+        assert_eq!(0, saves(&storage));
+        item.count = 1;
+        storage.save(&id, &item, &lock).await?;
+        storage.save(&id, &item, &lock).await?;
+        assert_eq!(1, saves(&storage));
+        assert_eq!(2, storage.skipped_saves());
+        storage.unlock(&id, lock).await?;
+
+        // new items are always saved
+        let (lock, item) = storage.lock(&id, "test").await?.success()?;
+        storage.save(&id, &item, &lock).await?;
+        assert_eq!(2, saves(&storage));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_doesnt_skip_saves_after_a_failed_save() -> Result<()> {
+        let storage = StorageSkipUnchanged::new(StorageMock::<TestItem>::default());
+        let id = String::from("1");
+        storage
+            .storage()
+            .script(StorageOperation::Exists, MockResponse::Bool(true));
+        let (lock, _) = storage.lock(&id, "test").await?.success()?;
+
+        storage
+            .storage()
+            .script(StorageOperation::Save, MockResponse::Err(eyre!("down")));
+        let item = TestItem { count: 1 };
+        assert!(storage.save(&id, &item, &lock).await.is_err());
+        storage.save(&id, &item, &lock).await?;
+        assert_eq!(2, saves(&storage));
+        assert_eq!(0, storage.skipped_saves());
+
+        Ok(())
+    }
+}
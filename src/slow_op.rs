@@ -0,0 +1,80 @@
+use std::fmt::Display;
+use std::time::Duration;
+use std::time::Instant;
+
+/// Warns when dropped later than the threshold after [SlowOp::start],
+/// and records the operation in [crate::operation_metrics], with or without a threshold.
+///
+/// Backends start one at the top of every operation, e.g.
+/// `let _slow_op = SlowOp::start(self.slow_op_threshold, "disk", "save", Some(id));`
+#[derive(Debug)]
+pub(crate) struct SlowOp {
+    /// `None` if no threshold is configured, nothing is tracked then.
+    started: Option<(Instant, Duration)>,
+    #[cfg(feature = "metrics")]
+    measured: Instant,
+    backend: &'static str,
+    operation: &'static str,
+    id: Option<String>,
+}
+
+impl SlowOp {
+    pub fn start(
+        threshold: Option<Duration>,
+        backend: &'static str,
+        operation: &'static str,
+        id: Option<&dyn Display>,
+    ) -> Self {
+        let started = threshold.map(|threshold| (Instant::now(), threshold));
+        Self {
+            started,
+            #[cfg(feature = "metrics")]
+            measured: Instant::now(),
+            backend,
+            operation,
+            id: started.and(id).map(|id| id.to_string()),
+        }
+    }
+}
+
+impl Drop for SlowOp {
+    fn drop(&mut self) {
+        #[cfg(feature = "metrics")]
+        crate::operation_metrics::record_operation(
+            self.backend,
+            self.operation,
+            self.measured.elapsed(),
+        );
+        let Some((start, threshold)) = self.started else {
+            return;
+        };
+        let duration = start.elapsed();
+        if duration > threshold {
+            tracing::warn!(
+                backend = self.backend,
+                operation = self.operation,
+                id = self.id.as_deref(),
+                duration_ms = duration.as_millis() as u64,
+                threshold_ms = threshold.as_millis() as u64,
+                "Slow storage operation"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::slow_op::SlowOp;
+    use std::time::Duration;
+
+    #[test]
+    fn it_only_tracks_with_threshold() {
+        let slow_op = SlowOp::start(None, "null", "load", Some(&"item"));
+        assert!(slow_op.started.is_none());
+        assert!(slow_op.id.is_none());
+
+        let slow_op = SlowOp::start(Some(Duration::ZERO), "null", "load", Some(&"item"));
+        assert!(slow_op.started.is_some());
+        assert_eq!(Some("item"), slow_op.id.as_deref());
+    }
+}
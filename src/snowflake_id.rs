@@ -0,0 +1,167 @@
+use crate::StorageId;
+use chrono::DateTime;
+use chrono::Utc;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+const NODE_ID_BITS: u32 = 10;
+const SEQUENCE_BITS: u32 = 12;
+const MAX_NODE_ID: u64 = (1 << NODE_ID_BITS) - 1;
+const MAX_SEQUENCE: u64 = (1 << SEQUENCE_BITS) - 1;
+/// 2024-01-01T00:00:00Z
+const DEFAULT_EPOCH_MS: u64 = 1_704_067_200_000;
+
+static NODE_ID: AtomicU64 = AtomicU64::new(0);
+static EPOCH_MS: AtomicU64 = AtomicU64::new(DEFAULT_EPOCH_MS);
+/// The last generated (timestamp << SEQUENCE_BITS | sequence)
+static LAST: AtomicU64 = AtomicU64::new(0);
+
+/// A 64 bit id made of a millisecond timestamp, a node id, and a sequence number.
+///
+/// Every instance uses its own node id, see [SnowflakeId::set_node_id],
+/// so instances can generate ids without coordination.
+/// Ids are roughly ordered by creation time.
+///
+/// Layout: 42 bits milliseconds since the epoch, 10 bits node id, 12 bits sequence.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SnowflakeId(u64);
+
+impl SnowflakeId {
+    /// Sets the node id used for all new ids in this process, must be below 1024.
+    pub fn set_node_id(node_id: u16) -> Result<()> {
+        if node_id as u64 > MAX_NODE_ID {
+            return Err(eyre!("Node id {node_id} too large, max is {MAX_NODE_ID}"));
+        }
+        NODE_ID.store(node_id as u64, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    /// Sets the epoch used for all new ids in this process, defaults to 2024-01-01.
+    /// Must be the same for all instances and never change once ids exist.
+    pub fn set_epoch(epoch: DateTime<Utc>) -> Result<()> {
+        let epoch_ms = u64::try_from(epoch.timestamp_millis())
+            .map_err(|_| eyre!("Epoch {epoch} is before 1970"))?;
+        EPOCH_MS.store(epoch_ms, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    pub fn value(&self) -> u64 {
+        self.0
+    }
+
+    /// The lowest id generated at `time`, e.g. as bound for [crate::Storage::scan_ids_between].
+    pub fn first_at(time: DateTime<Utc>) -> Self {
+        let epoch_ms = EPOCH_MS.load(Ordering::Relaxed);
+        let ms = (time.timestamp_millis().max(0) as u64).saturating_sub(epoch_ms);
+        Self(ms << (NODE_ID_BITS + SEQUENCE_BITS))
+    }
+
+    /// Milliseconds since the epoch
+    pub fn timestamp(&self) -> u64 {
+        self.0 >> (NODE_ID_BITS + SEQUENCE_BITS)
+    }
+
+    pub fn node_id(&self) -> u16 {
+        ((self.0 >> SEQUENCE_BITS) & MAX_NODE_ID) as u16
+    }
+
+    pub fn sequence(&self) -> u16 {
+        (self.0 & MAX_SEQUENCE) as u16
+    }
+}
+
+impl From<u64> for SnowflakeId {
+    fn from(value: u64) -> Self {
+        Self(value)
+    }
+}
+
+impl std::fmt::Display for SnowflakeId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl StorageId for SnowflakeId {
+    fn from_string(id: &str) -> Result<Self> {
+        if !Self::is_valid_format(id) {
+            return Err(eyre!("Invalid snowflake id {id}"));
+        }
+        Ok(Self(id.parse()?))
+    }
+
+    fn generate_new(_previous: Option<&Self>) -> Self {
+        let epoch_ms = EPOCH_MS.load(Ordering::Relaxed);
+        let now_ms = (Utc::now().timestamp_millis().max(0) as u64).saturating_sub(epoch_ms);
+        let mut last = LAST.load(Ordering::Relaxed);
+        loop {
+            let last_ms = last >> SEQUENCE_BITS;
+            let next = if now_ms > last_ms {
+                now_ms << SEQUENCE_BITS
+            } else if last & MAX_SEQUENCE < MAX_SEQUENCE {
+                last + 1
+            } else {
+                // sequence exhausted, or the clock went backwards: borrow the next millisecond
+                (last_ms + 1) << SEQUENCE_BITS
+            };
+            match LAST.compare_exchange_weak(last, next, Ordering::Relaxed, Ordering::Relaxed) {
+                Ok(_) => {
+                    let timestamp = next >> SEQUENCE_BITS;
+                    let sequence = next & MAX_SEQUENCE;
+                    let node_id = NODE_ID.load(Ordering::Relaxed);
+                    return Self(
+                        timestamp << (NODE_ID_BITS + SEQUENCE_BITS)
+                            | node_id << SEQUENCE_BITS
+                            | sequence,
+                    );
+                }
+                Err(current) => last = current,
+            }
+        }
+    }
+
+    fn is_valid_format(id: &str) -> bool {
+        !id.is_empty()
+            && id.len() <= 20
+            && id.bytes().all(|b| b.is_ascii_digit())
+            && id.parse::<u64>().is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::SnowflakeId;
+    use crate::StorageId;
+    use chrono::Duration;
+    use chrono::Utc;
+    use std::collections::HashSet;
+
+    #[test]
+    fn it_generates_unique_ordered_ids() -> color_eyre::Result<()> {
+        SnowflakeId::set_node_id(42)?;
+        assert!(SnowflakeId::set_node_id(1024).is_err());
+
+        let mut ids = Vec::default();
+        for _ in 0..10_000 {
+            ids.push(SnowflakeId::generate_new(None));
+        }
+        let unique: HashSet<_> = ids.iter().collect();
+        assert_eq!(ids.len(), unique.len());
+        assert!(ids.windows(2).all(|w| w[0] < w[1]));
+        assert!(ids.iter().all(|id| id.node_id() == 42));
+
+        let id = ids[0];
+        assert_eq!(id, SnowflakeId::from_string(&id.to_string())?);
+        let now = Utc::now();
+        assert!(SnowflakeId::first_at(now - Duration::minutes(1)) < id);
+        assert!(SnowflakeId::first_at(now + Duration::minutes(1)) > ids[ids.len() - 1]);
+        assert!(!SnowflakeId::is_valid_format("-1"));
+        assert!(!SnowflakeId::is_valid_format("99999999999999999999"));
+
+        Ok(())
+    }
+}
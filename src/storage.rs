@@ -1,3 +1,6 @@
+use crate::storage_item::deserialize_item;
+use crate::Page;
+use crate::StorageError;
 use crate::StorageItem;
 use async_trait::async_trait;
 use chrono::DateTime;
@@ -6,6 +9,132 @@ use color_eyre::eyre::eyre;
 use color_eyre::eyre::Result;
 use serde::Deserialize;
 use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+
+/// The number of ids [Storage::lock_and_load_many] handles at the same time by default.
+pub(crate) const LOCK_MANY_CONCURRENCY: usize = 16;
+
+async fn lock_existing<ITEM, S>(storage: &S, id: &ITEM::ID, who: &str) -> LockManyOutcome<ITEM>
+where
+    ITEM: StorageItem + Send,
+    S: Storage<ITEM> + ?Sized,
+{
+    match storage.exists(id).await {
+        Ok(true) => {}
+        Ok(false) => return LockManyOutcome::NotFound,
+        Err(e) => return LockManyOutcome::Failed(e),
+    }
+    let (lock, item) = match storage.lock(id, who).await {
+        Ok(LockResult::Success { lock, item }) => (lock, item),
+        Ok(result) => return result.into(),
+        Err(e) => return LockManyOutcome::Failed(e),
+    };
+    // removed between the check, and the lock, which handed out a default item
+    match storage.load_raw(id).await {
+        Ok(_) => LockManyOutcome::Success { lock, item },
+        Err(e) => {
+            let not_found = matches!(e.downcast_ref(), Some(StorageError::NotFound { .. }));
+            if let Err(unlock_e) = storage.unlock(id, lock).await {
+                tracing::warn!("Can't unlock {id} after failed check -> {unlock_e:?}");
+                return LockManyOutcome::Failed(e);
+            }
+            if not_found {
+                LockManyOutcome::NotFound
+            } else {
+                LockManyOutcome::Failed(e)
+            }
+        }
+    }
+}
+
+/// Finishes [Storage::upsert] and [crate::Repository::upsert] once `lock` is held.
+/// `item` is the one returned by the lock. Returns whether the item was created.
+pub(crate) async fn upsert_locked<ITEM, S>(
+    storage: &S,
+    id: &ITEM::ID,
+    lock: StorageLock,
+    item: ITEM,
+    f: impl FnOnce(Option<ITEM>) -> Result<ITEM> + Send,
+) -> Result<bool>
+where
+    ITEM: StorageItem + Send,
+    S: Storage<ITEM> + ?Sized,
+{
+    // locking hands out a default item for missing ones, only the stored data tells them apart
+    let existing = match storage.load_raw(id).await {
+        Ok(_) => Ok(Some(item)),
+        Err(e) if matches!(e.downcast_ref(), Some(StorageError::NotFound { .. })) => Ok(None),
+        Err(e) => Err(e),
+    };
+    let created = matches!(existing, Ok(None));
+    let result = match existing.and_then(f) {
+        Ok(item) => storage.save_and_unlock(id, &item, lock.duplicate()).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        // a failed save keeps the lock, too, so it mustn't wait for the expiry
+        if let Err(unlock_e) = storage.unlock(id, lock).await {
+            tracing::warn!("Can't unlock {id} after failed upsert -> {unlock_e:?}");
+        }
+        return Err(e);
+    }
+
+    Ok(created)
+}
+
+/// Who holds the lock taken by [probe_storage].
+pub(crate) const PROBE_WHO: &str = "oml-storage-probe";
+
+/// Locks, writes, and reads back `id`, so misconfiguration fails `ensure_storage_exists`,
+/// instead of the first real request.
+/// The item is saved unchanged and kept. `hint` tells what to check for the backend.
+/// Immutable storages only save it once, later probes just lock and read it.
+pub(crate) async fn probe_storage<ITEM, S>(storage: &S, id: &ITEM::ID, hint: &str) -> Result<()>
+where
+    ITEM: StorageItem + Send,
+    S: Storage<ITEM> + ?Sized,
+{
+    let failed = |step: &str, e: color_eyre::Report| {
+        e.wrap_err(format!(
+            "Storage probe of {id} failed to {step}, check {hint}"
+        ))
+    };
+    let mut result = storage.lock(id, PROBE_WHO).await;
+    if let Ok(LockResult::AlreadyLocked { who, .. }) = &result {
+        if who != PROBE_WHO {
+            return Err(eyre!(
+                "Storage probe of {id} failed to lock, it is locked by {who:?}, use an id reserved for probing"
+            ));
+        }
+        // left behind by an interrupted probe
+        storage
+            .force_unlock(id)
+            .await
+            .map_err(|e| failed("unlock", e))?;
+        result = storage.lock(id, PROBE_WHO).await;
+    }
+    let (lock, item) = result
+        .and_then(|result| result.success())
+        .map_err(|e| failed("lock", e))?;
+    match storage.save(id, &item, &lock).await {
+        // the lock was written already
+        Err(e) if matches!(e.downcast_ref(), Some(StorageError::Immutable { .. })) => {}
+        r => r.map_err(|e| failed("write", e))?,
+    }
+    storage
+        .unlock(id, lock)
+        .await
+        .map_err(|e| failed("unlock", e))?;
+    storage.load(id).await.map_err(|e| failed("read", e))?;
+
+    Ok(())
+}
+
+/// A reader over the serialized data of an item, see [Storage::load_stream].
+pub type PayloadReader = Box<dyn AsyncRead + Send + Unpin>;
 
 /// The interface to all storage backends.
 ///
@@ -17,35 +146,514 @@ use serde::Serialize;
 ///
 /// You can just ignore them. In the end the `fn` are just `async` and return a [color_eyre::eyre::Result]
 #[async_trait]
-pub trait Storage<ITEM: StorageItem + Sized>: Send + Sync + std::fmt::Debug {
+pub trait Storage<ITEM: StorageItem + Send + Sized>: Send + Sync + std::fmt::Debug {
     /// Ensure the storage layer actually exists
     async fn ensure_storage_exists(&mut self) -> Result<()>;
 
+    /// What this storage supports, so generic code can check before calling.
+    /// The default claims nothing beyond the required operations.
+    fn capabilities(&self) -> crate::Capabilities {
+        crate::Capabilities::default()
+    }
+
     /// Creates a new item with a random id.
     /// If you want a specific it use [Storage::lock] instead.
     /// Warning: `id` creation is still work-in-progress.
     async fn create(&self) -> Result<ITEM::ID>;
     async fn exists(&self, id: &ITEM::ID) -> Result<bool>;
+    /// Fails with [crate::StorageError::NotFound] if the item doesn't exist.
     async fn load(&self, id: &ITEM::ID) -> Result<ITEM>;
+
+    /// Like [Storage::load], but `None` if the item doesn't exist.
+    async fn try_load(&self, id: &ITEM::ID) -> Result<Option<ITEM>> {
+        match self.load(id).await {
+            Ok(item) => Ok(Some(item)),
+            Err(e) if matches!(e.downcast_ref(), Some(StorageError::NotFound { .. })) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// [Storage::exists] with per call [crate::OpOptions].
+    /// Backends without anything to choose from ignore them.
+    async fn exists_with(&self, id: &ITEM::ID, _options: &crate::OpOptions) -> Result<bool> {
+        self.exists(id).await
+    }
+
+    /// [Storage::load] with per call [crate::OpOptions].
+    async fn load_with(&self, id: &ITEM::ID, _options: &crate::OpOptions) -> Result<ITEM> {
+        self.load(id).await
+    }
+
+    /// Loads multiple items at once.
+    /// The result has the same order as `ids`, with `None` for items that don't exist.
+    ///
+    /// Backends should override this if they can batch requests.
+    async fn load_many(&self, ids: &[ITEM::ID]) -> Result<Vec<Option<ITEM>>> {
+        let mut items = Vec::with_capacity(ids.len());
+        for id in ids {
+            if self.exists(id).await? {
+                items.push(Some(self.load(id).await?));
+            } else {
+                items.push(None);
+            }
+        }
+
+        Ok(items)
+    }
+
+    /// Hints that the items will be loaded soon, e.g. to warm a cache.
+    ///
+    /// Does nothing by default, see [crate::StorageCached].
+    async fn prefetch(&self, _ids: &[ITEM::ID]) -> Result<()> {
+        Ok(())
+    }
     async fn save(&self, id: &ITEM::ID, item: &ITEM, lock: &StorageLock) -> Result<()>;
 
     /// Tries to lock an (existing or new) item
     async fn lock(&self, id: &ITEM::ID, who: &str) -> Result<LockResult<ITEM>>;
     async fn unlock(&self, id: &ITEM::ID, lock: StorageLock) -> Result<()>;
 
-    async fn force_unlock(&self, id: &ITEM::ID) -> Result<()>;
+    /// A view locking as `who` that tracks its locks and releases them together, e.g. per request.
+    ///
+    /// For trait objects use [crate::StorageSession::new].
+    fn session(&self, who: &str) -> crate::StorageSession<'_, ITEM>
+    where
+        Self: Sized,
+    {
+        crate::StorageSession::new(self, who)
+    }
+
+    /// Locks and loads multiple existing items at once, e.g. all players of a match,
+    /// and returns what happened to each of them, in the order of `ids`.
+    ///
+    /// Unlike [Storage::lock] missing items aren't locked, see [LockManyOutcome::NotFound],
+    /// items removed while locking are unlocked again.
+    /// Nothing is rolled back, unlock the acquired locks if not all of them are needed.
+    ///
+    /// Checks and locks the ids concurrently by default.
+    /// Backends should override this if they can do it in fewer requests.
+    async fn lock_and_load_many(
+        &self,
+        ids: &[ITEM::ID],
+        who: &str,
+    ) -> Result<Vec<LockManyOutcome<ITEM>>> {
+        let mut outcomes = Vec::with_capacity(ids.len());
+        for chunk in ids.chunks(LOCK_MANY_CONCURRENCY) {
+            let chunk = chunk.iter().map(|id| lock_existing(self, id, who));
+            outcomes.extend(futures_util::future::join_all(chunk).await);
+        }
+
+        Ok(outcomes)
+    }
+
+    /// Creates or updates the item in one go. `f` gets the stored item or `None` if there is none
+    /// and returns the item to save. Returns whether the item was created.
+    ///
+    /// [Storage::lock] covers both cases atomically, so there is no race between creating and updating.
+    /// Fails with [StorageError::AlreadyLocked] if someone holds the lock, see [crate::Repository::upsert] for retries.
+    /// When `f` or the save fails, the item is only unlocked.
+    async fn upsert<F>(&self, id: &ITEM::ID, who: &str, f: F) -> Result<bool>
+    where
+        Self: Sized,
+        F: FnOnce(Option<ITEM>) -> Result<ITEM> + Send,
+    {
+        match self.lock(id, who).await? {
+            LockResult::Success { lock, item } => upsert_locked(self, id, lock, item, f).await,
+            LockResult::AlreadyLocked { who, .. } => Err(StorageError::AlreadyLocked {
+                id: id.to_string(),
+                who,
+            }
+            .into()),
+        }
+    }
+
+    /// Saves the item and releases the lock.
+    ///
+    /// Backends should override this if they can do both atomically.
+    async fn save_and_unlock(&self, id: &ITEM::ID, item: &ITEM, lock: StorageLock) -> Result<()> {
+        self.save(id, item, &lock).await?;
+        self.unlock(id, lock).await
+    }
+
+    /// Transfers the held `lock` to `new_who` without releasing it and returns the new lock.
+    ///
+    /// The item stays locked throughout, so nobody else can take the lock in between,
+    /// unlike an [Storage::unlock] followed by a [Storage::lock].
+    async fn handoff_lock(
+        &self,
+        _id: &ITEM::ID,
+        _lock: StorageLock,
+        _new_who: &str,
+    ) -> Result<StorageLock> {
+        Err(eyre!("Lock handoff is not supported by this storage"))
+    }
+
+    /// Loads the item as stored, without deserializing it,
+    /// e.g. for tools that move items without knowing their type.
+    ///
+    /// Backends should override this if they can skip the round trip.
+    async fn load_raw(&self, id: &ITEM::ID) -> Result<Vec<u8>> {
+        self.load(id).await?.serialize()
+    }
+
+    /// Saves data as returned by [StorageItem::serialize] or [Storage::load_raw].
+    /// Requires the item to be locked with `lock`.
+    ///
+    /// Backends overriding this store the data as is, without checking it deserializes.
+    async fn save_raw(&self, id: &ITEM::ID, data: &[u8], lock: &StorageLock) -> Result<()> {
+        let item = deserialize_item::<ITEM>(data)?;
+        self.save(id, &item, lock).await
+    }
+
+    /// Returns the data of [Storage::load_raw] as a reader, e.g. for multi-megabyte items.
+    ///
+    /// Backends that can't stream load the data into memory first.
+    async fn load_stream(&self, id: &ITEM::ID) -> Result<PayloadReader> {
+        let data = self.load_raw(id).await?;
+        Ok(Box::new(std::io::Cursor::new(data)))
+    }
+
+    /// Saves the data read from `reader`, like [Storage::save_raw].
+    /// Requires the item to be locked with `lock`.
+    ///
+    /// Backends that can't stream read all of it into memory first.
+    async fn save_stream(
+        &self,
+        id: &ITEM::ID,
+        reader: &mut (dyn AsyncRead + Send + Unpin),
+        lock: &StorageLock,
+    ) -> Result<()> {
+        let mut data = Vec::default();
+        reader.read_to_end(&mut data).await?;
+        self.save_raw(id, &data, lock).await
+    }
+
+    /// Stores a binary attachment `name` for the item, replacing an existing one.
+    /// Requires the item to be locked with `lock`.
+    ///
+    /// Use this for large data, e.g. images, that shouldn't be part of the serialized item.
+    async fn put_blob(
+        &self,
+        _id: &ITEM::ID,
+        _name: &str,
+        _data: &[u8],
+        _lock: &StorageLock,
+    ) -> Result<()> {
+        Err(eyre!("Blobs are not supported by this storage"))
+    }
+
+    /// Returns the attachment `name` of the item, or `None` if it doesn't exist.
+    async fn get_blob(&self, _id: &ITEM::ID, _name: &str) -> Result<Option<Vec<u8>>> {
+        Err(eyre!("Blobs are not supported by this storage"))
+    }
+
+    /// Returns the names of all attachments of the item.
+    async fn list_blobs(&self, _id: &ITEM::ID) -> Result<Vec<String>> {
+        Err(eyre!("Blobs are not supported by this storage"))
+    }
+
+    /// Stores a small storage wide value, e.g. a schema version or migration progress,
+    /// in a keyspace separate from the items, replacing an existing one.
+    ///
+    /// Keys are `[A-Za-z0-9_.-]`, see [Storage::get_meta].
+    async fn put_meta(&self, _key: &str, _value: &[u8]) -> Result<()> {
+        Err(eyre!("Meta values are not supported by this storage"))
+    }
+
+    /// Returns the value stored via [Storage::put_meta], or `None` if it doesn't exist.
+    async fn get_meta(&self, _key: &str) -> Result<Option<Vec<u8>>> {
+        Err(eyre!("Meta values are not supported by this storage"))
+    }
+
+    /// Makes the item `canonical` also reachable as `alias`, e.g. via a legacy or external platform id.
+    /// Item operations on `alias` act on `canonical`.
+    async fn add_alias(&self, _alias: &ITEM::ID, _canonical: &ITEM::ID) -> Result<()> {
+        Err(eyre!("Aliases are not supported by this storage"))
+    }
+
+    async fn remove_alias(&self, _alias: &ITEM::ID) -> Result<()> {
+        Err(eyre!("Aliases are not supported by this storage"))
+    }
+
+    /// Returns the canonical id for `id`, or `id` itself if it isn't an alias.
+    async fn resolve_alias(&self, id: &ITEM::ID) -> Result<ITEM::ID> {
+        Ok(id.clone())
+    }
+
+    /// Links the item `from` to the item `to`, e.g. a guild to its `member`s.
+    /// Requires `from` to be locked with `lock` and `to` to exist.
+    ///
+    /// Links are stored with `from` and removed with it, see [crate::links::find_dangling_links].
+    async fn add_link(
+        &self,
+        _from: &ITEM::ID,
+        _relation: &str,
+        _to: &ITEM::ID,
+        _lock: &StorageLock,
+    ) -> Result<()> {
+        Err(eyre!("Links are not supported by this storage"))
+    }
+
+    /// Requires `from` to be locked with `lock`.
+    async fn remove_link(
+        &self,
+        _from: &ITEM::ID,
+        _relation: &str,
+        _to: &ITEM::ID,
+        _lock: &StorageLock,
+    ) -> Result<()> {
+        Err(eyre!("Links are not supported by this storage"))
+    }
+
+    /// Returns the ids `id` links to via `relation`, sorted.
+    async fn links_of(&self, _id: &ITEM::ID, _relation: &str) -> Result<Vec<ITEM::ID>> {
+        Err(eyre!("Links are not supported by this storage"))
+    }
+
+    /// Returns the ids linking to `id` via `relation`, sorted.
+    ///
+    /// Note: This scans all items.
+    async fn linked_from(&self, _id: &ITEM::ID, _relation: &str) -> Result<Vec<ITEM::ID>> {
+        Err(eyre!("Links are not supported by this storage"))
+    }
+
+    /// Removes the lock, whoever holds it, and returns it. `None` if the item wasn't locked.
+    async fn force_unlock(&self, id: &ITEM::ID) -> Result<Option<StorageLock>>;
+
+    /// Removes the lock only if it is still held by `who` since `when`, e.g. as seen in an [crate::ItemSummary],
+    /// and returns whether it was removed. Tokens aren't compared, see [Storage::force_unlock_all].
+    ///
+    /// Fails with [StorageError::Unsupported] by default.
+    async fn force_unlock_if_held(
+        &self,
+        _id: &ITEM::ID,
+        _who: &str,
+        _when: DateTime<Utc>,
+    ) -> Result<bool> {
+        Err(StorageError::Unsupported {
+            operation: crate::StorageOperation::ForceUnlock,
+        }
+        .into())
+    }
+
+    /// Removes items that were locked but never saved, with locks older than `max_age`,
+    /// e.g. after a crash during creation, and returns how many were removed.
+    ///
+    /// Pick a `max_age` well above the longest creation, otherwise items being created are removed.
+    /// See [crate::ExistsPolicy] to ignore orphans in [Storage::exists] until then.
+    async fn remove_orphaned_locks(&self, _max_age: Duration) -> Result<usize> {
+        Err(eyre!(
+            "Removing orphaned locks is not supported by this storage"
+        ))
+    }
+
+    /// Removes every lock held by someone starting with `who_prefix`, e.g. a crashed host's name,
+    /// and returns the ids of the unlocked items.
+    ///
+    /// Locks are found via [Storage::list] and only removed if unchanged since then.
+    /// Locks taken in between are kept.
+    /// Items locked but never saved are not listed, see [Storage::remove_orphaned_locks] for them.
+    ///
+    /// Fails on the first error, e.g. [StorageError::Unsupported], items unlocked until then stay unlocked.
+    async fn force_unlock_all(&self, who_prefix: &str) -> Result<Vec<ITEM::ID>> {
+        let mut unlocked = Vec::default();
+        let mut start = None;
+        loop {
+            let page = self.list(start.as_deref(), None).await?;
+            for summary in page.items {
+                let (Some(who), Some(when)) = (summary.locked_by, summary.locked_at) else {
+                    continue;
+                };
+                if !who.starts_with(who_prefix) {
+                    continue;
+                }
+                match self.force_unlock_if_held(&summary.id, &who, when).await {
+                    Ok(true) => unlocked.push(summary.id),
+                    Ok(false) => tracing::warn!("Skipping {}, lock changed", summary.id),
+                    Err(e) => return Err(e),
+                }
+            }
+            match page.cursor {
+                Some(cursor) => start = Some(cursor),
+                None => break,
+            }
+        }
+
+        Ok(unlocked)
+    }
     async fn verify_lock(&self, id: &ITEM::ID, lock: &StorageLock) -> Result<bool>;
 
+    /// [Storage::verify_lock] with per call [crate::OpOptions].
+    async fn verify_lock_with(
+        &self,
+        id: &ITEM::ID,
+        lock: &StorageLock,
+        _options: &crate::OpOptions,
+    ) -> Result<bool> {
+        self.verify_lock(id, lock).await
+    }
+
     // Experimental
     /// Returns all ids. This is a :HACK: and we will probably switch to an iterator at some point
     async fn all_ids(&self) -> Result<Vec<ITEM::ID>>;
 
+    /// Returns up to `limit` ids, starting after the cursor `start` of the previous [Page].
+    ///
+    /// See [crate::paginate] to stream all ids.
+    /// Fails with [StorageError::Unsupported] by default, for backends without [crate::Capabilities::scan].
     async fn scan_ids(
         &self,
         _start: Option<&str>,
         _limit: Option<usize>,
-    ) -> Result<(Vec<ITEM::ID>, Option<String>)> {
-        todo!("Implement scan position for ...");
+    ) -> Result<Page<ITEM::ID>> {
+        Err(StorageError::Unsupported {
+            operation: crate::StorageOperation::ScanIds,
+        }
+        .into())
+    }
+
+    /// Like [Storage::scan_ids], but only returns ids starting with `prefix`,
+    /// e.g. all children of a [crate::CompositeId] parent.
+    ///
+    /// Backends that can't filter efficiently filter each page after scanning,
+    /// so pages may be short or even empty. Keep scanning until the cursor is `None`.
+    async fn scan_ids_with_prefix(
+        &self,
+        prefix: &str,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        let mut page = self.scan_ids(start, limit).await?;
+        page.items.retain(|id| id.to_string().starts_with(prefix));
+        if !prefix.is_empty() {
+            page.approximate_total = None;
+        }
+
+        Ok(page)
+    }
+
+    /// Like [Storage::scan_ids], but only returns ids matching `filter`.
+    ///
+    /// Prefixes use [Storage::scan_ids_with_prefix], ranges are applied to each page after scanning,
+    /// so, again, pages may be short. Backends that don't track save times fail for [crate::IdFilter::ModifiedBefore].
+    async fn scan_ids_filtered(
+        &self,
+        filter: &crate::IdFilter,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        match filter {
+            crate::IdFilter::Prefix(prefix) => {
+                self.scan_ids_with_prefix(prefix, start, limit).await
+            }
+            crate::IdFilter::Range { .. } => {
+                let mut page = self.scan_ids(start, limit).await?;
+                page.items
+                    .retain(|id| filter.matches(&id.to_string(), None));
+                page.approximate_total = None;
+
+                Ok(page)
+            }
+            crate::IdFilter::ModifiedBefore(_) => {
+                Err(eyre!("This storage can't filter by save time"))
+            }
+        }
+    }
+
+    /// Like [Storage::scan_ids], but only ids from `from`, inclusive, to `to`, exclusive, by [crate::StorageId::id_cmp],
+    /// e.g. the items created last week for time-ordered ids, see [crate::SnowflakeId::first_at].
+    ///
+    /// Backends listing ids cheaply return them ordered and in full pages,
+    /// others filter each page after scanning, so pages may be short. Keep scanning until the cursor is `None`.
+    async fn scan_ids_between(
+        &self,
+        from: &ITEM::ID,
+        to: &ITEM::ID,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        let mut page = self.scan_ids(start, limit).await?;
+        page.items
+            .retain(|id| crate::page::is_between(id, from, to));
+        page.approximate_total = None;
+
+        Ok(page)
+    }
+
+    /// Like [Storage::scan_ids], but with the lock, size, and save time of each item, in one call.
+    async fn list(
+        &self,
+        _start: Option<&str>,
+        _limit: Option<usize>,
+    ) -> Result<Page<crate::ItemSummary<ITEM::ID>>> {
+        Err(eyre!("Listing is not supported by this storage"))
+    }
+
+    /// The stored size of the item in bytes, e.g. after compression, without deserializing it.
+    ///
+    /// Backends that can't tell without reading the item return the size of [Storage::load_raw].
+    async fn item_size(&self, id: &ITEM::ID) -> Result<u64> {
+        Ok(self.load_raw(id).await?.len() as u64)
+    }
+
+    /// The approximate size of all items and the capacity units to touch them, if the backend is billed by them.
+    ///
+    /// Only reads, via [Storage::list], so it is safe to run against a live storage, but it visits every item.
+    async fn estimate_cost(&self) -> Result<crate::StorageCost> {
+        crate::storage_cost::estimate_cost(self, false).await
+    }
+
+    /// Checks for anomalies, e.g. after a crash, before taking traffic, see [crate::FsckReport].
+    ///
+    /// Loads every item and only repairs what is safe, and only with [crate::FsckOptions::repair].
+    async fn fsck(&self, options: &crate::FsckOptions) -> Result<crate::FsckReport> {
+        crate::fsck::fsck(self, options).await
+    }
+
+    /// A complete, portable copy of the item, with its payload, blobs, lock state, and,
+    /// where the backend keeps them, timestamps and previous versions, see [crate::ItemArchive].
+    ///
+    /// Locks the item while reading, unless someone else holds the lock.
+    async fn export_item(&self, id: &ITEM::ID) -> Result<crate::ItemArchive> {
+        crate::item_archive::export_item(self, id).await
+    }
+
+    /// Restores an item from [Storage::export_item], replacing an existing one, and returns its id.
+    ///
+    /// Checks the checksums first and fails if the item is locked.
+    /// Lock state and previous versions are only informational and not restored.
+    async fn import_item(&self, archive: &crate::ItemArchive) -> Result<ITEM::ID> {
+        crate::item_archive::import_item(self, archive).await
+    }
+
+    /// Writes buffered state, e.g. of [crate::StorageWriteBehind] and metadata, before the process exits.
+    ///
+    /// Wrappers shut down their own state first and then the wrapped storages, so stacks shut down from the outside in.
+    /// Nothing to do by default. The storage stays usable, but state written afterwards is only flushed as usual.
+    async fn shutdown(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Like [Storage::scan_ids_with_prefix], but loads the items too.
+    /// Items removed while scanning are skipped.
+    ///
+    /// See [crate::paginate] to stream all items.
+    async fn scan_items(
+        &self,
+        prefix: &str,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<(ITEM::ID, ITEM)>> {
+        let page = self.scan_ids_with_prefix(prefix, start, limit).await?;
+        let items = self.load_many(&page.items).await?;
+        let items = page
+            .items
+            .into_iter()
+            .zip(items)
+            .filter_map(|(id, item)| Some((id, item?)))
+            .collect();
+
+        Ok(Page::new(items, page.cursor))
     }
 
     /// Returns a human readable version of the current lock status for debugging
@@ -54,42 +662,353 @@ pub trait Storage<ITEM: StorageItem + Sized>: Send + Sync + std::fmt::Debug {
     #[cfg(feature = "metadata")]
     async fn metadata_highest_seen_id(&self) -> Option<ITEM::ID>;
 
+    /// The number of items, `None` until the backend counted them once.
+    /// Updated opportunistically, so treat it as approximate.
+    #[cfg(feature = "metadata")]
+    async fn metadata_item_count(&self) -> Option<u64>;
+
+    /// The approximate size of all items, `None` until the backend measured them once.
+    #[cfg(feature = "metadata")]
+    async fn metadata_total_bytes(&self) -> Option<u64>;
+
+    /// When an item was last written by any instance sharing the metadata.
+    #[cfg(feature = "metadata")]
+    async fn metadata_last_write(&self) -> Option<DateTime<Utc>>;
+
+    /// Lock statistics of this instance, e.g. to find hot items.
+    #[cfg(feature = "metadata")]
+    async fn metadata_lock_stats(&self) -> crate::LockStats;
+
+    /// All tracked metadata in one serializable struct, e.g. for debugging id generation across instances.
+    #[cfg(feature = "metadata")]
+    async fn metadata_snapshot(&self) -> crate::MetadataSnapshot {
+        crate::MetadataSnapshot {
+            highest_seen_id: self
+                .metadata_highest_seen_id()
+                .await
+                .map(|id| id.to_string()),
+            item_count: self.metadata_item_count().await,
+            total_bytes: self.metadata_total_bytes().await,
+            last_write: self.metadata_last_write().await,
+            lock_stats: self.metadata_lock_stats().await,
+        }
+    }
+
+    /// Removes all items or only reports what would be removed, see [crate::WipeOptions].
+    /// `progress` receives an update per item, throttled, see [crate::ProgressSink].
+    /// Once `cancel` is cancelled the wipe stops between items or pages,
+    /// and fails with [crate::StorageError::Cancelled].
     #[cfg(feature = "wipe")]
-    async fn wipe(&self, confirmation: &str) -> Result<()>;
+    async fn wipe(
+        &self,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport>;
+
+    /// Like [Storage::wipe], but only removes the items matching `filter`.
+    /// Aliases pointing to removed items are kept.
+    #[cfg(feature = "wipe")]
+    async fn wipe_matching(
+        &self,
+        filter: &crate::IdFilter,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport>;
+
+    /// Removes the given items with their locks and blobs, e.g. after a load test,
+    /// and returns what happened to each of them, in the order of `ids`.
+    /// Locked items are kept, unless [crate::DeleteOptions::with_force] is set.
+    ///
+    /// Aliases aren't resolved, and aliases pointing to removed items are kept.
+    #[cfg(feature = "wipe")]
+    async fn delete_many(
+        &self,
+        ids: &[ITEM::ID],
+        options: &crate::DeleteOptions,
+    ) -> Result<Vec<crate::DeleteOutcome>>;
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+/// How often [Storage::create] generates a new id, before giving up with [crate::StorageError::IdExhausted].
+pub(crate) const DEFAULT_CREATE_TRIES: usize = 10;
+
+/// Checks the number of tries passed to the `set_create_tries` of the backends.
+pub(crate) fn ensure_valid_create_tries(tries: usize) -> Result<()> {
+    if tries == 0 {
+        return Err(eyre!("create needs at least one try"));
+    }
+
+    Ok(())
+}
+
+/// Meta keys become part of file names and ids, so only `[A-Za-z0-9_.-]` is allowed.
+pub(crate) fn ensure_valid_meta_key(key: &str) -> Result<()> {
+    if key.is_empty()
+        || key.starts_with('.')
+        || !key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-')
+    {
+        return Err(eyre!("Invalid meta key {key:?}"));
+    }
+
+    Ok(())
+}
+
+/// The version of the serialized [StorageLock] written by this crate.
+/// Locks without a `v` field were written before locks were versioned,
+/// version 1 locks have no token.
+pub const LOCK_VERSION: u32 = 2;
+
+/// Field order is stable, backends like DynamoDB compare the serialized lock as a string.
+/// Unknown fields, e.g. from newer crate versions, are kept, so they survive a round-trip.
+#[derive(Debug, Serialize, Deserialize)]
 pub struct StorageLock {
+    #[serde(default, skip_serializing_if = "is_legacy_version")]
+    v: u32,
     who: String,
     when: DateTime<Utc>,
+    /// Unique per acquisition, empty for locks without one, e.g. [StorageLock::observed]
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    token: String,
+    #[serde(flatten)]
+    extra: BTreeMap<String, serde_json::Value>,
+}
+
+fn is_legacy_version(v: &u32) -> bool {
+    *v == 0
+}
+
+/// Locks with tokens are the same if their tokens are, and never the same as locks without one.
+/// Locks without tokens, e.g. from older crate versions, are the same if held by the same `who`
+/// since the same `when`, regardless of version.
+impl PartialEq for StorageLock {
+    fn eq(&self, other: &Self) -> bool {
+        match (self.token(), other.token()) {
+            (Some(token), Some(other_token)) => token == other_token,
+            (None, None) => self.who == other.who && self.when == other.when,
+            _ => false,
+        }
+    }
 }
 
 impl StorageLock {
     pub fn new(who: &str) -> Self {
+        Self::new_at(who, Utc::now())
+    }
+    /// A lock taken at `when`, e.g. from a backend's [crate::Clock], with a new token.
+    pub fn new_at(who: &str, when: DateTime<Utc>) -> Self {
         Self {
+            token: nanoid::nanoid!(),
+            ..Self::observed(who, when)
+        }
+    }
+    /// A lock as seen from the outside, e.g. in an [crate::ItemSummary], without a token.
+    pub(crate) fn observed(who: &str, when: DateTime<Utc>) -> Self {
+        Self {
+            v: LOCK_VERSION,
             who: who.to_string(),
-            when: Utc::now(),
+            when,
+            token: String::default(),
+            extra: BTreeMap::default(),
         }
     }
     pub fn who(&self) -> &str {
         &self.who
     }
+    /// Whether held by `who` since `when`, for [Storage::force_unlock_if_held], tokens aren't compared.
+    pub(crate) fn is_held_by(&self, who: &str, when: DateTime<Utc>) -> bool {
+        self.who == who && self.when == when
+    }
     pub fn when(&self) -> &DateTime<Utc> {
         &self.when
     }
+    /// The version this lock was serialized with, `0` for locks from before versioning.
+    pub fn version(&self) -> u32 {
+        self.v
+    }
+    /// The token of this acquisition, `None` for older and observed locks.
+    pub fn token(&self) -> Option<&str> {
+        (!self.token.is_empty()).then_some(self.token.as_str())
+    }
+
+    /// Locks are deliberately not `Clone`, but backends holding them in memory need a copy.
+    pub(crate) fn duplicate(&self) -> Self {
+        Self {
+            v: self.v,
+            who: self.who.clone(),
+            when: self.when,
+            token: self.token.clone(),
+            extra: self.extra.clone(),
+        }
+    }
+
+    /// The lock without its token, for backends storing the token separately.
+    pub(crate) fn without_token(&self) -> Self {
+        Self {
+            token: String::default(),
+            ..self.duplicate()
+        }
+    }
+
+    /// The lock with `token`, e.g. read from a separate attribute.
+    pub(crate) fn with_token(mut self, token: &str) -> Self {
+        self.token = token.to_string();
+        self
+    }
+
+    /// The lock as an older crate version would have written it,
+    /// for backends comparing serialized locks.
+    pub(crate) fn legacy_json_pretty(&self) -> Result<String> {
+        let legacy = Self {
+            v: 0,
+            ..Self::observed(&self.who, self.when)
+        };
+        Ok(serde_json::to_string_pretty(&legacy)?)
+    }
 }
 
 #[derive(Debug)]
 pub enum LockResult<ITEM> {
-    Success { lock: StorageLock, item: ITEM },
-    AlreadyLocked { who: String },
+    Success {
+        lock: StorageLock,
+        item: ITEM,
+    },
+    /// `when` is the time the existing lock was taken,
+    /// or the time of the lock attempt if the backend couldn't read the existing lock.
+    AlreadyLocked {
+        who: String,
+        when: DateTime<Utc>,
+    },
 }
 
 impl<ITEM> LockResult<ITEM> {
     pub fn success(self) -> Result<(StorageLock, ITEM)> {
         match self {
             LockResult::Success { lock, item } => Ok((lock, item)),
-            LockResult::AlreadyLocked { who } => Err(eyre!("Already locked by {who:?}")),
+            LockResult::AlreadyLocked { who, .. } => Err(eyre!("Already locked by {who:?}")),
         }
     }
+
+    /// How long the existing lock is held already, `None` for [LockResult::Success].
+    /// Useful to decide between waiting and [Storage::force_unlock].
+    pub fn lock_age(&self) -> Option<Duration> {
+        self.lock_age_at(Utc::now())
+    }
+
+    /// Like [LockResult::lock_age], but as of `now`, e.g. from a [crate::Clock].
+    pub fn lock_age_at(&self, now: DateTime<Utc>) -> Option<Duration> {
+        match self {
+            LockResult::Success { .. } => None,
+            LockResult::AlreadyLocked { when, .. } => {
+                Some((now - *when).to_std().unwrap_or_default())
+            }
+        }
+    }
+
+    pub(crate) fn already_locked(lock: &StorageLock) -> Self {
+        LockResult::AlreadyLocked {
+            who: lock.who().to_string(),
+            when: *lock.when(),
+        }
+    }
+}
+
+/// What [Storage::lock_and_load_many] did with one of the ids.
+#[derive(Debug)]
+pub enum LockManyOutcome<ITEM> {
+    Success {
+        lock: StorageLock,
+        item: ITEM,
+    },
+    /// See [LockResult::AlreadyLocked]
+    AlreadyLocked {
+        who: String,
+        when: DateTime<Utc>,
+    },
+    /// Nothing was saved for the id and it wasn't locked
+    NotFound,
+    /// Checking or locking failed. The item might have been locked anyway.
+    Failed(color_eyre::eyre::Report),
+}
+
+impl<ITEM> LockManyOutcome<ITEM> {
+    /// The lock and item for [LockManyOutcome::Success].
+    pub fn success(self) -> Option<(StorageLock, ITEM)> {
+        match self {
+            LockManyOutcome::Success { lock, item } => Some((lock, item)),
+            _ => None,
+        }
+    }
+}
+
+impl<ITEM> From<LockResult<ITEM>> for LockManyOutcome<ITEM> {
+    fn from(result: LockResult<ITEM>) -> Self {
+        match result {
+            LockResult::Success { lock, item } => LockManyOutcome::Success { lock, item },
+            LockResult::AlreadyLocked { who, when } => LockManyOutcome::AlreadyLocked { who, when },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_round_trips_versioned_locks() -> Result<()> {
+        let legacy_json = "{\n  \"who\": \"TEST\",\n  \"when\": \"2024-01-01T00:00:00Z\"\n}";
+        let legacy = serde_json::from_str::<StorageLock>(legacy_json)?;
+        assert_eq!(0, legacy.version());
+        assert_eq!(legacy_json, serde_json::to_string_pretty(&legacy)?);
+        assert_eq!(legacy_json, legacy.legacy_json_pretty()?);
+
+        let lock = StorageLock::new_at("TEST", *legacy.when());
+        assert_eq!(LOCK_VERSION, lock.version());
+        assert_ne!(legacy, lock);
+        assert_eq!(legacy, lock.without_token());
+        let lock_json = serde_json::to_string_pretty(&lock)?;
+        assert!(lock_json.starts_with("{\n  \"v\": 2,\n  \"who\": \"TEST\""));
+        assert_eq!(legacy_json, lock.legacy_json_pretty()?);
+
+        let newer_json = "{\n  \"v\": 3,\n  \"who\": \"TEST\",\n  \"when\": \"2024-01-01T00:00:00Z\",\n  \"lease\": 30\n}";
+        let newer = serde_json::from_str::<StorageLock>(newer_json)?;
+        assert_eq!(3, newer.version());
+        assert_eq!(legacy, newer);
+        assert_eq!(
+            newer_json,
+            serde_json::to_string_pretty(&newer.duplicate())?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_compares_locks_by_token() -> Result<()> {
+        let when = Utc::now();
+        let lock = StorageLock::new_at("TEST", when);
+        let relock = StorageLock::new_at("TEST", when);
+        assert_ne!(lock.token(), None);
+        assert_ne!(lock, relock);
+
+        let observed = StorageLock::observed("TEST", when);
+        assert_eq!(None, observed.token());
+        assert_ne!(lock, observed);
+        assert_ne!(observed, relock);
+        assert_eq!(observed, StorageLock::observed("TEST", when));
+        assert!(lock.is_held_by("TEST", when));
+        assert!(!lock.is_held_by("OTHER", when));
+
+        let read = serde_json::from_str::<StorageLock>(&serde_json::to_string(&lock)?)?;
+        assert_eq!(lock.token(), read.token());
+        assert_eq!(lock, read);
+        assert_ne!(relock, read);
+
+        let stored = lock.without_token();
+        assert_eq!(None, stored.token());
+        assert_eq!(lock, stored.with_token(lock.token().unwrap_or_default()));
+
+        Ok(())
+    }
 }
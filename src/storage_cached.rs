@@ -0,0 +1,891 @@
+use crate::storage_item::deserialize_item;
+use crate::Consistency;
+use crate::LockResult;
+use crate::OpOptions;
+use crate::Page;
+use crate::Redacted;
+use crate::Storage;
+use crate::StorageItem;
+use crate::StorageLock;
+use async_trait::async_trait;
+use chrono::DateTime;
+use chrono::Utc;
+use color_eyre::eyre::Result;
+use core::marker::PhantomData;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+const DEFAULT_MAX_ENTRIES: usize = 10_000;
+
+/// Cache statistics of a [StorageCached], since it was created.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    /// Includes loads of items locked by this instance, which always bypass the cache
+    pub misses: u64,
+    /// Entries dropped because the item was locked, saved, or unlocked
+    pub invalidations: u64,
+    /// [Storage::exists] calls answered from the known missing ids, see [StorageCached::set_max_missing]
+    pub missing_hits: u64,
+}
+
+impl CacheStats {
+    /// Between `0.0` and `1.0`, `0.0` before the first load.
+    pub fn hit_rate(&self) -> f64 {
+        let loads = self.hits + self.misses;
+        if loads == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / loads as f64
+    }
+}
+
+struct CacheEntry {
+    data: Vec<u8>,
+    loaded_at: Instant,
+}
+
+impl std::fmt::Debug for CacheEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CacheEntry")
+            .field("data", &Redacted(&self.data))
+            .field("loaded_at", &self.loaded_at)
+            .finish()
+    }
+}
+
+#[derive(Debug, Default)]
+struct Cache {
+    entries: HashMap<String, CacheEntry>,
+    /// Items locked via this instance, the holder will change them
+    locked: HashSet<String>,
+    /// Ids that didn't exist and when that was checked
+    missing: HashMap<String, Instant>,
+    /// Ids never evicted, see [StorageCached::pin]
+    pinned: HashSet<String>,
+    /// Increased by every invalidation, so loads started before one don't fill the cache
+    epoch: u64,
+    stats: CacheStats,
+}
+
+impl Cache {
+    fn invalidate(&mut self, key: &str) {
+        self.epoch += 1;
+        self.missing.remove(key);
+        if self.entries.remove(key).is_some() {
+            self.stats.invalidations += 1;
+        }
+    }
+}
+
+/// Wraps any [Storage] and caches the results of [Storage::load].
+///
+/// Locking an item bypasses the cache and drops its entry, since the holder will change it.
+/// Until it is unlocked loads bypass the cache too.
+///
+/// Optionally ids that don't exist are remembered too, see [StorageCached::set_max_missing].
+/// [StorageCached::pin] keeps hot items, e.g. global config, cached regardless of `max_entries`.
+///
+/// Invalidation only sees locks taken via this instance,
+/// use [StorageCached::set_ttl] if other instances change the items.
+/// Items are cached serialized and must survive a round trip through [StorageItem::serialize].
+#[derive(Debug)]
+pub struct StorageCached<ITEM: StorageItem, S: Storage<ITEM>>
+where
+    ITEM: Send,
+{
+    storage: S,
+    cache: Mutex<Cache>,
+    max_entries: usize,
+    max_missing: usize,
+    ttl: Option<Duration>,
+    item_type: PhantomData<ITEM>,
+}
+
+impl<ITEM: StorageItem + Send, S: Storage<ITEM>> StorageCached<ITEM, S> {
+    pub fn new(storage: S) -> Self {
+        Self {
+            storage,
+            cache: Mutex::new(Cache::default()),
+            max_entries: DEFAULT_MAX_ENTRIES,
+            max_missing: 0,
+            ttl: None,
+            item_type: PhantomData,
+        }
+    }
+
+    /// The oldest entries are evicted beyond `max_entries`, defaults to 10000.
+    pub fn set_max_entries(&mut self, max_entries: usize) -> Result<()> {
+        self.max_entries = max_entries;
+
+        Ok(())
+    }
+
+    /// Remembers up to `max_missing` ids that don't exist and answers [Storage::exists] for them
+    /// without asking the inner storage, e.g. against bots probing random ids.
+    /// Off by default.
+    ///
+    /// Creating, locking, or saving an id via this instance forgets it.
+    pub fn set_max_missing(&mut self, max_missing: usize) -> Result<()> {
+        self.max_missing = max_missing;
+
+        Ok(())
+    }
+
+    /// Entries and missing ids older than `ttl` are checked again, by default they never expire.
+    pub fn set_ttl(&mut self, ttl: Option<Duration>) -> Result<()> {
+        self.ttl = ttl;
+
+        Ok(())
+    }
+
+    /// Never evicts the entry of `id` to make room for others, even beyond `max_entries`.
+    ///
+    /// Pinned entries are still dropped when the item is locked or saved, and checked again after the ttl.
+    pub fn pin(&self, id: &ITEM::ID) {
+        self.cache
+            .lock()
+            .expect("can lock")
+            .pinned
+            .insert(id.to_string());
+    }
+
+    pub fn unpin(&self, id: &ITEM::ID) {
+        self.cache
+            .lock()
+            .expect("can lock")
+            .pinned
+            .remove(&id.to_string());
+    }
+
+    pub fn is_pinned(&self, id: &ITEM::ID) -> bool {
+        self.cache
+            .lock()
+            .expect("can lock")
+            .pinned
+            .contains(&id.to_string())
+    }
+
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache.lock().expect("can lock").stats.clone()
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    pub fn into_inner(self) -> S {
+        self.storage
+    }
+
+    fn invalidate(&self, id: &ITEM::ID) {
+        self.cache
+            .lock()
+            .expect("can lock")
+            .invalidate(&id.to_string());
+    }
+
+    fn invalidate_and_release(&self, id: &ITEM::ID) {
+        let key = id.to_string();
+        let mut cache = self.cache.lock().expect("can lock");
+        cache.invalidate(&key);
+        cache.locked.remove(&key);
+    }
+
+    /// Returns the cached data or the epoch to pass to [StorageCached::insert] after loading.
+    /// With `accept_stale` expired entries are returned too.
+    fn lookup(&self, key: &str, accept_stale: bool) -> std::result::Result<Vec<u8>, Option<u64>> {
+        let mut cache = self.cache.lock().expect("can lock");
+        if cache.locked.contains(key) {
+            cache.stats.misses += 1;
+            return Err(None);
+        }
+        let fresh = cache
+            .entries
+            .get(key)
+            .filter(|e| accept_stale || self.is_fresh(e.loaded_at))
+            .map(|e| e.data.clone());
+        match fresh {
+            Some(data) => {
+                cache.stats.hits += 1;
+                Ok(data)
+            }
+            None => {
+                cache.stats.misses += 1;
+                Err(Some(cache.epoch))
+            }
+        }
+    }
+
+    fn is_fresh(&self, since: Instant) -> bool {
+        self.ttl.is_none_or(|ttl| since.elapsed() <= ttl)
+    }
+
+    /// Succeeds if `key` is known to be missing, or returns the epoch to pass to [StorageCached::insert_missing].
+    fn lookup_missing(&self, key: &str, accept_stale: bool) -> std::result::Result<(), u64> {
+        let mut cache = self.cache.lock().expect("can lock");
+        if cache
+            .missing
+            .get(key)
+            .is_some_and(|since| accept_stale || self.is_fresh(*since))
+        {
+            cache.stats.missing_hits += 1;
+            return Ok(());
+        }
+        Err(cache.epoch)
+    }
+
+    fn insert_missing(&self, key: String, epoch: u64) {
+        let mut cache = self.cache.lock().expect("can lock");
+        if cache.epoch != epoch {
+            return;
+        }
+        if cache.missing.len() >= self.max_missing && !cache.missing.contains_key(&key) {
+            let oldest = cache
+                .missing
+                .iter()
+                .min_by_key(|(_, since)| **since)
+                .map(|(k, _)| k.clone());
+            if let Some(oldest) = oldest {
+                cache.missing.remove(&oldest);
+            }
+        }
+        cache.missing.insert(key, Instant::now());
+    }
+
+    fn insert(&self, key: String, data: Vec<u8>, epoch: u64) {
+        let mut cache = self.cache.lock().expect("can lock");
+        if cache.epoch != epoch || self.max_entries == 0 {
+            return;
+        }
+        if cache.entries.len() >= self.max_entries && !cache.entries.contains_key(&key) {
+            let oldest = cache
+                .entries
+                .iter()
+                .filter(|(k, _)| !cache.pinned.contains(*k))
+                .min_by_key(|(_, e)| e.loaded_at)
+                .map(|(k, _)| k.clone());
+            match oldest {
+                Some(oldest) => {
+                    cache.entries.remove(&oldest);
+                }
+                // only pinned entries left
+                None if !cache.pinned.contains(&key) => return,
+                None => {}
+            }
+        }
+        cache.entries.insert(
+            key,
+            CacheEntry {
+                data,
+                loaded_at: Instant::now(),
+            },
+        );
+    }
+}
+
+#[async_trait]
+impl<ITEM: StorageItem + Send, S: Storage<ITEM>> Storage<ITEM> for StorageCached<ITEM, S> {
+    async fn ensure_storage_exists(&mut self) -> Result<()> {
+        self.storage.ensure_storage_exists().await
+    }
+
+    /// Cached reads can miss writes of other instances.
+    fn capabilities(&self) -> crate::Capabilities {
+        crate::Capabilities {
+            consistent_reads: false,
+            ..self.storage.capabilities()
+        }
+    }
+
+    async fn create(&self) -> Result<ITEM::ID> {
+        let id = self.storage.create().await?;
+        self.invalidate(&id);
+
+        Ok(id)
+    }
+
+    async fn exists(&self, id: &ITEM::ID) -> Result<bool> {
+        self.exists_with(id, &OpOptions::default()).await
+    }
+
+    /// Bypassing the cache doesn't remember missing ids either.
+    async fn exists_with(&self, id: &ITEM::ID, options: &OpOptions) -> Result<bool> {
+        if self.max_missing == 0 || options.bypass_cache() {
+            return self.storage.exists_with(id, options).await;
+        }
+        let key = id.to_string();
+        let accept_stale = options.consistency() == Consistency::Eventual;
+        let epoch = match self.lookup_missing(&key, accept_stale) {
+            Ok(()) => return Ok(false),
+            Err(epoch) => epoch,
+        };
+        let exists = self.storage.exists_with(id, options).await?;
+        if !exists {
+            self.insert_missing(key, epoch);
+        }
+
+        Ok(exists)
+    }
+
+    async fn load(&self, id: &ITEM::ID) -> Result<ITEM> {
+        self.load_with(id, &OpOptions::default()).await
+    }
+
+    /// Bypassing the cache doesn't count as a miss and doesn't fill the cache.
+    async fn load_with(&self, id: &ITEM::ID, options: &OpOptions) -> Result<ITEM> {
+        if options.bypass_cache() {
+            return self.storage.load_with(id, options).await;
+        }
+        let key = id.to_string();
+        let accept_stale = options.consistency() == Consistency::Eventual;
+        let epoch = match self.lookup(&key, accept_stale) {
+            Ok(data) => return deserialize_item::<ITEM>(&data),
+            Err(epoch) => epoch,
+        };
+        let item = self.storage.load_with(id, options).await?;
+        if let Some(epoch) = epoch {
+            self.insert(key, item.serialize()?, epoch);
+        }
+
+        Ok(item)
+    }
+
+    /// Loads the items that aren't cached yet via [Storage::load_many], without counting hits or misses.
+    async fn prefetch(&self, ids: &[ITEM::ID]) -> Result<()> {
+        let (uncached, epoch) = {
+            let cache = self.cache.lock().expect("can lock");
+            let uncached: Vec<ITEM::ID> = ids
+                .iter()
+                .filter(|id| {
+                    let key = id.to_string();
+                    !cache.locked.contains(&key)
+                        && !cache
+                            .entries
+                            .get(&key)
+                            .is_some_and(|e| self.is_fresh(e.loaded_at))
+                })
+                .cloned()
+                .collect();
+            (uncached, cache.epoch)
+        };
+        if uncached.is_empty() {
+            return Ok(());
+        }
+        let items = self.storage.load_many(&uncached).await?;
+        for (id, item) in uncached.iter().zip(items) {
+            if let Some(item) = item {
+                self.insert(id.to_string(), item.serialize()?, epoch);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn load_raw(&self, id: &ITEM::ID) -> Result<Vec<u8>> {
+        let key = id.to_string();
+        let epoch = match self.lookup(&key, false) {
+            Ok(data) => return Ok(data),
+            Err(epoch) => epoch,
+        };
+        let data = self.storage.load_raw(id).await?;
+        if let Some(epoch) = epoch {
+            self.insert(key, data.clone(), epoch);
+        }
+
+        Ok(data)
+    }
+
+    async fn save(&self, id: &ITEM::ID, item: &ITEM, lock: &StorageLock) -> Result<()> {
+        self.invalidate(id);
+        self.storage.save(id, item, lock).await
+    }
+
+    async fn save_raw(&self, id: &ITEM::ID, data: &[u8], lock: &StorageLock) -> Result<()> {
+        self.invalidate(id);
+        self.storage.save_raw(id, data, lock).await
+    }
+
+    /// Always bypasses the cache.
+    async fn lock(&self, id: &ITEM::ID, who: &str) -> Result<LockResult<ITEM>> {
+        {
+            let key = id.to_string();
+            let mut cache = self.cache.lock().expect("can lock");
+            cache.invalidate(&key);
+            cache.locked.insert(key);
+        }
+        let result = self.storage.lock(id, who).await;
+        if !matches!(result, Ok(LockResult::Success { .. })) {
+            self.cache
+                .lock()
+                .expect("can lock")
+                .locked
+                .remove(&id.to_string());
+        }
+
+        result
+    }
+
+    async fn unlock(&self, id: &ITEM::ID, lock: StorageLock) -> Result<()> {
+        let result = self.storage.unlock(id, lock).await;
+        self.invalidate_and_release(id);
+
+        result
+    }
+
+    async fn handoff_lock(
+        &self,
+        id: &ITEM::ID,
+        lock: StorageLock,
+        new_who: &str,
+    ) -> Result<StorageLock> {
+        self.storage.handoff_lock(id, lock, new_who).await
+    }
+
+    async fn save_and_unlock(&self, id: &ITEM::ID, item: &ITEM, lock: StorageLock) -> Result<()> {
+        let result = self.storage.save_and_unlock(id, item, lock).await;
+        self.invalidate_and_release(id);
+
+        result
+    }
+
+    async fn put_blob(
+        &self,
+        id: &ITEM::ID,
+        name: &str,
+        data: &[u8],
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.storage.put_blob(id, name, data, lock).await
+    }
+
+    async fn get_blob(&self, id: &ITEM::ID, name: &str) -> Result<Option<Vec<u8>>> {
+        self.storage.get_blob(id, name).await
+    }
+
+    async fn list_blobs(&self, id: &ITEM::ID) -> Result<Vec<String>> {
+        self.storage.list_blobs(id).await
+    }
+
+    async fn put_meta(&self, key: &str, value: &[u8]) -> Result<()> {
+        self.storage.put_meta(key, value).await
+    }
+
+    async fn get_meta(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.storage.get_meta(key).await
+    }
+
+    async fn add_alias(&self, alias: &ITEM::ID, canonical: &ITEM::ID) -> Result<()> {
+        self.invalidate(alias);
+        self.storage.add_alias(alias, canonical).await
+    }
+
+    async fn remove_alias(&self, alias: &ITEM::ID) -> Result<()> {
+        self.invalidate(alias);
+        self.storage.remove_alias(alias).await
+    }
+
+    async fn resolve_alias(&self, id: &ITEM::ID) -> Result<ITEM::ID> {
+        self.storage.resolve_alias(id).await
+    }
+
+    async fn add_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.storage.add_link(from, relation, to, lock).await
+    }
+
+    async fn remove_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.storage.remove_link(from, relation, to, lock).await
+    }
+
+    async fn links_of(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        self.storage.links_of(id, relation).await
+    }
+
+    async fn linked_from(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        self.storage.linked_from(id, relation).await
+    }
+
+    async fn force_unlock(&self, id: &ITEM::ID) -> Result<Option<StorageLock>> {
+        let result = self.storage.force_unlock(id).await;
+        self.invalidate_and_release(id);
+
+        result
+    }
+
+    async fn force_unlock_if_held(
+        &self,
+        id: &ITEM::ID,
+        who: &str,
+        when: DateTime<Utc>,
+    ) -> Result<bool> {
+        let result = self.storage.force_unlock_if_held(id, who, when).await;
+        self.invalidate_and_release(id);
+
+        result
+    }
+
+    async fn remove_orphaned_locks(&self, max_age: Duration) -> Result<usize> {
+        self.storage.remove_orphaned_locks(max_age).await
+    }
+
+    async fn verify_lock(&self, id: &ITEM::ID, lock: &StorageLock) -> Result<bool> {
+        self.storage.verify_lock(id, lock).await
+    }
+
+    async fn verify_lock_with(
+        &self,
+        id: &ITEM::ID,
+        lock: &StorageLock,
+        options: &OpOptions,
+    ) -> Result<bool> {
+        self.storage.verify_lock_with(id, lock, options).await
+    }
+
+    async fn all_ids(&self) -> Result<Vec<ITEM::ID>> {
+        self.storage.all_ids().await
+    }
+
+    async fn scan_ids(&self, start: Option<&str>, limit: Option<usize>) -> Result<Page<ITEM::ID>> {
+        self.storage.scan_ids(start, limit).await
+    }
+
+    async fn scan_ids_with_prefix(
+        &self,
+        prefix: &str,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.storage
+            .scan_ids_with_prefix(prefix, start, limit)
+            .await
+    }
+
+    async fn scan_ids_filtered(
+        &self,
+        filter: &crate::IdFilter,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.storage.scan_ids_filtered(filter, start, limit).await
+    }
+
+    async fn scan_ids_between(
+        &self,
+        from: &ITEM::ID,
+        to: &ITEM::ID,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.storage.scan_ids_between(from, to, start, limit).await
+    }
+
+    async fn list(
+        &self,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<crate::ItemSummary<ITEM::ID>>> {
+        self.storage.list(start, limit).await
+    }
+
+    async fn item_size(&self, id: &ITEM::ID) -> Result<u64> {
+        self.storage.item_size(id).await
+    }
+
+    async fn estimate_cost(&self) -> Result<crate::StorageCost> {
+        self.storage.estimate_cost().await
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        self.storage.shutdown().await
+    }
+
+    async fn fsck(&self, options: &crate::FsckOptions) -> Result<crate::FsckReport> {
+        self.storage.fsck(options).await
+    }
+
+    async fn display_lock(&self, id: &ITEM::ID) -> Result<String> {
+        self.storage.display_lock(id).await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_highest_seen_id(&self) -> Option<ITEM::ID> {
+        self.storage.metadata_highest_seen_id().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_item_count(&self) -> Option<u64> {
+        self.storage.metadata_item_count().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_total_bytes(&self) -> Option<u64> {
+        self.storage.metadata_total_bytes().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_last_write(&self) -> Option<DateTime<Utc>> {
+        self.storage.metadata_last_write().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_lock_stats(&self) -> crate::LockStats {
+        self.storage.metadata_lock_stats().await
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn wipe(
+        &self,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        let result = self.storage.wipe(options, progress, cancel).await;
+        if options.is_dry_run() {
+            return result;
+        }
+        let mut cache = self.cache.lock().expect("can lock");
+        cache.epoch += 1;
+        cache.entries.clear();
+        cache.missing.clear();
+
+        result
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn wipe_matching(
+        &self,
+        filter: &crate::IdFilter,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        let result = self
+            .storage
+            .wipe_matching(filter, options, progress, cancel)
+            .await;
+        if options.is_dry_run() {
+            return result;
+        }
+        let mut cache = self.cache.lock().expect("can lock");
+        cache.epoch += 1;
+        cache.entries.clear();
+        cache.missing.clear();
+
+        result
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn delete_many(
+        &self,
+        ids: &[ITEM::ID],
+        options: &crate::DeleteOptions,
+    ) -> Result<Vec<crate::DeleteOutcome>> {
+        let result = self.storage.delete_many(ids, options).await;
+        for id in ids {
+            self.invalidate_and_release(id);
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_item::TestItem;
+    use crate::CacheStats;
+    use crate::Consistency;
+    use crate::MockResponse;
+    use crate::OpOptions;
+    use crate::Storage;
+    use crate::StorageCached;
+    use crate::StorageMemory;
+    use crate::StorageMock;
+    use crate::StorageOperation;
+    use color_eyre::Result;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn it_invalidates_on_lock() -> Result<()> {
+        let storage = StorageCached::new(StorageMemory::<TestItem>::default());
+        let id = String::from("1");
+        let (lock, _) = storage.lock(&id, "tester").await?.success()?;
+        storage
+            .save_and_unlock(&id, &TestItem { count: 1 }, lock)
+            .await?;
+
+        assert_eq!(TestItem { count: 1 }, storage.load(&id).await?);
+        assert_eq!(TestItem { count: 1 }, storage.load(&id).await?);
+
+        let (lock, item) = storage.lock(&id, "tester").await?.success()?;
+        assert_eq!(TestItem { count: 1 }, item);
+        storage.save(&id, &TestItem { count: 2 }, &lock).await?;
+        assert_eq!(TestItem { count: 2 }, storage.load(&id).await?);
+        storage.unlock(&id, lock).await?;
+
+        assert_eq!(TestItem { count: 2 }, storage.load(&id).await?);
+        assert_eq!(TestItem { count: 2 }, storage.load(&id).await?);
+
+        let stats = storage.cache_stats();
+        assert_eq!(
+            CacheStats {
+                hits: 2,
+                misses: 3,
+                invalidations: 1,
+                missing_hits: 0,
+            },
+            stats
+        );
+        assert_eq!(0.4, stats.hit_rate());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_keeps_pinned_entries() -> Result<()> {
+        let mut storage = StorageCached::new(StorageMemory::<TestItem>::default());
+        storage.set_max_entries(1)?;
+        let ids: Vec<String> = ["config", "a", "b"].map(String::from).into();
+        for (count, id) in ids.iter().enumerate() {
+            let (lock, _) = storage.lock(id, "tester").await?.success()?;
+            storage
+                .save_and_unlock(
+                    id,
+                    &TestItem {
+                        count: count as u32,
+                    },
+                    lock,
+                )
+                .await?;
+        }
+        storage.pin(&ids[0]);
+        assert!(storage.is_pinned(&ids[0]));
+
+        for id in &ids {
+            storage.load(id).await?;
+        }
+        let hits = storage.cache_stats().hits;
+        assert_eq!(TestItem { count: 0 }, storage.load(&ids[0]).await?);
+        assert_eq!(hits + 1, storage.cache_stats().hits);
+        // the others never got in
+        storage.load(&ids[1]).await?;
+        assert_eq!(hits + 1, storage.cache_stats().hits);
+
+        storage.unpin(&ids[0]);
+        storage.load(&ids[1]).await?;
+        storage.load(&ids[0]).await?;
+        assert_eq!(hits + 1, storage.cache_stats().hits);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_remembers_missing_ids() -> Result<()> {
+        let mut storage = StorageCached::new(StorageMock::<TestItem>::default());
+        storage.set_max_missing(1)?;
+        let bot = String::from("bot");
+        let other = String::from("other");
+
+        assert!(!storage.exists(&bot).await?);
+        assert!(!storage.exists(&bot).await?);
+        assert_eq!(1, storage.cache_stats().missing_hits);
+
+        // evicts bot
+        assert!(!storage.exists(&other).await?);
+        assert!(!storage.exists(&bot).await?);
+
+        let (lock, item) = storage.lock(&bot, "tester").await?.success()?;
+        storage.save_and_unlock(&bot, &item, lock).await?;
+        storage
+            .storage()
+            .script(StorageOperation::Exists, MockResponse::Bool(true));
+        assert!(storage.exists(&bot).await?);
+
+        let exists_calls = storage
+            .storage()
+            .calls()
+            .iter()
+            .filter(|c| c.operation == StorageOperation::Exists)
+            .count();
+        assert_eq!(4, exists_calls);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_prefetches() -> Result<()> {
+        let storage = StorageCached::new(StorageMemory::<TestItem>::default());
+        let ids = vec![
+            String::from("1"),
+            String::from("2"),
+            String::from("missing"),
+        ];
+        for id in ids[..2].iter() {
+            let (lock, _) = storage.lock(id, "tester").await?.success()?;
+            storage
+                .save_and_unlock(id, &TestItem { count: 1 }, lock)
+                .await?;
+        }
+
+        storage.prefetch(&ids).await?;
+        assert_eq!(TestItem { count: 1 }, storage.load(&ids[0]).await?);
+        assert_eq!(TestItem { count: 1 }, storage.load(&ids[1]).await?);
+        assert_eq!(2, storage.cache_stats().hits);
+        assert_eq!(0, storage.cache_stats().misses);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_honors_op_options() -> Result<()> {
+        let mut storage = StorageCached::new(StorageMemory::<TestItem>::default());
+        storage.set_ttl(Some(Duration::from_millis(1)))?;
+        let id = String::from("1");
+        let (lock, _) = storage.lock(&id, "tester").await?.success()?;
+        storage
+            .save_and_unlock(&id, &TestItem { count: 1 }, lock)
+            .await?;
+        assert_eq!(TestItem { count: 1 }, storage.load(&id).await?);
+
+        // changed behind the cache's back
+        let (lock, _) = storage.storage().lock(&id, "other").await?.success()?;
+        storage
+            .storage()
+            .save_and_unlock(&id, &TestItem { count: 2 }, lock)
+            .await?;
+        tokio::time::sleep(Duration::from_millis(5)).await;
+
+        let stale = OpOptions::default().with_consistency(Consistency::Eventual);
+        assert_eq!(TestItem { count: 1 }, storage.load_with(&id, &stale).await?);
+        let bypass = OpOptions::default().with_bypass_cache(true);
+        assert_eq!(
+            TestItem { count: 2 },
+            storage.load_with(&id, &bypass).await?
+        );
+        assert_eq!(TestItem { count: 1 }, storage.load_with(&id, &stale).await?);
+        let strong = OpOptions::default().with_consistency(Consistency::Strong);
+        assert_eq!(
+            TestItem { count: 2 },
+            storage.load_with(&id, &strong).await?
+        );
+        assert_eq!(TestItem { count: 2 }, storage.load(&id).await?);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,148 @@
+use crate::Storage;
+use crate::StorageDisk;
+use crate::StorageDiskLayout;
+use crate::StorageDiskPacked;
+use crate::StorageDynamoDb;
+use crate::StorageItem;
+use crate::StorageMemory;
+use crate::StorageNull;
+use color_eyre::eyre::Result;
+use serde::Deserialize;
+use std::path::PathBuf;
+
+/// The backend and its parameters, e.g. from a config file.
+///
+/// ```toml
+/// [storage]
+/// backend = "disk"
+/// path = "data/items"
+/// layout = "sharded"
+/// ```
+///
+/// Also see [crate::from_url], which builds the same storages from a url.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "backend", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum StorageConfig {
+    Disk {
+        path: PathBuf,
+        #[serde(default = "default_extension")]
+        extension: String,
+        #[serde(default)]
+        layout: StorageDiskLayout,
+        #[serde(default)]
+        read_only: bool,
+    },
+    DiskPacked {
+        path: PathBuf,
+    },
+    #[serde(rename = "dynamodb")]
+    DynamoDb {
+        table: String,
+        endpoint_url: Option<String>,
+        region: Option<String>,
+        consistent_read: Option<bool>,
+    },
+    Memory,
+    Null,
+}
+
+fn default_extension() -> String {
+    String::from("json")
+}
+
+impl StorageConfig {
+    /// Creates the configured storage.
+    /// Call [Storage::ensure_storage_exists] before using it.
+    pub async fn build<ITEM: StorageItem + Send + 'static>(
+        &self,
+    ) -> Result<Box<dyn Storage<ITEM>>> {
+        let storage: Box<dyn Storage<ITEM>> = match self {
+            StorageConfig::Disk {
+                path,
+                extension,
+                layout,
+                read_only,
+            } => {
+                let extension = PathBuf::from(extension);
+                let mut storage = if *read_only {
+                    StorageDisk::new_read_only(path, &extension).await
+                } else {
+                    StorageDisk::new(path, &extension).await
+                };
+                storage.set_layout(*layout)?;
+                Box::new(storage)
+            }
+            StorageConfig::DiskPacked { path } => Box::new(StorageDiskPacked::new(path).await),
+            StorageConfig::DynamoDb {
+                table,
+                endpoint_url,
+                region,
+                consistent_read,
+            } => {
+                let mut storage = StorageDynamoDb::new(table).await;
+                if let Some(endpoint_url) = endpoint_url {
+                    storage.set_endpoint_url(endpoint_url)?;
+                }
+                if let Some(region) = region {
+                    storage.set_region(region)?;
+                }
+                if let Some(consistent_read) = consistent_read {
+                    storage.set_consistent_read(*consistent_read)?;
+                }
+                Box::new(storage)
+            }
+            StorageConfig::Memory => Box::new(StorageMemory::default()),
+            StorageConfig::Null => Box::new(StorageNull::default()),
+        };
+
+        Ok(storage)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::StorageConfig;
+    use crate::StorageDiskLayout;
+    use color_eyre::Result;
+    use std::path::PathBuf;
+
+    #[test]
+    fn it_deserializes_configs() -> Result<()> {
+        let config: StorageConfig = serde_json::from_str(
+            r#"{ "backend": "disk", "path": "data/items", "layout": "sharded" }"#,
+        )?;
+        assert_eq!(
+            StorageConfig::Disk {
+                path: PathBuf::from("data/items"),
+                extension: String::from("json"),
+                layout: StorageDiskLayout::Sharded,
+                read_only: false,
+            },
+            config
+        );
+
+        let config: StorageConfig = serde_json::from_str(
+            r#"{ "backend": "dynamodb", "table": "items", "region": "eu-west-1" }"#,
+        )?;
+        assert_eq!(
+            StorageConfig::DynamoDb {
+                table: String::from("items"),
+                endpoint_url: None,
+                region: Some(String::from("eu-west-1")),
+                consistent_read: None,
+            },
+            config
+        );
+
+        let config: StorageConfig = serde_json::from_str(r#"{ "backend": "memory" }"#)?;
+        assert_eq!(StorageConfig::Memory, config);
+
+        assert!(serde_json::from_str::<StorageConfig>(
+            r#"{ "backend": "disk", "path": "data", "colour": "blue" }"#
+        )
+        .is_err());
+        assert!(serde_json::from_str::<StorageConfig>(r#"{ "backend": "ftp" }"#).is_err());
+
+        Ok(())
+    }
+}
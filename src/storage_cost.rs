@@ -0,0 +1,166 @@
+use crate::Storage;
+use crate::StorageItem;
+use serde::Serialize;
+
+/// DynamoDB reads are billed per 4 KB, strongly consistent.
+const READ_UNIT_BYTES: u64 = 4 * 1024;
+/// DynamoDB writes are billed per 1 KB.
+const WRITE_UNIT_BYTES: u64 = 1024;
+const LIST_PAGE_SIZE: usize = 1000;
+
+/// Capacity units to touch every item once, e.g. for a full export or a migration.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct CapacityUnits {
+    /// Strongly consistent, eventually consistent reads need half
+    pub read_units: u64,
+    pub write_units: u64,
+}
+
+impl CapacityUnits {
+    /// The units to read and write a single item of `size` bytes.
+    pub fn for_item(size: u64) -> Self {
+        Self {
+            read_units: size.div_ceil(READ_UNIT_BYTES).max(1),
+            write_units: size.div_ceil(WRITE_UNIT_BYTES).max(1),
+        }
+    }
+}
+
+/// The approximate size of a storage, from [Storage::estimate_cost], e.g. for capacity planning.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct StorageCost {
+    pub item_count: u64,
+    /// The stored size of all items, e.g. after compression, without locks and other overhead
+    pub total_bytes: u64,
+    pub largest_item_bytes: u64,
+    /// `None` for backends that aren't billed per capacity unit
+    pub capacity: Option<CapacityUnits>,
+}
+
+impl StorageCost {
+    fn add_item(&mut self, size: u64) {
+        self.item_count += 1;
+        self.total_bytes += size;
+        self.largest_item_bytes = self.largest_item_bytes.max(size);
+        if let Some(capacity) = &mut self.capacity {
+            let item = CapacityUnits::for_item(size);
+            capacity.read_units += item.read_units;
+            capacity.write_units += item.write_units;
+        }
+    }
+}
+
+/// Sums the sizes [Storage::list] reports and asks [Storage::item_size] for missing ones.
+/// Only reads, so it is safe to run against a live storage.
+pub(crate) async fn estimate_cost<ITEM, S>(
+    storage: &S,
+    with_capacity: bool,
+) -> color_eyre::eyre::Result<StorageCost>
+where
+    ITEM: StorageItem + Send,
+    S: Storage<ITEM> + ?Sized,
+{
+    let mut cost = StorageCost {
+        capacity: with_capacity.then(CapacityUnits::default),
+        ..Default::default()
+    };
+    let mut start = None;
+    loop {
+        let page = storage.list(start.as_deref(), Some(LIST_PAGE_SIZE)).await?;
+        for summary in page.items {
+            let size = match summary.size {
+                Some(size) => size,
+                None => storage.item_size(&summary.id).await?,
+            };
+            cost.add_item(size);
+        }
+        start = page.cursor;
+        if start.is_none() {
+            return Ok(cost);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::estimate_cost;
+    use crate::test_item::TestItem;
+    use crate::CapacityUnits;
+    use crate::Storage;
+    use crate::StorageDisk;
+    use crate::StorageMemory;
+    use color_eyre::Result;
+    use std::env;
+    use std::path::Path;
+
+    async fn save_counts<S: Storage<TestItem>>(storage: &S) -> Result<()> {
+        // `{"count":1}` is 11 bytes, `{"count":1000}` 14
+        for (id, count) in [("a", 1), ("b", 1000)] {
+            let id = String::from(id);
+            let (lock, _) = storage.lock(&id, "TEST").await?.success()?;
+            storage
+                .save_and_unlock(&id, &TestItem { count }, lock)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_rounds_capacity_units_up() {
+        let units = |read_units, write_units| CapacityUnits {
+            read_units,
+            write_units,
+        };
+        assert_eq!(units(1, 1), CapacityUnits::for_item(0));
+        assert_eq!(units(1, 1), CapacityUnits::for_item(1));
+        assert_eq!(units(1, 1), CapacityUnits::for_item(1024));
+        assert_eq!(units(1, 2), CapacityUnits::for_item(1025));
+        assert_eq!(units(1, 4), CapacityUnits::for_item(4096));
+        assert_eq!(units(2, 5), CapacityUnits::for_item(4097));
+        assert_eq!(units(100, 400), CapacityUnits::for_item(400 * 1024));
+    }
+
+    #[tokio::test]
+    async fn it_estimates_memory_storages() -> Result<()> {
+        let storage = StorageMemory::<TestItem>::default();
+        let cost = estimate_cost(&storage, true).await?;
+        assert_eq!(0, cost.item_count);
+        assert_eq!(Some(CapacityUnits::default()), cost.capacity);
+
+        save_counts(&storage).await?;
+        let cost = estimate_cost(&storage, true).await?;
+        assert_eq!(2, cost.item_count);
+        assert_eq!(25, cost.total_bytes);
+        assert_eq!(14, cost.largest_item_bytes);
+        assert_eq!(
+            Some(CapacityUnits {
+                read_units: 2,
+                write_units: 2,
+            }),
+            cost.capacity
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_estimates_disk_storages() -> Result<()> {
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_items_cost");
+        let _ = std::fs::remove_dir_all(&path);
+        let mut storage = StorageDisk::<TestItem>::new(&path, Path::new("test_item")).await;
+        storage.ensure_storage_exists().await?;
+
+        save_counts(&storage).await?;
+        let cost = storage.estimate_cost().await?;
+        assert_eq!(2, cost.item_count);
+        assert_eq!(25, cost.total_bytes);
+        assert_eq!(14, cost.largest_item_bytes);
+        assert_eq!(None, cost.capacity);
+
+        let _ = std::fs::remove_dir_all(&path);
+        Ok(())
+    }
+}
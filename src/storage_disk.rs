@@ -1,23 +1,654 @@
+use crate::exists_policy::is_orphan;
+use crate::links::ensure_valid_relation;
+use crate::operation_metrics;
+use crate::slow_op::SlowOp;
+use crate::storage::ensure_valid_create_tries;
+use crate::storage::ensure_valid_meta_key;
+use crate::storage::probe_storage;
+use crate::storage::DEFAULT_CREATE_TRIES;
+use crate::storage_item::deserialize_stored;
+use crate::storage_item::ensure_valid_id;
+use crate::storage_item::serialize_valid;
+use crate::Clock;
+use crate::DefaultIdGenerator;
+use crate::ExistsPolicy;
+use crate::IdAllocator;
+use crate::IdCounter;
+use crate::IdGenerator;
+use crate::ItemSummary;
 use crate::LockResult;
 #[cfg(feature = "metadata")]
 use crate::Metadata;
+use crate::Page;
+use crate::PayloadReader;
+use crate::ProgressSink;
+use crate::ProgressTracker;
 use crate::Storage;
+use crate::StorageError;
+use crate::StorageId;
 use crate::StorageItem;
 use crate::StorageLock;
+use crate::SystemClock;
 use async_trait::async_trait;
+use chrono::DateTime;
 use color_eyre::eyre::eyre;
 use color_eyre::eyre::Result;
+#[cfg(feature = "wipe")]
+use futures_util::future::join_all;
+use serde::Deserialize;
+use serde::Serialize;
 use tokio::sync::Semaphore;
 
+use chrono::Utc;
+
 use core::marker::PhantomData;
-use std::fs;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::SystemTime;
+use tokio::fs;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+use tokio_util::sync::CancellationToken;
+
+/// How hard StorageDisk tries to get writes onto the disk before returning.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum StorageDiskDurability {
+    /// Leave flushing to the operating system. Fast, but recent writes can be lost on power failure.
+    /// This is the default.
+    #[default]
+    None,
+    /// Sync the file contents before it replaces the old file.
+    SyncFile,
+    /// Sync the file contents and the containing folder after the rename,
+    /// so the new directory entry survives a power failure, too.
+    SyncFileAndFolder,
+}
+
+/// Mode bits and ownership of the files StorageDisk writes, see [StorageDisk::set_permissions].
+///
+/// Only supported on unix. Changing the owner usually needs root.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StorageDiskPermissions {
+    /// e.g. `0o600`, set explicitly, so the umask doesn't matter
+    pub mode: Option<u32>,
+    /// The user id
+    pub owner: Option<u32>,
+    /// The group id
+    pub group: Option<u32>,
+}
+
+impl StorageDiskPermissions {
+    #[cfg(unix)]
+    async fn apply(&self, file: &fs::File) -> std::io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        if let Some(mode) = self.mode {
+            file.set_permissions(std::fs::Permissions::from_mode(mode))
+                .await?;
+        }
+        if self.owner.is_some() || self.group.is_some() {
+            std::os::unix::fs::fchown(file, self.owner, self.group)?;
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    async fn apply(&self, _file: &fs::File) -> std::io::Result<()> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "File permissions are only supported on unix",
+        ))
+    }
+
+    /// Changes the file at `path` if it doesn't match, returns if it did.
+    #[cfg(unix)]
+    async fn enforce(&self, path: &Path) -> std::io::Result<bool> {
+        use std::os::unix::fs::MetadataExt;
+        use std::os::unix::fs::PermissionsExt;
+
+        let metadata = fs::metadata(path).await?;
+        let mut changed = false;
+        if let Some(mode) = self.mode.filter(|m| metadata.mode() & 0o7777 != *m) {
+            fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).await?;
+            changed = true;
+        }
+        let owner = self.owner.filter(|o| metadata.uid() != *o);
+        let group = self.group.filter(|g| metadata.gid() != *g);
+        if owner.is_some() || group.is_some() {
+            std::os::unix::fs::chown(path, owner, group)?;
+            changed = true;
+        }
+
+        Ok(changed)
+    }
+
+    #[cfg(not(unix))]
+    async fn enforce(&self, _path: &Path) -> std::io::Result<bool> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "File permissions are only supported on unix",
+        ))
+    }
+}
+
+/// How files are written, see [StorageDisk::set_durability] and [StorageDisk::set_permissions].
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct WriteOptions {
+    durability: StorageDiskDurability,
+    permissions: Option<StorageDiskPermissions>,
+}
+
+impl From<StorageDiskDurability> for WriteOptions {
+    fn from(durability: StorageDiskDurability) -> Self {
+        Self {
+            durability,
+            permissions: None,
+        }
+    }
+}
+
+/// Writes everything read from `reader` to a new file at `path`, syncing it if requested.
+/// Returns the number of bytes written.
+async fn write_new<R: AsyncRead + Unpin + ?Sized>(
+    path: &Path,
+    reader: &mut R,
+    write: WriteOptions,
+) -> std::io::Result<u64> {
+    let mut file = fs::File::create(path).await?;
+    // before anything is in it
+    if let Some(permissions) = &write.permissions {
+        permissions.apply(&file).await?;
+    }
+    let len = tokio::io::copy(reader, &mut file).await?;
+    if write.durability != StorageDiskDurability::None {
+        file.sync_all().await?;
+    }
+
+    Ok(len)
+}
+
+/// Syncs the folder containing `path`, if requested.
+async fn sync_folder(path: &Path, durability: StorageDiskDurability) -> std::io::Result<()> {
+    // folders can only be opened, and synced, on unix
+    if cfg!(unix) && durability == StorageDiskDurability::SyncFileAndFolder {
+        if let Some(folder) = path.parent() {
+            fs::File::open(folder).await?.sync_all().await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes to a temporary file next to `path` and then renames it over `path`.
+/// Readers see either the old or the new content, never a partial write.
+pub(crate) async fn write_atomic(
+    path: &Path,
+    mut data: &[u8],
+    write: WriteOptions,
+) -> std::io::Result<()> {
+    write_atomic_from(path, &mut data, write).await?;
+
+    Ok(())
+}
+
+/// Whether `name` is a temporary file of [write_atomic], still being written.
+fn is_temp_file(name: &std::ffi::OsStr) -> bool {
+    let name = name.to_string_lossy();
+    name.starts_with('.') && name.ends_with(".tmp")
+}
+
+/// Like [write_atomic], with the content read from `reader`, never holding all of it in memory.
+/// Returns the number of bytes written.
+async fn write_atomic_from<R: AsyncRead + Unpin + ?Sized>(
+    path: &Path,
+    reader: &mut R,
+    write: WriteOptions,
+) -> std::io::Result<u64> {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp_path = path.with_file_name(format!(".{file_name}.{}.tmp", nanoid::nanoid!()));
+    let r = match write_new(&temp_path, reader, write).await {
+        Ok(len) => fs::rename(&temp_path, path).await.map(|()| len),
+        Err(e) => Err(e),
+    };
+    let len = match r {
+        Ok(len) => len,
+        Err(e) => {
+            let _ = fs::remove_file(&temp_path).await;
+            return Err(e);
+        }
+    };
+    sync_folder(path, write.durability).await?;
+
+    Ok(len)
+}
+
+/// Names that Windows reserves, regardless of extension
+const RESERVED_FILE_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM0", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7",
+    "COM8", "COM9", "LPT0", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Percent-encodes everything but `[A-Za-z0-9_-]`, so any ID becomes a safe file name
+/// that can't escape the base path, e.g. `../a.b` becomes `%2E%2E%2Fa%2Eb`.
+/// IDs like nanoids are unchanged, unless [StorageDiskFileNames::CaseSafe] encodes their uppercase letters, too.
+fn encode_file_name(id: &str, names: StorageDiskFileNames) -> String {
+    let case_safe = names == StorageDiskFileNames::CaseSafe;
+    let mut name = String::with_capacity(id.len());
+    for b in id.bytes() {
+        let plain = if case_safe {
+            b.is_ascii_lowercase() || b.is_ascii_digit()
+        } else {
+            b.is_ascii_alphanumeric()
+        };
+        if plain || b == b'_' || b == b'-' {
+            name.push(b as char);
+        } else {
+            name.push_str(&format!("%{b:02X}"));
+        }
+    }
+    if RESERVED_FILE_NAMES
+        .iter()
+        .any(|r| r.eq_ignore_ascii_case(&name))
+    {
+        // all reserved names start with an ascii letter
+        name = format!("%{:02X}{}", name.as_bytes()[0], &name[1..]);
+    }
+
+    name
+}
+
+/// Windows limits paths to 260 characters, unless they are absolute and start with `\\?\`.
+#[cfg(windows)]
+fn long_path(path: &Path) -> PathBuf {
+    match std::path::absolute(path) {
+        // UNC, or already extended
+        Ok(p) if !p.as_os_str().to_string_lossy().starts_with(r"\\") => {
+            let mut long = std::ffi::OsString::from(r"\\?\");
+            long.push(p.as_os_str());
+            PathBuf::from(long)
+        }
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(not(windows))]
+fn long_path(path: &Path) -> PathBuf {
+    path.to_path_buf()
+}
+
+/// Checks if `folder` treats file names only differing in case as the same file, e.g. on macOS.
+async fn is_case_insensitive(folder: &Path) -> std::io::Result<bool> {
+    let name = format!(".case-probe-{}.tmp", nanoid::nanoid!().to_lowercase());
+    let probe = folder.join(&name);
+    fs::write(&probe, b"").await?;
+    let r = fs::metadata(folder.join(name.to_uppercase())).await;
+    let _ = fs::remove_file(&probe).await;
+
+    Ok(r.is_ok())
+}
+
+/// The decoded names of all files ending in `extension`, e.g. `.json`, in `folders`.
+fn names_in_folders(folders: &[PathBuf], extension: &str) -> Result<Vec<String>> {
+    let mut names = Vec::default();
+    for folder in folders {
+        let entries =
+            std::fs::read_dir(folder).map_err(|e| eyre!("Can't read folder {folder:?} -> {e}"))?;
+        for entry in entries {
+            let entry = entry?;
+            if !entry.file_type().is_ok_and(|t| t.is_file()) {
+                continue;
+            }
+            let f = entry.file_name();
+            // item file names are always encoded, so never lossy
+            let Some(name) = f.to_str().and_then(|f| f.strip_suffix(extension)) else {
+                continue;
+            };
+            match decode_file_name(name) {
+                Some(id) => names.push(id),
+                None => tracing::warn!("Skipping unexpected file {f:?}"),
+            }
+        }
+    }
+
+    Ok(names)
+}
+
+/// Reverses [encode_file_name]. Returns `None` for names we didn't encode,
+/// e.g. `a.b` or `%61`, so no two file names decode to the same ID.
+pub(crate) fn decode_file_name(name: &str) -> Option<String> {
+    let mut id = Vec::with_capacity(name.len());
+    let mut bytes = name.bytes();
+    while let Some(b) = bytes.next() {
+        if b == b'%' {
+            let hex = [bytes.next()?, bytes.next()?];
+            let hex = std::str::from_utf8(&hex).ok()?;
+            id.push(u8::from_str_radix(hex, 16).ok()?);
+        } else {
+            id.push(b);
+        }
+    }
+
+    let id = String::from_utf8(id).ok()?;
+    let encoded = [StorageDiskFileNames::Plain, StorageDiskFileNames::CaseSafe]
+        .into_iter()
+        .any(|names| encode_file_name(&id, names) == name);
+
+    encoded.then_some(id)
+}
+
+/// How ids become file names, see [StorageDisk::set_file_names].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageDiskFileNames {
+    /// Letters are kept as they are, e.g. `AbC1.ext`. This is the default.
+    /// Ids only differing in case share a file on case-insensitive file systems, e.g. on macOS and Windows.
+    #[default]
+    Plain,
+    /// Uppercase letters are percent-encoded, too, e.g. `%41b%431.ext`,
+    /// so ids only differing in case never share a file.
+    CaseSafe,
+}
+
+/// How item files are arranged below the base path.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageDiskLayout {
+    /// All files are placed directly in the base path, e.g. `abcd1234.ext`. This is the default.
+    #[default]
+    Flat,
+    /// Files are spread over two levels of folders derived from a hash of the ID,
+    /// e.g. `3f/a2/abcd1234.ext`, so folders stay small even with millions of items.
+    Sharded,
+}
+
+impl StorageDiskLayout {
+    fn depth(&self) -> usize {
+        match self {
+            Self::Flat => 0,
+            Self::Sharded => 2,
+        }
+    }
+
+    fn item_folder(&self, base_path: &Path, id: &str) -> PathBuf {
+        let mut p = base_path.to_path_buf();
+        if *self == Self::Sharded {
+            // FNV-1a, since the hash must be stable across builds and platforms
+            let hash = id.bytes().fold(0xcbf29ce484222325_u64, |h, b| {
+                (h ^ b as u64).wrapping_mul(0x100000001b3)
+            });
+            p.push(format!("{:02x}", hash & 0xff));
+            p.push(format!("{:02x}", (hash >> 8) & 0xff));
+        }
+
+        p
+    }
+
+    fn is_shard_name(name: &str) -> bool {
+        name.len() == 2 && name.bytes().all(|b| b.is_ascii_hexdigit())
+    }
+}
+
+/// Creates `path` with the given content, failing with `AlreadyExists` if it already exists.
+/// The content is written to a temporary file first, which is then hard linked to `path`.
+/// Linking is atomic across processes and never exposes a partially written file.
+async fn create_exclusive(
+    path: &Path,
+    mut data: &[u8],
+    write: WriteOptions,
+) -> std::io::Result<()> {
+    create_exclusive_from(path, &mut data, write).await?;
+
+    Ok(())
+}
+
+/// Like [create_exclusive], with the content read from `reader`.
+/// Returns the number of bytes written.
+async fn create_exclusive_from<R: AsyncRead + Unpin + ?Sized>(
+    path: &Path,
+    reader: &mut R,
+    write: WriteOptions,
+) -> std::io::Result<u64> {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp_path = path.with_file_name(format!(".{file_name}.{}.tmp", nanoid::nanoid!()));
+    let r = match write_new(&temp_path, reader, write).await {
+        Ok(len) => fs::hard_link(&temp_path, path).await.map(|()| len),
+        Err(e) => Err(e),
+    };
+    let _ = fs::remove_file(&temp_path).await;
+    let len = r?;
+    sync_folder(path, write.durability).await?;
+
+    Ok(len)
+}
+
+/// The manifest of a backup, see [StorageDisk::backup_to].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageDiskBackup {
+    pub created_at: chrono::DateTime<Utc>,
+    pub layout: StorageDiskLayout,
+    /// Backups of older versions always used [StorageDiskFileNames::Plain]
+    #[serde(default)]
+    pub file_names: StorageDiskFileNames,
+    pub extension: PathBuf,
+    /// Item files only, blobs, aliases, and links are not counted
+    pub items: usize,
+    /// All files, relative to the base path
+    pub files: Vec<PathBuf>,
+}
+
+/// Backups are folders named after their creation time, so sorting by name sorts by age
+const BACKUP_NAME_FORMAT: &str = "%Y%m%dT%H%M%S%.3fZ";
+const BACKUP_MANIFEST: &str = "manifest.json";
+
+/// Which lockfiles are considered stale, e.g. after a crash.
+///
+/// A lock is stale if it matches *any* of the given criteria.
+#[derive(Debug, Default, Clone)]
+pub struct StorageDiskStaleLocks {
+    /// Locks older than this are stale
+    pub max_age: Option<Duration>,
+    /// Locks held by somebody starting with this are stale, e.g. the name of a crashed process
+    pub who_prefix: Option<String>,
+}
+
+impl StorageDiskStaleLocks {
+    fn is_stale(&self, lock: &StorageLock, now: chrono::DateTime<Utc>) -> bool {
+        let too_old = self.max_age.is_some_and(|max_age| {
+            let age = now.signed_duration_since(*lock.when());
+            age.to_std().is_ok_and(|age| age > max_age)
+        });
+        let matches_who = self
+            .who_prefix
+            .as_ref()
+            .is_some_and(|who_prefix| lock.who().starts_with(who_prefix));
+
+        too_old || matches_who
+    }
+}
+
+/// The magic bytes every zstd frame starts with
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// How item files are compressed on save.
+///
+/// Loading detects compressed files by their magic bytes,
+/// so folders with a mix of compressed and uncompressed files keep working.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum StorageDiskCompression {
+    /// Item files are written as serialized. This is the default.
+    #[default]
+    None,
+    /// Item files are compressed with zstd at the given level, e.g. `3`.
+    #[cfg(feature = "zstd")]
+    Zstd { level: i32 },
+}
+
+impl StorageDiskCompression {
+    fn compress(&self, data: Vec<u8>) -> Result<Vec<u8>> {
+        match self {
+            Self::None => Ok(data),
+            #[cfg(feature = "zstd")]
+            Self::Zstd { level } => Ok(zstd::encode_all(data.as_slice(), *level)?),
+        }
+    }
+}
+
+/// Returns the serialized item from the content of an item file, decompressing if needed.
+pub(crate) fn decompress_item_data(data: Vec<u8>) -> Result<Vec<u8>> {
+    if !data.starts_with(&ZSTD_MAGIC) {
+        return Ok(data);
+    }
+    #[cfg(feature = "zstd")]
+    {
+        Ok(zstd::decode_all(data.as_slice())?)
+    }
+    #[cfg(not(feature = "zstd"))]
+    {
+        Err(eyre!("Item is zstd compressed, enable the zstd feature"))
+    }
+}
+
+/// What to do when a save would exceed the [StorageDiskQuota].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum StorageDiskQuotaAction {
+    /// Fail the save with [StorageError::QuotaExceeded]. This is the default.
+    #[default]
+    Reject,
+    /// Remove the least recently modified unlocked items until the save fits.
+    /// Fails with [StorageError::QuotaExceeded] if that isn't enough.
+    EvictLeastRecentlyModified,
+}
+
+/// Limits for the total size of all item files and their number.
+///
+/// Note: Usage is measured by scanning the folder on every save, so this is meant for small storages.
+#[derive(Debug, Default, Clone)]
+pub struct StorageDiskQuota {
+    pub max_bytes: Option<u64>,
+    pub max_items: Option<usize>,
+    pub on_exceeded: StorageDiskQuotaAction,
+}
+
+impl StorageDiskQuota {
+    fn is_exceeded(&self, bytes: u64, items: usize) -> bool {
+        self.max_bytes.is_some_and(|max_bytes| bytes > max_bytes)
+            || self.max_items.is_some_and(|max_items| items > max_items)
+    }
+
+    fn exceeded_error(&self, bytes: u64, items: usize) -> StorageError {
+        StorageError::QuotaExceeded {
+            bytes,
+            max_bytes: self.max_bytes,
+            items,
+            max_items: self.max_items,
+        }
+    }
+}
+
+/// An item file found on disk.
+#[derive(Debug)]
+struct ItemFile {
+    id: String,
+    path: PathBuf,
+    len: u64,
+    modified: SystemTime,
+}
+
+/// How often [DiskIdCounter::reserve] checks if the counter got unlocked, before giving up
+const COUNTER_LOCK_TRIES: u32 = 500;
+
+/// How many items [Storage::delete_many] removes in parallel
+#[cfg(feature = "wipe")]
+const DELETE_CONCURRENCY: usize = 16;
+
+/// An [IdCounter] stored in a file, see [StorageDisk::id_counter].
+///
+/// The file is locked while updating, so it can be shared by multiple processes.
+#[derive(Debug)]
+pub struct DiskIdCounter {
+    path: PathBuf,
+    write: WriteOptions,
+}
+
+impl DiskIdCounter {
+    async fn reserve_locked(&self, count: u64) -> Result<u64> {
+        let first = match fs::read_to_string(&self.path).await {
+            Ok(value) => value
+                .trim()
+                .parse::<u64>()
+                .map_err(|e| eyre!("Broken counter {:?} -> {e}", &self.path))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => 1,
+            Err(e) => return Err(eyre!("Can't read counter {:?} -> {e}", &self.path)),
+        };
+        let next = first + count;
+        write_atomic(&self.path, next.to_string().as_bytes(), self.write)
+            .await
+            .map_err(|e| eyre!("Can't write counter {:?} -> {e:?}", &self.path))?;
+
+        Ok(first)
+    }
+}
+
+#[async_trait]
+impl IdCounter for DiskIdCounter {
+    async fn reserve(&self, count: u64) -> Result<u64> {
+        // not `.lock`, this is not an item lock
+        let mut lock_path = self.path.clone();
+        lock_path.set_extension("counter-lock");
+        let mut tries = 0;
+        loop {
+            match create_exclusive(&lock_path, b"", self.write).await {
+                Ok(()) => break,
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    tries += 1;
+                    if tries >= COUNTER_LOCK_TRIES {
+                        return Err(eyre!(
+                            "Counter {lock_path:?} stays locked, remove it if stale"
+                        ));
+                    }
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+                Err(e) => return Err(eyre!("Can't lock counter {lock_path:?} -> {e:?}")),
+            }
+        }
+
+        let r = self.reserve_locked(count).await;
+        if let Err(e) = fs::remove_file(&lock_path).await {
+            tracing::error!("Can't unlock counter {lock_path:?} -> {e:?}");
+        }
+
+        r
+    }
+}
 
 #[derive(Debug)]
 pub struct StorageDisk<ITEM: StorageItem> {
     base_path: PathBuf,
     extension: PathBuf,
+    layout: StorageDiskLayout,
+    file_names: StorageDiskFileNames,
+    durability: StorageDiskDurability,
+    permissions: Option<StorageDiskPermissions>,
+    stale_locks_on_start: Option<StorageDiskStaleLocks>,
+    probe_id: Option<ITEM::ID>,
+    quota: Option<StorageDiskQuota>,
+    compression: StorageDiskCompression,
+    read_only: bool,
+    id_allocator: Option<IdAllocator>,
+    aliases: bool,
+    slow_op_threshold: Option<Duration>,
+    create_tries: usize,
+    versions: usize,
+    clock: Arc<dyn Clock>,
+    id_generator: Arc<dyn IdGenerator<ITEM::ID>>,
+    exists_policy: ExistsPolicy,
+    immutable: bool,
     item_type: PhantomData<ITEM>,
     lock_semaphore: Semaphore,
     #[cfg(feature = "metadata")]
@@ -26,17 +657,45 @@ pub struct StorageDisk<ITEM: StorageItem> {
 
 impl<ITEM: StorageItem> StorageDisk<ITEM> {
     pub async fn ensure_folder_exists(&mut self) -> Result<()> {
-        std::fs::create_dir_all(&self.base_path)
+        if self.read_only {
+            // never create anything, just make sure there is something to read
+            return match fs::metadata(&self.base_path).await {
+                Ok(metadata) if metadata.is_dir() => Ok(()),
+                _ => Err(eyre!("Folder {:?} doesn't exist", &self.base_path)),
+            };
+        }
+        fs::create_dir_all(&self.base_path)
+            .await
             .map_err(|e| eyre!("Could not create folder {:?} -> {e}", &self.base_path))?;
 
         Ok(())
     }
 }
 impl<ITEM: StorageItem> StorageDisk<ITEM> {
+    /// On Windows the `base_path` is made absolute and extended, so deep layouts and long ids
+    /// aren't limited to 260 characters.
     pub async fn new(base_path: &Path, extension: &Path) -> Self {
         Self {
-            base_path: base_path.to_path_buf(),
+            base_path: long_path(base_path),
             extension: extension.to_path_buf(),
+            layout: StorageDiskLayout::default(),
+            file_names: StorageDiskFileNames::default(),
+            durability: StorageDiskDurability::default(),
+            permissions: None,
+            stale_locks_on_start: None,
+            probe_id: None,
+            quota: None,
+            compression: StorageDiskCompression::default(),
+            read_only: false,
+            id_allocator: None,
+            aliases: false,
+            slow_op_threshold: None,
+            create_tries: DEFAULT_CREATE_TRIES,
+            versions: 0,
+            clock: Arc::new(SystemClock),
+            id_generator: Arc::new(DefaultIdGenerator),
+            exists_policy: ExistsPolicy::default(),
+            immutable: false,
             item_type: PhantomData,
             lock_semaphore: Semaphore::new(1),
             #[cfg(feature = "metadata")]
@@ -44,450 +703,3827 @@ impl<ITEM: StorageItem> StorageDisk<ITEM> {
         }
     }
 
-    fn file_path(&self, id: &ITEM::ID) -> PathBuf {
-        let mut p = PathBuf::new();
-        p.push(&self.base_path);
-        let id = format!("{id}");
-        let idp = Path::new(&id);
-        p.push(idp);
-        p.set_extension(&self.extension);
+    /// Opens the storage read-only.
+    /// Everything that would write, e.g. `lock`, `save`, and `unlock`, fails with [StorageError::ReadOnly],
+    /// and folders are never created.
+    pub async fn new_read_only(base_path: &Path, extension: &Path) -> Self {
+        let mut s = Self::new(base_path, extension).await;
+        s.read_only = true;
 
-        p
+        s
     }
-    fn lock_path(&self, id: &ITEM::ID) -> PathBuf {
-        let mut p = PathBuf::new();
-        p.push(&self.base_path);
-        let id = format!("{id}");
-        let idp = Path::new(&id);
-        p.push(idp);
-        p.set_extension("lock");
 
-        p
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
     }
-}
 
-#[cfg(feature = "metadata")]
-impl<ITEM: StorageItem> StorageDisk<ITEM> {
-    fn update_highest_seen_id(&self, id: &ITEM::ID) {
-        self.metadata.update_highest_seen_id(id);
+    fn ensure_writable(&self) -> Result<()> {
+        if self.read_only {
+            Err(StorageError::ReadOnly.into())
+        } else {
+            Ok(())
+        }
     }
-}
 
-#[cfg(not(feature = "metadata"))]
-impl<ITEM: StorageItem> StorageDisk<ITEM> {
-    fn update_highest_seen_id(&self, _id: &ITEM::ID) {}
-}
+    pub fn base_path(&self) -> &Path {
+        &self.base_path
+    }
 
-#[async_trait]
-impl<ITEM: StorageItem + std::marker::Send> Storage<ITEM> for StorageDisk<ITEM> {
-    async fn ensure_storage_exists(&mut self) -> Result<()> {
-        self.ensure_folder_exists().await
+    pub fn extension(&self) -> &Path {
+        &self.extension
     }
-    async fn create(&self) -> Result<ITEM::ID> {
-        let mut tries = 10;
-        loop {
-            //let id = nanoid::nanoid!();
-            let id = ITEM::generate_next_id(None);
-            if !self.exists(&id).await? {
-                return Ok(id);
-            }
 
-            tries -= 1;
-            if tries <= 0 {
-                todo!();
-            }
+    pub fn layout(&self) -> StorageDiskLayout {
+        self.layout
+    }
+
+    /// Selects how item files are arranged below the base path.
+    /// Use [StorageDisk::migrate_layout] to move existing items.
+    pub fn set_layout(&mut self, layout: StorageDiskLayout) -> Result<()> {
+        self.layout = layout;
+
+        Ok(())
+    }
+
+    pub fn file_names(&self) -> StorageDiskFileNames {
+        self.file_names
+    }
+
+    /// Selects how ids become file names, use [StorageDiskFileNames::CaseSafe] on case-insensitive file systems.
+    /// Use [StorageDisk::migrate_file_names] to rename existing items.
+    pub fn set_file_names(&mut self, file_names: StorageDiskFileNames) -> Result<()> {
+        self.file_names = file_names;
+
+        Ok(())
+    }
+
+    /// Selects if and how item and lock writes are synced to disk.
+    pub fn set_durability(&mut self, durability: StorageDiskDurability) -> Result<()> {
+        self.durability = durability;
+
+        Ok(())
+    }
+
+    /// Sets the mode bits and ownership of every file written, e.g. `0o600` for items with user data.
+    /// Existing files are changed by [Storage::ensure_storage_exists], see [StorageDisk::enforce_permissions].
+    ///
+    /// `None`, the default, leaves them to the umask and the user of the process.
+    pub fn set_permissions(&mut self, permissions: Option<StorageDiskPermissions>) -> Result<()> {
+        if cfg!(not(unix)) && permissions.is_some() {
+            return Err(eyre!("File permissions are only supported on unix"));
         }
+        self.permissions = permissions;
+
+        Ok(())
     }
-    async fn exists(&self, id: &ITEM::ID) -> Result<bool> {
-        //let p = self.file_path(id.into());
-        //let p = self.file_path(&format!("{id}"));
-        let p = self.file_path(id);
-        tracing::debug!("{p:?}");
 
-        if fs::metadata(p).is_ok() {
-            self.update_highest_seen_id(&id);
-            Ok(true)
-        } else {
-            // the lockfile already exists, but the data file doesn't
-            // might happen when somebody crashed during creation
-            // or is in the middle of creation
-            let p = self.lock_path(id);
-            if fs::metadata(p).is_ok() {
-                self.update_highest_seen_id(&id);
-                Ok(true)
-            } else {
-                Ok(false)
+    fn write_options(&self) -> WriteOptions {
+        WriteOptions {
+            durability: self.durability,
+            permissions: self.permissions,
+        }
+    }
+
+    /// Applies the [StorageDisk::set_permissions] to all existing files below the base path,
+    /// and returns how many were changed. Temporary files are skipped, they get the permissions before their data.
+    pub async fn enforce_permissions(&self) -> Result<usize> {
+        let Some(permissions) = &self.permissions else {
+            return Ok(0);
+        };
+        self.ensure_writable()?;
+        let mut changed = 0;
+        let mut folders = vec![self.base_path.clone()];
+        while let Some(folder) = folders.pop() {
+            let mut entries = fs::read_dir(&folder).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let Ok(file_type) = entry.file_type().await else {
+                    continue;
+                };
+                if file_type.is_dir() {
+                    folders.push(entry.path());
+                    continue;
+                }
+                if is_temp_file(&entry.file_name()) {
+                    continue;
+                }
+                match permissions.enforce(&entry.path()).await {
+                    Ok(true) => changed += 1,
+                    Ok(false) => {}
+                    // removed since listing the folder
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(e) => {
+                        return Err(eyre!("Can't set permissions of {:?} -> {e}", entry.path()))
+                    }
+                }
             }
         }
+
+        Ok(changed)
     }
 
-    async fn load(&self, id: &ITEM::ID) -> Result<ITEM> {
-        let p = self.file_path(id);
-        let b = fs::read(p.clone()).map_err(|e| eyre!("Can't load from {p:?} -> {e}"))?;
-        let i = ITEM::deserialize(&b)?;
-        self.update_highest_seen_id(&id);
+    /// Keeps the previous `versions` item files on save, e.g. `abcd1234.item.1` for the one before the current,
+    /// see [StorageDisk::load_previous]. Older versions are removed on save.
+    ///
+    /// Versions don't count towards the [StorageDiskQuota]. 0, the default, keeps none.
+    pub fn set_versions(&mut self, versions: usize) -> Result<()> {
+        self.versions = versions;
 
-        Ok(i)
+        Ok(())
     }
 
-    async fn save(&self, id: &ITEM::ID, item: &ITEM, lock: &StorageLock) -> Result<()> {
-        if !self.verify_lock(id, lock).await? {
-            Err(eyre!("Lock invalid!"))
-        } else {
-            let p = self.file_path(id);
-            let b = item.serialize()?;
-            fs::write(p.clone(), b).map_err(|e| eyre!("Can't save to {p:?}: {e:?}"))?;
-            self.update_highest_seen_id(&id);
-            Ok(())
-        }
+    /// Selects how item files are compressed on save.
+    pub fn set_compression(&mut self, compression: StorageDiskCompression) -> Result<()> {
+        self.compression = compression;
+
+        Ok(())
+    }
+
+    /// Enables [Storage::add_alias].
+    /// Every item operation checks for an alias file first, so this is off by default.
+    pub fn set_aliases_enabled(&mut self, enabled: bool) -> Result<()> {
+        self.aliases = enabled;
+
+        Ok(())
+    }
+
+    /// Logs a warning for every operation that takes longer than `threshold`.
+    pub fn set_slow_op_threshold(&mut self, threshold: Option<Duration>) -> Result<()> {
+        self.slow_op_threshold = threshold;
+
+        Ok(())
+    }
+
+    /// How often [Storage::create] generates a new id, when it collides with an existing one, defaults to 10.
+    pub fn set_create_tries(&mut self, tries: usize) -> Result<()> {
+        ensure_valid_create_tries(tries)?;
+        self.create_tries = tries;
+
+        Ok(())
+    }
+
+    /// Where the time for locks and stale lock checks comes from, defaults to [SystemClock].
+    pub fn set_clock(&mut self, clock: impl Clock + 'static) -> Result<()> {
+        self.clock = Arc::new(clock);
+
+        Ok(())
+    }
+
+    /// Where [Storage::create] gets new ids from, defaults to [DefaultIdGenerator].
+    pub fn set_id_generator(
+        &mut self,
+        id_generator: impl IdGenerator<ITEM::ID> + 'static,
+    ) -> Result<()> {
+        self.id_generator = Arc::new(id_generator);
+
+        Ok(())
+    }
+
+    /// Locks held longer than this are logged with a warning and counted in [crate::LockStats].
+    #[cfg(feature = "metadata")]
+    pub fn set_max_lock_hold_time(&mut self, max_lock_hold_time: Option<Duration>) -> Result<()> {
+        self.metadata.set_max_lock_hold_time(max_lock_hold_time);
+
+        Ok(())
+    }
+
+    /// Whether lockfiles without item files count in [Storage::exists], see [ExistsPolicy].
+    pub fn set_exists_policy(&mut self, exists_policy: ExistsPolicy) -> Result<()> {
+        self.exists_policy = exists_policy;
+
+        Ok(())
+    }
+
+    /// Items can only be saved once, later saves fail with [StorageError::Immutable].
+    /// Item files are created exclusively, so concurrent saves can't both win,
+    /// and [StorageDisk::set_versions] has nothing to keep. Off by default.
+    pub fn set_immutable(&mut self, immutable: bool) -> Result<()> {
+        self.immutable = immutable;
+
+        Ok(())
+    }
+
+    /// Lets [Storage::create] take ids from the allocator, instead of [crate::StorageId::generate_new].
+    pub fn set_id_allocator(&mut self, id_allocator: Option<IdAllocator>) -> Result<()> {
+        self.id_allocator = id_allocator;
+
+        Ok(())
+    }
+
+    /// Returns a counter stored next to the items, e.g. for an [IdAllocator].
+    /// All instances sharing the folder share the counter.
+    pub fn id_counter(&self, name: &str) -> Result<DiskIdCounter> {
+        self.ensure_writable()?;
+        // chosen by the application, and kept across changes of the file names
+        let mut path = self
+            .base_path
+            .join(encode_file_name(name, StorageDiskFileNames::Plain));
+        path.set_extension("counter");
+
+        Ok(DiskIdCounter {
+            path,
+            write: self.write_options(),
+        })
+    }
+
+    /// Limits the disk usage, checked on every save.
+    pub fn set_quota(&mut self, quota: Option<StorageDiskQuota>) -> Result<()> {
+        self.quota = quota;
+
+        Ok(())
+    }
+
+    /// Returns the total size of all item files and their number.
+    pub async fn usage(&self) -> Result<(u64, usize)> {
+        let files = self.item_files().await?;
+        let bytes = files.iter().map(|f| f.len).sum();
+        self.record_totals(files.len() as u64, Some(bytes));
+        Ok((bytes, files.len()))
+    }
+
+    /// Removes stale lockfiles in [Storage::ensure_storage_exists].
+    pub fn set_stale_locks_on_start(
+        &mut self,
+        stale_locks: Option<StorageDiskStaleLocks>,
+    ) -> Result<()> {
+        self.stale_locks_on_start = stale_locks;
+
+        Ok(())
+    }
+
+    /// Locks, writes, and reads back the reserved `probe_id` in [Storage::ensure_storage_exists],
+    /// so missing permissions fail there, instead of on the first real request.
+    /// Read-only storages only check that it exists.
+    pub fn set_probe_id(&mut self, probe_id: Option<ITEM::ID>) -> Result<()> {
+        self.probe_id = probe_id;
+
+        Ok(())
+    }
+
+    /// Removes all stale lockfiles and returns how many were removed.
+    ///
+    /// Only use this when no other process is working on the folder,
+    /// otherwise a lock could be removed while it is being renewed.
+    pub async fn cleanup_stale_locks(&self, stale_locks: &StorageDiskStaleLocks) -> Result<usize> {
+        self.ensure_writable()?;
+        let _sem = self.lock_semaphore.acquire().await?;
+
+        let now = self.clock.now();
+        let mut removed = 0;
+        for id in self.names_in_layout(self.layout, Path::new("lock")).await? {
+            let id = ITEM::ID::from_string(&id)?;
+            let l = self.lock_path(&id);
+            let lock_json = fs::read(&l).await?;
+            let is_stale = match serde_json::from_slice::<StorageLock>(&lock_json) {
+                Ok(lock) => stale_locks.is_stale(&lock, now),
+                Err(e) => {
+                    // probably from a crash while writing the lock
+                    tracing::warn!("Unreadable lockfile {l:?} is stale -> {e:?}");
+                    true
+                }
+            };
+            if is_stale {
+                tracing::info!("Removing stale lockfile {l:?}");
+                fs::remove_file(&l)
+                    .await
+                    .map_err(|e| eyre!("Can't remove stale lock {l:?}: {e:?}"))?;
+                removed += 1;
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// Meta values live in their own folder next to the items, see [Storage::put_meta].
+    fn meta_path(&self, key: &str) -> PathBuf {
+        self.base_path.join("oml-storage-meta").join(key)
+    }
+
+    /// Lockfiles without item files, older than `max_age`, see [Storage::remove_orphaned_locks].
+    async fn remove_orphaned_lockfiles(&self, max_age: Duration) -> Result<usize> {
+        self.ensure_writable()?;
+        let _sem = self.lock_semaphore.acquire().await?;
+
+        let now = self.clock.now();
+        let mut removed = 0;
+        for id in self.names_in_layout(self.layout, Path::new("lock")).await? {
+            let id = ITEM::ID::from_string(&id)?;
+            if fs::metadata(self.file_path(&id)).await.is_ok() {
+                continue;
+            }
+            let l = self.lock_path(&id);
+            let Ok(lock_json) = fs::read(&l).await else {
+                // unlocked since listing
+                continue;
+            };
+            let is_orphan = match serde_json::from_slice::<StorageLock>(&lock_json) {
+                Ok(lock) => is_orphan(&lock, max_age, now),
+                // probably from a crash while writing the lock
+                Err(_) => true,
+            };
+            if is_orphan {
+                tracing::info!("Removing orphaned lockfile {l:?}");
+                fs::remove_file(&l)
+                    .await
+                    .map_err(|e| eyre!("Can't remove orphaned lock {l:?}: {e:?}"))?;
+                removed += 1;
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// Moves all items and their lockfiles and blobs from the `from` layout into the current layout.
+    /// Returns the number of moved items.
+    ///
+    /// Stops between items once `cancel` is cancelled, running it again continues the migration.
+    pub async fn migrate_layout(
+        &self,
+        from: StorageDiskLayout,
+        progress: Option<&dyn ProgressSink>,
+        cancel: Option<&CancellationToken>,
+    ) -> Result<usize> {
+        self.migrate((from, self.file_names), progress, cancel)
+            .await
+    }
+
+    /// Renames all items and their lockfiles and blobs from the `from` file names to the current ones,
+    /// e.g. after switching to [StorageDiskFileNames::CaseSafe]. Returns the number of moved items.
+    ///
+    /// Stops between items once `cancel` is cancelled, running it again continues the migration.
+    pub async fn migrate_file_names(
+        &self,
+        from: StorageDiskFileNames,
+        progress: Option<&dyn ProgressSink>,
+        cancel: Option<&CancellationToken>,
+    ) -> Result<usize> {
+        self.migrate((self.layout, from), progress, cancel).await
+    }
+
+    async fn migrate(
+        &self,
+        from: (StorageDiskLayout, StorageDiskFileNames),
+        progress: Option<&dyn ProgressSink>,
+        cancel: Option<&CancellationToken>,
+    ) -> Result<usize> {
+        self.ensure_writable()?;
+        if from == self.naming() {
+            return Ok(0);
+        }
+
+        let _sem = self.lock_semaphore.acquire().await?;
+
+        let ids = self.ids_in_layout(from.0).await?;
+        tracing::info!(
+            "Migrating {} items from {from:?} to {:?}",
+            ids.len(),
+            self.naming()
+        );
+        for alias in self.names_in_layout(from.0, Path::new("alias")).await? {
+            let alias = ITEM::ID::from_string(&alias)?;
+            let (old, new) = (
+                self.path_in_layout(from, &alias, Path::new("alias")),
+                self.alias_path(&alias),
+            );
+            self.ensure_item_folder_exists(&new).await?;
+            fs::rename(&old, &new)
+                .await
+                .map_err(|e| eyre!("Can't move {old:?} to {new:?}: {e:?}"))?;
+        }
+        let mut tracker =
+            ProgressTracker::new(progress, Some(ids.len() as u64)).with_cancellation(cancel);
+        for id in ids.iter() {
+            tracker.ensure_not_cancelled()?;
+            let moves = [
+                (
+                    self.path_in_layout(from, id, &self.extension),
+                    self.file_path(id),
+                ),
+                (
+                    self.path_in_layout(from, id, Path::new("lock")),
+                    self.lock_path(id),
+                ),
+                (
+                    self.path_in_layout(from, id, Path::new("blobs")),
+                    self.blob_folder(id),
+                ),
+                (
+                    self.path_in_layout(from, id, Path::new("links")),
+                    self.links_path(id),
+                ),
+            ];
+            for (old, new) in moves {
+                if fs::metadata(&old).await.is_ok() {
+                    self.ensure_item_folder_exists(&new).await?;
+                    fs::rename(&old, &new)
+                        .await
+                        .map_err(|e| eyre!("Can't move {old:?} to {new:?}: {e:?}"))?;
+                }
+            }
+            for n in 1.. {
+                let (old, new) = (
+                    self.version_path_in_layout(from, id, n),
+                    self.version_path(id, n),
+                );
+                if fs::metadata(&old).await.is_err() {
+                    break;
+                }
+                fs::rename(&old, &new)
+                    .await
+                    .map_err(|e| eyre!("Can't move {old:?} to {new:?}: {e:?}"))?;
+            }
+            if from.1 != self.file_names {
+                self.rename_blobs(id).await?;
+            }
+            tracker.advance(id);
+        }
+        tracker.finish();
+
+        Ok(ids.len())
+    }
+
+    /// Re-encodes the blob names of a moved item with the current file names.
+    async fn rename_blobs(&self, id: &ITEM::ID) -> Result<()> {
+        let folder = self.blob_folder(id);
+        let mut entries = match fs::read_dir(&folder).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(eyre!("Can't read blobs in {folder:?} -> {e}")),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let Some(name) = entry.file_name().to_str().and_then(decode_file_name) else {
+                continue;
+            };
+            let new = folder.join(encode_file_name(&name, self.file_names));
+            if new != entry.path() {
+                fs::rename(entry.path(), &new)
+                    .await
+                    .map_err(|e| eyre!("Can't move {:?} to {new:?}: {e:?}", entry.path()))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Copies all files but locks into a new folder in `dir`, named after the current time,
+    /// e.g. `20261017T093000.123Z`, and keeps only the newest `keep` backups in `dir`.
+    ///
+    /// Files are hard linked where possible, which is safe since files are only ever replaced, never changed.
+    /// The folder is renamed into place once complete, so a backup is either whole or missing.
+    ///
+    /// Note: Every file is copied as last written, items saved during the backup may or may not be included.
+    pub async fn backup_to(&self, dir: &Path, keep: usize) -> Result<StorageDiskBackup> {
+        fs::create_dir_all(dir)
+            .await
+            .map_err(|e| eyre!("Could not create folder {dir:?} -> {e}"))?;
+        if fs::canonicalize(dir)
+            .await?
+            .starts_with(fs::canonicalize(&self.base_path).await?)
+        {
+            return Err(eyre!("Can't backup {:?} into itself", &self.base_path));
+        }
+
+        let created_at = self.clock.now();
+        let name = created_at.format(BACKUP_NAME_FORMAT).to_string();
+        let path = dir.join(&name);
+        if fs::metadata(&path).await.is_ok() {
+            return Err(eyre!("Backup {path:?} already exists"));
+        }
+        let temp_path = dir.join(format!(".{name}.tmp"));
+
+        let mut backup = StorageDiskBackup {
+            created_at,
+            layout: self.layout,
+            file_names: self.file_names,
+            extension: self.extension.clone(),
+            items: 0,
+            files: Vec::new(),
+        };
+        let item_extension = self.extension.as_os_str();
+        let mut folders = vec![PathBuf::new()];
+        while let Some(folder) = folders.pop() {
+            fs::create_dir_all(temp_path.join(&folder)).await?;
+            let mut entries = fs::read_dir(self.base_path.join(&folder)).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let file = folder.join(entry.file_name());
+                let Ok(file_type) = entry.file_type().await else {
+                    continue;
+                };
+                if file_type.is_dir() {
+                    folders.push(file);
+                    continue;
+                }
+                let name = entry.file_name().to_string_lossy().to_string();
+                // temporary files, item locks, and counter locks
+                if name.starts_with('.') || name.ends_with(".lock") || name.ends_with("-lock") {
+                    continue;
+                }
+                let target = temp_path.join(&file);
+                if fs::hard_link(entry.path(), &target).await.is_err() {
+                    match fs::copy(entry.path(), &target).await {
+                        Ok(_) => {}
+                        // removed since listing the folder
+                        Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                        Err(e) => return Err(eyre!("Can't backup {file:?} -> {e}")),
+                    }
+                }
+                if file.extension() == Some(item_extension) {
+                    backup.items += 1;
+                }
+                backup.files.push(file);
+            }
+        }
+        backup.files.sort();
+
+        let manifest = serde_json::to_vec_pretty(&backup)?;
+        write_atomic(
+            &temp_path.join(BACKUP_MANIFEST),
+            &manifest,
+            self.write_options(),
+        )
+        .await?;
+        fs::rename(&temp_path, &path)
+            .await
+            .map_err(|e| eyre!("Can't move {temp_path:?} to {path:?}: {e:?}"))?;
+        sync_folder(&path, self.durability).await?;
+        tracing::info!("Backed up {} items to {path:?}", backup.items);
+
+        let backups = Self::backups_in(dir).await?;
+        for old in backups
+            .iter()
+            .take(backups.len().saturating_sub(keep.max(1)))
+        {
+            tracing::info!("Removing old backup {old:?}");
+            fs::remove_dir_all(old).await?;
+        }
+
+        Ok(backup)
+    }
+
+    /// The backups in `dir`, oldest first.
+    pub async fn backups_in(dir: &Path) -> Result<Vec<PathBuf>> {
+        let mut backups = Vec::new();
+        let mut entries = fs::read_dir(dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let is_dir = entry.file_type().await.is_ok_and(|t| t.is_dir());
+            if is_dir && chrono::NaiveDateTime::parse_from_str(&name, BACKUP_NAME_FORMAT).is_ok() {
+                backups.push(entry.path());
+            }
+        }
+        backups.sort();
+
+        Ok(backups)
+    }
+
+    /// Copies all files of the backup at `path` into the storage, which must not contain any items yet.
+    /// Backups taken with another layout or other file names are migrated.
+    pub async fn restore_from(&self, path: &Path) -> Result<StorageDiskBackup> {
+        self.ensure_writable()?;
+        let manifest = fs::read(path.join(BACKUP_MANIFEST))
+            .await
+            .map_err(|e| eyre!("Can't read backup manifest in {path:?} -> {e}"))?;
+        let backup: StorageDiskBackup = serde_json::from_slice(&manifest)?;
+        if backup.extension != self.extension {
+            return Err(eyre!(
+                "Backup has extension {:?}, not {:?}",
+                backup.extension,
+                self.extension
+            ));
+        }
+        for layout in [self.layout, backup.layout] {
+            if !self.id_names_in_layout(layout).await?.is_empty() {
+                return Err(eyre!(
+                    "Can't restore into {:?}, it already has items",
+                    &self.base_path
+                ));
+            }
+        }
+
+        for file in backup.files.iter() {
+            // never trust a manifest to stay inside the base path
+            if !file
+                .components()
+                .all(|c| matches!(c, std::path::Component::Normal(_)))
+            {
+                return Err(eyre!("Invalid file {file:?} in backup {path:?}"));
+            }
+            let data = fs::read(path.join(file))
+                .await
+                .map_err(|e| eyre!("Can't read {file:?} from backup {path:?} -> {e}"))?;
+            let target = self.base_path.join(file);
+            if let Some(folder) = target.parent() {
+                fs::create_dir_all(folder).await?;
+            }
+            write_atomic(&target, &data, self.write_options()).await?;
+        }
+        self.migrate((backup.layout, backup.file_names), None, None)
+            .await?;
+        tracing::info!("Restored {} items from {path:?}", backup.items);
+
+        Ok(backup)
+    }
+
+    fn path_in_layout(
+        &self,
+        (layout, names): (StorageDiskLayout, StorageDiskFileNames),
+        id: &ITEM::ID,
+        extension: &Path,
+    ) -> PathBuf {
+        let id = format!("{id}");
+        let mut p = layout.item_folder(&self.base_path, &id);
+        let name = encode_file_name(&id, names);
+        let idp = Path::new(&name);
+        p.push(idp);
+        p.set_extension(extension);
+
+        p
+    }
+    fn naming(&self) -> (StorageDiskLayout, StorageDiskFileNames) {
+        (self.layout, self.file_names)
+    }
+    fn file_path(&self, id: &ITEM::ID) -> PathBuf {
+        self.path_in_layout(self.naming(), id, &self.extension)
+    }
+    fn lock_path(&self, id: &ITEM::ID) -> PathBuf {
+        self.path_in_layout(self.naming(), id, Path::new("lock"))
+    }
+    /// Previous versions of the item file get a numbered extension, e.g. `abcd1234.item.2`.
+    fn version_path(&self, id: &ITEM::ID, n: usize) -> PathBuf {
+        self.version_path_in_layout(self.naming(), id, n)
+    }
+    fn version_path_in_layout(
+        &self,
+        layout: (StorageDiskLayout, StorageDiskFileNames),
+        id: &ITEM::ID,
+        n: usize,
+    ) -> PathBuf {
+        let extension = format!("{}.{n}", self.extension.to_string_lossy());
+        self.path_in_layout(layout, id, Path::new(&extension))
+    }
+    /// Blobs are stored in a sibling folder of the item file, e.g. `abcd1234.blobs/image`.
+    fn blob_folder(&self, id: &ITEM::ID) -> PathBuf {
+        self.path_in_layout(self.naming(), id, Path::new("blobs"))
+    }
+    /// Alias files contain the canonical id, e.g. `legacy-42.alias`.
+    fn alias_path(&self, id: &ITEM::ID) -> PathBuf {
+        self.path_in_layout(self.naming(), id, Path::new("alias"))
+    }
+    /// Link files map relations to the linked ids, e.g. `guild-1.links`.
+    fn links_path(&self, id: &ITEM::ID) -> PathBuf {
+        self.path_in_layout(self.naming(), id, Path::new("links"))
+    }
+
+    async fn read_links(&self, id: &ITEM::ID) -> Result<BTreeMap<String, BTreeSet<String>>> {
+        let p = self.links_path(id);
+        match fs::read(&p).await {
+            Ok(json) => {
+                serde_json::from_slice(&json).map_err(|e| eyre!("Can't parse links {p:?} -> {e}"))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::default()),
+            Err(e) => Err(eyre!("Can't load links from {p:?} -> {e}")),
+        }
+    }
+
+    /// Removes the link file once the last link is gone.
+    async fn write_links(
+        &self,
+        id: &ITEM::ID,
+        links: &BTreeMap<String, BTreeSet<String>>,
+    ) -> Result<()> {
+        let p = self.links_path(id);
+        if links.is_empty() {
+            return fs::remove_file(&p)
+                .await
+                .map_err(|e| eyre!("Can't remove links {p:?} -> {e}"));
+        }
+        let json = serde_json::to_vec_pretty(links)?;
+        self.ensure_item_folder_exists(&p).await?;
+        write_atomic(&p, &json, self.write_options())
+            .await
+            .map_err(|e| eyre!("Can't save links to {p:?}: {e:?}"))
+    }
+
+    async fn ensure_item_folder_exists(&self, path: &Path) -> Result<()> {
+        if self.layout == StorageDiskLayout::Flat {
+            return Ok(());
+        }
+        if let Some(folder) = path.parent() {
+            fs::create_dir_all(folder)
+                .await
+                .map_err(|e| eyre!("Could not create folder {folder:?} -> {e}"))?;
+        }
+
+        Ok(())
+    }
+
+    async fn item_files(&self) -> Result<Vec<ItemFile>> {
+        let extension = format!(".{}", self.extension.to_string_lossy());
+        let mut files = Vec::default();
+        for folder in self.item_folders(self.layout).await? {
+            let mut entries = fs::read_dir(&folder).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let f = entry.file_name();
+                let f = f.to_string_lossy();
+                let Some(id) = f.strip_suffix(&extension).and_then(decode_file_name) else {
+                    continue;
+                };
+                let Ok(metadata) = entry.metadata().await else {
+                    continue; // removed in the meantime
+                };
+                if metadata.is_file() {
+                    files.push(ItemFile {
+                        id,
+                        path: entry.path(),
+                        len: metadata.len(),
+                        modified: metadata.modified()?,
+                    });
+                }
+            }
+        }
+
+        Ok(files)
+    }
+
+    /// Makes room for writing `len` bytes to `path`, or fails with [StorageError::QuotaExceeded].
+    async fn enforce_quota(&self, quota: &StorageDiskQuota, path: &Path, len: u64) -> Result<()> {
+        let mut files = self.item_files().await?;
+        let mut bytes: u64 = files.iter().map(|f| f.len).sum();
+        let mut items = files.len();
+        self.record_totals(items as u64, Some(bytes));
+        match files.iter().find(|f| f.path == path) {
+            Some(existing) => bytes = bytes - existing.len + len,
+            None => {
+                bytes += len;
+                items += 1;
+            }
+        }
+        if !quota.is_exceeded(bytes, items) {
+            return Ok(());
+        }
+        if quota.on_exceeded == StorageDiskQuotaAction::Reject {
+            return Err(quota.exceeded_error(bytes, items).into());
+        }
+
+        // keep our own lock from racing with the eviction
+        let _sem = self.lock_semaphore.acquire().await?;
+        files.sort_by_key(|f| f.modified);
+        for file in files {
+            if !quota.is_exceeded(bytes, items) {
+                break;
+            }
+            if file.path == path {
+                continue;
+            }
+            let id = ITEM::ID::from_string(&file.id)?;
+            if fs::metadata(self.lock_path(&id)).await.is_ok() {
+                continue;
+            }
+            tracing::info!("Evicting {id} to stay within quota");
+            fs::remove_file(&file.path)
+                .await
+                .map_err(|e| eyre!("Can't evict {:?}: {e:?}", &file.path))?;
+            self.record_remove(file.len);
+            bytes -= file.len;
+            items -= 1;
+        }
+        if quota.is_exceeded(bytes, items) {
+            return Err(quota.exceeded_error(bytes, items).into());
+        }
+
+        Ok(())
+    }
+
+    /// All folders that can contain item files in the given layout.
+    async fn item_folders(&self, layout: StorageDiskLayout) -> Result<Vec<PathBuf>> {
+        let mut folders = vec![self.base_path.clone()];
+        for _ in 0..layout.depth() {
+            let mut sub_folders = Vec::default();
+            for folder in folders {
+                let mut entries = fs::read_dir(&folder).await?;
+                while let Some(entry) = entries.next_entry().await? {
+                    let is_dir = entry.file_type().await.is_ok_and(|t| t.is_dir());
+                    let name = entry.file_name();
+                    if is_dir && StorageDiskLayout::is_shard_name(&name.to_string_lossy()) {
+                        sub_folders.push(entry.path());
+                    }
+                }
+            }
+            folders = sub_folders;
+        }
+
+        Ok(folders)
+    }
+
+    /// The raw IDs of all item files in the given layout, unsorted.
+    async fn id_names_in_layout(&self, layout: StorageDiskLayout) -> Result<Vec<String>> {
+        self.names_in_layout(layout, &self.extension).await
+    }
+
+    /// The decoded IDs of all files with the given extension in the given layout, sorted.
+    ///
+    /// The folders are read in parallel, in chunks, on the blocking thread pool.
+    async fn names_in_layout(
+        &self,
+        layout: StorageDiskLayout,
+        extension: &Path,
+    ) -> Result<Vec<String>> {
+        let extension = Arc::new(format!(".{}", extension.to_string_lossy()));
+        let folders = self.item_folders(layout).await?;
+        let threads = std::thread::available_parallelism().map_or(4, |n| n.get());
+        let chunk_size = folders.len().div_ceil(threads).max(1);
+        let tasks: Vec<_> = folders
+            .chunks(chunk_size)
+            .map(|folders| {
+                let folders = folders.to_vec();
+                let extension = Arc::clone(&extension);
+                tokio::task::spawn_blocking(move || names_in_folders(&folders, &extension))
+            })
+            .collect();
+        let mut names = Vec::default();
+        for task in tasks {
+            let chunk = task
+                .await
+                .map_err(|e| eyre!("Reading folders failed -> {e}"))??;
+            names.extend(chunk);
+        }
+        names.sort_unstable();
+
+        Ok(names)
+    }
+
+    async fn ids_in_layout(&self, layout: StorageDiskLayout) -> Result<Vec<ITEM::ID>> {
+        self.id_names_in_layout(layout)
+            .await?
+            .iter()
+            .map(|id| ITEM::ID::from_string(id))
+            .collect()
+    }
+}
+
+#[cfg(feature = "metadata")]
+impl<ITEM: StorageItem> StorageDisk<ITEM> {
+    fn update_highest_seen_id(&self, id: &ITEM::ID) {
+        self.metadata.update_highest_seen_id(id);
+    }
+
+    fn record_lock_acquired(&self, id: &ITEM::ID, lock: &StorageLock) {
+        self.metadata.record_lock_acquired(id, lock);
+    }
+
+    fn record_lock_contended(&self) {
+        self.metadata.record_lock_contended();
+        operation_metrics::record_lock_contended("disk");
+    }
+
+    fn record_lock_released(&self, id: &ITEM::ID, lock: &StorageLock) {
+        self.metadata
+            .record_lock_released(id, lock, self.clock.now());
+    }
+
+    fn record_save(&self, previous_len: Option<u64>, len: u64) {
+        self.metadata.record_save(previous_len, len);
+    }
+
+    fn record_remove(&self, len: u64) {
+        self.metadata.record_remove(len);
+    }
+
+    fn record_totals(&self, item_count: u64, total_bytes: Option<u64>) {
+        self.metadata.record_totals(item_count, total_bytes);
+    }
+
+    /// The metadata file lives next to the items and never matches the item extension.
+    fn metadata_path(&self) -> PathBuf {
+        self.base_path.join(".oml-storage-metadata")
+    }
+
+    async fn load_metadata(&self) -> Result<()> {
+        let p = self.metadata_path();
+        match fs::read(&p).await {
+            Ok(data) => self.metadata.restore(&data),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(eyre!("Could not read metadata {p:?} -> {e}")),
+        }
+    }
+
+    fn start_metadata_flush(&self) {
+        if self.read_only {
+            return;
+        }
+        let path = self.metadata_path();
+        let write = self.write_options();
+        self.metadata.spawn_flush(move |data| {
+            let path = path.clone();
+            async move {
+                write_atomic(&path, &data, write)
+                    .await
+                    .map_err(|e| eyre!("Could not write metadata {path:?} -> {e}"))
+            }
+        });
+    }
+
+    /// Writes changed metadata now, instead of waiting for the periodic flush,
+    /// e.g. before shutting down.
+    pub async fn flush_metadata(&self) -> Result<()> {
+        self.ensure_writable()?;
+        if let Some(data) = self.metadata.take_dirty()? {
+            let p = self.metadata_path();
+            write_atomic(&p, &data, self.write_options())
+                .await
+                .map_err(|e| eyre!("Could not write metadata {p:?} -> {e}"))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "metadata"))]
+impl<ITEM: StorageItem> StorageDisk<ITEM> {
+    fn update_highest_seen_id(&self, _id: &ITEM::ID) {}
+
+    fn record_lock_acquired(&self, _id: &ITEM::ID, _lock: &StorageLock) {}
+
+    fn record_lock_contended(&self) {
+        operation_metrics::record_lock_contended("disk");
+    }
+
+    fn record_lock_released(&self, _id: &ITEM::ID, _lock: &StorageLock) {}
+
+    fn record_save(&self, _previous_len: Option<u64>, _len: u64) {}
+
+    fn record_remove(&self, _len: u64) {}
+
+    fn record_totals(&self, _item_count: u64, _total_bytes: Option<u64>) {}
+
+    async fn load_metadata(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn start_metadata_flush(&self) {}
+
+    pub async fn flush_metadata(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<ITEM: StorageItem + std::marker::Send> StorageDisk<ITEM> {
+    /// The serialized item, decompressed.
+    async fn read_data(&self, id: &ITEM::ID) -> Result<Vec<u8>> {
+        ensure_valid_id::<ITEM>(id)?;
+        let id = &self.resolve_alias(id).await?;
+        let p = self.file_path(id);
+        let b = fs::read(p.clone()).await.map_err(|e| {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                StorageError::NotFound { id: id.to_string() }.into()
+            } else {
+                eyre!("Can't load from {p:?} -> {e}")
+            }
+        })?;
+        let b = decompress_item_data(b)?;
+        self.update_highest_seen_id(id);
+
+        Ok(b)
+    }
+
+    /// Loads the item as it was `n` saves ago, `1` being the version before the current one.
+    /// Fails with [StorageError::NotFound] if that version wasn't kept.
+    pub async fn load_previous(&self, id: &ITEM::ID, n: usize) -> Result<ITEM> {
+        ensure_valid_id::<ITEM>(id)?;
+        if n == 0 {
+            return Err(eyre!("Versions start at 1, use load for the current one"));
+        }
+        let id = &self.resolve_alias(id).await?;
+        let p = self.version_path(id, n);
+        let b = fs::read(&p).await.map_err(|e| {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                StorageError::NotFound { id: id.to_string() }.into()
+            } else {
+                eyre!("Can't load from {p:?} -> {e}")
+            }
+        })?;
+
+        deserialize_stored::<ITEM>(id, &decompress_item_data(b)?)
+    }
+
+    /// Opens the item file for reading, only compressed files are read into memory.
+    async fn open_data(&self, id: &ITEM::ID) -> Result<PayloadReader> {
+        ensure_valid_id::<ITEM>(id)?;
+        let id = &self.resolve_alias(id).await?;
+        let p = self.file_path(id);
+        let mut file = fs::File::open(&p).await.map_err(|e| {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                StorageError::NotFound { id: id.to_string() }.into()
+            } else {
+                eyre!("Can't load from {p:?} -> {e}")
+            }
+        })?;
+        let mut head = Vec::with_capacity(ZSTD_MAGIC.len());
+        (&mut file)
+            .take(ZSTD_MAGIC.len() as u64)
+            .read_to_end(&mut head)
+            .await?;
+        self.update_highest_seen_id(id);
+        if head == ZSTD_MAGIC {
+            let mut data = head;
+            file.read_to_end(&mut data).await?;
+            let data = decompress_item_data(data)?;
+            return Ok(Box::new(std::io::Cursor::new(data)));
+        }
+
+        Ok(Box::new(std::io::Cursor::new(head).chain(file)))
+    }
+
+    /// Shifts the kept versions up by one and keeps the current item file as version 1.
+    /// Versions beyond the configured number are removed, e.g. after lowering it.
+    async fn keep_version(&self, id: &ITEM::ID) -> Result<()> {
+        if self.versions == 0 {
+            return Ok(());
+        }
+        let mut n = self.versions;
+        while fs::remove_file(self.version_path(id, n)).await.is_ok() {
+            n += 1;
+        }
+        for old in (1..self.versions).rev() {
+            let (from, to) = (self.version_path(id, old), self.version_path(id, old + 1));
+            if fs::metadata(&from).await.is_ok() {
+                fs::rename(&from, &to)
+                    .await
+                    .map_err(|e| eyre!("Can't move {from:?} to {to:?}: {e:?}"))?;
+            }
+        }
+        // the current file is replaced by a rename, so a link keeps its content
+        let (current, first) = (self.file_path(id), self.version_path(id, 1));
+        if fs::hard_link(&current, &first).await.is_err() {
+            fs::copy(&current, &first)
+                .await
+                .map_err(|e| eyre!("Can't keep {current:?} as {first:?}: {e:?}"))?;
+        }
+
+        Ok(())
+    }
+
+    /// Why a save with `lock` was rejected, from the current lock file and the item file's modification time.
+    async fn lock_mismatch(&self, id: &ITEM::ID, lock: &StorageLock) -> color_eyre::Report {
+        let current: Option<StorageLock> = match fs::read(self.lock_path(id)).await {
+            Ok(lock_json) => serde_json::from_slice(&lock_json).ok(),
+            Err(_) => None,
+        };
+        let modified = fs::metadata(self.file_path(id))
+            .await
+            .and_then(|m| m.modified())
+            .ok()
+            .map(DateTime::<Utc>::from);
+        StorageError::lock_mismatch(
+            &id.to_string(),
+            lock,
+            current.as_ref(),
+            modified,
+            self.clock.now(),
+        )
+        .into()
+    }
+
+    /// Writes the item file at `p`, or, if immutable, creates it, failing if it exists.
+    async fn write_item_file<R: AsyncRead + Unpin + Send + ?Sized>(
+        &self,
+        id: &ITEM::ID,
+        p: &Path,
+        reader: &mut R,
+    ) -> Result<u64> {
+        let written = if self.immutable {
+            create_exclusive_from(p, reader, self.write_options()).await
+        } else {
+            write_atomic_from(p, reader, self.write_options()).await
+        };
+        match written {
+            Ok(len) => Ok(len),
+            Err(e) if self.immutable && e.kind() == std::io::ErrorKind::AlreadyExists => {
+                Err(StorageError::Immutable { id: id.to_string() }.into())
+            }
+            Err(e) => Err(eyre!("Can't save to {p:?}: {e:?}")),
+        }
+    }
+
+    /// Like [StorageDisk::write_data], streaming `reader` into the item file.
+    /// Compression and quotas need the full data, so it is read into memory for them.
+    async fn write_stream(
+        &self,
+        id: &ITEM::ID,
+        reader: &mut (dyn AsyncRead + Send + Unpin),
+        lock: &StorageLock,
+    ) -> Result<()> {
+        if self.compression != StorageDiskCompression::None || self.quota.is_some() {
+            let mut data = Vec::default();
+            reader.read_to_end(&mut data).await?;
+            return self.write_data(id, data, lock).await;
+        }
+        ensure_valid_id::<ITEM>(id)?;
+        self.ensure_writable()?;
+        let id = &self.resolve_alias(id).await?;
+        if !self.verify_lock(id, lock).await? {
+            return Err(self.lock_mismatch(id, lock).await);
+        }
+        let p = self.file_path(id);
+        self.ensure_item_folder_exists(&p).await?;
+        let previous_len = fs::metadata(&p).await.ok().map(|m| m.len());
+        if self.immutable && previous_len.is_some() {
+            return Err(StorageError::Immutable { id: id.to_string() }.into());
+        }
+        if previous_len.is_some() {
+            self.keep_version(id).await?;
+        }
+        let len = self.write_item_file(id, &p, reader).await?;
+        self.update_highest_seen_id(id);
+        self.record_save(previous_len, len);
+
+        Ok(())
+    }
+
+    async fn write_data(&self, id: &ITEM::ID, data: Vec<u8>, lock: &StorageLock) -> Result<()> {
+        ensure_valid_id::<ITEM>(id)?;
+        self.ensure_writable()?;
+        let id = &self.resolve_alias(id).await?;
+        if !self.verify_lock(id, lock).await? {
+            Err(self.lock_mismatch(id, lock).await)
+        } else {
+            let p = self.file_path(id);
+            let b = self.compression.compress(data)?;
+            if let Some(quota) = &self.quota {
+                self.enforce_quota(quota, &p, b.len() as u64).await?;
+            }
+            self.ensure_item_folder_exists(&p).await?;
+            let previous_len = fs::metadata(&p).await.ok().map(|m| m.len());
+            if self.immutable && previous_len.is_some() {
+                return Err(StorageError::Immutable { id: id.to_string() }.into());
+            }
+            if previous_len.is_some() {
+                self.keep_version(id).await?;
+            }
+            self.write_item_file(id, &p, &mut b.as_slice()).await?;
+            self.update_highest_seen_id(id);
+            self.record_save(previous_len, b.len() as u64);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "wipe")]
+impl<ITEM: StorageItem + std::marker::Send> StorageDisk<ITEM> {
+    /// Removes the lock, data, blobs, and links of the item, errors are ignored.
+    /// Returns the size of the removed data file.
+    async fn remove_item_files(&self, id: &ITEM::ID) -> Option<u64> {
+        let l = self.lock_path(id);
+        if fs::metadata(&l).await.is_ok() {
+            let _ = fs::remove_file(l.clone())
+                .await
+                .map_err(|e| eyre!("Can't remove {l:?}: {e:?}"));
+        }
+        let f = self.file_path(id);
+        let len = fs::metadata(&f).await.ok().map(|m| m.len());
+        if len.is_some() {
+            let _ = fs::remove_file(f.clone())
+                .await
+                .map_err(|e| eyre!("Can't remove {f:?}: {e:?}"));
+        }
+        for n in 1.. {
+            let v = self.version_path(id, n);
+            if fs::remove_file(&v).await.is_err() {
+                break;
+            }
+        }
+        let b = self.blob_folder(id);
+        if fs::metadata(&b).await.is_ok() {
+            let _ = fs::remove_dir_all(b.clone())
+                .await
+                .map_err(|e| eyre!("Can't remove {b:?}: {e:?}"));
+        }
+        let l = self.links_path(id);
+        if fs::metadata(&l).await.is_ok() {
+            let _ = fs::remove_file(l.clone())
+                .await
+                .map_err(|e| eyre!("Can't remove {l:?}: {e:?}"));
+        }
+
+        len
+    }
+
+    /// Removes the item files, unless the item is locked and `options` don't force it.
+    async fn delete_item_files(
+        &self,
+        id: &ITEM::ID,
+        options: &crate::DeleteOptions,
+    ) -> crate::DeleteOutcome {
+        let lock = match fs::read(self.lock_path(id)).await {
+            // an unreadable lock still locks
+            Ok(lock_json) => Some(
+                serde_json::from_slice::<StorageLock>(&lock_json)
+                    .unwrap_or_else(|_| StorageLock::new_at("", self.clock.now())),
+            ),
+            Err(_) => None,
+        };
+        if let Some(outcome) = options.blocked_by(lock.as_ref()) {
+            return outcome;
+        }
+        match self.remove_item_files(id).await {
+            Some(len) => {
+                self.record_remove(len);
+                crate::DeleteOutcome::Deleted
+            }
+            None => crate::DeleteOutcome::NotFound,
+        }
+    }
+}
+
+#[async_trait]
+impl<ITEM: StorageItem + std::marker::Send> Storage<ITEM> for StorageDisk<ITEM> {
+    #[tracing::instrument(
+        name = "storage.ensure_storage_exists",
+        skip_all,
+        fields(backend = "disk", db.system = "disk", db.operation = "ensure_storage_exists")
+    )]
+    async fn ensure_storage_exists(&mut self) -> Result<()> {
+        let _slow_op = SlowOp::start(
+            self.slow_op_threshold,
+            "disk",
+            "ensure_storage_exists",
+            None,
+        );
+        self.ensure_folder_exists().await?;
+        if !self.read_only {
+            let changed = self.enforce_permissions().await?;
+            if changed > 0 {
+                tracing::info!("Changed permissions of {changed} files");
+            }
+            if self.file_names == StorageDiskFileNames::Plain
+                && is_case_insensitive(&self.base_path).await?
+            {
+                tracing::warn!(
+                    "{:?} is case-insensitive, ids only differing in case share a file, use StorageDiskFileNames::CaseSafe",
+                    &self.base_path
+                );
+            }
+        }
+        if let Some(stale_locks) = &self.stale_locks_on_start {
+            let removed = self.cleanup_stale_locks(stale_locks).await?;
+            if removed > 0 {
+                tracing::warn!("Removed {removed} stale locks");
+            }
+        }
+        self.load_metadata().await?;
+        if let Some(id) = &self.probe_id {
+            let hint = format!("the permissions of {:?}", &self.base_path);
+            if self.read_only {
+                if !self.exists(id).await? {
+                    return Err(eyre!("Storage probe of {id} failed to read, check {hint}"));
+                }
+            } else {
+                probe_storage(&*self, id, &hint).await?;
+            }
+        }
+        self.start_metadata_flush();
+
+        Ok(())
+    }
+
+    fn capabilities(&self) -> crate::Capabilities {
+        crate::Capabilities {
+            scan: true,
+            list: true,
+            blobs: true,
+            meta: true,
+            aliases: true,
+            links: true,
+            remove_orphaned_locks: true,
+            streaming: true,
+            consistent_reads: true,
+            ..Default::default()
+        }
+    }
+    #[tracing::instrument(
+        name = "storage.create",
+        skip_all,
+        fields(backend = "disk", db.system = "disk", db.operation = "create")
+    )]
+    async fn create(&self) -> Result<ITEM::ID> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk", "create", None);
+        if let Some(id_allocator) = &self.id_allocator {
+            let id = id_allocator.next().await?;
+            return ITEM::ID::from_string(&id.to_string());
+        }
+        // collisions are passed on, for id types that derive the next id from them
+        let mut previous = None;
+        for _ in 0..self.create_tries {
+            let id = self.id_generator.generate(previous.as_ref());
+            if !self.exists(&id).await? {
+                return Ok(id);
+            }
+            previous = Some(id);
+        }
+
+        Err(StorageError::IdExhausted {
+            tries: self.create_tries,
+        }
+        .into())
+    }
+    #[tracing::instrument(
+        name = "storage.exists",
+        skip_all,
+        fields(backend = "disk", db.system = "disk", db.operation = "exists", id = %id)
+    )]
+    async fn exists(&self, id: &ITEM::ID) -> Result<bool> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk", "exists", Some(id));
+        ensure_valid_id::<ITEM>(id)?;
+        let id = &self.resolve_alias(id).await?;
+        //let p = self.file_path(id.into());
+        //let p = self.file_path(&format!("{id}"));
+        let p = self.file_path(id);
+        tracing::debug!("{p:?}");
+
+        if fs::metadata(p).await.is_ok() {
+            self.update_highest_seen_id(id);
+            Ok(true)
+        } else {
+            // the lockfile already exists, but the data file doesn't
+            // might happen when somebody crashed during creation
+            // or is in the middle of creation
+            let p = self.lock_path(id);
+            let exists = match self.exists_policy {
+                ExistsPolicy::IncludeLocked => fs::metadata(p).await.is_ok(),
+                policy => match fs::read(&p).await {
+                    Ok(lock_json) => {
+                        let lock = serde_json::from_slice::<StorageLock>(&lock_json).ok();
+                        policy.lock_only_exists(lock.as_ref(), self.clock.now())
+                    }
+                    Err(_) => false,
+                },
+            };
+            if exists {
+                self.update_highest_seen_id(id);
+            }
+            Ok(exists)
+        }
+    }
+
+    #[tracing::instrument(
+        name = "storage.load",
+        skip_all,
+        fields(backend = "disk", db.system = "disk", db.operation = "load", id = %id)
+    )]
+    async fn load(&self, id: &ITEM::ID) -> Result<ITEM> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk", "load", Some(id));
+        let b = self.read_data(id).await?;
+        deserialize_stored::<ITEM>(id, &b)
+    }
+
+    #[tracing::instrument(
+        name = "storage.load_raw",
+        skip_all,
+        fields(backend = "disk", db.system = "disk", db.operation = "load_raw", id = %id)
+    )]
+    async fn load_raw(&self, id: &ITEM::ID) -> Result<Vec<u8>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk", "load_raw", Some(id));
+        self.read_data(id).await
+    }
+
+    #[tracing::instrument(
+        name = "storage.save",
+        skip_all,
+        fields(backend = "disk", db.system = "disk", db.operation = "save", id = %id)
+    )]
+    async fn save(&self, id: &ITEM::ID, item: &ITEM, lock: &StorageLock) -> Result<()> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk", "save", Some(id));
+        self.write_data(id, serialize_valid(id, item)?, lock).await
+    }
+
+    #[tracing::instrument(
+        name = "storage.save_raw",
+        skip_all,
+        fields(backend = "disk", db.system = "disk", db.operation = "save_raw", id = %id)
+    )]
+    async fn save_raw(&self, id: &ITEM::ID, data: &[u8], lock: &StorageLock) -> Result<()> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk", "save_raw", Some(id));
+        self.write_data(id, data.to_vec(), lock).await
+    }
+
+    #[tracing::instrument(
+        name = "storage.load_stream",
+        skip_all,
+        fields(backend = "disk", db.system = "disk", db.operation = "load_stream", id = %id)
+    )]
+    async fn load_stream(&self, id: &ITEM::ID) -> Result<PayloadReader> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk", "load_stream", Some(id));
+        self.open_data(id).await
+    }
+
+    #[tracing::instrument(
+        name = "storage.save_stream",
+        skip_all,
+        fields(backend = "disk", db.system = "disk", db.operation = "save_stream", id = %id)
+    )]
+    async fn save_stream(
+        &self,
+        id: &ITEM::ID,
+        reader: &mut (dyn AsyncRead + Send + Unpin),
+        lock: &StorageLock,
+    ) -> Result<()> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk", "save_stream", Some(id));
+        self.write_stream(id, reader, lock).await
+    }
+    #[tracing::instrument(
+        name = "storage.lock",
+        skip_all,
+        fields(backend = "disk", db.system = "disk", db.operation = "lock", id = %id, who = %who)
+    )]
+    async fn lock(&self, id: &ITEM::ID, who: &str) -> Result<LockResult<ITEM>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk", "lock", Some(id));
+        ensure_valid_id::<ITEM>(id)?;
+        self.ensure_writable()?;
+        let id = &self.resolve_alias(id).await?;
+        let l = self.lock_path(id);
+        let (lock, item) = {
+            let sem = self.lock_semaphore.acquire().await?;
+            tracing::debug!("Lock[{who}]: Got Semaphore");
+
+            let lock = StorageLock::new_at(who, self.clock.now());
+            let lock_json = serde_json::to_string_pretty(&lock)?;
+
+            tracing::debug!("Lock[{who}]: Create lock {l:?}");
+            self.ensure_item_folder_exists(&l).await?;
+            // the semaphore only protects against ourselves,
+            // the exclusive create protects against other processes sharing the folder
+            match create_exclusive(&l, lock_json.as_bytes(), self.write_options()).await {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    tracing::warn!("Lockfile {l:?} already exists");
+                    drop(sem);
+                    tracing::debug!("Lock[{who}]: Dropped Semaphore"); // close enough
+                    self.update_highest_seen_id(id);
+                    self.record_lock_contended();
+                    // the lock might be removed, or still be written, by its holder
+                    let existing = match fs::read(&l).await {
+                        Ok(lock_json) => serde_json::from_slice(&lock_json).ok(),
+                        Err(_) => None,
+                    };
+                    let existing = existing.unwrap_or_else(|| {
+                        tracing::warn!("{id} is locked, but the lock can't be read");
+                        StorageLock::new_at("", self.clock.now())
+                    });
+                    return Ok(LockResult::already_locked(&existing));
+                }
+                Err(e) => return Err(eyre!("Can't lock {l:?} for {who}: {e:?}")),
+            }
+
+            tracing::debug!("Lock[{who}]: Load {id}");
+            let item = match self.load(id).await {
+                Ok(item) => item,
+                Err(e) if matches!(e.downcast_ref(), Some(StorageError::NotFound { .. })) => {
+                    ITEM::default()
+                }
+                Err(e) => {
+                    // corrupt items aren't kept locked, saving a default over them would lose the data
+                    if let Err(remove_e) = fs::remove_file(&l).await {
+                        tracing::warn!("Can't remove lock {l:?} after failed load -> {remove_e:?}");
+                    }
+                    return Err(e);
+                }
+            };
+
+            drop(sem);
+            tracing::debug!("Lock[{who}]: Dropped Semaphore"); // close enough
+            (lock, item)
+        };
+        self.update_highest_seen_id(id);
+        self.record_lock_acquired(id, &lock);
+        Ok(LockResult::Success { lock, item })
+    }
+
+    #[tracing::instrument(
+        name = "storage.unlock",
+        skip_all,
+        fields(backend = "disk", db.system = "disk", db.operation = "unlock", id = %id)
+    )]
+    async fn unlock(&self, id: &ITEM::ID, lock: StorageLock) -> Result<()> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk", "unlock", Some(id));
+        self.ensure_writable()?;
+        let id = &self.resolve_alias(id).await?;
+        if !self.verify_lock(id, &lock).await? {
+            Err(eyre!("Lock invalid!"))
+        } else {
+            let l = self.lock_path(id);
+            fs::remove_file(l.clone())
+                .await
+                .map_err(|e| eyre!("Can't unlock {l:?}: {e:?}"))?;
+            self.record_lock_released(id, &lock);
+            Ok(())
+        }
+    }
+
+    /// The lock file is replaced atomically, so it never disappears, and no one else can create it.
+    #[tracing::instrument(
+        name = "storage.handoff_lock",
+        skip_all,
+        fields(
+            backend = "disk",
+            db.system = "disk",
+            db.operation = "handoff_lock",
+            id = %id,
+            who = %new_who,
+        )
+    )]
+    async fn handoff_lock(
+        &self,
+        id: &ITEM::ID,
+        lock: StorageLock,
+        new_who: &str,
+    ) -> Result<StorageLock> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk", "handoff_lock", Some(id));
+        self.ensure_writable()?;
+        let id = &self.resolve_alias(id).await?;
+        let sem = self.lock_semaphore.acquire().await?;
+        if !self.verify_lock(id, &lock).await? {
+            return Err(eyre!("Lock invalid!"));
+        }
+        let new_lock = StorageLock::new_at(new_who, self.clock.now());
+        let lock_json = serde_json::to_string_pretty(&new_lock)?;
+        let l = self.lock_path(id);
+        write_atomic(&l, lock_json.as_bytes(), self.write_options())
+            .await
+            .map_err(|e| eyre!("Can't hand off {l:?} to {new_who}: {e:?}"))?;
+        drop(sem);
+        self.record_lock_released(id, &lock);
+        self.record_lock_acquired(id, &new_lock);
+
+        Ok(new_lock)
+    }
+
+    #[tracing::instrument(
+        name = "storage.put_blob",
+        skip_all,
+        fields(
+            backend = "disk",
+            db.system = "disk",
+            db.operation = "put_blob",
+            id = %id,
+            name = %name,
+        )
+    )]
+    async fn put_blob(
+        &self,
+        id: &ITEM::ID,
+        name: &str,
+        data: &[u8],
+        lock: &StorageLock,
+    ) -> Result<()> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk", "put_blob", Some(id));
+        ensure_valid_id::<ITEM>(id)?;
+        self.ensure_writable()?;
+        let id = &self.resolve_alias(id).await?;
+        if !self.verify_lock(id, lock).await? {
+            return Err(eyre!("Lock invalid!"));
+        }
+        let folder = self.blob_folder(id);
+        fs::create_dir_all(&folder)
+            .await
+            .map_err(|e| eyre!("Could not create folder {folder:?} -> {e}"))?;
+        let p = folder.join(encode_file_name(name, self.file_names));
+        write_atomic(&p, data, self.write_options())
+            .await
+            .map_err(|e| eyre!("Can't save blob to {p:?}: {e:?}"))?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(
+        name = "storage.get_blob",
+        skip_all,
+        fields(
+            backend = "disk",
+            db.system = "disk",
+            db.operation = "get_blob",
+            id = %id,
+            name = %name,
+        )
+    )]
+    async fn get_blob(&self, id: &ITEM::ID, name: &str) -> Result<Option<Vec<u8>>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk", "get_blob", Some(id));
+        ensure_valid_id::<ITEM>(id)?;
+        let id = &self.resolve_alias(id).await?;
+        let p = self
+            .blob_folder(id)
+            .join(encode_file_name(name, self.file_names));
+        match fs::read(&p).await {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(eyre!("Can't load blob from {p:?} -> {e}")),
+        }
+    }
+
+    #[tracing::instrument(
+        name = "storage.list_blobs",
+        skip_all,
+        fields(backend = "disk", db.system = "disk", db.operation = "list_blobs", id = %id)
+    )]
+    async fn list_blobs(&self, id: &ITEM::ID) -> Result<Vec<String>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk", "list_blobs", Some(id));
+        ensure_valid_id::<ITEM>(id)?;
+        let id = &self.resolve_alias(id).await?;
+        let folder = self.blob_folder(id);
+        let mut entries = match fs::read_dir(&folder).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::default()),
+            Err(e) => return Err(eyre!("Can't list blobs in {folder:?} -> {e}")),
+        };
+        let mut names = Vec::default();
+        while let Some(entry) = entries.next_entry().await? {
+            let f = entry.file_name();
+            let f = f.to_string_lossy();
+            // skip temporary files
+            if f.starts_with('.') {
+                continue;
+            }
+            if let Some(name) = decode_file_name(&f) {
+                names.push(name);
+            }
+        }
+        names.sort();
+
+        Ok(names)
+    }
+
+    /// Adds the save time and the kept versions, see [StorageDisk::set_versions].
+    async fn export_item(&self, id: &ITEM::ID) -> Result<crate::ItemArchive> {
+        let mut archive = crate::item_archive::export_item(self, id).await?;
+        let id = &self.resolve_alias(id).await?;
+        archive.modified = fs::metadata(self.file_path(id))
+            .await
+            .and_then(|m| m.modified())
+            .ok()
+            .map(DateTime::<Utc>::from);
+        // versions beyond the configured number may still be around, e.g. after lowering it
+        let mut n = 1;
+        while let Ok(data) = fs::read(self.version_path(id, n)).await {
+            archive
+                .versions
+                .push(crate::ArchivedData::new(decompress_item_data(data)?));
+            n += 1;
+        }
+
+        Ok(archive)
+    }
+
+    /// Writes changed metadata, see [StorageDisk::flush_metadata], read only storages have nothing to write.
+    async fn shutdown(&self) -> Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        self.flush_metadata().await
+    }
+
+    #[tracing::instrument(
+        name = "storage.add_alias",
+        skip_all,
+        fields(
+            backend = "disk",
+            db.system = "disk",
+            db.operation = "add_alias",
+            alias = %alias,
+            canonical = %canonical,
+        )
+    )]
+    async fn add_alias(&self, alias: &ITEM::ID, canonical: &ITEM::ID) -> Result<()> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk", "add_alias", Some(alias));
+        self.ensure_writable()?;
+        if !self.aliases {
+            return Err(eyre!(
+                "Aliases are not enabled, see StorageDisk::set_aliases_enabled"
+            ));
+        }
+        ensure_valid_id::<ITEM>(alias)?;
+        ensure_valid_id::<ITEM>(canonical)?;
+        // never chain aliases
+        let canonical = self.resolve_alias(canonical).await?;
+        if alias.to_string() == canonical.to_string() {
+            return Err(eyre!("Can't alias {alias} to itself"));
+        }
+        if fs::metadata(self.file_path(alias)).await.is_ok()
+            || fs::metadata(self.lock_path(alias)).await.is_ok()
+        {
+            return Err(eyre!("Can't alias {alias}, an item with that id exists"));
+        }
+        if !self.exists(&canonical).await? {
+            return Err(eyre!("Can't alias {alias} to missing item {canonical}"));
+        }
+
+        let p = self.alias_path(alias);
+        self.ensure_item_folder_exists(&p).await?;
+        match create_exclusive(&p, canonical.to_string().as_bytes(), self.write_options()).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                Err(eyre!("Alias {alias} already exists"))
+            }
+            Err(e) => Err(eyre!("Could not create alias {p:?} -> {e}")),
+        }
+    }
+
+    #[tracing::instrument(
+        name = "storage.remove_alias",
+        skip_all,
+        fields(backend = "disk", db.system = "disk", db.operation = "remove_alias", alias = %alias)
+    )]
+    async fn remove_alias(&self, alias: &ITEM::ID) -> Result<()> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk", "remove_alias", Some(alias));
+        self.ensure_writable()?;
+        let p = self.alias_path(alias);
+        match fs::remove_file(&p).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(eyre!("{alias} is not an alias"))
+            }
+            Err(e) => Err(eyre!("Could not remove alias {p:?} -> {e}")),
+        }
+    }
+
+    #[tracing::instrument(
+        name = "storage.resolve_alias",
+        skip_all,
+        fields(backend = "disk", db.system = "disk", db.operation = "resolve_alias", id = %id)
+    )]
+    async fn resolve_alias(&self, id: &ITEM::ID) -> Result<ITEM::ID> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk", "resolve_alias", Some(id));
+        if !self.aliases {
+            return Ok(id.clone());
+        }
+        let p = self.alias_path(id);
+        match fs::read_to_string(&p).await {
+            Ok(canonical) => ITEM::ID::from_string(&canonical),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(id.clone()),
+            Err(e) => Err(eyre!("Could not read alias {p:?} -> {e}")),
+        }
+    }
+
+    #[tracing::instrument(
+        name = "storage.add_link",
+        skip_all,
+        fields(
+            backend = "disk",
+            db.system = "disk",
+            db.operation = "add_link",
+            id = %from,
+            relation = %relation,
+            to = %to,
+        )
+    )]
+    async fn add_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk", "add_link", Some(from));
+        ensure_valid_id::<ITEM>(from)?;
+        ensure_valid_id::<ITEM>(to)?;
+        ensure_valid_relation(relation)?;
+        self.ensure_writable()?;
+        let from = &self.resolve_alias(from).await?;
+        let to = &self.resolve_alias(to).await?;
+        if !self.verify_lock(from, lock).await? {
+            return Err(eyre!("Lock invalid!"));
+        }
+        if !self.exists(to).await? {
+            return Err(eyre!("Can't link {from} to missing item {to}"));
+        }
+        let mut links = self.read_links(from).await?;
+        if links
+            .entry(relation.to_string())
+            .or_default()
+            .insert(to.to_string())
+        {
+            self.write_links(from, &links).await?;
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(
+        name = "storage.remove_link",
+        skip_all,
+        fields(
+            backend = "disk",
+            db.system = "disk",
+            db.operation = "remove_link",
+            id = %from,
+            relation = %relation,
+            to = %to,
+        )
+    )]
+    async fn remove_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk", "remove_link", Some(from));
+        ensure_valid_id::<ITEM>(from)?;
+        ensure_valid_relation(relation)?;
+        self.ensure_writable()?;
+        let from = &self.resolve_alias(from).await?;
+        let to = &self.resolve_alias(to).await?;
+        if !self.verify_lock(from, lock).await? {
+            return Err(eyre!("Lock invalid!"));
+        }
+        let mut links = self.read_links(from).await?;
+        let removed = match links.get_mut(relation) {
+            Some(ids) => {
+                let removed = ids.remove(&to.to_string());
+                if ids.is_empty() {
+                    links.remove(relation);
+                }
+                removed
+            }
+            None => false,
+        };
+        if !removed {
+            return Err(eyre!("{from} doesn't link to {to} via {relation}"));
+        }
+
+        self.write_links(from, &links).await
+    }
+
+    #[tracing::instrument(
+        name = "storage.links_of",
+        skip_all,
+        fields(
+            backend = "disk",
+            db.system = "disk",
+            db.operation = "links_of",
+            id = %id,
+            relation = %relation,
+        )
+    )]
+    async fn links_of(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk", "links_of", Some(id));
+        ensure_valid_id::<ITEM>(id)?;
+        ensure_valid_relation(relation)?;
+        let id = &self.resolve_alias(id).await?;
+        let mut links = self.read_links(id).await?;
+        links
+            .remove(relation)
+            .unwrap_or_default()
+            .iter()
+            .map(|to| ITEM::ID::from_string(to))
+            .collect()
+    }
+
+    #[tracing::instrument(
+        name = "storage.linked_from",
+        skip_all,
+        fields(
+            backend = "disk",
+            db.system = "disk",
+            db.operation = "linked_from",
+            id = %id,
+            relation = %relation,
+        )
+    )]
+    async fn linked_from(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk", "linked_from", Some(id));
+        ensure_valid_id::<ITEM>(id)?;
+        ensure_valid_relation(relation)?;
+        let id = self.resolve_alias(id).await?.to_string();
+        let mut from = Vec::default();
+        for name in self
+            .names_in_layout(self.layout, Path::new("links"))
+            .await?
+        {
+            let other = ITEM::ID::from_string(&name)?;
+            let links = self.read_links(&other).await?;
+            if links.get(relation).is_some_and(|ids| ids.contains(&id)) {
+                from.push(name);
+            }
+        }
+        from.sort();
+
+        from.iter().map(|f| ITEM::ID::from_string(f)).collect()
+    }
+
+    #[tracing::instrument(
+        name = "storage.remove_orphaned_locks",
+        skip_all,
+        fields(backend = "disk", db.system = "disk", db.operation = "remove_orphaned_locks")
+    )]
+    async fn remove_orphaned_locks(&self, max_age: Duration) -> Result<usize> {
+        let _slow_op = SlowOp::start(
+            self.slow_op_threshold,
+            "disk",
+            "remove_orphaned_locks",
+            None,
+        );
+        self.remove_orphaned_lockfiles(max_age).await
+    }
+
+    #[tracing::instrument(
+        name = "storage.put_meta",
+        skip_all,
+        fields(backend = "disk", db.system = "disk", db.operation = "put_meta", key = %key)
+    )]
+    async fn put_meta(&self, key: &str, value: &[u8]) -> Result<()> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk", "put_meta", None);
+        ensure_valid_meta_key(key)?;
+        self.ensure_writable()?;
+        let p = self.meta_path(key);
+        if let Some(folder) = p.parent() {
+            fs::create_dir_all(folder)
+                .await
+                .map_err(|e| eyre!("Could not create folder {folder:?} -> {e}"))?;
+        }
+        write_atomic(&p, value, self.write_options())
+            .await
+            .map_err(|e| eyre!("Can't save meta value to {p:?}: {e:?}"))
+    }
+
+    #[tracing::instrument(
+        name = "storage.get_meta",
+        skip_all,
+        fields(backend = "disk", db.system = "disk", db.operation = "get_meta", key = %key)
+    )]
+    async fn get_meta(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk", "get_meta", None);
+        ensure_valid_meta_key(key)?;
+        let p = self.meta_path(key);
+        match fs::read(&p).await {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(eyre!("Can't load meta value from {p:?} -> {e}")),
+        }
+    }
+
+    #[tracing::instrument(
+        name = "storage.force_unlock",
+        skip_all,
+        fields(backend = "disk", db.system = "disk", db.operation = "force_unlock", id = %id)
+    )]
+    async fn force_unlock(&self, id: &ITEM::ID) -> Result<Option<StorageLock>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk", "force_unlock", Some(id));
+        self.ensure_writable()?;
+        let id = &self.resolve_alias(id).await?;
+        let l = self.lock_path(id);
+        let lock_json = match fs::read(&l).await {
+            Ok(lock_json) => lock_json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                tracing::warn!("Lockfile {l:?} doesn't exists");
+                return Ok(None);
+            }
+            Err(e) => return Err(eyre!("Can't read lock {l:?}: {e:?}")),
+        };
+        let lock = serde_json::from_slice(&lock_json).unwrap_or_else(|e| {
+            tracing::warn!("Can't parse lock {l:?}, removing it anyway -> {e:?}");
+            StorageLock::new_at("", self.clock.now())
+        });
+
+        fs::remove_file(l.clone())
+            .await
+            .map_err(|e| eyre!("Can't force unlock {l:?}: {e:?}"))?;
+        Ok(Some(lock))
+    }
+    #[tracing::instrument(
+        name = "storage.force_unlock_if_held",
+        skip_all,
+        fields(
+            backend = "disk",
+            db.system = "disk",
+            db.operation = "force_unlock_if_held",
+            id = %id,
+            who = %who,
+        )
+    )]
+    async fn force_unlock_if_held(
+        &self,
+        id: &ITEM::ID,
+        who: &str,
+        when: DateTime<Utc>,
+    ) -> Result<bool> {
+        let _slow_op = SlowOp::start(
+            self.slow_op_threshold,
+            "disk",
+            "force_unlock_if_held",
+            Some(id),
+        );
+        self.ensure_writable()?;
+        let id = &self.resolve_alias(id).await?;
+        let _sem = self.lock_semaphore.acquire().await?;
+        let l = self.lock_path(id);
+        let lock_json = match fs::read(&l).await {
+            Ok(lock_json) => lock_json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(eyre!("Can't read lock {l:?}: {e:?}")),
+        };
+        let lock: StorageLock = serde_json::from_slice(&lock_json)?;
+        if !lock.is_held_by(who, when) {
+            return Ok(false);
+        }
+        fs::remove_file(l.clone())
+            .await
+            .map_err(|e| eyre!("Can't force unlock {l:?}: {e:?}"))?;
+        Ok(true)
+    }
+    #[tracing::instrument(
+        name = "storage.verify_lock",
+        skip_all,
+        fields(backend = "disk", db.system = "disk", db.operation = "verify_lock", id = %id)
+    )]
+    async fn verify_lock(&self, id: &ITEM::ID, lock: &StorageLock) -> Result<bool> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk", "verify_lock", Some(id));
+        let id = &self.resolve_alias(id).await?;
+        let l = self.lock_path(id);
+        if fs::metadata(&l).await.is_err() {
+            tracing::warn!("Lockfile {l:?} doesn't exists");
+            return Ok(false);
+        }
+
+        let expected_lock_json = fs::read(&l).await?;
+        let expected_lock: StorageLock = serde_json::from_slice(&expected_lock_json)?;
+
+        // compares tokens, or who and when for locks without one
+        if expected_lock != *lock {
+            tracing::warn!("Lock mismatch for {id} {lock:?} != {expected_lock:?}");
+            return Ok(false);
+        }
+        Ok(true)
+    }
+    #[tracing::instrument(
+        name = "storage.all_ids",
+        skip_all,
+        fields(backend = "disk", db.system = "disk", db.operation = "all_ids")
+    )]
+    async fn all_ids(&self) -> Result<Vec<ITEM::ID>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk", "all_ids", None);
+        //tracing::debug!("all_ids");
+        let ids = self.ids_in_layout(self.layout).await?;
+        let mut highest_id = ITEM::ID::default();
+        for id in ids.iter() {
+            if id.id_cmp(&highest_id) == Ordering::Greater {
+                highest_id = id.to_owned(); // :TODO: decide if we want to keep this
+            } else {
+                tracing::debug!("{id} < {highest_id}");
+            }
+        }
+        self.update_highest_seen_id(&highest_id);
+        self.record_totals(ids.len() as u64, None);
+        Ok(ids)
+    }
+    /// Scans ids in sorted order.
+    /// The scan position is the last returned id, so items added or removed during a scan
+    /// never cause ids to be skipped or returned twice.
+    #[tracing::instrument(
+        name = "storage.scan_ids",
+        skip_all,
+        fields(backend = "disk", db.system = "disk", db.operation = "scan_ids")
+    )]
+    async fn scan_ids(&self, start: Option<&str>, limit: Option<usize>) -> Result<Page<ITEM::ID>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk", "scan_ids", None);
+        self.scan_ids_with_prefix("", start, limit).await
+    }
+    #[tracing::instrument(
+        name = "storage.scan_ids_with_prefix",
+        skip_all,
+        fields(
+            backend = "disk",
+            db.system = "disk",
+            db.operation = "scan_ids_with_prefix",
+            prefix = %prefix,
+        )
+    )]
+    async fn scan_ids_with_prefix(
+        &self,
+        prefix: &str,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk", "scan_ids_with_prefix", None);
+        self.scan_ids_filtered(&crate::IdFilter::Prefix(prefix.to_string()), start, limit)
+            .await
+    }
+
+    #[tracing::instrument(
+        name = "storage.scan_ids_filtered",
+        skip_all,
+        fields(backend = "disk", db.system = "disk", db.operation = "scan_ids_filtered")
+    )]
+    async fn scan_ids_filtered(
+        &self,
+        filter: &crate::IdFilter,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        let mut names = self.id_names_in_layout(self.layout).await?;
+        // the directory listing is complete, only the save times are unknown
+        let mut total = None;
+        if !matches!(filter, crate::IdFilter::ModifiedBefore(_)) {
+            names.retain(|name| filter.matches(name, None));
+            total = Some(names.len() as u64);
+        }
+        names.retain(|name| start.is_none_or(|s| name.as_str() > s));
+        if let crate::IdFilter::ModifiedBefore(_) = filter {
+            // only stat the remaining files
+            let mut matching = Vec::with_capacity(names.len());
+            for name in names {
+                let modified = fs::metadata(self.file_path(&ITEM::ID::from_string(&name)?))
+                    .await
+                    .and_then(|m| m.modified())
+                    .ok()
+                    .map(chrono::DateTime::<Utc>::from);
+                if filter.matches(&name, modified) {
+                    matching.push(name);
+                }
+            }
+            names = matching;
+        }
+
+        let more = limit.is_some_and(|limit| names.len() > limit);
+        if let Some(limit) = limit.filter(|_| more) {
+            // already sorted by names_in_layout
+            names.truncate(limit);
+        }
+
+        let scan_pos = if more {
+            names.last().cloned().or(start.map(str::to_string))
+        } else {
+            None
+        };
+
+        let ids = names
+            .iter()
+            .map(|id| ITEM::ID::from_string(id))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut page = Page::new(ids, scan_pos);
+        page.approximate_total = total;
+
+        Ok(page)
+    }
+    #[tracing::instrument(
+        name = "storage.scan_ids_between",
+        skip_all,
+        fields(backend = "disk", db.system = "disk", db.operation = "scan_ids_between")
+    )]
+    async fn scan_ids_between(
+        &self,
+        from: &ITEM::ID,
+        to: &ITEM::ID,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk", "scan_ids_between", None);
+        let ids = self.ids_in_layout(self.layout).await?;
+
+        crate::page::page_between(ids, from, to, start, limit)
+    }
+
+    /// Sizes are of the item files, after compression.
+    #[tracing::instrument(
+        name = "storage.list",
+        skip_all,
+        fields(backend = "disk", db.system = "disk", db.operation = "list")
+    )]
+    async fn list(
+        &self,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ItemSummary<ITEM::ID>>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk", "list", None);
+        let page = self.scan_ids(start, limit).await?;
+        let mut summaries = Vec::with_capacity(page.items.len());
+        for id in page.items {
+            let mut summary = ItemSummary::new(id);
+            if let Ok(metadata) = fs::metadata(self.file_path(&summary.id)).await {
+                summary.size = Some(metadata.len());
+                summary.modified = metadata.modified().ok().map(chrono::DateTime::<Utc>::from);
+            }
+            let lock = match fs::read(self.lock_path(&summary.id)).await {
+                Ok(lock_json) => serde_json::from_slice::<StorageLock>(&lock_json).ok(),
+                Err(_) => None,
+            };
+            summaries.push(summary.with_lock(lock.as_ref()));
+        }
+
+        Ok(Page {
+            items: summaries,
+            cursor: page.cursor,
+            approximate_total: page.approximate_total,
+        })
+    }
+
+    /// The size of the item file, after compression.
+    #[tracing::instrument(
+        name = "storage.item_size",
+        skip_all,
+        fields(backend = "disk", db.system = "disk", db.operation = "item_size", id = %id)
+    )]
+    async fn item_size(&self, id: &ITEM::ID) -> Result<u64> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk", "item_size", Some(id));
+        let id = &self.resolve_alias(id).await?;
+        match fs::metadata(self.file_path(id)).await {
+            Ok(metadata) => Ok(metadata.len()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(StorageError::NotFound { id: id.to_string() }.into())
+            }
+            Err(e) => Err(eyre!("Can't read the size of {id} -> {e:?}")),
+        }
+    }
+
+    #[tracing::instrument(
+        name = "storage.display_lock",
+        skip_all,
+        fields(backend = "disk", db.system = "disk", db.operation = "display_lock", id = %id)
+    )]
+    async fn display_lock(&self, id: &ITEM::ID) -> Result<String> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk", "display_lock", Some(id));
+        let id = &self.resolve_alias(id).await?;
+        let l = self.lock_path(id);
+        if fs::metadata(&l).await.is_err() {
+            return Ok(String::default());
+        } else {
+            let lock_json = fs::read(&l).await?;
+            let lock: StorageLock = serde_json::from_slice(&lock_json)?;
+            let lock_string = format!("Locked by {} at {:?}", lock.who(), lock.when());
+            //            let lock_string = format!("{:?}", lock);
+
+            Ok(lock_string)
+        }
+    }
+    #[cfg(feature = "metadata")]
+    async fn metadata_highest_seen_id(&self) -> Option<ITEM::ID> {
+        self.metadata.highest_seen_id()
+    }
+    #[cfg(feature = "metadata")]
+    async fn metadata_item_count(&self) -> Option<u64> {
+        self.metadata.item_count()
+    }
+    #[cfg(feature = "metadata")]
+    async fn metadata_total_bytes(&self) -> Option<u64> {
+        self.metadata.total_bytes()
+    }
+    #[cfg(feature = "metadata")]
+    async fn metadata_last_write(&self) -> Option<DateTime<Utc>> {
+        self.metadata.last_write()
+    }
+    #[cfg(feature = "metadata")]
+    async fn metadata_lock_stats(&self) -> crate::LockStats {
+        self.metadata.lock_stats()
+    }
+
+    #[cfg(feature = "wipe")]
+    #[tracing::instrument(
+        name = "storage.wipe",
+        skip_all,
+        fields(backend = "disk", db.system = "disk", db.operation = "wipe")
+    )]
+    async fn wipe(
+        &self,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk", "wipe", None);
+        let mut report = crate::WipeReport::new(options);
+        if options.is_dry_run() {
+            let ids = self.all_ids().await?;
+            let mut tracker = crate::ProgressTracker::new(progress, Some(ids.len() as u64))
+                .with_cancellation(cancel);
+            for id in ids {
+                tracker.ensure_not_cancelled()?;
+                report.record(&id);
+                tracker.advance(&id);
+            }
+            tracker.finish();
+            return Ok(report);
+        }
+        self.ensure_writable()?;
+        options.ensure_confirmed()?;
+
+        let _sem = self.lock_semaphore.acquire().await?;
+
+        // we know all_ids doesn't use the semaphore
+        let ids = self.all_ids().await?;
+
+        tracing::warn!("Wiping {} items.", ids.len());
+        let mut tracker =
+            crate::ProgressTracker::new(progress, Some(ids.len() as u64)).with_cancellation(cancel);
+        for id in ids {
+            tracker.ensure_not_cancelled()?;
+            report.record(&id);
+            if let Some(len) = self.remove_item_files(&id).await {
+                self.record_remove(len);
+            }
+            tracker.advance(&id);
+        }
+        tracker.finish();
+        for alias in self
+            .names_in_layout(self.layout, Path::new("alias"))
+            .await?
+        {
+            let a = self.alias_path(&ITEM::ID::from_string(&alias)?);
+            let _ = fs::remove_file(a.clone())
+                .await
+                .map_err(|e| eyre!("Can't remove {a:?}: {e:?}"));
+        }
+        self.record_totals(0, Some(0));
+        Ok(report)
+    }
+
+    #[cfg(feature = "wipe")]
+    #[tracing::instrument(
+        name = "storage.wipe_matching",
+        skip_all,
+        fields(backend = "disk", db.system = "disk", db.operation = "wipe_matching")
+    )]
+    async fn wipe_matching(
+        &self,
+        filter: &crate::IdFilter,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk", "wipe_matching", None);
+        if !options.is_dry_run() {
+            self.ensure_writable()?;
+        }
+        options.ensure_confirmed()?;
+
+        let _sem = self.lock_semaphore.acquire().await?;
+
+        let mut report = crate::WipeReport::new(options);
+        let mut tracker = crate::ProgressTracker::new(progress, None).with_cancellation(cancel);
+        // we know all_ids doesn't use the semaphore
+        for id in self.all_ids().await? {
+            tracker.ensure_not_cancelled()?;
+            let modified = match filter {
+                crate::IdFilter::ModifiedBefore(_) => fs::metadata(self.file_path(&id))
+                    .await
+                    .and_then(|m| m.modified())
+                    .ok()
+                    .map(chrono::DateTime::<Utc>::from),
+                _ => None,
+            };
+            if !filter.matches(&id.to_string(), modified) {
+                continue;
+            }
+            report.record(&id);
+            if !options.is_dry_run() {
+                if let Some(len) = self.remove_item_files(&id).await {
+                    self.record_remove(len);
+                }
+            }
+            tracker.advance(&id);
+        }
+        tracker.finish();
+        if !report.dry_run {
+            tracing::warn!("Wiped {} matching items.", report.count);
+        }
+
+        Ok(report)
+    }
+
+    #[cfg(feature = "wipe")]
+    #[tracing::instrument(
+        name = "storage.delete_many",
+        skip_all,
+        fields(backend = "disk", db.system = "disk", db.operation = "delete_many")
+    )]
+    async fn delete_many(
+        &self,
+        ids: &[ITEM::ID],
+        options: &crate::DeleteOptions,
+    ) -> Result<Vec<crate::DeleteOutcome>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk", "delete_many", None);
+        self.ensure_writable()?;
+        for id in ids {
+            ensure_valid_id::<ITEM>(id)?;
+        }
+
+        // no new locks while checking, and removing
+        let _sem = self.lock_semaphore.acquire().await?;
+
+        let mut outcomes = Vec::with_capacity(ids.len());
+        for chunk in ids.chunks(DELETE_CONCURRENCY) {
+            outcomes
+                .extend(join_all(chunk.iter().map(|id| self.delete_item_files(id, options))).await);
+        }
+
+        Ok(outcomes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Clock;
+    use crate::ExistsPolicy;
+    use crate::LockResult;
+    use crate::MockClock;
+    use crate::Page;
+    use crate::Storage;
+    use crate::StorageDisk;
+    use crate::StorageDiskDurability;
+    use crate::StorageDiskFileNames;
+    use crate::StorageDiskLayout;
+    use crate::StorageDiskQuota;
+    use crate::StorageDiskQuotaAction;
+    use crate::StorageDiskStaleLocks;
+    use crate::StorageError;
+    use crate::StorageId;
+    use crate::StorageItem;
+    use crate::StorageLock;
+    use color_eyre::Result;
+    use serde::Deserialize;
+    use serde::Serialize;
+    use std::env;
+    use std::path::Path;
+    use std::time::Duration;
+
+    #[derive(Default, Debug, Serialize, Deserialize)]
+    struct TestItem {}
+
+    impl StorageItem for TestItem {
+        type ID = String;
+
+        fn serialize(&self) -> Result<Vec<u8>> {
+            let json = serde_json::to_string_pretty(&self)?;
+
+            Ok(json.into())
+        }
+        fn deserialize(data: &[u8]) -> Result<Self>
+        where
+            Self: Sized,
+        {
+            let i = serde_json::from_slice(data)?;
+
+            Ok(i)
+        }
+    }
+
+    #[tokio::test]
+    async fn it_debugs() -> Result<()> {
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_items");
+        let extension = Path::new("test_item");
+
+        let storage = StorageDisk::<TestItem>::new(&path, extension).await;
+        println!("{storage:?}");
+
+        let storage: Box<dyn Storage<TestItem>> = Box::new(storage);
+        println!("{storage:?}");
+
+        Ok(())
+    }
+    #[tokio::test]
+    async fn it_gives_all_ids() -> Result<()> {
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_items");
+        let extension = Path::new("test_item.json");
+
+        let storage = StorageDisk::<TestItem>::new(&path, extension).await;
+        //println!("{storage:?}");
+
+        let mut storage: Box<dyn Storage<TestItem>> = Box::new(storage);
+        //println!("{storage:?}");
+        storage.ensure_storage_exists().await?;
+
+        let us = "TEST";
+
+        let mut ids = Vec::default();
+
+        for _ in 0..5 {
+            let item_id = storage.create().await.unwrap();
+            //println!("{item_id:?}");
+
+            let (lock, item) = match storage.lock(&item_id, us).await? {
+                LockResult::Success { lock, item } => (lock, item),
+                LockResult::AlreadyLocked { .. } => {
+                    todo!();
+                }
+            };
+            storage.save(&item_id, &item, &lock).await?;
+            storage.unlock(&item_id, lock).await?;
+
+            ids.push(item_id);
+        }
+        let all_ids = storage.all_ids().await?;
+
+        //println!("{all_ids:#?}");
+
+        assert!(all_ids.len() >= ids.len());
+        assert!(ids.iter().all(|id| all_ids.contains(id)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_displays_locks() -> Result<()> {
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_items");
+        let extension = Path::new("test_item");
+
+        let storage = StorageDisk::<TestItem>::new(&path, extension).await;
+        // println!("{storage:?}");
+
+        let mut storage: Box<dyn Storage<TestItem>> = Box::new(storage);
+        // println!("{storage:?}");
+        storage.ensure_storage_exists().await?;
+
+        let us = "TEST";
+
+        let item_id = storage.create().await.unwrap();
+        //println!("{item_id:?}");
+
+        let (lock, item) = match storage.lock(&item_id, us).await? {
+            LockResult::Success { lock, item } => (lock, item),
+            LockResult::AlreadyLocked { .. } => {
+                todo!();
+            }
+        };
+        storage.save(&item_id, &item, &lock).await?;
+        let l = storage.display_lock(&item_id).await?;
+        println!("{l:?}");
+        match storage.lock(&item_id, "other").await? {
+            LockResult::AlreadyLocked { who, when } => {
+                assert_eq!(us, who);
+                assert_eq!(lock.when(), &when);
+            }
+            LockResult::Success { .. } => panic!("locked twice"),
+        }
+        storage.unlock(&item_id, lock).await?;
+        let l = storage.display_lock(&item_id).await?;
+        println!("{l:?}");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn exists_works_during_creation() -> Result<()> {
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_items");
+        let extension = Path::new("test_item");
+
+        let storage = StorageDisk::<TestItem>::new(&path, extension).await;
+        // println!("{storage:?}");
+
+        let mut storage: Box<dyn Storage<TestItem>> = Box::new(storage);
+        // println!("{storage:?}");
+        storage.ensure_storage_exists().await?;
+
+        let us = "TEST";
+
+        let item_id = nanoid::nanoid!();
+
+        let (lock, _item) = match storage.lock(&item_id, us).await? {
+            LockResult::Success { lock, item } => (lock, item),
+            LockResult::AlreadyLocked { .. } => {
+                todo!();
+            }
+        };
+        let exists_during_creation = storage.exists(&item_id).await?;
+
+        // storage.save(&item_id, &item, &lock).await?;
+        let _l = storage.display_lock(&item_id).await?;
+        // println!("{l:?}");
+        storage.unlock(&item_id, lock).await?;
+        // let l = storage.display_lock(&item_id).await?;
+        // println!("{l:?}");
+
+        assert!(exists_during_creation);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_loads_many() -> Result<()> {
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_items");
+        let extension = Path::new("test_item");
+
+        let storage = StorageDisk::<TestItem>::new(&path, extension).await;
+        let mut storage: Box<dyn Storage<TestItem>> = Box::new(storage);
+        storage.ensure_storage_exists().await?;
+
+        let us = "TEST";
+
+        let item_id = storage.create().await?;
+        let (lock, item) = storage.lock(&item_id, us).await?.success()?;
+        storage.save(&item_id, &item, &lock).await?;
+        storage.unlock(&item_id, lock).await?;
+
+        let missing_id = nanoid::nanoid!();
+        assert!(storage.try_load(&missing_id).await?.is_none());
+        assert!(storage.try_load(&item_id).await?.is_some());
+        let items = storage
+            .load_many(&[item_id.clone(), missing_id, item_id])
+            .await?;
+
+        assert_eq!(3, items.len());
+        assert!(items[0].is_some());
+        assert!(items[1].is_none());
+        assert!(items[2].is_some());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_writes_atomically() -> Result<()> {
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_atomic");
+        std::fs::create_dir_all(&path)?;
+        path.push("item.test_item");
+
+        super::write_atomic(&path, b"first", StorageDiskDurability::None.into()).await?;
+        super::write_atomic(
+            &path,
+            b"second",
+            StorageDiskDurability::SyncFileAndFolder.into(),
+        )
+        .await?;
+        assert_eq!(b"second".to_vec(), std::fs::read(&path)?);
+
+        let leftovers = std::fs::read_dir(path.parent().unwrap())?
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_name().to_string_lossy().ends_with(".tmp"))
+            .count();
+        assert_eq!(0, leftovers);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_migrates_to_sharded_layout() -> Result<()> {
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_items_sharded");
+        let _ = std::fs::remove_dir_all(&path);
+        let extension = Path::new("test_item");
+
+        let mut storage = StorageDisk::<TestItem>::new(&path, extension).await;
+        storage.ensure_storage_exists().await?;
+
+        let us = "TEST";
+
+        let mut ids = Vec::default();
+        for _ in 0..5 {
+            let item_id = storage.create().await?;
+            let (lock, item) = storage.lock(&item_id, us).await?.success()?;
+            storage.save(&item_id, &item, &lock).await?;
+            storage.unlock(&item_id, lock).await?;
+            ids.push(item_id);
+        }
+
+        storage.set_layout(StorageDiskLayout::Sharded)?;
+        assert!(storage.all_ids().await?.is_empty());
+        assert_eq!(
+            5,
+            storage
+                .migrate_layout(StorageDiskLayout::Flat, None, None)
+                .await?
+        );
+
+        // sorted across all shards
+        let all_ids = storage.all_ids().await?;
+        ids.sort();
+        assert_eq!(ids, all_ids);
+        for id in ids.iter() {
+            assert!(storage.exists(id).await?);
+            let folder = storage.file_path(id).parent().unwrap().to_path_buf();
+            assert_ne!(path, folder);
+            assert_eq!(path, folder.parent().unwrap().parent().unwrap());
+        }
+
+        let item_id = storage.create().await?;
+        let (lock, item) = storage.lock(&item_id, us).await?.success()?;
+        storage.save(&item_id, &item, &lock).await?;
+        storage.unlock(&item_id, lock).await?;
+        assert_eq!(6, storage.all_ids().await?.len());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_locks_exclusively_across_instances() -> Result<()> {
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_items");
+        let extension = Path::new("test_item");
+
+        // separate instances don't share a semaphore, just like separate processes
+        let mut storages = Vec::default();
+        for _ in 0..4 {
+            let mut storage = StorageDisk::<TestItem>::new(&path, extension).await;
+            storage.ensure_storage_exists().await?;
+            storages.push(storage);
+        }
+
+        let item_id = nanoid::nanoid!();
+        let results = tokio::join!(
+            storages[0].lock(&item_id, "TEST"),
+            storages[1].lock(&item_id, "TEST"),
+            storages[2].lock(&item_id, "TEST"),
+            storages[3].lock(&item_id, "TEST"),
+        );
+
+        let mut locks = Vec::default();
+        for r in [results.0, results.1, results.2, results.3] {
+            if let LockResult::Success { lock, .. } = r? {
+                locks.push(lock);
+            }
+        }
+        assert_eq!(1, locks.len());
+
+        storages[0].unlock(&item_id, locks.remove(0)).await?;
+        assert!(!storages[0].exists(&item_id).await?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_scans_ids_with_cursor() -> Result<()> {
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_items_scan");
+        let _ = std::fs::remove_dir_all(&path);
+        let extension = Path::new("test_item");
+
+        let mut storage = StorageDisk::<TestItem>::new(&path, extension).await;
+        storage.ensure_storage_exists().await?;
+
+        let us = "TEST";
+
+        for _ in 0..7 {
+            let item_id = storage.create().await?;
+            let (lock, item) = storage.lock(&item_id, us).await?.success()?;
+            storage.save(&item_id, &item, &lock).await?;
+            storage.unlock(&item_id, lock).await?;
+        }
+        let mut all_ids = storage.all_ids().await?;
+        all_ids.sort();
+
+        let mut scanned_ids = Vec::default();
+        let mut scan_pos = None;
+        loop {
+            let Page {
+                items: ids,
+                cursor: next,
+                ..
+            } = storage.scan_ids(scan_pos.as_deref(), Some(3)).await?;
+            assert!(ids.len() <= 3);
+            scanned_ids.extend(ids);
+            match next {
+                Some(next) => scan_pos = Some(next),
+                None => break,
+            }
+        }
+        assert_eq!(all_ids, scanned_ids);
+
+        let Page {
+            items: ids,
+            cursor: next,
+            approximate_total,
+        } = storage.scan_ids(None, None).await?;
+        assert_eq!(all_ids, ids);
+        assert_eq!(None, next);
+        assert_eq!(Some(all_ids.len() as u64), approximate_total);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_cleans_up_stale_locks() -> Result<()> {
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_items_stale_locks");
+        let _ = std::fs::remove_dir_all(&path);
+        let extension = Path::new("test_item");
+
+        let mut storage = StorageDisk::<TestItem>::new(&path, extension).await;
+        storage.ensure_storage_exists().await?;
+
+        let crashed_id = nanoid::nanoid!();
+        let _lock = storage.lock(&crashed_id, "crashed-1").await?.success()?;
+        let alive_id = nanoid::nanoid!();
+        let lock = storage.lock(&alive_id, "alive").await?.success()?;
+
+        let stale_locks = StorageDiskStaleLocks {
+            max_age: Some(Duration::from_secs(60 * 60)),
+            who_prefix: Some(String::from("crashed-")),
+        };
+        storage.set_stale_locks_on_start(Some(stale_locks))?;
+        storage.ensure_storage_exists().await?;
+
+        assert!(!storage.exists(&crashed_id).await?);
+        assert!(storage.verify_lock(&alive_id, &lock.0).await?);
+
+        let stale_locks = StorageDiskStaleLocks {
+            max_age: Some(Duration::ZERO),
+            who_prefix: None,
+        };
+        assert_eq!(1, storage.cleanup_stale_locks(&stale_locks).await?);
+        assert!(!storage.exists(&alive_id).await?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_backs_up_and_restores() -> Result<()> {
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_items_backup");
+        let _ = std::fs::remove_dir_all(&path);
+        let extension = Path::new("test_item");
+        let backups = path.join("backups");
+
+        let clock = MockClock::default();
+        let mut storage = StorageDisk::<TestItem>::new(&path.join("items"), extension).await;
+        storage.set_layout(StorageDiskLayout::Sharded)?;
+        storage.set_clock(clock.clone())?;
+        storage.ensure_storage_exists().await?;
+
+        let saved_id = storage.create().await?;
+        let (lock, item) = storage.lock(&saved_id, "TEST").await?.success()?;
+        storage.save(&saved_id, &item, &lock).await?;
+        storage
+            .put_blob(&saved_id, "avatar", &[1, 2], &lock)
+            .await?;
+        let locked_id = nanoid::nanoid!();
+        let _lock = storage.lock(&locked_id, "TEST").await?.success()?;
+
+        assert!(storage.backup_to(&path.join("items"), 2).await.is_err());
+        for _ in 0..3 {
+            let backup = storage.backup_to(&backups, 2).await?;
+            assert_eq!(1, backup.items);
+            assert_eq!(2, backup.files.len());
+            clock.advance(Duration::from_secs(1));
+        }
+        let kept = StorageDisk::<TestItem>::backups_in(&backups).await?;
+        assert_eq!(2, kept.len());
+
+        let mut restored = StorageDisk::<TestItem>::new(&path.join("restored"), extension).await;
+        restored.ensure_storage_exists().await?;
+        restored.restore_from(&kept[1]).await?;
+        assert_eq!(vec![saved_id.clone()], restored.all_ids().await?);
+        assert_eq!(
+            Some(vec![1, 2]),
+            restored.get_blob(&saved_id, "avatar").await?
+        );
+        // locks are not backed up
+        assert!(!restored.exists(&locked_id).await?);
+        assert!(restored.restore_from(&kept[1]).await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_ages_locks_with_the_clock() -> Result<()> {
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_items_clock");
+        let _ = std::fs::remove_dir_all(&path);
+        let extension = Path::new("test_item");
+
+        let clock = MockClock::default();
+        let mut storage = StorageDisk::<TestItem>::new(&path, extension).await;
+        storage.set_clock(clock.clone())?;
+        storage.ensure_storage_exists().await?;
+
+        let id = nanoid::nanoid!();
+        let (lock, _) = storage.lock(&id, "TEST").await?.success()?;
+        assert_eq!(clock.now(), *lock.when());
+
+        let stale_locks = StorageDiskStaleLocks {
+            max_age: Some(Duration::from_secs(60 * 60)),
+            who_prefix: None,
+        };
+        assert_eq!(0, storage.cleanup_stale_locks(&stale_locks).await?);
+        clock.advance(Duration::from_secs(2 * 60 * 60));
+        assert_eq!(1, storage.cleanup_stale_locks(&stale_locks).await?);
+
+        // unreadable locks are dated by the clock too
+        std::fs::write(storage.lock_path(&id), b"{ not json")?;
+        match storage.lock(&id, "other").await? {
+            LockResult::AlreadyLocked { when, .. } => assert_eq!(clock.now(), when),
+            LockResult::Success { .. } => panic!("locked twice"),
+        }
+        let displaced = storage.force_unlock(&id).await?;
+        assert_eq!(Some(clock.now()), displaced.map(|l| *l.when()));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_removes_orphaned_locks() -> Result<()> {
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_items_orphans");
+        let _ = std::fs::remove_dir_all(&path);
+        let extension = Path::new("test_item");
+
+        let clock = MockClock::default();
+        let mut storage = StorageDisk::<TestItem>::new(&path, extension).await;
+        storage.set_clock(clock.clone())?;
+        storage.set_exists_policy(ExistsPolicy::IgnoreOrphansOlderThan(Duration::from_secs(
+            60,
+        )))?;
+        storage.ensure_storage_exists().await?;
+
+        let orphan_id = nanoid::nanoid!();
+        let _lock = storage.lock(&orphan_id, "crashed").await?.success()?;
+        let saved_id = storage.create().await?;
+        let (lock, item) = storage.lock(&saved_id, "TEST").await?.success()?;
+        storage.save(&saved_id, &item, &lock).await?;
+
+        assert!(storage.exists(&orphan_id).await?);
+        assert_eq!(
+            0,
+            storage
+                .remove_orphaned_locks(Duration::from_secs(60))
+                .await?
+        );
+        clock.advance(Duration::from_secs(120));
+        assert!(!storage.exists(&orphan_id).await?);
+        assert!(storage.exists(&saved_id).await?);
+
+        assert_eq!(
+            1,
+            storage
+                .remove_orphaned_locks(Duration::from_secs(60))
+                .await?
+        );
+        assert!(storage.verify_lock(&saved_id, &lock).await?);
+        assert!(storage.lock(&orphan_id, "TEST").await?.success().is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_encodes_file_names() {
+        for (id, name) in [
+            ("V1StGXR8_Z5jdHi6B-myT", "V1StGXR8_Z5jdHi6B-myT"),
+            ("../evil", "%2E%2E%2Fevil"),
+            ("a/b\\c", "a%2Fb%5Cc"),
+            ("c:d.e", "c%3Ad%2Ee"),
+            ("100%", "100%25"),
+            ("con", "%63on"),
+            ("LPT1", "%4CPT1"),
+            ("com0", "%63om0"),
+            ("", ""),
+            ("ünï", "%C3%BCn%C3%AF"),
+        ] {
+            assert_eq!(
+                name,
+                super::encode_file_name(id, StorageDiskFileNames::Plain)
+            );
+            assert_eq!(Some(id.to_string()), super::decode_file_name(name));
+        }
+        for (id, name) in [
+            ("abc-1_2", "abc-1_2"),
+            ("AbC", "%41b%43"),
+            ("LPT1", "%4C%50%541"),
+            ("con", "%63on"),
+        ] {
+            assert_eq!(
+                name,
+                super::encode_file_name(id, StorageDiskFileNames::CaseSafe)
+            );
+            assert_eq!(Some(id.to_string()), super::decode_file_name(name));
+        }
+        for name in [
+            "broken%2", "a.b", "%61", "%2e%2e", "c%3Ad.e", "con", "%2D", "%C3", "%zz",
+        ] {
+            assert_eq!(None, super::decode_file_name(name), "{name}");
+        }
+    }
+
+    /// Runs on all platforms, case-sensitive file systems only check that the names would be unique.
+    #[tokio::test]
+    async fn it_keeps_ids_only_differing_in_case_apart() -> Result<()> {
+        async fn save(storage: &StorageDisk<TestItem>, id: &String) -> Result<()> {
+            let (lock, _) = storage.lock(id, "TEST").await?.success()?;
+            storage.save_raw(id, id.as_bytes(), &lock).await?;
+            storage.unlock(id, lock).await
+        }
+
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_items_case_safe");
+        let _ = std::fs::remove_dir_all(&path);
+        let extension = Path::new("test_item");
+
+        let ids = ["player", "Player", "PLAYER"].map(String::from);
+        let mut storage = StorageDisk::<TestItem>::new(&path, extension).await;
+        storage.ensure_storage_exists().await?;
+        save(&storage, &ids[1]).await?;
+
+        storage.set_file_names(StorageDiskFileNames::CaseSafe)?;
+        assert_eq!(
+            1,
+            storage
+                .migrate_file_names(StorageDiskFileNames::Plain, None, None)
+                .await?
+        );
+        for id in ids.iter() {
+            if id != &ids[1] {
+                save(&storage, id).await?;
+            }
+        }
+
+        let mut names: Vec<String> = std::fs::read_dir(&path)?
+            .filter_map(|e| e.ok())
+            .map(|e| e.file_name().to_string_lossy().to_lowercase())
+            .filter(|n| n.ends_with(".test_item"))
+            .collect();
+        names.sort();
+        names.dedup();
+        assert_eq!(3, names.len());
+        for id in ids.iter() {
+            assert_eq!(id.as_bytes(), storage.load_raw(id).await?);
+        }
+        let mut all_ids = storage.all_ids().await?;
+        all_ids.sort();
+        let mut expected = ids.to_vec();
+        expected.sort();
+        assert_eq!(expected, all_ids);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_keeps_hostile_ids_inside_base_path() -> Result<()> {
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_items_hostile");
+        let _ = std::fs::remove_dir_all(&path);
+        let extension = Path::new("test_item");
+
+        let mut storage = StorageDisk::<TestItem>::new(&path, extension).await;
+        storage.ensure_storage_exists().await?;
+
+        let us = "TEST";
+
+        let mut ids = vec![
+            String::from("../escaped"),
+            String::from("sub/folder"),
+            String::from("NUL"),
+            String::from("a.b"),
+        ];
+        for item_id in ids.iter() {
+            let file_path = storage.file_path(item_id);
+            assert_eq!(Some(path.as_path()), file_path.parent());
+
+            let (lock, item) = storage.lock(item_id, us).await?.success()?;
+            storage.save(item_id, &item, &lock).await?;
+            storage.unlock(item_id, lock).await?;
+            assert!(storage.exists(item_id).await?);
+        }
+        assert!(!path.parent().unwrap().join("escaped.test_item").exists());
+
+        let mut all_ids = storage.all_ids().await?;
+        all_ids.sort();
+        ids.sort();
+        assert_eq!(ids, all_ids);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_enforces_quota() -> Result<()> {
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_items_quota");
+        let _ = std::fs::remove_dir_all(&path);
+        let extension = Path::new("test_item");
+
+        let mut storage = StorageDisk::<TestItem>::new(&path, extension).await;
+        storage.ensure_storage_exists().await?;
+        storage.set_quota(Some(StorageDiskQuota {
+            max_bytes: None,
+            max_items: Some(2),
+            on_exceeded: StorageDiskQuotaAction::Reject,
+        }))?;
+
+        let us = "TEST";
+
+        let mut ids = Vec::default();
+        for _ in 0..3 {
+            let item_id = storage.create().await?;
+            let (lock, item) = storage.lock(&item_id, us).await?.success()?;
+            let r = storage.save(&item_id, &item, &lock).await;
+            storage.unlock(&item_id, lock).await?;
+            if ids.len() < 2 {
+                r?;
+            } else {
+                let e = r.expect_err("third save should exceed quota");
+                assert!(matches!(
+                    e.downcast_ref::<StorageError>(),
+                    Some(StorageError::QuotaExceeded { items: 3, .. })
+                ));
+            }
+            ids.push(item_id);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(2, storage.usage().await?.1);
+
+        storage.set_quota(Some(StorageDiskQuota {
+            max_bytes: None,
+            max_items: Some(2),
+            on_exceeded: StorageDiskQuotaAction::EvictLeastRecentlyModified,
+        }))?;
+        // the oldest item is locked, so the second one gets evicted
+        let (oldest_lock, _) = storage.lock(&ids[0], us).await?.success()?;
+        let (lock, item) = storage.lock(&ids[2], us).await?.success()?;
+        storage.save_and_unlock(&ids[2], &item, lock).await?;
+        storage.unlock(&ids[0], oldest_lock).await?;
+
+        assert!(storage.exists(&ids[0]).await?);
+        assert!(!storage.exists(&ids[1]).await?);
+        assert!(storage.exists(&ids[2]).await?);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "zstd")]
+    #[tokio::test]
+    async fn it_loads_compressed_and_uncompressed_items() -> Result<()> {
+        use crate::StorageDiskCompression;
+
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_items");
+        let extension = Path::new("test_item");
+
+        let mut storage = StorageDisk::<TestItem>::new(&path, extension).await;
+        storage.ensure_storage_exists().await?;
+
+        let us = "TEST";
+
+        let plain_id = storage.create().await?;
+        let (lock, item) = storage.lock(&plain_id, us).await?.success()?;
+        storage.save_and_unlock(&plain_id, &item, lock).await?;
+
+        storage.set_compression(StorageDiskCompression::Zstd { level: 3 })?;
+        let compressed_id = storage.create().await?;
+        let (lock, item) = storage.lock(&compressed_id, us).await?.success()?;
+        storage.save_and_unlock(&compressed_id, &item, lock).await?;
+
+        let data = std::fs::read(storage.file_path(&compressed_id))?;
+        assert!(data.starts_with(&super::ZSTD_MAGIC));
+
+        storage.load(&plain_id).await?;
+        storage.load(&compressed_id).await?;
+
+        let (lock, _) = storage.lock(&compressed_id, us).await?.success()?;
+        storage.save_raw(&compressed_id, b"{}", &lock).await?;
+        storage.unlock(&compressed_id, lock).await?;
+        assert_eq!(b"{}".to_vec(), storage.load_raw(&compressed_id).await?);
+        assert_eq!(
+            storage.load_raw(&plain_id).await?,
+            StorageItem::serialize(&TestItem::default())?
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_keeps_previous_versions() -> Result<()> {
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_items");
+        let extension = Path::new("test_item");
+
+        let mut storage = StorageDisk::<TestItem>::new(&path, extension).await;
+        storage.ensure_storage_exists().await?;
+        storage.set_versions(2)?;
+
+        let item_id = storage.create().await?;
+        let (lock, _) = storage.lock(&item_id, "TEST").await?.success()?;
+        for v in 0..4 {
+            storage
+                .save_raw(&item_id, format!("{{\"v\":{v}}}").as_bytes(), &lock)
+                .await?;
+        }
+        storage.unlock(&item_id, lock).await?;
+
+        assert_eq!(b"{\"v\":3}".to_vec(), storage.load_raw(&item_id).await?);
+        assert_eq!(
+            b"{\"v\":2}".to_vec(),
+            std::fs::read(storage.version_path(&item_id, 1))?
+        );
+        assert_eq!(
+            b"{\"v\":1}".to_vec(),
+            std::fs::read(storage.version_path(&item_id, 2))?
+        );
+        assert!(!storage.version_path(&item_id, 3).exists());
+        storage.load_previous(&item_id, 2).await?;
+        let e = storage.load_previous(&item_id, 3).await.unwrap_err();
+        assert!(matches!(
+            e.downcast_ref::<StorageError>(),
+            Some(StorageError::NotFound { .. })
+        ));
+        assert!(storage.load_previous(&item_id, 0).await.is_err());
+
+        // the item scan skips versions
+        assert_eq!(
+            1,
+            storage
+                .all_ids()
+                .await?
+                .iter()
+                .filter(|id| **id == item_id)
+                .count()
+        );
+
+        // exports carry the versions, newest first
+        let archive = storage.export_item(&item_id).await?;
+        assert!(archive.modified.is_some());
+        assert_eq!(
+            vec![b"{\"v\":2}".to_vec(), b"{\"v\":1}".to_vec()],
+            archive
+                .versions
+                .into_iter()
+                .map(|v| v.data)
+                .collect::<Vec<_>>()
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_streams_item_data() -> Result<()> {
+        use tokio::io::AsyncReadExt;
+
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_items");
+        let extension = Path::new("test_item");
+
+        let mut storage = StorageDisk::<TestItem>::new(&path, extension).await;
+        storage.ensure_storage_exists().await?;
+
+        let item_id = storage.create().await?;
+        let (lock, _) = storage.lock(&item_id, "TEST").await?.success()?;
+        let data = serde_json::to_vec(&TestItem::default())?;
+        storage
+            .save_stream(&item_id, &mut data.as_slice(), &lock)
+            .await?;
+        storage.unlock(&item_id, lock).await?;
+
+        let mut loaded = Vec::default();
+        storage
+            .load_stream(&item_id)
+            .await?
+            .read_to_end(&mut loaded)
+            .await?;
+        assert_eq!(data, loaded);
+        assert_eq!(data, storage.load_raw(&item_id).await?);
+
+        let e = storage.load_stream(&storage.create().await?).await;
+        assert!(matches!(
+            e.err()
+                .and_then(|e| e.downcast_ref::<StorageError>().cloned()),
+            Some(StorageError::NotFound { .. })
+        ));
+
+        Ok(())
     }
-    async fn lock(&self, id: &ITEM::ID, who: &str) -> Result<LockResult<ITEM>> {
-        let l = self.lock_path(id);
-        let (lock, item) = {
-            let sem = self.lock_semaphore.acquire().await?;
-            tracing::debug!("Lock[{who}]: Got Semaphore");
 
-            tracing::debug!("Lock[{who}]: Does {l:?} exist");
+    #[tokio::test]
+    async fn it_refuses_writes_when_read_only() -> Result<()> {
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_items");
+        let extension = Path::new("test_item");
 
-            if fs::metadata(&l).is_ok() {
-                tracing::warn!("Lockfile {l:?} already exists");
-                drop(sem);
-                tracing::debug!("Lock[{who}]: Dropped Semaphore"); // close enough
-                                                                   //return Err(eyre!("Already locked"));
-                                                                   // :TODO: load lock
-                self.update_highest_seen_id(&id);
-                return Ok(LockResult::AlreadyLocked {
-                    who: String::from(":TODO:"),
-                });
-            }
+        let mut storage = StorageDisk::<TestItem>::new(&path, extension).await;
+        storage.ensure_storage_exists().await?;
+        let item_id = storage.create().await?;
+        let (lock, item) = storage.lock(&item_id, "TEST").await?.success()?;
+        storage.save_and_unlock(&item_id, &item, lock).await?;
 
-            let lock = StorageLock::new(who);
-            let lock_json = serde_json::to_string_pretty(&lock)?;
+        let mut read_only = StorageDisk::<TestItem>::new_read_only(&path, extension).await;
+        read_only.ensure_storage_exists().await?;
+        read_only.load(&item_id).await?;
+        assert!(read_only.all_ids().await?.contains(&item_id));
 
-            tracing::debug!("Lock[{who}]: Write lock to {l:?}");
-            fs::write(l.clone(), lock_json)
-                .map_err(|e| eyre!("Can't lock {l:?} for {who}: {e:?}"))?;
+        let e = read_only.lock(&item_id, "TEST").await.expect_err("lock");
+        assert_eq!(Some(&StorageError::ReadOnly), e.downcast_ref());
+        let e = read_only.force_unlock(&item_id).await.expect_err("unlock");
+        assert_eq!(Some(&StorageError::ReadOnly), e.downcast_ref());
+        assert!(!read_only.exists(&nanoid::nanoid!()).await?);
 
-            tracing::debug!("Lock[{who}]: Load {id}");
-            let item = self.load(id).await.unwrap_or_default();
+        let missing = path.join("missing");
+        let mut read_only = StorageDisk::<TestItem>::new_read_only(&missing, extension).await;
+        assert!(read_only.ensure_storage_exists().await.is_err());
+        assert!(!missing.exists());
 
-            drop(sem);
-            tracing::debug!("Lock[{who}]: Dropped Semaphore"); // close enough
-            (lock, item)
-        };
-        self.update_highest_seen_id(&id);
-        Ok(LockResult::Success { lock, item })
+        Ok(())
     }
 
-    async fn unlock(&self, id: &ITEM::ID, lock: StorageLock) -> Result<()> {
-        if !self.verify_lock(id, &lock).await? {
-            Err(eyre!("Lock invalid!"))
-        } else {
-            let l = self.lock_path(id);
-            std::fs::remove_file(l.clone()).map_err(|e| eyre!("Can't unlock {l:?}: {e:?}"))?;
-            Ok(())
-        }
-    }
+    #[tokio::test]
+    async fn it_probes_in_ensure_storage_exists() -> Result<()> {
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_items_probe");
+        let extension = Path::new("test_item");
+        let probe_id = String::from("probe");
 
-    async fn force_unlock(&self, id: &ITEM::ID) -> Result<()> {
-        let l = self.lock_path(id);
-        if !fs::metadata(&l).is_ok() {
-            tracing::warn!("Lockfile {l:?} doesn't exists");
-            return Err(eyre!("Not locked"));
-        }
+        let mut storage = StorageDisk::<TestItem>::new(&path, extension).await;
+        storage.set_probe_id(Some(probe_id.clone()))?;
+        storage.ensure_storage_exists().await?;
+        // again, with the probe item already there
+        storage.ensure_storage_exists().await?;
+        assert!(storage.exists(&probe_id).await?);
+
+        let mut read_only = StorageDisk::<TestItem>::new_read_only(&path, extension).await;
+        read_only.set_probe_id(Some(probe_id.clone()))?;
+        read_only.ensure_storage_exists().await?;
+
+        let (lock, _item) = storage.lock(&probe_id, "TEST").await?.success()?;
+        let e = storage.ensure_storage_exists().await.expect_err("probe");
+        assert!(format!("{e}").contains("locked by \"TEST\""), "{e}");
+        storage.unlock(&probe_id, lock).await?;
 
-        std::fs::remove_file(l.clone()).map_err(|e| eyre!("Can't force unlock {l:?}: {e:?}"))?;
         Ok(())
     }
-    async fn verify_lock(&self, id: &ITEM::ID, lock: &StorageLock) -> Result<bool> {
-        let l = self.lock_path(id);
-        if !fs::metadata(&l).is_ok() {
-            tracing::warn!("Lockfile {l:?} doesn't exists");
-            return Ok(false);
-        }
 
-        let expected_lock_json = fs::read(&l)?;
-        let expected_lock: StorageLock = serde_json::from_slice(&expected_lock_json)?;
+    #[tokio::test]
+    async fn it_saves_immutable_items_once() -> Result<()> {
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_items");
+        let extension = Path::new("test_item");
 
-        if expected_lock != *lock {
-            tracing::warn!("Lock mismatch for {id} {lock:?} != {expected_lock:?}");
-            return Ok(false);
-        }
-        Ok(true)
-    }
-    async fn all_ids(&self) -> Result<Vec<ITEM::ID>> {
-        //tracing::debug!("all_ids");
-        let mut ids = Vec::default();
-        let extension = self.extension.to_string_lossy(); //.to_string();
-        let extension = format!(".{}", extension);
-        let mut highest_id = ITEM::ID::default();
-        for entry in fs::read_dir(&self.base_path)? {
-            if let Ok(entry) = &entry {
-                match entry.file_type() {
-                    Ok(file_type) if file_type.is_file() => {
-                        //tracing::debug!("{entry:?}");
-                        //let p = entry.path();
-                        let f = entry.file_name();
-                        let f = f.to_string_lossy().to_string();
-                        if let Some(id) = f.strip_suffix(&extension) {
-                            //tracing::debug!("{f} -> {id:?}");
-                            //let id: ITEM::ID = id.try_into().map_err(|e| eyre!("Can not convert {id} into ITEM::ID -> {e:?}") )?;
-                            let id: ITEM::ID = ITEM::make_id(id)?;
-                            if id > highest_id {
-                                highest_id = id.to_owned(); // :TODO: decide if we want to keep this
-                            } else {
-                                tracing::debug!("{id} < {highest_id}");
-                            }
-                            ids.push(id);
-                        }
-                    }
-                    _ => {} // skip
-                }
-            }
-        }
-        self.update_highest_seen_id(&highest_id);
-        Ok(ids)
+        let mut storage = StorageDisk::<TestItem>::new(&path, extension).await;
+        storage.set_immutable(true)?;
+        storage.ensure_storage_exists().await?;
+        let item_id = storage.create().await?;
+        let (lock, item) = storage.lock(&item_id, "TEST").await?.success()?;
+        storage.save(&item_id, &item, &lock).await?;
+
+        let e = storage
+            .save(&item_id, &item, &lock)
+            .await
+            .expect_err("save");
+        assert_eq!(
+            Some(&StorageError::Immutable {
+                id: item_id.to_string()
+            }),
+            e.downcast_ref()
+        );
+        storage.unlock(&item_id, lock).await?;
+        storage.load(&item_id).await?;
+
+        Ok(())
     }
-    async fn scan_ids(
-        &self,
-        start: Option<&str>,
-        limit: Option<usize>,
-    ) -> Result<(Vec<ITEM::ID>, Option<String>)> {
-        // :HACK: just scan all and filter after
-        let mut all_ids = self.all_ids().await?;
-
-        let skip_count = if let Some(start) = start {
-            let skip_count = start.parse::<usize>()?;
-            let skip_count = skip_count.min(all_ids.len());
-            all_ids.drain(0..skip_count);
-            skip_count
-        } else {
-            0
-        };
 
-        if let Some(limit) = limit {
-            let limit = limit.min(all_ids.len());
-            all_ids.resize_with(limit, || {
-                /* :TODO: trace? */
-                unimplemented!() /* ITEM::ID::default() */
-            });
-        }
+    #[tokio::test]
+    async fn it_hands_off_locks() -> Result<()> {
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_items");
+        let extension = Path::new("test_item");
 
-        let scan_pos = skip_count + all_ids.len();
+        let mut storage = StorageDisk::<TestItem>::new(&path, extension).await;
+        storage.ensure_storage_exists().await?;
+        let item_id = storage.create().await?;
+        let (lock, item) = storage.lock(&item_id, "worker-1").await?.success()?;
+        let stale = StorageLock::new_at(lock.who(), *lock.when());
 
-        let scan_pos = if scan_pos <= all_ids.len() {
-            Some(format!("{scan_pos}"))
-        } else {
-            None
-        };
+        let handed_off = storage.handoff_lock(&item_id, lock, "worker-2").await?;
+        assert!(storage.verify_lock(&item_id, &handed_off).await?);
+        assert!(!storage.verify_lock(&item_id, &stale).await?);
+        assert!(storage.save(&item_id, &item, &stale).await.is_err());
+        assert!(storage.lock(&item_id, "worker-3").await?.success().is_err());
+
+        storage.save_and_unlock(&item_id, &item, handed_off).await?;
 
-        Ok((all_ids, scan_pos))
+        Ok(())
     }
 
-    async fn display_lock(&self, id: &ITEM::ID) -> Result<String> {
-        let l = self.lock_path(id);
-        if !fs::metadata(&l).is_ok() {
-            return Ok(String::default());
-        } else {
-            let lock_json = fs::read(&l)?;
-            let lock: StorageLock = serde_json::from_slice(&lock_json)?;
-            let lock_string = format!("Locked by {} at {:?}", lock.who(), lock.when());
-            //            let lock_string = format!("{:?}", lock);
+    #[tokio::test]
+    async fn it_stores_blobs() -> Result<()> {
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_items");
+        let extension = Path::new("test_item");
 
-            Ok(lock_string)
-        }
-    }
-    #[cfg(feature = "metadata")]
-    async fn metadata_highest_seen_id(&self) -> Option<ITEM::ID> {
-        self.metadata.highest_seen_id()
+        let mut storage = StorageDisk::<TestItem>::new(&path, extension).await;
+        storage.ensure_storage_exists().await?;
+
+        let item_id = storage.create().await?;
+        let (lock, item) = storage.lock(&item_id, "TEST").await?.success()?;
+        storage.save(&item_id, &item, &lock).await?;
+        storage
+            .put_blob(&item_id, "avatar.png", &[1, 2, 3], &lock)
+            .await?;
+        storage
+            .put_blob(&item_id, "../notes", b"hello", &lock)
+            .await?;
+        storage
+            .put_blob(&item_id, "avatar.png", &[4, 5], &lock)
+            .await?;
+        storage.unlock(&item_id, lock).await?;
+
+        assert_eq!(
+            Some(vec![4, 5]),
+            storage.get_blob(&item_id, "avatar.png").await?
+        );
+        assert_eq!(None, storage.get_blob(&item_id, "missing").await?);
+        assert_eq!(
+            vec![String::from("../notes"), String::from("avatar.png")],
+            storage.list_blobs(&item_id).await?
+        );
+        // blobs don't show up as items
+        assert!(!storage
+            .all_ids()
+            .await?
+            .iter()
+            .any(|id| id.contains("blobs")));
+
+        let lock = crate::StorageLock::new("TEST");
+        assert!(storage
+            .put_blob(&item_id, "avatar.png", &[], &lock)
+            .await
+            .is_err());
+
+        Ok(())
     }
 
-    #[cfg(feature = "wipe")]
-    async fn wipe(&self, confirmation: &str) -> Result<()> {
-        if confirmation != "Yes, I know what I am doing!" {
-            tracing::error!("Please confirm you know what you are doing");
-            return Err(eyre!("Unconfirmed wipe attempt"));
-        }
+    #[tokio::test]
+    async fn it_allocates_ids_from_a_shared_counter() -> Result<()> {
+        use crate::IdAllocator;
 
-        let _sem = self.lock_semaphore.acquire().await?;
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_items_counter");
+        let _ = std::fs::remove_dir_all(&path);
+        let extension = Path::new("test_item");
 
-        // we know all_ids doesn't use the semaphore
-        let ids = self.all_ids().await?;
+        // two instances, like two processes
+        let mut storages = Vec::default();
+        for _ in 0..2 {
+            let mut storage = StorageDisk::<TestItem>::new(&path, extension).await;
+            storage.ensure_storage_exists().await?;
+            let mut id_allocator = IdAllocator::new(Box::new(storage.id_counter("ids")?));
+            id_allocator.set_batch_size(3)?;
+            storage.set_id_allocator(Some(id_allocator))?;
+            storages.push(storage);
+        }
 
-        tracing::warn!("Wiping {} items.", ids.len());
-        for id in ids {
-            let l = self.lock_path(&id);
-            if fs::metadata(&l).is_ok() {
-                let _ =
-                    std::fs::remove_file(l.clone()).map_err(|e| eyre!("Can't remove {l:?}: {e:?}"));
-            }
-            let f = self.file_path(&id);
-            if fs::metadata(&f).is_ok() {
-                let _ =
-                    std::fs::remove_file(f.clone()).map_err(|e| eyre!("Can't remove {f:?}: {e:?}"));
+        let mut ids = Vec::default();
+        for _ in 0..5 {
+            for storage in storages.iter() {
+                ids.push(storage.create().await?);
             }
         }
+        let mut numbers: Vec<u64> = ids.iter().map(|id| id.parse().unwrap()).collect();
+        numbers.sort();
+        numbers.dedup();
+        assert_eq!(10, numbers.len());
+        // batches of 3: 1..=3, 4..=6, 7..=9, 10..=12
+        assert!(numbers.iter().all(|n| (1..=12).contains(n)));
+
+        // the counter is not an item
+        assert!(storages[0].all_ids().await?.is_empty());
+
         Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::LockResult;
-    use crate::Storage;
-    use crate::StorageDisk;
-    use crate::StorageItem;
-    use color_eyre::Result;
-    use serde::Deserialize;
-    use serde::Serialize;
-    use std::env;
-    use std::path::Path;
+    #[tokio::test]
+    async fn it_scans_ids_with_prefix() -> Result<()> {
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_items_prefix");
+        let _ = std::fs::remove_dir_all(&path);
+        let extension = Path::new("test_item");
 
-    #[derive(Default, Debug, Serialize, Deserialize)]
-    struct TestItem {}
+        let mut storage = StorageDisk::<TestItem>::new(&path, extension).await;
+        storage.ensure_storage_exists().await?;
 
-    impl StorageItem for TestItem {
-        fn serialize(&self) -> Result<Vec<u8>> {
-            let json = serde_json::to_string_pretty(&self)?;
+        let us = "TEST";
 
-            Ok(json.into())
+        for item_id in ["a/1", "a/2", "a/3", "ab/1", "b/1"] {
+            let item_id = String::from(item_id);
+            let (lock, item) = storage.lock(&item_id, us).await?.success()?;
+            storage.save(&item_id, &item, &lock).await?;
+            storage.unlock(&item_id, lock).await?;
         }
-        fn deserialize(data: &[u8]) -> Result<Self>
-        where
-            Self: Sized,
-        {
-            let i = serde_json::from_slice(&data)?;
 
-            Ok(i)
+        let mut scanned_ids = Vec::default();
+        let mut scan_pos = None;
+        loop {
+            let Page {
+                items: ids,
+                cursor: next,
+                ..
+            } = storage
+                .scan_ids_with_prefix("a/", scan_pos.as_deref(), Some(2))
+                .await?;
+            scanned_ids.extend(ids);
+            match next {
+                Some(next) => scan_pos = Some(next),
+                None => break,
+            }
         }
+        assert_eq!(vec!["a/1", "a/2", "a/3"], scanned_ids);
+
+        Ok(())
     }
 
     #[tokio::test]
-    async fn it_debugs() -> Result<()> {
+    async fn it_rejects_invalid_ids() -> Result<()> {
         let mut path = env::current_dir()?;
         path.push("data");
-        path.push("test_items");
+        path.push("test_items_invalid_id");
+        let _ = std::fs::remove_dir_all(&path);
         let extension = Path::new("test_item");
 
-        let storage = StorageDisk::<TestItem>::new(&path, &extension).await;
-        println!("{storage:?}");
+        let mut storage = StorageDisk::<crate::test_item::StrictItem>::new(&path, extension).await;
+        storage.ensure_storage_exists().await?;
 
-        let storage: Box<dyn Storage<TestItem>> = Box::new(storage);
-        println!("{storage:?}");
+        let bad_id = crate::test_item::StrictId::from_string("../escaped")?;
+        let e = storage.lock(&bad_id, "TEST").await.unwrap_err();
+        assert_eq!(
+            Some(&StorageError::InvalidId {
+                id: bad_id.to_string()
+            }),
+            e.downcast_ref::<StorageError>()
+        );
+        assert!(storage.exists(&bad_id).await.is_err());
+
+        let good_id = crate::test_item::StrictId::from_string("good1")?;
+        let (lock, item) = storage.lock(&good_id, "TEST").await?.success()?;
+        storage.save_and_unlock(&good_id, &item, lock).await?;
+        assert!(storage.exists(&good_id).await?);
 
         Ok(())
     }
+
     #[tokio::test]
-    async fn it_gives_all_ids() -> Result<()> {
+    async fn it_resolves_aliases() -> Result<()> {
         let mut path = env::current_dir()?;
         path.push("data");
-        path.push("test_items");
-        let extension = Path::new("test_item.json");
-
-        let storage = StorageDisk::<TestItem>::new(&path, &extension).await;
-        //println!("{storage:?}");
+        path.push("test_items_alias");
+        let _ = std::fs::remove_dir_all(&path);
+        let extension = Path::new("test_item");
 
-        let storage: Box<dyn Storage<TestItem>> = Box::new(storage);
-        //println!("{storage:?}");
+        let mut storage = StorageDisk::<TestItem>::new(&path, extension).await;
+        storage.ensure_storage_exists().await?;
 
         let us = "TEST";
+        let canonical = storage.create().await?;
+        let (lock, item) = storage.lock(&canonical, us).await?.success()?;
+        storage.save_and_unlock(&canonical, &item, lock).await?;
+        let alias = String::from("legacy-42");
+        assert!(storage.add_alias(&alias, &canonical).await.is_err());
 
-        let mut ids = Vec::default();
+        storage.set_aliases_enabled(true)?;
+        assert!(storage
+            .add_alias(&alias, &String::from("missing"))
+            .await
+            .is_err());
+        storage.add_alias(&alias, &canonical).await?;
+        assert!(storage.add_alias(&alias, &canonical).await.is_err());
+        assert!(storage.add_alias(&canonical, &alias).await.is_err());
+        assert_eq!(canonical, storage.resolve_alias(&alias).await?);
 
-        for _ in 0..5 {
-            let item_id = storage.create().await.unwrap();
-            //println!("{item_id:?}");
+        // locking via the alias locks the canonical item
+        assert!(storage.exists(&alias).await?);
+        let (lock, item) = storage.lock(&alias, us).await?.success()?;
+        assert!(storage.verify_lock(&canonical, &lock).await?);
+        storage.save(&alias, &item, &lock).await?;
+        storage.unlock(&alias, lock).await?;
+        assert_eq!(vec![canonical.clone()], storage.all_ids().await?);
 
-            let (lock, item) = match storage.lock(&item_id, &us).await? {
-                LockResult::Success { lock, item } => (lock, item),
-                LockResult::AlreadyLocked { .. } => {
-                    todo!();
-                }
-            };
-            storage.save(&item_id, &item, &lock).await?;
-            storage.unlock(&item_id, lock).await?;
+        storage.remove_alias(&alias).await?;
+        assert!(!storage.exists(&alias).await?);
+        assert!(storage.remove_alias(&alias).await.is_err());
 
-            ids.push(item_id);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_links_items() -> Result<()> {
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_items_links");
+        let _ = std::fs::remove_dir_all(&path);
+        let extension = Path::new("test_item");
+
+        let mut storage = StorageDisk::<TestItem>::new(&path, extension).await;
+        storage.ensure_storage_exists().await?;
+
+        let us = "TEST";
+        let mut members = Vec::new();
+        for _ in 0..2 {
+            let member = storage.create().await?;
+            let (lock, item) = storage.lock(&member, us).await?.success()?;
+            storage.save_and_unlock(&member, &item, lock).await?;
+            members.push(member);
         }
-        let all_ids = storage.all_ids().await?;
+        members.sort();
+        let guild = storage.create().await?;
+        let (lock, item) = storage.lock(&guild, us).await?.success()?;
+        storage.save(&guild, &item, &lock).await?;
+        for member in members.iter() {
+            storage.add_link(&guild, "member", member, &lock).await?;
+        }
+        assert!(storage
+            .add_link(&guild, "member", &String::from("missing"), &lock)
+            .await
+            .is_err());
+        assert!(storage
+            .add_link(&guild, "../member", &members[0], &lock)
+            .await
+            .is_err());
+        storage.unlock(&guild, lock).await?;
+        let other_lock = crate::StorageLock::new(us);
+        assert!(storage
+            .remove_link(&guild, "member", &members[0], &other_lock)
+            .await
+            .is_err());
 
-        //println!("{all_ids:#?}");
+        assert_eq!(members, storage.links_of(&guild, "member").await?);
+        assert!(storage.links_of(&guild, "officer").await?.is_empty());
+        assert_eq!(
+            vec![guild.clone()],
+            storage.linked_from(&members[1], "member").await?
+        );
+        // links don't show up as items
+        assert_eq!(3, storage.all_ids().await?.len());
 
-        assert!(all_ids.len() >= ids.len());
-        assert!(ids.iter().all(|id| all_ids.contains(id)));
+        let (lock, _item) = storage.lock(&guild, us).await?.success()?;
+        storage
+            .remove_link(&guild, "member", &members[1], &lock)
+            .await?;
+        storage.unlock(&guild, lock).await?;
+        assert_eq!(
+            vec![members[0].clone()],
+            storage.links_of(&guild, "member").await?
+        );
+        assert!(storage.linked_from(&members[1], "member").await?.is_empty());
+
+        std::fs::remove_file(path.join(format!("{}.test_item", members[0])))?;
+        let dangling = crate::links::find_dangling_links(&storage, "member").await?;
+        assert_eq!(
+            vec![crate::links::DanglingLink {
+                from: guild,
+                to: members[0].clone(),
+            }],
+            dangling
+        );
 
         Ok(())
     }
 
+    #[cfg(feature = "metadata")]
     #[tokio::test]
-    async fn it_displays_locks() -> Result<()> {
+    async fn it_persists_metadata() -> Result<()> {
         let mut path = env::current_dir()?;
         path.push("data");
-        path.push("test_items");
+        path.push("test_items_metadata");
+        let _ = std::fs::remove_dir_all(&path);
         let extension = Path::new("test_item");
 
-        let storage = StorageDisk::<TestItem>::new(&path, &extension).await;
-        // println!("{storage:?}");
-
-        let storage: Box<dyn Storage<TestItem>> = Box::new(storage);
-        // println!("{storage:?}");
+        let mut storage = StorageDisk::<TestItem>::new(&path, extension).await;
+        storage.ensure_storage_exists().await?;
+        for item_id in ["b", "c", "a"] {
+            let (lock, item) = storage
+                .lock(&String::from(item_id), "TEST")
+                .await?
+                .success()?;
+            storage
+                .save_and_unlock(&String::from(item_id), &item, lock)
+                .await?;
+        }
+        storage.flush_metadata().await?;
+        drop(storage);
 
-        let us = "TEST";
+        let mut storage = StorageDisk::<TestItem>::new(&path, extension).await;
+        assert_eq!(None, storage.metadata_highest_seen_id().await);
+        storage.ensure_storage_exists().await?;
+        assert_eq!(
+            Some(String::from("c")),
+            storage.metadata_highest_seen_id().await
+        );
+        assert!(storage.metadata_last_write().await.is_some());
+        let lock_stats = storage.metadata_lock_stats().await;
+        assert_eq!(0, lock_stats.acquisitions);
+        assert!(storage
+            .lock(&String::from("a"), "TEST")
+            .await?
+            .success()
+            .is_ok());
+        assert!(storage
+            .lock(&String::from("a"), "TEST")
+            .await?
+            .success()
+            .is_err());
+        let lock_stats = storage.metadata_lock_stats().await;
+        assert_eq!(1, lock_stats.held);
+        assert_eq!(1, lock_stats.acquisitions);
+        assert_eq!(1, lock_stats.contentions);
 
-        let item_id = storage.create().await.unwrap();
-        //println!("{item_id:?}");
+        let snapshot = storage.metadata_snapshot().await;
+        assert_eq!(Some(String::from("c")), snapshot.highest_seen_id);
+        assert_eq!(lock_stats, snapshot.lock_stats);
+        let json = serde_json::to_string(&snapshot)?;
+        assert_eq!(snapshot, serde_json::from_str(&json)?);
+        assert_eq!(None, storage.metadata_item_count().await);
 
-        let (lock, item) = match storage.lock(&item_id, &us).await? {
-            LockResult::Success { lock, item } => (lock, item),
-            LockResult::AlreadyLocked { .. } => {
-                todo!();
-            }
-        };
-        storage.save(&item_id, &item, &lock).await?;
-        let l = storage.display_lock(&item_id).await?;
-        println!("{l:?}");
-        storage.unlock(&item_id, lock).await?;
-        let l = storage.display_lock(&item_id).await?;
-        println!("{l:?}");
+        let (bytes, items) = storage.usage().await?;
+        assert_eq!(Some(3), storage.metadata_item_count().await);
+        assert_eq!(Some(bytes), storage.metadata_total_bytes().await);
+        let item_id = String::from("d");
+        let (lock, item) = storage.lock(&item_id, "TEST").await?.success()?;
+        storage.save_and_unlock(&item_id, &item, lock).await?;
+        assert_eq!(Some(items as u64 + 1), storage.metadata_item_count().await);
+        assert_eq!(
+            storage.usage().await?.0,
+            storage.metadata_total_bytes().await.unwrap()
+        );
 
         Ok(())
     }
 
+    #[cfg(unix)]
     #[tokio::test]
-    async fn exists_works_during_creation() -> Result<()> {
+    async fn it_refuses_to_lock_corrupt_items() -> Result<()> {
         let mut path = env::current_dir()?;
         path.push("data");
-        path.push("test_items");
+        path.push("test_items_corrupt");
+        let _ = std::fs::remove_dir_all(&path);
         let extension = Path::new("test_item");
 
-        let storage = StorageDisk::<TestItem>::new(&path, &extension).await;
-        // println!("{storage:?}");
+        let mut storage = StorageDisk::<TestItem>::new(&path, extension).await;
+        storage.ensure_storage_exists().await?;
+        let id = String::from("corrupt");
+        let (lock, _) = storage.lock(&id, "TEST").await?.success()?;
+        storage.save_raw(&id, b"{ not json", &lock).await?;
+        storage.unlock(&id, lock).await?;
 
-        let storage: Box<dyn Storage<TestItem>> = Box::new(storage);
-        // println!("{storage:?}");
+        let e = storage.lock(&id, "TEST").await.err();
+        assert!(matches!(
+            e.as_ref().and_then(|e| e.downcast_ref()),
+            Some(StorageError::Corrupt { .. })
+        ));
+        assert!(storage.force_unlock(&id).await?.is_none());
+        assert_eq!(b"{ not json".to_vec(), storage.load_raw(&id).await?);
 
-        let us = "TEST";
+        Ok(())
+    }
 
-        let item_id = nanoid::nanoid!();
+    #[tokio::test]
+    async fn it_sets_file_permissions() -> Result<()> {
+        use crate::StorageDiskPermissions;
+        use std::os::unix::fs::PermissionsExt;
 
-        let (lock, item) = match storage.lock(&item_id, &us).await? {
-            LockResult::Success { lock, item } => (lock, item),
-            LockResult::AlreadyLocked { .. } => {
-                todo!();
-            }
-        };
-        let exists_during_creation = storage.exists(&item_id).await?;
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_items_permissions");
+        let _ = std::fs::remove_dir_all(&path);
+        let extension = Path::new("test_item");
+        let mode =
+            |p: &Path| -> Result<u32> { Ok(std::fs::metadata(p)?.permissions().mode() & 0o7777) };
 
-        // storage.save(&item_id, &item, &lock).await?;
-        let l = storage.display_lock(&item_id).await?;
-        // println!("{l:?}");
-        storage.unlock(&item_id, lock).await?;
-        // let l = storage.display_lock(&item_id).await?;
-        // println!("{l:?}");
+        let mut storage = StorageDisk::<TestItem>::new(&path, extension).await;
+        storage.set_permissions(Some(StorageDiskPermissions {
+            mode: Some(0o600),
+            ..Default::default()
+        }))?;
+        storage.ensure_storage_exists().await?;
+
+        let id = nanoid::nanoid!();
+        let (lock, item) = storage.lock(&id, "TEST").await?.success()?;
+        assert_eq!(0o600, mode(&storage.lock_path(&id))?);
+        storage.save_and_unlock(&id, &item, lock).await?;
+        let file = storage.file_path(&id);
+        assert_eq!(0o600, mode(&file)?);
+
+        std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o644))?;
+        storage.ensure_storage_exists().await?;
+        assert_eq!(0o600, mode(&file)?);
+        // the periodic flush only writes after its first interval
+        storage.flush_metadata().await?;
+        assert_eq!(0, storage.enforce_permissions().await?);
+
+        std::fs::write(path.join(".in-flight.tmp"), b"")?;
+        std::fs::set_permissions(
+            path.join(".in-flight.tmp"),
+            std::fs::Permissions::from_mode(0o644),
+        )?;
+        assert_eq!(0, storage.enforce_permissions().await?);
 
-        assert_eq!(true, exists_during_creation);
         Ok(())
     }
-
     //ensure_storage_exists
 }
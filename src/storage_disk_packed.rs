@@ -0,0 +1,1416 @@
+use crate::exists_policy::is_orphan;
+use crate::operation_metrics;
+use crate::slow_op::SlowOp;
+use crate::storage::ensure_valid_create_tries;
+use crate::storage::ensure_valid_meta_key;
+use crate::storage::probe_storage;
+use crate::storage::DEFAULT_CREATE_TRIES;
+use crate::storage_disk::write_atomic;
+use crate::storage_item::deserialize_stored;
+use crate::storage_item::ensure_valid_id;
+use crate::storage_item::serialize_valid;
+use crate::Clock;
+use crate::DefaultIdGenerator;
+use crate::ExistsPolicy;
+use crate::IdGenerator;
+use crate::ItemSummary;
+use crate::LockResult;
+#[cfg(feature = "metadata")]
+use crate::Metadata;
+use crate::Page;
+use crate::Storage;
+use crate::StorageDiskDurability;
+use crate::StorageError;
+use crate::StorageId;
+use crate::StorageItem;
+use crate::StorageLock;
+use crate::SystemClock;
+use async_trait::async_trait;
+use chrono::DateTime;
+use chrono::Utc;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use tokio::sync::Mutex;
+
+use core::marker::PhantomData;
+use std::collections::HashMap;
+use std::io::SeekFrom;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::fs;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncSeekExt;
+use tokio::io::AsyncWriteExt;
+
+const RECORD_SAVE: u8 = 1;
+const RECORD_LOCK: u8 = 2;
+const RECORD_UNLOCK: u8 = 3;
+/// Removes data and lock
+const RECORD_DELETE: u8 = 4;
+/// A meta value, keyed by the id field, see [Storage::put_meta]
+const RECORD_META: u8 = 5;
+/// kind (u8), id length (u32 LE), data length (u32 LE)
+const RECORD_HEADER_LEN: usize = 9;
+/// Logs smaller than this are never compacted automatically
+const MIN_COMPACTION_LEN: u64 = 1024 * 1024;
+
+fn encode_record(kind: u8, id: &str, data: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(RECORD_HEADER_LEN + id.len() + data.len());
+    record.push(kind);
+    record.extend_from_slice(&(id.len() as u32).to_le_bytes());
+    record.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    record.extend_from_slice(id.as_bytes());
+    record.extend_from_slice(data);
+
+    record
+}
+
+/// A record decoded from the start of a buffer.
+struct DecodedRecord<'a> {
+    kind: u8,
+    id: &'a str,
+    /// Offset of the data relative to the start of the record
+    data_offset: usize,
+    data: &'a [u8],
+    len: usize,
+}
+
+/// Returns `None` if the buffer doesn't start with a complete record, e.g. after a crash during append.
+fn decode_record(buffer: &[u8]) -> Option<DecodedRecord<'_>> {
+    let header = buffer.get(..RECORD_HEADER_LEN)?;
+    let kind = header[0];
+    let id_len = u32::from_le_bytes(header[1..5].try_into().ok()?) as usize;
+    let data_len = u32::from_le_bytes(header[5..9].try_into().ok()?) as usize;
+    let data_offset = RECORD_HEADER_LEN + id_len;
+    let len = data_offset + data_len;
+    let id = std::str::from_utf8(buffer.get(RECORD_HEADER_LEN..data_offset)?).ok()?;
+    let data = buffer.get(data_offset..len)?;
+
+    Some(DecodedRecord {
+        kind,
+        id,
+        data_offset,
+        data,
+        len,
+    })
+}
+
+#[derive(Debug, Default)]
+struct IndexEntry {
+    /// Position and length of the item data in the log
+    data: Option<(u64, usize)>,
+    data_record_len: u64,
+    lock: Option<StorageLock>,
+    lock_record_len: u64,
+}
+
+/// The open log file and the in-memory index of its latest records.
+#[derive(Debug)]
+struct PackedLog {
+    file: fs::File,
+    len: u64,
+    /// Bytes used by records that are still current
+    live_len: u64,
+    index: HashMap<String, IndexEntry>,
+    /// Meta values and the length of their record
+    meta: HashMap<String, (Vec<u8>, u64)>,
+}
+
+impl PackedLog {
+    async fn open(path: &Path) -> Result<Self> {
+        let buffer = match fs::read(path).await {
+            Ok(buffer) => buffer,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::default(),
+            Err(e) => return Err(eyre!("Can't read log {path:?} -> {e}")),
+        };
+
+        let mut log = Self {
+            file: fs::OpenOptions::new()
+                .create(true)
+                .read(true)
+                .append(true)
+                .open(path)
+                .await
+                .map_err(|e| eyre!("Can't open log {path:?} -> {e}"))?,
+            len: 0,
+            live_len: 0,
+            index: HashMap::default(),
+            meta: HashMap::default(),
+        };
+
+        let mut pos = 0;
+        while let Some(record) = decode_record(&buffer[pos..]) {
+            log.apply(&record, pos as u64);
+            pos += record.len;
+        }
+        if pos < buffer.len() {
+            tracing::warn!(
+                "Dropping {} bytes of incomplete record at the end of {path:?}",
+                buffer.len() - pos
+            );
+            log.file.set_len(pos as u64).await?;
+        }
+        log.len = pos as u64;
+
+        Ok(log)
+    }
+
+    fn apply(&mut self, record: &DecodedRecord, pos: u64) {
+        if record.kind == RECORD_META {
+            let len = record.len as u64;
+            let value = (record.data.to_vec(), len);
+            if let Some((_, previous_len)) = self.meta.insert(record.id.to_string(), value) {
+                self.live_len -= previous_len;
+            }
+            self.live_len += len;
+            return;
+        }
+        let entry = self.index.entry(record.id.to_string()).or_default();
+        let len = record.len as u64;
+        match record.kind {
+            RECORD_SAVE => {
+                self.live_len -= entry.data_record_len;
+                entry.data = Some((pos + record.data_offset as u64, record.data.len()));
+                entry.data_record_len = len;
+                self.live_len += len;
+            }
+            RECORD_LOCK => match serde_json::from_slice(record.data) {
+                Ok(lock) => {
+                    self.live_len -= entry.lock_record_len;
+                    entry.lock = Some(lock);
+                    entry.lock_record_len = len;
+                    self.live_len += len;
+                }
+                Err(e) => tracing::warn!("Ignoring broken lock for {} -> {e:?}", record.id),
+            },
+            RECORD_UNLOCK => {
+                self.live_len -= entry.lock_record_len;
+                entry.lock = None;
+                entry.lock_record_len = 0;
+            }
+            RECORD_DELETE => {
+                self.live_len -= entry.data_record_len + entry.lock_record_len;
+                entry.data = None;
+                entry.lock = None;
+            }
+            kind => tracing::warn!("Ignoring unknown record kind {kind} for {}", record.id),
+        }
+        if entry.data.is_none() && entry.lock.is_none() {
+            self.index.remove(record.id);
+        }
+    }
+
+    async fn append(
+        &mut self,
+        kind: u8,
+        id: &str,
+        data: &[u8],
+        durability: StorageDiskDurability,
+    ) -> Result<()> {
+        let buffer = encode_record(kind, id, data);
+        self.file.write_all(&buffer).await?;
+        if durability != StorageDiskDurability::None {
+            self.file.sync_data().await?;
+        }
+        let record = decode_record(&buffer).ok_or_else(|| eyre!("Can't decode own record"))?;
+        self.apply(&record, self.len);
+        self.len += buffer.len() as u64;
+
+        Ok(())
+    }
+
+    async fn read_data(&mut self, id: &str) -> Result<Option<Vec<u8>>> {
+        let Some((pos, len)) = self.index.get(id).and_then(|e| e.data) else {
+            return Ok(None);
+        };
+        let mut data = vec![0; len];
+        self.file.seek(SeekFrom::Start(pos)).await?;
+        self.file.read_exact(&mut data).await?;
+
+        Ok(Some(data))
+    }
+
+    /// Bytes used by records that have been superseded
+    fn garbage_len(&self) -> u64 {
+        self.len - self.live_len
+    }
+}
+
+/// An alternative disk engine that appends all changes to a single log file.
+///
+/// An in-memory index points to the latest record of each item,
+/// and the log is compacted once enough of it is outdated.
+/// For many small items this avoids the per file overhead of [crate::StorageDisk].
+///
+/// Note: The log is owned by a single process, it must not be shared.
+#[derive(Debug)]
+pub struct StorageDiskPacked<ITEM: StorageItem> {
+    path: PathBuf,
+    durability: StorageDiskDurability,
+    compaction_ratio: Option<f64>,
+    probe_id: Option<ITEM::ID>,
+    log: Mutex<Option<PackedLog>>,
+    slow_op_threshold: Option<Duration>,
+    create_tries: usize,
+    clock: Arc<dyn Clock>,
+    id_generator: Arc<dyn IdGenerator<ITEM::ID>>,
+    exists_policy: ExistsPolicy,
+    immutable: bool,
+    item_type: PhantomData<ITEM>,
+    #[cfg(feature = "metadata")]
+    metadata: Metadata<ITEM>,
+}
+
+impl<ITEM: StorageItem> StorageDiskPacked<ITEM> {
+    /// `path` is the log file, its folder is created by [Storage::ensure_storage_exists].
+    pub async fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            durability: StorageDiskDurability::default(),
+            compaction_ratio: Some(0.5),
+            probe_id: None,
+            log: Mutex::new(None),
+            slow_op_threshold: None,
+            create_tries: DEFAULT_CREATE_TRIES,
+            clock: Arc::new(SystemClock),
+            id_generator: Arc::new(DefaultIdGenerator),
+            exists_policy: ExistsPolicy::default(),
+            immutable: false,
+            item_type: PhantomData,
+            #[cfg(feature = "metadata")]
+            metadata: Metadata::default(),
+        }
+    }
+
+    /// Selects if and how appends are synced to disk.
+    pub fn set_durability(&mut self, durability: StorageDiskDurability) -> Result<()> {
+        self.durability = durability;
+
+        Ok(())
+    }
+
+    /// Locks, writes, and reads back the reserved `probe_id` in [Storage::ensure_storage_exists],
+    /// so missing permissions fail there, instead of on the first real request.
+    pub fn set_probe_id(&mut self, probe_id: Option<ITEM::ID>) -> Result<()> {
+        self.probe_id = probe_id;
+
+        Ok(())
+    }
+
+    /// Logs a warning for every operation that takes longer than `threshold`.
+    pub fn set_slow_op_threshold(&mut self, threshold: Option<Duration>) -> Result<()> {
+        self.slow_op_threshold = threshold;
+
+        Ok(())
+    }
+
+    /// How often [Storage::create] generates a new id, when it collides with an existing one, defaults to 10.
+    pub fn set_create_tries(&mut self, tries: usize) -> Result<()> {
+        ensure_valid_create_tries(tries)?;
+        self.create_tries = tries;
+
+        Ok(())
+    }
+
+    /// Where the time for locks comes from, defaults to [SystemClock].
+    pub fn set_clock(&mut self, clock: impl Clock + 'static) -> Result<()> {
+        self.clock = Arc::new(clock);
+
+        Ok(())
+    }
+
+    /// Where [Storage::create] gets new ids from, defaults to [DefaultIdGenerator].
+    pub fn set_id_generator(
+        &mut self,
+        id_generator: impl IdGenerator<ITEM::ID> + 'static,
+    ) -> Result<()> {
+        self.id_generator = Arc::new(id_generator);
+
+        Ok(())
+    }
+
+    /// Locks held longer than this are logged with a warning and counted in [crate::LockStats].
+    #[cfg(feature = "metadata")]
+    pub fn set_max_lock_hold_time(&mut self, max_lock_hold_time: Option<Duration>) -> Result<()> {
+        self.metadata.set_max_lock_hold_time(max_lock_hold_time);
+
+        Ok(())
+    }
+
+    /// Whether locked but never saved items count in [Storage::exists], see [ExistsPolicy].
+    pub fn set_exists_policy(&mut self, exists_policy: ExistsPolicy) -> Result<()> {
+        self.exists_policy = exists_policy;
+
+        Ok(())
+    }
+
+    /// Items can only be saved once, later saves fail with [StorageError::Immutable].
+    /// Checked against the index while holding the log, so concurrent saves can't both win. Off by default.
+    pub fn set_immutable(&mut self, immutable: bool) -> Result<()> {
+        self.immutable = immutable;
+
+        Ok(())
+    }
+
+    /// Compact automatically once more than this fraction of the log is outdated.
+    /// `None` disables automatic compaction, see [StorageDiskPacked::compact].
+    pub fn set_compaction_ratio(&mut self, compaction_ratio: Option<f64>) -> Result<()> {
+        self.compaction_ratio = compaction_ratio;
+
+        Ok(())
+    }
+
+    /// Rewrites the log with only the current records.
+    pub async fn compact(&self) -> Result<()> {
+        let mut log = self.log.lock().await;
+        let log = self.opened(&mut log)?;
+        self.compact_log(log).await
+    }
+
+    /// Returns the size of the log and how much of it is outdated.
+    pub async fn log_len(&self) -> Result<(u64, u64)> {
+        let mut log = self.log.lock().await;
+        let log = self.opened(&mut log)?;
+        Ok((log.len, log.garbage_len()))
+    }
+
+    fn opened<'a>(&self, log: &'a mut Option<PackedLog>) -> Result<&'a mut PackedLog> {
+        log.as_mut().ok_or_else(|| {
+            eyre!(
+                "Log {:?} not open, call ensure_storage_exists first",
+                &self.path
+            )
+        })
+    }
+
+    async fn compact_log(&self, log: &mut PackedLog) -> Result<()> {
+        let before = log.len;
+        let mut ids: Vec<String> = log.index.keys().cloned().collect();
+        ids.sort_unstable();
+
+        let mut buffer = Vec::with_capacity(log.live_len as usize);
+        for id in ids {
+            if let Some(data) = log.read_data(&id).await? {
+                buffer.extend(encode_record(RECORD_SAVE, &id, &data));
+            }
+            if let Some(lock) = log.index.get(&id).and_then(|e| e.lock.as_ref()) {
+                let lock_json = serde_json::to_vec(lock)?;
+                buffer.extend(encode_record(RECORD_LOCK, &id, &lock_json));
+            }
+        }
+        for (key, (value, _)) in log.meta.iter() {
+            buffer.extend(encode_record(RECORD_META, key, value));
+        }
+        write_atomic(&self.path, &buffer, self.durability.into())
+            .await
+            .map_err(|e| eyre!("Can't write compacted log {:?} -> {e:?}", &self.path))?;
+        *log = PackedLog::open(&self.path).await?;
+
+        tracing::info!(
+            "Compacted {:?} from {before} to {} bytes",
+            &self.path,
+            log.len
+        );
+        Ok(())
+    }
+
+    async fn compact_if_needed(&self, log: &mut PackedLog) -> Result<()> {
+        let Some(compaction_ratio) = self.compaction_ratio else {
+            return Ok(());
+        };
+        if log.len >= MIN_COMPACTION_LEN
+            && log.garbage_len() as f64 > log.len as f64 * compaction_ratio
+        {
+            self.compact_log(log).await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "metadata")]
+impl<ITEM: StorageItem> StorageDiskPacked<ITEM> {
+    fn update_highest_seen_id(&self, id: &ITEM::ID) {
+        self.metadata.update_highest_seen_id(id);
+    }
+
+    fn record_lock_acquired(&self, id: &ITEM::ID, lock: &StorageLock) {
+        self.metadata.record_lock_acquired(id, lock);
+    }
+
+    fn record_lock_contended(&self) {
+        self.metadata.record_lock_contended();
+        operation_metrics::record_lock_contended("disk_packed");
+    }
+
+    fn record_lock_released(&self, id: &ITEM::ID, lock: &StorageLock) {
+        self.metadata
+            .record_lock_released(id, lock, self.clock.now());
+    }
+
+    fn record_save(&self, previous_len: Option<u64>, len: u64) {
+        self.metadata.record_save(previous_len, len);
+    }
+
+    #[cfg(feature = "wipe")]
+    fn record_remove(&self, len: u64) {
+        self.metadata.record_remove(len);
+    }
+
+    fn record_totals(&self, item_count: u64, total_bytes: Option<u64>) {
+        self.metadata.record_totals(item_count, total_bytes);
+    }
+}
+
+#[cfg(not(feature = "metadata"))]
+impl<ITEM: StorageItem> StorageDiskPacked<ITEM> {
+    fn update_highest_seen_id(&self, _id: &ITEM::ID) {}
+
+    fn record_lock_acquired(&self, _id: &ITEM::ID, _lock: &StorageLock) {}
+
+    fn record_lock_contended(&self) {
+        operation_metrics::record_lock_contended("disk_packed");
+    }
+
+    fn record_lock_released(&self, _id: &ITEM::ID, _lock: &StorageLock) {}
+
+    fn record_save(&self, _previous_len: Option<u64>, _len: u64) {}
+
+    #[cfg(feature = "wipe")]
+    fn record_remove(&self, _len: u64) {}
+
+    fn record_totals(&self, _item_count: u64, _total_bytes: Option<u64>) {}
+}
+
+impl<ITEM: StorageItem> StorageDiskPacked<ITEM> {
+    async fn read_data(&self, id: &ITEM::ID) -> Result<Vec<u8>> {
+        ensure_valid_id::<ITEM>(id)?;
+        let mut log = self.log.lock().await;
+        let log = self.opened(&mut log)?;
+        let Some(data) = log.read_data(&id.to_string()).await? else {
+            return Err(StorageError::NotFound { id: id.to_string() }.into());
+        };
+        self.update_highest_seen_id(id);
+
+        Ok(data)
+    }
+
+    async fn write_data(&self, id: &ITEM::ID, data: Vec<u8>, lock: &StorageLock) -> Result<()> {
+        ensure_valid_id::<ITEM>(id)?;
+        let mut log = self.log.lock().await;
+        let log = self.opened(&mut log)?;
+        let key = id.to_string();
+        let Some(entry) = log
+            .index
+            .get(&key)
+            .filter(|e| e.lock.as_ref() == Some(lock))
+        else {
+            let current = log.index.get(&key).and_then(|e| e.lock.as_ref());
+            // the log doesn't keep save times
+            return Err(
+                StorageError::lock_mismatch(&key, lock, current, None, self.clock.now()).into(),
+            );
+        };
+        if self.immutable && entry.data.is_some() {
+            return Err(StorageError::Immutable { id: key }.into());
+        }
+        let previous_len = entry.data.map(|(_, len)| len as u64);
+        log.append(RECORD_SAVE, &key, &data, self.durability)
+            .await
+            .map_err(|e| eyre!("Can't save {id} to {:?}: {e:?}", &self.path))?;
+        self.update_highest_seen_id(id);
+        self.record_save(previous_len, data.len() as u64);
+        self.compact_if_needed(log).await
+    }
+}
+
+#[async_trait]
+impl<ITEM: StorageItem + std::marker::Send> Storage<ITEM> for StorageDiskPacked<ITEM> {
+    #[tracing::instrument(
+        name = "storage.ensure_storage_exists",
+        skip_all,
+        fields(
+            backend = "disk_packed",
+            db.system = "disk_packed",
+            db.operation = "ensure_storage_exists",
+        )
+    )]
+    async fn ensure_storage_exists(&mut self) -> Result<()> {
+        let _slow_op = SlowOp::start(
+            self.slow_op_threshold,
+            "disk_packed",
+            "ensure_storage_exists",
+            None,
+        );
+        if let Some(folder) = self.path.parent() {
+            fs::create_dir_all(folder)
+                .await
+                .map_err(|e| eyre!("Could not create folder {folder:?} -> {e}"))?;
+        }
+        let log = PackedLog::open(&self.path).await?;
+        tracing::info!(
+            "Opened {:?} with {} entries, {} bytes",
+            &self.path,
+            log.index.len(),
+            log.len
+        );
+        // the log already has every id, so there is no need to persist metadata separately
+        for id in log.index.keys() {
+            self.update_highest_seen_id(&ITEM::ID::from_string(id)?);
+        }
+        let saved = log.index.values().filter_map(|e| e.data);
+        let (items, bytes) = saved.fold((0, 0), |(items, bytes), (_, len)| {
+            (items + 1, bytes + len as u64)
+        });
+        self.record_totals(items, Some(bytes));
+        *self.log.get_mut() = Some(log);
+        if let Some(id) = &self.probe_id {
+            let hint = format!("the permissions of {:?}", &self.path);
+            probe_storage(&*self, id, &hint).await?;
+        }
+
+        Ok(())
+    }
+
+    fn capabilities(&self) -> crate::Capabilities {
+        crate::Capabilities {
+            scan: true,
+            list: true,
+            meta: true,
+            remove_orphaned_locks: true,
+            consistent_reads: true,
+            ..Default::default()
+        }
+    }
+    #[tracing::instrument(
+        name = "storage.create",
+        skip_all,
+        fields(backend = "disk_packed", db.system = "disk_packed", db.operation = "create")
+    )]
+    async fn create(&self) -> Result<ITEM::ID> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk_packed", "create", None);
+        // collisions are passed on, for id types that derive the next id from them
+        let mut previous = None;
+        for _ in 0..self.create_tries {
+            let id = self.id_generator.generate(previous.as_ref());
+            if !self.exists(&id).await? {
+                return Ok(id);
+            }
+            previous = Some(id);
+        }
+
+        Err(StorageError::IdExhausted {
+            tries: self.create_tries,
+        }
+        .into())
+    }
+    #[tracing::instrument(
+        name = "storage.exists",
+        skip_all,
+        fields(
+            backend = "disk_packed",
+            db.system = "disk_packed",
+            db.operation = "exists",
+            id = %id,
+        )
+    )]
+    async fn exists(&self, id: &ITEM::ID) -> Result<bool> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk_packed", "exists", Some(id));
+        ensure_valid_id::<ITEM>(id)?;
+        let mut log = self.log.lock().await;
+        let log = self.opened(&mut log)?;
+        // locked, but unsaved items exist, too
+        let now = self.clock.now();
+        if log.index.get(&id.to_string()).is_some_and(|e| {
+            e.data.is_some() || self.exists_policy.lock_only_exists(e.lock.as_ref(), now)
+        }) {
+            self.update_highest_seen_id(id);
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    #[tracing::instrument(
+        name = "storage.load",
+        skip_all,
+        fields(backend = "disk_packed", db.system = "disk_packed", db.operation = "load", id = %id)
+    )]
+    async fn load(&self, id: &ITEM::ID) -> Result<ITEM> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk_packed", "load", Some(id));
+        let data = self.read_data(id).await?;
+        deserialize_stored::<ITEM>(id, &data)
+    }
+
+    #[tracing::instrument(
+        name = "storage.load_raw",
+        skip_all,
+        fields(
+            backend = "disk_packed",
+            db.system = "disk_packed",
+            db.operation = "load_raw",
+            id = %id,
+        )
+    )]
+    async fn load_raw(&self, id: &ITEM::ID) -> Result<Vec<u8>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk_packed", "load_raw", Some(id));
+        self.read_data(id).await
+    }
+
+    #[tracing::instrument(
+        name = "storage.save",
+        skip_all,
+        fields(backend = "disk_packed", db.system = "disk_packed", db.operation = "save", id = %id)
+    )]
+    async fn save(&self, id: &ITEM::ID, item: &ITEM, lock: &StorageLock) -> Result<()> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk_packed", "save", Some(id));
+        self.write_data(id, serialize_valid(id, item)?, lock).await
+    }
+
+    #[tracing::instrument(
+        name = "storage.save_raw",
+        skip_all,
+        fields(
+            backend = "disk_packed",
+            db.system = "disk_packed",
+            db.operation = "save_raw",
+            id = %id,
+        )
+    )]
+    async fn save_raw(&self, id: &ITEM::ID, data: &[u8], lock: &StorageLock) -> Result<()> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk_packed", "save_raw", Some(id));
+        self.write_data(id, data.to_vec(), lock).await
+    }
+    #[tracing::instrument(
+        name = "storage.lock",
+        skip_all,
+        fields(
+            backend = "disk_packed",
+            db.system = "disk_packed",
+            db.operation = "lock",
+            id = %id,
+            who = %who,
+        )
+    )]
+    async fn lock(&self, id: &ITEM::ID, who: &str) -> Result<LockResult<ITEM>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk_packed", "lock", Some(id));
+        ensure_valid_id::<ITEM>(id)?;
+        let mut log = self.log.lock().await;
+        let log = self.opened(&mut log)?;
+        let key = id.to_string();
+        self.update_highest_seen_id(id);
+        if let Some(lock) = log.index.get(&key).and_then(|e| e.lock.as_ref()) {
+            tracing::warn!("{id} already locked by {}", lock.who());
+            self.record_lock_contended();
+            return Ok(LockResult::already_locked(lock));
+        }
+
+        // corrupt items aren't locked, saving a default over them would lose the data
+        let item = match log.read_data(&key).await? {
+            Some(data) => deserialize_stored::<ITEM>(id, &data)?,
+            None => ITEM::default(),
+        };
+        let lock = StorageLock::new_at(who, self.clock.now());
+        let lock_json = serde_json::to_vec(&lock)?;
+        log.append(RECORD_LOCK, &key, &lock_json, self.durability)
+            .await
+            .map_err(|e| eyre!("Can't lock {id} for {who}: {e:?}"))?;
+
+        self.record_lock_acquired(id, &lock);
+        Ok(LockResult::Success { lock, item })
+    }
+
+    #[tracing::instrument(
+        name = "storage.unlock",
+        skip_all,
+        fields(
+            backend = "disk_packed",
+            db.system = "disk_packed",
+            db.operation = "unlock",
+            id = %id,
+        )
+    )]
+    async fn unlock(&self, id: &ITEM::ID, lock: StorageLock) -> Result<()> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk_packed", "unlock", Some(id));
+        let mut log = self.log.lock().await;
+        let log = self.opened(&mut log)?;
+        let key = id.to_string();
+        if log.index.get(&key).and_then(|e| e.lock.as_ref()) != Some(&lock) {
+            return Err(eyre!("Lock invalid!"));
+        }
+        log.append(RECORD_UNLOCK, &key, &[], self.durability)
+            .await
+            .map_err(|e| eyre!("Can't unlock {id}: {e:?}"))?;
+        self.record_lock_released(id, &lock);
+        self.compact_if_needed(log).await
+    }
+
+    /// Appends a lock record for `new_who`, which replaces the current lock when replayed.
+    #[tracing::instrument(
+        name = "storage.handoff_lock",
+        skip_all,
+        fields(
+            backend = "disk_packed",
+            db.system = "disk_packed",
+            db.operation = "handoff_lock",
+            id = %id,
+            who = %new_who,
+        )
+    )]
+    async fn handoff_lock(
+        &self,
+        id: &ITEM::ID,
+        lock: StorageLock,
+        new_who: &str,
+    ) -> Result<StorageLock> {
+        let _slow_op = SlowOp::start(
+            self.slow_op_threshold,
+            "disk_packed",
+            "handoff_lock",
+            Some(id),
+        );
+        let mut log = self.log.lock().await;
+        let log = self.opened(&mut log)?;
+        let key = id.to_string();
+        if log.index.get(&key).and_then(|e| e.lock.as_ref()) != Some(&lock) {
+            return Err(eyre!("Lock invalid!"));
+        }
+        let new_lock = StorageLock::new_at(new_who, self.clock.now());
+        let lock_json = serde_json::to_vec(&new_lock)?;
+        log.append(RECORD_LOCK, &key, &lock_json, self.durability)
+            .await
+            .map_err(|e| eyre!("Can't hand off {id} to {new_who}: {e:?}"))?;
+        self.record_lock_released(id, &lock);
+        self.record_lock_acquired(id, &new_lock);
+        self.compact_if_needed(log).await?;
+
+        Ok(new_lock)
+    }
+
+    #[tracing::instrument(
+        name = "storage.put_meta",
+        skip_all,
+        fields(
+            backend = "disk_packed",
+            db.system = "disk_packed",
+            db.operation = "put_meta",
+            key = %key,
+        )
+    )]
+    async fn put_meta(&self, key: &str, value: &[u8]) -> Result<()> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk_packed", "put_meta", None);
+        ensure_valid_meta_key(key)?;
+        let mut log = self.log.lock().await;
+        let log = self.opened(&mut log)?;
+        log.append(RECORD_META, key, value, self.durability)
+            .await
+            .map_err(|e| eyre!("Can't save meta value {key}: {e:?}"))
+    }
+
+    #[tracing::instrument(
+        name = "storage.get_meta",
+        skip_all,
+        fields(
+            backend = "disk_packed",
+            db.system = "disk_packed",
+            db.operation = "get_meta",
+            key = %key,
+        )
+    )]
+    async fn get_meta(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        ensure_valid_meta_key(key)?;
+        let mut log = self.log.lock().await;
+        let log = self.opened(&mut log)?;
+        Ok(log.meta.get(key).map(|(value, _)| value.clone()))
+    }
+
+    #[tracing::instrument(
+        name = "storage.force_unlock",
+        skip_all,
+        fields(
+            backend = "disk_packed",
+            db.system = "disk_packed",
+            db.operation = "force_unlock",
+            id = %id,
+        )
+    )]
+    async fn force_unlock(&self, id: &ITEM::ID) -> Result<Option<StorageLock>> {
+        let _slow_op = SlowOp::start(
+            self.slow_op_threshold,
+            "disk_packed",
+            "force_unlock",
+            Some(id),
+        );
+        let mut log = self.log.lock().await;
+        let log = self.opened(&mut log)?;
+        let key = id.to_string();
+        let Some(lock) = log.index.get(&key).and_then(|e| e.lock.as_ref()) else {
+            tracing::warn!("{id} isn't locked");
+            return Ok(None);
+        };
+        let lock = lock.duplicate();
+        log.append(RECORD_UNLOCK, &key, &[], self.durability)
+            .await
+            .map_err(|e| eyre!("Can't force unlock {id}: {e:?}"))?;
+        Ok(Some(lock))
+    }
+    #[tracing::instrument(
+        name = "storage.force_unlock_if_held",
+        skip_all,
+        fields(
+            backend = "disk_packed",
+            db.system = "disk_packed",
+            db.operation = "force_unlock_if_held",
+            id = %id,
+            who = %who,
+        )
+    )]
+    async fn force_unlock_if_held(
+        &self,
+        id: &ITEM::ID,
+        who: &str,
+        when: DateTime<Utc>,
+    ) -> Result<bool> {
+        let _slow_op = SlowOp::start(
+            self.slow_op_threshold,
+            "disk_packed",
+            "force_unlock_if_held",
+            Some(id),
+        );
+        let mut log = self.log.lock().await;
+        let log = self.opened(&mut log)?;
+        let key = id.to_string();
+        let held = log
+            .index
+            .get(&key)
+            .and_then(|e| e.lock.as_ref())
+            .is_some_and(|lock| lock.is_held_by(who, when));
+        if !held {
+            return Ok(false);
+        }
+        log.append(RECORD_UNLOCK, &key, &[], self.durability)
+            .await
+            .map_err(|e| eyre!("Can't force unlock {id}: {e:?}"))?;
+        Ok(true)
+    }
+    #[tracing::instrument(
+        name = "storage.remove_orphaned_locks",
+        skip_all,
+        fields(
+            backend = "disk_packed",
+            db.system = "disk_packed",
+            db.operation = "remove_orphaned_locks",
+        )
+    )]
+    async fn remove_orphaned_locks(&self, max_age: Duration) -> Result<usize> {
+        let now = self.clock.now();
+        let mut log = self.log.lock().await;
+        let log = self.opened(&mut log)?;
+        let orphans: Vec<String> = log
+            .index
+            .iter()
+            .filter(|(_, e)| {
+                e.data.is_none()
+                    && e.lock
+                        .as_ref()
+                        .is_some_and(|lock| is_orphan(lock, max_age, now))
+            })
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in orphans.iter() {
+            tracing::info!("Removing orphaned lock of {id}");
+            log.append(RECORD_UNLOCK, id, &[], self.durability)
+                .await
+                .map_err(|e| eyre!("Can't remove orphaned lock of {id}: {e:?}"))?;
+        }
+
+        Ok(orphans.len())
+    }
+    #[tracing::instrument(
+        name = "storage.verify_lock",
+        skip_all,
+        fields(
+            backend = "disk_packed",
+            db.system = "disk_packed",
+            db.operation = "verify_lock",
+            id = %id,
+        )
+    )]
+    async fn verify_lock(&self, id: &ITEM::ID, lock: &StorageLock) -> Result<bool> {
+        let _slow_op = SlowOp::start(
+            self.slow_op_threshold,
+            "disk_packed",
+            "verify_lock",
+            Some(id),
+        );
+        let mut log = self.log.lock().await;
+        let log = self.opened(&mut log)?;
+        match log.index.get(&id.to_string()).and_then(|e| e.lock.as_ref()) {
+            Some(expected_lock) if expected_lock == lock => Ok(true),
+            expected_lock => {
+                tracing::warn!("Lock mismatch for {id} {lock:?} != {expected_lock:?}");
+                Ok(false)
+            }
+        }
+    }
+    #[tracing::instrument(
+        name = "storage.all_ids",
+        skip_all,
+        fields(backend = "disk_packed", db.system = "disk_packed", db.operation = "all_ids")
+    )]
+    async fn all_ids(&self) -> Result<Vec<ITEM::ID>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk_packed", "all_ids", None);
+        let mut log = self.log.lock().await;
+        let log = self.opened(&mut log)?;
+        let ids = log
+            .index
+            .iter()
+            .filter(|(_, e)| e.data.is_some())
+            .map(|(id, _)| ITEM::ID::from_string(id))
+            .collect::<Result<Vec<_>>>()?;
+        for id in ids.iter() {
+            self.update_highest_seen_id(id);
+        }
+        Ok(ids)
+    }
+    /// Scans ids in sorted order, the scan position is the last returned id.
+    #[tracing::instrument(
+        name = "storage.scan_ids",
+        skip_all,
+        fields(backend = "disk_packed", db.system = "disk_packed", db.operation = "scan_ids")
+    )]
+    async fn scan_ids(&self, start: Option<&str>, limit: Option<usize>) -> Result<Page<ITEM::ID>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk_packed", "scan_ids", None);
+        self.scan_ids_with_prefix("", start, limit).await
+    }
+    #[tracing::instrument(
+        name = "storage.scan_ids_with_prefix",
+        skip_all,
+        fields(
+            backend = "disk_packed",
+            db.system = "disk_packed",
+            db.operation = "scan_ids_with_prefix",
+            prefix = %prefix,
+        )
+    )]
+    async fn scan_ids_with_prefix(
+        &self,
+        prefix: &str,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        let _slow_op = SlowOp::start(
+            self.slow_op_threshold,
+            "disk_packed",
+            "scan_ids_with_prefix",
+            None,
+        );
+        self.scan_ids_filtered(&crate::IdFilter::Prefix(prefix.to_string()), start, limit)
+            .await
+    }
+    #[tracing::instrument(
+        name = "storage.scan_ids_filtered",
+        skip_all,
+        fields(
+            backend = "disk_packed",
+            db.system = "disk_packed",
+            db.operation = "scan_ids_filtered",
+        )
+    )]
+    async fn scan_ids_filtered(
+        &self,
+        filter: &crate::IdFilter,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        if matches!(filter, crate::IdFilter::ModifiedBefore(_)) {
+            return Err(eyre!(
+                "StorageDiskPacked doesn't track when items were saved"
+            ));
+        }
+        let mut names: Vec<String> = {
+            let mut log = self.log.lock().await;
+            let log = self.opened(&mut log)?;
+            log.index
+                .iter()
+                .filter(|(id, e)| e.data.is_some() && filter.matches(id, None))
+                .map(|(id, _)| id.clone())
+                .collect()
+        };
+        let total = names.len() as u64;
+        names.retain(|id| start.is_none_or(|s| id.as_str() > s));
+        names.sort_unstable();
+
+        let scan_pos = match limit {
+            Some(limit) if names.len() > limit => {
+                names.truncate(limit);
+                names.last().cloned().or(start.map(str::to_string))
+            }
+            _ => None,
+        };
+        let ids = names
+            .iter()
+            .map(|id| ITEM::ID::from_string(id))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Page::new(ids, scan_pos).with_approximate_total(total))
+    }
+
+    /// Save times are not tracked, so `modified` is always `None`.
+    #[tracing::instrument(
+        name = "storage.list",
+        skip_all,
+        fields(backend = "disk_packed", db.system = "disk_packed", db.operation = "list")
+    )]
+    async fn list(
+        &self,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ItemSummary<ITEM::ID>>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk_packed", "list", None);
+        let mut log = self.log.lock().await;
+        let log = self.opened(&mut log)?;
+        let mut rows: Vec<(&String, &IndexEntry)> =
+            log.index.iter().filter(|(_, e)| e.data.is_some()).collect();
+        let total = rows.len() as u64;
+        rows.retain(|(id, _)| start.is_none_or(|s| id.as_str() > s));
+        rows.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        let scan_pos = match limit {
+            Some(limit) if rows.len() > limit => {
+                rows.truncate(limit);
+                rows.last()
+                    .map(|(id, _)| id.to_string())
+                    .or(start.map(str::to_string))
+            }
+            _ => None,
+        };
+        let summaries = rows
+            .into_iter()
+            .map(|(id, e)| {
+                let mut summary = ItemSummary::new(ITEM::ID::from_string(id)?);
+                summary.size = e.data.map(|(_, len)| len as u64);
+                Ok(summary.with_lock(e.lock.as_ref()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Page::new(summaries, scan_pos).with_approximate_total(total))
+    }
+
+    /// Read from the index, without touching the log.
+    #[tracing::instrument(
+        name = "storage.item_size",
+        skip_all,
+        fields(
+            backend = "disk_packed",
+            db.system = "disk_packed",
+            db.operation = "item_size",
+            id = %id,
+        )
+    )]
+    async fn item_size(&self, id: &ITEM::ID) -> Result<u64> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk_packed", "item_size", Some(id));
+        ensure_valid_id::<ITEM>(id)?;
+        let mut log = self.log.lock().await;
+        let log = self.opened(&mut log)?;
+        log.index
+            .get(&id.to_string())
+            .and_then(|e| e.data)
+            .map(|(_, len)| len as u64)
+            .ok_or_else(|| StorageError::NotFound { id: id.to_string() }.into())
+    }
+
+    #[tracing::instrument(
+        name = "storage.display_lock",
+        skip_all,
+        fields(
+            backend = "disk_packed",
+            db.system = "disk_packed",
+            db.operation = "display_lock",
+            id = %id,
+        )
+    )]
+    async fn display_lock(&self, id: &ITEM::ID) -> Result<String> {
+        let _slow_op = SlowOp::start(
+            self.slow_op_threshold,
+            "disk_packed",
+            "display_lock",
+            Some(id),
+        );
+        let mut log = self.log.lock().await;
+        let log = self.opened(&mut log)?;
+        match log.index.get(&id.to_string()).and_then(|e| e.lock.as_ref()) {
+            Some(lock) => Ok(format!("Locked by {} at {:?}", lock.who(), lock.when())),
+            None => Ok(String::default()),
+        }
+    }
+    #[cfg(feature = "metadata")]
+    async fn metadata_highest_seen_id(&self) -> Option<ITEM::ID> {
+        self.metadata.highest_seen_id()
+    }
+    #[cfg(feature = "metadata")]
+    async fn metadata_item_count(&self) -> Option<u64> {
+        self.metadata.item_count()
+    }
+    #[cfg(feature = "metadata")]
+    async fn metadata_total_bytes(&self) -> Option<u64> {
+        self.metadata.total_bytes()
+    }
+    #[cfg(feature = "metadata")]
+    async fn metadata_last_write(&self) -> Option<DateTime<Utc>> {
+        self.metadata.last_write()
+    }
+    #[cfg(feature = "metadata")]
+    async fn metadata_lock_stats(&self) -> crate::LockStats {
+        self.metadata.lock_stats()
+    }
+
+    #[cfg(feature = "wipe")]
+    #[tracing::instrument(
+        name = "storage.wipe",
+        skip_all,
+        fields(backend = "disk_packed", db.system = "disk_packed", db.operation = "wipe")
+    )]
+    async fn wipe(
+        &self,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk_packed", "wipe", None);
+        options.ensure_confirmed()?;
+
+        let mut log = self.log.lock().await;
+        let log = self.opened(&mut log)?;
+        let mut report = crate::WipeReport::new(options);
+        let mut tracker = crate::ProgressTracker::new(progress, Some(log.index.len() as u64))
+            .with_cancellation(cancel);
+        // the log is truncated at once, after counting
+        for id in log.index.keys() {
+            tracker.ensure_not_cancelled()?;
+            report.record(id);
+            tracker.advance(id);
+        }
+        if !options.is_dry_run() {
+            tracing::warn!("Wiping {} items.", log.index.len());
+            log.file.set_len(0).await?;
+            log.len = 0;
+            log.live_len = 0;
+            log.index.clear();
+            self.record_totals(0, Some(0));
+        }
+        tracker.finish();
+
+        Ok(report)
+    }
+
+    #[cfg(feature = "wipe")]
+    #[tracing::instrument(
+        name = "storage.wipe_matching",
+        skip_all,
+        fields(backend = "disk_packed", db.system = "disk_packed", db.operation = "wipe_matching")
+    )]
+    async fn wipe_matching(
+        &self,
+        filter: &crate::IdFilter,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk_packed", "wipe_matching", None);
+        if matches!(filter, crate::IdFilter::ModifiedBefore(_)) {
+            return Err(eyre!(
+                "StorageDiskPacked doesn't track when items were saved"
+            ));
+        }
+        options.ensure_confirmed()?;
+
+        let mut log = self.log.lock().await;
+        let log = self.opened(&mut log)?;
+        let mut matching: Vec<(String, Option<usize>)> = log
+            .index
+            .iter()
+            .filter(|(id, _)| filter.matches(id, None))
+            .map(|(id, e)| (id.clone(), e.data.map(|(_, len)| len)))
+            .collect();
+        matching.sort_unstable();
+        let mut report = crate::WipeReport::new(options);
+        let mut tracker = crate::ProgressTracker::new(progress, Some(matching.len() as u64))
+            .with_cancellation(cancel);
+        for (id, len) in matching {
+            if let Err(e) = tracker.ensure_not_cancelled() {
+                // the deletes so far are in the log already
+                self.compact_if_needed(log).await?;
+                return Err(e);
+            }
+            report.record(&id);
+            tracker.advance(&id);
+            if options.is_dry_run() {
+                continue;
+            }
+            log.append(RECORD_DELETE, &id, &[], self.durability)
+                .await
+                .map_err(|e| eyre!("Can't remove {id} from {:?}: {e:?}", &self.path))?;
+            if let Some(len) = len {
+                self.record_remove(len as u64);
+            }
+        }
+        tracker.finish();
+        if report.dry_run {
+            return Ok(report);
+        }
+        tracing::warn!("Wiped {} matching items.", report.count);
+        self.compact_if_needed(log).await?;
+
+        Ok(report)
+    }
+
+    #[cfg(feature = "wipe")]
+    #[tracing::instrument(
+        name = "storage.delete_many",
+        skip_all,
+        fields(backend = "disk_packed", db.system = "disk_packed", db.operation = "delete_many")
+    )]
+    async fn delete_many(
+        &self,
+        ids: &[ITEM::ID],
+        options: &crate::DeleteOptions,
+    ) -> Result<Vec<crate::DeleteOutcome>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "disk_packed", "delete_many", None);
+        for id in ids {
+            ensure_valid_id::<ITEM>(id)?;
+        }
+
+        let mut log = self.log.lock().await;
+        let log = self.opened(&mut log)?;
+        let mut outcomes = Vec::with_capacity(ids.len());
+        for id in ids {
+            let id = id.to_string();
+            let Some(entry) = log.index.get(&id) else {
+                outcomes.push(crate::DeleteOutcome::NotFound);
+                continue;
+            };
+            if let Some(outcome) = options.blocked_by(entry.lock.as_ref()) {
+                outcomes.push(outcome);
+                continue;
+            }
+            let len = entry.data.map(|(_, len)| len);
+            log.append(RECORD_DELETE, &id, &[], self.durability)
+                .await
+                .map_err(|e| eyre!("Can't remove {id} from {:?}: {e:?}", &self.path))?;
+            match len {
+                Some(len) => {
+                    self.record_remove(len as u64);
+                    outcomes.push(crate::DeleteOutcome::Deleted);
+                }
+                None => outcomes.push(crate::DeleteOutcome::NotFound),
+            }
+        }
+        self.compact_if_needed(log).await?;
+
+        Ok(outcomes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Storage;
+    use crate::StorageDiskPacked;
+    use crate::StorageError;
+    use crate::StorageItem;
+    use color_eyre::Result;
+    use serde::Deserialize;
+    use serde::Serialize;
+    use std::env;
+
+    #[derive(Default, Debug, Serialize, Deserialize, PartialEq)]
+    struct TestItem {
+        count: u32,
+    }
+
+    impl StorageItem for TestItem {
+        type ID = String;
+
+        fn serialize(&self) -> Result<Vec<u8>> {
+            let json = serde_json::to_string_pretty(&self)?;
+
+            Ok(json.into())
+        }
+        fn deserialize(data: &[u8]) -> Result<Self> {
+            let i = serde_json::from_slice(data)?;
+
+            Ok(i)
+        }
+    }
+
+    #[tokio::test]
+    async fn it_reopens_and_compacts() -> Result<()> {
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_packed");
+        path.push("reopen.log");
+        let _ = std::fs::remove_file(&path);
+
+        let mut storage = StorageDiskPacked::<TestItem>::new(&path).await;
+        storage.ensure_storage_exists().await?;
+
+        let us = "TEST";
+
+        let item_id = storage.create().await?;
+        for count in 0..10 {
+            let (lock, _) = storage.lock(&item_id, us).await?.success()?;
+            storage.save(&item_id, &TestItem { count }, &lock).await?;
+            storage.unlock(&item_id, lock).await?;
+        }
+        let locked_id = storage.create().await?;
+        let (lock, _) = storage.lock(&locked_id, us).await?.success()?;
+
+        let (len, garbage_len) = storage.log_len().await?;
+        assert!(garbage_len > 0);
+        storage.compact().await?;
+        let (compacted_len, garbage_len) = storage.log_len().await?;
+        assert!(compacted_len < len);
+        assert_eq!(0, garbage_len);
+
+        // simulate a crash during append
+        let mut data = std::fs::read(&path)?;
+        data.extend_from_slice(&[1, 200, 0]);
+        std::fs::write(&path, data)?;
+
+        let mut storage = StorageDiskPacked::<TestItem>::new(&path).await;
+        storage.ensure_storage_exists().await?;
+        assert_eq!(TestItem { count: 9 }, storage.load(&item_id).await?);
+        assert_eq!(vec![item_id], storage.all_ids().await?);
+        assert!(storage.exists(&locked_id).await?);
+        assert!(storage.verify_lock(&locked_id, &lock).await?);
+        assert_eq!(compacted_len, storage.log_len().await?.0);
+
+        Ok(())
+    }
+    #[tokio::test]
+    async fn it_refuses_to_lock_corrupt_items() -> Result<()> {
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_packed");
+        path.push("corrupt.log");
+        let _ = std::fs::remove_file(&path);
+
+        let mut storage = StorageDiskPacked::<TestItem>::new(&path).await;
+        storage.ensure_storage_exists().await?;
+        let id = String::from("corrupt");
+        let (lock, _) = storage.lock(&id, "TEST").await?.success()?;
+        storage.save_raw(&id, b"{ not json", &lock).await?;
+        storage.unlock(&id, lock).await?;
+
+        let e = storage.lock(&id, "TEST").await.err();
+        assert!(matches!(
+            e.as_ref().and_then(|e| e.downcast_ref()),
+            Some(StorageError::Corrupt { .. })
+        ));
+        assert!(storage.force_unlock(&id).await?.is_none());
+        assert_eq!(b"{ not json".to_vec(), storage.load_raw(&id).await?);
+
+        Ok(())
+    }
+}
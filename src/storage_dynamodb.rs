@@ -1,31 +1,492 @@
+use crate::dynamodb_key_hasher::KEY_SHARD_SEPARATOR;
+use crate::dynamodb_scan_throttle::ScanPacers;
+use crate::exists_policy::is_orphan;
+use crate::links::ensure_valid_relation;
+use crate::operation_metrics;
+use crate::slow_op::SlowOp;
+use crate::storage::ensure_valid_create_tries;
+use crate::storage::ensure_valid_meta_key;
+use crate::storage::probe_storage;
+use crate::storage::DEFAULT_CREATE_TRIES;
+use crate::storage::LOCK_MANY_CONCURRENCY;
+use crate::storage_item::corrupt;
+use crate::storage_item::deserialize_stored;
+use crate::storage_item::ensure_valid_id;
+use crate::storage_item::serialize_valid;
+use crate::Clock;
+use crate::DefaultIdGenerator;
+use crate::DynamoDbErrorClass;
+use crate::DynamoDbKeyHasher;
+use crate::DynamoDbRetryPolicy;
+use crate::DynamoDbScanThrottle;
+use crate::IdAllocator;
+use crate::IdCodec;
+use crate::IdCounter;
+use crate::IdFilter;
+use crate::IdGenerator;
+use crate::ItemSummary;
+use crate::LockManyOutcome;
 use crate::LockResult;
 #[cfg(feature = "metadata")]
 use crate::Metadata;
+use crate::OpOptions;
+use crate::Page;
+use crate::Redacted;
 use crate::Storage;
+use crate::StorageError;
+use crate::StorageId;
 use crate::StorageItem;
 use crate::StorageLock;
+use crate::StorageOperation;
+use crate::SystemClock;
 use async_trait::async_trait;
+use aws_config::sts::AssumeRoleProvider;
+use aws_config::Region;
+use aws_config::SdkConfig;
+use aws_sdk_dynamodb::config::retry::RetryConfig;
+use aws_sdk_dynamodb::config::Credentials;
+use aws_sdk_dynamodb::config::SharedCredentialsProvider;
+use aws_sdk_dynamodb::error::ProvideErrorMetadata;
 use aws_sdk_dynamodb::error::SdkError;
+#[cfg(feature = "wipe")]
+use aws_sdk_dynamodb::operation::delete_item::DeleteItemError;
 use aws_sdk_dynamodb::operation::describe_table::DescribeTableError::ResourceNotFoundException;
 use aws_sdk_dynamodb::operation::get_item::GetItemOutput;
 use aws_sdk_dynamodb::operation::scan::ScanOutput;
+use aws_sdk_dynamodb::operation::transact_write_items::TransactWriteItemsError;
+use aws_sdk_dynamodb::operation::update_item::UpdateItemError;
 use aws_sdk_dynamodb::operation::update_item::UpdateItemOutput;
+use aws_sdk_dynamodb::primitives::Blob;
 use aws_sdk_dynamodb::types::AttributeDefinition;
 use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::types::ConditionCheck;
+#[cfg(feature = "wipe")]
+use aws_sdk_dynamodb::types::DeleteRequest;
 use aws_sdk_dynamodb::types::KeySchemaElement;
 use aws_sdk_dynamodb::types::KeyType;
+use aws_sdk_dynamodb::types::KeysAndAttributes;
 use aws_sdk_dynamodb::types::ProvisionedThroughput;
+use aws_sdk_dynamodb::types::Put;
+use aws_sdk_dynamodb::types::ReturnConsumedCapacity;
 use aws_sdk_dynamodb::types::ReturnValue;
+use aws_sdk_dynamodb::types::ReturnValuesOnConditionCheckFailure;
 use aws_sdk_dynamodb::types::ScalarAttributeType;
+use aws_sdk_dynamodb::types::TableDescription;
+use aws_sdk_dynamodb::types::TableStatus;
+use aws_sdk_dynamodb::types::TimeToLiveSpecification;
+use aws_sdk_dynamodb::types::TimeToLiveStatus;
+use aws_sdk_dynamodb::types::TransactWriteItem;
+use aws_sdk_dynamodb::types::Update;
+#[cfg(feature = "wipe")]
+use aws_sdk_dynamodb::types::WriteRequest;
+use chrono::DateTime;
+use chrono::Utc;
 use color_eyre::eyre::eyre;
+use color_eyre::eyre::Report;
 use color_eyre::eyre::Result;
+use futures_util::future::join_all;
 
 use core::marker::PhantomData;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// DynamoDB accepts at most this many keys per `BatchGetItem`
+const BATCH_GET_ITEM_LIMIT: usize = 100;
+/// DynamoDB accepts at most this many requests per `BatchWriteItem`
+#[cfg(feature = "wipe")]
+const BATCH_WRITE_ITEM_LIMIT: usize = 25;
+/// DynamoDB accepts at most this many items per `TransactWriteItems`
+const TRANSACT_WRITE_ITEMS_LIMIT: usize = 100;
+/// DynamoDB rejects items larger than this, including attribute names
+const ITEM_SIZE_LIMIT: usize = 400 * 1024;
+/// How often unprocessed keys/items are retried before giving up
+const BATCH_MAX_RETRIES: u32 = 8;
+/// Items evaluated per scan page while wiping, DynamoDB also ends pages at 1MB
+#[cfg(feature = "wipe")]
+const WIPE_SCAN_LIMIT: usize = 1000;
+/// Conditional `DeleteItem` requests [Storage::delete_many] sends in parallel
+#[cfg(feature = "wipe")]
+const DELETE_CONCURRENCY: usize = 16;
+
+/// How the serialized item is stored in the `data` attribute.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DynamoDbDataFormat {
+    /// The serialized item is stored as a string. This is the default.
+    #[default]
+    String,
+    /// The serialized item is parsed as JSON and stored as a native DynamoDB map.
+    ///
+    /// This enables projection expressions, per-field updates, and GSIs on item fields.
+    /// Requires [StorageItem::serialize] to produce JSON.
+    Map,
+}
+
+/// The attribute names of the items table, e.g. to use an existing table, see [StorageDynamoDb::set_attribute_names].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DynamoDbAttributeNames {
+    /// The string hash key, `id` by default
+    pub id: String,
+    /// `lock` by default
+    pub lock: String,
+    /// `data` by default
+    pub data: String,
+    /// For tables shared with other entities, the type attribute and the value for this storage, e.g. `("entity", "player")`.
+    ///
+    /// Saved items get the attribute, and scans skip items without it.
+    pub item_type: Option<(String, String)>,
+}
+
+impl Default for DynamoDbAttributeNames {
+    fn default() -> Self {
+        Self {
+            id: String::from("id"),
+            lock: String::from("lock"),
+            data: String::from("data"),
+            item_type: None,
+        }
+    }
+}
+
+impl DynamoDbAttributeNames {
+    fn ensure_valid(&self) -> Result<()> {
+        let mut names = vec![self.id.as_str(), self.lock.as_str(), self.data.as_str()];
+        if let Some((attribute, _)) = &self.item_type {
+            names.push(attribute.as_str());
+        }
+        if names.iter().any(|name| name.is_empty()) {
+            return Err(eyre!("Attribute names must not be empty {names:?}"));
+        }
+        let mut unique = names.clone();
+        unique.sort_unstable();
+        unique.dedup();
+        if unique.len() != names.len() {
+            return Err(eyre!("Attribute names must be different {names:?}"));
+        }
+
+        Ok(())
+    }
+}
+
+/// Name of the numeric attribute used for DynamoDB's time to live feature
+const TTL_ATTRIBUTE: &str = "expires_at";
+/// Name of the numeric attribute holding when the item was last saved, in seconds since the epoch
+const MODIFIED_AT_ATTRIBUTE: &str = "modified_at";
+/// Name of the string attribute holding the token of the current lock, next to `lock`.
+/// Conditions compare the token, not the serialized lock
+const LOCK_TOKEN_ATTRIBUTE: &str = "lock_token";
+/// Blobs are stored as binary attributes with this prefix, next to `data`
+const BLOB_ATTRIBUTE_PREFIX: &str = "blob_";
+/// Links are stored as string set attributes with this prefix, one per relation
+const LINK_ATTRIBUTE_PREFIX: &str = "link_";
+/// Counters are stored in the items table, under ids with this prefix
+const COUNTER_ID_PREFIX: &str = "#counter#";
+/// Alias items map `#alias#{alias}` to the canonical id
+const ALIAS_ID_PREFIX: &str = "#alias#";
+/// The item holding the persisted metadata
+const METADATA_ID: &str = "#metadata#";
+/// Meta values are stored in the items table, under ids with this prefix, see [Storage::put_meta]
+const META_ID_PREFIX: &str = "#meta#";
+/// Ends every key prefix, so prefixes never start other prefixes, see [StorageDynamoDb::set_key_prefix]
+const KEY_PREFIX_SEPARATOR: char = '#';
+
+/// An [IdCounter] stored in the items table, see [StorageDynamoDb::id_counter].
+///
+/// Reserving is a single atomic `ADD` update.
+#[derive(Debug)]
+pub struct DynamoDbIdCounter {
+    client: aws_sdk_dynamodb::Client,
+    table_name: String,
+    id_attribute: String,
+    id: String,
+    retry_policy: DynamoDbRetryPolicy,
+}
+
+#[async_trait]
+impl IdCounter for DynamoDbIdCounter {
+    async fn reserve(&self, count: u64) -> Result<u64> {
+        let o = self
+            .retry_policy
+            .run("Reserve - UpdateItem", || {
+                self.client
+                    .update_item()
+                    .table_name(&self.table_name)
+                    .key(&self.id_attribute, AttributeValue::S(self.id.clone()))
+                    .update_expression("ADD #Value :count")
+                    .expression_attribute_names("#Value", "value")
+                    .expression_attribute_values(":count", AttributeValue::N(count.to_string()))
+                    .return_values(ReturnValue::UpdatedNew)
+                    .send()
+            })
+            .await
+            .map_err(|e| eyre!("Can't reserve from counter {} -> {e:?}", &self.id))?;
+        let value = o
+            .attributes()
+            .and_then(|a| a.get("value"))
+            .and_then(|v| v.as_n().ok())
+            .ok_or_else(|| eyre!("Counter {} returned no value", &self.id))?;
+        let next = value.parse::<u64>()?;
+
+        // `next` is the last reserved value
+        Ok(next + 1 - count)
+    }
+}
+
+/// Time to live settings, based on DynamoDB's TTL feature.
+///
+/// When enabled, [StorageDynamoDb::ensure_table_exists] enables TTL on the `expires_at` attribute.
+/// Note: DynamoDB deletes the *whole* item, usually within a few days after it expired.
+#[derive(Debug, Default, Clone)]
+pub struct DynamoDbTtl {
+    /// Items expire this long after their last save, e.g. for sessions
+    pub item_ttl: Option<Duration>,
+    /// Items that got locked but never saved expire this long after locking.
+    /// This cleans up after crashes during creation.
+    pub unsaved_lock_ttl: Option<Duration>,
+}
+
+impl DynamoDbTtl {
+    pub fn is_enabled(&self) -> bool {
+        self.item_ttl.is_some() || self.unsaved_lock_ttl.is_some()
+    }
+}
+
+fn expires_at(now: DateTime<Utc>, ttl: Duration) -> AttributeValue {
+    let expires_at = now.timestamp() + ttl.as_secs() as i64;
+    AttributeValue::N(expires_at.to_string())
+}
+
+/// A typed [StorageError::Backend] for a failed request, after all retries.
+fn backend_error<E, R>(operation: StorageOperation, e: &SdkError<E, R>) -> Report
+where
+    E: ProvideErrorMetadata + std::fmt::Debug,
+    R: std::fmt::Debug,
+{
+    StorageError::Backend {
+        operation,
+        transient: DynamoDbErrorClass::classify(e).is_transient(),
+        message: format!("{e:?}"),
+    }
+    .into()
+}
+
+/// The condition that a lock is still held, see [StorageDynamoDb::lock_condition].
+struct LockCondition {
+    expression: &'static str,
+    names: HashMap<String, String>,
+    values: HashMap<String, AttributeValue>,
+}
+
+impl LockCondition {
+    /// Values only compared, never stored.
+    const VALUES: [&'static str; 3] = [":lock", ":legacy_lock", ":lock_token"];
+}
+
+struct SaveExpression {
+    update_expression: String,
+    condition_expression: String,
+    names: HashMap<String, String>,
+    values: HashMap<String, AttributeValue>,
+}
+
+impl SaveExpression {
+    /// The estimated size of the saved item, following DynamoDB's rules for item sizes.
+    ///
+    /// Attributes not written by the save, e.g. blobs, links, and the lock, are not included.
+    fn item_size(&self, id_attribute: &str, id: &str) -> usize {
+        let names: usize = self.names.values().map(String::len).sum();
+        let values: usize = self
+            .values
+            .iter()
+            .filter(|(name, _)| !LockCondition::VALUES.contains(&name.as_str()))
+            .map(|(_, value)| attribute_value_size(value))
+            .sum();
+        id_attribute.len() + id.len() + names + values
+    }
+}
+
+/// The size DynamoDB counts for `value`, see
+/// <https://docs.aws.amazon.com/amazondynamodb/latest/developerguide/CapacityUnitCalculations.html>
+fn attribute_value_size(value: &AttributeValue) -> usize {
+    match value {
+        AttributeValue::S(s) => s.len(),
+        // numbers take about one byte per two digits, plus one
+        AttributeValue::N(n) => n.len().div_ceil(2) + 1,
+        AttributeValue::B(b) => b.as_ref().len(),
+        AttributeValue::Bool(_) | AttributeValue::Null(_) => 1,
+        AttributeValue::Ss(ss) => ss.iter().map(String::len).sum(),
+        AttributeValue::Ns(ns) => ns.iter().map(|n| n.len().div_ceil(2) + 1).sum(),
+        AttributeValue::Bs(bs) => bs.iter().map(|b| b.as_ref().len()).sum(),
+        AttributeValue::L(l) => 3 + l.iter().map(|v| 1 + attribute_value_size(v)).sum::<usize>(),
+        AttributeValue::M(m) => {
+            3 + m
+                .iter()
+                .map(|(k, v)| 1 + k.len() + attribute_value_size(v))
+                .sum::<usize>()
+        }
+        _ => 0,
+    }
+}
+
+struct ScanFilter {
+    filter_expression: String,
+    names: HashMap<String, String>,
+    values: HashMap<String, AttributeValue>,
+}
+
+/// The decoded id in `key` without the key prefix and the shard, `None` for keys of other storages.
+pub(crate) fn id_from_key(
+    key: &str,
+    key_prefix: &str,
+    key_hasher: Option<&dyn DynamoDbKeyHasher>,
+    id_codec: Option<&dyn IdCodec>,
+) -> Option<String> {
+    let key = key.strip_prefix(key_prefix)?;
+    let encoded = match key_hasher {
+        Some(key_hasher) => {
+            let (shard, encoded) = key.split_once(KEY_SHARD_SEPARATOR)?;
+            if key_hasher.shard(encoded) != shard {
+                return None;
+            }
+            encoded
+        }
+        None => key,
+    };
+    match id_codec {
+        Some(id_codec) => id_codec.decode(encoded),
+        None => Some(encoded.to_string()),
+    }
+}
+
+/// The scan filter for `filter`, always skipping counters, aliases, and the metadata,
+/// and items of other types or without `key_prefix`.
+fn scan_filter(
+    filter: &IdFilter,
+    attribute_names: &DynamoDbAttributeNames,
+    key_prefix: &str,
+) -> ScanFilter {
+    let mut clauses = vec![
+        "NOT begins_with(#Id, :counter_prefix)",
+        "NOT begins_with(#Id, :alias_prefix)",
+        "NOT begins_with(#Id, :meta_prefix)",
+        "#Id <> :metadata_id",
+    ];
+    let mut names = HashMap::from([(String::from("#Id"), attribute_names.id.clone())]);
+    let mut values = HashMap::from([
+        (
+            String::from(":counter_prefix"),
+            AttributeValue::S(format!("{key_prefix}{COUNTER_ID_PREFIX}")),
+        ),
+        (
+            String::from(":alias_prefix"),
+            AttributeValue::S(format!("{key_prefix}{ALIAS_ID_PREFIX}")),
+        ),
+        (
+            String::from(":meta_prefix"),
+            AttributeValue::S(format!("{key_prefix}{META_ID_PREFIX}")),
+        ),
+        (
+            String::from(":metadata_id"),
+            AttributeValue::S(format!("{key_prefix}{METADATA_ID}")),
+        ),
+    ]);
+    match filter {
+        IdFilter::Prefix(prefix) => {
+            if !prefix.is_empty() || !key_prefix.is_empty() {
+                clauses.insert(0, "begins_with(#Id, :prefix)");
+                values.insert(
+                    String::from(":prefix"),
+                    AttributeValue::S(format!("{key_prefix}{prefix}")),
+                );
+            }
+        }
+        IdFilter::Range { start, end } => {
+            if !key_prefix.is_empty() {
+                clauses.insert(0, "begins_with(#Id, :prefix)");
+                values.insert(
+                    String::from(":prefix"),
+                    AttributeValue::S(String::from(key_prefix)),
+                );
+            }
+            if let Some(start) = start {
+                clauses.push("#Id >= :start");
+                values.insert(
+                    String::from(":start"),
+                    AttributeValue::S(format!("{key_prefix}{start}")),
+                );
+            }
+            if let Some(end) = end {
+                clauses.push("#Id < :end");
+                values.insert(
+                    String::from(":end"),
+                    AttributeValue::S(format!("{key_prefix}{end}")),
+                );
+            }
+        }
+        IdFilter::ModifiedBefore(before) => {
+            if !key_prefix.is_empty() {
+                clauses.insert(0, "begins_with(#Id, :prefix)");
+                values.insert(
+                    String::from(":prefix"),
+                    AttributeValue::S(String::from(key_prefix)),
+                );
+            }
+            clauses.push("#ModifiedAt < :before");
+            names.insert(
+                String::from("#ModifiedAt"),
+                String::from(MODIFIED_AT_ATTRIBUTE),
+            );
+            values.insert(
+                String::from(":before"),
+                AttributeValue::N(before.timestamp().to_string()),
+            );
+        }
+    }
+
+    if let Some((attribute, value)) = &attribute_names.item_type {
+        clauses.push("#Type = :item_type");
+        names.insert(String::from("#Type"), attribute.clone());
+        values.insert(String::from(":item_type"), AttributeValue::S(value.clone()));
+    }
+
+    ScanFilter {
+        filter_expression: clauses.join(" AND "),
+        names,
+        values,
+    }
+}
 
 #[derive(Debug)]
 pub struct StorageDynamoDb<ITEM: StorageItem> {
     table_name: String,
     endpoint_url: Option<String>,
+    region: Option<String>,
+    profile: Option<String>,
+    credentials: Option<Credentials>,
+    /// role arn and session name
+    assume_role: Option<(String, String)>,
+    sdk_config: Option<SdkConfig>,
+    client: Option<aws_sdk_dynamodb::Client>,
+    data_format: DynamoDbDataFormat,
+    attribute_names: DynamoDbAttributeNames,
+    key_prefix: String,
+    key_hasher: Option<Arc<dyn DynamoDbKeyHasher>>,
+    id_codec: Option<Arc<dyn IdCodec>>,
+    consistent_read: bool,
+    immutable: bool,
+    retry_policy: DynamoDbRetryPolicy,
+    scan_throttle: DynamoDbScanThrottle,
+    scan_pacers: Option<ScanPacers>,
+    ttl: DynamoDbTtl,
+    id_allocator: Option<IdAllocator>,
+    aliases: bool,
+    slow_op_threshold: Option<Duration>,
+    max_item_size: usize,
+    create_tries: usize,
+    probe_id: Option<ITEM::ID>,
+    clock: Arc<dyn Clock>,
+    id_generator: Arc<dyn IdGenerator<ITEM::ID>>,
     item_type: PhantomData<ITEM>,
     #[cfg(feature = "metadata")]
     metadata: Metadata<ITEM>,
@@ -36,11 +497,153 @@ impl<ITEM: StorageItem> StorageDynamoDb<ITEM> {
     fn update_highest_seen_id(&self, id: &ITEM::ID) {
         self.metadata.update_highest_seen_id(id);
     }
+
+    fn record_lock_acquired(&self, id: &ITEM::ID, lock: &StorageLock) {
+        self.metadata.record_lock_acquired(id, lock);
+    }
+
+    fn record_lock_contended(&self) {
+        self.metadata.record_lock_contended();
+        operation_metrics::record_lock_contended("dynamodb");
+    }
+
+    fn record_lock_released(&self, id: &ITEM::ID, lock: &StorageLock) {
+        self.metadata
+            .record_lock_released(id, lock, self.clock.now());
+    }
+
+    fn record_write(&self) {
+        self.metadata.record_write();
+    }
+
+    fn record_totals(&self, item_count: u64, total_bytes: Option<u64>) {
+        self.metadata.record_totals(item_count, total_bytes);
+    }
+
+    fn approximate_item_count(&self) -> Option<u64> {
+        self.metadata.item_count()
+    }
+
+    async fn load_metadata(&self) -> Result<()> {
+        let client = self.client().await?;
+        let o = self
+            .retry_policy
+            .run("Load Metadata - GetItem", || {
+                client
+                    .get_item()
+                    .table_name(&self.table_name)
+                    .key(&self.attribute_names.id, self.internal_key(METADATA_ID))
+                    .consistent_read(true)
+                    .send()
+            })
+            .await
+            .map_err(|e| eyre!("Load Metadata - GetItem failed -> {e:?}"))?;
+        match o.item.as_ref().and_then(|item| item.get("metadata")) {
+            Some(AttributeValue::S(data)) => self.metadata.restore(data.as_bytes()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn write_metadata(
+        client: &aws_sdk_dynamodb::Client,
+        table_name: &str,
+        (id_attribute, key): (&str, &AttributeValue),
+        retry_policy: &DynamoDbRetryPolicy,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        let data = String::from_utf8(data)?;
+        retry_policy
+            .run("Flush Metadata - PutItem", || {
+                client
+                    .put_item()
+                    .table_name(table_name)
+                    .item(id_attribute, key.clone())
+                    .item("metadata", AttributeValue::S(data.clone()))
+                    .send()
+            })
+            .await
+            .map_err(|e| eyre!("Flush Metadata - PutItem failed -> {e:?}"))?;
+
+        Ok(())
+    }
+
+    async fn start_metadata_flush(&self) -> Result<()> {
+        let client = self.client().await?;
+        let table_name = self.table_name.clone();
+        let id_attribute = self.attribute_names.id.clone();
+        let key = self.internal_key(METADATA_ID);
+        let retry_policy = self.retry_policy.clone();
+        self.metadata.spawn_flush(move |data| {
+            let client = client.clone();
+            let table_name = table_name.clone();
+            let id_attribute = id_attribute.clone();
+            let key = key.clone();
+            let retry_policy = retry_policy.clone();
+            async move {
+                Self::write_metadata(
+                    &client,
+                    &table_name,
+                    (&id_attribute, &key),
+                    &retry_policy,
+                    data,
+                )
+                .await
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Writes changed metadata now, instead of waiting for the periodic flush,
+    /// e.g. before shutting down.
+    pub async fn flush_metadata(&self) -> Result<()> {
+        if let Some(data) = self.metadata.take_dirty()? {
+            let client = self.client().await?;
+            Self::write_metadata(
+                &client,
+                &self.table_name,
+                (&self.attribute_names.id, &self.internal_key(METADATA_ID)),
+                &self.retry_policy,
+                data,
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
 }
 
 #[cfg(not(feature = "metadata"))]
 impl<ITEM: StorageItem> StorageDynamoDb<ITEM> {
     fn update_highest_seen_id(&self, _id: &ITEM::ID) {}
+
+    fn record_lock_acquired(&self, _id: &ITEM::ID, _lock: &StorageLock) {}
+
+    fn record_lock_contended(&self) {
+        operation_metrics::record_lock_contended("dynamodb");
+    }
+
+    fn record_lock_released(&self, _id: &ITEM::ID, _lock: &StorageLock) {}
+
+    fn record_write(&self) {}
+
+    fn record_totals(&self, _item_count: u64, _total_bytes: Option<u64>) {}
+
+    fn approximate_item_count(&self) -> Option<u64> {
+        None
+    }
+
+    async fn load_metadata(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn start_metadata_flush(&self) -> Result<()> {
+        Ok(())
+    }
+
+    pub async fn flush_metadata(&self) -> Result<()> {
+        Ok(())
+    }
 }
 
 impl<ITEM: StorageItem> StorageDynamoDb<ITEM> {
@@ -48,436 +651,493 @@ impl<ITEM: StorageItem> StorageDynamoDb<ITEM> {
         Self {
             table_name: String::from(table_name),
             endpoint_url: None,
+            region: None,
+            profile: None,
+            credentials: None,
+            assume_role: None,
+            sdk_config: None,
+            client: None,
+            data_format: DynamoDbDataFormat::default(),
+            attribute_names: DynamoDbAttributeNames::default(),
+            key_prefix: String::new(),
+            key_hasher: None,
+            id_codec: None,
+            consistent_read: false,
+            immutable: false,
+            retry_policy: DynamoDbRetryPolicy::default(),
+            scan_throttle: DynamoDbScanThrottle::default(),
+            scan_pacers: None,
+            ttl: DynamoDbTtl::default(),
+            id_allocator: None,
+            aliases: false,
+            slow_op_threshold: None,
+            max_item_size: ITEM_SIZE_LIMIT,
+            create_tries: DEFAULT_CREATE_TRIES,
+            probe_id: None,
+            clock: Arc::new(SystemClock),
+            id_generator: Arc::new(DefaultIdGenerator),
             item_type: PhantomData,
             #[cfg(feature = "metadata")]
             metadata: Metadata::default(),
         }
     }
 
+    pub fn table_name(&self) -> &str {
+        &self.table_name
+    }
+
+    pub fn endpoint_url(&self) -> Option<&str> {
+        self.endpoint_url.as_deref()
+    }
+
     pub fn set_endpoint_url(&mut self, url: &str) -> Result<()> {
         self.endpoint_url = Some(String::from(url));
 
         Ok(())
     }
-    async fn client(&self) -> Result<aws_sdk_dynamodb::Client> {
-        // let config = aws_config::load_from_env().await;
-        let config = aws_config::defaults(aws_config::BehaviorVersion::latest());
-        let config = if let Some(endpoint_url) = &self.endpoint_url {
-            config.endpoint_url(endpoint_url)
-        } else {
-            config
-        };
-        let config = config.load().await;
-        let client = aws_sdk_dynamodb::Client::new(&config);
 
-        Ok(client)
+    /// Selects how items are written to the `data` attribute.
+    /// Reading always accepts both formats, so existing tables can be migrated item by item.
+    pub fn set_data_format(&mut self, data_format: DynamoDbDataFormat) -> Result<()> {
+        self.data_format = data_format;
+
+        Ok(())
     }
-    pub async fn ensure_table_exists(&mut self) -> Result<()> {
-        /*
-        // let config = aws_config::load_from_env().await;
-        let config = aws_config::defaults(aws_config::BehaviorVersion::latest());
-        let config = if let Some(endpoint_url) = &self.endpoint_url {
-            config.endpoint_url(endpoint_url)
-        } else {
-            config
-        };
-        let config = config.load().await;
-        let client = aws_sdk_dynamodb::Client::new(&config);
-        */
-        let client = self.client().await?;
 
-        match client
-            .describe_table()
-            .table_name(&self.table_name)
-            .send()
-            .await
-        {
-            Ok(_o) => {
-                // :TODO: verify table format?
-                // tracing::info!("Table {} exists -> {o:#?}", &self.table_name);
-                tracing::info!("Table {} exists", &self.table_name);
+    /// Renames the `id`, `lock`, and `data` attributes, e.g. to use an existing table.
+    ///
+    /// Blob, link, and bookkeeping attributes, e.g. `modified_at`, keep their names.
+    pub fn set_attribute_names(&mut self, attribute_names: DynamoDbAttributeNames) -> Result<()> {
+        attribute_names.ensure_valid()?;
+        self.attribute_names = attribute_names;
+
+        Ok(())
+    }
+
+    pub fn attribute_names(&self) -> &DynamoDbAttributeNames {
+        &self.attribute_names
+    }
+
+    /// Prepends `key_prefix` to all keys, e.g. `"tenant_a#"`, to share one table between many storages.
+    /// The trailing `#` is appended if missing, so `"tenant"` doesn't see the keys of `"tenant_a"`.
+    /// Fails for prefixes with another `#`, they could collide with other prefixes or the reserved keys,
+    /// e.g. of counters. Empty for no prefix.
+    ///
+    /// Ids passed in and returned stay without the prefix, scans only see items with it.
+    /// Counters, aliases, and the metadata are prefixed too.
+    pub fn set_key_prefix(&mut self, key_prefix: &str) -> Result<()> {
+        let name = key_prefix
+            .strip_suffix(KEY_PREFIX_SEPARATOR)
+            .unwrap_or(key_prefix);
+        if name.is_empty() {
+            if !key_prefix.is_empty() {
+                return Err(eyre!("Key prefix {key_prefix:?} has no name"));
             }
-            Err(e) => {
-                // tracing::debug!("Err {e:?}");
-                match e {
-                    SdkError::ServiceError(se) => {
-                        match se.err() {
-                            ResourceNotFoundException(_nf) => {
-                                // tracing::debug!("{nf:?}");
-                                tracing::info!("Table {} not found. Creating...", &self.table_name);
+            self.key_prefix = String::new();
+            return Ok(());
+        }
+        if name.contains(KEY_PREFIX_SEPARATOR) {
+            return Err(eyre!(
+                "Key prefix {key_prefix:?} must not contain {KEY_PREFIX_SEPARATOR:?}, except at the end"
+            ));
+        }
+        self.key_prefix = format!("{name}{KEY_PREFIX_SEPARATOR}");
 
-                                // :TODO:
+        Ok(())
+    }
 
-                                let ad_id = AttributeDefinition::builder()
-                                    .attribute_name("id")
-                                    .attribute_type(ScalarAttributeType::S)
-                                    .build()?;
+    pub fn key_prefix(&self) -> &str {
+        &self.key_prefix
+    }
 
-                                let key_id = KeySchemaElement::builder()
-                                    .attribute_name("id")
-                                    .key_type(KeyType::Hash)
-                                    .build()?;
+    /// Prepends a shard picked by `key_hasher` to the keys of items, after the key prefix,
+    /// e.g. `"07#1234"` for id `1234`, to spread sequential ids over many partitions.
+    ///
+    /// Scans with an [IdFilter::Prefix] or [IdFilter::Range] have to filter after reading then,
+    /// so pages get shorter. Items saved before or with another hasher are not found anymore.
+    pub fn set_key_hasher(&mut self, key_hasher: impl DynamoDbKeyHasher + 'static) -> Result<()> {
+        self.key_hasher = Some(Arc::new(key_hasher));
 
-                                let pt = ProvisionedThroughput::builder()
-                                    .read_capacity_units(1)
-                                    .write_capacity_units(1)
-                                    .build()?;
+        Ok(())
+    }
 
-                                let r = client
-                                    .create_table()
-                                    .table_name(&self.table_name)
-                                    .attribute_definitions(ad_id)
-                                    //.attribute_definitions(ad_lock)
-                                    //.attribute_definitions(ad_data)
-                                    .key_schema(key_id)
-                                    //.key_schema(key_lock)
-                                    //.key_schema(key_data)
-                                    .provisioned_throughput(pt);
-                                r.send().await?;
-                            }
-                            oe => return Err(eyre!("Error describing table {oe:?}")),
-                        }
-                    }
-                    _o => {
-                        todo!();
-                    }
-                }
-            }
-        };
+    pub fn key_hasher(&self) -> Option<Arc<dyn DynamoDbKeyHasher>> {
+        self.key_hasher.clone()
+    }
 
-        // tracing::debug!("{client:?}");
+    /// Stores items under the keys `id_codec` encodes their ids to, e.g. via [crate::EncryptedIdCodec],
+    /// so raw ids never show up as keys. Sharding, see [StorageDynamoDb::set_key_hasher], uses the encoded ids.
+    ///
+    /// Scans with an [IdFilter::Prefix] or [IdFilter::Range] have to filter after reading then,
+    /// so pages get shorter. Items saved before or with another codec are not found anymore.
+    pub fn set_id_codec(&mut self, id_codec: impl IdCodec + 'static) -> Result<()> {
+        self.id_codec = Some(Arc::new(id_codec));
 
-        // insert test data
-        /*
-        let request = client
-            .put_item()
-            .table_name(&self.table_name)
-            .item("id", AttributeValue::S(nanoid::nanoid!()))
-            .item("lock", AttributeValue::S(String::from("")))
-            .item("data", AttributeValue::S(String::from("{}")))
-            .send()
-            .await?;
-        */
         Ok(())
     }
-}
 
-#[async_trait]
-impl<ITEM: StorageItem + std::marker::Send> Storage<ITEM> for StorageDynamoDb<ITEM> {
-    async fn ensure_storage_exists(&mut self) -> Result<()> {
-        self.ensure_table_exists().await
+    pub fn id_codec(&self) -> Option<Arc<dyn IdCodec>> {
+        self.id_codec.clone()
     }
-    async fn create(&self) -> Result<ITEM::ID> {
-        let mut tries = 10;
-        loop {
-            //let id = nanoid::nanoid!();
-            let id = ITEM::generate_next_id(None);
-            if !self.exists(&id).await? {
-                return Ok(id);
-            }
 
-            tries -= 1;
-            if tries <= 0 {
-                todo!();
+    /// The encoded key of item `id` with the key prefix and the shard.
+    fn key_string(&self, id: &(impl Display + ?Sized)) -> String {
+        let id = match &self.id_codec {
+            Some(id_codec) => id_codec.encode(&id.to_string()),
+            None => id.to_string(),
+        };
+        match &self.key_hasher {
+            Some(key_hasher) => {
+                let shard = key_hasher.shard(&id);
+                format!("{}{shard}{KEY_SHARD_SEPARATOR}{id}", self.key_prefix)
             }
+            None => format!("{}{id}", self.key_prefix),
         }
     }
-    async fn exists(&self, id: &ITEM::ID) -> Result<bool> {
-        tracing::info!("Checking if {id} exists");
-        let client = self.client().await?;
-        match client
-            .get_item()
-            .table_name(&self.table_name)
-            .key("id", AttributeValue::S(id.to_string()))
-            .projection_expression("#Id")
-            .expression_attribute_names("#Id", "id")
-            .send()
-            .await
-        {
-            Ok(o) => {
-                tracing::info!("Check - GetItem {id} success {o:?}");
-                let Some(_item) = o.item else {
-                    return Ok(false);
-                };
-                self.update_highest_seen_id(&id);
-                Ok(true)
-            }
-            Err(e) => {
-                tracing::warn!("Check - GetItem {id} failure {e:?}");
-                Err(eyre!(":TODO:"))
-            }
-        }
-        //Ok(false) // :TODO:
+
+    /// The key attribute value for item `id`.
+    fn key(&self, id: &(impl Display + ?Sized)) -> AttributeValue {
+        AttributeValue::S(self.key_string(id))
     }
 
-    async fn load(&self, _id: &ITEM::ID) -> Result<ITEM> {
-        todo!();
+    /// The key attribute value of counters, aliases, and meta values, with the key prefix but never sharded.
+    fn internal_key(&self, id: &str) -> AttributeValue {
+        AttributeValue::S(format!("{}{id}", self.key_prefix))
     }
 
-    async fn save(&self, id: &ITEM::ID, item: &ITEM, lock: &StorageLock) -> Result<()> {
-        tracing::info!("Saving: {id} -> {item:?} with lock {lock:?}");
-        let lock_json = serde_json::to_string_pretty(&lock)?;
-        let client = self.client().await?;
-        let data = item.serialize()?;
-        let data = String::from_utf8_lossy(&data);
-        match client
-            .update_item()
-            .table_name(&self.table_name)
-            .key("id", AttributeValue::S(id.to_string()))
-            .update_expression("SET #Data = :data")
-            .expression_attribute_names("#Data", "data")
-            .expression_attribute_values(
-                ":data",
-                aws_sdk_dynamodb::types::AttributeValue::S(data.to_string()),
-            )
-            .condition_expression("#Lock = :lock")
-            .expression_attribute_names("#Lock", "lock")
-            .expression_attribute_values(
-                ":lock",
-                aws_sdk_dynamodb::types::AttributeValue::S(lock_json),
-            )
-            .return_values(ReturnValue::AllOld)
-            .send()
-            .await
-        {
-            Ok(o) => {
-                tracing::info!("Save - UpdateItem {id} success {o:?}");
-                self.update_highest_seen_id(&id);
-                Ok(())
-            }
-            Err(e) => {
-                tracing::warn!("Save - UpdateItem {id} failure {e:?}");
-                // :TODO: check if it was actually the lock that failed
-                Err(eyre!("Lock invalid!"))
-            }
-        }
+    /// The id of a scanned key, `None` for keys of other storages.
+    fn id_from_key(&self, key: &str) -> Option<String> {
+        id_from_key(
+            key,
+            &self.key_prefix,
+            self.key_hasher.as_deref(),
+            self.id_codec.as_deref(),
+        )
     }
-    async fn lock(&self, id: &ITEM::ID, who: &str) -> Result<LockResult<ITEM>> {
-        let lock = StorageLock::new(who);
-        let lock_json = serde_json::to_string_pretty(&lock)?;
 
-        // write lock
-        let client = self.client().await?;
+    /// Use strongly consistent reads for `load`, `exists`, and `verify_lock`.
+    /// The `*_with_consistency` variants allow overriding this per call.
+    pub fn set_consistent_read(&mut self, consistent_read: bool) -> Result<()> {
+        self.consistent_read = consistent_read;
 
-        match client
-            .update_item()
-            .table_name(&self.table_name)
-            //.key("id", AttributeValue::S(String::from(id)))
-            .key("id", AttributeValue::S(id.to_string()))
-            //.expression_attribute_names()
-            //.update_expression("SET #Count = if_not_exists(#Count, :zero) + :one, Images = list_append(if_not_exists(Images, :empty), :image)")
-            .update_expression("SET #Lock = :lock")
-            .expression_attribute_names("#Lock", "lock")
-            .expression_attribute_values(
-                ":lock",
-                aws_sdk_dynamodb::types::AttributeValue::S(lock_json),
-            )
-            .condition_expression("attribute_not_exists(#Lock)")
-            .return_values(ReturnValue::AllOld)
-            .send()
-            .await
-        {
-            Ok(o) => {
-                tracing::info!("Lock - UpdateItem {id} success {o:?}");
-                let item = match o {
-                    UpdateItemOutput { ref attributes, .. } => {
-                        if let Some(attributes) = &attributes {
-                            if let Some(data) = attributes.get("data") {
-                                match data {
-                                    AttributeValue::S(data) => {
-                                        let item = ITEM::deserialize(data.as_bytes())?;
-                                        tracing::info!("Lock - Got item {item:?}");
-                                        self.update_highest_seen_id(&id);
-                                        item
-                                    }
-                                    o => {
-                                        tracing::warn!(
-                                            "No data attribute for item is not a string {o:?}"
-                                        );
-                                        ITEM::default()
-                                    }
-                                }
-                            } else {
-                                tracing::warn!("No data attribute for item");
-                                ITEM::default()
-                            }
-                        } else {
-                            tracing::warn!("No attributes for item");
-                            ITEM::default()
-                        }
-                    }
-                };
+        Ok(())
+    }
 
-                //let item = ITEM::default();
-                Ok(LockResult::Success { lock, item })
-            }
-            Err(e) => {
-                tracing::warn!("Lock - UpdateItem {id} failure {e:?}");
-                return Ok(LockResult::AlreadyLocked {
-                    who: String::from(":TODO:"),
-                });
-            }
+    /// Items can only be saved once, later saves fail with [StorageError::Immutable].
+    /// Enforced by the save condition `attribute_not_exists(#Data)`, so concurrent saves can't both win.
+    /// Off by default.
+    pub fn set_immutable(&mut self, immutable: bool) -> Result<()> {
+        self.immutable = immutable;
+
+        Ok(())
+    }
+
+    /// Sets the policy for retrying throttled or failed DynamoDB calls.
+    /// The SDK's own retries are disabled, so attempts don't multiply.
+    pub fn set_retry_policy(&mut self, retry_policy: DynamoDbRetryPolicy) -> Result<()> {
+        self.retry_policy = retry_policy;
+
+        Ok(())
+    }
+
+    /// Keeps scans, e.g. of exports, wipes, and migrations, under a share of the table throughput,
+    /// see [DynamoDbScanThrottle].
+    pub fn set_scan_throttle(&mut self, scan_throttle: DynamoDbScanThrottle) -> Result<()> {
+        scan_throttle.validate()?;
+        self.scan_throttle = scan_throttle;
+        // shares are resolved by ensure_table_exists
+        self.scan_pacers = scan_throttle.pacers(None)?;
+
+        Ok(())
+    }
+
+    /// Configures time to live for items, see [DynamoDbTtl].
+    pub fn set_ttl(&mut self, ttl: DynamoDbTtl) -> Result<()> {
+        self.ttl = ttl;
+
+        Ok(())
+    }
+
+    /// Enables [Storage::add_alias].
+    /// Every item operation reads the alias item first, so this is off by default.
+    pub fn set_aliases_enabled(&mut self, enabled: bool) -> Result<()> {
+        self.aliases = enabled;
+
+        Ok(())
+    }
+
+    /// Saves of items larger than this fail with [StorageError::ItemTooLarge],
+    /// before reaching DynamoDB. Defaults to DynamoDB's limit of 400KB.
+    ///
+    /// Lower it to leave room for blobs and links, which are not part of the estimate.
+    pub fn set_max_item_size(&mut self, max_item_size: usize) -> Result<()> {
+        if max_item_size == 0 || max_item_size > ITEM_SIZE_LIMIT {
+            return Err(eyre!(
+                "Max item size must be between 1 and {ITEM_SIZE_LIMIT}, got {max_item_size}"
+            ));
         }
+        self.max_item_size = max_item_size;
+
+        Ok(())
     }
 
-    async fn unlock(&self, id: &ITEM::ID, lock: StorageLock) -> Result<()> {
-        tracing::info!("Unlocking: {id} with lock {lock:?}");
-        let lock_json = serde_json::to_string_pretty(&lock)?;
-        let client = self.client().await?;
-        match client
-            .update_item()
-            .table_name(&self.table_name)
-            .key("id", AttributeValue::S(id.to_string()))
-            .update_expression("REMOVE #Lock")
-            .expression_attribute_names("#Lock", "lock")
-            .condition_expression("#Lock = :lock")
-            .expression_attribute_values(
-                ":lock",
-                aws_sdk_dynamodb::types::AttributeValue::S(lock_json),
-            )
-            .return_values(ReturnValue::None)
-            .send()
-            .await
-        {
-            Ok(o) => {
-                tracing::info!("Unlock - UpdateItem {id} success {o:?}");
-                self.update_highest_seen_id(&id);
-                Ok(())
-            }
-            Err(e) => {
-                tracing::warn!("Unlock - UpdateItem {id} failure {e:?}");
-                // :TODO: check if it was actually the lock that failed
-                Err(eyre!("Lock invalid!"))
+    /// Fails with [StorageError::ItemTooLarge], if the saved item would exceed the max item size.
+    fn ensure_item_fits(&self, id: &ITEM::ID, expression: &SaveExpression) -> Result<()> {
+        let key = self.key_string(id);
+        let id = id.to_string();
+        let size = expression.item_size(&self.attribute_names.id, &key);
+        if size > self.max_item_size {
+            return Err(StorageError::ItemTooLarge {
+                id,
+                size,
+                max_size: self.max_item_size,
             }
+            .into());
         }
+
+        Ok(())
     }
 
-    async fn force_unlock(&self, id: &ITEM::ID) -> Result<()> {
-        tracing::info!("Force Unlocking: {id}");
-        let client = self.client().await?;
-        match client
-            .update_item()
-            .table_name(&self.table_name)
-            .key("id", AttributeValue::S(id.to_string()))
-            .update_expression("REMOVE #Lock")
-            .expression_attribute_names("#Lock", "lock")
-            .return_values(ReturnValue::None)
-            .send()
-            .await
-        {
-            Ok(o) => {
-                tracing::info!("Force Unlock - UpdateItem {id} success {o:?}");
-                self.update_highest_seen_id(&id);
-                Ok(())
+    /// Logs a warning for every operation that takes longer than `threshold`.
+    pub fn set_slow_op_threshold(&mut self, threshold: Option<Duration>) -> Result<()> {
+        self.slow_op_threshold = threshold;
+
+        Ok(())
+    }
+
+    /// How often [Storage::create] generates a new id, when it collides with an existing one, defaults to 10.
+    pub fn set_create_tries(&mut self, tries: usize) -> Result<()> {
+        ensure_valid_create_tries(tries)?;
+        self.create_tries = tries;
+
+        Ok(())
+    }
+
+    /// Locks, writes, and reads back the reserved `probe_id` in [Storage::ensure_storage_exists],
+    /// so wrong credentials, missing permissions or a table with another schema fail there,
+    /// instead of on the first real request.
+    pub fn set_probe_id(&mut self, probe_id: Option<ITEM::ID>) -> Result<()> {
+        self.probe_id = probe_id;
+
+        Ok(())
+    }
+
+    /// Where the time for locks, modification times and TTLs comes from, defaults to [SystemClock].
+    pub fn set_clock(&mut self, clock: impl Clock + 'static) -> Result<()> {
+        self.clock = Arc::new(clock);
+
+        Ok(())
+    }
+
+    /// Where [Storage::create] gets new ids from, defaults to [DefaultIdGenerator].
+    pub fn set_id_generator(
+        &mut self,
+        id_generator: impl IdGenerator<ITEM::ID> + 'static,
+    ) -> Result<()> {
+        self.id_generator = Arc::new(id_generator);
+
+        Ok(())
+    }
+
+    /// Locks held longer than this are logged with a warning and counted in [crate::LockStats].
+    #[cfg(feature = "metadata")]
+    pub fn set_max_lock_hold_time(&mut self, max_lock_hold_time: Option<Duration>) -> Result<()> {
+        self.metadata.set_max_lock_hold_time(max_lock_hold_time);
+
+        Ok(())
+    }
+
+    /// Lets [Storage::create] take ids from the allocator, instead of [crate::StorageId::generate_new].
+    pub fn set_id_allocator(&mut self, id_allocator: Option<IdAllocator>) -> Result<()> {
+        self.id_allocator = id_allocator;
+
+        Ok(())
+    }
+
+    /// Returns a counter stored in the items table, e.g. for an [IdAllocator].
+    /// Counter items are skipped by [Storage::scan_ids], like alias items.
+    pub async fn id_counter(&self, name: &str) -> Result<DynamoDbIdCounter> {
+        Ok(DynamoDbIdCounter {
+            client: self.client().await?,
+            table_name: self.table_name.clone(),
+            id_attribute: self.attribute_names.id.clone(),
+            id: format!("{}{COUNTER_ID_PREFIX}{name}", self.key_prefix),
+            retry_policy: self.retry_policy.clone(),
+        })
+    }
+
+    fn item_to_data(&self, id: &ITEM::ID, item: &ITEM) -> Result<AttributeValue> {
+        self.raw_to_data(serialize_valid(id, item)?)
+    }
+
+    fn raw_to_data(&self, data: Vec<u8>) -> Result<AttributeValue> {
+        match self.data_format {
+            DynamoDbDataFormat::String => {
+                let data = String::from_utf8_lossy(&data);
+                Ok(AttributeValue::S(data.to_string()))
             }
-            Err(e) => {
-                tracing::warn!("Force Unlock - UpdateItem {id} failure {e:?}");
-                // :TODO: check
-                Err(eyre!("Lock invalid!"))
+            DynamoDbDataFormat::Map => {
+                let json: serde_json::Value = serde_json::from_slice(&data)
+                    .map_err(|e| eyre!("Item data is not JSON, can't store as map -> {e}"))?;
+                let data: AttributeValue = serde_dynamo::to_attribute_value(json)?;
+                Ok(data)
             }
         }
     }
-    async fn verify_lock(&self, id: &ITEM::ID, lock: &StorageLock) -> Result<bool> {
-        tracing::info!("Checking if lock {lock:?} is correct for {id}");
-        let client = self.client().await?;
-        match client
-            .get_item()
-            .table_name(&self.table_name)
-            .key("id", AttributeValue::S(id.to_string()))
-            .projection_expression("#Id, #Lock")
-            .expression_attribute_names("#Id", "id")
-            .expression_attribute_names("#Lock", "lock")
-            .send()
-            .await
+
+    fn lock_from_attributes(
+        &self,
+        attributes: &HashMap<String, AttributeValue>,
+    ) -> Option<StorageLock> {
+        let lock_json = attributes.get(&self.attribute_names.lock)?.as_s().ok()?;
+        let lock: StorageLock = serde_json::from_str(lock_json).ok()?;
+        match attributes
+            .get(LOCK_TOKEN_ATTRIBUTE)
+            .map(AttributeValue::as_s)
         {
-            Ok(o) => {
-                let Some(item) = o.item else {
-                    // item does not exist so lock can't be valid
-                    return Ok(false);
-                };
-                // tracing::info!("{item:#?}");
-                self.update_highest_seen_id(&id);
-                let Some(lock_json) = item.get("lock") else {
-                    // item has no lock so lock can't be valid
-                    return Ok(false);
-                };
-                let Ok(lock_json) = lock_json.as_s() else {
-                    // item lock has wrong type so lock can't be valid
-                    return Ok(false);
-                };
+            Some(Ok(token)) => Some(lock.with_token(token)),
+            _ => Some(lock),
+        }
+    }
 
-                let Ok(db_lock) = serde_json::from_str::<StorageLock>(lock_json) else {
-                    // item lock has wrong content so lock can't be valid
-                    return Ok(false);
-                };
+    /// Fails with [StorageError::Corrupt] if the data can't be read.
+    fn item_from_data(id: &impl std::fmt::Display, data: &AttributeValue) -> Result<ITEM> {
+        let raw = Self::raw_from_data(data).map_err(|e| corrupt(id, e))?;
+        deserialize_stored::<ITEM>(id, &raw)
+    }
 
-                Ok(*lock == db_lock)
+    fn raw_from_data(data: &AttributeValue) -> Result<Vec<u8>> {
+        match data {
+            AttributeValue::S(data) => Ok(data.as_bytes().to_vec()),
+            AttributeValue::M(_) => {
+                let json: serde_json::Value = serde_dynamo::from_attribute_value(data.clone())?;
+                Ok(serde_json::to_vec(&json)?)
             }
-            Err(e) => {
-                tracing::warn!("Check - GetItem {id} failure {e:?}");
-                Err(eyre!(":TODO:"))
+            o => Err(eyre!("Unsupported data attribute {:?}", Redacted(&o))),
+        }
+    }
+
+    /// Note: The filter is applied as a scan filter,
+    /// so pages may be short or empty while the scan continues.
+    async fn scan_ids_matching(
+        &self,
+        filter: &IdFilter,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        let keys_are_ids = self.key_hasher.is_none() && self.id_codec.is_none();
+        if keys_are_ids || matches!(filter, IdFilter::ModifiedBefore(_)) {
+            let mut page = self
+                .scan_ids_with_scan_filter(
+                    scan_filter(filter, &self.attribute_names, &self.key_prefix),
+                    start,
+                    limit,
+                )
+                .await?;
+            // the item count of DescribeTable, only for unfiltered scans of the whole table
+            if self.key_prefix.is_empty() && *filter == IdFilter::Prefix(String::new()) {
+                page.approximate_total = self.approximate_item_count();
             }
+
+            return Ok(page);
         }
+        // the shard comes before the id, or the id is encoded, so ids can only be filtered after reading
+        let mut page = self
+            .scan_ids_with_scan_filter(
+                scan_filter(
+                    &IdFilter::Prefix(String::new()),
+                    &self.attribute_names,
+                    &self.key_prefix,
+                ),
+                start,
+                limit,
+            )
+            .await?;
+        page.items
+            .retain(|id| filter.matches(&id.to_string(), None));
+
+        Ok(page)
     }
-    async fn all_ids(&self) -> Result<Vec<ITEM::ID>> {
-        todo!();
-        // Ok(Vec::default())
+
+    async fn scan_ids_with_scan_filter(
+        &self,
+        filter: ScanFilter,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        let (items, scan_pos) = self.scan_page(filter, "#Id", start, limit).await?;
+        // :TODO: map and collect ?
+        let mut ids = Vec::default();
+        for item in items {
+            if let Some(ida) = item.get(&self.attribute_names.id) {
+                if let Some(id_s) = ida.as_s().ok().and_then(|k| self.id_from_key(k)) {
+                    let id: ITEM::ID = ITEM::ID::from_string(&id_s)?;
+                    // :LATER: self.update_highest_seen_id(&id);
+                    ids.push(id);
+                }
+            }
+        }
+        Ok(Page::new(ids, scan_pos))
     }
-    async fn scan_ids(
+
+    /// One page of a scan, with the attributes in `projection` and the scan position.
+    ///
+    /// The scan position is the raw key DynamoDB stopped at, e.g. of another storage sharing the table,
+    /// and is passed back unchanged. All names in `projection` must be in the names of `filter`.
+    async fn scan_page(
         &self,
+        filter: ScanFilter,
+        projection: &str,
         start: Option<&str>,
         limit: Option<usize>,
-    ) -> Result<(Vec<ITEM::ID>, Option<String>)> {
-        // tracing::info!("Scanning Ids: {start:?} {limit:?}");
+    ) -> Result<(Vec<HashMap<String, AttributeValue>>, Option<String>)> {
         let client = self.client().await?;
         let mut scan = client
             .scan()
             .table_name(&self.table_name)
-            .projection_expression("#Id")
-            .expression_attribute_names("#Id", "id");
+            .projection_expression(projection)
+            .filter_expression(filter.filter_expression)
+            .set_expression_attribute_names(Some(filter.names))
+            .set_expression_attribute_values(Some(filter.values));
         if let Some(start) = start {
-            scan = scan.exclusive_start_key("id", AttributeValue::S(start.to_string()));
+            scan = scan.exclusive_start_key(
+                &self.attribute_names.id,
+                AttributeValue::S(start.to_string()),
+            );
         }
         if let Some(limit) = limit {
             scan = scan.limit(limit as i32);
         }
-        match scan.send().await {
+        if let Some(pacers) = &self.scan_pacers {
+            pacers.read.wait().await;
+            scan = scan.return_consumed_capacity(ReturnConsumedCapacity::Total);
+        }
+        match self
+            .retry_policy
+            .run("Scanning Ids - Scan", || scan.clone().send())
+            .await
+        {
             Ok(ScanOutput {
                 items,
                 last_evaluated_key,
+                consumed_capacity,
                 ..
             }) => {
+                if let (Some(pacers), Some(consumed)) = (&self.scan_pacers, &consumed_capacity) {
+                    pacers.read.consumed(consumed);
+                }
                 // tracing::info!("Scanning Ids - Scan success {items:?} {last_evaluated_key:?}");
-
-                // :TODO: flatten
                 let scan_pos = match last_evaluated_key {
-                    None => None,
-                    Some(k) => {
-                        if let Some(last_id) = k.get("id") {
-                            if let Ok(last_id_s) = last_id.as_s() {
-                                Some(last_id_s.to_string())
-                            } else {
-                                None
-                            }
-                        } else {
-                            None
-                        }
-                    }
-                };
-                // :TODO: map and collect ?
-                let mut ids = Vec::default();
-                if let Some(items) = items {
-                    for item in items {
-                        if let Some(ida) = item.get("id") {
-                            if let Ok(id_s) = ida.as_s() {
-                                let id: ITEM::ID = ITEM::make_id(id_s)?;
-                                // :LATER: self.update_highest_seen_id(&id);
-                                ids.push(id);
-                            }
+                    Some(key) => {
+                        match key.get(&self.attribute_names.id).map(AttributeValue::as_s) {
+                            Some(Ok(key)) => Some(key.clone()),
+                            _ => return Err(eyre!("Scan stopped at an unexpected key {key:?}")),
                         }
                     }
+                    None => None,
                 };
-                Ok((ids, scan_pos))
+
+                Ok((items.unwrap_or_default(), scan_pos))
             }
             Err(e) => {
                 tracing::warn!("Scanning Ids - Scan failure {e:?}");
@@ -487,116 +1147,2782 @@ impl<ITEM: StorageItem + std::marker::Send> Storage<ITEM> for StorageDynamoDb<IT
         }
     }
 
-    async fn display_lock(&self, id: &ITEM::ID) -> Result<String> {
-        let client = self.client().await?;
-        match client
-            .get_item()
-            .table_name(&self.table_name)
-            .key("id", AttributeValue::S(id.to_string()))
-            .projection_expression("#Lock")
-            .expression_attribute_names("#Lock", "lock")
-            .send()
-            .await
-        {
-            Ok(GetItemOutput { mut item, .. }) => {
-                // tracing::info!("Display Lock - GetItem {id} success {item:?}");
-                if let Some(item) = item.take() {
-                    // locked
-                    let Some(lock_json) = item.get("lock") else {
-                        // not locked
-                        return Ok(String::default());
-                    };
-                    let lock_json = lock_json.as_s().map_err(|e| eyre!(":TODO: {e:?}"))?;
-                    let lock: StorageLock = serde_json::from_str(lock_json)?;
-                    let lock_string = format!("Locked by {} at {:?}", lock.who(), lock.when());
+    /// Scans for matching items in large pages and deletes each page via [Self::batch_delete].
+    #[cfg(feature = "wipe")]
+    async fn wipe_scanned(
+        &self,
+        filter: &IdFilter,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        let mut report = crate::WipeReport::new(options);
+        let mut tracker = crate::ProgressTracker::new(progress, None).with_cancellation(cancel);
+        let mut scan_pos: Option<String> = None;
+        loop {
+            // pages are deleted as a whole
+            tracker.ensure_not_cancelled()?;
+            let Page {
+                items: ids, cursor, ..
+            } = self
+                .scan_ids_matching(filter, scan_pos.as_deref(), Some(WIPE_SCAN_LIMIT))
+                .await?;
+            scan_pos = cursor;
 
-                    Ok(lock_string)
-                } else {
-                    // not locked
-                    Ok(String::default())
-                }
+            if !options.is_dry_run() {
+                // fails unless all items were deleted
+                self.batch_delete(&ids).await?;
             }
-            Err(e) => {
-                tracing::warn!("Display Lock  - GetItem {id} failure {e:?}");
-                Err(eyre!(":TODO: {e:?}"))
+            for id in ids.iter() {
+                report.record(id);
+                tracker.advance(id);
+            }
+
+            if scan_pos.is_none() {
+                break;
             }
         }
-    }
-    #[cfg(feature = "metadata")]
-    async fn metadata_highest_seen_id(&self) -> Option<ITEM::ID> {
-        self.metadata.highest_seen_id()
+        tracker.finish();
+
+        Ok(report)
     }
 
+    /// Deletes the given items via `BatchWriteItem`, retrying unprocessed items.
+    /// Returns the number of deleted items.
     #[cfg(feature = "wipe")]
-    async fn wipe(&self, confirmation: &str) -> Result<()> {
-        if confirmation != "Yes, I know what I am doing!" {
-            tracing::error!("Please confirm you know what you are doing");
-            return Err(eyre!("Unconfirmed wipe attempt"));
-        }
-
+    async fn batch_delete(&self, ids: &[ITEM::ID]) -> Result<usize> {
+        let client = self.client().await?;
         let mut count = 0;
-        let mut scan_pos: Option<String> = None;
-        loop {
-            let (ids, new_scan_pos) = self.scan_ids(scan_pos.as_deref(), Some(3)).await?;
-            scan_pos = new_scan_pos;
-
-            for id in ids {
-                tracing::info!("Deleting {id}");
-                let client = self.client().await?;
-                match client
-                    .delete_item()
-                    .table_name(&self.table_name)
-                    .key("id", AttributeValue::S(id.to_string()))
-                    .return_values(ReturnValue::None)
-                    .send()
-                    .await
-                {
-                    Ok(o) => {
-                        tracing::info!("Deleting - UpdateItem {id} success {o:?}");
-                        self.update_highest_seen_id(&id);
-                        count += 1;
+        for chunk in ids.chunks(BATCH_WRITE_ITEM_LIMIT) {
+            let mut requests = Vec::with_capacity(chunk.len());
+            for id in chunk {
+                let delete = DeleteRequest::builder()
+                    .key(&self.attribute_names.id, self.key(id))
+                    .build()?;
+                requests.push(WriteRequest::builder().delete_request(delete).build());
+            }
+            let mut request_items = HashMap::from([(self.table_name.clone(), requests)]);
+            let mut attempt = 0;
+            loop {
+                let consumed_capacity = match &self.scan_pacers {
+                    Some(pacers) => {
+                        pacers.write.wait().await;
+                        Some(ReturnConsumedCapacity::Total)
                     }
-                    Err(e) => {
-                        tracing::warn!("Deleting - UpdateItem {id} failure {e:?}");
+                    None => None,
+                };
+                let o = self
+                    .retry_policy
+                    .run("BatchWriteItem", || {
+                        client
+                            .batch_write_item()
+                            .set_request_items(Some(request_items.clone()))
+                            .set_return_consumed_capacity(consumed_capacity.clone())
+                            .send()
+                    })
+                    .await
+                    .map_err(|e| eyre!("BatchWriteItem failed -> {e:?}"))?;
+                if let Some(pacers) = &self.scan_pacers {
+                    for consumed in o.consumed_capacity() {
+                        pacers.write.consumed(consumed);
                     }
                 }
-            }
 
-            if scan_pos.is_none() {
-                break;
+                let unprocessed = o.unprocessed_items.unwrap_or_default();
+                let unprocessed_count: usize = unprocessed.values().map(|r| r.len()).sum();
+                count += chunk.len() - unprocessed_count;
+                if unprocessed_count == 0 {
+                    break;
+                }
+                attempt += 1;
+                if attempt > BATCH_MAX_RETRIES {
+                    return Err(eyre!(
+                        "BatchWriteItem left {unprocessed_count} items unprocessed after {BATCH_MAX_RETRIES} retries"
+                    ));
+                }
+                tracing::debug!("BatchWriteItem retrying {unprocessed_count} unprocessed items");
+                tokio::time::sleep(self.retry_policy.delay(attempt)).await;
+                request_items = unprocessed;
             }
         }
+        for id in ids {
+            self.update_highest_seen_id(id);
+        }
 
-        tracing::warn!("Deleted {count} items");
-        Ok(())
+        Ok(count)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::Storage;
-    use crate::StorageDynamoDb;
-    use crate::StorageItem;
-    use color_eyre::Result;
-    use serde::Deserialize;
-    use serde::Serialize;
+    /// Deletes the item via a conditional `DeleteItem`, unless it is locked.
+    #[cfg(feature = "wipe")]
+    async fn delete_unlocked(
+        &self,
+        client: &aws_sdk_dynamodb::Client,
+        id: &ITEM::ID,
+    ) -> crate::DeleteOutcome {
+        match self
+            .retry_policy
+            .run_conditional("Delete - DeleteItem", || {
+                client
+                    .delete_item()
+                    .table_name(&self.table_name)
+                    .key(&self.attribute_names.id, self.key(id))
+                    .condition_expression("attribute_not_exists(#Lock)")
+                    .expression_attribute_names("#Lock", &self.attribute_names.lock)
+                    .return_values(ReturnValue::AllOld)
+                    .return_values_on_condition_check_failure(
+                        ReturnValuesOnConditionCheckFailure::AllOld,
+                    )
+                    .send()
+            })
+            .await
+        {
+            Ok(o) => {
+                self.update_highest_seen_id(id);
+                match o.attributes() {
+                    Some(a) if a.contains_key(&self.attribute_names.data) => {
+                        crate::DeleteOutcome::Deleted
+                    }
+                    _ => crate::DeleteOutcome::NotFound,
+                }
+            }
+            Err(SdkError::ServiceError(se))
+                if matches!(
+                    se.err(),
+                    DeleteItemError::ConditionalCheckFailedException(_)
+                ) =>
+            {
+                let lock = match se.err() {
+                    DeleteItemError::ConditionalCheckFailedException(ccf) => {
+                        ccf.item().and_then(|a| self.lock_from_attributes(a))
+                    }
+                    _ => None,
+                };
+                crate::DeleteOutcome::Locked {
+                    who: lock.map(|l| l.who().to_string()).unwrap_or_default(),
+                }
+            }
+            Err(e) => {
+                tracing::warn!("Delete - DeleteItem {id} failure {e:?}");
+                crate::DeleteOutcome::Failed {
+                    message: backend_error(StorageOperation::DeleteMany, &e).to_string(),
+                }
+            }
+        }
+    }
+
+    /// Use the given region instead of the one from the environment.
+    pub fn set_region(&mut self, region: &str) -> Result<()> {
+        self.region = Some(String::from(region));
+
+        Ok(())
+    }
+
+    /// Use the given profile from the shared AWS config/credentials files.
+    pub fn set_profile(&mut self, profile: &str) -> Result<()> {
+        self.profile = Some(String::from(profile));
+
+        Ok(())
+    }
+
+    /// Use static credentials instead of the default credential chain.
+    pub fn set_credentials(
+        &mut self,
+        access_key_id: &str,
+        secret_access_key: &str,
+        session_token: Option<&str>,
+    ) -> Result<()> {
+        self.credentials = Some(Credentials::new(
+            access_key_id,
+            secret_access_key,
+            session_token.map(String::from),
+            None,
+            "oml-storage",
+        ));
+
+        Ok(())
+    }
+
+    /// Assume the given role, using the otherwise configured credentials as source.
+    pub fn set_assume_role(&mut self, role_arn: &str, session_name: &str) -> Result<()> {
+        self.assume_role = Some((String::from(role_arn), String::from(session_name)));
+
+        Ok(())
+    }
+
+    /// Use a preconfigured [SdkConfig].
+    /// Region, profile, credentials and assume role settings are ignored, the endpoint url is still applied.
+    pub fn set_sdk_config(&mut self, sdk_config: SdkConfig) -> Result<()> {
+        self.sdk_config = Some(sdk_config);
+
+        Ok(())
+    }
+
+    /// Use a preconfigured client. All other connection settings are ignored.
+    /// Its retries are disabled, the [DynamoDbRetryPolicy] retries instead.
+    pub fn set_client(&mut self, client: aws_sdk_dynamodb::Client) -> Result<()> {
+        let config = client
+            .config()
+            .to_builder()
+            .retry_config(RetryConfig::disabled());
+        #[cfg(feature = "otel")]
+        let config = config.interceptor(crate::otel::OtelContextInterceptor);
+        self.client = Some(aws_sdk_dynamodb::Client::from_conf(config.build()));
+
+        Ok(())
+    }
+
+    pub(crate) async fn sdk_config(&self) -> SdkConfig {
+        if let Some(sdk_config) = &self.sdk_config {
+            return sdk_config.clone();
+        }
+        // let config = aws_config::load_from_env().await;
+        let mut config = aws_config::defaults(aws_config::BehaviorVersion::latest());
+        if let Some(region) = &self.region {
+            config = config.region(Region::new(region.clone()));
+        }
+        if let Some(profile) = &self.profile {
+            config = config.profile_name(profile);
+        }
+        if let Some(credentials) = &self.credentials {
+            config = config.credentials_provider(credentials.clone());
+        }
+        let config = config.load().await;
+
+        if let Some((role_arn, session_name)) = &self.assume_role {
+            let provider = AssumeRoleProvider::builder(role_arn)
+                .session_name(session_name)
+                .configure(&config)
+                .build()
+                .await;
+            config
+                .into_builder()
+                .credentials_provider(SharedCredentialsProvider::new(provider))
+                .build()
+        } else {
+            config
+        }
+    }
+
+    pub(crate) async fn client(&self) -> Result<aws_sdk_dynamodb::Client> {
+        if let Some(client) = &self.client {
+            return Ok(client.clone());
+        }
+        #[cfg(feature = "metrics")]
+        let start = std::time::Instant::now();
+        let config = self.sdk_config().await;
+        let config = aws_sdk_dynamodb::config::Builder::from(&config);
+        let config = if let Some(endpoint_url) = &self.endpoint_url {
+            config.endpoint_url(endpoint_url)
+        } else {
+            config
+        };
+        // the retry policy retries, SDK retries would multiply the attempts, and retry ambiguous conditional writes
+        let config = config.retry_config(RetryConfig::disabled());
+        #[cfg(feature = "otel")]
+        let config = config.interceptor(crate::otel::OtelContextInterceptor);
+        let client = aws_sdk_dynamodb::Client::from_conf(config.build());
+        #[cfg(feature = "metrics")]
+        operation_metrics::record_client_creation("dynamodb", start.elapsed());
+
+        Ok(client)
+    }
+    pub async fn ensure_table_exists(&mut self) -> Result<()> {
+        /*
+        // let config = aws_config::load_from_env().await;
+        let config = aws_config::defaults(aws_config::BehaviorVersion::latest());
+        let config = if let Some(endpoint_url) = &self.endpoint_url {
+            config.endpoint_url(endpoint_url)
+        } else {
+            config
+        };
+        let config = config.load().await;
+        let client = aws_sdk_dynamodb::Client::new(&config);
+        */
+        let client = self.client().await?;
+
+        match self
+            .retry_policy
+            .run("Describe Table - DescribeTable", || {
+                client.describe_table().table_name(&self.table_name).send()
+            })
+            .await
+        {
+            Ok(o) => {
+                // tracing::info!("Table {} exists -> {o:#?}", &self.table_name);
+                tracing::info!("Table {} exists", &self.table_name);
+                let Some(table) = o.table() else {
+                    return Err(eyre!("Table {} has no description", &self.table_name));
+                };
+                self.verify_table(table)?;
+                if let Some(pacers) = self.scan_throttle.pacers(table.provisioned_throughput())? {
+                    self.scan_pacers = Some(pacers);
+                }
+                // DynamoDB updates these about every six hours, and they include internal items
+                if let Some(item_count) = table.item_count() {
+                    let total_bytes = table.table_size_bytes().map(|b| b.max(0) as u64);
+                    self.record_totals(item_count.max(0) as u64, total_bytes);
+                }
+            }
+            Err(e) => {
+                // tracing::debug!("Err {e:?}");
+                match e {
+                    SdkError::ServiceError(se) => {
+                        match se.err() {
+                            ResourceNotFoundException(_nf) => {
+                                // tracing::debug!("{nf:?}");
+                                tracing::info!("Table {} not found. Creating...", &self.table_name);
+
+                                // :TODO:
+
+                                let ad_id = AttributeDefinition::builder()
+                                    .attribute_name(&self.attribute_names.id)
+                                    .attribute_type(ScalarAttributeType::S)
+                                    .build()?;
+
+                                let key_id = KeySchemaElement::builder()
+                                    .attribute_name(&self.attribute_names.id)
+                                    .key_type(KeyType::Hash)
+                                    .build()?;
+
+                                let pt = ProvisionedThroughput::builder()
+                                    .read_capacity_units(1)
+                                    .write_capacity_units(1)
+                                    .build()?;
+
+                                let r = client
+                                    .create_table()
+                                    .table_name(&self.table_name)
+                                    .attribute_definitions(ad_id)
+                                    //.attribute_definitions(ad_lock)
+                                    //.attribute_definitions(ad_data)
+                                    .key_schema(key_id)
+                                    //.key_schema(key_lock)
+                                    //.key_schema(key_data)
+                                    .provisioned_throughput(pt);
+                                let o = r.send().await?;
+                                let provisioned = o
+                                    .table_description()
+                                    .and_then(|t| t.provisioned_throughput());
+                                if let Some(pacers) = self.scan_throttle.pacers(provisioned)? {
+                                    self.scan_pacers = Some(pacers);
+                                }
+                                self.wait_for_table_active(&client).await?;
+                            }
+                            oe => return Err(eyre!("Error describing table {oe:?}")),
+                        }
+                    }
+                    _o => {
+                        todo!();
+                    }
+                }
+            }
+        };
+
+        if self.ttl.is_enabled() {
+            self.ensure_ttl_enabled(&client).await?;
+        }
+
+        // tracing::debug!("{client:?}");
+
+        // insert test data
+        /*
+        let request = client
+            .put_item()
+            .table_name(&self.table_name)
+            .item("id", AttributeValue::S(nanoid::nanoid!()))
+            .item("lock", AttributeValue::S(String::from("")))
+            .item("data", AttributeValue::S(String::from("{}")))
+            .send()
+            .await?;
+        */
+        Ok(())
+    }
+
+    /// Verifies the table uses a single string hash key, named `id` by default.
+    fn verify_table(&self, table: &TableDescription) -> Result<()> {
+        let key_schema: Vec<(&str, &KeyType)> = table
+            .key_schema()
+            .iter()
+            .map(|k| (k.attribute_name(), k.key_type()))
+            .collect();
+        let id = self.attribute_names.id.as_str();
+        if key_schema != [(id, &KeyType::Hash)] {
+            return Err(eyre!(
+                "Table {} has key schema {key_schema:?}, expected a single hash key {id:?}",
+                &self.table_name
+            ));
+        }
+
+        let id_type = table
+            .attribute_definitions()
+            .iter()
+            .find(|a| a.attribute_name() == id)
+            .map(|a| a.attribute_type());
+        if id_type != Some(&ScalarAttributeType::S) {
+            return Err(eyre!(
+                "Table {} has key {id:?} of type {id_type:?}, expected a string",
+                &self.table_name
+            ));
+        }
+
+        Ok(())
+    }
+
+    async fn wait_for_table_active(&self, client: &aws_sdk_dynamodb::Client) -> Result<()> {
+        for _ in 0..60 {
+            let o = client
+                .describe_table()
+                .table_name(&self.table_name)
+                .send()
+                .await
+                .map_err(|e| eyre!("Can't describe table {} -> {e:?}", &self.table_name))?;
+            let status = o.table().and_then(|t| t.table_status());
+            if status == Some(&TableStatus::Active) {
+                return Ok(());
+            }
+            tracing::debug!("Waiting for table {} -> {status:?}", &self.table_name);
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+        Err(eyre!("Table {} did not become active", &self.table_name))
+    }
+
+    async fn ensure_ttl_enabled(&self, client: &aws_sdk_dynamodb::Client) -> Result<()> {
+        let o = self
+            .retry_policy
+            .run("Describe TTL - DescribeTimeToLive", || {
+                client
+                    .describe_time_to_live()
+                    .table_name(&self.table_name)
+                    .send()
+            })
+            .await
+            .map_err(|e| eyre!("Can't describe TTL of {} -> {e:?}", &self.table_name))?;
+        let description = o.time_to_live_description();
+        let status = description.and_then(|d| d.time_to_live_status());
+        let attribute = description.and_then(|d| d.attribute_name());
+        match status {
+            Some(TimeToLiveStatus::Enabled) | Some(TimeToLiveStatus::Enabling) => {
+                if attribute != Some(TTL_ATTRIBUTE) {
+                    return Err(eyre!(
+                        "Table {} uses TTL attribute {attribute:?}, expected {TTL_ATTRIBUTE:?}",
+                        &self.table_name
+                    ));
+                }
+                tracing::info!("Table {} has TTL enabled", &self.table_name);
+            }
+            _ => {
+                tracing::info!(
+                    "Enabling TTL on {TTL_ATTRIBUTE:?} for table {}",
+                    &self.table_name
+                );
+                let spec = TimeToLiveSpecification::builder()
+                    .enabled(true)
+                    .attribute_name(TTL_ATTRIBUTE)
+                    .build()?;
+                client
+                    .update_time_to_live()
+                    .table_name(&self.table_name)
+                    .time_to_live_specification(spec)
+                    .send()
+                    .await
+                    .map_err(|e| eyre!("Can't enable TTL for {} -> {e:?}", &self.table_name))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Lets a locked but never saved item expire.
+    async fn expire_unsaved(
+        &self,
+        client: &aws_sdk_dynamodb::Client,
+        id: &ITEM::ID,
+        ttl: Duration,
+    ) {
+        let r = self
+            .retry_policy
+            .run_conditional("Expire Unsaved - UpdateItem", || {
+                client
+                    .update_item()
+                    .table_name(&self.table_name)
+                    .key(&self.attribute_names.id, self.key(id))
+                    .update_expression("SET #ExpiresAt = :expires_at")
+                    .expression_attribute_names("#ExpiresAt", TTL_ATTRIBUTE)
+                    .expression_attribute_values(":expires_at", expires_at(self.clock.now(), ttl))
+                    .condition_expression("attribute_not_exists(#Data)")
+                    .expression_attribute_names("#Data", &self.attribute_names.data)
+                    .send()
+            })
+            .await;
+        if let Err(e) = r {
+            // the lock is still valid, the item just won't expire on its own
+            tracing::warn!("Expire Unsaved - UpdateItem {id} failure {e:?}");
+        }
+    }
+
+    /// Removes the lock just taken on a corrupt item, conditional on our token.
+    /// Unlike [Storage::unlock] it isn't recorded, the lock was never handed out.
+    async fn release_corrupt(
+        &self,
+        client: &aws_sdk_dynamodb::Client,
+        id: &ITEM::ID,
+        lock_token: &str,
+    ) {
+        let r = self
+            .retry_policy
+            .run_conditional("Release Corrupt - UpdateItem", || {
+                client
+                    .update_item()
+                    .table_name(&self.table_name)
+                    .key(&self.attribute_names.id, self.key(id))
+                    .update_expression("REMOVE #Lock, #LockToken")
+                    .expression_attribute_names("#Lock", &self.attribute_names.lock)
+                    .expression_attribute_names("#LockToken", LOCK_TOKEN_ATTRIBUTE)
+                    .expression_attribute_values(
+                        ":lock_token",
+                        AttributeValue::S(lock_token.to_string()),
+                    )
+                    .condition_expression("#LockToken = :lock_token")
+                    .send()
+            })
+            .await;
+        if let Err(e) = r {
+            tracing::warn!("Release Corrupt - UpdateItem {id} failure {e:?}");
+        }
+    }
+}
+
+impl<ITEM: StorageItem + std::marker::Send> StorageDynamoDb<ITEM> {
+    /// The condition that `lock` is still held.
+    ///
+    /// Locks with a token compare the token attribute,
+    /// others, e.g. from older crate versions, compare the serialized lock or its legacy form
+    /// and only match stored locks without a token.
+    fn lock_condition(&self, lock: &StorageLock) -> Result<LockCondition> {
+        match lock.token() {
+            Some(token) => Ok(LockCondition {
+                expression: "#LockToken = :lock_token",
+                names: HashMap::from([(
+                    String::from("#LockToken"),
+                    String::from(LOCK_TOKEN_ATTRIBUTE),
+                )]),
+                values: HashMap::from([(
+                    String::from(":lock_token"),
+                    AttributeValue::S(token.to_string()),
+                )]),
+            }),
+            None => {
+                let mut condition = self.held_by_condition(lock)?;
+                condition.expression =
+                    "#Lock IN (:lock, :legacy_lock) AND attribute_not_exists(#LockToken)";
+                condition.names.insert(
+                    String::from("#LockToken"),
+                    String::from(LOCK_TOKEN_ATTRIBUTE),
+                );
+                Ok(condition)
+            }
+        }
+    }
+
+    /// The condition that the lock is held by `lock.who()` since `lock.when()`, whatever its token.
+    fn held_by_condition(&self, lock: &StorageLock) -> Result<LockCondition> {
+        Ok(LockCondition {
+            expression: "#Lock IN (:lock, :legacy_lock)",
+            names: HashMap::from([(String::from("#Lock"), self.attribute_names.lock.clone())]),
+            values: HashMap::from([
+                (
+                    String::from(":lock"),
+                    AttributeValue::S(serde_json::to_string_pretty(lock)?),
+                ),
+                (
+                    String::from(":legacy_lock"),
+                    AttributeValue::S(lock.legacy_json_pretty()?),
+                ),
+            ]),
+        })
+    }
+
+    /// The update for saving an item, guarded by the lock, optionally also removing the lock.
+    fn save_expression(
+        &self,
+        data: AttributeValue,
+        lock: &StorageLock,
+        unlock: bool,
+    ) -> Result<SaveExpression> {
+        let condition = self.lock_condition(lock)?;
+
+        let mut set = vec!["#Data = :data", "#ModifiedAt = :modified_at"];
+        let mut remove = Vec::default();
+        let mut names = HashMap::from([
+            (String::from("#Data"), self.attribute_names.data.clone()),
+            (
+                String::from("#ModifiedAt"),
+                String::from(MODIFIED_AT_ATTRIBUTE),
+            ),
+        ]);
+        let mut values = HashMap::from([
+            (String::from(":data"), data),
+            (
+                String::from(":modified_at"),
+                AttributeValue::N(self.clock.now().timestamp().to_string()),
+            ),
+        ]);
+        names.extend(condition.names);
+        values.extend(condition.values);
+        if unlock {
+            remove.extend(["#Lock", "#LockToken"]);
+            names.insert(String::from("#Lock"), self.attribute_names.lock.clone());
+            names.insert(
+                String::from("#LockToken"),
+                String::from(LOCK_TOKEN_ATTRIBUTE),
+            );
+        }
+        if let Some((attribute, value)) = &self.attribute_names.item_type {
+            set.push("#Type = :item_type");
+            names.insert(String::from("#Type"), attribute.clone());
+            values.insert(String::from(":item_type"), AttributeValue::S(value.clone()));
+        }
+        if self.ttl.is_enabled() {
+            names.insert(String::from("#ExpiresAt"), String::from(TTL_ATTRIBUTE));
+            if let Some(ttl) = self.ttl.item_ttl {
+                set.push("#ExpiresAt = :expires_at");
+                values.insert(
+                    String::from(":expires_at"),
+                    expires_at(self.clock.now(), ttl),
+                );
+            } else {
+                // saved items must not expire as unsaved ones
+                remove.push("#ExpiresAt");
+            }
+        }
+
+        let mut update_expression = format!("SET {}", set.join(", "));
+        if !remove.is_empty() {
+            update_expression = format!("{update_expression} REMOVE {}", remove.join(", "));
+        }
+
+        // see [StorageDynamoDb::set_immutable]
+        let condition_expression = if self.immutable {
+            format!("{} AND attribute_not_exists(#Data)", condition.expression)
+        } else {
+            String::from(condition.expression)
+        };
+
+        Ok(SaveExpression {
+            update_expression,
+            condition_expression,
+            names,
+            values,
+        })
+    }
+
+    async fn save_with_unlock(
+        &self,
+        id: &ITEM::ID,
+        data: AttributeValue,
+        lock: &StorageLock,
+        unlock: bool,
+    ) -> Result<()> {
+        ensure_valid_id::<ITEM>(id)?;
+        let expression = self.save_expression(data, lock, unlock)?;
+        self.ensure_item_fits(id, &expression)?;
+        let id = &self.resolve_alias(id).await?;
+        tracing::debug!("Saving: {id} with lock {lock:?}, unlock {unlock}");
+        let client = self.client().await?;
+        match self
+            .retry_policy
+            .run_conditional("Save - UpdateItem", || {
+                client
+                    .update_item()
+                    .table_name(&self.table_name)
+                    .key(&self.attribute_names.id, self.key(id))
+                    .update_expression(&expression.update_expression)
+                    .condition_expression(&expression.condition_expression)
+                    .set_expression_attribute_names(Some(expression.names.clone()))
+                    .set_expression_attribute_values(Some(expression.values.clone()))
+                    .return_values(ReturnValue::None)
+                    .return_values_on_condition_check_failure(
+                        ReturnValuesOnConditionCheckFailure::AllOld,
+                    )
+                    .send()
+            })
+            .await
+        {
+            Ok(o) => {
+                tracing::debug!("Save - UpdateItem {id} success {:?}", Redacted(&o));
+                self.update_highest_seen_id(id);
+                self.record_write();
+                if unlock {
+                    self.record_lock_released(id, lock);
+                }
+                Ok(())
+            }
+            Err(SdkError::ServiceError(se))
+                if matches!(
+                    se.err(),
+                    UpdateItemError::ConditionalCheckFailedException(_)
+                ) =>
+            {
+                let current = match se.err() {
+                    UpdateItemError::ConditionalCheckFailedException(ccf) => ccf.item(),
+                    _ => None,
+                };
+                let written = current.is_some_and(|a| a.contains_key(&self.attribute_names.data));
+                let locked =
+                    current.and_then(|a| self.lock_from_attributes(a)).as_ref() == Some(lock);
+                if self.immutable && written && locked {
+                    tracing::warn!("Save - UpdateItem {id} immutable");
+                    return Err(StorageError::Immutable { id: id.to_string() }.into());
+                }
+                tracing::warn!("Save - UpdateItem {id} lock invalid");
+                Err(self.lock_mismatch(id, lock, current).into())
+            }
+            Err(e) => {
+                tracing::warn!("Save - UpdateItem {id} failure {e:?}");
+                let operation = if unlock {
+                    StorageOperation::SaveAndUnlock
+                } else {
+                    StorageOperation::Save
+                };
+                Err(backend_error(operation, &e))
+            }
+        }
+    }
+
+    /// The [StorageError::LockMismatch] for a save of `id` with `lock`, from the `current` attributes of a failed condition.
+    fn lock_mismatch(
+        &self,
+        id: &ITEM::ID,
+        lock: &StorageLock,
+        current: Option<&HashMap<String, AttributeValue>>,
+    ) -> StorageError {
+        let modified = current
+            .and_then(|a| a.get(MODIFIED_AT_ATTRIBUTE))
+            .and_then(|m| m.as_n().ok())
+            .and_then(|m| m.parse().ok())
+            .and_then(|m| DateTime::<Utc>::from_timestamp(m, 0));
+        StorageError::lock_mismatch(
+            &id.to_string(),
+            lock,
+            current.and_then(|a| self.lock_from_attributes(a)).as_ref(),
+            modified,
+            self.clock.now(),
+        )
+    }
+
+    /// Saves multiple items in one `TransactWriteItems`, so either all or none get written.
+    /// Every item must be locked by the given lock. With `unlock` all locks are released too.
+    /// Fails with the [StorageError::LockMismatch] of the first item whose lock is invalid, naming all of them.
+    ///
+    /// DynamoDB limits transactions to 100 items.
+    pub async fn transact_save(
+        &self,
+        saves: &[(&ITEM::ID, &ITEM, &StorageLock)],
+        unlock: bool,
+    ) -> Result<()> {
+        if saves.len() > TRANSACT_WRITE_ITEMS_LIMIT {
+            return Err(eyre!(
+                "Can't save {} items in one transaction, the limit is {TRANSACT_WRITE_ITEMS_LIMIT}",
+                saves.len()
+            ));
+        }
+        let mut transact_items = Vec::with_capacity(saves.len());
+        let mut resolved = Vec::with_capacity(saves.len());
+        for (id, item, lock) in saves {
+            ensure_valid_id::<ITEM>(id)?;
+            let data = self.item_to_data(*id, *item)?;
+            let expression = self.save_expression(data, lock, unlock)?;
+            self.ensure_item_fits(id, &expression)?;
+            let id = self.resolve_alias(id).await?;
+            let update = Update::builder()
+                .table_name(&self.table_name)
+                .key(&self.attribute_names.id, self.key(&id))
+                .update_expression(expression.update_expression)
+                .condition_expression(expression.condition_expression)
+                .set_expression_attribute_names(Some(expression.names))
+                .set_expression_attribute_values(Some(expression.values))
+                .return_values_on_condition_check_failure(
+                    ReturnValuesOnConditionCheckFailure::AllOld,
+                )
+                .build()?;
+            transact_items.push(TransactWriteItem::builder().update(update).build());
+            resolved.push((id, *lock));
+        }
+
+        // makes retries idempotent, an applied transaction isn't applied again
+        let request_token = nanoid::nanoid!();
+        let client = self.client().await?;
+        match self
+            .retry_policy
+            .run("Transact Save - TransactWriteItems", || {
+                client
+                    .transact_write_items()
+                    .set_transact_items(Some(transact_items.clone()))
+                    .client_request_token(&request_token)
+                    .send()
+            })
+            .await
+        {
+            Ok(_o) => {
+                for (id, lock) in &resolved {
+                    self.update_highest_seen_id(id);
+                    if unlock {
+                        self.record_lock_released(id, lock);
+                    }
+                }
+                self.record_write();
+                Ok(())
+            }
+            Err(e) => {
+                if let SdkError::ServiceError(se) = &e {
+                    if let TransactWriteItemsError::TransactionCanceledException(tce) = se.err() {
+                        let failed: Vec<_> = resolved
+                            .iter()
+                            .zip(tce.cancellation_reasons())
+                            .filter(|(_, r)| r.code() == Some("ConditionalCheckFailed"))
+                            .collect();
+                        let failed_ids: Vec<String> =
+                            failed.iter().map(|((id, _), _)| id.to_string()).collect();
+                        tracing::warn!("Transact Save - Lock invalid for {failed_ids:?}");
+                        if let Some(((id, lock), reason)) = failed.first() {
+                            let mismatch = self.lock_mismatch(id, lock, reason.item());
+                            return Err(color_eyre::Report::new(mismatch)
+                                .wrap_err(format!("Lock invalid for {failed_ids:?}!")));
+                        }
+                    }
+                }
+                tracing::warn!("Transact Save - TransactWriteItems failure {e:?}");
+                Err(eyre!("Transaction failed -> {e:?}"))
+            }
+        }
+    }
+
+    /// Like [Storage::exists], but overriding the storage wide consistent read setting.
+    pub async fn exists_with_consistency(
+        &self,
+        id: &ITEM::ID,
+        consistent_read: bool,
+    ) -> Result<bool> {
+        ensure_valid_id::<ITEM>(id)?;
+        let id = &self.resolve_alias(id).await?;
+        tracing::debug!("Checking if {id} exists");
+        let client = self.client().await?;
+        match self
+            .retry_policy
+            .run("Check - GetItem", || {
+                client
+                    .get_item()
+                    .table_name(&self.table_name)
+                    .key(&self.attribute_names.id, self.key(id))
+                    .projection_expression("#Id")
+                    .expression_attribute_names("#Id", &self.attribute_names.id)
+                    .consistent_read(consistent_read)
+                    .send()
+            })
+            .await
+        {
+            Ok(o) => {
+                tracing::debug!("Check - GetItem {id} success {:?}", Redacted(&o));
+                let Some(_item) = o.item else {
+                    return Ok(false);
+                };
+                self.update_highest_seen_id(id);
+                Ok(true)
+            }
+            Err(e) => {
+                tracing::warn!("Check - GetItem {id} failure {e:?}");
+                Err(backend_error(StorageOperation::Exists, &e))
+            }
+        }
+    }
+
+    /// Like [Storage::load], but overriding the storage wide consistent read setting.
+    pub async fn load_with_consistency(
+        &self,
+        id: &ITEM::ID,
+        consistent_read: bool,
+    ) -> Result<ITEM> {
+        deserialize_stored::<ITEM>(
+            id,
+            &self.load_raw_with_consistency(id, consistent_read).await?,
+        )
+    }
+
+    async fn load_raw_with_consistency(
+        &self,
+        id: &ITEM::ID,
+        consistent_read: bool,
+    ) -> Result<Vec<u8>> {
+        ensure_valid_id::<ITEM>(id)?;
+        let id = &self.resolve_alias(id).await?;
+        let client = self.client().await?;
+        match self
+            .retry_policy
+            .run("Load - GetItem", || {
+                client
+                    .get_item()
+                    .table_name(&self.table_name)
+                    .key(&self.attribute_names.id, self.key(id))
+                    .projection_expression("#Id, #Data")
+                    .expression_attribute_names("#Id", &self.attribute_names.id)
+                    .expression_attribute_names("#Data", &self.attribute_names.data)
+                    .consistent_read(consistent_read)
+                    .send()
+            })
+            .await
+        {
+            Ok(GetItemOutput { item, .. }) => {
+                // locked, but never saved, is not found too
+                let Some(data) = item
+                    .as_ref()
+                    .and_then(|item| item.get(&self.attribute_names.data))
+                else {
+                    return Err(StorageError::NotFound { id: id.to_string() }.into());
+                };
+                let data = Self::raw_from_data(data)?;
+                self.update_highest_seen_id(id);
+
+                Ok(data)
+            }
+            Err(e) => {
+                tracing::warn!("Load - GetItem {id} failure {e:?}");
+                Err(backend_error(StorageOperation::Load, &e))
+            }
+        }
+    }
+
+    /// Like [Storage::verify_lock], but overriding the storage wide consistent read setting.
+    pub async fn verify_lock_with_consistency(
+        &self,
+        id: &ITEM::ID,
+        lock: &StorageLock,
+        consistent_read: bool,
+    ) -> Result<bool> {
+        let id = &self.resolve_alias(id).await?;
+        tracing::debug!("Checking if lock {lock:?} is correct for {id}");
+        let client = self.client().await?;
+        match self
+            .retry_policy
+            .run("Verify Lock - GetItem", || {
+                client
+                    .get_item()
+                    .table_name(&self.table_name)
+                    .key(&self.attribute_names.id, self.key(id))
+                    .projection_expression("#Id, #Lock, #LockToken")
+                    .expression_attribute_names("#Id", &self.attribute_names.id)
+                    .expression_attribute_names("#Lock", &self.attribute_names.lock)
+                    .expression_attribute_names("#LockToken", LOCK_TOKEN_ATTRIBUTE)
+                    .consistent_read(consistent_read)
+                    .send()
+            })
+            .await
+        {
+            Ok(o) => {
+                let Some(item) = o.item else {
+                    // item does not exist so lock can't be valid
+                    return Ok(false);
+                };
+                // tracing::info!("{item:#?}");
+                self.update_highest_seen_id(id);
+                let Some(db_lock) = self.lock_from_attributes(&item) else {
+                    // item has no readable lock so lock can't be valid
+                    return Ok(false);
+                };
+
+                Ok(*lock == db_lock)
+            }
+            Err(e) => {
+                tracing::warn!("Verify Lock - GetItem {id} failure {e:?}");
+                Err(backend_error(StorageOperation::VerifyLock, &e))
+            }
+        }
+    }
+
+    async fn lock_existing(&self, id: &ITEM::ID, who: &str) -> LockManyOutcome<ITEM> {
+        let id = match self.resolve_alias(id).await {
+            Ok(id) => id,
+            Err(e) => return LockManyOutcome::Failed(e),
+        };
+        match self.lock_item(&id, who, true).await {
+            Ok(Some(result)) => result.into(),
+            Ok(None) => LockManyOutcome::NotFound,
+            Err(e) => LockManyOutcome::Failed(e),
+        }
+    }
+
+    /// Locks the item, `None` if `existing_only` is set and it doesn't exist or has no data.
+    /// `id` is resolved already.
+    async fn lock_item(
+        &self,
+        id: &ITEM::ID,
+        who: &str,
+        existing_only: bool,
+    ) -> Result<Option<LockResult<ITEM>>> {
+        let lock = StorageLock::new_at(who, self.clock.now());
+        // the token is stored separately, and compared by conditions
+        let lock_json = serde_json::to_string_pretty(&lock.without_token())?;
+        let lock_token = lock.token().unwrap_or_default().to_string();
+
+        // write lock
+        let client = self.client().await?;
+        let (condition, data_names) = if existing_only {
+            (
+                "attribute_not_exists(#Lock) AND attribute_exists(#Data)",
+                Some(HashMap::from([(
+                    String::from("#Data"),
+                    self.attribute_names.data.clone(),
+                )])),
+            )
+        } else {
+            ("attribute_not_exists(#Lock)", None)
+        };
+
+        let attributes = match self
+            .retry_policy
+            .run("Lock - UpdateItem", || {
+                client
+                    .update_item()
+                    .table_name(&self.table_name)
+                    //.key(&self.attribute_names.id, AttributeValue::S(String::from(id)))
+                    .key(&self.attribute_names.id, self.key(id))
+                    //.expression_attribute_names()
+                    //.update_expression("SET #Count = if_not_exists(#Count, :zero) + :one, Images = list_append(if_not_exists(Images, :empty), :image)")
+                    .update_expression("SET #Lock = :lock, #LockToken = :lock_token")
+                    .expression_attribute_names("#Lock", &self.attribute_names.lock)
+                    .expression_attribute_names("#LockToken", LOCK_TOKEN_ATTRIBUTE)
+                    .set_expression_attribute_names(data_names.clone())
+                    .expression_attribute_values(
+                        ":lock",
+                        aws_sdk_dynamodb::types::AttributeValue::S(lock_json.clone()),
+                    )
+                    .expression_attribute_values(
+                        ":lock_token",
+                        AttributeValue::S(lock_token.clone()),
+                    )
+                    .condition_expression(condition)
+                    .return_values(ReturnValue::AllOld)
+                    .return_values_on_condition_check_failure(
+                        ReturnValuesOnConditionCheckFailure::AllOld,
+                    )
+                    .send()
+            })
+            .await
+        {
+            Ok(o) => {
+                tracing::debug!("Lock - UpdateItem {id} success {:?}", Redacted(&o));
+                let UpdateItemOutput { attributes, .. } = o;
+                attributes
+            }
+            Err(e) => {
+                let ccf = match &e {
+                    SdkError::ServiceError(se) => match se.err() {
+                        UpdateItemError::ConditionalCheckFailedException(ccf) => Some(ccf),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                let Some(ccf) = ccf else {
+                    tracing::warn!("Lock - UpdateItem {id} failure {e:?}");
+                    return Err(eyre!("Can't lock {id} for {who} -> {e:?}"));
+                };
+                let token = ccf
+                    .item()
+                    .and_then(|a| a.get(LOCK_TOKEN_ATTRIBUTE))
+                    .and_then(|t| t.as_s().ok());
+                if token != Some(&lock_token) {
+                    let lock = ccf.item().and_then(|a| self.lock_from_attributes(a));
+                    if existing_only && lock.is_none() {
+                        tracing::debug!("Lock - {id} not found");
+                        return Ok(None);
+                    }
+                    let lock = match lock {
+                        Some(lock) => lock,
+                        None => {
+                            tracing::warn!("Lock - {id} is locked, but the lock can't be read");
+                            StorageLock::new_at("", self.clock.now())
+                        }
+                    };
+                    tracing::debug!("Lock - {id} already locked by {:?}", lock.who());
+                    self.update_highest_seen_id(id);
+                    self.record_lock_contended();
+                    return Ok(Some(LockResult::already_locked(&lock)));
+                }
+                // a retry after a lost response fails on the lock written by the first attempt
+                tracing::debug!("Lock - UpdateItem {id} was applied, but the response got lost");
+                ccf.item().cloned()
+            }
+        };
+        let has_data = attributes
+            .as_ref()
+            .is_some_and(|a| a.contains_key(&self.attribute_names.data));
+        if let (false, Some(ttl)) = (has_data, self.ttl.unsaved_lock_ttl) {
+            self.expire_unsaved(&client, id, ttl).await;
+        }
+        let item = if let Some(attributes) = &attributes {
+            if let Some(data) = attributes.get(&self.attribute_names.data) {
+                match Self::item_from_data(id, data) {
+                    Ok(item) => {
+                        tracing::debug!("Lock - Got item {:?}", Redacted(&item));
+                        self.update_highest_seen_id(id);
+                        item
+                    }
+                    Err(e) => {
+                        // corrupt items aren't kept locked, saving a default over them would lose the data
+                        self.release_corrupt(&client, id, &lock_token).await;
+                        return Err(e);
+                    }
+                }
+            } else {
+                tracing::warn!("No data attribute for item");
+                ITEM::default()
+            }
+        } else {
+            tracing::warn!("No attributes for item");
+            ITEM::default()
+        };
+
+        //let item = ITEM::default();
+        self.record_lock_acquired(id, &lock);
+        Ok(Some(LockResult::Success { lock, item }))
+    }
+}
+
+#[async_trait]
+impl<ITEM: StorageItem + std::marker::Send> Storage<ITEM> for StorageDynamoDb<ITEM> {
+    #[tracing::instrument(
+        name = "storage.ensure_storage_exists",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb")
+    )]
+    async fn ensure_storage_exists(&mut self) -> Result<()> {
+        let _slow_op = SlowOp::start(
+            self.slow_op_threshold,
+            "dynamodb",
+            "ensure_storage_exists",
+            None,
+        );
+        self.ensure_table_exists().await?;
+        self.load_metadata().await?;
+        if let Some(id) = &self.probe_id {
+            let hint = format!(
+                "the credentials, the permissions on {:?}, and its key schema",
+                &self.table_name
+            );
+            probe_storage(&*self, id, &hint).await?;
+        }
+        self.start_metadata_flush().await
+    }
+
+    fn capabilities(&self) -> crate::Capabilities {
+        crate::Capabilities {
+            scan: true,
+            list: true,
+            blobs: true,
+            meta: true,
+            aliases: true,
+            links: true,
+            remove_orphaned_locks: true,
+            consistent_reads: self.consistent_read,
+            ttl: self.ttl.is_enabled(),
+            ..Default::default()
+        }
+    }
+    #[tracing::instrument(
+        name = "storage.create",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb")
+    )]
+    async fn create(&self) -> Result<ITEM::ID> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "dynamodb", "create", None);
+        if let Some(id_allocator) = &self.id_allocator {
+            let id = id_allocator.next().await?;
+            return ITEM::ID::from_string(&id.to_string());
+        }
+        // collisions are passed on, for id types that derive the next id from them
+        let mut previous = None;
+        for _ in 0..self.create_tries {
+            let id = self.id_generator.generate(previous.as_ref());
+            if !self.exists(&id).await? {
+                return Ok(id);
+            }
+            previous = Some(id);
+        }
+
+        Err(StorageError::IdExhausted {
+            tries: self.create_tries,
+        }
+        .into())
+    }
+    #[tracing::instrument(
+        name = "storage.exists",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb", id = %id)
+    )]
+    async fn exists(&self, id: &ITEM::ID) -> Result<bool> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "dynamodb", "exists", Some(id));
+        self.exists_with_consistency(id, self.consistent_read).await
+    }
+
+    #[tracing::instrument(
+        name = "storage.exists",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb", id = %id)
+    )]
+    async fn exists_with(&self, id: &ITEM::ID, options: &OpOptions) -> Result<bool> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "dynamodb", "exists", Some(id));
+        self.exists_with_consistency(id, options.consistent_read(self.consistent_read))
+            .await
+    }
+
+    #[tracing::instrument(
+        name = "storage.load",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb", id = %id)
+    )]
+    async fn load(&self, id: &ITEM::ID) -> Result<ITEM> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "dynamodb", "load", Some(id));
+        self.load_with_consistency(id, self.consistent_read).await
+    }
+
+    #[tracing::instrument(
+        name = "storage.load",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb", id = %id)
+    )]
+    async fn load_with(&self, id: &ITEM::ID, options: &OpOptions) -> Result<ITEM> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "dynamodb", "load", Some(id));
+        self.load_with_consistency(id, options.consistent_read(self.consistent_read))
+            .await
+    }
+
+    #[tracing::instrument(
+        name = "storage.load_raw",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb", id = %id)
+    )]
+    async fn load_raw(&self, id: &ITEM::ID) -> Result<Vec<u8>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "dynamodb", "load_raw", Some(id));
+        self.load_raw_with_consistency(id, self.consistent_read)
+            .await
+    }
+
+    #[tracing::instrument(
+        name = "storage.load_many",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb", count = ids.len())
+    )]
+    async fn load_many(&self, ids: &[ITEM::ID]) -> Result<Vec<Option<ITEM>>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "dynamodb", "load_many", None);
+        for id in ids {
+            ensure_valid_id::<ITEM>(id)?;
+        }
+        let resolved;
+        let ids = if self.aliases {
+            let mut r = Vec::with_capacity(ids.len());
+            for id in ids {
+                r.push(self.resolve_alias(id).await?);
+            }
+            resolved = r;
+            &resolved[..]
+        } else {
+            ids
+        };
+        let client = self.client().await?;
+        let mut found: HashMap<String, ITEM> = HashMap::with_capacity(ids.len());
+        for chunk in ids.chunks(BATCH_GET_ITEM_LIMIT) {
+            let keys = chunk
+                .iter()
+                .map(|id| HashMap::from([(self.attribute_names.id.clone(), self.key(id))]))
+                .collect();
+            let keys_and_attributes = KeysAndAttributes::builder()
+                .set_keys(Some(keys))
+                .projection_expression("#Id, #Data")
+                .expression_attribute_names("#Id", &self.attribute_names.id)
+                .expression_attribute_names("#Data", &self.attribute_names.data)
+                .consistent_read(self.consistent_read)
+                .build()?;
+            let mut request_items = HashMap::from([(self.table_name.clone(), keys_and_attributes)]);
+            let mut attempt = 0;
+            loop {
+                let o = self
+                    .retry_policy
+                    .run("BatchGetItem", || {
+                        client
+                            .batch_get_item()
+                            .set_request_items(Some(request_items.clone()))
+                            .send()
+                    })
+                    .await
+                    .map_err(|e| eyre!("BatchGetItem failed -> {e:?}"))?;
+
+                let responses = o.responses.unwrap_or_default();
+                for item in responses.into_values().flatten() {
+                    let Some(id) = item
+                        .get(&self.attribute_names.id)
+                        .and_then(|id| id.as_s().ok())
+                        .and_then(|id| self.id_from_key(id))
+                    else {
+                        tracing::warn!("BatchGetItem returned item without id");
+                        continue;
+                    };
+                    let Some(data) = item.get(&self.attribute_names.data) else {
+                        // item exists, but was never saved
+                        found.insert(id.to_string(), ITEM::default());
+                        continue;
+                    };
+                    found.insert(id.to_string(), Self::item_from_data(&id, data)?);
+                }
+
+                let unprocessed = o.unprocessed_keys.unwrap_or_default();
+                if unprocessed.values().all(|k| k.keys().is_empty()) {
+                    break;
+                }
+                attempt += 1;
+                if attempt > BATCH_MAX_RETRIES {
+                    return Err(eyre!(
+                        "BatchGetItem left keys unprocessed after {BATCH_MAX_RETRIES} retries"
+                    ));
+                }
+                tracing::debug!("BatchGetItem retrying unprocessed keys");
+                tokio::time::sleep(self.retry_policy.delay(attempt)).await;
+                request_items = unprocessed;
+            }
+        }
+
+        let items = ids
+            .iter()
+            .map(|id| {
+                let item = found.remove(&id.to_string());
+                if item.is_some() {
+                    self.update_highest_seen_id(id);
+                }
+                item
+            })
+            .collect();
+
+        Ok(items)
+    }
+
+    #[tracing::instrument(
+        name = "storage.save",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb", id = %id)
+    )]
+    async fn save(&self, id: &ITEM::ID, item: &ITEM, lock: &StorageLock) -> Result<()> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "dynamodb", "save", Some(id));
+        self.save_with_unlock(id, self.item_to_data(id, item)?, lock, false)
+            .await
+    }
+
+    #[tracing::instrument(
+        name = "storage.save_raw",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb", id = %id)
+    )]
+    async fn save_raw(&self, id: &ITEM::ID, data: &[u8], lock: &StorageLock) -> Result<()> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "dynamodb", "save_raw", Some(id));
+        self.save_with_unlock(id, self.raw_to_data(data.to_vec())?, lock, false)
+            .await
+    }
+
+    /// Saves and unlocks in a single conditional `UpdateItem`.
+    #[tracing::instrument(
+        name = "storage.save_and_unlock",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb", id = %id)
+    )]
+    async fn save_and_unlock(&self, id: &ITEM::ID, item: &ITEM, lock: StorageLock) -> Result<()> {
+        let _slow_op = SlowOp::start(
+            self.slow_op_threshold,
+            "dynamodb",
+            "save_and_unlock",
+            Some(id),
+        );
+        self.save_with_unlock(id, self.item_to_data(id, item)?, &lock, true)
+            .await
+    }
+
+    #[tracing::instrument(
+        name = "storage.lock",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb", id = %id, who = %who)
+    )]
+    async fn lock(&self, id: &ITEM::ID, who: &str) -> Result<LockResult<ITEM>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "dynamodb", "lock", Some(id));
+        ensure_valid_id::<ITEM>(id)?;
+        let id = &self.resolve_alias(id).await?;
+        self.lock_item(id, who, false)
+            .await?
+            .ok_or_else(|| eyre!("Can't lock {id} for {who} -> not found"))
+    }
+
+    /// One conditional UpdateItem per id, it returns the item too.
+    /// There is no batched UpdateItem.
+    #[tracing::instrument(
+        name = "storage.lock_and_load_many",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb", count = ids.len(), who = %who)
+    )]
+    async fn lock_and_load_many(
+        &self,
+        ids: &[ITEM::ID],
+        who: &str,
+    ) -> Result<Vec<LockManyOutcome<ITEM>>> {
+        let _slow_op = SlowOp::start(
+            self.slow_op_threshold,
+            "dynamodb",
+            "lock_and_load_many",
+            None,
+        );
+        for id in ids {
+            ensure_valid_id::<ITEM>(id)?;
+        }
+        let mut outcomes = Vec::with_capacity(ids.len());
+        for chunk in ids.chunks(LOCK_MANY_CONCURRENCY) {
+            let chunk = chunk.iter().map(|id| self.lock_existing(id, who));
+            outcomes.extend(join_all(chunk).await);
+        }
+
+        Ok(outcomes)
+    }
+
+    #[tracing::instrument(
+        name = "storage.unlock",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb", id = %id)
+    )]
+    async fn unlock(&self, id: &ITEM::ID, lock: StorageLock) -> Result<()> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "dynamodb", "unlock", Some(id));
+        let id = &self.resolve_alias(id).await?;
+        tracing::debug!("Unlocking: {id} with lock {lock:?}");
+        let mut condition = self.lock_condition(&lock)?;
+        condition
+            .names
+            .insert(String::from("#Lock"), self.attribute_names.lock.clone());
+        condition.names.insert(
+            String::from("#LockToken"),
+            String::from(LOCK_TOKEN_ATTRIBUTE),
+        );
+        let client = self.client().await?;
+        match self
+            .retry_policy
+            .run_conditional("Unlock - UpdateItem", || {
+                client
+                    .update_item()
+                    .table_name(&self.table_name)
+                    .key(&self.attribute_names.id, self.key(id))
+                    .update_expression("REMOVE #Lock, #LockToken")
+                    .condition_expression(condition.expression)
+                    .set_expression_attribute_names(Some(condition.names.clone()))
+                    .set_expression_attribute_values(Some(condition.values.clone()))
+                    .return_values(ReturnValue::None)
+                    .send()
+            })
+            .await
+        {
+            Ok(o) => {
+                tracing::debug!("Unlock - UpdateItem {id} success {:?}", Redacted(&o));
+                self.update_highest_seen_id(id);
+                self.record_lock_released(id, &lock);
+                Ok(())
+            }
+            Err(e) => {
+                tracing::warn!("Unlock - UpdateItem {id} failure {e:?}");
+                // :TODO: check if it was actually the lock that failed
+                Err(eyre!("Lock invalid!"))
+            }
+        }
+    }
+
+    /// Replaces the lock attribute, conditional on the current lock, in a single UpdateItem.
+    #[tracing::instrument(
+        name = "storage.handoff_lock",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb", id = %id, who = %new_who)
+    )]
+    async fn handoff_lock(
+        &self,
+        id: &ITEM::ID,
+        lock: StorageLock,
+        new_who: &str,
+    ) -> Result<StorageLock> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "dynamodb", "handoff_lock", Some(id));
+        let id = &self.resolve_alias(id).await?;
+        let new_lock = StorageLock::new_at(new_who, self.clock.now());
+        let mut condition = self.lock_condition(&lock)?;
+        condition
+            .names
+            .insert(String::from("#Lock"), self.attribute_names.lock.clone());
+        condition.names.insert(
+            String::from("#LockToken"),
+            String::from(LOCK_TOKEN_ATTRIBUTE),
+        );
+        condition.values.insert(
+            String::from(":new_lock"),
+            AttributeValue::S(serde_json::to_string_pretty(&new_lock.without_token())?),
+        );
+        condition.values.insert(
+            String::from(":new_lock_token"),
+            AttributeValue::S(new_lock.token().unwrap_or_default().to_string()),
+        );
+        let client = self.client().await?;
+        match self
+            .retry_policy
+            .run_conditional("Handoff Lock - UpdateItem", || {
+                client
+                    .update_item()
+                    .table_name(&self.table_name)
+                    .key(&self.attribute_names.id, self.key(id))
+                    .update_expression("SET #Lock = :new_lock, #LockToken = :new_lock_token")
+                    .condition_expression(condition.expression)
+                    .set_expression_attribute_names(Some(condition.names.clone()))
+                    .set_expression_attribute_values(Some(condition.values.clone()))
+                    .return_values(ReturnValue::None)
+                    .send()
+            })
+            .await
+        {
+            Ok(o) => {
+                tracing::debug!("Handoff Lock - UpdateItem {id} success {:?}", Redacted(&o));
+                self.record_lock_released(id, &lock);
+                self.record_lock_acquired(id, &new_lock);
+                Ok(new_lock)
+            }
+            Err(SdkError::ServiceError(se))
+                if matches!(
+                    se.err(),
+                    UpdateItemError::ConditionalCheckFailedException(_)
+                ) =>
+            {
+                tracing::warn!("Handoff Lock - UpdateItem {id} lock invalid");
+                Err(eyre!("Lock invalid!"))
+            }
+            Err(e) => {
+                tracing::warn!("Handoff Lock - UpdateItem {id} failure {e:?}");
+                Err(eyre!("Can't hand off lock of {id} to {new_who} -> {e:?}"))
+            }
+        }
+    }
+
+    /// Blobs are stored as separate binary attributes of the item,
+    /// so they are not part of loads, but count towards DynamoDB's 400KB item size limit.
+    #[tracing::instrument(
+        name = "storage.put_blob",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb", id = %id, name = %name)
+    )]
+    async fn put_blob(
+        &self,
+        id: &ITEM::ID,
+        name: &str,
+        data: &[u8],
+        lock: &StorageLock,
+    ) -> Result<()> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "dynamodb", "put_blob", Some(id));
+        ensure_valid_id::<ITEM>(id)?;
+        let id = &self.resolve_alias(id).await?;
+        let mut condition = self.lock_condition(lock)?;
+        condition.names.insert(
+            String::from("#Blob"),
+            format!("{BLOB_ATTRIBUTE_PREFIX}{name}"),
+        );
+        condition
+            .values
+            .insert(String::from(":blob"), AttributeValue::B(Blob::new(data)));
+        let client = self.client().await?;
+        match self
+            .retry_policy
+            .run_conditional("Put Blob - UpdateItem", || {
+                client
+                    .update_item()
+                    .table_name(&self.table_name)
+                    .key(&self.attribute_names.id, self.key(id))
+                    .update_expression("SET #Blob = :blob")
+                    .condition_expression(condition.expression)
+                    .set_expression_attribute_names(Some(condition.names.clone()))
+                    .set_expression_attribute_values(Some(condition.values.clone()))
+                    .send()
+            })
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                tracing::warn!("Put Blob - UpdateItem {id} {name} failure {e:?}");
+                Err(eyre!("Can't put blob {name} for {id} -> {e:?}"))
+            }
+        }
+    }
+
+    #[tracing::instrument(
+        name = "storage.get_blob",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb", id = %id, name = %name)
+    )]
+    async fn get_blob(&self, id: &ITEM::ID, name: &str) -> Result<Option<Vec<u8>>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "dynamodb", "get_blob", Some(id));
+        ensure_valid_id::<ITEM>(id)?;
+        let id = &self.resolve_alias(id).await?;
+        let client = self.client().await?;
+        let attribute = format!("{BLOB_ATTRIBUTE_PREFIX}{name}");
+        let o = self
+            .retry_policy
+            .run("Get Blob - GetItem", || {
+                client
+                    .get_item()
+                    .table_name(&self.table_name)
+                    .key(&self.attribute_names.id, self.key(id))
+                    .projection_expression("#Blob")
+                    .expression_attribute_names("#Blob", &attribute)
+                    .consistent_read(self.consistent_read)
+                    .send()
+            })
+            .await
+            .map_err(|e| eyre!("Can't get blob {name} for {id} -> {e:?}"))?;
+        match o.item.as_ref().and_then(|i| i.get(&attribute)) {
+            Some(AttributeValue::B(data)) => Ok(Some(data.clone().into_inner())),
+            Some(o) => Err(eyre!("Unsupported blob attribute {:?}", Redacted(&o))),
+            None => Ok(None),
+        }
+    }
+
+    #[tracing::instrument(
+        name = "storage.list_blobs",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb", id = %id)
+    )]
+    async fn list_blobs(&self, id: &ITEM::ID) -> Result<Vec<String>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "dynamodb", "list_blobs", Some(id));
+        ensure_valid_id::<ITEM>(id)?;
+        let id = &self.resolve_alias(id).await?;
+        // :TODO: avoid fetching the blobs
+        let client = self.client().await?;
+        let o = self
+            .retry_policy
+            .run("List Blobs - GetItem", || {
+                client
+                    .get_item()
+                    .table_name(&self.table_name)
+                    .key(&self.attribute_names.id, self.key(id))
+                    .consistent_read(self.consistent_read)
+                    .send()
+            })
+            .await
+            .map_err(|e| eyre!("Can't list blobs for {id} -> {e:?}"))?;
+        let mut names: Vec<String> = o
+            .item
+            .unwrap_or_default()
+            .into_keys()
+            .filter_map(|k| k.strip_prefix(BLOB_ATTRIBUTE_PREFIX).map(String::from))
+            .collect();
+        names.sort();
+
+        Ok(names)
+    }
+
+    #[tracing::instrument(
+        name = "storage.add_alias",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb", alias = %alias, canonical = %canonical)
+    )]
+    async fn add_alias(&self, alias: &ITEM::ID, canonical: &ITEM::ID) -> Result<()> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "dynamodb", "add_alias", Some(alias));
+        if !self.aliases {
+            return Err(eyre!(
+                "Aliases are not enabled, see StorageDynamoDb::set_aliases_enabled"
+            ));
+        }
+        ensure_valid_id::<ITEM>(alias)?;
+        ensure_valid_id::<ITEM>(canonical)?;
+        // never chain aliases
+        let canonical = self.resolve_alias(canonical).await?;
+        if alias.to_string() == canonical.to_string() {
+            return Err(eyre!("Can't alias {alias} to itself"));
+        }
+        let no_item_with_alias_id = ConditionCheck::builder()
+            .table_name(&self.table_name)
+            .key(&self.attribute_names.id, self.key(alias))
+            .condition_expression("attribute_not_exists(#Id)")
+            .expression_attribute_names("#Id", &self.attribute_names.id)
+            .build()?;
+        let canonical_exists = ConditionCheck::builder()
+            .table_name(&self.table_name)
+            .key(&self.attribute_names.id, self.key(&canonical))
+            .condition_expression("attribute_exists(#Id)")
+            .expression_attribute_names("#Id", &self.attribute_names.id)
+            .build()?;
+        let put_alias = Put::builder()
+            .table_name(&self.table_name)
+            .item(
+                &self.attribute_names.id,
+                self.internal_key(&format!("{ALIAS_ID_PREFIX}{alias}")),
+            )
+            .item("canonical", AttributeValue::S(canonical.to_string()))
+            .condition_expression("attribute_not_exists(#Id)")
+            .expression_attribute_names("#Id", &self.attribute_names.id)
+            .build()?;
+        let transact_items = vec![
+            TransactWriteItem::builder()
+                .condition_check(no_item_with_alias_id)
+                .build(),
+            TransactWriteItem::builder()
+                .condition_check(canonical_exists)
+                .build(),
+            TransactWriteItem::builder().put(put_alias).build(),
+        ];
+
+        // makes retries idempotent, an applied transaction isn't applied again
+        let request_token = nanoid::nanoid!();
+        let client = self.client().await?;
+        match self
+            .retry_policy
+            .run("Add Alias - TransactWriteItems", || {
+                client
+                    .transact_write_items()
+                    .set_transact_items(Some(transact_items.clone()))
+                    .client_request_token(&request_token)
+                    .send()
+            })
+            .await
+        {
+            Ok(_o) => Ok(()),
+            Err(e) => {
+                if let SdkError::ServiceError(se) = &e {
+                    if let TransactWriteItemsError::TransactionCanceledException(tce) = se.err() {
+                        let reasons = [
+                            "an item with that id exists",
+                            "the item is missing",
+                            "the alias already exists",
+                        ];
+                        let failed: Vec<&str> = reasons
+                            .iter()
+                            .zip(tce.cancellation_reasons())
+                            .filter(|(_, r)| r.code() == Some("ConditionalCheckFailed"))
+                            .map(|(reason, _)| *reason)
+                            .collect();
+                        return Err(eyre!("Can't alias {alias} to {canonical}: {failed:?}"));
+                    }
+                }
+                tracing::warn!("Add Alias - TransactWriteItems failure {e:?}");
+                Err(eyre!("Adding alias failed -> {e:?}"))
+            }
+        }
+    }
+
+    #[tracing::instrument(
+        name = "storage.remove_alias",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb", alias = %alias)
+    )]
+    async fn remove_alias(&self, alias: &ITEM::ID) -> Result<()> {
+        let _slow_op = SlowOp::start(
+            self.slow_op_threshold,
+            "dynamodb",
+            "remove_alias",
+            Some(alias),
+        );
+        let client = self.client().await?;
+        match self
+            .retry_policy
+            .run_conditional("Remove Alias - DeleteItem", || {
+                client
+                    .delete_item()
+                    .table_name(&self.table_name)
+                    .key(
+                        &self.attribute_names.id,
+                        self.internal_key(&format!("{ALIAS_ID_PREFIX}{alias}")),
+                    )
+                    .condition_expression("attribute_exists(#Id)")
+                    .expression_attribute_names("#Id", &self.attribute_names.id)
+                    .send()
+            })
+            .await
+        {
+            Ok(_o) => Ok(()),
+            Err(e) => {
+                if let SdkError::ServiceError(se) = &e {
+                    if se.err().is_conditional_check_failed_exception() {
+                        return Err(eyre!("{alias} is not an alias"));
+                    }
+                }
+                tracing::warn!("Remove Alias - DeleteItem failure {e:?}");
+                Err(eyre!("Removing alias failed -> {e:?}"))
+            }
+        }
+    }
+
+    #[tracing::instrument(
+        name = "storage.resolve_alias",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb", id = %id)
+    )]
+    async fn resolve_alias(&self, id: &ITEM::ID) -> Result<ITEM::ID> {
+        let _slow_op = SlowOp::start(
+            self.slow_op_threshold,
+            "dynamodb",
+            "resolve_alias",
+            Some(id),
+        );
+        if !self.aliases {
+            return Ok(id.clone());
+        }
+        let client = self.client().await?;
+        let o = self
+            .retry_policy
+            .run("Resolve Alias - GetItem", || {
+                client
+                    .get_item()
+                    .table_name(&self.table_name)
+                    .key(
+                        &self.attribute_names.id,
+                        self.internal_key(&format!("{ALIAS_ID_PREFIX}{id}")),
+                    )
+                    .projection_expression("#Canonical")
+                    .expression_attribute_names("#Canonical", "canonical")
+                    .consistent_read(self.consistent_read)
+                    .send()
+            })
+            .await
+            .map_err(|e| eyre!("Resolve Alias - GetItem {id} failed -> {e:?}"))?;
+        match o.item.as_ref().and_then(|item| item.get("canonical")) {
+            Some(AttributeValue::S(canonical)) => ITEM::ID::from_string(canonical),
+            _ => Ok(id.clone()),
+        }
+    }
+
+    #[tracing::instrument(
+        name = "storage.add_link",
+        skip_all,
+        fields(
+            backend = "dynamodb",
+            db.system = "dynamodb",
+            id = %from,
+            relation = %relation,
+            to = %to,
+        )
+    )]
+    async fn add_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "dynamodb", "add_link", Some(from));
+        ensure_valid_id::<ITEM>(from)?;
+        ensure_valid_id::<ITEM>(to)?;
+        ensure_valid_relation(relation)?;
+        let from = &self.resolve_alias(from).await?;
+        let to = &self.resolve_alias(to).await?;
+        if !self.exists(to).await? {
+            return Err(eyre!("Can't link {from} to missing item {to}"));
+        }
+        let mut condition = self.lock_condition(lock)?;
+        condition.names.insert(
+            String::from("#Link"),
+            format!("{LINK_ATTRIBUTE_PREFIX}{relation}"),
+        );
+        condition.values.insert(
+            String::from(":to"),
+            AttributeValue::Ss(vec![to.to_string()]),
+        );
+        let client = self.client().await?;
+        match self
+            .retry_policy
+            .run_conditional("Add Link - UpdateItem", || {
+                client
+                    .update_item()
+                    .table_name(&self.table_name)
+                    .key(&self.attribute_names.id, self.key(from))
+                    .update_expression("ADD #Link :to")
+                    .condition_expression(condition.expression)
+                    .set_expression_attribute_names(Some(condition.names.clone()))
+                    .set_expression_attribute_values(Some(condition.values.clone()))
+                    .send()
+            })
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                tracing::warn!("Add Link - UpdateItem {from} {relation} {to} failure {e:?}");
+                Err(eyre!("Can't link {from} to {to} via {relation} -> {e:?}"))
+            }
+        }
+    }
+
+    #[tracing::instrument(
+        name = "storage.remove_link",
+        skip_all,
+        fields(
+            backend = "dynamodb",
+            db.system = "dynamodb",
+            id = %from,
+            relation = %relation,
+            to = %to,
+        )
+    )]
+    async fn remove_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        let _slow_op = SlowOp::start(
+            self.slow_op_threshold,
+            "dynamodb",
+            "remove_link",
+            Some(from),
+        );
+        ensure_valid_id::<ITEM>(from)?;
+        ensure_valid_relation(relation)?;
+        let from = &self.resolve_alias(from).await?;
+        let to = &self.resolve_alias(to).await?;
+        let mut condition = self.lock_condition(lock)?;
+        condition.names.insert(
+            String::from("#Link"),
+            format!("{LINK_ATTRIBUTE_PREFIX}{relation}"),
+        );
+        condition.values.insert(
+            String::from(":to"),
+            AttributeValue::Ss(vec![to.to_string()]),
+        );
+        condition
+            .values
+            .insert(String::from(":to_id"), AttributeValue::S(to.to_string()));
+        let condition_expression = format!("{} AND contains(#Link, :to_id)", condition.expression);
+        let client = self.client().await?;
+        match self
+            .retry_policy
+            .run_conditional("Remove Link - UpdateItem", || {
+                client
+                    .update_item()
+                    .table_name(&self.table_name)
+                    .key(&self.attribute_names.id, self.key(from))
+                    .update_expression("DELETE #Link :to")
+                    .condition_expression(&condition_expression)
+                    .set_expression_attribute_names(Some(condition.names.clone()))
+                    .set_expression_attribute_values(Some(condition.values.clone()))
+                    .send()
+            })
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                if let SdkError::ServiceError(se) = &e {
+                    if se.err().is_conditional_check_failed_exception() {
+                        return Err(eyre!(
+                            "Can't remove link from {from} to {to} via {relation}, lock invalid, or no such link"
+                        ));
+                    }
+                }
+                tracing::warn!("Remove Link - UpdateItem {from} {relation} {to} failure {e:?}");
+                Err(eyre!("Removing link failed -> {e:?}"))
+            }
+        }
+    }
+
+    #[tracing::instrument(
+        name = "storage.links_of",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb", id = %id, relation = %relation)
+    )]
+    async fn links_of(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "dynamodb", "links_of", Some(id));
+        ensure_valid_id::<ITEM>(id)?;
+        ensure_valid_relation(relation)?;
+        let id = &self.resolve_alias(id).await?;
+        let client = self.client().await?;
+        let attribute = format!("{LINK_ATTRIBUTE_PREFIX}{relation}");
+        let o = self
+            .retry_policy
+            .run("Links Of - GetItem", || {
+                client
+                    .get_item()
+                    .table_name(&self.table_name)
+                    .key(&self.attribute_names.id, self.key(id))
+                    .projection_expression("#Link")
+                    .expression_attribute_names("#Link", &attribute)
+                    .consistent_read(self.consistent_read)
+                    .send()
+            })
+            .await
+            .map_err(|e| eyre!("Can't get links {relation} of {id} -> {e:?}"))?;
+        let mut to = match o.item.as_ref().and_then(|i| i.get(&attribute)) {
+            Some(AttributeValue::Ss(to)) => to.clone(),
+            Some(o) => return Err(eyre!("Unsupported link attribute {o:?}")),
+            None => Vec::default(),
+        };
+        to.sort();
+
+        to.iter().map(|to| ITEM::ID::from_string(to)).collect()
+    }
+
+    #[tracing::instrument(
+        name = "storage.linked_from",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb", id = %id, relation = %relation)
+    )]
+    async fn linked_from(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "dynamodb", "linked_from", Some(id));
+        ensure_valid_id::<ITEM>(id)?;
+        ensure_valid_relation(relation)?;
+        let id = self.resolve_alias(id).await?;
+        let mut from = Vec::default();
+        let mut scan_pos = None;
+        loop {
+            let mut filter = scan_filter(
+                &IdFilter::Prefix(String::new()),
+                &self.attribute_names,
+                &self.key_prefix,
+            );
+            filter
+                .filter_expression
+                .push_str(" AND contains(#Link, :to)");
+            filter.names.insert(
+                String::from("#Link"),
+                format!("{LINK_ATTRIBUTE_PREFIX}{relation}"),
+            );
+            filter
+                .values
+                .insert(String::from(":to"), AttributeValue::S(id.to_string()));
+            let page = self
+                .scan_ids_with_scan_filter(filter, scan_pos.as_deref(), None)
+                .await?;
+            from.extend(page.items);
+            scan_pos = page.cursor;
+            if scan_pos.is_none() {
+                break;
+            }
+        }
+        from.sort_by_key(|id| id.to_string());
+
+        Ok(from)
+    }
+
+    #[tracing::instrument(
+        name = "storage.put_meta",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb", key = %key)
+    )]
+    async fn put_meta(&self, key: &str, value: &[u8]) -> Result<()> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "dynamodb", "put_meta", None);
+        ensure_valid_meta_key(key)?;
+        let client = self.client().await?;
+        self.retry_policy
+            .run("Put Meta - PutItem", || {
+                client
+                    .put_item()
+                    .table_name(&self.table_name)
+                    .item(
+                        &self.attribute_names.id,
+                        self.internal_key(&format!("{META_ID_PREFIX}{key}")),
+                    )
+                    .item("value", AttributeValue::B(Blob::new(value)))
+                    .send()
+            })
+            .await
+            .map_err(|e| {
+                tracing::warn!("Put Meta - PutItem {key} failure {e:?}");
+                eyre!("Can't put meta value {key} -> {e:?}")
+            })?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(
+        name = "storage.get_meta",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb", key = %key)
+    )]
+    async fn get_meta(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "dynamodb", "get_meta", None);
+        ensure_valid_meta_key(key)?;
+        let client = self.client().await?;
+        let o = self
+            .retry_policy
+            .run("Get Meta - GetItem", || {
+                client
+                    .get_item()
+                    .table_name(&self.table_name)
+                    .key(
+                        &self.attribute_names.id,
+                        self.internal_key(&format!("{META_ID_PREFIX}{key}")),
+                    )
+                    .consistent_read(true)
+                    .send()
+            })
+            .await
+            .map_err(|e| eyre!("Can't get meta value {key} -> {e:?}"))?;
+        match o.item.as_ref().and_then(|item| item.get("value")) {
+            None => Ok(None),
+            Some(AttributeValue::B(value)) => Ok(Some(value.clone().into_inner())),
+            Some(o) => Err(eyre!("Unsupported meta value {o:?} for {key}")),
+        }
+    }
+
+    #[tracing::instrument(
+        name = "storage.force_unlock",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb", id = %id)
+    )]
+    async fn force_unlock(&self, id: &ITEM::ID) -> Result<Option<StorageLock>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "dynamodb", "force_unlock", Some(id));
+        let id = &self.resolve_alias(id).await?;
+        tracing::info!("Force Unlocking: {id}");
+        let client = self.client().await?;
+        match self
+            .retry_policy
+            .run_conditional("Force Unlock - UpdateItem", || {
+                client
+                    .update_item()
+                    .table_name(&self.table_name)
+                    .key(&self.attribute_names.id, self.key(id))
+                    .update_expression("REMOVE #Lock, #LockToken")
+                    .condition_expression("attribute_exists(#Lock)")
+                    .expression_attribute_names("#Lock", &self.attribute_names.lock)
+                    .expression_attribute_names("#LockToken", LOCK_TOKEN_ATTRIBUTE)
+                    .return_values(ReturnValue::UpdatedOld)
+                    .send()
+            })
+            .await
+        {
+            Ok(o) => {
+                tracing::debug!("Force Unlock - UpdateItem {id} success {:?}", Redacted(&o));
+                self.update_highest_seen_id(id);
+                let lock = o.attributes().and_then(|a| self.lock_from_attributes(a));
+                if lock.is_none() {
+                    tracing::warn!("Force Unlock - {id} was locked, but the lock can't be read");
+                }
+                Ok(Some(lock.unwrap_or_else(|| {
+                    StorageLock::new_at("", self.clock.now())
+                })))
+            }
+            Err(SdkError::ServiceError(se))
+                if matches!(
+                    se.err(),
+                    UpdateItemError::ConditionalCheckFailedException(_)
+                ) =>
+            {
+                tracing::warn!("Force Unlock - {id} isn't locked");
+                Ok(None)
+            }
+            Err(e) => {
+                tracing::warn!("Force Unlock - UpdateItem {id} failure {e:?}");
+                Err(eyre!("Can't force unlock {id} -> {e:?}"))
+            }
+        }
+    }
+    #[tracing::instrument(
+        name = "storage.force_unlock_if_held",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb", id = %id, who = %who)
+    )]
+    async fn force_unlock_if_held(
+        &self,
+        id: &ITEM::ID,
+        who: &str,
+        when: DateTime<Utc>,
+    ) -> Result<bool> {
+        let _slow_op = SlowOp::start(
+            self.slow_op_threshold,
+            "dynamodb",
+            "force_unlock_if_held",
+            Some(id),
+        );
+        let id = &self.resolve_alias(id).await?;
+        tracing::info!("Force Unlocking: {id} if held by {who} since {when}");
+        let mut condition = self.held_by_condition(&StorageLock::observed(who, when))?;
+        condition.names.insert(
+            String::from("#LockToken"),
+            String::from(LOCK_TOKEN_ATTRIBUTE),
+        );
+        let client = self.client().await?;
+        match self
+            .retry_policy
+            .run_conditional("Force Unlock If Held - UpdateItem", || {
+                client
+                    .update_item()
+                    .table_name(&self.table_name)
+                    .key(&self.attribute_names.id, self.key(id))
+                    .update_expression("REMOVE #Lock, #LockToken")
+                    .condition_expression(condition.expression)
+                    .set_expression_attribute_names(Some(condition.names.clone()))
+                    .set_expression_attribute_values(Some(condition.values.clone()))
+                    .return_values(ReturnValue::None)
+                    .send()
+            })
+            .await
+        {
+            Ok(o) => {
+                tracing::debug!(
+                    "Force Unlock If Held - UpdateItem {id} success {:?}",
+                    Redacted(&o)
+                );
+                self.update_highest_seen_id(id);
+                Ok(true)
+            }
+            Err(SdkError::ServiceError(se))
+                if matches!(
+                    se.err(),
+                    UpdateItemError::ConditionalCheckFailedException(_)
+                ) =>
+            {
+                tracing::warn!("Force Unlock If Held - {id} isn't held by {who} since {when}");
+                Ok(false)
+            }
+            Err(e) => {
+                tracing::warn!("Force Unlock If Held - UpdateItem {id} failure {e:?}");
+                Err(eyre!("Can't force unlock {id} -> {e:?}"))
+            }
+        }
+    }
+    /// Note: This scans all items. Items that were force unlocked before their first save are removed, too.
+    /// Locks don't write the item type, so with [DynamoDbAttributeNames::item_type] records without one are removed, too.
+    #[tracing::instrument(
+        name = "storage.remove_orphaned_locks",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb")
+    )]
+    async fn remove_orphaned_locks(&self, max_age: Duration) -> Result<usize> {
+        let _slow_op = SlowOp::start(
+            self.slow_op_threshold,
+            "dynamodb",
+            "remove_orphaned_locks",
+            None,
+        );
+        let client = self.client().await?;
+        let now = self.clock.now();
+        let mut removed = 0;
+        let mut scan_pos = None;
+        loop {
+            let mut filter = scan_filter(
+                &IdFilter::Prefix(String::new()),
+                &self.attribute_names,
+                &self.key_prefix,
+            );
+            // lock only records have no type
+            filter.filter_expression = filter.filter_expression.replace(
+                "#Type = :item_type",
+                "(#Type = :item_type OR attribute_not_exists(#Type))",
+            );
+            filter
+                .filter_expression
+                .push_str(" AND attribute_not_exists(#Data)");
+            filter
+                .names
+                .insert(String::from("#Data"), self.attribute_names.data.clone());
+            filter
+                .names
+                .insert(String::from("#Lock"), self.attribute_names.lock.clone());
+            let scan = client
+                .scan()
+                .table_name(&self.table_name)
+                .projection_expression("#Id, #Lock")
+                .filter_expression(filter.filter_expression)
+                .set_expression_attribute_names(Some(filter.names))
+                .set_expression_attribute_values(Some(filter.values))
+                .set_exclusive_start_key(scan_pos);
+            let scan = match &self.scan_pacers {
+                Some(pacers) => {
+                    pacers.read.wait().await;
+                    scan.return_consumed_capacity(ReturnConsumedCapacity::Total)
+                }
+                None => scan,
+            };
+            let o = self
+                .retry_policy
+                .run("Remove Orphaned Locks - Scan", || scan.clone().send())
+                .await
+                .map_err(|e| eyre!("Can't scan for orphaned locks -> {e:?}"))?;
+            if let (Some(pacers), Some(consumed)) = (&self.scan_pacers, o.consumed_capacity()) {
+                pacers.read.consumed(consumed);
+            }
+            for item in o.items() {
+                let Some(Ok(id)) = item.get(&self.attribute_names.id).map(AttributeValue::as_s)
+                else {
+                    continue;
+                };
+                let is_orphan = self
+                    .lock_from_attributes(item)
+                    .is_none_or(|lock| is_orphan(&lock, max_age, now));
+                if !is_orphan {
+                    continue;
+                }
+                // only while still unsaved, and nobody took over the lock
+                let delete = client
+                    .delete_item()
+                    .table_name(&self.table_name)
+                    .key(&self.attribute_names.id, AttributeValue::S(id.to_string()))
+                    .expression_attribute_names("#Data", &self.attribute_names.data)
+                    .expression_attribute_names("#Lock", &self.attribute_names.lock);
+                let delete = match item.get(&self.attribute_names.lock) {
+                    Some(lock) => delete
+                        .condition_expression("attribute_not_exists(#Data) AND #Lock = :lock")
+                        .expression_attribute_values(":lock", lock.clone()),
+                    None => delete.condition_expression(
+                        "attribute_not_exists(#Data) AND attribute_not_exists(#Lock)",
+                    ),
+                };
+                match self
+                    .retry_policy
+                    .run_conditional("Remove Orphaned Locks - DeleteItem", || {
+                        delete.clone().send()
+                    })
+                    .await
+                {
+                    Ok(_o) => {
+                        tracing::info!("Removed orphaned lock of {id}");
+                        removed += 1;
+                    }
+                    Err(SdkError::ServiceError(se))
+                        if se.err().is_conditional_check_failed_exception() =>
+                    {
+                        tracing::debug!("{id} was saved, or locked again, keeping it");
+                    }
+                    Err(e) => {
+                        tracing::warn!("Remove Orphaned Locks - DeleteItem {id} failure {e:?}");
+                        return Err(eyre!("Can't remove orphaned lock of {id} -> {e:?}"));
+                    }
+                }
+            }
+            scan_pos = o.last_evaluated_key;
+            if scan_pos.is_none() {
+                break;
+            }
+        }
+
+        Ok(removed)
+    }
+    #[tracing::instrument(
+        name = "storage.verify_lock",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb", id = %id)
+    )]
+    async fn verify_lock(&self, id: &ITEM::ID, lock: &StorageLock) -> Result<bool> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "dynamodb", "verify_lock", Some(id));
+        self.verify_lock_with_consistency(id, lock, self.consistent_read)
+            .await
+    }
+    #[tracing::instrument(
+        name = "storage.verify_lock",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb", id = %id)
+    )]
+    async fn verify_lock_with(
+        &self,
+        id: &ITEM::ID,
+        lock: &StorageLock,
+        options: &OpOptions,
+    ) -> Result<bool> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "dynamodb", "verify_lock", Some(id));
+        self.verify_lock_with_consistency(id, lock, options.consistent_read(self.consistent_read))
+            .await
+    }
+    #[tracing::instrument(
+        name = "storage.all_ids",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb")
+    )]
+    async fn all_ids(&self) -> Result<Vec<ITEM::ID>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "dynamodb", "all_ids", None);
+        todo!();
+        // Ok(Vec::default())
+    }
+    #[tracing::instrument(
+        name = "storage.scan_ids",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb")
+    )]
+    async fn scan_ids(&self, start: Option<&str>, limit: Option<usize>) -> Result<Page<ITEM::ID>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "dynamodb", "scan_ids", None);
+        self.scan_ids_with_prefix("", start, limit).await
+    }
+
+    /// Note: The prefix is applied as a scan filter,
+    /// so pages may be short or empty while the scan continues.
+    #[tracing::instrument(
+        name = "storage.scan_ids_with_prefix",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb", prefix = %prefix)
+    )]
+    async fn scan_ids_with_prefix(
+        &self,
+        prefix: &str,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        let _slow_op = SlowOp::start(
+            self.slow_op_threshold,
+            "dynamodb",
+            "scan_ids_with_prefix",
+            None,
+        );
+        self.scan_ids_matching(&IdFilter::Prefix(prefix.to_string()), start, limit)
+            .await
+    }
+
+    /// Note: DynamoDB can only query by exact partition key, so all filters are scan filters.
+    #[tracing::instrument(
+        name = "storage.scan_ids_filtered",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb")
+    )]
+    async fn scan_ids_filtered(
+        &self,
+        filter: &IdFilter,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        let _slow_op = SlowOp::start(
+            self.slow_op_threshold,
+            "dynamodb",
+            "scan_ids_filtered",
+            None,
+        );
+        self.scan_ids_matching(filter, start, limit).await
+    }
+
+    /// Sizes are of the serialized data, as stored in the data attribute.
+    #[tracing::instrument(
+        name = "storage.list",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb")
+    )]
+    async fn list(
+        &self,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ItemSummary<ITEM::ID>>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "dynamodb", "list", None);
+        let mut filter = scan_filter(
+            &IdFilter::Prefix(String::new()),
+            &self.attribute_names,
+            &self.key_prefix,
+        );
+        for (name, attribute) in [
+            ("#Data", self.attribute_names.data.as_str()),
+            ("#Lock", self.attribute_names.lock.as_str()),
+            ("#ModifiedAt", MODIFIED_AT_ATTRIBUTE),
+        ] {
+            filter
+                .names
+                .insert(String::from(name), String::from(attribute));
+        }
+        let (items, scan_pos) = self
+            .scan_page(filter, "#Id, #Data, #Lock, #ModifiedAt", start, limit)
+            .await?;
+        let mut summaries = Vec::with_capacity(items.len());
+        for item in items {
+            let Some(id) = item
+                .get(&self.attribute_names.id)
+                .and_then(|id| id.as_s().ok())
+                .and_then(|id| self.id_from_key(id))
+            else {
+                continue;
+            };
+            let mut summary = ItemSummary::new(ITEM::ID::from_string(&id)?);
+            if let Some(data) = item.get(&self.attribute_names.data) {
+                summary.size = Some(Self::raw_from_data(data)?.len() as u64);
+            }
+            summary.modified = item
+                .get(MODIFIED_AT_ATTRIBUTE)
+                .and_then(|m| m.as_n().ok())
+                .and_then(|m| m.parse().ok())
+                .and_then(|m| DateTime::<Utc>::from_timestamp(m, 0));
+            let lock = self.lock_from_attributes(&item);
+            summaries.push(summary.with_lock(lock.as_ref()));
+        }
+
+        Ok(Page::new(summaries, scan_pos))
+    }
+
+    /// Only fetches the data attribute but doesn't deserialize it.
+    #[tracing::instrument(
+        name = "storage.item_size",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb", id = %id)
+    )]
+    async fn item_size(&self, id: &ITEM::ID) -> Result<u64> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "dynamodb", "item_size", Some(id));
+        ensure_valid_id::<ITEM>(id)?;
+        let id = &self.resolve_alias(id).await?;
+        let client = self.client().await?;
+        match self
+            .retry_policy
+            .run("Size - GetItem", || {
+                client
+                    .get_item()
+                    .table_name(&self.table_name)
+                    .key(&self.attribute_names.id, self.key(id))
+                    .projection_expression("#Data")
+                    .expression_attribute_names("#Data", &self.attribute_names.data)
+                    .consistent_read(self.consistent_read)
+                    .send()
+            })
+            .await
+        {
+            Ok(o) => {
+                let Some(data) = o
+                    .item
+                    .as_ref()
+                    .and_then(|i| i.get(&self.attribute_names.data))
+                else {
+                    return Err(StorageError::NotFound { id: id.to_string() }.into());
+                };
+                Ok(Self::raw_from_data(data)?.len() as u64)
+            }
+            Err(e) => {
+                tracing::warn!("Size - GetItem {id} failure {e:?}");
+                Err(backend_error(StorageOperation::ItemSize, &e))
+            }
+        }
+    }
+
+    /// Capacity units are estimated from the data attribute alone,
+    /// the id, lock, and other attributes add a little on top.
+    async fn estimate_cost(&self) -> Result<crate::StorageCost> {
+        crate::storage_cost::estimate_cost(self, true).await
+    }
+
+    /// Writes changed metadata, see [StorageDynamoDb::flush_metadata].
+    async fn shutdown(&self) -> Result<()> {
+        self.flush_metadata().await
+    }
+
+    #[tracing::instrument(
+        name = "storage.display_lock",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb", id = %id)
+    )]
+    async fn display_lock(&self, id: &ITEM::ID) -> Result<String> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "dynamodb", "display_lock", Some(id));
+        let id = &self.resolve_alias(id).await?;
+        let client = self.client().await?;
+        match self
+            .retry_policy
+            .run("Display Lock - GetItem", || {
+                client
+                    .get_item()
+                    .table_name(&self.table_name)
+                    .key(&self.attribute_names.id, self.key(id))
+                    .projection_expression("#Lock")
+                    .expression_attribute_names("#Lock", &self.attribute_names.lock)
+                    .send()
+            })
+            .await
+        {
+            Ok(GetItemOutput { mut item, .. }) => {
+                // tracing::info!("Display Lock - GetItem {id} success {item:?}");
+                if let Some(item) = item.take() {
+                    // locked
+                    let Some(lock_json) = item.get(&self.attribute_names.lock) else {
+                        // not locked
+                        return Ok(String::default());
+                    };
+                    let lock_json = lock_json.as_s().map_err(|e| eyre!(":TODO: {e:?}"))?;
+                    let lock: StorageLock = serde_json::from_str(lock_json)?;
+                    let lock_string = format!("Locked by {} at {:?}", lock.who(), lock.when());
+
+                    Ok(lock_string)
+                } else {
+                    // not locked
+                    Ok(String::default())
+                }
+            }
+            Err(e) => {
+                tracing::warn!("Display Lock  - GetItem {id} failure {e:?}");
+                Err(eyre!(":TODO: {e:?}"))
+            }
+        }
+    }
+    #[cfg(feature = "metadata")]
+    async fn metadata_highest_seen_id(&self) -> Option<ITEM::ID> {
+        self.metadata.highest_seen_id()
+    }
+    #[cfg(feature = "metadata")]
+    async fn metadata_item_count(&self) -> Option<u64> {
+        self.metadata.item_count()
+    }
+    #[cfg(feature = "metadata")]
+    async fn metadata_total_bytes(&self) -> Option<u64> {
+        self.metadata.total_bytes()
+    }
+    #[cfg(feature = "metadata")]
+    async fn metadata_last_write(&self) -> Option<DateTime<Utc>> {
+        self.metadata.last_write()
+    }
+    #[cfg(feature = "metadata")]
+    async fn metadata_lock_stats(&self) -> crate::LockStats {
+        self.metadata.lock_stats()
+    }
+
+    #[cfg(feature = "wipe")]
+    #[tracing::instrument(
+        name = "storage.wipe",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb")
+    )]
+    async fn wipe(
+        &self,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "dynamodb", "wipe", None);
+        options.ensure_confirmed()?;
+
+        let report = self
+            .wipe_scanned(&IdFilter::Prefix(String::new()), options, progress, cancel)
+            .await?;
+        if !report.dry_run {
+            tracing::warn!("Deleted {} items", report.count);
+        }
+        Ok(report)
+    }
+
+    /// Note: [IdFilter::ModifiedBefore] only matches items saved since `modified_at` is written.
+    #[cfg(feature = "wipe")]
+    #[tracing::instrument(
+        name = "storage.wipe_matching",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb")
+    )]
+    async fn wipe_matching(
+        &self,
+        filter: &IdFilter,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "dynamodb", "wipe_matching", None);
+        options.ensure_confirmed()?;
+
+        let report = self.wipe_scanned(filter, options, progress, cancel).await?;
+        if !report.dry_run {
+            tracing::warn!("Deleted {} matching items", report.count);
+        }
+        Ok(report)
+    }
+
+    /// Forced deletes use `BatchWriteItem`, which doesn't tell which items existed,
+    /// so all of them are reported as [crate::DeleteOutcome::Deleted].
+    #[cfg(feature = "wipe")]
+    #[tracing::instrument(
+        name = "storage.delete_many",
+        skip_all,
+        fields(backend = "dynamodb", db.system = "dynamodb")
+    )]
+    async fn delete_many(
+        &self,
+        ids: &[ITEM::ID],
+        options: &crate::DeleteOptions,
+    ) -> Result<Vec<crate::DeleteOutcome>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "dynamodb", "delete_many", None);
+        for id in ids {
+            ensure_valid_id::<ITEM>(id)?;
+        }
+
+        if options.force() {
+            let mut outcomes = Vec::with_capacity(ids.len());
+            for chunk in ids.chunks(BATCH_WRITE_ITEM_LIMIT) {
+                let outcome = match self.batch_delete(chunk).await {
+                    Ok(_) => crate::DeleteOutcome::Deleted,
+                    Err(e) => crate::DeleteOutcome::Failed {
+                        message: e.to_string(),
+                    },
+                };
+                outcomes.extend(std::iter::repeat_n(outcome, chunk.len()));
+            }
+            return Ok(outcomes);
+        }
+        let client = self.client().await?;
+        let mut outcomes = Vec::with_capacity(ids.len());
+        for chunk in ids.chunks(DELETE_CONCURRENCY) {
+            outcomes
+                .extend(join_all(chunk.iter().map(|id| self.delete_unlocked(&client, id))).await);
+        }
+
+        Ok(outcomes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage_dynamodb::backend_error;
+    use crate::storage_dynamodb::scan_filter;
+    use crate::DynamoDbAttributeNames;
+    use crate::DynamoDbDataFormat;
+    use crate::DynamoDbHashShards;
+    use crate::DynamoDbKeyHasher;
+    use crate::DynamoDbRetryPolicy;
+    use crate::DynamoDbTtl;
+    use crate::IdCodec;
+    use crate::IdFilter;
+    use crate::Storage;
+    use crate::StorageDynamoDb;
+    use crate::StorageError;
+    use crate::StorageId;
+    use crate::StorageItem;
+    use crate::StorageLock;
+    use crate::StorageOperation;
+    use aws_sdk_dynamodb::config::retry::RetryConfig;
+    use aws_sdk_dynamodb::config::BehaviorVersion;
+    use aws_sdk_dynamodb::config::Credentials;
+    use aws_sdk_dynamodb::config::Region;
+    use aws_sdk_dynamodb::config::RuntimeComponents;
+    use aws_sdk_dynamodb::error::SdkError;
+    use aws_sdk_dynamodb::operation::get_item::GetItemError;
+    use aws_sdk_dynamodb::types::AttributeDefinition;
+    use aws_sdk_dynamodb::types::AttributeValue;
+    use aws_sdk_dynamodb::types::KeySchemaElement;
+    use aws_sdk_dynamodb::types::KeyType;
+    use aws_sdk_dynamodb::types::ScalarAttributeType;
+    use aws_sdk_dynamodb::types::TableDescription;
+    use aws_smithy_runtime_api::client::http::HttpClient;
+    use aws_smithy_runtime_api::client::http::HttpConnector;
+    use aws_smithy_runtime_api::client::http::HttpConnectorFuture;
+    use aws_smithy_runtime_api::client::http::HttpConnectorSettings;
+    use aws_smithy_runtime_api::client::http::SharedHttpConnector;
+    use aws_smithy_runtime_api::client::orchestrator::HttpRequest;
+    use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
+    use aws_smithy_runtime_api::client::result::ConnectorError;
+    use aws_smithy_runtime_api::http::StatusCode;
+    use aws_smithy_types::body::SdkBody;
+    use chrono::Utc;
+    use color_eyre::Result;
+    use serde::Deserialize;
+    use serde::Serialize;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use std::time::Duration;
 
-    #[derive(Default, Debug, Serialize, Deserialize)]
-    struct TestItem {}
+    #[derive(Default, Debug, Serialize, Deserialize, PartialEq)]
+    struct TestItem {
+        name: String,
+        count: u32,
+        tags: Vec<String>,
+    }
 
     impl StorageItem for TestItem {
+        type ID = String;
+
         fn serialize(&self) -> Result<Vec<u8>> {
-            todo!()
+            let json = serde_json::to_string_pretty(&self)?;
+
+            Ok(json.into())
         }
-        fn deserialize(_: &[u8]) -> Result<Self> {
-            todo!()
+        fn deserialize(data: &[u8]) -> Result<Self> {
+            let i = serde_json::from_slice(data)?;
+
+            Ok(i)
         }
     }
 
     #[tokio::test]
     async fn it_debugs() -> Result<()> {
         let table_name = "test_items";
-        let storage = StorageDynamoDb::<TestItem>::new(&table_name).await;
+        let storage = StorageDynamoDb::<TestItem>::new(table_name).await;
         println!("{storage:?}");
 
         let storage: Box<dyn Storage<TestItem>> = Box::new(storage);
@@ -604,4 +3930,944 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn it_converts_data_formats() -> Result<()> {
+        let table_name = "test_items";
+        let mut storage = StorageDynamoDb::<TestItem>::new(table_name).await;
+        let id = String::from("item");
+        let item = TestItem {
+            name: String::from("test"),
+            count: 42,
+            tags: vec![String::from("a"), String::from("b")],
+        };
+
+        let data = storage.item_to_data(&id, &item)?;
+        assert!(data.is_s());
+        let string_item = StorageDynamoDb::<TestItem>::item_from_data(&id, &data)?;
+        assert_eq!(item, string_item);
+
+        storage.set_data_format(DynamoDbDataFormat::Map)?;
+        let data = storage.item_to_data(&id, &item)?;
+        assert!(data.is_m());
+        let map_item = StorageDynamoDb::<TestItem>::item_from_data(&id, &data)?;
+        assert_eq!(item, map_item);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_reads_lock_from_attributes() -> Result<()> {
+        let lock = StorageLock::new("TEST");
+        let lock_json = serde_json::to_string_pretty(&lock)?;
+        let attributes = HashMap::from([(String::from("lock"), AttributeValue::S(lock_json))]);
+
+        let mut storage = StorageDynamoDb::<TestItem>::new("test_items").await;
+        let read_lock = storage.lock_from_attributes(&attributes);
+        assert_eq!(Some(&lock), read_lock.as_ref());
+
+        let lock_json = serde_json::to_string_pretty(&lock.without_token())?;
+        let token = lock.token().unwrap_or_default();
+        let attributes = HashMap::from([
+            (String::from("lock"), AttributeValue::S(lock_json)),
+            (
+                String::from(super::LOCK_TOKEN_ATTRIBUTE),
+                AttributeValue::S(token.to_string()),
+            ),
+        ]);
+        let read_lock = storage.lock_from_attributes(&attributes);
+        assert_eq!(Some(token), read_lock.as_ref().and_then(StorageLock::token));
+
+        let attributes = HashMap::default();
+        let read_lock = storage.lock_from_attributes(&attributes);
+        assert_eq!(None, read_lock);
+
+        storage.set_attribute_names(DynamoDbAttributeNames {
+            lock: String::from("held_by"),
+            ..Default::default()
+        })?;
+        let lock = StorageLock::new("TEST");
+        let lock_json = serde_json::to_string_pretty(&lock)?;
+        let attributes = HashMap::from([(String::from("held_by"), AttributeValue::S(lock_json))]);
+        assert_eq!(Some(lock), storage.lock_from_attributes(&attributes));
+
+        assert!(storage
+            .set_attribute_names(DynamoDbAttributeNames {
+                data: String::from("lock"),
+                ..Default::default()
+            })
+            .is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_builds_save_expressions() -> Result<()> {
+        let mut storage = StorageDynamoDb::<TestItem>::new("test_items").await;
+        let id = String::from("item");
+        let item = TestItem::default();
+        let lock = StorageLock::new("TEST");
+
+        let expression =
+            storage.save_expression(storage.item_to_data(&id, &item)?, &lock, false)?;
+        assert_eq!(
+            "SET #Data = :data, #ModifiedAt = :modified_at",
+            expression.update_expression
+        );
+        let expression = storage.save_expression(storage.item_to_data(&id, &item)?, &lock, true)?;
+        assert_eq!(
+            "SET #Data = :data, #ModifiedAt = :modified_at REMOVE #Lock, #LockToken",
+            expression.update_expression
+        );
+        assert_eq!("#LockToken = :lock_token", expression.condition_expression);
+
+        let observed = StorageLock::observed(lock.who(), *lock.when());
+        let expression =
+            storage.save_expression(storage.item_to_data(&id, &item)?, &observed, false)?;
+        assert_eq!(
+            "#Lock IN (:lock, :legacy_lock) AND attribute_not_exists(#LockToken)",
+            expression.condition_expression
+        );
+        assert_eq!(
+            Some(&String::from(super::LOCK_TOKEN_ATTRIBUTE)),
+            expression.names.get("#LockToken")
+        );
+        let condition = storage.held_by_condition(&observed)?;
+        assert_eq!("#Lock IN (:lock, :legacy_lock)", condition.expression);
+        assert!(!condition.names.contains_key("#LockToken"));
+
+        storage.set_ttl(DynamoDbTtl {
+            unsaved_lock_ttl: Some(Duration::from_secs(60)),
+            ..Default::default()
+        })?;
+        let expression = storage.save_expression(storage.item_to_data(&id, &item)?, &lock, true)?;
+        assert_eq!(
+            "SET #Data = :data, #ModifiedAt = :modified_at REMOVE #Lock, #LockToken, #ExpiresAt",
+            expression.update_expression
+        );
+
+        storage.set_ttl(DynamoDbTtl {
+            item_ttl: Some(Duration::from_secs(60)),
+            ..Default::default()
+        })?;
+        let expression =
+            storage.save_expression(storage.item_to_data(&id, &item)?, &lock, false)?;
+        assert_eq!(
+            "SET #Data = :data, #ModifiedAt = :modified_at, #ExpiresAt = :expires_at",
+            expression.update_expression
+        );
+        assert!(expression.values.contains_key(":expires_at"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_rejects_oversized_items() -> Result<()> {
+        let mut storage = StorageDynamoDb::<TestItem>::new("test_items").await;
+        let id = String::from("item");
+        let lock = StorageLock::new("TEST");
+        let item = TestItem {
+            name: "x".repeat(1000),
+            ..Default::default()
+        };
+        let expression =
+            storage.save_expression(storage.item_to_data(&id, &item)?, &lock, false)?;
+        let size = expression.item_size("id", "item");
+        assert!(size > 1000 && size < 1200, "{size}");
+
+        storage.set_max_item_size(500)?;
+        let e = storage.save(&id, &item, &lock).await.unwrap_err();
+        assert_eq!(
+            Some(&StorageError::ItemTooLarge {
+                id,
+                size,
+                max_size: 500,
+            }),
+            e.downcast_ref()
+        );
+
+        assert!(storage.set_max_item_size(0).is_err());
+        assert!(storage.set_max_item_size(401 * 1024).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_classifies_backend_errors() {
+        let e: SdkError<GetItemError, ()> = SdkError::timeout_error("too slow");
+        let e = backend_error(StorageOperation::Exists, &e);
+        assert!(e.downcast_ref::<StorageError>().unwrap().is_transient());
+
+        let e: SdkError<GetItemError, ()> = SdkError::construction_failure("no region");
+        let e = backend_error(StorageOperation::Exists, &e);
+        assert!(!e.downcast_ref::<StorageError>().unwrap().is_transient());
+    }
+
+    #[test]
+    fn it_builds_scan_filters() {
+        let filter = scan_filter(
+            &IdFilter::Prefix(String::new()),
+            &DynamoDbAttributeNames::default(),
+            "",
+        );
+        assert_eq!(
+            "NOT begins_with(#Id, :counter_prefix) AND NOT begins_with(#Id, :alias_prefix) AND NOT begins_with(#Id, :meta_prefix) AND #Id <> :metadata_id",
+            filter.filter_expression
+        );
+        assert!(!filter.values.contains_key(":prefix"));
+
+        let filter = scan_filter(
+            &IdFilter::Range {
+                start: Some(String::from("b")),
+                end: None,
+            },
+            &DynamoDbAttributeNames::default(),
+            "",
+        );
+        assert!(filter.filter_expression.ends_with(" AND #Id >= :start"));
+        assert!(!filter.values.contains_key(":end"));
+
+        let filter = scan_filter(
+            &IdFilter::ModifiedBefore(Utc::now()),
+            &DynamoDbAttributeNames::default(),
+            "",
+        );
+        assert!(filter
+            .filter_expression
+            .ends_with(" AND #ModifiedAt < :before"));
+        assert_eq!(
+            Some(&String::from("modified_at")),
+            filter.names.get("#ModifiedAt")
+        );
+
+        let attribute_names = DynamoDbAttributeNames {
+            id: String::from("pk"),
+            item_type: Some((String::from("entity"), String::from("player"))),
+            ..Default::default()
+        };
+        let filter = scan_filter(&IdFilter::Prefix(String::new()), &attribute_names, "");
+        assert!(filter
+            .filter_expression
+            .ends_with(" AND #Type = :item_type"));
+        assert_eq!(Some(&String::from("pk")), filter.names.get("#Id"));
+    }
+
+    #[tokio::test]
+    async fn it_separates_key_prefixes() -> Result<()> {
+        let mut tenant = StorageDynamoDb::<TestItem>::new("test_items").await;
+        tenant.set_key_prefix("tenant")?;
+        assert_eq!("tenant#", tenant.key_prefix());
+        let mut tenant_a = StorageDynamoDb::<TestItem>::new("test_items").await;
+        tenant_a.set_key_prefix("tenant_a#")?;
+        assert_eq!("tenant_a#", tenant_a.key_prefix());
+
+        let key = tenant_a.key_string(&String::from("1234"));
+        assert_eq!("tenant_a#1234", key);
+        assert_eq!(None, tenant.id_from_key(&key));
+        let filter = scan_filter(
+            &IdFilter::Prefix(String::new()),
+            &DynamoDbAttributeNames::default(),
+            tenant.key_prefix(),
+        );
+        let Some(AttributeValue::S(prefix)) = filter.values.get(":prefix") else {
+            panic!("no prefix in {:?}", filter.values);
+        };
+        assert!(!key.starts_with(prefix.as_str()));
+
+        for invalid in ["#", "#counter#", "#alias", "tenant#a", "a#b#"] {
+            assert!(tenant.set_key_prefix(invalid).is_err(), "{invalid}");
+        }
+        assert_eq!("tenant#", tenant.key_prefix());
+        tenant.set_key_prefix("")?;
+        assert_eq!("", tenant.key_prefix());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_shards_keys() -> Result<()> {
+        let mut storage = StorageDynamoDb::<TestItem>::new("test_items").await;
+        storage.set_key_prefix("tenant#")?;
+        storage.set_key_hasher(DynamoDbHashShards::new(16)?)?;
+
+        let shard = DynamoDbHashShards::new(16)?.shard("1234");
+        let key = storage.key_string(&String::from("1234"));
+        assert_eq!(format!("tenant#{shard}#1234"), key);
+        assert_eq!(Some("1234"), storage.id_from_key(&key).as_deref());
+        assert_eq!(None, storage.id_from_key("tenant#1234"));
+        assert_eq!(
+            AttributeValue::S(String::from("tenant##alias#a")),
+            storage.internal_key("#alias#a")
+        );
+
+        Ok(())
+    }
+
+    /// Reverses ids and marks them with `~`.
+    #[derive(Debug)]
+    struct ReversedIds;
+
+    impl IdCodec for ReversedIds {
+        fn encode(&self, id: &str) -> String {
+            format!("~{}", id.chars().rev().collect::<String>())
+        }
+        fn decode(&self, key: &str) -> Option<String> {
+            Some(key.strip_prefix('~')?.chars().rev().collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn it_encodes_keys() -> Result<()> {
+        let mut storage = StorageDynamoDb::<TestItem>::new("test_items").await;
+        storage.set_key_prefix("tenant#")?;
+        storage.set_id_codec(ReversedIds)?;
+
+        let key = storage.key_string(&String::from("1234"));
+        assert_eq!("tenant#~4321", key);
+        assert_eq!(Some("1234"), storage.id_from_key(&key).as_deref());
+        assert_eq!(None, storage.id_from_key("tenant#1234"));
+
+        storage.set_key_hasher(DynamoDbHashShards::new(16)?)?;
+        let shard = DynamoDbHashShards::new(16)?.shard("~4321");
+        let key = storage.key_string(&String::from("1234"));
+        assert_eq!(format!("tenant#{shard}#~4321"), key);
+        assert_eq!(Some("1234"), storage.id_from_key(&key).as_deref());
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_prefixes_scan_filter_keys() {
+        let attribute_names = DynamoDbAttributeNames::default();
+        let filter = scan_filter(
+            &IdFilter::Prefix(String::from("a")),
+            &attribute_names,
+            "tenant#",
+        );
+        assert!(filter
+            .filter_expression
+            .starts_with("begins_with(#Id, :prefix)"));
+        assert_eq!(
+            Some(&AttributeValue::S(String::from("tenant#a"))),
+            filter.values.get(":prefix")
+        );
+        assert_eq!(
+            Some(&AttributeValue::S(String::from("tenant##alias#"))),
+            filter.values.get(":alias_prefix")
+        );
+
+        let filter = scan_filter(
+            &IdFilter::Range {
+                start: Some(String::from("b")),
+                end: None,
+            },
+            &attribute_names,
+            "tenant#",
+        );
+        assert_eq!(
+            Some(&AttributeValue::S(String::from("tenant#"))),
+            filter.values.get(":prefix")
+        );
+        assert_eq!(
+            Some(&AttributeValue::S(String::from("tenant#b"))),
+            filter.values.get(":start")
+        );
+    }
+
+    #[tokio::test]
+    async fn it_uses_configured_connection_settings() -> Result<()> {
+        let mut storage = StorageDynamoDb::<TestItem>::new("test_items").await;
+        storage.set_region("eu-west-1")?;
+        storage.set_credentials("AKIDEXAMPLE", "secret", None)?;
+        storage.set_endpoint_url("http://localhost:8000")?;
+
+        let client = storage.client().await?;
+        assert_eq!(
+            Some("eu-west-1"),
+            client.config().region().map(|r| r.as_ref())
+        );
+
+        let mut other = StorageDynamoDb::<TestItem>::new("test_items").await;
+        other.set_client(client)?;
+        let client = other.client().await?;
+        assert_eq!(
+            Some("eu-west-1"),
+            client.config().region().map(|r| r.as_ref())
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_verifies_table_schema() -> Result<()> {
+        let storage = StorageDynamoDb::<TestItem>::new("test_items").await;
+
+        let id_definition = |t| {
+            AttributeDefinition::builder()
+                .attribute_name("id")
+                .attribute_type(t)
+                .build()
+        };
+        let key = |name, key_type| {
+            KeySchemaElement::builder()
+                .attribute_name(name)
+                .key_type(key_type)
+                .build()
+        };
+
+        let table = TableDescription::builder()
+            .attribute_definitions(id_definition(ScalarAttributeType::S)?)
+            .key_schema(key("id", KeyType::Hash)?)
+            .build();
+        storage.verify_table(&table)?;
+
+        let table = TableDescription::builder()
+            .attribute_definitions(id_definition(ScalarAttributeType::N)?)
+            .key_schema(key("id", KeyType::Hash)?)
+            .build();
+        assert!(storage.verify_table(&table).is_err());
+
+        let table = TableDescription::builder()
+            .attribute_definitions(id_definition(ScalarAttributeType::S)?)
+            .key_schema(key("pk", KeyType::Hash)?)
+            .build();
+        assert!(storage.verify_table(&table).is_err());
+
+        let table = TableDescription::builder()
+            .attribute_definitions(id_definition(ScalarAttributeType::S)?)
+            .key_schema(key("id", KeyType::Hash)?)
+            .key_schema(key("sk", KeyType::Range)?)
+            .build();
+        assert!(storage.verify_table(&table).is_err());
+
+        Ok(())
+    }
+
+    /// Applies the first request but loses its response, like a timeout after DynamoDB received it.
+    /// Later requests fail their condition on the state the first one left.
+    #[derive(Debug, Clone, Default)]
+    struct LostFirstResponse {
+        requests: Arc<Mutex<Vec<serde_json::Value>>>,
+    }
+
+    impl HttpConnector for LostFirstResponse {
+        fn call(&self, request: HttpRequest) -> HttpConnectorFuture {
+            let body = request.body().bytes().unwrap_or_default();
+            let body: serde_json::Value = serde_json::from_slice(body).unwrap_or_default();
+            let mut requests = self.requests.lock().expect("can lock");
+            requests.push(body);
+            if requests.len() == 1 {
+                return HttpConnectorFuture::ready(Err(ConnectorError::timeout(
+                    "response lost".into(),
+                )));
+            }
+
+            let first = &requests[0]["ExpressionAttributeValues"];
+            let data = serde_json::to_string(&TestItem::default()).unwrap_or_default();
+            let failure = serde_json::json!({
+                "__type": "com.amazonaws.dynamodb.v20120810#ConditionalCheckFailedException",
+                "message": "The conditional request failed",
+                "Item": {
+                    "id": requests[0]["Key"]["id"],
+                    "lock": first[":lock"],
+                    "lock_token": first[":lock_token"],
+                    "data": { "S": data },
+                },
+            });
+            let status = StatusCode::try_from(400).expect("valid status");
+            HttpConnectorFuture::ready(Ok(HttpResponse::new(
+                status,
+                SdkBody::from(failure.to_string()),
+            )))
+        }
+    }
+
+    impl HttpClient for LostFirstResponse {
+        fn http_connector(
+            &self,
+            _settings: &HttpConnectorSettings,
+            _components: &RuntimeComponents,
+        ) -> SharedHttpConnector {
+            SharedHttpConnector::new(self.clone())
+        }
+    }
+
+    async fn storage_with_http_client(
+        http_client: impl HttpClient + 'static,
+    ) -> Result<StorageDynamoDb<TestItem>> {
+        item_storage_with_http_client(http_client).await
+    }
+
+    async fn item_storage_with_http_client<ITEM: StorageItem + Send>(
+        http_client: impl HttpClient + 'static,
+    ) -> Result<StorageDynamoDb<ITEM>> {
+        let config = aws_sdk_dynamodb::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("eu-west-1"))
+            .credentials_provider(Credentials::new("test", "test", None, None, "test"))
+            .endpoint_url("http://localhost:8000")
+            // disabled by set_client, the retry policy retries
+            .retry_config(RetryConfig::standard())
+            .http_client(http_client)
+            .build();
+        let mut storage = StorageDynamoDb::<ITEM>::new("test_items").await;
+        storage.set_client(aws_sdk_dynamodb::Client::from_conf(config))?;
+        storage.set_retry_policy(DynamoDbRetryPolicy {
+            base_delay: Duration::ZERO,
+            ..Default::default()
+        })?;
+
+        Ok(storage)
+    }
+
+    async fn storage_losing_first_response(
+    ) -> Result<(StorageDynamoDb<TestItem>, LostFirstResponse)> {
+        let http_client = LostFirstResponse::default();
+        let storage = storage_with_http_client(http_client.clone()).await?;
+
+        Ok((storage, http_client))
+    }
+
+    #[tokio::test]
+    async fn it_keeps_locks_whose_response_got_lost() -> Result<()> {
+        let (storage, http_client) = storage_losing_first_response().await?;
+        let id = String::from("item");
+
+        let (lock, item) = storage.lock(&id, "TEST").await?.success()?;
+        assert_eq!(TestItem::default(), item);
+        let requests = http_client.requests.lock().expect("can lock");
+        assert_eq!(2, requests.len());
+        assert_eq!(
+            lock.token(),
+            requests[0]["ExpressionAttributeValues"][":lock_token"]["S"].as_str()
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_doesnt_retry_saves_whose_response_got_lost() -> Result<()> {
+        let (storage, http_client) = storage_losing_first_response().await?;
+        let id = String::from("item");
+
+        let lock = StorageLock::new("TEST");
+        assert!(storage
+            .save_and_unlock(&id, &TestItem::default(), lock)
+            .await
+            .is_err());
+        assert_eq!(1, http_client.requests.lock().expect("can lock").len());
+
+        Ok(())
+    }
+
+    /// Fails every request, like a table that doesn't exist.
+    #[derive(Debug, Clone)]
+    struct MissingTable;
+
+    impl HttpConnector for MissingTable {
+        fn call(&self, _request: HttpRequest) -> HttpConnectorFuture {
+            let failure = serde_json::json!({
+                "__type": "com.amazonaws.dynamodb.v20120810#ResourceNotFoundException",
+                "message": "Requested resource not found",
+            });
+            let status = StatusCode::try_from(400).expect("valid status");
+            HttpConnectorFuture::ready(Ok(HttpResponse::new(
+                status,
+                SdkBody::from(failure.to_string()),
+            )))
+        }
+    }
+
+    impl HttpClient for MissingTable {
+        fn http_connector(
+            &self,
+            _settings: &HttpConnectorSettings,
+            _components: &RuntimeComponents,
+        ) -> SharedHttpConnector {
+            SharedHttpConnector::new(self.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn it_fails_loads_and_lock_checks_with_backend_errors() -> Result<()> {
+        let storage = storage_with_http_client(MissingTable).await?;
+        let id = String::from("item");
+
+        let e = storage.load_raw(&id).await.expect_err("no table");
+        assert!(matches!(
+            e.downcast_ref::<StorageError>(),
+            Some(StorageError::Backend {
+                operation: StorageOperation::Load,
+                transient: false,
+                ..
+            })
+        ));
+        let e = storage
+            .verify_lock(&id, &StorageLock::new("TEST"))
+            .await
+            .expect_err("no table");
+        assert!(matches!(
+            e.downcast_ref::<StorageError>(),
+            Some(StorageError::Backend {
+                operation: StorageOperation::VerifyLock,
+                transient: false,
+                ..
+            })
+        ));
+
+        Ok(())
+    }
+
+    /// Cancels every transaction, because the second item is locked by `OTHER`.
+    #[derive(Debug, Clone)]
+    struct LockedSecondItem;
+
+    impl HttpConnector for LockedSecondItem {
+        fn call(&self, _request: HttpRequest) -> HttpConnectorFuture {
+            let lock = serde_json::to_string(&StorageLock::new("OTHER")).expect("serializable");
+            let failure = serde_json::json!({
+                "__type": "com.amazonaws.dynamodb.v20120810#TransactionCanceledException",
+                "Message": "Transaction cancelled",
+                "CancellationReasons": [
+                    { "Code": "None" },
+                    {
+                        "Code": "ConditionalCheckFailed",
+                        "Item": {
+                            "id": { "S": "second" },
+                            "lock": { "S": lock },
+                            "data": { "S": "{\"count\":0}" },
+                        },
+                    },
+                ],
+            });
+            let status = StatusCode::try_from(400).expect("valid status");
+            HttpConnectorFuture::ready(Ok(HttpResponse::new(
+                status,
+                SdkBody::from(failure.to_string()),
+            )))
+        }
+    }
+
+    impl HttpClient for LockedSecondItem {
+        fn http_connector(
+            &self,
+            _settings: &HttpConnectorSettings,
+            _components: &RuntimeComponents,
+        ) -> SharedHttpConnector {
+            SharedHttpConnector::new(self.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn it_fails_transact_saves_with_lock_mismatches() -> Result<()> {
+        let storage = storage_with_http_client(LockedSecondItem).await?;
+        let first = String::from("first");
+        let second = String::from("second");
+        let item = TestItem::default();
+        let lock = StorageLock::new("TEST");
+
+        let e = storage
+            .transact_save(&[(&first, &item, &lock), (&second, &item, &lock)], true)
+            .await
+            .expect_err("second item locked");
+        assert_eq!(r#"Lock invalid for ["second"]!"#, e.to_string());
+        match e.downcast_ref::<StorageError>() {
+            Some(StorageError::LockMismatch {
+                id, who, holder, ..
+            }) => {
+                assert_eq!("second", id);
+                assert_eq!("TEST", who);
+                assert_eq!(Some("OTHER"), holder.as_deref());
+            }
+            e => panic!("Expected a lock mismatch, got {e:?}"),
+        }
+
+        Ok(())
+    }
+
+    /// Locks a corrupt item and records all requests.
+    #[derive(Debug, Clone, Default)]
+    struct CorruptItem {
+        requests: Arc<Mutex<Vec<serde_json::Value>>>,
+    }
+
+    impl HttpConnector for CorruptItem {
+        fn call(&self, request: HttpRequest) -> HttpConnectorFuture {
+            let body = request.body().bytes().expect("in memory body");
+            let request: serde_json::Value = serde_json::from_slice(body).expect("json body");
+            let mut requests = self.requests.lock().expect("can lock");
+            requests.push(request.clone());
+            let response = if requests.len() == 1 {
+                serde_json::json!({
+                    "Attributes": {
+                        "id": request["Key"]["id"],
+                        "data": { "S": "not json" },
+                    },
+                })
+            } else {
+                serde_json::json!({})
+            };
+            let status = StatusCode::try_from(200).expect("valid status");
+            HttpConnectorFuture::ready(Ok(HttpResponse::new(
+                status,
+                SdkBody::from(response.to_string()),
+            )))
+        }
+    }
+
+    impl HttpClient for CorruptItem {
+        fn http_connector(
+            &self,
+            _settings: &HttpConnectorSettings,
+            _components: &RuntimeComponents,
+        ) -> SharedHttpConnector {
+            SharedHttpConnector::new(self.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn it_releases_locks_of_corrupt_items_unrecorded() -> Result<()> {
+        let http_client = CorruptItem::default();
+        let storage = storage_with_http_client(http_client.clone()).await?;
+        let id = String::from("item");
+
+        let e = storage.lock(&id, "TEST").await.expect_err("corrupt");
+        assert!(matches!(
+            e.downcast_ref::<StorageError>(),
+            Some(StorageError::Corrupt { .. })
+        ));
+        let requests = http_client.requests.lock().expect("can lock").clone();
+        assert_eq!(2, requests.len());
+        assert_eq!("REMOVE #Lock, #LockToken", requests[1]["UpdateExpression"]);
+        assert_eq!(
+            "#LockToken = :lock_token",
+            requests[1]["ConditionExpression"]
+        );
+        assert_eq!(
+            requests[0]["ExpressionAttributeValues"][":lock_token"],
+            requests[1]["ExpressionAttributeValues"][":lock_token"]
+        );
+        #[cfg(feature = "metadata")]
+        {
+            let lock_stats = storage.metadata_lock_stats().await;
+            assert_eq!(0, lock_stats.held);
+            assert_eq!(0, lock_stats.hold_times.count);
+        }
+
+        Ok(())
+    }
+
+    /// Scans a table shared with another storage, the first page stops at a key of the other one.
+    #[derive(Debug, Clone, Default)]
+    struct SharedTable {
+        requests: Arc<Mutex<Vec<serde_json::Value>>>,
+    }
+
+    impl HttpConnector for SharedTable {
+        fn call(&self, request: HttpRequest) -> HttpConnectorFuture {
+            let body = request.body().bytes().expect("in memory body");
+            let request: serde_json::Value = serde_json::from_slice(body).expect("json body");
+            let mut requests = self.requests.lock().expect("can lock");
+            requests.push(request);
+            let response = if requests.len() == 1 {
+                serde_json::json!({
+                    "Items": [{ "id": { "S": "ours#a" } }],
+                    "LastEvaluatedKey": { "id": { "S": "theirs#b" } },
+                })
+            } else {
+                serde_json::json!({
+                    "Items": [{ "id": { "S": "ours#c" } }],
+                })
+            };
+            let status = StatusCode::try_from(200).expect("valid status");
+            HttpConnectorFuture::ready(Ok(HttpResponse::new(
+                status,
+                SdkBody::from(response.to_string()),
+            )))
+        }
+    }
+
+    impl HttpClient for SharedTable {
+        fn http_connector(
+            &self,
+            _settings: &HttpConnectorSettings,
+            _components: &RuntimeComponents,
+        ) -> SharedHttpConnector {
+            SharedHttpConnector::new(self.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn it_continues_scans_after_keys_of_other_storages() -> Result<()> {
+        let http_client = SharedTable::default();
+        let mut storage = storage_with_http_client(http_client.clone()).await?;
+        storage.set_key_prefix("ours")?;
+
+        let page = storage.scan_ids(None, Some(10)).await?;
+        assert_eq!(vec![String::from("a")], page.items);
+        assert_eq!(Some("theirs#b"), page.cursor.as_deref());
+
+        let page = storage.scan_ids(page.cursor.as_deref(), Some(10)).await?;
+        assert_eq!(vec![String::from("c")], page.items);
+        assert_eq!(None, page.cursor);
+
+        let requests = http_client.requests.lock().expect("can lock");
+        assert_eq!(
+            serde_json::json!({ "id": { "S": "theirs#b" } }),
+            requests[1]["ExclusiveStartKey"]
+        );
+
+        Ok(())
+    }
+
+    /// Scans find one record without the item type, locked two hours ago, and deletes succeed.
+    #[derive(Debug, Clone, Default)]
+    struct UntypedOrphan {
+        requests: Arc<Mutex<Vec<serde_json::Value>>>,
+    }
+
+    impl HttpConnector for UntypedOrphan {
+        fn call(&self, request: HttpRequest) -> HttpConnectorFuture {
+            let body = request.body().bytes().expect("in memory body");
+            let request: serde_json::Value = serde_json::from_slice(body).expect("json body");
+            let mut requests = self.requests.lock().expect("can lock");
+            requests.push(request);
+            let response = if requests.len() == 1 {
+                let lock = StorageLock::new_at("crashed", Utc::now() - chrono::Duration::hours(2));
+                serde_json::json!({
+                    "Items": [{
+                        "id": { "S": "orphan" },
+                        "lock": { "S": serde_json::to_string(&lock).expect("serializable") },
+                    }],
+                })
+            } else {
+                serde_json::json!({})
+            };
+            let status = StatusCode::try_from(200).expect("valid status");
+            HttpConnectorFuture::ready(Ok(HttpResponse::new(
+                status,
+                SdkBody::from(response.to_string()),
+            )))
+        }
+    }
+
+    impl HttpClient for UntypedOrphan {
+        fn http_connector(
+            &self,
+            _settings: &HttpConnectorSettings,
+            _components: &RuntimeComponents,
+        ) -> SharedHttpConnector {
+            SharedHttpConnector::new(self.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn it_removes_orphaned_locks_without_item_type() -> Result<()> {
+        let http_client = UntypedOrphan::default();
+        let mut storage = storage_with_http_client(http_client.clone()).await?;
+        storage.set_attribute_names(DynamoDbAttributeNames {
+            item_type: Some((String::from("entity"), String::from("player"))),
+            ..Default::default()
+        })?;
+
+        let removed = storage
+            .remove_orphaned_locks(Duration::from_secs(60 * 60))
+            .await?;
+        assert_eq!(1, removed);
+        let requests = http_client.requests.lock().expect("can lock");
+        assert_eq!(2, requests.len());
+        let filter = requests[0]["FilterExpression"].as_str().unwrap_or_default();
+        assert!(
+            filter.contains("(#Type = :item_type OR attribute_not_exists(#Type))"),
+            "{filter}"
+        );
+        assert_eq!(
+            serde_json::json!({ "S": "orphan" }),
+            requests[1]["Key"]["id"]
+        );
+
+        Ok(())
+    }
+
+    /// Resolves every alias to `target` and records all requests.
+    #[derive(Debug, Clone, Default)]
+    struct AliasedTarget {
+        requests: Arc<Mutex<Vec<serde_json::Value>>>,
+    }
+
+    impl HttpConnector for AliasedTarget {
+        fn call(&self, request: HttpRequest) -> HttpConnectorFuture {
+            let target = request.headers().get("x-amz-target").unwrap_or_default();
+            let response = if target.ends_with(".GetItem") {
+                serde_json::json!({ "Item": { "canonical": { "S": "target" } } })
+            } else {
+                serde_json::json!({})
+            };
+            let body = request.body().bytes().expect("in memory body");
+            let request: serde_json::Value = serde_json::from_slice(body).expect("json body");
+            self.requests.lock().expect("can lock").push(request);
+            let status = StatusCode::try_from(200).expect("valid status");
+            HttpConnectorFuture::ready(Ok(HttpResponse::new(
+                status,
+                SdkBody::from(response.to_string()),
+            )))
+        }
+    }
+
+    impl HttpClient for AliasedTarget {
+        fn http_connector(
+            &self,
+            _settings: &HttpConnectorSettings,
+            _components: &RuntimeComponents,
+        ) -> SharedHttpConnector {
+            SharedHttpConnector::new(self.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn it_transact_saves_to_alias_targets() -> Result<()> {
+        let http_client = AliasedTarget::default();
+        let mut storage = storage_with_http_client(http_client.clone()).await?;
+        storage.set_aliases_enabled(true)?;
+        let alias = String::from("alias");
+        let lock = StorageLock::new("TEST");
+
+        storage
+            .transact_save(&[(&alias, &TestItem::default(), &lock)], false)
+            .await?;
+        let requests = http_client.requests.lock().expect("can lock");
+        let transaction = requests.last().expect("transaction sent");
+        assert_eq!(
+            serde_json::json!({ "S": "target" }),
+            transaction["TransactItems"][0]["Update"]["Key"]["id"]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_rejects_invalid_ids_in_transact_saves() -> Result<()> {
+        let http_client = AliasedTarget::default();
+        let storage =
+            item_storage_with_http_client::<crate::test_item::StrictItem>(http_client.clone())
+                .await?;
+        let good_id = crate::test_item::StrictId::from_string("good1")?;
+        let bad_id = crate::test_item::StrictId::from_string("../escaped")?;
+        let item = crate::test_item::StrictItem::default();
+        let lock = StorageLock::new("TEST");
+
+        let e = storage
+            .transact_save(&[(&good_id, &item, &lock), (&bad_id, &item, &lock)], false)
+            .await
+            .expect_err("invalid id");
+        assert_eq!(
+            Some(&StorageError::InvalidId {
+                id: bad_id.to_string()
+            }),
+            e.downcast_ref::<StorageError>()
+        );
+        assert!(http_client.requests.lock().expect("can lock").is_empty());
+
+        Ok(())
+    }
 }
@@ -0,0 +1,240 @@
+use crate::StorageLock;
+use crate::StorageOperation;
+use chrono::DateTime;
+use chrono::Utc;
+use std::fmt;
+use std::time::Duration;
+
+/// Errors callers might want to handle explicitly.
+///
+/// These are returned inside the usual [color_eyre::eyre::Report],
+/// check for them via `report.downcast_ref::<StorageError>()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StorageError {
+    /// Saving would exceed the configured quota, and nothing could be evicted.
+    QuotaExceeded {
+        bytes: u64,
+        max_bytes: Option<u64>,
+        items: usize,
+        max_items: Option<usize>,
+    },
+    /// Saving would exceed the quota of the item's namespace, see [crate::StorageNamespaceQuotas].
+    NamespaceQuotaExceeded {
+        namespace: String,
+        bytes: u64,
+        max_bytes: Option<u64>,
+        items: usize,
+        max_items: Option<usize>,
+    },
+    /// The storage was opened read-only, e.g. via [crate::StorageDisk::new_read_only].
+    ReadOnly,
+    /// The id failed [crate::StorageId::is_valid_format] and was rejected before reaching the backend.
+    InvalidId { id: String },
+    /// The [crate::AccessPolicy] denied the operation.
+    PermissionDenied {
+        operation: StorageOperation,
+        id: Option<String>,
+        who: Option<String>,
+    },
+    /// The item stayed locked by `who`, e.g. for all attempts of a [crate::LockRetryPolicy].
+    AlreadyLocked { id: String, who: String },
+    /// A save used a lock that doesn't hold the item anymore, e.g. after a [crate::Storage::force_unlock].
+    LockMismatch {
+        id: String,
+        /// Who the rejected lock belongs to
+        who: String,
+        /// Who holds the item now, `None` if nobody does
+        holder: Option<String>,
+        /// How long `holder` has held the item
+        held_for: Option<Duration>,
+        /// Whether the item was saved after the rejected lock was taken, `None` if the backend can't tell
+        changed: Option<bool>,
+    },
+    /// The item doesn't exist or was locked but never saved, see [crate::Storage::try_load].
+    NotFound { id: String },
+    /// [crate::Storage::create] only generated ids that already exist, for all `tries`.
+    IdExhausted { tries: usize },
+    /// The backend failed, e.g. a DynamoDB request, even after all retries.
+    /// `transient` failures, e.g. throttling or timeouts, might succeed later. The others, e.g. configuration problems, won't.
+    Backend {
+        operation: StorageOperation,
+        transient: bool,
+        message: String,
+    },
+    /// The item failed [crate::StorageItem::validate] and was rejected before saving.
+    InvalidItem { id: String },
+    /// The serialized item is larger than the backend accepts, e.g. DynamoDB's 400KB item limit.
+    ItemTooLarge {
+        id: String,
+        size: usize,
+        max_size: usize,
+    },
+    /// The backend refuses the operation, e.g. a [crate::StorageNull] configured via [crate::NullProfile].
+    Unsupported { operation: StorageOperation },
+    /// A long running operation stopped early, because its cancellation token was cancelled.
+    ///
+    /// Items are either fully handled or untouched.
+    Cancelled {
+        /// Items handled before stopping
+        processed: u64,
+        /// Where a scan can continue, if the operation scans
+        cursor: Option<String>,
+    },
+    /// The item was saved before and the storage only writes items once,
+    /// e.g. [crate::StorageMemory::set_immutable].
+    Immutable { id: String },
+    /// The stored data failed [crate::StorageItem::deserialize],
+    /// see [crate::StorageWithMiddleware::set_quarantine_corrupt_items].
+    Corrupt {
+        id: String,
+        /// When a copy was recorded by the [crate::QuarantineSink], `None` if it wasn't
+        preserved_at: Option<DateTime<Utc>>,
+    },
+    /// The circuit breaker of a [crate::StorageResilient] is open, the backend wasn't called.
+    CircuitOpen {
+        operation: StorageOperation,
+        /// How long until the breaker lets a probe call through
+        retry_in: Duration,
+    },
+    /// A retry was skipped, because the shared [crate::RetryBudget] is exhausted.
+    /// The error of the last attempt is wrapped by it.
+    RetryBudgetExhausted { operation: String },
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::QuotaExceeded {
+                bytes,
+                max_bytes,
+                items,
+                max_items,
+            } => write!(
+                f,
+                "Quota exceeded: {bytes} bytes (max {max_bytes:?}), {items} items (max {max_items:?})"
+            ),
+            StorageError::NamespaceQuotaExceeded {
+                namespace,
+                bytes,
+                max_bytes,
+                items,
+                max_items,
+            } => write!(
+                f,
+                "Quota of namespace {namespace:?} exceeded: {bytes} bytes (max {max_bytes:?}), {items} items (max {max_items:?})"
+            ),
+            StorageError::ReadOnly => write!(f, "Storage is read-only"),
+            StorageError::InvalidId { id } => write!(f, "Invalid id {id:?}"),
+            StorageError::PermissionDenied { operation, id, who } => {
+                write!(f, "Permission denied: {operation} of {id:?} by {who:?}")
+            }
+            StorageError::AlreadyLocked { id, who } => {
+                write!(f, "{id:?} is already locked by {who:?}")
+            }
+            StorageError::LockMismatch {
+                id,
+                who,
+                holder,
+                held_for,
+                changed,
+            } => {
+                write!(f, "Lock invalid! {id:?} is not locked by {who:?}")?;
+                match (holder, held_for) {
+                    (Some(holder), Some(held_for)) => {
+                        write!(f, ", but by {holder:?} for {held_for:?}")?
+                    }
+                    (Some(holder), None) => write!(f, ", but by {holder:?}")?,
+                    (None, _) => write!(f, ", nobody holds it")?,
+                }
+                match changed {
+                    Some(true) => write!(f, ", and it was saved since {who:?} locked it"),
+                    Some(false) => write!(f, ", and it wasn't saved since {who:?} locked it"),
+                    None => Ok(()),
+                }
+            }
+            StorageError::NotFound { id } => write!(f, "{id:?} not found"),
+            StorageError::IdExhausted { tries } => {
+                write!(f, "No unused id found in {tries} tries")
+            }
+            StorageError::Backend {
+                operation,
+                transient,
+                message,
+            } => {
+                let kind = if *transient { "temporarily " } else { "" };
+                write!(f, "{operation} {kind}failed: {message}")
+            }
+            StorageError::InvalidItem { id } => write!(f, "{id:?} failed validation"),
+            StorageError::ItemTooLarge { id, size, max_size } => {
+                write!(f, "{id:?} is too large: {size} bytes (max {max_size})")
+            }
+            StorageError::Unsupported { operation } => {
+                write!(f, "{operation} is not supported by this storage")
+            }
+            StorageError::Cancelled { processed, cursor } => {
+                write!(f, "Cancelled after {processed} items")?;
+                match cursor {
+                    Some(cursor) => write!(f, ", continue at {cursor:?}"),
+                    None => Ok(()),
+                }
+            }
+            StorageError::Immutable { id } => {
+                write!(f, "{id:?} was already written, and is immutable")
+            }
+            StorageError::Corrupt { id, preserved_at } => {
+                write!(f, "{id:?} is corrupt")?;
+                match preserved_at {
+                    Some(preserved_at) => write!(f, ", preserved at {preserved_at}"),
+                    None => write!(f, ", and wasn't preserved"),
+                }
+            }
+            StorageError::CircuitOpen {
+                operation,
+                retry_in,
+            } => write!(
+                f,
+                "{operation} rejected, the circuit breaker is open for another {retry_in:?}"
+            ),
+            StorageError::RetryBudgetExhausted { operation } => {
+                write!(f, "Retry budget exhausted, not retrying {operation}")
+            }
+        }
+    }
+}
+
+impl StorageError {
+    /// The [StorageError::LockMismatch] for a save of `id` with `lock`,
+    /// with the `current` lock and when the item was last saved, if known.
+    pub(crate) fn lock_mismatch(
+        id: &str,
+        lock: &StorageLock,
+        current: Option<&StorageLock>,
+        modified: Option<DateTime<Utc>>,
+        now: DateTime<Utc>,
+    ) -> Self {
+        StorageError::LockMismatch {
+            id: id.to_string(),
+            who: lock.who().to_string(),
+            holder: current.map(|l| l.who().to_string()),
+            held_for: current.map(|l| {
+                now.signed_duration_since(*l.when())
+                    .to_std()
+                    .unwrap_or_default()
+            }),
+            changed: modified.map(|modified| modified > *lock.when()),
+        }
+    }
+
+    /// Whether retrying the operation later might succeed.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            StorageError::Backend {
+                transient: true,
+                ..
+            } | StorageError::CircuitOpen { .. }
+        )
+    }
+}
+
+impl std::error::Error for StorageError {}
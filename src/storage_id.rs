@@ -0,0 +1,72 @@
+use color_eyre::eyre::Result;
+use std::cmp::Ordering;
+
+/// The `trait` for ids of [crate::StorageItem]s.
+///
+/// Ids are stored by their [std::fmt::Display] representation,
+/// and parsed back via [StorageId::from_string].
+pub trait StorageId:
+    ToString + Sync + Send + core::fmt::Debug + std::fmt::Display + PartialOrd + Clone + Default + Sized
+{
+    /// Parses an id from its string representation, failing if the format is invalid.
+    fn from_string(id: &str) -> Result<Self>;
+
+    /// Generates a new id.
+    /// `previous` is the last known id, for id types that derive new ids from it.
+    fn generate_new(previous: Option<&Self>) -> Self;
+
+    /// Checks if `id` is a valid string representation of this id type.
+    fn is_valid_format(id: &str) -> bool;
+
+    /// The order used to track the highest id, e.g. for [crate::Storage::metadata_highest_seen_id].
+    /// Defaults to [PartialOrd], treating incomparable ids as equal.
+    fn id_cmp(&self, other: &Self) -> Ordering {
+        self.partial_cmp(other).unwrap_or(Ordering::Equal)
+    }
+}
+
+/// Plain strings are accepted as is, new ones are random nanoids.
+impl StorageId for String {
+    fn from_string(id: &str) -> Result<Self> {
+        Ok(id.to_string())
+    }
+
+    fn generate_new(_previous: Option<&Self>) -> Self {
+        nanoid::nanoid!()
+    }
+
+    fn is_valid_format(_id: &str) -> bool {
+        true
+    }
+
+    /// Numeric strings compare by value, so "10" is after "9".
+    fn id_cmp(&self, other: &Self) -> Ordering {
+        let is_numeric = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+        if is_numeric(self) && is_numeric(other) {
+            let a = self.trim_start_matches('0');
+            let b = other.trim_start_matches('0');
+            a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+        } else {
+            self.cmp(other)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::StorageId;
+    use std::cmp::Ordering;
+
+    #[test]
+    fn it_compares_numeric_strings_by_value() {
+        let id = |s: &str| String::from(s);
+        assert_eq!(Ordering::Greater, id("10").id_cmp(&id("9")));
+        assert_eq!(Ordering::Equal, id("007").id_cmp(&id("7")));
+        assert_eq!(
+            Ordering::Greater,
+            id("123456789012345678901234567890").id_cmp(&id("99"))
+        );
+        assert_eq!(Ordering::Greater, id("b").id_cmp(&id("a")));
+        assert_eq!(Ordering::Greater, id("a").id_cmp(&id("10")));
+    }
+}
@@ -1,3 +1,5 @@
+use crate::StorageError;
+use crate::StorageId;
 use async_trait::async_trait;
 use color_eyre::eyre::Result;
 
@@ -13,6 +15,8 @@ use color_eyre::eyre::Result;
 /// #[derive(Debug,Default,Serialize,Deserialize)]
 /// pub struct TestItem {}
 /// impl StorageItem for TestItem {
+///     type ID = String;
+///
 ///     fn serialize(&self) -> Result<Vec<u8>> {
 ///         let json = serde_json::to_string_pretty(&self)?;
 ///     
@@ -32,24 +36,98 @@ use color_eyre::eyre::Result;
 
 #[async_trait]
 pub trait StorageItem: core::fmt::Debug + std::default::Default + std::marker::Sync {
-    type ID: ToString
-        // + for<'a> From<&'a str> -> use make_id
-        + Sync
-        + Send
-        + core::fmt::Debug
-        + std::fmt::Display
-        + PartialOrd
-        + Clone
-        + Default;
+    /// Parsing, generation, and validation of ids is handled by [StorageId].
+    type ID: StorageId;
     fn serialize(&self) -> Result<Vec<u8>>;
     fn deserialize(data: &[u8]) -> Result<Self>
     where
         Self: Sized;
 
-    /// Experimental. Might be gone soon, or not.
-    fn generate_next_id(a_previous_id: Option<&Self::ID>) -> Self::ID;
+    /// Checks the item before it is serialized for saving, the default accepts everything.
+    ///
+    /// Saves of items failing this are rejected with [StorageError::InvalidItem],
+    /// the returned error stays available via `report.downcast_ref()`.
+    fn validate(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// A hash of everything [StorageItem::serialize] writes, [crate::StorageSkipUnchanged] skips saves if it didn't change.
+    ///
+    /// `None`, the default, hashes the serialized item instead.
+    /// Implement it if the item can tell cheaper, e.g. from a revision bumped on every change.
+    fn content_hash(&self) -> Option<u64> {
+        None
+    }
+
+    /// The [crate::ContentId] of the serialized item, e.g. for storing immutable items deduplicated.
+    #[cfg(feature = "content-id")]
+    fn content_id(&self) -> Result<crate::ContentId> {
+        Ok(crate::ContentId::from_data(&self.serialize()?))
+    }
+}
+
+/// Rejects ids failing [StorageId::is_valid_format] with [StorageError::InvalidId].
+pub(crate) fn ensure_valid_id<ITEM: StorageItem>(id: &ITEM::ID) -> Result<()> {
+    let id = id.to_string();
+    if !ITEM::ID::is_valid_format(&id) {
+        return Err(StorageError::InvalidId { id }.into());
+    }
+
+    Ok(())
+}
+/// Serializes the item for saving, rejecting items failing [StorageItem::validate] with [StorageError::InvalidItem].
+pub(crate) fn serialize_valid<ITEM: StorageItem>(id: &ITEM::ID, item: &ITEM) -> Result<Vec<u8>> {
+    serialize_valid_as(id, item, &crate::NativeFormat)
+}
+
+/// Like [serialize_valid], but in the given `format`.
+pub(crate) fn serialize_valid_as<ITEM: StorageItem>(
+    id: &ITEM::ID,
+    item: &ITEM,
+    format: &dyn crate::ItemFormat<ITEM>,
+) -> Result<Vec<u8>> {
+    if let Err(e) = item.validate() {
+        return Err(e.wrap_err(StorageError::InvalidItem { id: id.to_string() }));
+    }
+
+    let start = std::time::Instant::now();
+    let data = format.serialize(item)?;
+    crate::serialization_stats::record_serialize::<ITEM>(id, start.elapsed(), data.len() as u64);
+
+    Ok(data)
+}
+
+/// Deserializes the item, counting the cost in [crate::SerializationStats].
+pub(crate) fn deserialize_item<ITEM: StorageItem>(data: &[u8]) -> Result<ITEM> {
+    deserialize_item_as(data, &crate::NativeFormat)
+}
+
+/// Like [deserialize_item], for data read from a backend, failures are [StorageError::Corrupt].
+pub(crate) fn deserialize_stored<ITEM: StorageItem>(
+    id: &impl std::fmt::Display,
+    data: &[u8],
+) -> Result<ITEM> {
+    deserialize_item(data).map_err(|e| corrupt(id, e))
+}
+
+/// Wraps `e` into [StorageError::Corrupt], keeping it as the cause.
+pub(crate) fn corrupt(id: &impl std::fmt::Display, e: color_eyre::Report) -> color_eyre::Report {
+    e.wrap_err(StorageError::Corrupt {
+        id: id.to_string(),
+        preserved_at: None,
+    })
+}
+
+/// Like [deserialize_item], but from the given `format`, failures aren't counted.
+pub(crate) fn deserialize_item_as<ITEM: StorageItem>(
+    data: &[u8],
+    format: &dyn crate::ItemFormat<ITEM>,
+) -> Result<ITEM> {
+    let start = std::time::Instant::now();
+    let item = format.deserialize(data)?;
+    crate::serialization_stats::record_deserialize::<ITEM>(start.elapsed(), data.len() as u64);
 
-    fn make_id(id: &str) -> Result<Self::ID>;
+    Ok(item)
 }
 /*
 pub trait StorageItemId {
@@ -0,0 +1,1684 @@
+use crate::exists_policy::is_orphan;
+use crate::operation_metrics;
+use crate::slow_op::SlowOp;
+use crate::storage::ensure_valid_create_tries;
+use crate::storage::ensure_valid_meta_key;
+use crate::storage::DEFAULT_CREATE_TRIES;
+use crate::storage_item::deserialize_stored;
+use crate::storage_item::ensure_valid_id;
+use crate::storage_item::serialize_valid;
+use crate::Clock;
+use crate::DefaultIdGenerator;
+use crate::ExistsPolicy;
+use crate::IdGenerator;
+use crate::ItemSummary;
+use crate::LockResult;
+#[cfg(feature = "metadata")]
+use crate::Metadata;
+use crate::Page;
+use crate::Redacted;
+use crate::Storage;
+use crate::StorageError;
+use crate::StorageId;
+use crate::StorageItem;
+use crate::StorageLock;
+use crate::StorageOperation;
+use crate::SystemClock;
+use async_trait::async_trait;
+use chrono::DateTime;
+use chrono::Utc;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use core::marker::PhantomData;
+use rand::rngs::StdRng;
+use rand::Rng;
+use rand::SeedableRng;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Simulated latency of an operation of [StorageMemory].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryLatency {
+    Fixed(Duration),
+    /// Uniformly distributed between `min` and `max`, inclusive
+    Uniform {
+        min: Duration,
+        max: Duration,
+    },
+}
+
+#[derive(Default)]
+struct Entry {
+    /// `None` for locked but never saved items
+    data: Option<Vec<u8>>,
+    lock: Option<StorageLock>,
+    /// When the data was last saved
+    modified: Option<DateTime<Utc>>,
+}
+
+impl std::fmt::Debug for Entry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Entry")
+            .field("data", &Redacted(&self.data))
+            .field("lock", &self.lock)
+            .field("modified", &self.modified)
+            .finish()
+    }
+}
+
+/// An in-memory storage for tests, with simulated latency.
+///
+/// Every operation first waits for its configured [MemoryLatency], drawn from a seeded random generator.
+/// With the same seed and tokio's paused test clock, lock races play out the same way on every run.
+///
+/// Items are serialized like in every other backend, but nothing outlives the storage.
+#[derive(Debug)]
+pub struct StorageMemory<ITEM: StorageItem> {
+    entries: Mutex<BTreeMap<String, Entry>>,
+    meta: Mutex<HashMap<String, Vec<u8>>>,
+    latencies: HashMap<StorageOperation, MemoryLatency>,
+    rng: Mutex<StdRng>,
+    slow_op_threshold: Option<Duration>,
+    create_tries: usize,
+    clock: Arc<dyn Clock>,
+    id_generator: Arc<dyn IdGenerator<ITEM::ID>>,
+    exists_policy: ExistsPolicy,
+    immutable: bool,
+    item_type: PhantomData<ITEM>,
+    #[cfg(feature = "metadata")]
+    metadata: Metadata<ITEM>,
+}
+
+impl<ITEM: StorageItem> Default for StorageMemory<ITEM> {
+    fn default() -> Self {
+        Self {
+            entries: Mutex::new(BTreeMap::new()),
+            meta: Mutex::default(),
+            latencies: HashMap::new(),
+            rng: Mutex::new(StdRng::seed_from_u64(0)),
+            slow_op_threshold: None,
+            create_tries: DEFAULT_CREATE_TRIES,
+            clock: Arc::new(SystemClock),
+            id_generator: Arc::new(DefaultIdGenerator),
+            exists_policy: ExistsPolicy::default(),
+            immutable: false,
+            item_type: PhantomData,
+            #[cfg(feature = "metadata")]
+            metadata: Metadata::default(),
+        }
+    }
+}
+
+impl<ITEM: StorageItem> StorageMemory<ITEM> {
+    /// Sets the simulated latency of `operation`, `None` removes it.
+    pub fn set_latency(
+        &mut self,
+        operation: StorageOperation,
+        latency: Option<MemoryLatency>,
+    ) -> Result<()> {
+        if let Some(MemoryLatency::Uniform { min, max }) = latency {
+            if min > max {
+                return Err(eyre!("Invalid latency, min {min:?} > max {max:?}"));
+            }
+        }
+        match latency {
+            Some(latency) => self.latencies.insert(operation, latency),
+            None => self.latencies.remove(&operation),
+        };
+
+        Ok(())
+    }
+
+    /// Restarts the random latencies from `seed`, the default seed is `0`.
+    pub fn set_seed(&mut self, seed: u64) -> Result<()> {
+        self.rng = Mutex::new(StdRng::seed_from_u64(seed));
+
+        Ok(())
+    }
+
+    /// Logs a warning for every operation that takes longer than `threshold`.
+    pub fn set_slow_op_threshold(&mut self, threshold: Option<Duration>) -> Result<()> {
+        self.slow_op_threshold = threshold;
+
+        Ok(())
+    }
+
+    /// How often [Storage::create] generates a new id, when it collides with an existing one, defaults to 10.
+    pub fn set_create_tries(&mut self, tries: usize) -> Result<()> {
+        ensure_valid_create_tries(tries)?;
+        self.create_tries = tries;
+
+        Ok(())
+    }
+
+    /// Where the time for locks and modification times comes from, defaults to [SystemClock].
+    pub fn set_clock(&mut self, clock: impl Clock + 'static) -> Result<()> {
+        self.clock = Arc::new(clock);
+
+        Ok(())
+    }
+
+    /// Where [Storage::create] gets new ids from, defaults to [DefaultIdGenerator].
+    pub fn set_id_generator(
+        &mut self,
+        id_generator: impl IdGenerator<ITEM::ID> + 'static,
+    ) -> Result<()> {
+        self.id_generator = Arc::new(id_generator);
+
+        Ok(())
+    }
+
+    /// Locks held longer than this are logged with a warning and counted in [crate::LockStats].
+    #[cfg(feature = "metadata")]
+    pub fn set_max_lock_hold_time(&mut self, max_lock_hold_time: Option<Duration>) -> Result<()> {
+        self.metadata.set_max_lock_hold_time(max_lock_hold_time);
+
+        Ok(())
+    }
+
+    /// Whether locked but never saved items count in [Storage::exists], see [ExistsPolicy].
+    pub fn set_exists_policy(&mut self, exists_policy: ExistsPolicy) -> Result<()> {
+        self.exists_policy = exists_policy;
+
+        Ok(())
+    }
+
+    async fn simulate_latency(&self, operation: StorageOperation) {
+        let latency = match self.latencies.get(&operation) {
+            Some(MemoryLatency::Fixed(latency)) => *latency,
+            Some(MemoryLatency::Uniform { min, max }) => {
+                self.rng.lock().expect("can lock").gen_range(*min..=*max)
+            }
+            None => return,
+        };
+        tokio::time::sleep(latency).await;
+    }
+}
+
+#[cfg(feature = "metadata")]
+impl<ITEM: StorageItem> StorageMemory<ITEM> {
+    fn update_highest_seen_id(&self, id: &ITEM::ID) {
+        self.metadata.update_highest_seen_id(id);
+    }
+
+    fn record_lock_acquired(&self, id: &ITEM::ID, lock: &StorageLock) {
+        self.metadata.record_lock_acquired(id, lock);
+    }
+
+    fn record_lock_contended(&self) {
+        self.metadata.record_lock_contended();
+        operation_metrics::record_lock_contended("memory");
+    }
+
+    fn record_lock_released(&self, id: &ITEM::ID, lock: &StorageLock) {
+        self.metadata
+            .record_lock_released(id, lock, self.clock.now());
+    }
+
+    fn record_save(&self, previous_len: Option<u64>, len: u64) {
+        self.metadata.record_save(previous_len, len);
+    }
+
+    #[cfg(feature = "wipe")]
+    fn record_remove(&self, len: u64) {
+        self.metadata.record_remove(len);
+    }
+
+    fn record_totals(&self, item_count: u64, total_bytes: Option<u64>) {
+        self.metadata.record_totals(item_count, total_bytes);
+    }
+}
+
+#[cfg(not(feature = "metadata"))]
+impl<ITEM: StorageItem> StorageMemory<ITEM> {
+    fn update_highest_seen_id(&self, _id: &ITEM::ID) {}
+
+    fn record_lock_acquired(&self, _id: &ITEM::ID, _lock: &StorageLock) {}
+
+    fn record_lock_contended(&self) {
+        operation_metrics::record_lock_contended("memory");
+    }
+
+    fn record_lock_released(&self, _id: &ITEM::ID, _lock: &StorageLock) {}
+
+    fn record_save(&self, _previous_len: Option<u64>, _len: u64) {}
+
+    #[cfg(feature = "wipe")]
+    fn record_remove(&self, _len: u64) {}
+
+    fn record_totals(&self, _item_count: u64, _total_bytes: Option<u64>) {}
+}
+
+impl<ITEM: StorageItem> StorageMemory<ITEM> {
+    fn read_data(&self, id: &ITEM::ID) -> Result<Vec<u8>> {
+        ensure_valid_id::<ITEM>(id)?;
+        let entries = self.entries.lock().expect("can lock");
+        let Some(data) = entries.get(&id.to_string()).and_then(|e| e.data.clone()) else {
+            return Err(StorageError::NotFound { id: id.to_string() }.into());
+        };
+        self.update_highest_seen_id(id);
+
+        Ok(data)
+    }
+
+    /// Items can only be saved once, e.g. for receipts, later saves fail with [StorageError::Immutable].
+    /// Off by default.
+    pub fn set_immutable(&mut self, immutable: bool) -> Result<()> {
+        self.immutable = immutable;
+
+        Ok(())
+    }
+
+    fn write_data(&self, id: &ITEM::ID, data: Vec<u8>, lock: &StorageLock) -> Result<()> {
+        ensure_valid_id::<ITEM>(id)?;
+        let mut entries = self.entries.lock().expect("can lock");
+        let Some(entry) = entries
+            .get_mut(&id.to_string())
+            .filter(|e| e.lock.as_ref() == Some(lock))
+        else {
+            let entry = entries.get(&id.to_string());
+            return Err(StorageError::lock_mismatch(
+                &id.to_string(),
+                lock,
+                entry.and_then(|e| e.lock.as_ref()),
+                entry.and_then(|e| e.modified),
+                self.clock.now(),
+            )
+            .into());
+        };
+        if self.immutable && entry.data.is_some() {
+            return Err(StorageError::Immutable { id: id.to_string() }.into());
+        }
+        let len = data.len() as u64;
+        let previous_len = entry.data.replace(data).map(|d| d.len() as u64);
+        entry.modified = Some(self.clock.now());
+        self.update_highest_seen_id(id);
+        self.record_save(previous_len, len);
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<ITEM: StorageItem + std::marker::Send> Storage<ITEM> for StorageMemory<ITEM> {
+    #[tracing::instrument(
+        name = "storage.ensure_storage_exists",
+        skip_all,
+        fields(backend = "memory", db.system = "memory", db.operation = "ensure_storage_exists")
+    )]
+    async fn ensure_storage_exists(&mut self) -> Result<()> {
+        let _slow_op = SlowOp::start(
+            self.slow_op_threshold,
+            "memory",
+            "ensure_storage_exists",
+            None,
+        );
+        self.simulate_latency(StorageOperation::EnsureStorageExists)
+            .await;
+        let (items, bytes) = {
+            let entries = self.entries.lock().expect("can lock");
+            let saved = entries.values().filter_map(|e| e.data.as_ref());
+            saved.fold((0, 0), |(items, bytes), data| {
+                (items + 1, bytes + data.len() as u64)
+            })
+        };
+        self.record_totals(items, Some(bytes));
+
+        Ok(())
+    }
+
+    fn capabilities(&self) -> crate::Capabilities {
+        crate::Capabilities {
+            scan: true,
+            list: true,
+            meta: true,
+            remove_orphaned_locks: true,
+            consistent_reads: true,
+            ..Default::default()
+        }
+    }
+    #[tracing::instrument(
+        name = "storage.create",
+        skip_all,
+        fields(backend = "memory", db.system = "memory", db.operation = "create")
+    )]
+    async fn create(&self) -> Result<ITEM::ID> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "memory", "create", None);
+        self.simulate_latency(StorageOperation::Create).await;
+        let entries = self.entries.lock().expect("can lock");
+        // collisions are passed on, for id types that derive the next id from them
+        let mut previous = None;
+        for _ in 0..self.create_tries {
+            let id = self.id_generator.generate(previous.as_ref());
+            if !entries.contains_key(&id.to_string()) {
+                return Ok(id);
+            }
+            previous = Some(id);
+        }
+
+        Err(StorageError::IdExhausted {
+            tries: self.create_tries,
+        }
+        .into())
+    }
+    #[tracing::instrument(
+        name = "storage.exists",
+        skip_all,
+        fields(backend = "memory", db.system = "memory", db.operation = "exists", id = %id)
+    )]
+    async fn exists(&self, id: &ITEM::ID) -> Result<bool> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "memory", "exists", Some(id));
+        ensure_valid_id::<ITEM>(id)?;
+        self.simulate_latency(StorageOperation::Exists).await;
+        // locked, but unsaved items exist, too
+        let now = self.clock.now();
+        if self
+            .entries
+            .lock()
+            .expect("can lock")
+            .get(&id.to_string())
+            .is_some_and(|e| {
+                e.data.is_some() || self.exists_policy.lock_only_exists(e.lock.as_ref(), now)
+            })
+        {
+            self.update_highest_seen_id(id);
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    #[tracing::instrument(
+        name = "storage.load",
+        skip_all,
+        fields(backend = "memory", db.system = "memory", db.operation = "load", id = %id)
+    )]
+    async fn load(&self, id: &ITEM::ID) -> Result<ITEM> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "memory", "load", Some(id));
+        self.simulate_latency(StorageOperation::Load).await;
+        let data = self.read_data(id)?;
+        deserialize_stored::<ITEM>(id, &data)
+    }
+
+    #[tracing::instrument(
+        name = "storage.load_raw",
+        skip_all,
+        fields(backend = "memory", db.system = "memory", db.operation = "load_raw", id = %id)
+    )]
+    async fn load_raw(&self, id: &ITEM::ID) -> Result<Vec<u8>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "memory", "load_raw", Some(id));
+        self.simulate_latency(StorageOperation::Load).await;
+        self.read_data(id)
+    }
+
+    #[tracing::instrument(
+        name = "storage.save",
+        skip_all,
+        fields(backend = "memory", db.system = "memory", db.operation = "save", id = %id)
+    )]
+    async fn save(&self, id: &ITEM::ID, item: &ITEM, lock: &StorageLock) -> Result<()> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "memory", "save", Some(id));
+        let data = serialize_valid(id, item)?;
+        self.simulate_latency(StorageOperation::Save).await;
+        self.write_data(id, data, lock)
+    }
+
+    #[tracing::instrument(
+        name = "storage.save_raw",
+        skip_all,
+        fields(backend = "memory", db.system = "memory", db.operation = "save_raw", id = %id)
+    )]
+    async fn save_raw(&self, id: &ITEM::ID, data: &[u8], lock: &StorageLock) -> Result<()> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "memory", "save_raw", Some(id));
+        self.simulate_latency(StorageOperation::Save).await;
+        self.write_data(id, data.to_vec(), lock)
+    }
+    #[tracing::instrument(
+        name = "storage.lock",
+        skip_all,
+        fields(
+            backend = "memory",
+            db.system = "memory",
+            db.operation = "lock",
+            id = %id,
+            who = %who,
+        )
+    )]
+    async fn lock(&self, id: &ITEM::ID, who: &str) -> Result<LockResult<ITEM>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "memory", "lock", Some(id));
+        ensure_valid_id::<ITEM>(id)?;
+        self.simulate_latency(StorageOperation::Lock).await;
+        let mut entries = self.entries.lock().expect("can lock");
+        self.update_highest_seen_id(id);
+        let entry = entries.entry(id.to_string()).or_default();
+        if let Some(lock) = &entry.lock {
+            tracing::warn!("{id} already locked by {}", lock.who());
+            self.record_lock_contended();
+            return Ok(LockResult::already_locked(lock));
+        }
+
+        // corrupt items aren't locked, saving a default over them would lose the data
+        let item = match &entry.data {
+            Some(data) => deserialize_stored::<ITEM>(id, data)?,
+            None => ITEM::default(),
+        };
+        let lock = StorageLock::new_at(who, self.clock.now());
+        entry.lock = Some(lock.duplicate());
+        self.record_lock_acquired(id, &lock);
+        Ok(LockResult::Success { lock, item })
+    }
+
+    #[tracing::instrument(
+        name = "storage.unlock",
+        skip_all,
+        fields(backend = "memory", db.system = "memory", db.operation = "unlock", id = %id)
+    )]
+    async fn unlock(&self, id: &ITEM::ID, lock: StorageLock) -> Result<()> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "memory", "unlock", Some(id));
+        self.simulate_latency(StorageOperation::Unlock).await;
+        let mut entries = self.entries.lock().expect("can lock");
+        let key = id.to_string();
+        let Some(entry) = entries
+            .get_mut(&key)
+            .filter(|e| e.lock.as_ref() == Some(&lock))
+        else {
+            return Err(eyre!("Lock invalid!"));
+        };
+        entry.lock = None;
+        if entry.data.is_none() {
+            entries.remove(&key);
+        }
+        self.record_lock_released(id, &lock);
+
+        Ok(())
+    }
+
+    #[tracing::instrument(
+        name = "storage.handoff_lock",
+        skip_all,
+        fields(
+            backend = "memory",
+            db.system = "memory",
+            db.operation = "handoff_lock",
+            id = %id,
+            who = %new_who,
+        )
+    )]
+    async fn handoff_lock(
+        &self,
+        id: &ITEM::ID,
+        lock: StorageLock,
+        new_who: &str,
+    ) -> Result<StorageLock> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "memory", "handoff_lock", Some(id));
+        self.simulate_latency(StorageOperation::HandoffLock).await;
+        let mut entries = self.entries.lock().expect("can lock");
+        let Some(entry) = entries
+            .get_mut(&id.to_string())
+            .filter(|e| e.lock.as_ref() == Some(&lock))
+        else {
+            return Err(eyre!("Lock invalid!"));
+        };
+        let new_lock = StorageLock::new_at(new_who, self.clock.now());
+        entry.lock = Some(new_lock.duplicate());
+        self.record_lock_released(id, &lock);
+        self.record_lock_acquired(id, &new_lock);
+
+        Ok(new_lock)
+    }
+
+    #[tracing::instrument(
+        name = "storage.put_meta",
+        skip_all,
+        fields(backend = "memory", db.system = "memory", db.operation = "put_meta", key = %key)
+    )]
+    async fn put_meta(&self, key: &str, value: &[u8]) -> Result<()> {
+        ensure_valid_meta_key(key)?;
+        self.meta
+            .lock()
+            .expect("can lock")
+            .insert(key.to_string(), value.to_vec());
+
+        Ok(())
+    }
+
+    #[tracing::instrument(
+        name = "storage.get_meta",
+        skip_all,
+        fields(backend = "memory", db.system = "memory", db.operation = "get_meta", key = %key)
+    )]
+    async fn get_meta(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        ensure_valid_meta_key(key)?;
+        Ok(self.meta.lock().expect("can lock").get(key).cloned())
+    }
+
+    #[tracing::instrument(
+        name = "storage.force_unlock",
+        skip_all,
+        fields(backend = "memory", db.system = "memory", db.operation = "force_unlock", id = %id)
+    )]
+    async fn force_unlock(&self, id: &ITEM::ID) -> Result<Option<StorageLock>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "memory", "force_unlock", Some(id));
+        self.simulate_latency(StorageOperation::ForceUnlock).await;
+        let mut entries = self.entries.lock().expect("can lock");
+        let key = id.to_string();
+        let Some(entry) = entries.get_mut(&key).filter(|e| e.lock.is_some()) else {
+            tracing::warn!("{id} isn't locked");
+            return Ok(None);
+        };
+        let lock = entry.lock.take();
+        if entry.data.is_none() {
+            entries.remove(&key);
+        }
+
+        Ok(lock)
+    }
+    #[tracing::instrument(
+        name = "storage.force_unlock_if_held",
+        skip_all,
+        fields(
+            backend = "memory",
+            db.system = "memory",
+            db.operation = "force_unlock_if_held",
+            id = %id,
+            who = %who,
+        )
+    )]
+    async fn force_unlock_if_held(
+        &self,
+        id: &ITEM::ID,
+        who: &str,
+        when: DateTime<Utc>,
+    ) -> Result<bool> {
+        let _slow_op = SlowOp::start(
+            self.slow_op_threshold,
+            "memory",
+            "force_unlock_if_held",
+            Some(id),
+        );
+        self.simulate_latency(StorageOperation::ForceUnlock).await;
+        let mut entries = self.entries.lock().expect("can lock");
+        let key = id.to_string();
+        let Some(entry) = entries
+            .get_mut(&key)
+            .filter(|e| e.lock.as_ref().is_some_and(|l| l.is_held_by(who, when)))
+        else {
+            return Ok(false);
+        };
+        entry.lock = None;
+        if entry.data.is_none() {
+            entries.remove(&key);
+        }
+
+        Ok(true)
+    }
+    #[tracing::instrument(
+        name = "storage.remove_orphaned_locks",
+        skip_all,
+        fields(backend = "memory", db.system = "memory", db.operation = "remove_orphaned_locks")
+    )]
+    async fn remove_orphaned_locks(&self, max_age: Duration) -> Result<usize> {
+        let now = self.clock.now();
+        let mut entries = self.entries.lock().expect("can lock");
+        let before = entries.len();
+        entries.retain(|_, e| {
+            e.data.is_some()
+                || e.lock
+                    .as_ref()
+                    .is_some_and(|lock| !is_orphan(lock, max_age, now))
+        });
+
+        Ok(before - entries.len())
+    }
+    #[tracing::instrument(
+        name = "storage.verify_lock",
+        skip_all,
+        fields(backend = "memory", db.system = "memory", db.operation = "verify_lock", id = %id)
+    )]
+    async fn verify_lock(&self, id: &ITEM::ID, lock: &StorageLock) -> Result<bool> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "memory", "verify_lock", Some(id));
+        self.simulate_latency(StorageOperation::VerifyLock).await;
+        let entries = self.entries.lock().expect("can lock");
+        match entries.get(&id.to_string()).and_then(|e| e.lock.as_ref()) {
+            Some(expected_lock) if expected_lock == lock => Ok(true),
+            expected_lock => {
+                tracing::warn!("Lock mismatch for {id} {lock:?} != {expected_lock:?}");
+                Ok(false)
+            }
+        }
+    }
+    #[tracing::instrument(
+        name = "storage.all_ids",
+        skip_all,
+        fields(backend = "memory", db.system = "memory", db.operation = "all_ids")
+    )]
+    async fn all_ids(&self) -> Result<Vec<ITEM::ID>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "memory", "all_ids", None);
+        self.simulate_latency(StorageOperation::AllIds).await;
+        let ids = self
+            .entries
+            .lock()
+            .expect("can lock")
+            .iter()
+            .filter(|(_, e)| e.data.is_some())
+            .map(|(id, _)| ITEM::ID::from_string(id))
+            .collect::<Result<Vec<_>>>()?;
+        for id in ids.iter() {
+            self.update_highest_seen_id(id);
+        }
+        Ok(ids)
+    }
+    /// Scans ids in sorted order, the scan position is the last returned id.
+    #[tracing::instrument(
+        name = "storage.scan_ids",
+        skip_all,
+        fields(backend = "memory", db.system = "memory", db.operation = "scan_ids")
+    )]
+    async fn scan_ids(&self, start: Option<&str>, limit: Option<usize>) -> Result<Page<ITEM::ID>> {
+        self.scan_ids_with_prefix("", start, limit).await
+    }
+    #[tracing::instrument(
+        name = "storage.scan_ids_with_prefix",
+        skip_all,
+        fields(
+            backend = "memory",
+            db.system = "memory",
+            db.operation = "scan_ids_with_prefix",
+            prefix = %prefix,
+        )
+    )]
+    async fn scan_ids_with_prefix(
+        &self,
+        prefix: &str,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        let _slow_op = SlowOp::start(
+            self.slow_op_threshold,
+            "memory",
+            "scan_ids_with_prefix",
+            None,
+        );
+        self.scan_ids_filtered(&crate::IdFilter::Prefix(prefix.to_string()), start, limit)
+            .await
+    }
+    #[tracing::instrument(
+        name = "storage.scan_ids_filtered",
+        skip_all,
+        fields(backend = "memory", db.system = "memory", db.operation = "scan_ids_filtered")
+    )]
+    async fn scan_ids_filtered(
+        &self,
+        filter: &crate::IdFilter,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.simulate_latency(StorageOperation::ScanIds).await;
+        let mut names: Vec<String> = self
+            .entries
+            .lock()
+            .expect("can lock")
+            .iter()
+            .filter(|(id, e)| e.data.is_some() && filter.matches(id, e.modified))
+            .map(|(id, _)| id.clone())
+            .collect();
+        let total = names.len() as u64;
+        names.retain(|id| start.is_none_or(|s| id.as_str() > s));
+
+        let scan_pos = match limit {
+            Some(limit) if names.len() > limit => {
+                names.truncate(limit);
+                names.last().cloned().or(start.map(str::to_string))
+            }
+            _ => None,
+        };
+        let ids = names
+            .iter()
+            .map(|id| ITEM::ID::from_string(id))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Page::new(ids, scan_pos).with_approximate_total(total))
+    }
+    #[tracing::instrument(
+        name = "storage.scan_ids_between",
+        skip_all,
+        fields(backend = "memory", db.system = "memory", db.operation = "scan_ids_between")
+    )]
+    async fn scan_ids_between(
+        &self,
+        from: &ITEM::ID,
+        to: &ITEM::ID,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "memory", "scan_ids_between", None);
+        self.simulate_latency(StorageOperation::ScanIds).await;
+        let ids = self
+            .entries
+            .lock()
+            .expect("can lock")
+            .iter()
+            .filter(|(_, e)| e.data.is_some())
+            .map(|(id, _)| ITEM::ID::from_string(id))
+            .collect::<Result<Vec<_>>>()?;
+
+        crate::page::page_between(ids, from, to, start, limit)
+    }
+
+    #[tracing::instrument(
+        name = "storage.list",
+        skip_all,
+        fields(backend = "memory", db.system = "memory", db.operation = "list")
+    )]
+    async fn list(
+        &self,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ItemSummary<ITEM::ID>>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "memory", "list", None);
+        self.simulate_latency(StorageOperation::List).await;
+        let entries = self.entries.lock().expect("can lock");
+        let total = entries.values().filter(|e| e.data.is_some()).count() as u64;
+        let mut rows: Vec<(&String, &Entry)> = entries
+            .iter()
+            .filter(|(id, e)| e.data.is_some() && start.is_none_or(|s| id.as_str() > s))
+            .take(limit.map_or(usize::MAX, |limit| limit.saturating_add(1)))
+            .collect();
+        let scan_pos = match limit {
+            Some(limit) if rows.len() > limit => {
+                rows.truncate(limit);
+                rows.last()
+                    .map(|(id, _)| id.to_string())
+                    .or(start.map(str::to_string))
+            }
+            _ => None,
+        };
+        let summaries = rows
+            .into_iter()
+            .map(|(id, e)| {
+                let mut summary = ItemSummary::new(ITEM::ID::from_string(id)?);
+                summary.size = e.data.as_ref().map(|data| data.len() as u64);
+                summary.modified = e.modified;
+                Ok(summary.with_lock(e.lock.as_ref()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Page::new(summaries, scan_pos).with_approximate_total(total))
+    }
+
+    #[tracing::instrument(
+        name = "storage.item_size",
+        skip_all,
+        fields(backend = "memory", db.system = "memory", db.operation = "item_size", id = %id)
+    )]
+    async fn item_size(&self, id: &ITEM::ID) -> Result<u64> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "memory", "item_size", Some(id));
+        ensure_valid_id::<ITEM>(id)?;
+        self.simulate_latency(StorageOperation::ItemSize).await;
+        let entries = self.entries.lock().expect("can lock");
+        entries
+            .get(&id.to_string())
+            .and_then(|e| e.data.as_ref())
+            .map(|data| data.len() as u64)
+            .ok_or_else(|| StorageError::NotFound { id: id.to_string() }.into())
+    }
+
+    #[tracing::instrument(
+        name = "storage.display_lock",
+        skip_all,
+        fields(backend = "memory", db.system = "memory", db.operation = "display_lock", id = %id)
+    )]
+    async fn display_lock(&self, id: &ITEM::ID) -> Result<String> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "memory", "display_lock", Some(id));
+        self.simulate_latency(StorageOperation::DisplayLock).await;
+        let entries = self.entries.lock().expect("can lock");
+        match entries.get(&id.to_string()).and_then(|e| e.lock.as_ref()) {
+            Some(lock) => Ok(format!("Locked by {} at {:?}", lock.who(), lock.when())),
+            None => Ok(String::default()),
+        }
+    }
+    #[cfg(feature = "metadata")]
+    async fn metadata_highest_seen_id(&self) -> Option<ITEM::ID> {
+        self.metadata.highest_seen_id()
+    }
+    #[cfg(feature = "metadata")]
+    async fn metadata_item_count(&self) -> Option<u64> {
+        self.metadata.item_count()
+    }
+    #[cfg(feature = "metadata")]
+    async fn metadata_total_bytes(&self) -> Option<u64> {
+        self.metadata.total_bytes()
+    }
+    #[cfg(feature = "metadata")]
+    async fn metadata_last_write(&self) -> Option<DateTime<Utc>> {
+        self.metadata.last_write()
+    }
+    #[cfg(feature = "metadata")]
+    async fn metadata_lock_stats(&self) -> crate::LockStats {
+        self.metadata.lock_stats()
+    }
+
+    #[cfg(feature = "wipe")]
+    #[tracing::instrument(
+        name = "storage.wipe",
+        skip_all,
+        fields(backend = "memory", db.system = "memory", db.operation = "wipe")
+    )]
+    async fn wipe(
+        &self,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "memory", "wipe", None);
+        options.ensure_confirmed()?;
+
+        self.simulate_latency(StorageOperation::Wipe).await;
+        let mut entries = self.entries.lock().expect("can lock");
+        let mut report = crate::WipeReport::new(options);
+        let mut tracker = crate::ProgressTracker::new(progress, Some(entries.len() as u64))
+            .with_cancellation(cancel);
+        // everything is removed at once, after counting
+        for (id, _) in entries.iter().filter(|(_, e)| e.data.is_some()) {
+            tracker.ensure_not_cancelled()?;
+            report.record(id);
+            tracker.advance(id);
+        }
+        if !options.is_dry_run() {
+            tracing::warn!("Wiping {} items.", entries.len());
+            entries.clear();
+            self.record_totals(0, Some(0));
+        }
+        tracker.finish();
+
+        Ok(report)
+    }
+
+    #[cfg(feature = "wipe")]
+    #[tracing::instrument(
+        name = "storage.wipe_matching",
+        skip_all,
+        fields(backend = "memory", db.system = "memory", db.operation = "wipe_matching")
+    )]
+    async fn wipe_matching(
+        &self,
+        filter: &crate::IdFilter,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "memory", "wipe_matching", None);
+        options.ensure_confirmed()?;
+
+        self.simulate_latency(StorageOperation::WipeMatching).await;
+        let mut entries = self.entries.lock().expect("can lock");
+        let mut ids: Vec<String> = entries
+            .iter()
+            .filter(|(id, e)| e.data.is_some() && filter.matches(id, e.modified))
+            .map(|(id, _)| id.clone())
+            .collect();
+        ids.sort_unstable();
+        let mut report = crate::WipeReport::new(options);
+        let mut tracker =
+            crate::ProgressTracker::new(progress, Some(ids.len() as u64)).with_cancellation(cancel);
+        for id in ids {
+            tracker.ensure_not_cancelled()?;
+            report.record(&id);
+            tracker.advance(&id);
+            if options.is_dry_run() {
+                continue;
+            }
+            if let Some(data) = entries.remove(&id).and_then(|e| e.data) {
+                self.record_remove(data.len() as u64);
+            }
+        }
+        tracker.finish();
+        if !report.dry_run {
+            tracing::warn!("Wiped {} matching items.", report.count);
+        }
+
+        Ok(report)
+    }
+
+    #[cfg(feature = "wipe")]
+    #[tracing::instrument(
+        name = "storage.delete_many",
+        skip_all,
+        fields(backend = "memory", db.system = "memory", db.operation = "delete_many")
+    )]
+    async fn delete_many(
+        &self,
+        ids: &[ITEM::ID],
+        options: &crate::DeleteOptions,
+    ) -> Result<Vec<crate::DeleteOutcome>> {
+        let _slow_op = SlowOp::start(self.slow_op_threshold, "memory", "delete_many", None);
+        for id in ids {
+            ensure_valid_id::<ITEM>(id)?;
+        }
+
+        self.simulate_latency(StorageOperation::DeleteMany).await;
+        let mut entries = self.entries.lock().expect("can lock");
+        let mut outcomes = Vec::with_capacity(ids.len());
+        for id in ids {
+            let id = id.to_string();
+            let Some(entry) = entries.get(&id) else {
+                outcomes.push(crate::DeleteOutcome::NotFound);
+                continue;
+            };
+            if let Some(outcome) = options.blocked_by(entry.lock.as_ref()) {
+                outcomes.push(outcome);
+                continue;
+            }
+            match entries.remove(&id).and_then(|e| e.data) {
+                Some(data) => {
+                    self.record_remove(data.len() as u64);
+                    outcomes.push(crate::DeleteOutcome::Deleted);
+                }
+                None => outcomes.push(crate::DeleteOutcome::NotFound),
+            }
+        }
+
+        Ok(outcomes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_item::TestItem;
+    #[cfg(feature = "wipe")]
+    use crate::DeleteOptions;
+    #[cfg(feature = "wipe")]
+    use crate::DeleteOutcome;
+    use crate::FsckOptions;
+    use crate::IdFilter;
+    use crate::LockManyOutcome;
+    use crate::LockResult;
+    use crate::MemoryLatency;
+    use crate::Page;
+    use crate::Storage;
+    use crate::StorageError;
+    use crate::StorageId;
+    use crate::StorageItem;
+    use crate::StorageLock;
+    use crate::StorageMemory;
+    use crate::StorageOperation;
+    #[cfg(feature = "wipe")]
+    use crate::WipeOptions;
+    #[cfg(feature = "wipe")]
+    use crate::WIPE_CONFIRMATION;
+    use color_eyre::Result;
+    use serde::Deserialize;
+    use serde::Serialize;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    /// Races `who` lockers for one item and returns the winner.
+    async fn race(seed: u64) -> Result<String> {
+        let mut storage = StorageMemory::<TestItem>::default();
+        storage.set_seed(seed)?;
+        storage.set_latency(
+            StorageOperation::Lock,
+            Some(MemoryLatency::Uniform {
+                min: Duration::from_millis(1),
+                max: Duration::from_millis(100),
+            }),
+        )?;
+        storage.ensure_storage_exists().await?;
+        let storage = Arc::new(storage);
+
+        let id = String::from("contested");
+        let lockers: Vec<_> = (0..8)
+            .map(|i| {
+                let storage = storage.clone();
+                let id = id.clone();
+                tokio::spawn(async move { storage.lock(&id, &format!("locker {i}")).await })
+            })
+            .collect();
+        let mut winners = Vec::new();
+        for locker in lockers {
+            if let LockResult::Success { lock, .. } = locker.await?? {
+                winners.push(lock.who().to_string());
+            }
+        }
+        assert_eq!(1, winners.len());
+
+        Ok(winners.remove(0))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn it_replays_lock_races() -> Result<()> {
+        let winner = race(42).await?;
+        assert_eq!(winner, race(42).await?);
+        let winners = [race(1).await?, race(2).await?, race(3).await?];
+        assert!(winners.iter().any(|w| *w != winners[0]), "{winners:?}");
+
+        Ok(())
+    }
+
+    /// Counts up from the previous collision
+    #[derive(Default, Debug, Clone, PartialEq, PartialOrd)]
+    struct CountingId(u32);
+
+    impl std::fmt::Display for CountingId {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            self.0.fmt(f)
+        }
+    }
+
+    impl StorageId for CountingId {
+        fn from_string(id: &str) -> Result<Self> {
+            Ok(Self(id.parse()?))
+        }
+        fn generate_new(previous: Option<&Self>) -> Self {
+            Self(previous.map(|p| p.0 + 1).unwrap_or_default())
+        }
+        fn is_valid_format(id: &str) -> bool {
+            id.parse::<u32>().is_ok()
+        }
+    }
+
+    #[derive(Default, Debug, Serialize, Deserialize)]
+    struct CountedItem {}
+
+    impl StorageItem for CountedItem {
+        type ID = CountingId;
+
+        fn serialize(&self) -> Result<Vec<u8>> {
+            Ok(serde_json::to_vec(&self)?)
+        }
+        fn deserialize(data: &[u8]) -> Result<Self> {
+            Ok(serde_json::from_slice(data)?)
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct NegativeCount(i32);
+
+    impl std::fmt::Display for NegativeCount {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "count {} is negative", self.0)
+        }
+    }
+
+    impl std::error::Error for NegativeCount {}
+
+    #[derive(Default, Debug, Serialize, Deserialize)]
+    struct CheckedItem {
+        count: i32,
+    }
+
+    impl StorageItem for CheckedItem {
+        type ID = String;
+
+        fn serialize(&self) -> Result<Vec<u8>> {
+            Ok(serde_json::to_vec(&self)?)
+        }
+        fn deserialize(data: &[u8]) -> Result<Self> {
+            Ok(serde_json::from_slice(data)?)
+        }
+        fn validate(&self) -> Result<()> {
+            if self.count < 0 {
+                return Err(NegativeCount(self.count).into());
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn it_rejects_invalid_items() -> Result<()> {
+        let storage = StorageMemory::<CheckedItem>::default();
+        let id = storage.create().await?;
+        let (lock, mut item) = storage.lock(&id, "TEST").await?.success()?;
+        item.count = -1;
+        let e = storage.save(&id, &item, &lock).await.unwrap_err();
+        assert_eq!(
+            Some(&StorageError::InvalidItem { id: id.clone() }),
+            e.downcast_ref()
+        );
+        assert_eq!(Some(&NegativeCount(-1)), e.downcast_ref());
+
+        item.count = 1;
+        storage.save_and_unlock(&id, &item, lock).await?;
+        assert_eq!(1, storage.load(&id).await?.count);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_gives_up_creating_after_collisions() -> Result<()> {
+        let mut storage = StorageMemory::<CountedItem>::default();
+        storage.set_create_tries(2)?;
+        for id in [CountingId(0), CountingId(1)] {
+            let (lock, item) = storage.lock(&id, "TEST").await?.success()?;
+            storage.save_and_unlock(&id, &item, lock).await?;
+        }
+        let e = storage.create().await.unwrap_err();
+        assert_eq!(
+            Some(&StorageError::IdExhausted { tries: 2 }),
+            e.downcast_ref()
+        );
+
+        storage.set_create_tries(3)?;
+        assert_eq!(CountingId(2), storage.create().await?);
+        assert!(storage.set_create_tries(0).is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_lists_items_with_locks() -> Result<()> {
+        let storage = StorageMemory::<TestItem>::default();
+        for id in ["a", "b", "c"] {
+            let id = String::from(id);
+            let (lock, item) = storage.lock(&id, "TEST").await?.success()?;
+            storage.save_and_unlock(&id, &item, lock).await?;
+        }
+        let (_lock, _) = storage.lock(&String::from("b"), "admin").await?.success()?;
+        // locked, but never saved
+        let (_lock, _) = storage.lock(&String::from("d"), "admin").await?.success()?;
+
+        let page = storage.list(None, Some(2)).await?;
+        assert_eq!(2, page.items.len());
+        assert_eq!("a", page.items[0].id);
+        assert!(!page.items[0].is_locked());
+        assert!(page.items[0].size.is_some_and(|size| size > 0));
+        assert!(page.items[0].modified.is_some());
+        assert_eq!(Some("admin"), page.items[1].locked_by.as_deref());
+
+        let page = storage.list(page.cursor.as_deref(), Some(2)).await?;
+        assert_eq!(1, page.items.len());
+        assert_eq!("c", page.items[0].id);
+        assert!(page.is_last());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_estimates_sizes() -> Result<()> {
+        let storage = StorageMemory::<TestItem>::default();
+        for (id, count) in [("a", 1), ("b", 1000)] {
+            let id = String::from(id);
+            let (lock, _) = storage.lock(&id, "TEST").await?.success()?;
+            storage
+                .save_and_unlock(&id, &TestItem { count }, lock)
+                .await?;
+        }
+
+        assert_eq!(11, storage.item_size(&String::from("a")).await?);
+        let missing = storage.item_size(&String::from("c")).await.unwrap_err();
+        assert!(matches!(
+            missing.downcast_ref(),
+            Some(StorageError::NotFound { .. })
+        ));
+
+        let cost = storage.estimate_cost().await?;
+        assert_eq!(2, cost.item_count);
+        assert_eq!(25, cost.total_bytes);
+        assert_eq!(14, cost.largest_item_bytes);
+        assert_eq!(None, cost.capacity);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_checks_consistency() -> Result<()> {
+        let mut storage = StorageMemory::<TestItem>::default();
+        // fsck ages locks by the system clock
+        let clock = crate::MockClock::new(chrono::Utc::now() - chrono::Duration::hours(2));
+        storage.set_clock(clock.clone())?;
+        for id in ["fine", "stale"] {
+            let id = String::from(id);
+            let (lock, item) = storage.lock(&id, "TEST").await?.success()?;
+            storage.save_and_unlock(&id, &item, lock).await?;
+        }
+        let broken = String::from("broken");
+        let (lock, _) = storage.lock(&broken, "TEST").await?.success()?;
+        storage.save_raw(&broken, b"not json", &lock).await?;
+        storage.unlock(&broken, lock).await?;
+        storage
+            .lock(&String::from("stale"), "crashed")
+            .await?
+            .success()?;
+        storage
+            .lock(&String::from("orphan"), "crashed")
+            .await?
+            .success()?;
+        clock.advance(Duration::from_secs(2 * 60 * 60));
+
+        let report = storage.fsck(&FsckOptions::default()).await?;
+        assert_eq!(3, report.items_checked);
+        assert_eq!(vec![broken.clone()], report.invalid);
+        assert_eq!(vec![String::from("stale")], report.stale_locks);
+        assert_eq!(None, report.orphaned_locks_removed);
+        assert!(!report.is_clean());
+
+        let options = FsckOptions::default().with_repair(true);
+        let report = storage.fsck(&options).await?;
+        assert_eq!(Some(1), report.orphaned_locks_removed);
+        assert!(!storage.exists(&String::from("orphan")).await?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_explains_rejected_saves() -> Result<()> {
+        let mut storage = StorageMemory::<TestItem>::default();
+        let clock = crate::MockClock::default();
+        storage.set_clock(clock.clone())?;
+        let id = String::from("a");
+        let (stale, item) = storage.lock(&id, "worker-1").await?.success()?;
+        storage.force_unlock(&id).await?;
+        clock.advance(Duration::from_secs(5));
+        let (lock, item2) = storage.lock(&id, "worker-2").await?.success()?;
+        clock.advance(Duration::from_secs(5));
+        storage.save(&id, &item2, &lock).await?;
+        clock.advance(Duration::from_secs(5));
+
+        let e = storage.save(&id, &item, &stale).await.unwrap_err();
+        assert_eq!(
+            Some(&StorageError::LockMismatch {
+                id: id.clone(),
+                who: String::from("worker-1"),
+                holder: Some(String::from("worker-2")),
+                held_for: Some(Duration::from_secs(10)),
+                changed: Some(true),
+            }),
+            e.downcast_ref()
+        );
+        assert!(e.to_string().starts_with("Lock invalid!"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_saves_immutable_items_once() -> Result<()> {
+        let mut storage = StorageMemory::<TestItem>::default();
+        storage.set_immutable(true)?;
+        let id = storage.create().await?;
+        let (lock, item) = storage.lock(&id, "TEST").await?.success()?;
+        storage.save(&id, &item, &lock).await?;
+
+        let e = storage.save(&id, &item, &lock).await.unwrap_err();
+        assert_eq!(
+            Some(&StorageError::Immutable { id: id.clone() }),
+            e.downcast_ref()
+        );
+        storage.unlock(&id, lock).await?;
+        storage.load(&id).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_hands_off_locks() -> Result<()> {
+        let storage = StorageMemory::<TestItem>::default();
+        let id = storage.create().await?;
+        let (lock, item) = storage.lock(&id, "worker-1").await?.success()?;
+
+        let handed_off = storage.handoff_lock(&id, lock, "worker-2").await?;
+        assert_eq!("worker-2", handed_off.who());
+        match storage.lock(&id, "worker-3").await? {
+            LockResult::AlreadyLocked { who, .. } => assert_eq!("worker-2", who),
+            LockResult::Success { .. } => panic!("lock was released during the handoff"),
+        }
+        let stale = StorageLock::new("worker-1");
+        assert!(storage.handoff_lock(&id, stale, "worker-3").await.is_err());
+
+        storage.save_and_unlock(&id, &item, handed_off).await?;
+        storage.lock(&id, "worker-3").await?.success()?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_force_unlocks_all_locks_of_an_owner() -> Result<()> {
+        let storage = StorageMemory::<TestItem>::default();
+        for id in ["a", "b", "c"] {
+            let id = String::from(id);
+            let (lock, item) = storage.lock(&id, "TEST").await?.success()?;
+            storage.save_and_unlock(&id, &item, lock).await?;
+        }
+        let (_lock, _) = storage
+            .lock(&String::from("a"), "host-1/worker-1")
+            .await?
+            .success()?;
+        let (_lock, _) = storage
+            .lock(&String::from("b"), "host-2/worker-1")
+            .await?
+            .success()?;
+        let (_lock, _) = storage
+            .lock(&String::from("c"), "host-1/worker-2")
+            .await?
+            .success()?;
+
+        let unlocked = storage.force_unlock_all("host-1/").await?;
+        assert_eq!(vec![String::from("a"), String::from("c")], unlocked);
+        assert!(storage
+            .lock(&String::from("a"), "TEST")
+            .await?
+            .success()
+            .is_ok());
+        assert!(storage
+            .lock(&String::from("b"), "TEST")
+            .await?
+            .success()
+            .is_err());
+        assert!(storage.force_unlock_all("host-1/").await?.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_refuses_to_lock_corrupt_items() -> Result<()> {
+        let storage = StorageMemory::<TestItem>::default();
+        let id = String::from("corrupt");
+        let (lock, _) = storage.lock(&id, "TEST").await?.success()?;
+        storage.save_raw(&id, b"{ not json", &lock).await?;
+        storage.unlock(&id, lock).await?;
+
+        let e = storage.lock(&id, "TEST").await.err();
+        assert!(matches!(
+            e.as_ref().and_then(|e| e.downcast_ref()),
+            Some(StorageError::Corrupt { .. })
+        ));
+        assert!(storage.force_unlock(&id).await?.is_none());
+        assert_eq!(b"{ not json".to_vec(), storage.load_raw(&id).await?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_needs_the_token_of_a_lock() -> Result<()> {
+        let storage = StorageMemory::<TestItem>::default();
+        let id = String::from("a");
+        let (lock, _) = storage.lock(&id, "TEST").await?.success()?;
+        let observed = StorageLock::observed(lock.who(), *lock.when());
+        assert!(!storage.verify_lock(&id, &observed).await?);
+        assert!(storage.unlock(&id, observed).await.is_err());
+        assert!(storage.verify_lock(&id, &lock).await?);
+
+        assert!(
+            !storage
+                .force_unlock_if_held(&id, "OTHER", *lock.when())
+                .await?
+        );
+        assert!(
+            storage
+                .force_unlock_if_held(&id, "TEST", *lock.when())
+                .await?
+        );
+        assert!(!storage.verify_lock(&id, &lock).await?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_stores_meta_values_apart_from_items() -> Result<()> {
+        let storage = StorageMemory::<TestItem>::default();
+        assert_eq!(None, storage.get_meta("schema_version").await?);
+        storage.put_meta("schema_version", b"1").await?;
+        storage.put_meta("schema_version", b"2").await?;
+        assert_eq!(
+            Some(b"2".to_vec()),
+            storage.get_meta("schema_version").await?
+        );
+        assert!(storage.all_ids().await?.is_empty());
+        assert!(storage.put_meta("../escape", b"x").await.is_err());
+
+        Ok(())
+    }
+
+    #[cfg(feature = "metadata")]
+    #[tokio::test]
+    async fn it_tracks_lock_hold_times() -> Result<()> {
+        let clock = crate::MockClock::default();
+        let mut storage = StorageMemory::<TestItem>::default();
+        storage.set_clock(clock.clone())?;
+        storage.set_max_lock_hold_time(Some(Duration::from_secs(5)))?;
+        storage.ensure_storage_exists().await?;
+
+        let id = String::from("slow");
+        for held_for in [Duration::from_millis(50), Duration::from_secs(30)] {
+            let (lock, _) = storage.lock(&id, "TEST").await?.success()?;
+            clock.advance(held_for);
+            storage.unlock(&id, lock).await?;
+        }
+
+        let hold_times = storage.metadata_lock_stats().await.hold_times;
+        assert_eq!(2, hold_times.count);
+        assert_eq!([0, 1, 0, 0, 1, 0], hold_times.buckets);
+        assert_eq!(1, hold_times.too_long);
+        assert_eq!(Some(Duration::from_millis(15025)), hold_times.mean());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_saves_and_scans() -> Result<()> {
+        let mut storage = StorageMemory::<TestItem>::default();
+        storage.ensure_storage_exists().await?;
+        let us = "TEST";
+
+        for id in ["b", "a", "c"] {
+            let id = String::from(id);
+            let (lock, _) = storage.lock(&id, us).await?.success()?;
+            storage
+                .save_and_unlock(&id, &TestItem { count: 1 }, lock)
+                .await?;
+        }
+        let unsaved = String::from("d");
+        let (lock, _) = storage.lock(&unsaved, us).await?.success()?;
+        assert!(storage.verify_lock(&unsaved, &lock).await?);
+        storage.unlock(&unsaved, lock).await?;
+        assert!(!storage.exists(&unsaved).await?);
+        assert_eq!(None, storage.try_load(&unsaved).await?);
+        let e = storage.load(&unsaved).await.unwrap_err();
+        assert_eq!(
+            Some(&StorageError::NotFound {
+                id: unsaved.clone()
+            }),
+            e.downcast_ref::<StorageError>()
+        );
+
+        let Page {
+            items: ids,
+            cursor: scan_pos,
+            approximate_total,
+        } = storage.scan_ids(None, Some(2)).await?;
+        assert_eq!(vec!["a", "b"], ids);
+        assert_eq!(Some(3), approximate_total);
+        let Page {
+            items: ids,
+            cursor: scan_pos,
+            ..
+        } = storage.scan_ids(scan_pos.as_deref(), Some(2)).await?;
+        assert_eq!(vec!["c"], ids);
+        assert_eq!(None, scan_pos);
+        assert_eq!(TestItem { count: 1 }, storage.load(&ids[0]).await?);
+        assert_eq!(
+            Some(TestItem { count: 1 }),
+            storage.try_load(&ids[0]).await?
+        );
+
+        let range = IdFilter::Range {
+            start: Some(String::from("b")),
+            end: None,
+        };
+        let page = storage.scan_ids_filtered(&range, None, Some(1)).await?;
+        assert_eq!(vec!["b"], page.items);
+        assert_eq!(Some(2), page.approximate_total);
+        let page = storage
+            .scan_ids_filtered(&range, page.cursor.as_deref(), Some(1))
+            .await?;
+        assert_eq!((vec![String::from("c")], None), (page.items, page.cursor));
+        let recent = IdFilter::ModifiedBefore(chrono::Utc::now());
+        assert_eq!(
+            3,
+            storage
+                .scan_ids_filtered(&recent, None, None)
+                .await?
+                .items
+                .len()
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_scans_ids_between() -> Result<()> {
+        let storage = StorageMemory::<TestItem>::default();
+        for id in 1..=12 {
+            let id = id.to_string();
+            let (lock, _) = storage.lock(&id, "test").await?.success()?;
+            storage
+                .save_and_unlock(&id, &TestItem { count: 1 }, lock)
+                .await?;
+        }
+
+        // numeric ids are ordered by value, not as strings
+        let (from, to) = (String::from("2"), String::from("11"));
+        let page = storage.scan_ids_between(&from, &to, None, Some(5)).await?;
+        assert_eq!(vec!["2", "3", "4", "5", "6"], page.items);
+        let page = storage
+            .scan_ids_between(&from, &to, page.cursor.as_deref(), Some(5))
+            .await?;
+        assert_eq!(vec!["7", "8", "9", "10"], page.items);
+        assert_eq!(None, page.cursor);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "wipe")]
+    #[tokio::test]
+    async fn it_wipes() -> Result<()> {
+        let storage = StorageMemory::<TestItem>::default();
+        let id = String::from("a");
+        let (lock, _) = storage.lock(&id, "TEST").await?.success()?;
+        storage
+            .save_and_unlock(&id, &TestItem { count: 1 }, lock)
+            .await?;
+
+        let report = storage.wipe(&WipeOptions::DryRun, None, None).await?;
+        assert!(report.dry_run);
+        assert_eq!(1, report.count);
+        assert_eq!(vec![id.clone()], report.sample_ids);
+        assert!(storage.exists(&id).await?);
+
+        assert!(storage
+            .wipe(&WipeOptions::delete("yes"), None, None)
+            .await
+            .is_err());
+        let report = storage
+            .wipe(&WipeOptions::delete(WIPE_CONFIRMATION), None, None)
+            .await?;
+        assert!(!report.dry_run);
+        assert_eq!(1, report.count);
+        assert!(!storage.exists(&id).await?);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "wipe")]
+    #[tokio::test]
+    async fn it_wipes_matching() -> Result<()> {
+        let storage = StorageMemory::<TestItem>::default();
+        for id in ["a/1", "a/2", "b/1"] {
+            let id = String::from(id);
+            let (lock, _) = storage.lock(&id, "TEST").await?.success()?;
+            storage
+                .save_and_unlock(&id, &TestItem { count: 1 }, lock)
+                .await?;
+        }
+
+        let filter = IdFilter::Prefix(String::from("a/"));
+        let report = storage
+            .wipe_matching(&filter, &WipeOptions::delete(WIPE_CONFIRMATION), None, None)
+            .await?;
+        assert_eq!(
+            vec![String::from("a/1"), String::from("a/2")],
+            report.sample_ids
+        );
+        assert!(!storage.exists(&String::from("a/1")).await?);
+        assert!(storage.exists(&String::from("b/1")).await?);
+
+        let filter = IdFilter::ModifiedBefore(chrono::Utc::now());
+        let report = storage
+            .wipe_matching(&filter, &WipeOptions::DryRun, None, None)
+            .await?;
+        assert_eq!(1, report.count);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "wipe")]
+    #[tokio::test]
+    async fn it_deletes_many() -> Result<()> {
+        let storage = StorageMemory::<TestItem>::default();
+        let ids: Vec<String> = ["a", "b", "c"].into_iter().map(String::from).collect();
+        for id in &ids[..2] {
+            let (lock, _) = storage.lock(id, "TEST").await?.success()?;
+            storage
+                .save_and_unlock(id, &TestItem { count: 1 }, lock)
+                .await?;
+        }
+        let (held, _) = storage.lock(&ids[1], "holder").await?.success()?;
+
+        let outcomes = storage.delete_many(&ids, &DeleteOptions::default()).await?;
+        assert_eq!(
+            vec![
+                DeleteOutcome::Deleted,
+                DeleteOutcome::Locked {
+                    who: String::from("holder")
+                },
+                DeleteOutcome::NotFound,
+            ],
+            outcomes
+        );
+        assert!(!storage.exists(&ids[0]).await?);
+
+        let forced = DeleteOptions::default().with_force(true);
+        let outcomes = storage.delete_many(&ids[1..2], &forced).await?;
+        assert_eq!(vec![DeleteOutcome::Deleted], outcomes);
+        assert!(!storage.verify_lock(&ids[1], &held).await?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_locks_and_loads_many() -> Result<()> {
+        let storage = StorageMemory::<TestItem>::default();
+        let ids: Vec<String> = ["a", "b", "c"].into_iter().map(String::from).collect();
+        for (count, id) in ids[..2].iter().enumerate() {
+            let (lock, _) = storage.lock(id, "TEST").await?.success()?;
+            storage
+                .save_and_unlock(
+                    id,
+                    &TestItem {
+                        count: count as u32,
+                    },
+                    lock,
+                )
+                .await?;
+        }
+        let (_held, _) = storage.lock(&ids[1], "holder").await?.success()?;
+
+        let mut outcomes = storage.lock_and_load_many(&ids, "matchmaker").await?;
+        assert!(matches!(outcomes.pop(), Some(LockManyOutcome::NotFound)));
+        assert!(matches!(
+            outcomes.pop(),
+            Some(LockManyOutcome::AlreadyLocked { who, .. }) if who == "holder"
+        ));
+        let (lock, item) = outcomes.pop().and_then(|o| o.success()).expect("locked");
+        assert_eq!(TestItem { count: 0 }, item);
+        assert_eq!("matchmaker", lock.who());
+        assert!(
+            !storage.exists(&ids[2]).await?,
+            "missing items aren't locked"
+        );
+
+        Ok(())
+    }
+}
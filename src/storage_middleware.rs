@@ -0,0 +1,1029 @@
+use crate::access_policy::AccessPolicyMiddleware;
+use crate::audit::AuditMiddleware;
+use crate::AccessPolicy;
+use crate::AuditSink;
+use crate::LockResult;
+use crate::OpOptions;
+use crate::Page;
+use crate::QuarantineRecord;
+use crate::QuarantineSink;
+use crate::RequestContext;
+use crate::Storage;
+use crate::StorageError;
+use crate::StorageItem;
+use crate::StorageLock;
+use async_trait::async_trait;
+use chrono::DateTime;
+use chrono::Utc;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Report;
+use color_eyre::eyre::Result;
+use core::marker::PhantomData;
+use serde::Deserialize;
+use serde::Serialize;
+use std::future::Future;
+use std::time::Duration;
+
+/// The operations of [Storage], as seen by a [StorageMiddleware].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageOperation {
+    EnsureStorageExists,
+    Create,
+    Exists,
+    Load,
+    LoadMany,
+    Prefetch,
+    Save,
+    Lock,
+    Unlock,
+    SaveAndUnlock,
+    HandoffLock,
+    LoadRaw,
+    SaveRaw,
+    PutBlob,
+    GetBlob,
+    ListBlobs,
+    PutMeta,
+    GetMeta,
+    AddAlias,
+    RemoveAlias,
+    ResolveAlias,
+    AddLink,
+    RemoveLink,
+    LinksOf,
+    LinkedFrom,
+    ForceUnlock,
+    RemoveOrphanedLocks,
+    VerifyLock,
+    AllIds,
+    ScanIds,
+    List,
+    ItemSize,
+    EstimateCost,
+    Shutdown,
+    Fsck,
+    DisplayLock,
+    Wipe,
+    WipeMatching,
+    DeleteMany,
+}
+
+impl StorageOperation {
+    /// The name used in tracing spans, e.g. `save_and_unlock`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            StorageOperation::EnsureStorageExists => "ensure_storage_exists",
+            StorageOperation::Create => "create",
+            StorageOperation::Exists => "exists",
+            StorageOperation::Load => "load",
+            StorageOperation::LoadMany => "load_many",
+            StorageOperation::Prefetch => "prefetch",
+            StorageOperation::Save => "save",
+            StorageOperation::Lock => "lock",
+            StorageOperation::Unlock => "unlock",
+            StorageOperation::SaveAndUnlock => "save_and_unlock",
+            StorageOperation::HandoffLock => "handoff_lock",
+            StorageOperation::LoadRaw => "load_raw",
+            StorageOperation::SaveRaw => "save_raw",
+            StorageOperation::PutBlob => "put_blob",
+            StorageOperation::GetBlob => "get_blob",
+            StorageOperation::ListBlobs => "list_blobs",
+            StorageOperation::PutMeta => "put_meta",
+            StorageOperation::GetMeta => "get_meta",
+            StorageOperation::AddAlias => "add_alias",
+            StorageOperation::RemoveAlias => "remove_alias",
+            StorageOperation::ResolveAlias => "resolve_alias",
+            StorageOperation::AddLink => "add_link",
+            StorageOperation::RemoveLink => "remove_link",
+            StorageOperation::LinksOf => "links_of",
+            StorageOperation::LinkedFrom => "linked_from",
+            StorageOperation::ForceUnlock => "force_unlock",
+            StorageOperation::RemoveOrphanedLocks => "remove_orphaned_locks",
+            StorageOperation::VerifyLock => "verify_lock",
+            StorageOperation::AllIds => "all_ids",
+            StorageOperation::ScanIds => "scan_ids",
+            StorageOperation::List => "list",
+            StorageOperation::ItemSize => "item_size",
+            StorageOperation::EstimateCost => "estimate_cost",
+            StorageOperation::Shutdown => "shutdown",
+            StorageOperation::Fsck => "fsck",
+            StorageOperation::DisplayLock => "display_lock",
+            StorageOperation::Wipe => "wipe",
+            StorageOperation::WipeMatching => "wipe_matching",
+            StorageOperation::DeleteMany => "delete_many",
+        }
+    }
+}
+
+impl StorageOperation {
+    /// Operations that can't be undone and are recorded by an [AuditSink].
+    pub fn is_destructive(&self) -> bool {
+        matches!(
+            self,
+            StorageOperation::ForceUnlock
+                | StorageOperation::RemoveOrphanedLocks
+                | StorageOperation::Wipe
+                | StorageOperation::WipeMatching
+                | StorageOperation::DeleteMany
+        )
+    }
+}
+
+impl StorageOperation {
+    /// Operations that only read, everything else, that isn't [StorageOperation::is_destructive], writes.
+    pub fn is_read(&self) -> bool {
+        matches!(
+            self,
+            StorageOperation::Exists
+                | StorageOperation::Load
+                | StorageOperation::LoadMany
+                | StorageOperation::Prefetch
+                | StorageOperation::LoadRaw
+                | StorageOperation::GetBlob
+                | StorageOperation::ListBlobs
+                | StorageOperation::GetMeta
+                | StorageOperation::ResolveAlias
+                | StorageOperation::LinksOf
+                | StorageOperation::LinkedFrom
+                | StorageOperation::VerifyLock
+                | StorageOperation::AllIds
+                | StorageOperation::ScanIds
+                | StorageOperation::List
+                | StorageOperation::ItemSize
+                | StorageOperation::EstimateCost
+                | StorageOperation::DisplayLock
+        )
+    }
+}
+
+impl std::fmt::Display for StorageOperation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A single operation passed to the hooks of a [StorageMiddleware].
+#[derive(Debug)]
+pub struct StorageRequest<'a, ITEM: StorageItem> {
+    operation: StorageOperation,
+    ids: Vec<&'a ITEM::ID>,
+    who: Option<&'a str>,
+    displaced_lock: Option<StorageLock>,
+    context: Option<RequestContext>,
+}
+
+impl<'a, ITEM: StorageItem> StorageRequest<'a, ITEM> {
+    fn new(operation: StorageOperation) -> Self {
+        Self {
+            operation,
+            ids: Vec::new(),
+            who: None,
+            displaced_lock: None,
+            context: RequestContext::current(),
+        }
+    }
+
+    fn with_id(mut self, id: &'a ITEM::ID) -> Self {
+        self.ids.push(id);
+        self
+    }
+
+    fn with_who(mut self, who: &'a str) -> Self {
+        self.who = Some(who);
+        self
+    }
+
+    pub fn operation(&self) -> StorageOperation {
+        self.operation
+    }
+
+    /// The ids the operation acts on, e.g. `[alias, canonical]` for [Storage::add_alias].
+    /// Empty for operations on the whole storage.
+    pub fn ids(&self) -> &[&'a ITEM::ID] {
+        &self.ids
+    }
+
+    /// Who wants the lock or holds the lock used by the operation.
+    pub fn who(&self) -> Option<&'a str> {
+        self.who
+    }
+
+    /// The lock removed by a successful [Storage::force_unlock], only seen by `after` hooks.
+    pub fn displaced_lock(&self) -> Option<&StorageLock> {
+        self.displaced_lock.as_ref()
+    }
+
+    /// The context the operation runs in, see [RequestContext::scope].
+    pub fn context(&self) -> Option<&RequestContext> {
+        self.context.as_ref()
+    }
+}
+
+/// Hooks around every operation of a [StorageWithMiddleware],
+/// e.g. for audit logging or simple authorization.
+///
+/// Middleware can observe and veto operations, but not change them.
+#[async_trait]
+pub trait StorageMiddleware<ITEM: StorageItem>: Send + Sync + std::fmt::Debug {
+    /// Called before the operation. Returning an error vetoes it,
+    /// and the error is returned to the caller.
+    async fn before(&self, _request: &StorageRequest<'_, ITEM>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called after the operation ran, with its error if it failed.
+    /// For vetoed operations it is called with the veto.
+    async fn after(&self, _request: &StorageRequest<'_, ITEM>, _error: Option<&Report>) {}
+}
+
+/// Wraps any [Storage] and runs its middleware chain around every operation.
+///
+/// `before` hooks run in the order the middleware was added, `after` hooks in reverse order.
+/// The first veto stops the chain, and only middleware that already ran `before` sees `after`.
+#[derive(Debug)]
+pub struct StorageWithMiddleware<ITEM: StorageItem, S: Storage<ITEM>>
+where
+    ITEM: Send,
+{
+    storage: S,
+    middleware: Vec<Box<dyn StorageMiddleware<ITEM>>>,
+    quarantine_sink: Option<Box<dyn QuarantineSink>>,
+    quarantine_corrupt_items: bool,
+    item_type: PhantomData<ITEM>,
+}
+
+impl<ITEM: StorageItem + Send, S: Storage<ITEM>> StorageWithMiddleware<ITEM, S> {
+    pub fn new(storage: S) -> Self {
+        Self {
+            storage,
+            middleware: Vec::new(),
+            quarantine_sink: None,
+            quarantine_corrupt_items: false,
+            item_type: PhantomData,
+        }
+    }
+
+    /// Appends `middleware` to the end of the chain.
+    pub fn add_middleware(
+        &mut self,
+        middleware: impl StorageMiddleware<ITEM> + 'static,
+    ) -> Result<()> {
+        self.middleware.push(Box::new(middleware));
+
+        Ok(())
+    }
+
+    /// Appends `policy` to the end of the chain, denied operations fail with
+    /// [crate::StorageError::PermissionDenied].
+    /// Add it first, so other middleware never sees denied operations.
+    pub fn add_access_policy(&mut self, policy: impl AccessPolicy<ITEM> + 'static) -> Result<()> {
+        self.add_middleware(AccessPolicyMiddleware(policy))
+    }
+
+    /// Appends `sink` to the end of the chain, to record destructive operations by `who`,
+    /// see [StorageOperation::is_destructive].
+    pub fn add_audit_sink(&mut self, sink: impl AuditSink + 'static, who: &str) -> Result<()> {
+        self.add_middleware(AuditMiddleware {
+            sink,
+            who: who.to_string(),
+        })
+    }
+
+    /// Copies items into `sink` before [Storage::force_unlock] removes their lock.
+    ///
+    /// Force unlocks fail if the item can't be loaded, failing to record the copy is only logged.
+    pub fn set_quarantine_sink(&mut self, sink: impl QuarantineSink + 'static) -> Result<()> {
+        self.quarantine_sink = Some(Box::new(sink));
+
+        Ok(())
+    }
+
+    /// Loads failing with [StorageError::Corrupt] copy the stored data into the quarantine sink, if one is set,
+    /// and return when it was preserved.
+    /// The item itself stays in place, so other ids can still be loaded, e.g. by batch jobs.
+    pub fn set_quarantine_corrupt_items(&mut self, quarantine_corrupt_items: bool) -> Result<()> {
+        self.quarantine_corrupt_items = quarantine_corrupt_items;
+
+        Ok(())
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    pub fn into_inner(self) -> S {
+        self.storage
+    }
+
+    /// Quarantines items whose load failed with [StorageError::Corrupt].
+    async fn checked_load(
+        &self,
+        id: &ITEM::ID,
+        load: impl Future<Output = Result<ITEM>> + Send,
+    ) -> Result<ITEM> {
+        let e = match load.await {
+            Ok(item) => return Ok(item),
+            Err(e) => e,
+        };
+        // failed for another reason, e.g. the item is missing, or the backend is down
+        if !matches!(e.downcast_ref(), Some(StorageError::Corrupt { .. })) {
+            return Err(e);
+        }
+        tracing::warn!("{id} is corrupt -> {e:?}");
+        let Some(sink) = self
+            .quarantine_sink
+            .as_ref()
+            .filter(|_| self.quarantine_corrupt_items)
+        else {
+            return Err(e);
+        };
+        let data = match self.storage.load_raw(id).await {
+            Ok(data) => data,
+            Err(raw_e) => {
+                tracing::error!("Can't read corrupt {id} to quarantine it -> {raw_e:?}");
+                return Err(e);
+            }
+        };
+        let record = QuarantineRecord::corrupt(&id.to_string(), data);
+        if let Err(sink_e) = sink.quarantine(&record).await {
+            tracing::error!("Can't quarantine corrupt {id} -> {sink_e:?}");
+            return Err(e);
+        }
+
+        Err(e.wrap_err(StorageError::Corrupt {
+            id: id.to_string(),
+            preserved_at: Some(record.when),
+        }))
+    }
+
+    async fn quarantined_force_unlock(&self, id: &ITEM::ID) -> Result<Option<StorageLock>> {
+        let Some(sink) = &self.quarantine_sink else {
+            return self.storage.force_unlock(id).await;
+        };
+        let data = match self.storage.load_raw(id).await {
+            Ok(data) => Some(data),
+            Err(e) if matches!(e.downcast_ref(), Some(StorageError::NotFound { .. })) => None,
+            Err(e) => return Err(eyre!("Can't quarantine {id}, not force unlocking -> {e:?}")),
+        };
+        let lock = self.storage.force_unlock(id).await?;
+        if let Some(lock) = &lock {
+            let record = QuarantineRecord::new(&id.to_string(), lock, data);
+            if let Err(e) = sink.quarantine(&record).await {
+                tracing::error!("Force unlocked {id}, but can't quarantine it -> {e:?}");
+            }
+        }
+
+        Ok(lock)
+    }
+
+    /// [StorageWithMiddleware::quarantined_force_unlock] for [Storage::force_unlock_if_held].
+    async fn quarantined_force_unlock_if_held(
+        &self,
+        id: &ITEM::ID,
+        who: &str,
+        when: DateTime<Utc>,
+    ) -> Result<bool> {
+        let Some(sink) = &self.quarantine_sink else {
+            return self.storage.force_unlock_if_held(id, who, when).await;
+        };
+        let data = match self.storage.load_raw(id).await {
+            Ok(data) => Some(data),
+            Err(e) if matches!(e.downcast_ref(), Some(StorageError::NotFound { .. })) => None,
+            Err(e) => return Err(eyre!("Can't quarantine {id}, not force unlocking -> {e:?}")),
+        };
+        let unlocked = self.storage.force_unlock_if_held(id, who, when).await?;
+        if unlocked {
+            let record =
+                QuarantineRecord::new(&id.to_string(), &StorageLock::observed(who, when), data);
+            if let Err(e) = sink.quarantine(&record).await {
+                tracing::error!("Force unlocked {id}, but can't quarantine it -> {e:?}");
+            }
+        }
+
+        Ok(unlocked)
+    }
+}
+
+async fn run<ITEM: StorageItem, T>(
+    middleware: &[Box<dyn StorageMiddleware<ITEM>>],
+    request: StorageRequest<'_, ITEM>,
+    operation: impl Future<Output = Result<T>> + Send,
+) -> Result<T> {
+    run_and_inspect(middleware, request, operation, |_, _| {}).await
+}
+
+/// Like [run], but `inspect` can add the result of the operation to the request,
+/// before the `after` hooks see it.
+async fn run_and_inspect<ITEM: StorageItem, T>(
+    middleware: &[Box<dyn StorageMiddleware<ITEM>>],
+    mut request: StorageRequest<'_, ITEM>,
+    operation: impl Future<Output = Result<T>> + Send,
+    inspect: impl FnOnce(&mut StorageRequest<'_, ITEM>, &T) + Send,
+) -> Result<T> {
+    for (i, m) in middleware.iter().enumerate() {
+        if let Err(e) = m.before(&request).await {
+            for m in middleware[..i].iter().rev() {
+                m.after(&request, Some(&e)).await;
+            }
+            return Err(e);
+        }
+    }
+    let r = operation.await;
+    if let Ok(t) = &r {
+        inspect(&mut request, t);
+    }
+    for m in middleware.iter().rev() {
+        m.after(&request, r.as_ref().err()).await;
+    }
+
+    r
+}
+
+#[async_trait]
+impl<ITEM: StorageItem + Send, S: Storage<ITEM>> Storage<ITEM> for StorageWithMiddleware<ITEM, S> {
+    async fn ensure_storage_exists(&mut self) -> Result<()> {
+        let request = StorageRequest::new(StorageOperation::EnsureStorageExists);
+        run(
+            &self.middleware,
+            request,
+            self.storage.ensure_storage_exists(),
+        )
+        .await
+    }
+
+    fn capabilities(&self) -> crate::Capabilities {
+        self.storage.capabilities()
+    }
+
+    async fn create(&self) -> Result<ITEM::ID> {
+        let request = StorageRequest::new(StorageOperation::Create);
+        run(&self.middleware, request, self.storage.create()).await
+    }
+
+    async fn exists(&self, id: &ITEM::ID) -> Result<bool> {
+        let request = StorageRequest::new(StorageOperation::Exists).with_id(id);
+        run(&self.middleware, request, self.storage.exists(id)).await
+    }
+
+    async fn exists_with(&self, id: &ITEM::ID, options: &OpOptions) -> Result<bool> {
+        let request = StorageRequest::new(StorageOperation::Exists).with_id(id);
+        run(
+            &self.middleware,
+            request,
+            self.storage.exists_with(id, options),
+        )
+        .await
+    }
+
+    async fn load(&self, id: &ITEM::ID) -> Result<ITEM> {
+        let request = StorageRequest::new(StorageOperation::Load).with_id(id);
+        run(
+            &self.middleware,
+            request,
+            self.checked_load(id, self.storage.load(id)),
+        )
+        .await
+    }
+
+    async fn load_with(&self, id: &ITEM::ID, options: &OpOptions) -> Result<ITEM> {
+        let request = StorageRequest::new(StorageOperation::Load).with_id(id);
+        run(
+            &self.middleware,
+            request,
+            self.checked_load(id, self.storage.load_with(id, options)),
+        )
+        .await
+    }
+
+    async fn load_many(&self, ids: &[ITEM::ID]) -> Result<Vec<Option<ITEM>>> {
+        let mut request = StorageRequest::new(StorageOperation::LoadMany);
+        request.ids.extend(ids);
+        run(&self.middleware, request, self.storage.load_many(ids)).await
+    }
+
+    async fn prefetch(&self, ids: &[ITEM::ID]) -> Result<()> {
+        let mut request = StorageRequest::new(StorageOperation::Prefetch);
+        request.ids.extend(ids);
+        run(&self.middleware, request, self.storage.prefetch(ids)).await
+    }
+
+    async fn save(&self, id: &ITEM::ID, item: &ITEM, lock: &StorageLock) -> Result<()> {
+        let request = StorageRequest::new(StorageOperation::Save)
+            .with_id(id)
+            .with_who(lock.who());
+        run(&self.middleware, request, self.storage.save(id, item, lock)).await
+    }
+
+    async fn lock(&self, id: &ITEM::ID, who: &str) -> Result<LockResult<ITEM>> {
+        let request = StorageRequest::new(StorageOperation::Lock)
+            .with_id(id)
+            .with_who(who);
+        run(&self.middleware, request, self.storage.lock(id, who)).await
+    }
+
+    async fn unlock(&self, id: &ITEM::ID, lock: StorageLock) -> Result<()> {
+        let who = lock.who().to_string();
+        let request = StorageRequest::new(StorageOperation::Unlock)
+            .with_id(id)
+            .with_who(&who);
+        run(&self.middleware, request, self.storage.unlock(id, lock)).await
+    }
+
+    async fn save_and_unlock(&self, id: &ITEM::ID, item: &ITEM, lock: StorageLock) -> Result<()> {
+        let who = lock.who().to_string();
+        let request = StorageRequest::new(StorageOperation::SaveAndUnlock)
+            .with_id(id)
+            .with_who(&who);
+        run(
+            &self.middleware,
+            request,
+            self.storage.save_and_unlock(id, item, lock),
+        )
+        .await
+    }
+
+    async fn handoff_lock(
+        &self,
+        id: &ITEM::ID,
+        lock: StorageLock,
+        new_who: &str,
+    ) -> Result<StorageLock> {
+        let who = lock.who().to_string();
+        let request = StorageRequest::new(StorageOperation::HandoffLock)
+            .with_id(id)
+            .with_who(&who);
+        run(
+            &self.middleware,
+            request,
+            self.storage.handoff_lock(id, lock, new_who),
+        )
+        .await
+    }
+
+    async fn load_raw(&self, id: &ITEM::ID) -> Result<Vec<u8>> {
+        let request = StorageRequest::new(StorageOperation::LoadRaw).with_id(id);
+        run(&self.middleware, request, self.storage.load_raw(id)).await
+    }
+
+    async fn save_raw(&self, id: &ITEM::ID, data: &[u8], lock: &StorageLock) -> Result<()> {
+        let request = StorageRequest::new(StorageOperation::SaveRaw)
+            .with_id(id)
+            .with_who(lock.who());
+        run(
+            &self.middleware,
+            request,
+            self.storage.save_raw(id, data, lock),
+        )
+        .await
+    }
+
+    async fn put_blob(
+        &self,
+        id: &ITEM::ID,
+        name: &str,
+        data: &[u8],
+        lock: &StorageLock,
+    ) -> Result<()> {
+        let request = StorageRequest::new(StorageOperation::PutBlob)
+            .with_id(id)
+            .with_who(lock.who());
+        run(
+            &self.middleware,
+            request,
+            self.storage.put_blob(id, name, data, lock),
+        )
+        .await
+    }
+
+    async fn get_blob(&self, id: &ITEM::ID, name: &str) -> Result<Option<Vec<u8>>> {
+        let request = StorageRequest::new(StorageOperation::GetBlob).with_id(id);
+        run(&self.middleware, request, self.storage.get_blob(id, name)).await
+    }
+
+    async fn list_blobs(&self, id: &ITEM::ID) -> Result<Vec<String>> {
+        let request = StorageRequest::new(StorageOperation::ListBlobs).with_id(id);
+        run(&self.middleware, request, self.storage.list_blobs(id)).await
+    }
+
+    async fn put_meta(&self, key: &str, value: &[u8]) -> Result<()> {
+        let request = StorageRequest::new(StorageOperation::PutMeta);
+        run(&self.middleware, request, self.storage.put_meta(key, value)).await
+    }
+
+    async fn get_meta(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let request = StorageRequest::new(StorageOperation::GetMeta);
+        run(&self.middleware, request, self.storage.get_meta(key)).await
+    }
+
+    async fn add_alias(&self, alias: &ITEM::ID, canonical: &ITEM::ID) -> Result<()> {
+        let request = StorageRequest::new(StorageOperation::AddAlias)
+            .with_id(alias)
+            .with_id(canonical);
+        run(
+            &self.middleware,
+            request,
+            self.storage.add_alias(alias, canonical),
+        )
+        .await
+    }
+
+    async fn remove_alias(&self, alias: &ITEM::ID) -> Result<()> {
+        let request = StorageRequest::new(StorageOperation::RemoveAlias).with_id(alias);
+        run(&self.middleware, request, self.storage.remove_alias(alias)).await
+    }
+
+    async fn resolve_alias(&self, id: &ITEM::ID) -> Result<ITEM::ID> {
+        let request = StorageRequest::new(StorageOperation::ResolveAlias).with_id(id);
+        run(&self.middleware, request, self.storage.resolve_alias(id)).await
+    }
+
+    async fn add_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        let request = StorageRequest::new(StorageOperation::AddLink)
+            .with_id(from)
+            .with_id(to)
+            .with_who(lock.who());
+        run(
+            &self.middleware,
+            request,
+            self.storage.add_link(from, relation, to, lock),
+        )
+        .await
+    }
+
+    async fn remove_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        let request = StorageRequest::new(StorageOperation::RemoveLink)
+            .with_id(from)
+            .with_id(to)
+            .with_who(lock.who());
+        run(
+            &self.middleware,
+            request,
+            self.storage.remove_link(from, relation, to, lock),
+        )
+        .await
+    }
+
+    async fn links_of(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        let request = StorageRequest::new(StorageOperation::LinksOf).with_id(id);
+        run(
+            &self.middleware,
+            request,
+            self.storage.links_of(id, relation),
+        )
+        .await
+    }
+
+    async fn linked_from(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        let request = StorageRequest::new(StorageOperation::LinkedFrom).with_id(id);
+        run(
+            &self.middleware,
+            request,
+            self.storage.linked_from(id, relation),
+        )
+        .await
+    }
+
+    async fn force_unlock(&self, id: &ITEM::ID) -> Result<Option<StorageLock>> {
+        let request = StorageRequest::new(StorageOperation::ForceUnlock).with_id(id);
+        run_and_inspect(
+            &self.middleware,
+            request,
+            self.quarantined_force_unlock(id),
+            |request, lock| request.displaced_lock = lock.as_ref().map(StorageLock::duplicate),
+        )
+        .await
+    }
+
+    async fn force_unlock_if_held(
+        &self,
+        id: &ITEM::ID,
+        who: &str,
+        when: DateTime<Utc>,
+    ) -> Result<bool> {
+        let request = StorageRequest::new(StorageOperation::ForceUnlock)
+            .with_id(id)
+            .with_who(who);
+        run_and_inspect(
+            &self.middleware,
+            request,
+            self.quarantined_force_unlock_if_held(id, who, when),
+            |request, unlocked| {
+                request.displaced_lock = unlocked.then(|| StorageLock::observed(who, when));
+            },
+        )
+        .await
+    }
+
+    async fn remove_orphaned_locks(&self, max_age: Duration) -> Result<usize> {
+        let request = StorageRequest::new(StorageOperation::RemoveOrphanedLocks);
+        run(
+            &self.middleware,
+            request,
+            self.storage.remove_orphaned_locks(max_age),
+        )
+        .await
+    }
+
+    async fn verify_lock(&self, id: &ITEM::ID, lock: &StorageLock) -> Result<bool> {
+        let request = StorageRequest::new(StorageOperation::VerifyLock)
+            .with_id(id)
+            .with_who(lock.who());
+        run(
+            &self.middleware,
+            request,
+            self.storage.verify_lock(id, lock),
+        )
+        .await
+    }
+
+    async fn verify_lock_with(
+        &self,
+        id: &ITEM::ID,
+        lock: &StorageLock,
+        options: &OpOptions,
+    ) -> Result<bool> {
+        let request = StorageRequest::new(StorageOperation::VerifyLock)
+            .with_id(id)
+            .with_who(lock.who());
+        run(
+            &self.middleware,
+            request,
+            self.storage.verify_lock_with(id, lock, options),
+        )
+        .await
+    }
+
+    async fn all_ids(&self) -> Result<Vec<ITEM::ID>> {
+        let request = StorageRequest::new(StorageOperation::AllIds);
+        run(&self.middleware, request, self.storage.all_ids()).await
+    }
+
+    async fn scan_ids(&self, start: Option<&str>, limit: Option<usize>) -> Result<Page<ITEM::ID>> {
+        let request = StorageRequest::new(StorageOperation::ScanIds);
+        run(
+            &self.middleware,
+            request,
+            self.storage.scan_ids(start, limit),
+        )
+        .await
+    }
+
+    async fn scan_ids_with_prefix(
+        &self,
+        prefix: &str,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        let request = StorageRequest::new(StorageOperation::ScanIds);
+        run(
+            &self.middleware,
+            request,
+            self.storage.scan_ids_with_prefix(prefix, start, limit),
+        )
+        .await
+    }
+
+    async fn scan_ids_filtered(
+        &self,
+        filter: &crate::IdFilter,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        let request = StorageRequest::new(StorageOperation::ScanIds);
+        run(
+            &self.middleware,
+            request,
+            self.storage.scan_ids_filtered(filter, start, limit),
+        )
+        .await
+    }
+
+    async fn scan_ids_between(
+        &self,
+        from: &ITEM::ID,
+        to: &ITEM::ID,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        let request = StorageRequest::new(StorageOperation::ScanIds);
+        run(
+            &self.middleware,
+            request,
+            self.storage.scan_ids_between(from, to, start, limit),
+        )
+        .await
+    }
+
+    async fn list(
+        &self,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<crate::ItemSummary<ITEM::ID>>> {
+        let request = StorageRequest::new(StorageOperation::List);
+        run(&self.middleware, request, self.storage.list(start, limit)).await
+    }
+
+    async fn item_size(&self, id: &ITEM::ID) -> Result<u64> {
+        let request = StorageRequest::new(StorageOperation::ItemSize).with_id(id);
+        run(&self.middleware, request, self.storage.item_size(id)).await
+    }
+
+    async fn estimate_cost(&self) -> Result<crate::StorageCost> {
+        let request = StorageRequest::new(StorageOperation::EstimateCost);
+        run(&self.middleware, request, self.storage.estimate_cost()).await
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        let request = StorageRequest::new(StorageOperation::Shutdown);
+        run(&self.middleware, request, self.storage.shutdown()).await
+    }
+
+    async fn fsck(&self, options: &crate::FsckOptions) -> Result<crate::FsckReport> {
+        let request = StorageRequest::new(StorageOperation::Fsck);
+        run(&self.middleware, request, self.storage.fsck(options)).await
+    }
+
+    async fn display_lock(&self, id: &ITEM::ID) -> Result<String> {
+        let request = StorageRequest::new(StorageOperation::DisplayLock).with_id(id);
+        run(&self.middleware, request, self.storage.display_lock(id)).await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_highest_seen_id(&self) -> Option<ITEM::ID> {
+        self.storage.metadata_highest_seen_id().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_item_count(&self) -> Option<u64> {
+        self.storage.metadata_item_count().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_total_bytes(&self) -> Option<u64> {
+        self.storage.metadata_total_bytes().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_last_write(&self) -> Option<DateTime<Utc>> {
+        self.storage.metadata_last_write().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_lock_stats(&self) -> crate::LockStats {
+        self.storage.metadata_lock_stats().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_snapshot(&self) -> crate::MetadataSnapshot {
+        self.storage.metadata_snapshot().await
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn wipe(
+        &self,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        let request = StorageRequest::new(StorageOperation::Wipe);
+        run(
+            &self.middleware,
+            request,
+            self.storage.wipe(options, progress, cancel),
+        )
+        .await
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn wipe_matching(
+        &self,
+        filter: &crate::IdFilter,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        let request = StorageRequest::new(StorageOperation::WipeMatching);
+        run(
+            &self.middleware,
+            request,
+            self.storage
+                .wipe_matching(filter, options, progress, cancel),
+        )
+        .await
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn delete_many(
+        &self,
+        ids: &[ITEM::ID],
+        options: &crate::DeleteOptions,
+    ) -> Result<Vec<crate::DeleteOutcome>> {
+        let request = ids.iter().fold(
+            StorageRequest::new(StorageOperation::DeleteMany),
+            StorageRequest::with_id,
+        );
+        run(
+            &self.middleware,
+            request,
+            self.storage.delete_many(ids, options),
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_item::TestItem;
+    use crate::Storage;
+    use crate::StorageMemory;
+    use crate::StorageMiddleware;
+    use crate::StorageNull;
+    use crate::StorageOperation;
+    use crate::StorageRequest;
+    use crate::StorageWithMiddleware;
+    use async_trait::async_trait;
+    use color_eyre::eyre::eyre;
+    use color_eyre::eyre::Report;
+    use color_eyre::Result;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    #[derive(Debug, Default)]
+    struct AuditMiddleware {
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl StorageMiddleware<TestItem> for AuditMiddleware {
+        async fn before(&self, request: &StorageRequest<'_, TestItem>) -> Result<()> {
+            if request.operation() == StorageOperation::ForceUnlock {
+                return Err(eyre!("Force unlock is not allowed"));
+            }
+            Ok(())
+        }
+
+        async fn after(&self, request: &StorageRequest<'_, TestItem>, error: Option<&Report>) {
+            self.log.lock().expect("can lock").push(format!(
+                "{} {:?} {:?} {}",
+                request.operation(),
+                request.ids(),
+                request.who(),
+                error.is_none()
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn it_runs_middleware() -> Result<()> {
+        let audit = AuditMiddleware::default();
+        let log = audit.log.clone();
+        let mut storage = StorageWithMiddleware::new(StorageNull::<TestItem>::default());
+        storage.add_middleware(audit)?;
+
+        let id = String::from("1");
+        let (lock, item) = storage.lock(&id, "tester").await?.success()?;
+        storage.save_and_unlock(&id, &item, lock).await?;
+        assert!(storage.force_unlock(&id).await.is_err());
+
+        assert_eq!(
+            vec![
+                String::from(r#"lock ["1"] Some("tester") true"#),
+                String::from(r#"save_and_unlock ["1"] Some("tester") true"#),
+            ],
+            *log.lock().expect("can lock")
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_fails_force_unlock_all_on_vetoed_unlocks() -> Result<()> {
+        let mut storage = StorageWithMiddleware::new(StorageMemory::<TestItem>::default());
+        storage.add_middleware(AuditMiddleware::default())?;
+        let id = String::from("1");
+        let (lock, item) = storage.lock(&id, "tester").await?.success()?;
+        storage.save_and_unlock(&id, &item, lock).await?;
+        let (_lock, _) = storage.lock(&id, "host-1/worker").await?.success()?;
+
+        let e = storage
+            .force_unlock_all("host-1/")
+            .await
+            .expect_err("vetoed");
+        assert_eq!("Force unlock is not allowed", e.to_string());
+
+        Ok(())
+    }
+}
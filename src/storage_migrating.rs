@@ -0,0 +1,783 @@
+use crate::paginate;
+use crate::storage_item::deserialize_stored;
+use crate::LockResult;
+use crate::OpOptions;
+use crate::Page;
+use crate::Storage;
+use crate::StorageError;
+use crate::StorageId;
+use crate::StorageItem;
+use crate::StorageLock;
+use async_trait::async_trait;
+use chrono::DateTime;
+use chrono::Utc;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Report;
+use color_eyre::eyre::Result;
+use core::marker::PhantomData;
+use futures_util::TryStreamExt;
+use std::collections::HashSet;
+use std::future::Future;
+use std::time::Duration;
+
+/// Used as lock holder.
+const WHO: &str = "migrating";
+/// Cursors of scans are prefixed with the storage they continue in.
+const NEW_CURSOR: &str = "new:";
+const OLD_CURSOR: &str = "old:";
+
+fn is_not_found(e: &Report) -> bool {
+    matches!(e.downcast_ref(), Some(StorageError::NotFound { .. }))
+}
+
+/// Where a scan continues, see [StorageMigrating::scan_both].
+enum Phase<'a> {
+    New(Option<&'a str>),
+    Old(Option<&'a str>),
+}
+
+impl<'a> Phase<'a> {
+    fn of(start: Option<&'a str>) -> Result<Self> {
+        let Some(start) = start else {
+            return Ok(Phase::New(None));
+        };
+        if let Some(cursor) = start.strip_prefix(NEW_CURSOR) {
+            Ok(Phase::New(Some(cursor)))
+        } else if let Some(cursor) = start.strip_prefix(OLD_CURSOR) {
+            Ok(Phase::Old((!cursor.is_empty()).then_some(cursor)))
+        } else {
+            Err(eyre!("Invalid cursor {start:?}"))
+        }
+    }
+}
+
+/// How far a [StorageMigrating] got, see [StorageMigrating::report].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MigrationReport {
+    /// Items in the old storage
+    pub old_items: usize,
+    /// Items of the old storage, that are also in the new one
+    pub migrated: usize,
+    /// Items only in the old storage
+    pub remaining: usize,
+}
+
+impl MigrationReport {
+    /// Whether all items of the old storage made it to the new one, and the old one can be retired.
+    pub fn is_drained(&self) -> bool {
+        self.remaining == 0
+    }
+}
+
+/// Moves items from an `old` storage, e.g. disk, to a `new` one, e.g. DynamoDB, while both serve traffic.
+///
+/// Reads try the new storage and fall back to the old one, and locking an item that is only in the old storage
+/// returns the old item, so the next save moves it.
+/// All writes go to the new storage, which is also the only lock authority.
+/// [StorageMigrating::set_backfill_on_read] also moves items on reads,
+/// and [StorageMigrating::report] tells when the old storage is drained.
+///
+/// Scans list the new storage, followed by the items only in the old one.
+/// Blobs, meta values, links, and aliases are read from both.
+///
+/// Note: Nothing is removed from the old storage, except via `delete_many`, `wipe`, and [Storage::remove_alias].
+/// Links only in the old storage can't be removed.
+#[derive(Debug)]
+pub struct StorageMigrating<ITEM: StorageItem, O: Storage<ITEM>, N: Storage<ITEM>>
+where
+    ITEM: Send,
+{
+    old: O,
+    new: N,
+    backfill_on_read: bool,
+    item_type: PhantomData<ITEM>,
+}
+
+impl<ITEM: StorageItem + Send, O: Storage<ITEM>, N: Storage<ITEM>> StorageMigrating<ITEM, O, N> {
+    pub fn new(old: O, new: N) -> Self {
+        Self {
+            old,
+            new,
+            backfill_on_read: false,
+            item_type: PhantomData,
+        }
+    }
+
+    /// Loads of items only in the old storage also copy them to the new one, unless they are locked.
+    /// Off by default, so reads never write.
+    pub fn set_backfill_on_read(&mut self, backfill_on_read: bool) -> Result<()> {
+        self.backfill_on_read = backfill_on_read;
+
+        Ok(())
+    }
+
+    pub fn old(&self) -> &O {
+        &self.old
+    }
+
+    pub fn new_storage(&self) -> &N {
+        &self.new
+    }
+
+    pub fn into_inner(self) -> (O, N) {
+        (self.old, self.new)
+    }
+
+    /// Whether the new storage has data for the item, locked but unsaved items don't count.
+    async fn in_new(&self, id: &ITEM::ID) -> Result<bool> {
+        match self.new.load_raw(id).await {
+            Ok(_) => Ok(true),
+            Err(e) if is_not_found(&e) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Copies the item from the old storage to the new one.
+    ///
+    /// Returns `false` if it is already in the new storage, locked there, or in neither storage.
+    pub async fn migrate(&self, id: &ITEM::ID) -> Result<bool> {
+        if self.in_new(id).await? {
+            return Ok(false);
+        }
+        let LockResult::Success { lock, .. } = self.new.lock(id, WHO).await? else {
+            return Ok(false);
+        };
+        let copied = self.copy_to_new(id, &lock).await;
+        if let Err(unlock_e) = self.new.unlock(id, lock).await {
+            tracing::warn!("Can't unlock {id} after migrating -> {unlock_e:?}");
+        }
+        if let Ok(true) = copied {
+            tracing::debug!("Migrated {id} to the new storage");
+        }
+
+        copied
+    }
+
+    /// Copies the item while `lock` holds it in the new storage.
+    async fn copy_to_new(&self, id: &ITEM::ID, lock: &StorageLock) -> Result<bool> {
+        // someone else may have saved it meanwhile
+        if self.in_new(id).await? {
+            return Ok(false);
+        }
+        let data = match self.old.load_raw(id).await {
+            Ok(data) => data,
+            Err(e) if is_not_found(&e) => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        self.new.save_raw(id, &data, lock).await?;
+
+        Ok(true)
+    }
+
+    /// Counts the items of the old storage, that are not in the new one yet.
+    ///
+    /// Note: This scans the old storage and checks every item in the new one.
+    pub async fn report(&self) -> Result<MigrationReport> {
+        let mut report = MigrationReport::default();
+        let mut ids = std::pin::pin!(paginate(&self.old).ids());
+        while let Some(id) = ids.try_next().await? {
+            report.old_items += 1;
+            if self.in_new(&id).await? {
+                report.migrated += 1;
+            } else {
+                report.remaining += 1;
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// The data from the old storage, after `new` didn't find the item.
+    async fn load_old_raw(&self, id: &ITEM::ID) -> Result<Vec<u8>> {
+        let data = self.old.load_raw(id).await?;
+        if self.backfill_on_read {
+            if let Err(e) = self.migrate(id).await {
+                tracing::warn!("Can't backfill {id} -> {e:?}");
+            }
+        }
+
+        Ok(data)
+    }
+
+    async fn load_fallback(&self, id: &ITEM::ID, loaded: Result<ITEM>) -> Result<ITEM> {
+        match loaded {
+            Err(e) if is_not_found(&e) => {
+                deserialize_stored::<ITEM>(id, &self.load_old_raw(id).await?)
+            }
+            loaded => loaded,
+        }
+    }
+
+    /// One page of the new storage, then of the items only in the old storage once the new one is done.
+    async fn scan_both<T, FN, FO, FutN, FutO>(
+        &self,
+        start: Option<&str>,
+        scan_new: FN,
+        scan_old: FO,
+        id_of: fn(&T) -> &ITEM::ID,
+    ) -> Result<Page<T>>
+    where
+        T: Send,
+        FN: FnOnce(Option<String>) -> FutN + Send,
+        FO: FnOnce(Option<String>) -> FutO + Send,
+        FutN: Future<Output = Result<Page<T>>> + Send,
+        FutO: Future<Output = Result<Page<T>>> + Send,
+    {
+        match Phase::of(start)? {
+            Phase::New(start) => {
+                let page = scan_new(start.map(str::to_string)).await?;
+                let cursor = match page.cursor {
+                    Some(cursor) => format!("{NEW_CURSOR}{cursor}"),
+                    None => OLD_CURSOR.to_string(),
+                };
+                Ok(Page::new(page.items, Some(cursor)))
+            }
+            Phase::Old(start) => {
+                let page = scan_old(start.map(str::to_string)).await?;
+                let mut items = Vec::with_capacity(page.items.len());
+                for item in page.items {
+                    if !self.in_new(id_of(&item)).await? {
+                        items.push(item);
+                    }
+                }
+                let cursor = page.cursor.map(|cursor| format!("{OLD_CURSOR}{cursor}"));
+                Ok(Page::new(items, cursor))
+            }
+        }
+    }
+
+    fn union(mut ids: Vec<ITEM::ID>, more: Vec<ITEM::ID>) -> Vec<ITEM::ID> {
+        let mut seen: HashSet<String> = ids.iter().map(|id| id.to_string()).collect();
+        ids.extend(more.into_iter().filter(|id| seen.insert(id.to_string())));
+        ids
+    }
+}
+
+#[async_trait]
+impl<ITEM: StorageItem + Send, O: Storage<ITEM>, N: Storage<ITEM>> Storage<ITEM>
+    for StorageMigrating<ITEM, O, N>
+{
+    async fn ensure_storage_exists(&mut self) -> Result<()> {
+        self.old.ensure_storage_exists().await?;
+        self.new.ensure_storage_exists().await
+    }
+
+    fn capabilities(&self) -> crate::Capabilities {
+        self.new.capabilities().intersect(&self.old.capabilities())
+    }
+
+    /// Skips ids the old storage still has.
+    async fn create(&self) -> Result<ITEM::ID> {
+        const TRIES: usize = 10;
+        for _ in 0..TRIES {
+            let id = self.new.create().await?;
+            if !self.old.exists(&id).await? {
+                return Ok(id);
+            }
+        }
+
+        Err(StorageError::IdExhausted { tries: TRIES }.into())
+    }
+
+    async fn exists(&self, id: &ITEM::ID) -> Result<bool> {
+        Ok(self.new.exists(id).await? || self.old.exists(id).await?)
+    }
+
+    async fn exists_with(&self, id: &ITEM::ID, options: &OpOptions) -> Result<bool> {
+        Ok(self.new.exists_with(id, options).await? || self.old.exists_with(id, options).await?)
+    }
+
+    async fn load(&self, id: &ITEM::ID) -> Result<ITEM> {
+        let loaded = self.new.load(id).await;
+        self.load_fallback(id, loaded).await
+    }
+
+    async fn load_with(&self, id: &ITEM::ID, options: &OpOptions) -> Result<ITEM> {
+        let loaded = self.new.load_with(id, options).await;
+        self.load_fallback(id, loaded).await
+    }
+
+    async fn load_many(&self, ids: &[ITEM::ID]) -> Result<Vec<Option<ITEM>>> {
+        let mut items = self.new.load_many(ids).await?;
+        for (id, item) in ids.iter().zip(items.iter_mut()) {
+            if item.is_none() {
+                *item = match self.load_old_raw(id).await {
+                    Ok(data) => Some(deserialize_stored::<ITEM>(id, &data)?),
+                    Err(e) if is_not_found(&e) => None,
+                    Err(e) => return Err(e),
+                };
+            }
+        }
+
+        Ok(items)
+    }
+
+    async fn prefetch(&self, ids: &[ITEM::ID]) -> Result<()> {
+        self.new.prefetch(ids).await
+    }
+
+    async fn save(&self, id: &ITEM::ID, item: &ITEM, lock: &StorageLock) -> Result<()> {
+        self.new.save(id, item, lock).await
+    }
+
+    /// Returns the old item, if the new storage doesn't have it yet.
+    async fn lock(&self, id: &ITEM::ID, who: &str) -> Result<LockResult<ITEM>> {
+        let result = self.new.lock(id, who).await?;
+        let LockResult::Success { lock, item } = result else {
+            return Ok(result);
+        };
+        let old_item = match self.in_new(id).await {
+            Ok(true) => return Ok(LockResult::Success { lock, item }),
+            Ok(false) => match self.old.load_raw(id).await {
+                Ok(data) => deserialize_stored::<ITEM>(id, &data),
+                Err(e) if is_not_found(&e) => Ok(item),
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        };
+        match old_item {
+            Ok(item) => Ok(LockResult::Success { lock, item }),
+            Err(e) => {
+                if let Err(unlock_e) = self.new.unlock(id, lock).await {
+                    tracing::warn!(
+                        "Can't unlock {id} after failed load from the old storage -> {unlock_e:?}"
+                    );
+                }
+                Err(e)
+            }
+        }
+    }
+
+    async fn unlock(&self, id: &ITEM::ID, lock: StorageLock) -> Result<()> {
+        self.new.unlock(id, lock).await
+    }
+
+    async fn handoff_lock(
+        &self,
+        id: &ITEM::ID,
+        lock: StorageLock,
+        new_who: &str,
+    ) -> Result<StorageLock> {
+        self.new.handoff_lock(id, lock, new_who).await
+    }
+
+    async fn save_and_unlock(&self, id: &ITEM::ID, item: &ITEM, lock: StorageLock) -> Result<()> {
+        self.new.save_and_unlock(id, item, lock).await
+    }
+
+    async fn load_raw(&self, id: &ITEM::ID) -> Result<Vec<u8>> {
+        match self.new.load_raw(id).await {
+            Err(e) if is_not_found(&e) => self.load_old_raw(id).await,
+            loaded => loaded,
+        }
+    }
+
+    async fn save_raw(&self, id: &ITEM::ID, data: &[u8], lock: &StorageLock) -> Result<()> {
+        self.new.save_raw(id, data, lock).await
+    }
+
+    async fn put_blob(
+        &self,
+        id: &ITEM::ID,
+        name: &str,
+        data: &[u8],
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.new.put_blob(id, name, data, lock).await
+    }
+
+    async fn get_blob(&self, id: &ITEM::ID, name: &str) -> Result<Option<Vec<u8>>> {
+        match self.new.get_blob(id, name).await? {
+            Some(data) => Ok(Some(data)),
+            None => self.old.get_blob(id, name).await,
+        }
+    }
+
+    async fn list_blobs(&self, id: &ITEM::ID) -> Result<Vec<String>> {
+        let mut names = self.new.list_blobs(id).await?;
+        for name in self.old.list_blobs(id).await? {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+
+        Ok(names)
+    }
+
+    async fn put_meta(&self, key: &str, value: &[u8]) -> Result<()> {
+        self.new.put_meta(key, value).await
+    }
+
+    async fn get_meta(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match self.new.get_meta(key).await? {
+            Some(value) => Ok(Some(value)),
+            None => self.old.get_meta(key).await,
+        }
+    }
+
+    async fn add_alias(&self, alias: &ITEM::ID, canonical: &ITEM::ID) -> Result<()> {
+        self.new.add_alias(alias, canonical).await
+    }
+
+    /// Removes the alias from both storages.
+    async fn remove_alias(&self, alias: &ITEM::ID) -> Result<()> {
+        self.new.remove_alias(alias).await?;
+        self.old.remove_alias(alias).await
+    }
+
+    async fn resolve_alias(&self, id: &ITEM::ID) -> Result<ITEM::ID> {
+        let resolved = self.new.resolve_alias(id).await?;
+        if resolved != *id {
+            return Ok(resolved);
+        }
+        self.old.resolve_alias(id).await
+    }
+
+    async fn add_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.new.add_link(from, relation, to, lock).await
+    }
+
+    async fn remove_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.new.remove_link(from, relation, to, lock).await
+    }
+
+    async fn links_of(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        let mut ids = Self::union(
+            self.new.links_of(id, relation).await?,
+            self.old.links_of(id, relation).await?,
+        );
+        ids.sort_by(|a, b| a.id_cmp(b));
+
+        Ok(ids)
+    }
+
+    async fn linked_from(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        let mut ids = Self::union(
+            self.new.linked_from(id, relation).await?,
+            self.old.linked_from(id, relation).await?,
+        );
+        ids.sort_by(|a, b| a.id_cmp(b));
+
+        Ok(ids)
+    }
+
+    async fn force_unlock(&self, id: &ITEM::ID) -> Result<Option<StorageLock>> {
+        self.new.force_unlock(id).await
+    }
+
+    async fn force_unlock_if_held(
+        &self,
+        id: &ITEM::ID,
+        who: &str,
+        when: DateTime<Utc>,
+    ) -> Result<bool> {
+        self.new.force_unlock_if_held(id, who, when).await
+    }
+
+    async fn remove_orphaned_locks(&self, max_age: Duration) -> Result<usize> {
+        self.new.remove_orphaned_locks(max_age).await
+    }
+
+    async fn verify_lock(&self, id: &ITEM::ID, lock: &StorageLock) -> Result<bool> {
+        self.new.verify_lock(id, lock).await
+    }
+
+    async fn verify_lock_with(
+        &self,
+        id: &ITEM::ID,
+        lock: &StorageLock,
+        options: &OpOptions,
+    ) -> Result<bool> {
+        self.new.verify_lock_with(id, lock, options).await
+    }
+
+    async fn all_ids(&self) -> Result<Vec<ITEM::ID>> {
+        Ok(Self::union(
+            self.new.all_ids().await?,
+            self.old.all_ids().await?,
+        ))
+    }
+
+    async fn scan_ids(&self, start: Option<&str>, limit: Option<usize>) -> Result<Page<ITEM::ID>> {
+        self.scan_both(
+            start,
+            |start| async move { self.new.scan_ids(start.as_deref(), limit).await },
+            |start| async move { self.old.scan_ids(start.as_deref(), limit).await },
+            |id| id,
+        )
+        .await
+    }
+
+    async fn scan_ids_with_prefix(
+        &self,
+        prefix: &str,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.scan_both(
+            start,
+            |start| async move {
+                self.new
+                    .scan_ids_with_prefix(prefix, start.as_deref(), limit)
+                    .await
+            },
+            |start| async move {
+                self.old
+                    .scan_ids_with_prefix(prefix, start.as_deref(), limit)
+                    .await
+            },
+            |id| id,
+        )
+        .await
+    }
+
+    async fn scan_ids_filtered(
+        &self,
+        filter: &crate::IdFilter,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.scan_both(
+            start,
+            |start| async move {
+                self.new
+                    .scan_ids_filtered(filter, start.as_deref(), limit)
+                    .await
+            },
+            |start| async move {
+                self.old
+                    .scan_ids_filtered(filter, start.as_deref(), limit)
+                    .await
+            },
+            |id| id,
+        )
+        .await
+    }
+
+    /// Pages through the ids of both storages, to keep them ordered.
+    async fn scan_ids_between(
+        &self,
+        from: &ITEM::ID,
+        to: &ITEM::ID,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        crate::page::page_between(self.all_ids().await?, from, to, start, limit)
+    }
+
+    async fn list(
+        &self,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<crate::ItemSummary<ITEM::ID>>> {
+        self.scan_both(
+            start,
+            |start| async move { self.new.list(start.as_deref(), limit).await },
+            |start| async move { self.old.list(start.as_deref(), limit).await },
+            |summary| &summary.id,
+        )
+        .await
+    }
+
+    async fn item_size(&self, id: &ITEM::ID) -> Result<u64> {
+        match self.new.item_size(id).await {
+            Err(e) if is_not_found(&e) => self.old.item_size(id).await,
+            size => size,
+        }
+    }
+
+    async fn display_lock(&self, id: &ITEM::ID) -> Result<String> {
+        self.new.display_lock(id).await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_highest_seen_id(&self) -> Option<ITEM::ID> {
+        self.new.metadata_highest_seen_id().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_item_count(&self) -> Option<u64> {
+        self.new.metadata_item_count().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_total_bytes(&self) -> Option<u64> {
+        self.new.metadata_total_bytes().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_last_write(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.new.metadata_last_write().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_lock_stats(&self) -> crate::LockStats {
+        self.new.metadata_lock_stats().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_snapshot(&self) -> crate::MetadataSnapshot {
+        self.new.metadata_snapshot().await
+    }
+
+    /// Wipes both storages, the report is the one of the new storage.
+    #[cfg(feature = "wipe")]
+    async fn wipe(
+        &self,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        let report = self.new.wipe(options, progress, cancel).await?;
+        self.old.wipe(options, None, cancel).await?;
+
+        Ok(report)
+    }
+
+    /// Wipes both storages, the report is the one of the new storage.
+    #[cfg(feature = "wipe")]
+    async fn wipe_matching(
+        &self,
+        filter: &crate::IdFilter,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        let report = self
+            .new
+            .wipe_matching(filter, options, progress, cancel)
+            .await?;
+        self.old
+            .wipe_matching(filter, options, None, cancel)
+            .await?;
+
+        Ok(report)
+    }
+
+    /// Deletes from both storages, items only in the old storage count as deleted.
+    #[cfg(feature = "wipe")]
+    async fn delete_many(
+        &self,
+        ids: &[ITEM::ID],
+        options: &crate::DeleteOptions,
+    ) -> Result<Vec<crate::DeleteOutcome>> {
+        let mut outcomes = self.new.delete_many(ids, options).await?;
+        let old_outcomes = self.old.delete_many(ids, options).await?;
+        for (outcome, old_outcome) in outcomes.iter_mut().zip(old_outcomes) {
+            if *outcome == crate::DeleteOutcome::NotFound {
+                *outcome = old_outcome;
+            }
+        }
+
+        Ok(outcomes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::paginate;
+    use crate::test_item::TestItem;
+    use crate::MockResponse;
+    use crate::Storage;
+    use crate::StorageMemory;
+    use crate::StorageMigrating;
+    use crate::StorageMock;
+    use crate::StorageOperation;
+    use color_eyre::eyre::eyre;
+    use color_eyre::Result;
+    use futures_util::TryStreamExt;
+
+    #[tokio::test]
+    async fn it_migrates_while_serving() -> Result<()> {
+        let old = StorageMemory::<TestItem>::default();
+        for (id, count) in [("a", 1), ("b", 2), ("c", 3)] {
+            let id = id.to_string();
+            let (lock, _) = old.lock(&id, "test").await?.success()?;
+            old.save_and_unlock(&id, &TestItem { count }, lock).await?;
+        }
+        let mut storage = StorageMigrating::new(old, StorageMemory::<TestItem>::default());
+        let report = storage.report().await?;
+        assert_eq!(
+            (3, 0, 3),
+            (report.old_items, report.migrated, report.remaining)
+        );
+
+        // reads fall back, without writing
+        let a = String::from("a");
+        assert_eq!(TestItem { count: 1 }, storage.load(&a).await?);
+        assert!(!storage.new_storage().exists(&a).await?);
+
+        // writes go to the new storage
+        let (lock, mut item) = storage.lock(&a, "test").await?.success()?;
+        assert_eq!(TestItem { count: 1 }, item);
+        item.count = 10;
+        storage.save_and_unlock(&a, &item, lock).await?;
+        assert_eq!(
+            TestItem { count: 10 },
+            storage.new_storage().load(&a).await?
+        );
+        assert_eq!(TestItem { count: 1 }, storage.old().load(&a).await?);
+        assert_eq!(TestItem { count: 10 }, storage.load(&a).await?);
+
+        storage.set_backfill_on_read(true)?;
+        let b = String::from("b");
+        assert_eq!(TestItem { count: 2 }, storage.load(&b).await?);
+        assert_eq!(TestItem { count: 2 }, storage.new_storage().load(&b).await?);
+
+        // scans see every item once
+        let mut ids: Vec<String> = paginate(&storage)
+            .with_page_size(1)
+            .ids()
+            .try_collect()
+            .await?;
+        ids.sort();
+        assert_eq!(vec!["a", "b", "c"], ids);
+
+        assert!(!storage.report().await?.is_drained());
+        assert!(storage.migrate(&String::from("c")).await?);
+        assert!(!storage.migrate(&String::from("c")).await?);
+        let report = storage.report().await?;
+        assert_eq!(
+            (3, 3, 0),
+            (report.old_items, report.migrated, report.remaining)
+        );
+        assert!(report.is_drained());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_releases_the_new_item_when_copying_fails() -> Result<()> {
+        let storage = StorageMigrating::new(
+            StorageMock::<TestItem>::default(),
+            StorageMemory::<TestItem>::default(),
+        );
+        let id = String::from("a");
+
+        // the new item is already locked, when reading the old one fails
+        storage
+            .old()
+            .script(StorageOperation::Load, MockResponse::Err(eyre!("down")));
+        assert!(storage.migrate(&id).await.is_err());
+        assert!(!storage.new_storage().exists(&id).await?);
+        let (lock, _) = storage.new_storage().lock(&id, "test").await?.success()?;
+        storage.new_storage().unlock(&id, lock).await?;
+
+        assert!(storage.migrate(&id).await?);
+        assert_eq!(TestItem::default(), storage.new_storage().load(&id).await?);
+
+        Ok(())
+    }
+}
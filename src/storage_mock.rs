@@ -0,0 +1,491 @@
+use crate::ItemSummary;
+use crate::LockResult;
+use crate::Page;
+use crate::Storage;
+use crate::StorageId;
+use crate::StorageItem;
+use crate::StorageLock;
+use crate::StorageOperation;
+use async_trait::async_trait;
+use chrono::DateTime;
+use chrono::Utc;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Report;
+use color_eyre::eyre::Result;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// A call recorded by [StorageMock].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MockCall {
+    pub operation: StorageOperation,
+    pub ids: Vec<String>,
+    pub who: Option<String>,
+}
+
+/// The scripted result of a single call, see [StorageMock::script].
+///
+/// Responses that don't fit the operation, e.g. [MockResponse::Bool] for [Storage::load], fail the call.
+#[derive(Debug)]
+pub enum MockResponse<ITEM: StorageItem> {
+    /// Succeed with the default result
+    Ok,
+    Err(Report),
+    /// For [Storage::lock]
+    AlreadyLocked {
+        who: String,
+        when: DateTime<Utc>,
+    },
+    /// For [Storage::load] and [Storage::lock]
+    Item(ITEM),
+    /// For [Storage::exists] and [Storage::verify_lock]
+    Bool(bool),
+    /// For [Storage::create]
+    Id(ITEM::ID),
+    /// For [Storage::all_ids], [Storage::scan_ids], and [Storage::list]
+    Ids(Vec<ITEM::ID>),
+}
+
+#[derive(Debug)]
+struct Scripted<ITEM: StorageItem> {
+    response: MockResponse<ITEM>,
+    delay: Option<Duration>,
+}
+
+/// A storage for tests, that returns scripted results and records all calls.
+///
+/// Calls without a script behave like [crate::StorageNull]:
+/// nothing exists, locks always succeed, and loading returns the default item.
+///
+/// Operations with default implementations, e.g. [Storage::load_many], are recorded as the calls they make.
+/// Blobs, aliases, and links are not supported.
+#[derive(Debug, Default)]
+pub struct StorageMock<ITEM: StorageItem> {
+    scripts: Mutex<HashMap<StorageOperation, VecDeque<Scripted<ITEM>>>>,
+    calls: Mutex<Vec<MockCall>>,
+}
+
+impl<ITEM: StorageItem> StorageMock<ITEM> {
+    /// Queues `response` for a call of `operation`, queued responses are used in order.
+    pub fn script(&self, operation: StorageOperation, response: MockResponse<ITEM>) {
+        self.script_delayed(operation, response, None);
+    }
+
+    /// Like [StorageMock::script], but waits `delay` before responding.
+    pub fn script_delayed(
+        &self,
+        operation: StorageOperation,
+        response: MockResponse<ITEM>,
+        delay: Option<Duration>,
+    ) {
+        self.scripts
+            .lock()
+            .expect("can lock")
+            .entry(operation)
+            .or_default()
+            .push_back(Scripted { response, delay });
+    }
+
+    /// All calls so far, in order.
+    pub fn calls(&self) -> Vec<MockCall> {
+        self.calls.lock().expect("can lock").clone()
+    }
+
+    /// Records the call and returns its scripted response, if any.
+    async fn respond(
+        &self,
+        operation: StorageOperation,
+        ids: &[&ITEM::ID],
+        who: Option<&str>,
+    ) -> Result<Option<MockResponse<ITEM>>> {
+        self.calls.lock().expect("can lock").push(MockCall {
+            operation,
+            ids: ids.iter().map(|id| id.to_string()).collect(),
+            who: who.map(String::from),
+        });
+        let scripted = self
+            .scripts
+            .lock()
+            .expect("can lock")
+            .get_mut(&operation)
+            .and_then(|s| s.pop_front());
+        let Some(scripted) = scripted else {
+            return Ok(None);
+        };
+        if let Some(delay) = scripted.delay {
+            tokio::time::sleep(delay).await;
+        }
+        match scripted.response {
+            MockResponse::Ok => Ok(None),
+            MockResponse::Err(e) => Err(e),
+            response => Ok(Some(response)),
+        }
+    }
+}
+
+fn unfit<ITEM: StorageItem>(operation: StorageOperation, response: MockResponse<ITEM>) -> Report {
+    eyre!("Scripted {response:?} doesn't fit {operation}")
+}
+
+#[async_trait]
+impl<ITEM: StorageItem + Send> Storage<ITEM> for StorageMock<ITEM> {
+    async fn ensure_storage_exists(&mut self) -> Result<()> {
+        let op = StorageOperation::EnsureStorageExists;
+        match self.respond(op, &[], None).await? {
+            None => Ok(()),
+            Some(r) => Err(unfit(op, r)),
+        }
+    }
+
+    fn capabilities(&self) -> crate::Capabilities {
+        crate::Capabilities {
+            scan: true,
+            list: true,
+            remove_orphaned_locks: true,
+            consistent_reads: true,
+            ..Default::default()
+        }
+    }
+
+    async fn create(&self) -> Result<ITEM::ID> {
+        let op = StorageOperation::Create;
+        match self.respond(op, &[], None).await? {
+            None => Ok(ITEM::ID::generate_new(None)),
+            Some(MockResponse::Id(id)) => Ok(id),
+            Some(r) => Err(unfit(op, r)),
+        }
+    }
+
+    async fn exists(&self, id: &ITEM::ID) -> Result<bool> {
+        let op = StorageOperation::Exists;
+        match self.respond(op, &[id], None).await? {
+            None => Ok(false),
+            Some(MockResponse::Bool(exists)) => Ok(exists),
+            Some(r) => Err(unfit(op, r)),
+        }
+    }
+
+    async fn load(&self, id: &ITEM::ID) -> Result<ITEM> {
+        let op = StorageOperation::Load;
+        match self.respond(op, &[id], None).await? {
+            None => Ok(ITEM::default()),
+            Some(MockResponse::Item(item)) => Ok(item),
+            Some(r) => Err(unfit(op, r)),
+        }
+    }
+
+    async fn save(&self, id: &ITEM::ID, _item: &ITEM, lock: &StorageLock) -> Result<()> {
+        let op = StorageOperation::Save;
+        match self.respond(op, &[id], Some(lock.who())).await? {
+            None => Ok(()),
+            Some(r) => Err(unfit(op, r)),
+        }
+    }
+
+    async fn lock(&self, id: &ITEM::ID, who: &str) -> Result<LockResult<ITEM>> {
+        let op = StorageOperation::Lock;
+        let item = match self.respond(op, &[id], Some(who)).await? {
+            None => ITEM::default(),
+            Some(MockResponse::Item(item)) => item,
+            Some(MockResponse::AlreadyLocked { who, when }) => {
+                return Ok(LockResult::AlreadyLocked { who, when });
+            }
+            Some(r) => return Err(unfit(op, r)),
+        };
+
+        Ok(LockResult::Success {
+            lock: StorageLock::new(who),
+            item,
+        })
+    }
+
+    async fn unlock(&self, id: &ITEM::ID, lock: StorageLock) -> Result<()> {
+        let op = StorageOperation::Unlock;
+        match self.respond(op, &[id], Some(lock.who())).await? {
+            None => Ok(()),
+            Some(r) => Err(unfit(op, r)),
+        }
+    }
+
+    async fn handoff_lock(
+        &self,
+        id: &ITEM::ID,
+        lock: StorageLock,
+        new_who: &str,
+    ) -> Result<StorageLock> {
+        let op = StorageOperation::HandoffLock;
+        match self.respond(op, &[id], Some(lock.who())).await? {
+            None => Ok(StorageLock::new(new_who)),
+            Some(r) => Err(unfit(op, r)),
+        }
+    }
+
+    async fn force_unlock(&self, id: &ITEM::ID) -> Result<Option<StorageLock>> {
+        let op = StorageOperation::ForceUnlock;
+        match self.respond(op, &[id], None).await? {
+            None => Ok(None),
+            Some(r) => Err(unfit(op, r)),
+        }
+    }
+
+    async fn force_unlock_if_held(
+        &self,
+        id: &ITEM::ID,
+        who: &str,
+        _when: DateTime<Utc>,
+    ) -> Result<bool> {
+        let op = StorageOperation::ForceUnlock;
+        match self.respond(op, &[id], Some(who)).await? {
+            None => Ok(true),
+            Some(r) => Err(unfit(op, r)),
+        }
+    }
+
+    async fn remove_orphaned_locks(&self, _max_age: Duration) -> Result<usize> {
+        let op = StorageOperation::RemoveOrphanedLocks;
+        match self.respond(op, &[], None).await? {
+            None => Ok(0),
+            Some(r) => Err(unfit(op, r)),
+        }
+    }
+
+    async fn verify_lock(&self, id: &ITEM::ID, lock: &StorageLock) -> Result<bool> {
+        let op = StorageOperation::VerifyLock;
+        match self.respond(op, &[id], Some(lock.who())).await? {
+            None => Ok(true),
+            Some(MockResponse::Bool(valid)) => Ok(valid),
+            Some(r) => Err(unfit(op, r)),
+        }
+    }
+
+    async fn all_ids(&self) -> Result<Vec<ITEM::ID>> {
+        let op = StorageOperation::AllIds;
+        match self.respond(op, &[], None).await? {
+            None => Ok(Vec::new()),
+            Some(MockResponse::Ids(ids)) => Ok(ids),
+            Some(r) => Err(unfit(op, r)),
+        }
+    }
+
+    /// Returns all scripted ids in one page.
+    async fn scan_ids(
+        &self,
+        _start: Option<&str>,
+        _limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        let op = StorageOperation::ScanIds;
+        match self.respond(op, &[], None).await? {
+            None => Ok(Page::new(Vec::new(), None)),
+            Some(MockResponse::Ids(ids)) => Ok(Page::new(ids, None)),
+            Some(r) => Err(unfit(op, r)),
+        }
+    }
+
+    async fn list(
+        &self,
+        _start: Option<&str>,
+        _limit: Option<usize>,
+    ) -> Result<Page<ItemSummary<ITEM::ID>>> {
+        let op = StorageOperation::List;
+        match self.respond(op, &[], None).await? {
+            None => Ok(Page::new(Vec::new(), None)),
+            Some(MockResponse::Ids(ids)) => Ok(Page::new(
+                ids.into_iter().map(ItemSummary::new).collect(),
+                None,
+            )),
+            Some(r) => Err(unfit(op, r)),
+        }
+    }
+
+    async fn display_lock(&self, id: &ITEM::ID) -> Result<String> {
+        let op = StorageOperation::DisplayLock;
+        match self.respond(op, &[id], None).await? {
+            None => Ok(String::from("[StorageMock]")),
+            Some(r) => Err(unfit(op, r)),
+        }
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_highest_seen_id(&self) -> Option<ITEM::ID> {
+        None
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_item_count(&self) -> Option<u64> {
+        None
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_total_bytes(&self) -> Option<u64> {
+        None
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_last_write(&self) -> Option<DateTime<Utc>> {
+        None
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_lock_stats(&self) -> crate::LockStats {
+        crate::LockStats::default()
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn wipe(
+        &self,
+        options: &crate::WipeOptions,
+        _progress: Option<&dyn crate::ProgressSink>,
+        _cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        let op = StorageOperation::Wipe;
+        match self.respond(op, &[], None).await? {
+            None => Ok(crate::WipeReport::new(options)),
+            Some(r) => Err(unfit(op, r)),
+        }
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn wipe_matching(
+        &self,
+        _filter: &crate::IdFilter,
+        options: &crate::WipeOptions,
+        _progress: Option<&dyn crate::ProgressSink>,
+        _cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        let op = StorageOperation::WipeMatching;
+        match self.respond(op, &[], None).await? {
+            None => Ok(crate::WipeReport::new(options)),
+            Some(r) => Err(unfit(op, r)),
+        }
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn delete_many(
+        &self,
+        ids: &[ITEM::ID],
+        _options: &crate::DeleteOptions,
+    ) -> Result<Vec<crate::DeleteOutcome>> {
+        let op = StorageOperation::DeleteMany;
+        let id_refs: Vec<&ITEM::ID> = ids.iter().collect();
+        match self.respond(op, &id_refs, None).await? {
+            None => Ok(vec![crate::DeleteOutcome::NotFound; ids.len()]),
+            Some(r) => Err(unfit(op, r)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::LockManyOutcome;
+    use crate::LockResult;
+    use crate::MockCall;
+    use crate::MockResponse;
+    use crate::Storage;
+    use crate::StorageError;
+    use crate::StorageItem;
+    use crate::StorageMock;
+    use crate::StorageOperation;
+    use chrono::Utc;
+    use color_eyre::eyre::eyre;
+    use color_eyre::Result;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    #[derive(Default, Debug, PartialEq)]
+    struct TestItem {
+        value: u32,
+    }
+
+    impl StorageItem for TestItem {
+        type ID = String;
+
+        fn serialize(&self) -> Result<Vec<u8>> {
+            Ok(Vec::new())
+        }
+        fn deserialize(_: &[u8]) -> Result<Self> {
+            Ok(Self::default())
+        }
+    }
+
+    #[tokio::test]
+    async fn it_scripts_and_records_calls() -> Result<()> {
+        let storage = StorageMock::<TestItem>::default();
+        let id = String::from("1");
+
+        storage.script(
+            StorageOperation::Lock,
+            MockResponse::AlreadyLocked {
+                who: String::from("other"),
+                when: Utc::now() - chrono::Duration::minutes(5),
+            },
+        );
+        storage.script(
+            StorageOperation::Lock,
+            MockResponse::Item(TestItem { value: 7 }),
+        );
+        storage.script_delayed(
+            StorageOperation::Save,
+            MockResponse::Err(eyre!("Disk full")),
+            Some(Duration::from_millis(20)),
+        );
+        storage.script(StorageOperation::Load, MockResponse::Bool(true));
+
+        let locked = storage.lock(&id, "tester").await?;
+        assert!(locked.lock_age() >= Some(Duration::from_secs(300)));
+        assert!(matches!(
+            locked,
+            LockResult::AlreadyLocked { who, .. } if who == "other"
+        ));
+        let (lock, item) = storage.lock(&id, "tester").await?.success()?;
+        assert_eq!(TestItem { value: 7 }, item);
+
+        let start = Instant::now();
+        assert!(storage.save(&id, &item, &lock).await.is_err());
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        storage.unlock(&id, lock).await?;
+
+        assert!(storage.load(&id).await.is_err());
+        assert_eq!(TestItem::default(), storage.load(&id).await?);
+
+        let calls = storage.calls();
+        assert_eq!(6, calls.len());
+        assert_eq!(
+            MockCall {
+                operation: StorageOperation::Save,
+                ids: vec![id.clone()],
+                who: Some(String::from("tester")),
+            },
+            calls[2]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_unlocks_items_removed_while_locking_many() -> Result<()> {
+        let storage = StorageMock::<TestItem>::default();
+        let id = String::from("1");
+        storage.script(StorageOperation::Exists, MockResponse::Bool(true));
+        storage.script(
+            StorageOperation::Load,
+            MockResponse::Err(StorageError::NotFound { id: id.clone() }.into()),
+        );
+
+        let outcomes = storage.lock_and_load_many(&[id], "tester").await?;
+        assert!(matches!(outcomes.as_slice(), [LockManyOutcome::NotFound]));
+        let operations: Vec<_> = storage.calls().into_iter().map(|c| c.operation).collect();
+        assert_eq!(
+            vec![
+                StorageOperation::Exists,
+                StorageOperation::Lock,
+                StorageOperation::Load,
+                StorageOperation::Unlock,
+            ],
+            operations
+        );
+
+        Ok(())
+    }
+}
@@ -1,29 +1,113 @@
+use crate::ItemSummary;
 use crate::LockResult;
-/// This is a *Null* implementation that does nothing.
-/// It can be used as a default, and can warn when actually being used.
-
 #[cfg(feature = "metadata")]
 use crate::Metadata;
+use crate::Page;
 use crate::Storage;
+use crate::StorageError;
+use crate::StorageId;
 use crate::StorageItem;
 use crate::StorageLock;
+use crate::StorageOperation;
 use async_trait::async_trait;
+use chrono::DateTime;
+use chrono::Utc;
 
 use color_eyre::eyre::Result;
 
 use core::marker::PhantomData;
+use std::time::Duration;
+
+/// What [StorageNull] does when an operation is used.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum NullBehavior {
+    /// Silently succeed, with nothing. This is the default.
+    #[default]
+    Ignore,
+    /// Succeed, but log a warning.
+    Warn,
+    /// Fail with [StorageError::Unsupported].
+    Error,
+    /// Panic, for things that must never happen.
+    Panic,
+}
+
+/// The [NullBehavior] per category of [StorageOperation], see [StorageNull::set_profile].
+///
+/// Destructive operations are [StorageOperation::is_destructive],
+/// reads are [StorageOperation::is_read], everything else writes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct NullProfile {
+    pub reads: NullBehavior,
+    pub writes: NullBehavior,
+    pub destructive: NullBehavior,
+}
+
+impl NullProfile {
+    /// The same behavior for all operations.
+    pub fn all(behavior: NullBehavior) -> Self {
+        Self {
+            reads: behavior,
+            writes: behavior,
+            destructive: behavior,
+        }
+    }
+
+    pub fn behavior_for(&self, operation: StorageOperation) -> NullBehavior {
+        if operation.is_destructive() {
+            self.destructive
+        } else if operation.is_read() {
+            self.reads
+        } else {
+            self.writes
+        }
+    }
+}
 
+/// This is a *Null* implementation that does nothing.
+/// It can be used as a default and can warn or fail when actually being used, see [StorageNull::set_profile].
 #[derive(Debug, Default)]
 pub struct StorageNull<ITEM: StorageItem> {
     item_type: PhantomData<ITEM>,
-    warnings_on_use: bool,
+    profile: NullProfile,
     #[cfg(feature = "metadata")]
     metadata: Metadata<ITEM>,
 }
 
 impl<ITEM: StorageItem> StorageNull<ITEM> {
     pub fn enable_warnings_on_use(&mut self) {
-        self.warnings_on_use = true;
+        self.profile = NullProfile::all(NullBehavior::Warn);
+    }
+
+    /// E.g. to fail loudly in production, when the real backend wasn't configured.
+    pub fn set_profile(&mut self, profile: NullProfile) -> Result<()> {
+        self.profile = profile;
+
+        Ok(())
+    }
+
+    fn on_use(&self, operation: StorageOperation) -> Result<()> {
+        match self.profile.behavior_for(operation) {
+            NullBehavior::Ignore => Ok(()),
+            NullBehavior::Warn => {
+                tracing::warn!("StorageNull {operation} used!");
+                Ok(())
+            }
+            NullBehavior::Error => Err(StorageError::Unsupported { operation }.into()),
+            NullBehavior::Panic => panic!("StorageNull {operation} used!"),
+        }
+    }
+
+    /// Metadata can't fail, so errors only warn.
+    #[cfg(feature = "metadata")]
+    fn on_metadata_use(&self, name: &str) {
+        match self.profile.reads {
+            NullBehavior::Ignore => {}
+            NullBehavior::Warn | NullBehavior::Error => {
+                tracing::warn!("StorageNull {name} used!")
+            }
+            NullBehavior::Panic => panic!("StorageNull {name} used!"),
+        }
     }
 }
 
@@ -41,122 +125,382 @@ impl<ITEM: StorageItem> StorageNull<ITEM> {
 
 #[async_trait]
 impl<ITEM: StorageItem + std::marker::Send> Storage<ITEM> for StorageNull<ITEM> {
+    #[tracing::instrument(
+        name = "storage.ensure_storage_exists",
+        skip_all,
+        fields(backend = "null", db.system = "null", db.operation = "ensure_storage_exists")
+    )]
     async fn ensure_storage_exists(&mut self) -> Result<()> {
+        self.on_use(StorageOperation::EnsureStorageExists)?;
         Ok(())
     }
-    async fn create(&self) -> Result<ITEM::ID> {
-        if self.warnings_on_use {
-            tracing::warn!("StorageNull create used!");
-        }
-        let mut tries = 10;
-        loop {
-            //let id = nanoid::nanoid!();
-            let id = ITEM::generate_next_id(None);
-            if !self.exists(&id).await? {
-                // NO! self.update_highest_seen_id( &id );
-                return Ok(id);
-            }
 
-            tries -= 1;
-            if tries <= 0 {
-                todo!();
-            }
+    /// Everything it accepts, even though nothing is stored.
+    fn capabilities(&self) -> crate::Capabilities {
+        crate::Capabilities {
+            scan: true,
+            list: true,
+            blobs: true,
+            meta: true,
+            links: true,
+            remove_orphaned_locks: true,
+            ..Default::default()
         }
     }
+    #[tracing::instrument(
+        name = "storage.create",
+        skip_all,
+        fields(backend = "null", db.system = "null", db.operation = "create")
+    )]
+    async fn create(&self) -> Result<ITEM::ID> {
+        self.on_use(StorageOperation::Create)?;
+        // nothing exists, so any id is free
+        Ok(ITEM::ID::generate_new(None))
+    }
+    #[tracing::instrument(
+        name = "storage.exists",
+        skip_all,
+        fields(backend = "null", db.system = "null", db.operation = "exists")
+    )]
     async fn exists(&self, _id: &ITEM::ID) -> Result<bool> {
-        if self.warnings_on_use {
-            tracing::warn!("StorageNull exists used!");
-        }
+        self.on_use(StorageOperation::Exists)?;
         Ok(false)
     }
 
+    #[tracing::instrument(
+        name = "storage.load",
+        skip_all,
+        fields(backend = "null", db.system = "null", db.operation = "load", id = %id)
+    )]
     async fn load(&self, id: &ITEM::ID) -> Result<ITEM> {
-        if self.warnings_on_use {
-            tracing::warn!("StorageNull load used!");
-        }
+        self.on_use(StorageOperation::Load)?;
         let i = ITEM::default();
-        self.update_highest_seen_id(&id);
+        self.update_highest_seen_id(id);
 
         Ok(i)
     }
 
+    #[tracing::instrument(
+        name = "storage.save",
+        skip_all,
+        fields(backend = "null", db.system = "null", db.operation = "save")
+    )]
     async fn save(&self, _id: &ITEM::ID, _item: &ITEM, _lock: &StorageLock) -> Result<()> {
-        if self.warnings_on_use {
-            tracing::warn!("StorageNull save used!");
-        }
+        self.on_use(StorageOperation::Save)?;
         Ok(())
     }
+    #[tracing::instrument(
+        name = "storage.lock",
+        skip_all,
+        fields(backend = "null", db.system = "null", db.operation = "lock", id = %id, who = %who)
+    )]
     async fn lock(&self, id: &ITEM::ID, who: &str) -> Result<LockResult<ITEM>> {
-        if self.warnings_on_use {
-            tracing::warn!("StorageNull lock used!");
-        }
+        self.on_use(StorageOperation::Lock)?;
         let (lock, item) = {
             let lock = StorageLock::new(who);
 
-            tracing::debug!("Lock[{who}]: Load {id}");
-            let item = self.load(id).await.unwrap_or_default();
+            // like load, without counting as a read
+            let item = ITEM::default();
+            self.update_highest_seen_id(id);
 
             (lock, item)
         };
         Ok(LockResult::Success { lock, item })
     }
 
+    #[tracing::instrument(
+        name = "storage.unlock",
+        skip_all,
+        fields(backend = "null", db.system = "null", db.operation = "unlock")
+    )]
     async fn unlock(&self, _id: &ITEM::ID, _lock: StorageLock) -> Result<()> {
-        if self.warnings_on_use {
-            tracing::warn!("StorageNull unlock used!");
-        }
+        self.on_use(StorageOperation::Unlock)?;
 
         Ok(())
     }
 
-    async fn force_unlock(&self, _id: &ITEM::ID) -> Result<()> {
-        if self.warnings_on_use {
-            tracing::warn!("StorageNull force_unlock used!");
-        }
+    #[tracing::instrument(
+        name = "storage.handoff_lock",
+        skip_all,
+        fields(backend = "null", db.system = "null", db.operation = "handoff_lock")
+    )]
+    async fn handoff_lock(
+        &self,
+        _id: &ITEM::ID,
+        _lock: StorageLock,
+        new_who: &str,
+    ) -> Result<StorageLock> {
+        self.on_use(StorageOperation::HandoffLock)?;
+
+        Ok(StorageLock::new(new_who))
+    }
+
+    #[tracing::instrument(
+        name = "storage.put_blob",
+        skip_all,
+        fields(backend = "null", db.system = "null", db.operation = "put_blob")
+    )]
+    async fn put_blob(
+        &self,
+        _id: &ITEM::ID,
+        _name: &str,
+        _data: &[u8],
+        _lock: &StorageLock,
+    ) -> Result<()> {
+        self.on_use(StorageOperation::PutBlob)?;
+        Ok(())
+    }
+    #[tracing::instrument(
+        name = "storage.get_blob",
+        skip_all,
+        fields(backend = "null", db.system = "null", db.operation = "get_blob")
+    )]
+    async fn get_blob(&self, _id: &ITEM::ID, _name: &str) -> Result<Option<Vec<u8>>> {
+        self.on_use(StorageOperation::GetBlob)?;
+        Ok(None)
+    }
+    #[tracing::instrument(
+        name = "storage.list_blobs",
+        skip_all,
+        fields(backend = "null", db.system = "null", db.operation = "list_blobs")
+    )]
+    async fn list_blobs(&self, _id: &ITEM::ID) -> Result<Vec<String>> {
+        self.on_use(StorageOperation::ListBlobs)?;
+        Ok(Vec::default())
+    }
+
+    #[tracing::instrument(
+        name = "storage.add_link",
+        skip_all,
+        fields(backend = "null", db.system = "null", db.operation = "add_link")
+    )]
+    async fn add_link(
+        &self,
+        _from: &ITEM::ID,
+        _relation: &str,
+        _to: &ITEM::ID,
+        _lock: &StorageLock,
+    ) -> Result<()> {
+        self.on_use(StorageOperation::AddLink)?;
         Ok(())
     }
+    #[tracing::instrument(
+        name = "storage.remove_link",
+        skip_all,
+        fields(backend = "null", db.system = "null", db.operation = "remove_link")
+    )]
+    async fn remove_link(
+        &self,
+        _from: &ITEM::ID,
+        _relation: &str,
+        _to: &ITEM::ID,
+        _lock: &StorageLock,
+    ) -> Result<()> {
+        self.on_use(StorageOperation::RemoveLink)?;
+        Ok(())
+    }
+    #[tracing::instrument(
+        name = "storage.links_of",
+        skip_all,
+        fields(backend = "null", db.system = "null", db.operation = "links_of")
+    )]
+    async fn links_of(&self, _id: &ITEM::ID, _relation: &str) -> Result<Vec<ITEM::ID>> {
+        self.on_use(StorageOperation::LinksOf)?;
+        Ok(Vec::default())
+    }
+    #[tracing::instrument(
+        name = "storage.linked_from",
+        skip_all,
+        fields(backend = "null", db.system = "null", db.operation = "linked_from")
+    )]
+    async fn linked_from(&self, _id: &ITEM::ID, _relation: &str) -> Result<Vec<ITEM::ID>> {
+        self.on_use(StorageOperation::LinkedFrom)?;
+        Ok(Vec::default())
+    }
+
+    #[tracing::instrument(
+        name = "storage.put_meta",
+        skip_all,
+        fields(backend = "null", db.system = "null", db.operation = "put_meta")
+    )]
+    async fn put_meta(&self, _key: &str, _value: &[u8]) -> Result<()> {
+        self.on_use(StorageOperation::PutMeta)?;
+        Ok(())
+    }
+    #[tracing::instrument(
+        name = "storage.get_meta",
+        skip_all,
+        fields(backend = "null", db.system = "null", db.operation = "get_meta")
+    )]
+    async fn get_meta(&self, _key: &str) -> Result<Option<Vec<u8>>> {
+        self.on_use(StorageOperation::GetMeta)?;
+        Ok(None)
+    }
+
+    #[tracing::instrument(
+        name = "storage.force_unlock",
+        skip_all,
+        fields(backend = "null", db.system = "null", db.operation = "force_unlock")
+    )]
+    async fn force_unlock(&self, _id: &ITEM::ID) -> Result<Option<StorageLock>> {
+        self.on_use(StorageOperation::ForceUnlock)?;
+        Ok(None)
+    }
+    #[tracing::instrument(
+        name = "storage.force_unlock_if_held",
+        skip_all,
+        fields(backend = "null", db.system = "null", db.operation = "force_unlock_if_held")
+    )]
+    async fn force_unlock_if_held(
+        &self,
+        _id: &ITEM::ID,
+        _who: &str,
+        _when: DateTime<Utc>,
+    ) -> Result<bool> {
+        self.on_use(StorageOperation::ForceUnlock)?;
+        Ok(false)
+    }
+    #[tracing::instrument(
+        name = "storage.remove_orphaned_locks",
+        skip_all,
+        fields(backend = "null", db.system = "null", db.operation = "remove_orphaned_locks")
+    )]
+    async fn remove_orphaned_locks(&self, _max_age: Duration) -> Result<usize> {
+        self.on_use(StorageOperation::RemoveOrphanedLocks)?;
+        Ok(0)
+    }
+    #[tracing::instrument(
+        name = "storage.verify_lock",
+        skip_all,
+        fields(backend = "null", db.system = "null", db.operation = "verify_lock")
+    )]
     async fn verify_lock(&self, _id: &ITEM::ID, _lock: &StorageLock) -> Result<bool> {
-        if self.warnings_on_use {
-            tracing::warn!("StorageNull verify_lock used!");
-        }
+        self.on_use(StorageOperation::VerifyLock)?;
         Ok(true)
     }
+    #[tracing::instrument(
+        name = "storage.all_ids",
+        skip_all,
+        fields(backend = "null", db.system = "null", db.operation = "all_ids")
+    )]
     async fn all_ids(&self) -> Result<Vec<ITEM::ID>> {
-        if self.warnings_on_use {
-            tracing::warn!("StorageNull all_ids used!");
-        }
+        self.on_use(StorageOperation::AllIds)?;
         Ok(Vec::default())
     }
+    #[tracing::instrument(
+        name = "storage.scan_ids",
+        skip_all,
+        fields(backend = "null", db.system = "null", db.operation = "scan_ids")
+    )]
+    async fn scan_ids(
+        &self,
+        _start: Option<&str>,
+        _limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.on_use(StorageOperation::ScanIds)?;
+        Ok(Page::new(Vec::default(), None))
+    }
+    #[tracing::instrument(
+        name = "storage.list",
+        skip_all,
+        fields(backend = "null", db.system = "null", db.operation = "list")
+    )]
+    async fn list(
+        &self,
+        _start: Option<&str>,
+        _limit: Option<usize>,
+    ) -> Result<Page<ItemSummary<ITEM::ID>>> {
+        self.on_use(StorageOperation::List)?;
+        Ok(Page::new(Vec::default(), None))
+    }
+    #[tracing::instrument(
+        name = "storage.display_lock",
+        skip_all,
+        fields(backend = "null", db.system = "null", db.operation = "display_lock")
+    )]
     async fn display_lock(&self, _id: &ITEM::ID) -> Result<String> {
-        if self.warnings_on_use {
-            tracing::warn!("StorageNull all_ids used!");
-        }
+        self.on_use(StorageOperation::DisplayLock)?;
         Ok(String::default())
     }
 
     #[cfg(feature = "metadata")]
     async fn metadata_highest_seen_id(&self) -> Option<ITEM::ID> {
-        if self.warnings_on_use {
-            tracing::warn!("StorageNull metadata_highest_seen_id used!");
-        }
+        self.on_metadata_use("metadata_highest_seen_id");
         self.metadata.highest_seen_id()
     }
+    #[cfg(feature = "metadata")]
+    async fn metadata_item_count(&self) -> Option<u64> {
+        self.metadata.item_count()
+    }
+    #[cfg(feature = "metadata")]
+    async fn metadata_total_bytes(&self) -> Option<u64> {
+        self.metadata.total_bytes()
+    }
+    #[cfg(feature = "metadata")]
+    async fn metadata_last_write(&self) -> Option<DateTime<Utc>> {
+        self.metadata.last_write()
+    }
+    #[cfg(feature = "metadata")]
+    async fn metadata_lock_stats(&self) -> crate::LockStats {
+        self.metadata.lock_stats()
+    }
 
     #[cfg(feature = "wipe")]
-    async fn wipe(&self, _confirmation: &str) -> Result<()> {
-        if self.warnings_on_use {
-            tracing::warn!("StorageNull wipe used!");
-        }
+    #[tracing::instrument(
+        name = "storage.wipe",
+        skip_all,
+        fields(backend = "null", db.system = "null", db.operation = "wipe")
+    )]
+    async fn wipe(
+        &self,
+        options: &crate::WipeOptions,
+        _progress: Option<&dyn crate::ProgressSink>,
+        _cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        self.on_use(StorageOperation::Wipe)?;
 
-        Ok(())
+        Ok(crate::WipeReport::new(options))
+    }
+
+    #[cfg(feature = "wipe")]
+    #[tracing::instrument(
+        name = "storage.wipe_matching",
+        skip_all,
+        fields(backend = "null", db.system = "null", db.operation = "wipe_matching")
+    )]
+    async fn wipe_matching(
+        &self,
+        _filter: &crate::IdFilter,
+        options: &crate::WipeOptions,
+        _progress: Option<&dyn crate::ProgressSink>,
+        _cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        self.on_use(StorageOperation::WipeMatching)?;
+
+        Ok(crate::WipeReport::new(options))
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn delete_many(
+        &self,
+        ids: &[ITEM::ID],
+        _options: &crate::DeleteOptions,
+    ) -> Result<Vec<crate::DeleteOutcome>> {
+        self.on_use(StorageOperation::DeleteMany)?;
+
+        Ok(vec![crate::DeleteOutcome::NotFound; ids.len()])
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::NullBehavior;
+    use crate::NullProfile;
     use crate::Storage;
+    use crate::StorageError;
     use crate::StorageItem;
     use crate::StorageNull;
+    use crate::StorageOperation;
     use color_eyre::Result;
     use serde::Deserialize;
     use serde::Serialize;
@@ -165,6 +509,8 @@ mod tests {
     struct TestItem {}
 
     impl StorageItem for TestItem {
+        type ID = String;
+
         fn serialize(&self) -> Result<Vec<u8>> {
             todo!()
         }
@@ -173,6 +519,40 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn it_scans_nothing() -> Result<()> {
+        let storage = StorageNull::<TestItem>::default();
+        let page = storage.scan_ids(None, Some(10)).await?;
+        assert!(page.items.is_empty());
+        assert!(page.is_last());
+        let page = storage.scan_ids_with_prefix("a", None, None).await?;
+        assert!(page.is_last());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_follows_its_profile() -> Result<()> {
+        let mut storage = StorageNull::<TestItem>::default();
+        storage.set_profile(NullProfile {
+            reads: NullBehavior::Warn,
+            writes: NullBehavior::Error,
+            destructive: NullBehavior::Panic,
+        })?;
+        assert!(!storage.exists(&String::from("a")).await?);
+        let e = storage.create().await.unwrap_err();
+        assert_eq!(
+            Some(&StorageError::Unsupported {
+                operation: StorageOperation::Create
+            }),
+            e.downcast_ref()
+        );
+        let panicked = std::panic::catch_unwind(|| storage.on_use(StorageOperation::ForceUnlock));
+        assert!(panicked.is_err());
+
+        Ok(())
+    }
+
     #[test]
     fn it_debugs() {
         let storage = StorageNull::<TestItem>::default();
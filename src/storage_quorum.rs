@@ -0,0 +1,864 @@
+use crate::envelope::join;
+use crate::envelope::split;
+use crate::storage_item::serialize_valid;
+use crate::Envelope;
+use crate::EnvelopeHeader;
+use crate::LockResult;
+use crate::OpOptions;
+use crate::Page;
+use crate::Storage;
+use crate::StorageError;
+use crate::StorageId;
+use crate::StorageItem;
+use crate::StorageLock;
+use async_trait::async_trait;
+use chrono::DateTime;
+use chrono::Utc;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use core::marker::PhantomData;
+use futures_util::future::join_all;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The per replica locks behind the lock handed out by [StorageQuorum::lock].
+#[derive(Debug)]
+struct QuorumLock {
+    lock: StorageLock,
+    replica_locks: Vec<Option<StorageLock>>,
+    created_at: DateTime<Utc>,
+}
+
+/// Newer saves win, items that were only created by locking lose against every save.
+fn freshness(header: &EnvelopeHeader) -> Option<DateTime<Utc>> {
+    (!header.updated_by.is_empty()).then_some(header.updated_at)
+}
+
+/// Links missing on some replicas missed a write quorum, but still count.
+fn union_sorted<ID: StorageId>(replica_ids: Vec<Vec<ID>>) -> Vec<ID> {
+    let mut ids: Vec<ID> = replica_ids.into_iter().flatten().collect();
+    ids.sort_by_key(|id| id.to_string());
+    ids.dedup_by_key(|id| id.to_string());
+
+    ids
+}
+
+/// Writes to all replicas and succeeds once `write_quorum` of them acknowledged.
+/// Reads ask all replicas, need `read_quorum` answers, and return the newest item.
+///
+/// Items are stored as [Envelope]s, the newest is the one with the latest `updated_at`.
+/// With `read_quorum + write_quorum > replicas` every read sees the latest write,
+/// the defaults are majorities, so one of three replicas can fail.
+///
+/// Locks are taken on all reachable replicas and need a write quorum too.
+/// Replicas that missed a save are not repaired, they catch up with the next save of the item.
+#[derive(Debug)]
+pub struct StorageQuorum<ITEM: StorageItem>
+where
+    ITEM: Send,
+{
+    replicas: Vec<Box<dyn Storage<Envelope<ITEM>>>>,
+    write_quorum: usize,
+    read_quorum: usize,
+    locks: Mutex<HashMap<String, QuorumLock>>,
+    item_type: PhantomData<ITEM>,
+}
+
+impl<ITEM: StorageItem + Send> StorageQuorum<ITEM> {
+    pub fn new(replicas: Vec<Box<dyn Storage<Envelope<ITEM>>>>) -> Self {
+        let majority = replicas.len() / 2 + 1;
+        Self {
+            replicas,
+            write_quorum: majority,
+            read_quorum: majority,
+            locks: Mutex::new(HashMap::new()),
+            item_type: PhantomData,
+        }
+    }
+
+    /// How many replicas must acknowledge a write, defaults to a majority.
+    pub fn set_write_quorum(&mut self, write_quorum: usize) -> Result<()> {
+        self.write_quorum = self.checked_quorum(write_quorum)?;
+
+        Ok(())
+    }
+
+    /// How many replicas must answer a read, defaults to a majority.
+    pub fn set_read_quorum(&mut self, read_quorum: usize) -> Result<()> {
+        self.read_quorum = self.checked_quorum(read_quorum)?;
+
+        Ok(())
+    }
+
+    pub fn replicas(&self) -> &[Box<dyn Storage<Envelope<ITEM>>>] {
+        &self.replicas
+    }
+
+    fn checked_quorum(&self, quorum: usize) -> Result<usize> {
+        if quorum == 0 || quorum > self.replicas.len() {
+            return Err(eyre!(
+                "Quorum must be between 1 and {}, got {quorum}",
+                self.replicas.len()
+            ));
+        }
+
+        Ok(quorum)
+    }
+
+    /// Fails unless at least `quorum` results are ok and returns those.
+    fn ensure_quorum<T>(results: Vec<Result<T>>, quorum: usize, operation: &str) -> Result<Vec<T>> {
+        let total = results.len();
+        let mut oks = Vec::with_capacity(total);
+        let mut last_error = None;
+        let mut all_not_found = true;
+        for r in results {
+            match r {
+                Ok(t) => oks.push(t),
+                Err(e) => {
+                    tracing::debug!("{operation} failed on a replica -> {e:?}");
+                    all_not_found &=
+                        matches!(e.downcast_ref(), Some(StorageError::NotFound { .. }));
+                    last_error = Some(e);
+                }
+            }
+        }
+        if oks.len() < quorum {
+            return match last_error {
+                // missing on too many replicas is just missing
+                Some(e) if all_not_found => Err(e),
+                last_error => Err(eyre!(
+                    "{operation} reached {} of {total} replicas, needs {quorum} -> {last_error:?}",
+                    oks.len()
+                )),
+            };
+        }
+
+        Ok(oks)
+    }
+
+    /// The enveloped data of the newest copy.
+    async fn read_newest(&self, id: &ITEM::ID) -> Result<Vec<u8>> {
+        let results = join_all(self.replicas.iter().map(|r| r.load_raw(id))).await;
+        Self::ensure_quorum(results, self.read_quorum, "Load")?
+            .into_iter()
+            .map(|data| Ok((freshness(&EnvelopeHeader::from_data(&data)?), data)))
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .max_by_key(|(freshness, _)| *freshness)
+            .map(|(_, data)| data)
+            .ok_or_else(|| eyre!("No replicas"))
+    }
+
+    /// The replica locks for `lock`, fails if `lock` isn't the current lock of the item.
+    fn replica_locks(&self, id: &ITEM::ID, lock: &StorageLock) -> Result<Vec<Option<StorageLock>>> {
+        let locks = self.locks.lock().expect("can lock");
+        match locks.get(&id.to_string()) {
+            Some(q) if q.lock == *lock => Ok(q
+                .replica_locks
+                .iter()
+                .map(|l| l.as_ref().map(StorageLock::duplicate))
+                .collect()),
+            _ => Err(eyre!("Lock invalid!")),
+        }
+    }
+
+    fn created_at(&self, id: &ITEM::ID) -> Option<DateTime<Utc>> {
+        let locks = self.locks.lock().expect("can lock");
+        locks.get(&id.to_string()).map(|q| q.created_at)
+    }
+
+    async fn unlock_replicas(&self, id: &ITEM::ID, replica_locks: Vec<Option<StorageLock>>) {
+        let unlocks = self
+            .replicas
+            .iter()
+            .zip(replica_locks)
+            .filter_map(|(r, l)| l.map(|l| r.unlock(id, l)));
+        for r in join_all(unlocks).await {
+            if let Err(e) = r {
+                tracing::warn!("Can't unlock {id} on a replica -> {e:?}");
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<ITEM: StorageItem + Send> Storage<ITEM> for StorageQuorum<ITEM> {
+    async fn ensure_storage_exists(&mut self) -> Result<()> {
+        for replica in self.replicas.iter_mut() {
+            replica.ensure_storage_exists().await?;
+        }
+
+        Ok(())
+    }
+
+    fn capabilities(&self) -> crate::Capabilities {
+        self.replicas
+            .iter()
+            .map(|replica| replica.capabilities())
+            .reduce(|a, b| a.intersect(&b))
+            .unwrap_or_default()
+    }
+
+    async fn create(&self) -> Result<ITEM::ID> {
+        let mut last_error = None;
+        for replica in self.replicas.iter() {
+            match replica.create().await {
+                Ok(id) => return Ok(id),
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| eyre!("No replicas")))
+    }
+
+    async fn exists(&self, id: &ITEM::ID) -> Result<bool> {
+        self.exists_with(id, &OpOptions::default()).await
+    }
+
+    async fn exists_with(&self, id: &ITEM::ID, options: &OpOptions) -> Result<bool> {
+        let results = join_all(self.replicas.iter().map(|r| r.exists_with(id, options))).await;
+        let exists = Self::ensure_quorum(results, self.read_quorum, "Exists")?;
+
+        Ok(exists.into_iter().any(|e| e))
+    }
+
+    async fn load(&self, id: &ITEM::ID) -> Result<ITEM> {
+        Ok(Envelope::<ITEM>::deserialize(&self.read_newest(id).await?)?.item)
+    }
+
+    async fn save(&self, id: &ITEM::ID, item: &ITEM, lock: &StorageLock) -> Result<()> {
+        self.save_raw(id, &serialize_valid(id, item)?, lock).await
+    }
+
+    async fn lock(&self, id: &ITEM::ID, who: &str) -> Result<LockResult<ITEM>> {
+        let results = join_all(self.replicas.iter().map(|r| r.lock(id, who))).await;
+        let mut replica_locks = Vec::with_capacity(results.len());
+        let mut newest: Option<Envelope<ITEM>> = None;
+        let mut already_locked = None;
+        let mut last_error = None;
+        for r in results {
+            match r {
+                Ok(LockResult::Success { lock, item }) => {
+                    replica_locks.push(Some(lock));
+                    if newest
+                        .as_ref()
+                        .is_none_or(|n| freshness(&item.header) > freshness(&n.header))
+                    {
+                        newest = Some(item);
+                    }
+                }
+                Ok(LockResult::AlreadyLocked { who, when }) => {
+                    replica_locks.push(None);
+                    already_locked.get_or_insert(LockResult::AlreadyLocked { who, when });
+                }
+                Err(e) => {
+                    replica_locks.push(None);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        let locked = replica_locks.iter().flatten().count();
+        if locked < self.write_quorum {
+            self.unlock_replicas(id, replica_locks).await;
+            return match already_locked {
+                Some(already_locked) => Ok(already_locked),
+                None => Err(eyre!(
+                    "Lock reached {locked} of {} replicas, needs {} -> {last_error:?}",
+                    self.replicas.len(),
+                    self.write_quorum
+                )),
+            };
+        }
+        let Envelope { header, item } = newest.ok_or_else(|| eyre!("No replicas"))?;
+        let lock = StorageLock::new(who);
+        self.locks.lock().expect("can lock").insert(
+            id.to_string(),
+            QuorumLock {
+                lock: lock.duplicate(),
+                replica_locks,
+                created_at: header.created_at,
+            },
+        );
+
+        Ok(LockResult::Success { lock, item })
+    }
+
+    async fn unlock(&self, id: &ITEM::ID, lock: StorageLock) -> Result<()> {
+        let replica_locks = self.replica_locks(id, &lock)?;
+        self.locks.lock().expect("can lock").remove(&id.to_string());
+        self.unlock_replicas(id, replica_locks).await;
+
+        Ok(())
+    }
+
+    /// Hands off the lock on every replica it is held on and needs a write quorum of them.
+    /// Otherwise the lock is released, like a [StorageQuorum::lock] that missed the quorum.
+    async fn handoff_lock(
+        &self,
+        id: &ITEM::ID,
+        lock: StorageLock,
+        new_who: &str,
+    ) -> Result<StorageLock> {
+        let replica_locks = self.replica_locks(id, &lock)?;
+        let handoffs = self
+            .replicas
+            .iter()
+            .zip(replica_locks)
+            .map(|(r, l)| async move {
+                match l {
+                    Some(l) => r.handoff_lock(id, l, new_who).await.map(Some),
+                    None => Ok(None),
+                }
+            });
+        let mut new_replica_locks = Vec::with_capacity(self.replicas.len());
+        let mut last_error = None;
+        for r in join_all(handoffs).await {
+            match r {
+                Ok(l) => new_replica_locks.push(l),
+                Err(e) => {
+                    tracing::warn!("Can't hand off lock of {id} on a replica -> {e:?}");
+                    new_replica_locks.push(None);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        let handed_off = new_replica_locks.iter().flatten().count();
+        if handed_off < self.write_quorum {
+            self.locks.lock().expect("can lock").remove(&id.to_string());
+            self.unlock_replicas(id, new_replica_locks).await;
+            return Err(eyre!(
+                "Handoff reached {handed_off} of {} replicas, needs {} -> {last_error:?}",
+                self.replicas.len(),
+                self.write_quorum
+            ));
+        }
+        let new_lock = StorageLock::new(new_who);
+        let mut locks = self.locks.lock().expect("can lock");
+        if let Some(q) = locks.get_mut(&id.to_string()) {
+            q.lock = new_lock.duplicate();
+            q.replica_locks = new_replica_locks;
+        }
+
+        Ok(new_lock)
+    }
+
+    async fn load_raw(&self, id: &ITEM::ID) -> Result<Vec<u8>> {
+        let data = self.read_newest(id).await?;
+        Ok(split(&data)?.1.to_vec())
+    }
+
+    async fn save_raw(&self, id: &ITEM::ID, data: &[u8], lock: &StorageLock) -> Result<()> {
+        let replica_locks = self.replica_locks(id, lock)?;
+        let header = EnvelopeHeader {
+            created_at: self.created_at(id).unwrap_or_else(Utc::now),
+            updated_at: Utc::now(),
+            updated_by: lock.who().to_string(),
+        };
+        let data = join(&header, data)?;
+        let saves = self
+            .replicas
+            .iter()
+            .zip(replica_locks.iter())
+            .filter_map(|(r, l)| l.as_ref().map(|l| r.save_raw(id, &data, l)));
+        let results = join_all(saves).await;
+        Self::ensure_quorum(results, self.write_quorum, "Save")?;
+
+        Ok(())
+    }
+
+    async fn put_blob(
+        &self,
+        id: &ITEM::ID,
+        name: &str,
+        data: &[u8],
+        lock: &StorageLock,
+    ) -> Result<()> {
+        let replica_locks = self.replica_locks(id, lock)?;
+        let puts = self
+            .replicas
+            .iter()
+            .zip(replica_locks.iter())
+            .filter_map(|(r, l)| l.as_ref().map(|l| r.put_blob(id, name, data, l)));
+        let results = join_all(puts).await;
+        Self::ensure_quorum(results, self.write_quorum, "Put blob")?;
+
+        Ok(())
+    }
+
+    async fn get_blob(&self, id: &ITEM::ID, name: &str) -> Result<Option<Vec<u8>>> {
+        let results = join_all(self.replicas.iter().map(|r| r.get_blob(id, name))).await;
+        let blobs = Self::ensure_quorum(results, self.read_quorum, "Get blob")?;
+
+        Ok(blobs.into_iter().flatten().next())
+    }
+
+    async fn list_blobs(&self, id: &ITEM::ID) -> Result<Vec<String>> {
+        let results = join_all(self.replicas.iter().map(|r| r.list_blobs(id))).await;
+        let mut names: Vec<String> = Self::ensure_quorum(results, self.read_quorum, "List blobs")?
+            .into_iter()
+            .flatten()
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+
+        Ok(names)
+    }
+
+    async fn put_meta(&self, key: &str, value: &[u8]) -> Result<()> {
+        let results = join_all(self.replicas.iter().map(|r| r.put_meta(key, value))).await;
+        Self::ensure_quorum(results, self.write_quorum, "Put meta")?;
+
+        Ok(())
+    }
+
+    /// Note: Meta values are not versioned, so any value found is returned.
+    async fn get_meta(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let results = join_all(self.replicas.iter().map(|r| r.get_meta(key))).await;
+        let values = Self::ensure_quorum(results, self.read_quorum, "Get meta")?;
+
+        Ok(values.into_iter().flatten().next())
+    }
+
+    async fn add_alias(&self, alias: &ITEM::ID, canonical: &ITEM::ID) -> Result<()> {
+        let results = join_all(self.replicas.iter().map(|r| r.add_alias(alias, canonical))).await;
+        Self::ensure_quorum(results, self.write_quorum, "Add alias")?;
+
+        Ok(())
+    }
+
+    async fn remove_alias(&self, alias: &ITEM::ID) -> Result<()> {
+        let results = join_all(self.replicas.iter().map(|r| r.remove_alias(alias))).await;
+        Self::ensure_quorum(results, self.write_quorum, "Remove alias")?;
+
+        Ok(())
+    }
+
+    async fn resolve_alias(&self, id: &ITEM::ID) -> Result<ITEM::ID> {
+        let results = join_all(self.replicas.iter().map(|r| r.resolve_alias(id))).await;
+        let resolved = Self::ensure_quorum(results, self.read_quorum, "Resolve alias")?;
+
+        // an alias only exists on some replicas, if it missed a write quorum
+        Ok(resolved
+            .iter()
+            .find(|r| *r != id)
+            .unwrap_or(&resolved[0])
+            .clone())
+    }
+
+    async fn add_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        let replica_locks = self.replica_locks(from, lock)?;
+        let adds = self
+            .replicas
+            .iter()
+            .zip(replica_locks.iter())
+            .filter_map(|(r, l)| l.as_ref().map(|l| r.add_link(from, relation, to, l)));
+        let results = join_all(adds).await;
+        Self::ensure_quorum(results, self.write_quorum, "Add link")?;
+
+        Ok(())
+    }
+
+    async fn remove_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        let replica_locks = self.replica_locks(from, lock)?;
+        let removes = self
+            .replicas
+            .iter()
+            .zip(replica_locks.iter())
+            .filter_map(|(r, l)| l.as_ref().map(|l| r.remove_link(from, relation, to, l)));
+        let results = join_all(removes).await;
+        Self::ensure_quorum(results, self.write_quorum, "Remove link")?;
+
+        Ok(())
+    }
+
+    async fn links_of(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        let results = join_all(self.replicas.iter().map(|r| r.links_of(id, relation))).await;
+        let links = Self::ensure_quorum(results, self.read_quorum, "Links of")?;
+
+        Ok(union_sorted(links))
+    }
+
+    async fn linked_from(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        let results = join_all(self.replicas.iter().map(|r| r.linked_from(id, relation))).await;
+        let links = Self::ensure_quorum(results, self.read_quorum, "Linked from")?;
+
+        Ok(union_sorted(links))
+    }
+
+    async fn force_unlock(&self, id: &ITEM::ID) -> Result<Option<StorageLock>> {
+        let quorum_lock = self.locks.lock().expect("can lock").remove(&id.to_string());
+        let results = join_all(self.replicas.iter().map(|r| r.force_unlock(id))).await;
+        let displaced = Self::ensure_quorum(results, self.write_quorum, "Force unlock")?;
+
+        Ok(match quorum_lock {
+            Some(q) => Some(q.lock),
+            None => displaced.into_iter().flatten().next(),
+        })
+    }
+
+    /// Releases the quorum lock on all replicas if it or one of its replica locks, as shown by
+    /// [StorageQuorum::list], is held by `who` since `when`. Otherwise asks every replica.
+    async fn force_unlock_if_held(
+        &self,
+        id: &ITEM::ID,
+        who: &str,
+        when: DateTime<Utc>,
+    ) -> Result<bool> {
+        let quorum_lock = {
+            let mut locks = self.locks.lock().expect("can lock");
+            let key = id.to_string();
+            let held = locks.get(&key).is_some_and(|q| {
+                q.lock.is_held_by(who, when)
+                    || q.replica_locks
+                        .iter()
+                        .flatten()
+                        .any(|l| l.is_held_by(who, when))
+            });
+            if held {
+                locks.remove(&key)
+            } else {
+                None
+            }
+        };
+        if let Some(q) = quorum_lock {
+            self.unlock_replicas(id, q.replica_locks).await;
+            return Ok(true);
+        }
+        let results = join_all(
+            self.replicas
+                .iter()
+                .map(|r| r.force_unlock_if_held(id, who, when)),
+        )
+        .await;
+        let unlocked = Self::ensure_quorum(results, self.write_quorum, "Force unlock")?;
+
+        Ok(unlocked.into_iter().any(|u| u))
+    }
+
+    async fn remove_orphaned_locks(&self, max_age: Duration) -> Result<usize> {
+        let results = join_all(
+            self.replicas
+                .iter()
+                .map(|r| r.remove_orphaned_locks(max_age)),
+        )
+        .await;
+        let removed = Self::ensure_quorum(results, self.write_quorum, "Remove orphaned locks")?;
+
+        Ok(removed.into_iter().max().unwrap_or_default())
+    }
+
+    async fn verify_lock(&self, id: &ITEM::ID, lock: &StorageLock) -> Result<bool> {
+        self.verify_lock_with(id, lock, &OpOptions::default()).await
+    }
+
+    async fn verify_lock_with(
+        &self,
+        id: &ITEM::ID,
+        lock: &StorageLock,
+        options: &OpOptions,
+    ) -> Result<bool> {
+        let Ok(replica_locks) = self.replica_locks(id, lock) else {
+            return Ok(false);
+        };
+        let verifies = self
+            .replicas
+            .iter()
+            .zip(replica_locks.iter())
+            .filter_map(|(r, l)| l.as_ref().map(|l| r.verify_lock_with(id, l, options)));
+        let valid = join_all(verifies)
+            .await
+            .into_iter()
+            .filter(|r| matches!(r, Ok(true)))
+            .count();
+
+        Ok(valid >= self.write_quorum)
+    }
+
+    async fn all_ids(&self) -> Result<Vec<ITEM::ID>> {
+        let results = join_all(self.replicas.iter().map(|r| r.all_ids())).await;
+        let ids: BTreeMap<String, ITEM::ID> =
+            Self::ensure_quorum(results, self.read_quorum, "All ids")?
+                .into_iter()
+                .flatten()
+                .map(|id| (id.to_string(), id))
+                .collect();
+
+        Ok(ids.into_values().collect())
+    }
+
+    /// Pages through the sorted ids of all replicas, the scan position is the last returned id.
+    async fn scan_ids(&self, start: Option<&str>, limit: Option<usize>) -> Result<Page<ITEM::ID>> {
+        let mut ids = self.all_ids().await?;
+        if let Some(start) = start {
+            ids.retain(|id| id.to_string().as_str() > start);
+        }
+        match limit {
+            Some(limit) if ids.len() > limit => {
+                ids.truncate(limit);
+                let scan_pos = ids.last().map(|id| id.to_string());
+                Ok(Page::new(ids, scan_pos))
+            }
+            _ => Ok(Page::new(ids, None)),
+        }
+    }
+
+    /// Note: The summaries come from the first replica that answered, they are not merged.
+    async fn list(
+        &self,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<crate::ItemSummary<ITEM::ID>>> {
+        let results = join_all(self.replicas.iter().map(|r| r.list(start, limit))).await;
+        let pages = Self::ensure_quorum(results, self.read_quorum, "List")?;
+        pages
+            .into_iter()
+            .next()
+            .ok_or_else(|| eyre!("List: no replica answered"))
+    }
+
+    async fn item_size(&self, id: &ITEM::ID) -> Result<u64> {
+        let results = join_all(self.replicas.iter().map(|r| r.item_size(id))).await;
+        let sizes = Self::ensure_quorum(results, self.read_quorum, "ItemSize")?;
+        sizes
+            .into_iter()
+            .max()
+            .ok_or_else(|| eyre!("ItemSize: no replica answered"))
+    }
+
+    /// Asks a single replica, estimating visits every item.
+    async fn estimate_cost(&self) -> Result<crate::StorageCost> {
+        let replica = self
+            .replicas
+            .first()
+            .ok_or_else(|| eyre!("EstimateCost: no replicas"))?;
+        replica.estimate_cost().await
+    }
+
+    /// Shuts down all replicas, even if some fail.
+    async fn shutdown(&self) -> Result<()> {
+        let results = join_all(self.replicas.iter().map(|r| r.shutdown())).await;
+        let failed = results.iter().filter(|r| r.is_err()).count();
+        if let Some(e) = results.into_iter().find_map(|r| r.err()) {
+            return Err(e.wrap_err(format!("Shutdown: {failed} replicas failed")));
+        }
+
+        Ok(())
+    }
+
+    async fn display_lock(&self, id: &ITEM::ID) -> Result<String> {
+        let locks = self.locks.lock().expect("can lock");
+        match locks.get(&id.to_string()) {
+            Some(q) => Ok(format!("{:?}", q.lock)),
+            None => Ok(String::new()),
+        }
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_highest_seen_id(&self) -> Option<ITEM::ID> {
+        None
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_item_count(&self) -> Option<u64> {
+        None
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_total_bytes(&self) -> Option<u64> {
+        None
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_last_write(&self) -> Option<DateTime<Utc>> {
+        None
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_lock_stats(&self) -> crate::LockStats {
+        crate::LockStats::default()
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn wipe(
+        &self,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        let results = join_all(
+            self.replicas
+                .iter()
+                .map(|r| r.wipe(options, progress, cancel)),
+        )
+        .await;
+        let reports = Self::ensure_quorum(results, self.write_quorum, "Wipe")?;
+        if !options.is_dry_run() {
+            self.locks.lock().expect("can lock").clear();
+        }
+
+        reports
+            .into_iter()
+            .max_by_key(|r| r.count)
+            .ok_or_else(|| eyre!("No replicas"))
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn wipe_matching(
+        &self,
+        filter: &crate::IdFilter,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        let results = join_all(
+            self.replicas
+                .iter()
+                .map(|r| r.wipe_matching(filter, options, progress, cancel)),
+        )
+        .await;
+        let reports = Self::ensure_quorum(results, self.write_quorum, "Wipe matching")?;
+        if !options.is_dry_run() {
+            self.locks.lock().expect("can lock").clear();
+        }
+
+        reports
+            .into_iter()
+            .max_by_key(|r| r.count)
+            .ok_or_else(|| eyre!("No replicas"))
+    }
+
+    /// An item counts as deleted, if any replica deleted it.
+    #[cfg(feature = "wipe")]
+    async fn delete_many(
+        &self,
+        ids: &[ITEM::ID],
+        options: &crate::DeleteOptions,
+    ) -> Result<Vec<crate::DeleteOutcome>> {
+        let results = join_all(self.replicas.iter().map(|r| r.delete_many(ids, options))).await;
+        let outcomes = Self::ensure_quorum(results, self.write_quorum, "Delete many")?;
+        if options.force() {
+            let mut locks = self.locks.lock().expect("can lock");
+            for id in ids {
+                locks.remove(&id.to_string());
+            }
+        }
+
+        outcomes
+            .into_iter()
+            .reduce(|merged, outcomes| {
+                merged
+                    .into_iter()
+                    .zip(outcomes)
+                    .map(|(merged, outcome)| match outcome {
+                        crate::DeleteOutcome::Deleted => outcome,
+                        _ => merged,
+                    })
+                    .collect()
+            })
+            .ok_or_else(|| eyre!("No replicas"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_item::TestItem;
+    use crate::Envelope;
+    use crate::MockResponse;
+    use crate::Storage;
+    use crate::StorageMemory;
+    use crate::StorageMock;
+    use crate::StorageOperation;
+    use crate::StorageQuorum;
+    use color_eyre::eyre::eyre;
+    use color_eyre::Result;
+
+    fn failing(operation: StorageOperation) -> StorageMock<Envelope<TestItem>> {
+        let mock = StorageMock::<Envelope<TestItem>>::default();
+        mock.script(operation, MockResponse::Err(eyre!("dead")));
+        mock
+    }
+
+    #[tokio::test]
+    async fn it_tolerates_one_dead_replica() -> Result<()> {
+        let dead = StorageMock::<Envelope<TestItem>>::default();
+        for op in [
+            StorageOperation::Lock,
+            StorageOperation::Load,
+            StorageOperation::LoadRaw,
+        ] {
+            dead.script(op, MockResponse::Err(eyre!("dead")));
+        }
+        let stale = StorageMemory::<Envelope<TestItem>>::default();
+        let id = String::from("a");
+        // only the stale replica has an older copy
+        let (lock, mut envelope) = stale.lock(&id, "old").await?.success()?;
+        envelope.header.updated_by = String::from("old");
+        envelope.item.count = 1;
+        stale.save_and_unlock(&id, &envelope, lock).await?;
+
+        let quorum = StorageQuorum::new(vec![
+            Box::new(StorageMemory::<Envelope<TestItem>>::default()),
+            Box::new(stale),
+            Box::new(dead),
+        ]);
+        let (lock, _) = quorum.lock(&id, "TEST").await?.success()?;
+        quorum
+            .save_and_unlock(&id, &TestItem { count: 7 }, lock)
+            .await?;
+        assert_eq!(TestItem { count: 7 }, quorum.load(&id).await?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_fails_locks_without_quorum() -> Result<()> {
+        let quorum = StorageQuorum::new(vec![
+            Box::new(StorageMemory::<Envelope<TestItem>>::default()),
+            Box::new(failing(StorageOperation::Lock)),
+            Box::new(failing(StorageOperation::Lock)),
+        ]);
+        let id = String::from("a");
+
+        let e = quorum.lock(&id, "TEST").await.expect_err("no quorum");
+        assert!(e.to_string().contains("needs 2"), "{e}");
+        // the lock on the reachable replica is released again
+        quorum.replicas()[0].lock(&id, "OTHER").await?.success()?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_fails_saves_without_quorum() -> Result<()> {
+        let quorum = StorageQuorum::new(vec![
+            Box::new(StorageMemory::<Envelope<TestItem>>::default()),
+            Box::new(failing(StorageOperation::Save)),
+            Box::new(failing(StorageOperation::Save)),
+        ]);
+        let id = String::from("a");
+
+        let (lock, _) = quorum.lock(&id, "TEST").await?.success()?;
+        let e = quorum
+            .save(&id, &TestItem { count: 1 }, &lock)
+            .await
+            .expect_err("no quorum");
+        assert!(e.to_string().contains("needs 2"), "{e}");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,865 @@
+use crate::Clock;
+use crate::LockResult;
+use crate::OpOptions;
+use crate::Page;
+use crate::RetryBudget;
+use crate::Storage;
+use crate::StorageError;
+use crate::StorageItem;
+use crate::StorageLock;
+use crate::StorageOperation;
+use crate::SystemClock;
+use async_trait::async_trait;
+use chrono::DateTime;
+use chrono::Utc;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Report;
+use color_eyre::eyre::Result;
+use core::marker::PhantomData;
+use rand::Rng;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// How [StorageResilient] classifies the errors of the wrapped storage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResilientErrorClass {
+    /// A [StorageError::Backend] that might succeed later, e.g. throttling or a timeout
+    Transient,
+    /// Any other backend failure, including errors that aren't a [StorageError], e.g. IO errors
+    Backend,
+    /// The backend works, but refused the request, e.g. [StorageError::NotFound] or [StorageError::LockMismatch]
+    Caller,
+}
+
+impl ResilientErrorClass {
+    pub fn classify(e: &Report) -> Self {
+        match e.downcast_ref::<StorageError>() {
+            Some(StorageError::Backend {
+                transient: true, ..
+            }) => Self::Transient,
+            Some(StorageError::Backend { .. }) | None => Self::Backend,
+            Some(_) => Self::Caller,
+        }
+    }
+}
+
+/// Which errors [StorageResilient] retries and how often.
+///
+/// Uses exponential backoff with full jitter, like [crate::DynamoDbRetryPolicy].
+#[derive(Debug, Clone)]
+pub struct ResilientRetryPolicy {
+    /// Maximum number of attempts, including the first one
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled for every further retry
+    pub base_delay: Duration,
+    /// Upper limit for the delay between two attempts
+    pub max_delay: Duration,
+    /// Retry [ResilientErrorClass::Transient] errors
+    pub retry_transient: bool,
+    /// Retry [ResilientErrorClass::Backend] errors, e.g. for backends that don't classify their errors
+    pub retry_backend: bool,
+    /// Shared limit for retries, e.g. of all wrappers of a backend, `None` for no limit
+    pub budget: Option<RetryBudget>,
+}
+
+impl Default for ResilientRetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(50),
+            max_delay: Duration::from_secs(2),
+            retry_transient: true,
+            retry_backend: false,
+            budget: None,
+        }
+    }
+}
+
+impl ResilientRetryPolicy {
+    /// A policy that never retries
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_retryable(&self, class: ResilientErrorClass) -> bool {
+        match class {
+            ResilientErrorClass::Transient => self.retry_transient,
+            ResilientErrorClass::Backend => self.retry_backend,
+            ResilientErrorClass::Caller => false,
+        }
+    }
+
+    /// The (jittered) delay before the given retry, starting with `1` for the first retry
+    pub fn delay(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        let delay = self.base_delay.saturating_mul(factor).min(self.max_delay);
+        let millis = delay.as_millis() as u64;
+        if millis == 0 {
+            return delay;
+        }
+        Duration::from_millis(rand::thread_rng().gen_range(0..=millis))
+    }
+}
+
+/// When the circuit breaker of [StorageResilient] opens and for how long.
+#[derive(Debug, Clone)]
+pub struct CircuitBreakerPolicy {
+    /// The number of recent attempts the failure rate is calculated over
+    pub window: usize,
+    /// The breaker doesn't open before this many attempts are in the window
+    pub min_calls: usize,
+    /// Opens the breaker once this share of the attempts in the window failed, `0.0 < failure_rate <= 1.0`
+    pub failure_rate: f64,
+    /// How long calls fail fast, before a single probe call is let through
+    pub open_for: Duration,
+}
+
+impl Default for CircuitBreakerPolicy {
+    fn default() -> Self {
+        Self {
+            window: 20,
+            min_calls: 10,
+            failure_rate: 0.5,
+            open_for: Duration::from_secs(30),
+        }
+    }
+}
+
+/// The state of the circuit breaker, e.g. for health checks, see [StorageResilient::circuit_state].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Calls go to the backend
+    Closed,
+    /// Calls fail fast with [StorageError::CircuitOpen] until `until`
+    Open { until: DateTime<Utc> },
+    /// The next call probes the backend and closes or reopens the breaker
+    HalfOpen,
+}
+
+#[derive(Debug, Default)]
+struct Breaker {
+    /// `true` for failed attempts, newest last
+    outcomes: VecDeque<bool>,
+    open_until: Option<DateTime<Utc>>,
+    /// When the current probe started, a probe that never finishes, e.g. because it was dropped,
+    /// is replaced after [CircuitBreakerPolicy::open_for]
+    probe_started: Option<DateTime<Utc>>,
+}
+
+/// Wraps any [Storage], retries failed calls per [ResilientRetryPolicy],
+/// and fails fast via a circuit breaker while the backend keeps failing.
+///
+/// Every attempt counts towards the failure rate, [ResilientErrorClass::Caller] errors count as successes.
+/// Once the breaker opens, calls fail with [StorageError::CircuitOpen] for [CircuitBreakerPolicy::open_for],
+/// then a single probe call decides whether it closes again.
+///
+/// Note: Retries repeat the whole operation, a `lock` or `create` that failed after reaching the backend
+/// might fail with e.g. [LockResult::AlreadyLocked] on retry.
+#[derive(Debug)]
+pub struct StorageResilient<ITEM: StorageItem, S: Storage<ITEM>>
+where
+    ITEM: Send,
+{
+    storage: S,
+    retry_policy: ResilientRetryPolicy,
+    breaker_policy: CircuitBreakerPolicy,
+    breaker: Mutex<Breaker>,
+    clock: Arc<dyn Clock>,
+    item_type: PhantomData<ITEM>,
+}
+
+impl<ITEM: StorageItem + Send, S: Storage<ITEM>> StorageResilient<ITEM, S> {
+    pub fn new(storage: S) -> Self {
+        Self {
+            storage,
+            retry_policy: ResilientRetryPolicy::default(),
+            breaker_policy: CircuitBreakerPolicy::default(),
+            breaker: Mutex::new(Breaker::default()),
+            clock: Arc::new(SystemClock),
+            item_type: PhantomData,
+        }
+    }
+
+    pub fn set_retry_policy(&mut self, retry_policy: ResilientRetryPolicy) -> Result<()> {
+        if retry_policy.max_attempts == 0 {
+            return Err(eyre!("Retry policy needs at least 1 attempt"));
+        }
+        self.retry_policy = retry_policy;
+
+        Ok(())
+    }
+
+    pub fn set_breaker_policy(&mut self, breaker_policy: CircuitBreakerPolicy) -> Result<()> {
+        let CircuitBreakerPolicy {
+            window,
+            min_calls,
+            failure_rate,
+            ..
+        } = breaker_policy;
+        if min_calls == 0 || min_calls > window {
+            return Err(eyre!(
+                "Circuit breaker min_calls must be in 1..={window}, got {min_calls}"
+            ));
+        }
+        if !(failure_rate > 0.0 && failure_rate <= 1.0) {
+            return Err(eyre!(
+                "Circuit breaker failure_rate must be in (0.0, 1.0], got {failure_rate}"
+            ));
+        }
+        if chrono::Duration::from_std(breaker_policy.open_for).is_err() {
+            return Err(eyre!(
+                "Circuit breaker open_for is too long: {:?}",
+                breaker_policy.open_for
+            ));
+        }
+        self.breaker_policy = breaker_policy;
+        *self.breaker.lock().expect("can lock") = Breaker::default();
+
+        Ok(())
+    }
+
+    /// Where the time for opening and closing the breaker comes from, defaults to [SystemClock].
+    pub fn set_clock(&mut self, clock: impl Clock + 'static) -> Result<()> {
+        self.clock = Arc::new(clock);
+
+        Ok(())
+    }
+
+    pub fn circuit_state(&self) -> CircuitState {
+        let breaker = self.breaker.lock().expect("can lock");
+        match breaker.open_until {
+            None => CircuitState::Closed,
+            Some(until) if self.clock.now() < until => CircuitState::Open { until },
+            Some(_) => CircuitState::HalfOpen,
+        }
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    pub fn into_inner(self) -> S {
+        self.storage
+    }
+
+    /// Runs `f` until it succeeds, fails with an error the policy doesn't retry,
+    /// runs out of attempts, or the breaker opens.
+    async fn call<T, F, Fut>(&self, operation: StorageOperation, mut f: F) -> Result<T>
+    where
+        F: FnMut() -> Fut + Send,
+        Fut: Future<Output = Result<T>> + Send,
+    {
+        let mut attempt = 1;
+        loop {
+            self.admit(operation)?;
+            let r = f().await;
+            self.record_result(&r);
+            let e = match r {
+                Ok(t) => return Ok(t),
+                Err(e) => e,
+            };
+            let class = ResilientErrorClass::classify(&e);
+            if attempt >= self.retry_policy.max_attempts || !self.retry_policy.is_retryable(class) {
+                return Err(e);
+            }
+            if let Some(budget) = &self.retry_policy.budget {
+                if !budget.try_retry() {
+                    return Err(e.wrap_err(StorageError::RetryBudgetExhausted {
+                        operation: operation.to_string(),
+                    }));
+                }
+            }
+            let delay = self.retry_policy.delay(attempt);
+            tracing::warn!(
+                "{operation} failed (attempt {attempt}/{}), retrying in {delay:?} -> {e:?}",
+                self.retry_policy.max_attempts
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// Fails with [StorageError::CircuitOpen] while the breaker is open, or another call probes.
+    fn admit(&self, operation: StorageOperation) -> Result<()> {
+        let now = self.clock.now();
+        let mut breaker = self.breaker.lock().expect("can lock");
+        let Some(until) = breaker.open_until else {
+            return Ok(());
+        };
+        let open_for = chrono::Duration::from_std(self.breaker_policy.open_for)?;
+        let retry_at = match breaker.probe_started {
+            _ if now < until => until,
+            Some(started) if now < started + open_for => started + open_for,
+            _ => {
+                breaker.probe_started = Some(now);
+                return Ok(());
+            }
+        };
+
+        Err(StorageError::CircuitOpen {
+            operation,
+            retry_in: retry_at
+                .signed_duration_since(now)
+                .to_std()
+                .unwrap_or_default(),
+        }
+        .into())
+    }
+
+    fn record_result<T>(&self, r: &Result<T>) {
+        let failed = match r {
+            Ok(_) => false,
+            Err(e) => ResilientErrorClass::classify(e) != ResilientErrorClass::Caller,
+        };
+        self.record(failed);
+    }
+
+    fn record(&self, failed: bool) {
+        let now = self.clock.now();
+        let open_until = now
+            + chrono::Duration::from_std(self.breaker_policy.open_for)
+                .expect("checked by set_breaker_policy");
+        let mut breaker = self.breaker.lock().expect("can lock");
+        if breaker.open_until.is_some() {
+            // only the probe gets through while the breaker isn't closed
+            breaker.probe_started = None;
+            if failed {
+                breaker.open_until = Some(open_until);
+            } else {
+                tracing::info!("Circuit breaker closed");
+                breaker.open_until = None;
+                breaker.outcomes.clear();
+            }
+            return;
+        }
+        breaker.outcomes.push_back(failed);
+        while breaker.outcomes.len() > self.breaker_policy.window {
+            breaker.outcomes.pop_front();
+        }
+        let calls = breaker.outcomes.len();
+        let failures = breaker.outcomes.iter().filter(|f| **f).count();
+        if calls >= self.breaker_policy.min_calls
+            && failures as f64 >= self.breaker_policy.failure_rate * calls as f64
+        {
+            tracing::warn!("Circuit breaker opened, {failures} of {calls} attempts failed");
+            breaker.open_until = Some(open_until);
+            breaker.outcomes.clear();
+        }
+    }
+}
+
+#[async_trait]
+impl<ITEM: StorageItem + Send, S: Storage<ITEM>> Storage<ITEM> for StorageResilient<ITEM, S> {
+    async fn ensure_storage_exists(&mut self) -> Result<()> {
+        self.storage.ensure_storage_exists().await
+    }
+
+    fn capabilities(&self) -> crate::Capabilities {
+        self.storage.capabilities()
+    }
+
+    async fn create(&self) -> Result<ITEM::ID> {
+        self.call(StorageOperation::Create, || self.storage.create())
+            .await
+    }
+
+    async fn exists(&self, id: &ITEM::ID) -> Result<bool> {
+        self.call(StorageOperation::Exists, || self.storage.exists(id))
+            .await
+    }
+
+    async fn exists_with(&self, id: &ITEM::ID, options: &OpOptions) -> Result<bool> {
+        self.call(StorageOperation::Exists, || {
+            self.storage.exists_with(id, options)
+        })
+        .await
+    }
+
+    async fn load(&self, id: &ITEM::ID) -> Result<ITEM> {
+        self.call(StorageOperation::Load, || self.storage.load(id))
+            .await
+    }
+
+    async fn load_with(&self, id: &ITEM::ID, options: &OpOptions) -> Result<ITEM> {
+        self.call(StorageOperation::Load, || {
+            self.storage.load_with(id, options)
+        })
+        .await
+    }
+
+    async fn load_many(&self, ids: &[ITEM::ID]) -> Result<Vec<Option<ITEM>>> {
+        self.call(StorageOperation::LoadMany, || self.storage.load_many(ids))
+            .await
+    }
+
+    async fn prefetch(&self, ids: &[ITEM::ID]) -> Result<()> {
+        self.call(StorageOperation::Prefetch, || self.storage.prefetch(ids))
+            .await
+    }
+
+    async fn save(&self, id: &ITEM::ID, item: &ITEM, lock: &StorageLock) -> Result<()> {
+        self.call(StorageOperation::Save, || self.storage.save(id, item, lock))
+            .await
+    }
+
+    async fn lock(&self, id: &ITEM::ID, who: &str) -> Result<LockResult<ITEM>> {
+        self.call(StorageOperation::Lock, || self.storage.lock(id, who))
+            .await
+    }
+
+    async fn unlock(&self, id: &ITEM::ID, lock: StorageLock) -> Result<()> {
+        self.call(StorageOperation::Unlock, || {
+            self.storage.unlock(id, lock.duplicate())
+        })
+        .await
+    }
+
+    async fn handoff_lock(
+        &self,
+        id: &ITEM::ID,
+        lock: StorageLock,
+        new_who: &str,
+    ) -> Result<StorageLock> {
+        self.call(StorageOperation::HandoffLock, || {
+            self.storage.handoff_lock(id, lock.duplicate(), new_who)
+        })
+        .await
+    }
+
+    async fn save_and_unlock(&self, id: &ITEM::ID, item: &ITEM, lock: StorageLock) -> Result<()> {
+        self.call(StorageOperation::SaveAndUnlock, || {
+            self.storage.save_and_unlock(id, item, lock.duplicate())
+        })
+        .await
+    }
+
+    async fn load_raw(&self, id: &ITEM::ID) -> Result<Vec<u8>> {
+        self.call(StorageOperation::LoadRaw, || self.storage.load_raw(id))
+            .await
+    }
+
+    async fn save_raw(&self, id: &ITEM::ID, data: &[u8], lock: &StorageLock) -> Result<()> {
+        self.call(StorageOperation::SaveRaw, || {
+            self.storage.save_raw(id, data, lock)
+        })
+        .await
+    }
+
+    async fn put_blob(
+        &self,
+        id: &ITEM::ID,
+        name: &str,
+        data: &[u8],
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.call(StorageOperation::PutBlob, || {
+            self.storage.put_blob(id, name, data, lock)
+        })
+        .await
+    }
+
+    async fn get_blob(&self, id: &ITEM::ID, name: &str) -> Result<Option<Vec<u8>>> {
+        self.call(StorageOperation::GetBlob, || {
+            self.storage.get_blob(id, name)
+        })
+        .await
+    }
+
+    async fn list_blobs(&self, id: &ITEM::ID) -> Result<Vec<String>> {
+        self.call(StorageOperation::ListBlobs, || self.storage.list_blobs(id))
+            .await
+    }
+
+    async fn put_meta(&self, key: &str, value: &[u8]) -> Result<()> {
+        self.call(StorageOperation::PutMeta, || {
+            self.storage.put_meta(key, value)
+        })
+        .await
+    }
+
+    async fn get_meta(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.call(StorageOperation::GetMeta, || self.storage.get_meta(key))
+            .await
+    }
+
+    async fn add_alias(&self, alias: &ITEM::ID, canonical: &ITEM::ID) -> Result<()> {
+        self.call(StorageOperation::AddAlias, || {
+            self.storage.add_alias(alias, canonical)
+        })
+        .await
+    }
+
+    async fn remove_alias(&self, alias: &ITEM::ID) -> Result<()> {
+        self.call(StorageOperation::RemoveAlias, || {
+            self.storage.remove_alias(alias)
+        })
+        .await
+    }
+
+    async fn resolve_alias(&self, id: &ITEM::ID) -> Result<ITEM::ID> {
+        self.call(StorageOperation::ResolveAlias, || {
+            self.storage.resolve_alias(id)
+        })
+        .await
+    }
+
+    async fn add_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.call(StorageOperation::AddLink, || {
+            self.storage.add_link(from, relation, to, lock)
+        })
+        .await
+    }
+
+    async fn remove_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.call(StorageOperation::RemoveLink, || {
+            self.storage.remove_link(from, relation, to, lock)
+        })
+        .await
+    }
+
+    async fn links_of(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        self.call(StorageOperation::LinksOf, || {
+            self.storage.links_of(id, relation)
+        })
+        .await
+    }
+
+    async fn linked_from(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        self.call(StorageOperation::LinkedFrom, || {
+            self.storage.linked_from(id, relation)
+        })
+        .await
+    }
+
+    async fn force_unlock(&self, id: &ITEM::ID) -> Result<Option<StorageLock>> {
+        self.call(StorageOperation::ForceUnlock, || {
+            self.storage.force_unlock(id)
+        })
+        .await
+    }
+
+    async fn force_unlock_if_held(
+        &self,
+        id: &ITEM::ID,
+        who: &str,
+        when: DateTime<Utc>,
+    ) -> Result<bool> {
+        self.call(StorageOperation::ForceUnlock, || {
+            self.storage.force_unlock_if_held(id, who, when)
+        })
+        .await
+    }
+
+    async fn remove_orphaned_locks(&self, max_age: Duration) -> Result<usize> {
+        self.call(StorageOperation::RemoveOrphanedLocks, || {
+            self.storage.remove_orphaned_locks(max_age)
+        })
+        .await
+    }
+
+    async fn verify_lock(&self, id: &ITEM::ID, lock: &StorageLock) -> Result<bool> {
+        self.call(StorageOperation::VerifyLock, || {
+            self.storage.verify_lock(id, lock)
+        })
+        .await
+    }
+
+    async fn verify_lock_with(
+        &self,
+        id: &ITEM::ID,
+        lock: &StorageLock,
+        options: &OpOptions,
+    ) -> Result<bool> {
+        self.call(StorageOperation::VerifyLock, || {
+            self.storage.verify_lock_with(id, lock, options)
+        })
+        .await
+    }
+
+    async fn all_ids(&self) -> Result<Vec<ITEM::ID>> {
+        self.call(StorageOperation::AllIds, || self.storage.all_ids())
+            .await
+    }
+
+    async fn scan_ids(&self, start: Option<&str>, limit: Option<usize>) -> Result<Page<ITEM::ID>> {
+        self.call(StorageOperation::ScanIds, || {
+            self.storage.scan_ids(start, limit)
+        })
+        .await
+    }
+
+    async fn scan_ids_with_prefix(
+        &self,
+        prefix: &str,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.call(StorageOperation::ScanIds, || {
+            self.storage.scan_ids_with_prefix(prefix, start, limit)
+        })
+        .await
+    }
+
+    async fn scan_ids_filtered(
+        &self,
+        filter: &crate::IdFilter,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.call(StorageOperation::ScanIds, || {
+            self.storage.scan_ids_filtered(filter, start, limit)
+        })
+        .await
+    }
+
+    async fn scan_ids_between(
+        &self,
+        from: &ITEM::ID,
+        to: &ITEM::ID,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.call(StorageOperation::ScanIds, || {
+            self.storage.scan_ids_between(from, to, start, limit)
+        })
+        .await
+    }
+
+    async fn list(
+        &self,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<crate::ItemSummary<ITEM::ID>>> {
+        self.call(StorageOperation::List, || self.storage.list(start, limit))
+            .await
+    }
+
+    async fn item_size(&self, id: &ITEM::ID) -> Result<u64> {
+        self.call(StorageOperation::ItemSize, || self.storage.item_size(id))
+            .await
+    }
+
+    async fn estimate_cost(&self) -> Result<crate::StorageCost> {
+        self.call(StorageOperation::EstimateCost, || {
+            self.storage.estimate_cost()
+        })
+        .await
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        self.call(StorageOperation::Shutdown, || self.storage.shutdown())
+            .await
+    }
+
+    async fn fsck(&self, options: &crate::FsckOptions) -> Result<crate::FsckReport> {
+        self.call(StorageOperation::Fsck, || self.storage.fsck(options))
+            .await
+    }
+
+    async fn display_lock(&self, id: &ITEM::ID) -> Result<String> {
+        self.call(StorageOperation::DisplayLock, || {
+            self.storage.display_lock(id)
+        })
+        .await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_highest_seen_id(&self) -> Option<ITEM::ID> {
+        self.storage.metadata_highest_seen_id().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_item_count(&self) -> Option<u64> {
+        self.storage.metadata_item_count().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_total_bytes(&self) -> Option<u64> {
+        self.storage.metadata_total_bytes().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_last_write(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.storage.metadata_last_write().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_lock_stats(&self) -> crate::LockStats {
+        self.storage.metadata_lock_stats().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_snapshot(&self) -> crate::MetadataSnapshot {
+        self.storage.metadata_snapshot().await
+    }
+
+    /// Wipes are never retried, they might have removed items before failing.
+    #[cfg(feature = "wipe")]
+    async fn wipe(
+        &self,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        self.admit(StorageOperation::Wipe)?;
+        let r = self.storage.wipe(options, progress, cancel).await;
+        self.record_result(&r);
+
+        r
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn wipe_matching(
+        &self,
+        filter: &crate::IdFilter,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        self.admit(StorageOperation::WipeMatching)?;
+        let r = self
+            .storage
+            .wipe_matching(filter, options, progress, cancel)
+            .await;
+        self.record_result(&r);
+
+        r
+    }
+
+    #[cfg(feature = "wipe")]
+    async fn delete_many(
+        &self,
+        ids: &[ITEM::ID],
+        options: &crate::DeleteOptions,
+    ) -> Result<Vec<crate::DeleteOutcome>> {
+        self.call(StorageOperation::DeleteMany, || {
+            self.storage.delete_many(ids, options)
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_item::TestItem;
+    use crate::CircuitBreakerPolicy;
+    use crate::CircuitState;
+    use crate::MockClock;
+    use crate::MockResponse;
+    use crate::ResilientRetryPolicy;
+    use crate::Storage;
+    use crate::StorageError;
+    use crate::StorageMock;
+    use crate::StorageOperation;
+    use crate::StorageResilient;
+    use color_eyre::Result;
+    use std::time::Duration;
+
+    fn failure(transient: bool) -> MockResponse<TestItem> {
+        MockResponse::Err(
+            StorageError::Backend {
+                operation: StorageOperation::Load,
+                transient,
+                message: String::from("down"),
+            }
+            .into(),
+        )
+    }
+
+    fn storage(clock: &MockClock) -> Result<StorageResilient<TestItem, StorageMock<TestItem>>> {
+        let mut storage = StorageResilient::new(StorageMock::<TestItem>::default());
+        storage.set_clock(clock.clone())?;
+        storage.set_retry_policy(ResilientRetryPolicy {
+            base_delay: Duration::ZERO,
+            ..Default::default()
+        })?;
+        storage.set_breaker_policy(CircuitBreakerPolicy {
+            window: 4,
+            min_calls: 3,
+            failure_rate: 0.5,
+            open_for: Duration::from_secs(10),
+        })?;
+
+        Ok(storage)
+    }
+
+    #[tokio::test]
+    async fn it_retries_and_opens_the_breaker() -> Result<()> {
+        let clock = MockClock::default();
+        let storage = storage(&clock)?;
+        let id = String::from("1");
+
+        // transient failures are retried, permanent ones aren't
+        storage
+            .storage()
+            .script(StorageOperation::Load, failure(true));
+        storage.load(&id).await?;
+        storage
+            .storage()
+            .script(StorageOperation::Load, failure(false));
+        assert!(storage.load(&id).await.is_err());
+        assert_eq!(3, storage.storage().calls().len());
+        assert!(matches!(storage.circuit_state(), CircuitState::Open { .. }));
+
+        // fails fast while open
+        let e = storage.load(&id).await.unwrap_err();
+        assert!(matches!(
+            e.downcast_ref::<StorageError>(),
+            Some(StorageError::CircuitOpen { .. })
+        ));
+        assert_eq!(3, storage.storage().calls().len());
+
+        // a successful probe closes it
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(CircuitState::HalfOpen, storage.circuit_state());
+        storage.load(&id).await?;
+        assert_eq!(CircuitState::Closed, storage.circuit_state());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_reopens_the_breaker_after_a_failed_probe() -> Result<()> {
+        let clock = MockClock::default();
+        let storage = storage(&clock)?;
+        let id = String::from("1");
+        for _ in 0..3 {
+            storage
+                .storage()
+                .script(StorageOperation::Load, failure(false));
+            assert!(storage.load(&id).await.is_err());
+        }
+        assert!(matches!(storage.circuit_state(), CircuitState::Open { .. }));
+
+        clock.advance(Duration::from_secs(10));
+        storage
+            .storage()
+            .script(StorageOperation::Load, failure(true));
+        assert!(storage.load(&id).await.is_err());
+        // the probe isn't retried, and the breaker is open for another `open_for`
+        assert_eq!(4, storage.storage().calls().len());
+        assert!(matches!(storage.circuit_state(), CircuitState::Open { .. }));
+        clock.advance(Duration::from_secs(9));
+        assert!(matches!(storage.circuit_state(), CircuitState::Open { .. }));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,246 @@
+use crate::LockResult;
+use crate::Storage;
+use crate::StorageError;
+use crate::StorageItem;
+use crate::StorageLock;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// A lock held by a session, with the item staged for [StorageSession::save_all].
+struct Held<ITEM: StorageItem> {
+    id: ITEM::ID,
+    lock: StorageLock,
+    staged: Option<ITEM>,
+}
+
+/// A view of a storage for one lock holder, e.g. one request, see [Storage::session].
+///
+/// Locks taken via the session are tracked and released together by
+/// [StorageSession::save_all] or [StorageSession::release_all] at the end of the request.
+/// Locks still held when the session is dropped are only logged, they can't be released without `await`.
+pub struct StorageSession<'a, ITEM: StorageItem + Send> {
+    storage: &'a dyn Storage<ITEM>,
+    who: String,
+    held: Mutex<BTreeMap<String, Held<ITEM>>>,
+}
+
+impl<ITEM: StorageItem + Send> std::fmt::Debug for StorageSession<'_, ITEM> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StorageSession")
+            .field("who", &self.who)
+            .field("held", &self.held())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a, ITEM: StorageItem + Send> StorageSession<'a, ITEM> {
+    /// Use [Storage::session], unless `storage` is a trait object.
+    pub fn new(storage: &'a dyn Storage<ITEM>, who: &str) -> Self {
+        Self {
+            storage,
+            who: who.to_string(),
+            held: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn who(&self) -> &str {
+        &self.who
+    }
+
+    /// The ids locked by this session.
+    pub fn held(&self) -> Vec<ITEM::ID> {
+        self.held
+            .lock()
+            .expect("can lock")
+            .values()
+            .map(|held| held.id.clone())
+            .collect()
+    }
+
+    pub fn is_held(&self, id: &ITEM::ID) -> bool {
+        self.held
+            .lock()
+            .expect("can lock")
+            .contains_key(&id.to_string())
+    }
+
+    /// Locks the item as [StorageSession::who] and returns it.
+    ///
+    /// Fails with [StorageError::AlreadyLocked] if someone else holds it or this session already does.
+    pub async fn lock(&self, id: &ITEM::ID) -> Result<ITEM> {
+        if self.is_held(id) {
+            return Err(StorageError::AlreadyLocked {
+                id: id.to_string(),
+                who: self.who.clone(),
+            }
+            .into());
+        }
+        match self.storage.lock(id, &self.who).await? {
+            LockResult::Success { lock, item } => {
+                let held = Held {
+                    id: id.clone(),
+                    lock,
+                    staged: None,
+                };
+                self.held
+                    .lock()
+                    .expect("can lock")
+                    .insert(id.to_string(), held);
+                Ok(item)
+            }
+            LockResult::AlreadyLocked { who, .. } => Err(StorageError::AlreadyLocked {
+                id: id.to_string(),
+                who,
+            }
+            .into()),
+        }
+    }
+
+    /// Keeps `item` to be saved by [StorageSession::save_all], replacing an item staged before.
+    pub fn stage(&self, id: &ITEM::ID, item: ITEM) -> Result<()> {
+        let mut held = self.held.lock().expect("can lock");
+        let Some(held) = held.get_mut(&id.to_string()) else {
+            return Err(eyre!("{id} is not locked by session {}", self.who));
+        };
+        held.staged = Some(item);
+
+        Ok(())
+    }
+
+    /// Saves the item right away, keeping the lock, and drops an item staged before.
+    pub async fn save(&self, id: &ITEM::ID, item: &ITEM) -> Result<()> {
+        let lock = self.lock_of(id)?;
+        self.storage.save(id, item, &lock).await?;
+        if let Some(held) = self.held.lock().expect("can lock").get_mut(&id.to_string()) {
+            held.staged = None;
+        }
+
+        Ok(())
+    }
+
+    /// Releases the lock without saving, dropping a staged item.
+    pub async fn unlock(&self, id: &ITEM::ID) -> Result<()> {
+        let held = self.take(id)?;
+        self.storage.unlock(id, held.lock).await
+    }
+
+    /// Saves all staged items and releases all locks, returning how many items were saved.
+    ///
+    /// Keeps going when an item fails and fails at the end, listing the ids.
+    pub async fn save_all(&self) -> Result<usize> {
+        self.finish(true).await
+    }
+
+    /// Releases all locks without saving, e.g. after a failed request.
+    pub async fn release_all(&self) -> Result<()> {
+        self.finish(false).await.map(|_| ())
+    }
+
+    async fn finish(&self, save: bool) -> Result<usize> {
+        let held = std::mem::take(&mut *self.held.lock().expect("can lock"));
+        let mut saved = 0;
+        let mut failed = Vec::new();
+        for held in held.into_values() {
+            let result = match held.staged.as_ref().filter(|_| save) {
+                Some(item) => {
+                    self.storage
+                        .save_and_unlock(&held.id, item, held.lock)
+                        .await
+                }
+                None => self.storage.unlock(&held.id, held.lock).await,
+            };
+            match result {
+                Ok(()) if save && held.staged.is_some() => saved += 1,
+                Ok(()) => {}
+                Err(e) => {
+                    tracing::warn!("Session {} can't release {} -> {e:?}", self.who, held.id);
+                    failed.push(held.id.to_string());
+                }
+            }
+        }
+        if !failed.is_empty() {
+            return Err(eyre!("Session {} failed to release {failed:?}", self.who));
+        }
+
+        Ok(saved)
+    }
+
+    fn lock_of(&self, id: &ITEM::ID) -> Result<StorageLock> {
+        self.held
+            .lock()
+            .expect("can lock")
+            .get(&id.to_string())
+            .map(|held| held.lock.duplicate())
+            .ok_or_else(|| eyre!("{id} is not locked by session {}", self.who))
+    }
+
+    fn take(&self, id: &ITEM::ID) -> Result<Held<ITEM>> {
+        self.held
+            .lock()
+            .expect("can lock")
+            .remove(&id.to_string())
+            .ok_or_else(|| eyre!("{id} is not locked by session {}", self.who))
+    }
+}
+
+impl<ITEM: StorageItem + Send> Drop for StorageSession<'_, ITEM> {
+    fn drop(&mut self) {
+        let held = self
+            .held
+            .get_mut()
+            .map(|held| held.len())
+            .unwrap_or_default();
+        if held > 0 {
+            tracing::warn!(
+                "Session {} dropped with {held} locks held, use save_all, or release_all",
+                self.who
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_item::TestItem;
+    use crate::LockResult;
+    use crate::Storage;
+    use crate::StorageError;
+    use crate::StorageMemory;
+    use color_eyre::Result;
+
+    #[tokio::test]
+    async fn it_releases_session_locks() -> Result<()> {
+        let storage = StorageMemory::<TestItem>::default();
+        let a = String::from("a");
+        let b = String::from("b");
+
+        let session = storage.session("request-1");
+        let mut item = session.lock(&a).await?;
+        item.count += 1;
+        session.stage(&a, item)?;
+        session.lock(&b).await?;
+        assert!(matches!(
+            session.lock(&a).await.unwrap_err().downcast_ref(),
+            Some(StorageError::AlreadyLocked { .. })
+        ));
+        assert!(matches!(
+            storage.lock(&a, "request-2").await?,
+            LockResult::AlreadyLocked { who, .. } if who == "request-1"
+        ));
+        assert_eq!(1, session.save_all().await?);
+        assert!(session.held().is_empty());
+        assert_eq!(TestItem { count: 1 }, storage.load(&a).await?);
+
+        let session = storage.session("request-2");
+        let mut item = session.lock(&a).await?;
+        item.count += 1;
+        session.stage(&a, item)?;
+        session.release_all().await?;
+        assert_eq!(TestItem { count: 1 }, storage.load(&a).await?);
+        assert!(storage.lock(&a, "request-3").await?.success().is_ok());
+
+        Ok(())
+    }
+}
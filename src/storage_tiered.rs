@@ -0,0 +1,696 @@
+use crate::storage_item::deserialize_stored;
+use crate::Clock;
+use crate::LockResult;
+use crate::OpOptions;
+use crate::Page;
+use crate::Storage;
+use crate::StorageError;
+use crate::StorageItem;
+use crate::StorageLock;
+use crate::SystemClock;
+use async_trait::async_trait;
+use chrono::DateTime;
+use chrono::Utc;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use core::marker::PhantomData;
+use std::fmt::Write;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Used as lock holder.
+const WHO: &str = "tiered";
+const ARCHIVED: &[u8] = b"1";
+const NOT_ARCHIVED: &[u8] = b"";
+const PINNED: &[u8] = b"1";
+const NOT_PINNED: &[u8] = b"";
+const LIST_PAGE_SIZE: usize = 1000;
+
+/// The meta key marking `id` as archived, with everything meta keys don't allow escaped.
+fn archived_key(id: &str) -> String {
+    marker_key("tiered.cold.", id)
+}
+
+/// The meta key marking `id` as pinned, see [StorageTiered::pin].
+fn pinned_key(id: &str) -> String {
+    marker_key("tiered.pinned.", id)
+}
+
+fn marker_key(prefix: &str, id: &str) -> String {
+    let mut key = String::from(prefix);
+    for b in id.bytes() {
+        if b.is_ascii_alphanumeric() || b == b'.' || b == b'-' {
+            key.push(b as char);
+        } else {
+            let _ = write!(key, "_{b:02x}");
+        }
+    }
+    key
+}
+
+/// Keeps rarely used items in a cheaper `cold` storage, e.g. another table or directory,
+/// while they stay addressable through the `hot` one.
+///
+/// [StorageTiered::archive] moves an item to the cold storage and leaves a default item,
+/// and an archived marker in the meta entries of the hot storage behind.
+/// Loads of archived items are served from the cold storage,
+/// and locking one moves it back, so saves always go to the hot storage.
+///
+/// The hot storage stays the only lock authority and keeps all ids, blobs, links, and aliases.
+/// Lists and [Storage::estimate_cost] see the placeholders of archived items.
+///
+/// [StorageTiered::pin] keeps hot items, e.g. global config, out of the cold storage, regardless of age.
+///
+/// Note: Promoting leaves the cold copy behind, until the item is archived again.
+#[derive(Debug)]
+pub struct StorageTiered<ITEM: StorageItem, H: Storage<ITEM>, C: Storage<ITEM>>
+where
+    ITEM: Send,
+{
+    hot: H,
+    cold: C,
+    promote_on_read: bool,
+    clock: Arc<dyn Clock>,
+    item_type: PhantomData<ITEM>,
+}
+
+impl<ITEM: StorageItem + Send, H: Storage<ITEM>, C: Storage<ITEM>> StorageTiered<ITEM, H, C> {
+    pub fn new(hot: H, cold: C) -> Self {
+        Self {
+            hot,
+            cold,
+            promote_on_read: false,
+            clock: Arc::new(SystemClock),
+            item_type: PhantomData,
+        }
+    }
+
+    /// Loads of archived items move them back to the hot storage, unless they are locked.
+    /// Off by default, so reads never write.
+    pub fn set_promote_on_read(&mut self, promote_on_read: bool) -> Result<()> {
+        self.promote_on_read = promote_on_read;
+
+        Ok(())
+    }
+
+    /// Where the time for [StorageTiered::archive_untouched] comes from, defaults to [SystemClock].
+    pub fn set_clock(&mut self, clock: impl Clock + 'static) -> Result<()> {
+        self.clock = Arc::new(clock);
+
+        Ok(())
+    }
+
+    pub fn hot(&self) -> &H {
+        &self.hot
+    }
+
+    pub fn cold(&self) -> &C {
+        &self.cold
+    }
+
+    pub fn into_inner(self) -> (H, C) {
+        (self.hot, self.cold)
+    }
+
+    pub async fn is_archived(&self, id: &ITEM::ID) -> Result<bool> {
+        let marker = self.hot.get_meta(&archived_key(&id.to_string())).await?;
+        Ok(marker.as_deref() == Some(ARCHIVED))
+    }
+
+    pub async fn is_pinned(&self, id: &ITEM::ID) -> Result<bool> {
+        let marker = self.hot.get_meta(&pinned_key(&id.to_string())).await?;
+        Ok(marker.as_deref() == Some(PINNED))
+    }
+
+    /// Keeps the item in the hot storage, [StorageTiered::archive] skips pinned items.
+    ///
+    /// Archived items are promoted, unless locked, then the next lock does.
+    pub async fn pin(&self, id: &ITEM::ID) -> Result<()> {
+        self.hot
+            .put_meta(&pinned_key(&id.to_string()), PINNED)
+            .await?;
+        if !self.is_archived(id).await? {
+            return Ok(());
+        }
+        let LockResult::Success { lock, .. } = self.hot.lock(id, WHO).await? else {
+            return Ok(());
+        };
+        // someone else may have promoted it meanwhile
+        let promoted = match self.is_archived(id).await {
+            Ok(true) => self.promote(id, &lock).await.map(|_| ()),
+            r => r.map(|_| ()),
+        };
+        self.hot.unlock(id, lock).await?;
+
+        promoted
+    }
+
+    /// Lets [StorageTiered::archive] move the item again.
+    pub async fn unpin(&self, id: &ITEM::ID) -> Result<()> {
+        self.hot
+            .put_meta(&pinned_key(&id.to_string()), NOT_PINNED)
+            .await
+    }
+
+    /// Moves the item to the cold storage.
+    ///
+    /// Returns `false` if it is locked, pinned, or already archived, and fails if it doesn't exist.
+    pub async fn archive(&self, id: &ITEM::ID) -> Result<bool> {
+        if self.is_archived(id).await? || self.is_pinned(id).await? {
+            return Ok(false);
+        }
+        // locking would create missing items
+        if !self.hot.exists(id).await? {
+            return Err(StorageError::NotFound { id: id.to_string() }.into());
+        }
+        let LockResult::Success { lock, .. } = self.hot.lock(id, WHO).await? else {
+            return Ok(false);
+        };
+        // pinning promotes under the lock, so pins from before are seen here
+        match self.is_pinned(id).await {
+            Ok(false) => {}
+            pinned => {
+                self.hot.unlock(id, lock).await?;
+                return pinned.map(|_| false);
+            }
+        }
+        if let Err(e) = self.move_to_cold(id, &lock).await {
+            if let Err(unlock_e) = self.hot.unlock(id, lock).await {
+                tracing::warn!("Can't unlock {id} after failed archiving -> {unlock_e:?}");
+            }
+            return Err(e);
+        }
+        self.hot.save_and_unlock(id, &ITEM::default(), lock).await?;
+
+        Ok(true)
+    }
+
+    /// Archives all unlocked items not saved for `older_than` and returns how many were archived.
+    ///
+    /// Needs a hot storage that supports [Storage::list] and tracks save times.
+    pub async fn archive_untouched(&self, older_than: Duration) -> Result<usize> {
+        let cutoff = self.clock.now() - chrono::Duration::from_std(older_than)?;
+        let mut archived = 0;
+        let mut start = None;
+        loop {
+            let page = self
+                .hot
+                .list(start.as_deref(), Some(LIST_PAGE_SIZE))
+                .await?;
+            for summary in page.items {
+                let untouched = summary.modified.is_some_and(|m| m < cutoff);
+                if untouched && !summary.is_locked() && self.archive(&summary.id).await? {
+                    archived += 1;
+                }
+            }
+            start = page.cursor;
+            if start.is_none() {
+                return Ok(archived);
+            }
+        }
+    }
+
+    /// Copies the item to the cold storage and marks it archived while `lock` holds it.
+    async fn move_to_cold(&self, id: &ITEM::ID, lock: &StorageLock) -> Result<()> {
+        let data = self.hot.load_raw(id).await?;
+        let (cold_lock, _) = self.cold.lock(id, WHO).await?.success()?;
+        if let Err(e) = self.cold.save_raw(id, &data, &cold_lock).await {
+            let _ = self.cold.unlock(id, cold_lock).await;
+            return Err(e);
+        }
+        self.cold.unlock(id, cold_lock).await?;
+        if !self.hot.verify_lock(id, lock).await? {
+            return Err(eyre!("Lost the lock of {id} while archiving"));
+        }
+        self.hot
+            .put_meta(&archived_key(&id.to_string()), ARCHIVED)
+            .await
+    }
+
+    /// Moves the archived item back to the hot storage, while `lock` holds it.
+    async fn promote(&self, id: &ITEM::ID, lock: &StorageLock) -> Result<Vec<u8>> {
+        let data = self.cold.load_raw(id).await?;
+        self.hot.save_raw(id, &data, lock).await?;
+        self.hot
+            .put_meta(&archived_key(&id.to_string()), NOT_ARCHIVED)
+            .await?;
+        tracing::debug!("Promoted {id} from the cold storage");
+
+        Ok(data)
+    }
+
+    /// The data of an archived item, promoted if [StorageTiered::set_promote_on_read] is on,
+    /// and nobody holds the lock.
+    async fn load_archived(&self, id: &ITEM::ID) -> Result<Vec<u8>> {
+        if !self.promote_on_read {
+            return self.cold.load_raw(id).await;
+        }
+        let LockResult::Success { lock, .. } = self.hot.lock(id, WHO).await? else {
+            return self.cold.load_raw(id).await;
+        };
+        // someone else may have promoted it meanwhile
+        let data = if self.is_archived(id).await? {
+            self.promote(id, &lock).await
+        } else {
+            self.hot.load_raw(id).await
+        };
+        self.hot.unlock(id, lock).await?;
+
+        data
+    }
+}
+
+#[async_trait]
+impl<ITEM: StorageItem + Send, H: Storage<ITEM>, C: Storage<ITEM>> Storage<ITEM>
+    for StorageTiered<ITEM, H, C>
+{
+    async fn ensure_storage_exists(&mut self) -> Result<()> {
+        self.hot.ensure_storage_exists().await?;
+        self.cold.ensure_storage_exists().await
+    }
+
+    fn capabilities(&self) -> crate::Capabilities {
+        self.hot.capabilities()
+    }
+
+    async fn create(&self) -> Result<ITEM::ID> {
+        self.hot.create().await
+    }
+
+    async fn exists(&self, id: &ITEM::ID) -> Result<bool> {
+        self.hot.exists(id).await
+    }
+
+    async fn exists_with(&self, id: &ITEM::ID, options: &OpOptions) -> Result<bool> {
+        self.hot.exists_with(id, options).await
+    }
+
+    async fn load(&self, id: &ITEM::ID) -> Result<ITEM> {
+        if self.is_archived(id).await? {
+            return deserialize_stored::<ITEM>(id, &self.load_archived(id).await?);
+        }
+        self.hot.load(id).await
+    }
+
+    async fn load_with(&self, id: &ITEM::ID, options: &OpOptions) -> Result<ITEM> {
+        if self.is_archived(id).await? {
+            return deserialize_stored::<ITEM>(id, &self.load_archived(id).await?);
+        }
+        self.hot.load_with(id, options).await
+    }
+
+    async fn load_many(&self, ids: &[ITEM::ID]) -> Result<Vec<Option<ITEM>>> {
+        let mut items = self.hot.load_many(ids).await?;
+        for (id, item) in ids.iter().zip(items.iter_mut()) {
+            if item.is_some() && self.is_archived(id).await? {
+                *item = Some(deserialize_stored::<ITEM>(
+                    id,
+                    &self.load_archived(id).await?,
+                )?);
+            }
+        }
+
+        Ok(items)
+    }
+
+    async fn prefetch(&self, ids: &[ITEM::ID]) -> Result<()> {
+        self.hot.prefetch(ids).await
+    }
+
+    async fn save(&self, id: &ITEM::ID, item: &ITEM, lock: &StorageLock) -> Result<()> {
+        self.hot.save(id, item, lock).await
+    }
+
+    /// Moves archived items back to the hot storage.
+    async fn lock(&self, id: &ITEM::ID, who: &str) -> Result<LockResult<ITEM>> {
+        let result = self.hot.lock(id, who).await?;
+        let LockResult::Success { lock, item } = result else {
+            return Ok(result);
+        };
+        let promoted = match self.is_archived(id).await {
+            Ok(false) => return Ok(LockResult::Success { lock, item }),
+            Ok(true) => self
+                .promote(id, &lock)
+                .await
+                .and_then(|data| deserialize_stored::<ITEM>(id, &data)),
+            Err(e) => Err(e),
+        };
+        match promoted {
+            Ok(item) => Ok(LockResult::Success { lock, item }),
+            Err(e) => {
+                if let Err(unlock_e) = self.hot.unlock(id, lock).await {
+                    tracing::warn!("Can't unlock {id} after failed promotion -> {unlock_e:?}");
+                }
+                Err(e)
+            }
+        }
+    }
+
+    async fn unlock(&self, id: &ITEM::ID, lock: StorageLock) -> Result<()> {
+        self.hot.unlock(id, lock).await
+    }
+
+    async fn handoff_lock(
+        &self,
+        id: &ITEM::ID,
+        lock: StorageLock,
+        new_who: &str,
+    ) -> Result<StorageLock> {
+        self.hot.handoff_lock(id, lock, new_who).await
+    }
+
+    async fn save_and_unlock(&self, id: &ITEM::ID, item: &ITEM, lock: StorageLock) -> Result<()> {
+        self.hot.save_and_unlock(id, item, lock).await
+    }
+
+    async fn load_raw(&self, id: &ITEM::ID) -> Result<Vec<u8>> {
+        if self.is_archived(id).await? {
+            return self.load_archived(id).await;
+        }
+        self.hot.load_raw(id).await
+    }
+
+    async fn save_raw(&self, id: &ITEM::ID, data: &[u8], lock: &StorageLock) -> Result<()> {
+        self.hot.save_raw(id, data, lock).await
+    }
+
+    async fn put_blob(
+        &self,
+        id: &ITEM::ID,
+        name: &str,
+        data: &[u8],
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.hot.put_blob(id, name, data, lock).await
+    }
+
+    async fn get_blob(&self, id: &ITEM::ID, name: &str) -> Result<Option<Vec<u8>>> {
+        self.hot.get_blob(id, name).await
+    }
+
+    async fn list_blobs(&self, id: &ITEM::ID) -> Result<Vec<String>> {
+        self.hot.list_blobs(id).await
+    }
+
+    async fn put_meta(&self, key: &str, value: &[u8]) -> Result<()> {
+        self.hot.put_meta(key, value).await
+    }
+
+    async fn get_meta(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.hot.get_meta(key).await
+    }
+
+    async fn add_alias(&self, alias: &ITEM::ID, canonical: &ITEM::ID) -> Result<()> {
+        self.hot.add_alias(alias, canonical).await
+    }
+
+    async fn remove_alias(&self, alias: &ITEM::ID) -> Result<()> {
+        self.hot.remove_alias(alias).await
+    }
+
+    async fn resolve_alias(&self, id: &ITEM::ID) -> Result<ITEM::ID> {
+        self.hot.resolve_alias(id).await
+    }
+
+    async fn add_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.hot.add_link(from, relation, to, lock).await
+    }
+
+    async fn remove_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.hot.remove_link(from, relation, to, lock).await
+    }
+
+    async fn links_of(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        self.hot.links_of(id, relation).await
+    }
+
+    async fn linked_from(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        self.hot.linked_from(id, relation).await
+    }
+
+    async fn force_unlock(&self, id: &ITEM::ID) -> Result<Option<StorageLock>> {
+        self.hot.force_unlock(id).await
+    }
+
+    async fn force_unlock_if_held(
+        &self,
+        id: &ITEM::ID,
+        who: &str,
+        when: DateTime<Utc>,
+    ) -> Result<bool> {
+        self.hot.force_unlock_if_held(id, who, when).await
+    }
+
+    async fn remove_orphaned_locks(&self, max_age: Duration) -> Result<usize> {
+        self.hot.remove_orphaned_locks(max_age).await
+    }
+
+    async fn verify_lock(&self, id: &ITEM::ID, lock: &StorageLock) -> Result<bool> {
+        self.hot.verify_lock(id, lock).await
+    }
+
+    async fn verify_lock_with(
+        &self,
+        id: &ITEM::ID,
+        lock: &StorageLock,
+        options: &OpOptions,
+    ) -> Result<bool> {
+        self.hot.verify_lock_with(id, lock, options).await
+    }
+
+    async fn all_ids(&self) -> Result<Vec<ITEM::ID>> {
+        self.hot.all_ids().await
+    }
+
+    async fn scan_ids(&self, start: Option<&str>, limit: Option<usize>) -> Result<Page<ITEM::ID>> {
+        self.hot.scan_ids(start, limit).await
+    }
+
+    async fn scan_ids_with_prefix(
+        &self,
+        prefix: &str,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.hot.scan_ids_with_prefix(prefix, start, limit).await
+    }
+
+    async fn scan_ids_filtered(
+        &self,
+        filter: &crate::IdFilter,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.hot.scan_ids_filtered(filter, start, limit).await
+    }
+
+    async fn scan_ids_between(
+        &self,
+        from: &ITEM::ID,
+        to: &ITEM::ID,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.hot.scan_ids_between(from, to, start, limit).await
+    }
+
+    async fn list(
+        &self,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<crate::ItemSummary<ITEM::ID>>> {
+        self.hot.list(start, limit).await
+    }
+
+    async fn item_size(&self, id: &ITEM::ID) -> Result<u64> {
+        if self.is_archived(id).await? {
+            return self.cold.item_size(id).await;
+        }
+        self.hot.item_size(id).await
+    }
+
+    async fn estimate_cost(&self) -> Result<crate::StorageCost> {
+        self.hot.estimate_cost().await
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        self.hot.shutdown().await?;
+        self.cold.shutdown().await
+    }
+
+    async fn display_lock(&self, id: &ITEM::ID) -> Result<String> {
+        self.hot.display_lock(id).await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_highest_seen_id(&self) -> Option<ITEM::ID> {
+        self.hot.metadata_highest_seen_id().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_item_count(&self) -> Option<u64> {
+        self.hot.metadata_item_count().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_total_bytes(&self) -> Option<u64> {
+        self.hot.metadata_total_bytes().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_last_write(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.hot.metadata_last_write().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_lock_stats(&self) -> crate::LockStats {
+        self.hot.metadata_lock_stats().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_snapshot(&self) -> crate::MetadataSnapshot {
+        self.hot.metadata_snapshot().await
+    }
+
+    /// Wipes both storages, the report is the one of the hot storage.
+    #[cfg(feature = "wipe")]
+    async fn wipe(
+        &self,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        let report = self.hot.wipe(options, progress, cancel).await?;
+        self.cold.wipe(options, None, cancel).await?;
+
+        Ok(report)
+    }
+
+    /// Wipes both storages, the report is the one of the hot storage.
+    #[cfg(feature = "wipe")]
+    async fn wipe_matching(
+        &self,
+        filter: &crate::IdFilter,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        let report = self
+            .hot
+            .wipe_matching(filter, options, progress, cancel)
+            .await?;
+        self.cold
+            .wipe_matching(filter, options, None, cancel)
+            .await?;
+
+        Ok(report)
+    }
+
+    /// Also removes the cold copies of deleted items.
+    #[cfg(feature = "wipe")]
+    async fn delete_many(
+        &self,
+        ids: &[ITEM::ID],
+        options: &crate::DeleteOptions,
+    ) -> Result<Vec<crate::DeleteOutcome>> {
+        let outcomes = self.hot.delete_many(ids, options).await?;
+        let deleted: Vec<ITEM::ID> = ids
+            .iter()
+            .zip(&outcomes)
+            .filter(|(_, outcome)| matches!(outcome, crate::DeleteOutcome::Deleted))
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in &deleted {
+            self.hot
+                .put_meta(&archived_key(&id.to_string()), NOT_ARCHIVED)
+                .await?;
+        }
+        let force = crate::DeleteOptions::default().with_force(true);
+        self.cold.delete_many(&deleted, &force).await?;
+
+        Ok(outcomes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_item::TestItem;
+    use crate::Storage;
+    use crate::StorageMemory;
+    use crate::StorageTiered;
+    use color_eyre::Result;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn it_archives_and_promotes() -> Result<()> {
+        let storage = StorageTiered::new(
+            StorageMemory::<TestItem>::default(),
+            StorageMemory::<TestItem>::default(),
+        );
+        let id = String::from("player:1");
+        let (lock, _) = storage.lock(&id, "test").await?.success()?;
+        storage
+            .save_and_unlock(&id, &TestItem { count: 42 }, lock)
+            .await?;
+
+        assert!(storage.archive(&id).await?);
+        assert!(!storage.archive(&id).await?);
+        assert!(storage.is_archived(&id).await?);
+        assert_eq!(TestItem::default(), storage.hot().load(&id).await?);
+        assert_eq!(TestItem { count: 42 }, storage.load(&id).await?);
+        assert!(storage.exists(&id).await?);
+
+        // locking promotes
+        let (lock, item) = storage.lock(&id, "test").await?.success()?;
+        assert_eq!(TestItem { count: 42 }, item);
+        assert!(!storage.is_archived(&id).await?);
+        storage
+            .save_and_unlock(&id, &TestItem { count: 43 }, lock)
+            .await?;
+        assert_eq!(TestItem { count: 43 }, storage.hot().load(&id).await?);
+
+        assert!(storage.archive(&String::from("missing")).await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_keeps_pinned_items_hot() -> Result<()> {
+        let storage = StorageTiered::new(
+            StorageMemory::<TestItem>::default(),
+            StorageMemory::<TestItem>::default(),
+        );
+        let id = String::from("config");
+        let (lock, _) = storage.lock(&id, "test").await?.success()?;
+        storage
+            .save_and_unlock(&id, &TestItem { count: 42 }, lock)
+            .await?;
+
+        storage.pin(&id).await?;
+        assert!(storage.is_pinned(&id).await?);
+        assert!(!storage.archive(&id).await?);
+        assert_eq!(0, storage.archive_untouched(Duration::ZERO).await?);
+        assert!(!storage.is_archived(&id).await?);
+
+        // pinning promotes
+        storage.unpin(&id).await?;
+        assert!(storage.archive(&id).await?);
+        storage.pin(&id).await?;
+        assert!(!storage.is_archived(&id).await?);
+        assert_eq!(TestItem { count: 42 }, storage.hot().load(&id).await?);
+
+        Ok(())
+    }
+}
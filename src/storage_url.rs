@@ -0,0 +1,185 @@
+use crate::Storage;
+use crate::StorageConfig;
+use crate::StorageDiskLayout;
+use crate::StorageItem;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// A parsed `scheme://location?key=value&...` url.
+#[derive(Debug)]
+struct StorageUrl<'a> {
+    scheme: &'a str,
+    location: &'a str,
+    params: HashMap<&'a str, &'a str>,
+}
+
+impl<'a> StorageUrl<'a> {
+    fn parse(url: &'a str) -> Result<Self> {
+        let (scheme, rest) = url
+            .split_once("://")
+            .ok_or_else(|| eyre!("Invalid storage url {url:?}, expected scheme://..."))?;
+        let (location, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let mut params = HashMap::new();
+        for param in query.split('&').filter(|p| !p.is_empty()) {
+            let (key, value) = param
+                .split_once('=')
+                .ok_or_else(|| eyre!("Invalid parameter {param:?} in storage url {url:?}"))?;
+            params.insert(key, value);
+        }
+
+        Ok(Self {
+            scheme,
+            location,
+            params,
+        })
+    }
+
+    fn take(&mut self, key: &str) -> Option<&'a str> {
+        self.params.remove(key)
+    }
+
+    fn take_bool(&mut self, key: &str) -> Result<Option<bool>> {
+        self.take(key)
+            .map(|v| {
+                v.parse()
+                    .map_err(|_| eyre!("Invalid {key} {v:?}, expected true or false"))
+            })
+            .transpose()
+    }
+
+    fn ensure_no_location(&self) -> Result<()> {
+        if !self.location.is_empty() {
+            return Err(eyre!("{}:// doesn't take a location", self.scheme));
+        }
+        Ok(())
+    }
+
+    /// Fails for parameters no one took.
+    fn ensure_all_taken(&self) -> Result<()> {
+        if let Some(key) = self.params.keys().next() {
+            return Err(eyre!("Unknown parameter {key:?} for {}://", self.scheme));
+        }
+        Ok(())
+    }
+}
+
+/// Creates a storage from a url, so the backend can be chosen by configuration.
+/// The url is turned into a [StorageConfig].
+///
+/// - `disk:///var/data/items?ext=json&layout=sharded&read_only=true`,
+///   relative paths like `disk://data/items` work too
+/// - `disk-packed:///var/data/items.log`
+/// - `dynamodb://table?endpoint=http://localhost:8000&region=eu-west-1&consistent_read=true`
+/// - `memory://`
+/// - `null://`
+///
+/// All parameters are optional. Values are used as is, they are not percent-decoded.
+/// Call [Storage::ensure_storage_exists] before using the storage.
+pub async fn from_url<ITEM: StorageItem + Send + 'static>(
+    url: &str,
+) -> Result<Box<dyn Storage<ITEM>>> {
+    config_from_url(url)?.build().await
+}
+
+fn config_from_url(url: &str) -> Result<StorageConfig> {
+    let mut url = StorageUrl::parse(url)?;
+    let config = match url.scheme {
+        "disk" => {
+            if url.location.is_empty() {
+                return Err(eyre!("disk:// needs a path"));
+            }
+            let layout = match url.take("layout") {
+                None | Some("flat") => StorageDiskLayout::Flat,
+                Some("sharded") => StorageDiskLayout::Sharded,
+                Some(o) => return Err(eyre!("Invalid layout {o:?}, expected flat or sharded")),
+            };
+            StorageConfig::Disk {
+                path: PathBuf::from(url.location),
+                extension: url.take("ext").unwrap_or("json").to_string(),
+                layout,
+                read_only: url.take_bool("read_only")?.unwrap_or_default(),
+            }
+        }
+        "disk-packed" => {
+            if url.location.is_empty() {
+                return Err(eyre!("disk-packed:// needs a path"));
+            }
+            StorageConfig::DiskPacked {
+                path: PathBuf::from(url.location),
+            }
+        }
+        "dynamodb" => {
+            if url.location.is_empty() {
+                return Err(eyre!("dynamodb:// needs a table name"));
+            }
+            StorageConfig::DynamoDb {
+                table: url.location.to_string(),
+                endpoint_url: url.take("endpoint").map(String::from),
+                region: url.take("region").map(String::from),
+                consistent_read: url.take_bool("consistent_read")?,
+            }
+        }
+        "memory" => {
+            url.ensure_no_location()?;
+            StorageConfig::Memory
+        }
+        "null" => {
+            url.ensure_no_location()?;
+            StorageConfig::Null
+        }
+        o => return Err(eyre!("Unknown storage scheme {o:?}")),
+    };
+    url.ensure_all_taken()?;
+
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::from_url;
+    use crate::StorageItem;
+    use color_eyre::Result;
+
+    #[derive(Default, Debug)]
+    struct TestItem {}
+
+    impl StorageItem for TestItem {
+        type ID = String;
+
+        fn serialize(&self) -> Result<Vec<u8>> {
+            Ok(b"{}".to_vec())
+        }
+        fn deserialize(_: &[u8]) -> Result<Self> {
+            Ok(Self {})
+        }
+    }
+
+    #[tokio::test]
+    async fn it_creates_storages_from_urls() -> Result<()> {
+        let mut storage = from_url::<TestItem>("memory://").await?;
+        storage.ensure_storage_exists().await?;
+        let id = storage.create().await?;
+        let (lock, item) = storage.lock(&id, "tester").await?.success()?;
+        storage.save_and_unlock(&id, &item, lock).await?;
+        assert!(storage.exists(&id).await?);
+
+        let mut storage =
+            from_url::<TestItem>("disk://data/test_url_items?ext=item&layout=sharded").await?;
+        storage.ensure_storage_exists().await?;
+
+        assert!(from_url::<TestItem>("memory://somewhere").await.is_err());
+        assert!(from_url::<TestItem>("disk://data?colour=blue")
+            .await
+            .is_err());
+        assert!(from_url::<TestItem>("disk://data?layout=round")
+            .await
+            .is_err());
+        assert!(from_url::<TestItem>("ftp://data").await.is_err());
+        assert!(from_url::<TestItem>("data/items").await.is_err());
+
+        let _ = std::fs::remove_dir_all("data/test_url_items");
+        Ok(())
+    }
+}
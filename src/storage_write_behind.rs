@@ -0,0 +1,883 @@
+use crate::storage_item::deserialize_item;
+use crate::storage_item::serialize_valid;
+use crate::LockResult;
+use crate::OpOptions;
+use crate::Page;
+use crate::Priority;
+use crate::Redacted;
+use crate::Storage;
+use crate::StorageItem;
+use crate::StorageLock;
+use async_trait::async_trait;
+use chrono::DateTime;
+use chrono::Utc;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use core::marker::PhantomData;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+const DEFAULT_MAX_PENDING: usize = 1000;
+
+/// The latest save of an item, not yet written to the inner storage.
+struct PendingWrite<ID> {
+    id: ID,
+    data: Vec<u8>,
+    lock: StorageLock,
+    /// Increased by every save, so a flush only removes what it wrote
+    generation: u64,
+    /// The highest priority of the coalesced saves
+    priority: Priority,
+}
+
+impl<ID: std::fmt::Debug> std::fmt::Debug for PendingWrite<ID> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PendingWrite")
+            .field("id", &self.id)
+            .field("data", &Redacted(&self.data))
+            .field("lock", &self.lock)
+            .field("generation", &self.generation)
+            .field("priority", &self.priority)
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+struct Shared<ITEM: StorageItem, S> {
+    storage: S,
+    pending: Mutex<BTreeMap<String, PendingWrite<ITEM::ID>>>,
+    /// Held while writing pending items, so an older save never overtakes a newer one
+    writing: tokio::sync::Mutex<()>,
+    item_type: PhantomData<ITEM>,
+}
+
+impl<ITEM: StorageItem + Send, S: Storage<ITEM>> Shared<ITEM, S> {
+    fn pending_data(&self, id: &ITEM::ID) -> Option<Vec<u8>> {
+        let pending = self.pending.lock().expect("can lock");
+        pending.get(&id.to_string()).map(|p| p.data.clone())
+    }
+
+    fn take_pending(&self, id: &ITEM::ID) -> Option<PendingWrite<ITEM::ID>> {
+        self.pending
+            .lock()
+            .expect("can lock")
+            .remove(&id.to_string())
+    }
+
+    /// Writes all pending items, foreground saves first, and returns the first error.
+    /// Failed items stay pending unless their lock was lost. Those are dropped and fail the flush too.
+    async fn flush(&self) -> Result<()> {
+        let _writing = self.writing.lock().await;
+        let mut snapshot: Vec<_> = self
+            .pending
+            .lock()
+            .expect("can lock")
+            .iter()
+            .map(|(key, p)| {
+                (
+                    key.clone(),
+                    p.id.clone(),
+                    p.data.clone(),
+                    p.lock.duplicate(),
+                    p.generation,
+                    p.priority,
+                )
+            })
+            .collect();
+        // stable, so items of the same priority stay in id order
+        snapshot.sort_by_key(|p| std::cmp::Reverse(p.5));
+
+        let mut result = Ok(());
+        for (key, id, data, lock, generation, _) in snapshot {
+            let written = self.storage.save_raw(&id, &data, &lock).await;
+            match written {
+                Ok(()) => self.remove_written(&key, generation),
+                Err(e) => {
+                    if let Ok(false) = self.storage.verify_lock(&id, &lock).await {
+                        tracing::error!(
+                            "Dropping pending write of {id}, the lock was lost -> {e:?}"
+                        );
+                        self.remove_written(&key, generation);
+                    } else {
+                        tracing::warn!("Writing {id} failed, retrying later -> {e:?}");
+                    }
+                    if result.is_ok() {
+                        result = Err(e);
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Writes the pending item, if any, before its lock is forced open.
+    /// It is dropped, if its lock doesn't hold the item anymore.
+    async fn write_pending_before_force_unlock(&self, id: &ITEM::ID) {
+        let Some(pending) = self.take_pending(id) else {
+            return;
+        };
+        let written = match self.storage.verify_lock(id, &pending.lock).await {
+            Ok(true) => {
+                self.storage
+                    .save_raw(id, &pending.data, &pending.lock)
+                    .await
+            }
+            Ok(false) => Err(eyre!("Lock invalid!")),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = written {
+            tracing::warn!("Dropping pending write of force unlocked {id} -> {e:?}");
+        }
+    }
+
+    /// Removes the pending item, unless it was saved again since `generation` was written.
+    fn remove_written(&self, key: &str, generation: u64) {
+        let mut pending = self.pending.lock().expect("can lock");
+        if pending.get(key).is_some_and(|p| p.generation <= generation) {
+            pending.remove(key);
+        }
+    }
+}
+
+/// Wraps any [Storage], acknowledges saves immediately, and writes them to the inner storage later.
+///
+/// Saves of the same item are coalesced, only the latest one is written.
+/// Flushes write saves made at [Priority::Foreground] before background ones.
+/// Pending items are written every flush interval, as soon as `max_pending` items are pending,
+/// on [StorageWriteBehind::flush] and before the item is unlocked.
+///
+/// Items are kept serialized and must survive a round trip through [StorageItem::serialize].
+/// Pending writes are lost if the wrapper is dropped, call [StorageWriteBehind::shutdown] first.
+#[derive(Debug)]
+pub struct StorageWriteBehind<ITEM: StorageItem, S: Storage<ITEM>>
+where
+    ITEM: Send,
+{
+    shared: Arc<Shared<ITEM, S>>,
+    flush_interval: Duration,
+    max_pending: usize,
+    flusher: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl<ITEM: StorageItem + Send + 'static, S: Storage<ITEM> + 'static> StorageWriteBehind<ITEM, S> {
+    pub fn new(storage: S) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                storage,
+                pending: Mutex::new(BTreeMap::new()),
+                writing: tokio::sync::Mutex::new(()),
+                item_type: PhantomData,
+            }),
+            flush_interval: DEFAULT_FLUSH_INTERVAL,
+            max_pending: DEFAULT_MAX_PENDING,
+            flusher: Mutex::new(None),
+        }
+    }
+
+    /// How often pending items are written, defaults to 5 seconds.
+    /// Must be set before the first save.
+    pub fn set_flush_interval(&mut self, flush_interval: Duration) -> Result<()> {
+        if self.flusher.get_mut().expect("can lock").is_some() {
+            return Err(eyre!("Flush interval must be set before the first save"));
+        }
+        self.flush_interval = flush_interval;
+
+        Ok(())
+    }
+
+    /// Pending items are written as soon as there are `max_pending`, defaults to 1000.
+    pub fn set_max_pending(&mut self, max_pending: usize) -> Result<()> {
+        if max_pending == 0 {
+            return Err(eyre!("Max pending must be at least 1"));
+        }
+        self.max_pending = max_pending;
+
+        Ok(())
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.shared.storage
+    }
+
+    /// Number of items with saves not yet written.
+    pub fn pending_count(&self) -> usize {
+        self.shared.pending.lock().expect("can lock").len()
+    }
+
+    /// Writes all pending items now.
+    pub async fn flush(&self) -> Result<()> {
+        self.shared.flush().await
+    }
+
+    /// Fails if there are pending items, see [Storage::shutdown].
+    pub fn into_inner(self) -> Result<S> {
+        let pending = self.pending_count();
+        if pending > 0 {
+            return Err(eyre!("{pending} pending writes, call shutdown first"));
+        }
+        if let Some(flusher) = self.flusher.lock().expect("can lock").take() {
+            flusher.abort();
+        }
+        Arc::try_unwrap(self.shared)
+            .map(|shared| shared.storage)
+            .map_err(|_| eyre!("Storage is still flushing"))
+    }
+
+    fn ensure_flusher(&self) {
+        let mut flusher = self.flusher.lock().expect("can lock");
+        if flusher.is_some() {
+            return;
+        }
+        let shared = Arc::downgrade(&self.shared);
+        let flush_interval = self.flush_interval;
+        *flusher = Some(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(flush_interval);
+            loop {
+                interval.tick().await;
+                let Some(shared) = shared.upgrade() else {
+                    break;
+                };
+                // errors are logged, and the items retried on the next tick
+                let _ = shared.flush().await;
+            }
+        }));
+    }
+
+    async fn save_data(&self, id: &ITEM::ID, data: Vec<u8>, lock: &StorageLock) -> Result<()> {
+        let key = id.to_string();
+        let known_lock = self
+            .shared
+            .pending
+            .lock()
+            .expect("can lock")
+            .get(&key)
+            .is_some_and(|p| p.lock == *lock);
+        if !known_lock && !self.shared.storage.verify_lock(id, lock).await? {
+            return Err(eyre!("Lock invalid!"));
+        }
+
+        let pending_count = {
+            let mut pending = self.shared.pending.lock().expect("can lock");
+            let previous = pending.get(&key);
+            let generation = previous.map_or(0, |p| p.generation + 1);
+            let priority =
+                previous.map_or(Priority::current(), |p| p.priority.max(Priority::current()));
+            pending.insert(
+                key,
+                PendingWrite {
+                    id: id.clone(),
+                    data,
+                    lock: lock.duplicate(),
+                    generation,
+                    priority,
+                },
+            );
+            pending.len()
+        };
+        self.ensure_flusher();
+        if pending_count >= self.max_pending {
+            self.shared.flush().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn stop_flusher(&self) {
+        let flusher = self.flusher.lock().expect("can lock").take();
+        if let Some(flusher) = flusher {
+            flusher.abort();
+            // only fails with the cancellation
+            let _ = flusher.await;
+        }
+    }
+}
+
+#[async_trait]
+impl<ITEM: StorageItem + Send + 'static, S: Storage<ITEM> + 'static> Storage<ITEM>
+    for StorageWriteBehind<ITEM, S>
+{
+    async fn ensure_storage_exists(&mut self) -> Result<()> {
+        self.stop_flusher().await;
+        let shared =
+            Arc::get_mut(&mut self.shared).ok_or_else(|| eyre!("Storage is still flushing"))?;
+        shared.storage.ensure_storage_exists().await
+    }
+
+    fn capabilities(&self) -> crate::Capabilities {
+        self.shared.storage.capabilities()
+    }
+
+    async fn create(&self) -> Result<ITEM::ID> {
+        self.shared.storage.create().await
+    }
+
+    async fn exists(&self, id: &ITEM::ID) -> Result<bool> {
+        if self.shared.pending_data(id).is_some() {
+            return Ok(true);
+        }
+        self.shared.storage.exists(id).await
+    }
+
+    async fn exists_with(&self, id: &ITEM::ID, options: &OpOptions) -> Result<bool> {
+        if self.shared.pending_data(id).is_some() {
+            return Ok(true);
+        }
+        self.shared.storage.exists_with(id, options).await
+    }
+
+    /// Returns the pending item, if there is one.
+    async fn load(&self, id: &ITEM::ID) -> Result<ITEM> {
+        match self.shared.pending_data(id) {
+            Some(data) => deserialize_item::<ITEM>(&data),
+            None => self.shared.storage.load(id).await,
+        }
+    }
+
+    /// Returns the pending item, if there is one, whatever the `options`.
+    async fn load_with(&self, id: &ITEM::ID, options: &OpOptions) -> Result<ITEM> {
+        match self.shared.pending_data(id) {
+            Some(data) => deserialize_item::<ITEM>(&data),
+            None => self.shared.storage.load_with(id, options).await,
+        }
+    }
+
+    async fn prefetch(&self, ids: &[ITEM::ID]) -> Result<()> {
+        self.shared.storage.prefetch(ids).await
+    }
+
+    /// Only verifies the lock with the inner storage on the first save while locked.
+    async fn save(&self, id: &ITEM::ID, item: &ITEM, lock: &StorageLock) -> Result<()> {
+        self.save_data(id, serialize_valid(id, item)?, lock).await
+    }
+
+    async fn load_raw(&self, id: &ITEM::ID) -> Result<Vec<u8>> {
+        match self.shared.pending_data(id) {
+            Some(data) => Ok(data),
+            None => self.shared.storage.load_raw(id).await,
+        }
+    }
+
+    async fn save_raw(&self, id: &ITEM::ID, data: &[u8], lock: &StorageLock) -> Result<()> {
+        self.save_data(id, data.to_vec(), lock).await
+    }
+
+    async fn lock(&self, id: &ITEM::ID, who: &str) -> Result<LockResult<ITEM>> {
+        self.shared.storage.lock(id, who).await
+    }
+
+    /// Items only have pending writes while locked, so the wrapped storage has the current data of all items it locks.
+    async fn lock_and_load_many(
+        &self,
+        ids: &[ITEM::ID],
+        who: &str,
+    ) -> Result<Vec<crate::LockManyOutcome<ITEM>>> {
+        self.shared.storage.lock_and_load_many(ids, who).await
+    }
+
+    /// Writes the pending item, if any, together with the unlock.
+    async fn unlock(&self, id: &ITEM::ID, lock: StorageLock) -> Result<()> {
+        let _writing = self.shared.writing.lock().await;
+        let Some(pending) = self.shared.take_pending(id) else {
+            return self.shared.storage.unlock(id, lock).await;
+        };
+        let result = match deserialize_item::<ITEM>(&pending.data) {
+            Ok(item) => self.shared.storage.save_and_unlock(id, &item, lock).await,
+            Err(e) => Err(e),
+        };
+        if result.is_err() {
+            // keep it for the next flush, unless saved again meanwhile
+            let key = id.to_string();
+            let mut all_pending = self.shared.pending.lock().expect("can lock");
+            all_pending.entry(key).or_insert(pending);
+        }
+
+        result
+    }
+
+    /// A pending item moves to the new lock and is written by the next flush as usual.
+    async fn handoff_lock(
+        &self,
+        id: &ITEM::ID,
+        lock: StorageLock,
+        new_who: &str,
+    ) -> Result<StorageLock> {
+        let _writing = self.shared.writing.lock().await;
+        let old_lock = lock.duplicate();
+        let new_lock = self.shared.storage.handoff_lock(id, lock, new_who).await?;
+        let mut all_pending = self.shared.pending.lock().expect("can lock");
+        if let Some(pending) = all_pending
+            .get_mut(&id.to_string())
+            .filter(|p| p.lock == old_lock)
+        {
+            pending.lock = new_lock.duplicate();
+        }
+
+        Ok(new_lock)
+    }
+
+    async fn put_blob(
+        &self,
+        id: &ITEM::ID,
+        name: &str,
+        data: &[u8],
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.shared.storage.put_blob(id, name, data, lock).await
+    }
+
+    async fn get_blob(&self, id: &ITEM::ID, name: &str) -> Result<Option<Vec<u8>>> {
+        self.shared.storage.get_blob(id, name).await
+    }
+
+    async fn list_blobs(&self, id: &ITEM::ID) -> Result<Vec<String>> {
+        self.shared.storage.list_blobs(id).await
+    }
+
+    async fn put_meta(&self, key: &str, value: &[u8]) -> Result<()> {
+        self.shared.storage.put_meta(key, value).await
+    }
+
+    async fn get_meta(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.shared.storage.get_meta(key).await
+    }
+
+    async fn add_alias(&self, alias: &ITEM::ID, canonical: &ITEM::ID) -> Result<()> {
+        self.shared.storage.add_alias(alias, canonical).await
+    }
+
+    async fn remove_alias(&self, alias: &ITEM::ID) -> Result<()> {
+        self.shared.storage.remove_alias(alias).await
+    }
+
+    async fn resolve_alias(&self, id: &ITEM::ID) -> Result<ITEM::ID> {
+        self.shared.storage.resolve_alias(id).await
+    }
+
+    async fn add_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.shared.storage.add_link(from, relation, to, lock).await
+    }
+
+    async fn remove_link(
+        &self,
+        from: &ITEM::ID,
+        relation: &str,
+        to: &ITEM::ID,
+        lock: &StorageLock,
+    ) -> Result<()> {
+        self.shared
+            .storage
+            .remove_link(from, relation, to, lock)
+            .await
+    }
+
+    async fn links_of(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        self.shared.storage.links_of(id, relation).await
+    }
+
+    async fn linked_from(&self, id: &ITEM::ID, relation: &str) -> Result<Vec<ITEM::ID>> {
+        self.shared.storage.linked_from(id, relation).await
+    }
+
+    /// Writes the pending item, if there is one and its lock still holds, before removing the lock.
+    async fn force_unlock(&self, id: &ITEM::ID) -> Result<Option<StorageLock>> {
+        let _writing = self.shared.writing.lock().await;
+        self.shared.write_pending_before_force_unlock(id).await;
+        self.shared.storage.force_unlock(id).await
+    }
+
+    /// Writes the pending item, if any, before removing the lock, like [StorageWriteBehind::force_unlock].
+    async fn force_unlock_if_held(
+        &self,
+        id: &ITEM::ID,
+        who: &str,
+        when: DateTime<Utc>,
+    ) -> Result<bool> {
+        let _writing = self.shared.writing.lock().await;
+        self.shared.write_pending_before_force_unlock(id).await;
+        self.shared
+            .storage
+            .force_unlock_if_held(id, who, when)
+            .await
+    }
+
+    async fn remove_orphaned_locks(&self, max_age: Duration) -> Result<usize> {
+        // pending items are saved, not orphaned
+        self.shared.flush().await?;
+        self.shared.storage.remove_orphaned_locks(max_age).await
+    }
+
+    async fn verify_lock(&self, id: &ITEM::ID, lock: &StorageLock) -> Result<bool> {
+        self.shared.storage.verify_lock(id, lock).await
+    }
+
+    async fn verify_lock_with(
+        &self,
+        id: &ITEM::ID,
+        lock: &StorageLock,
+        options: &OpOptions,
+    ) -> Result<bool> {
+        self.shared
+            .storage
+            .verify_lock_with(id, lock, options)
+            .await
+    }
+
+    async fn all_ids(&self) -> Result<Vec<ITEM::ID>> {
+        self.shared.storage.all_ids().await
+    }
+
+    async fn scan_ids(&self, start: Option<&str>, limit: Option<usize>) -> Result<Page<ITEM::ID>> {
+        self.shared.storage.scan_ids(start, limit).await
+    }
+
+    async fn scan_ids_with_prefix(
+        &self,
+        prefix: &str,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.shared
+            .storage
+            .scan_ids_with_prefix(prefix, start, limit)
+            .await
+    }
+
+    async fn scan_ids_filtered(
+        &self,
+        filter: &crate::IdFilter,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.shared
+            .storage
+            .scan_ids_filtered(filter, start, limit)
+            .await
+    }
+
+    async fn scan_ids_between(
+        &self,
+        from: &ITEM::ID,
+        to: &ITEM::ID,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<ITEM::ID>> {
+        self.shared
+            .storage
+            .scan_ids_between(from, to, start, limit)
+            .await
+    }
+
+    async fn list(
+        &self,
+        start: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Page<crate::ItemSummary<ITEM::ID>>> {
+        self.shared.storage.list(start, limit).await
+    }
+
+    /// The serialized size of the pending item, if there is one.
+    async fn item_size(&self, id: &ITEM::ID) -> Result<u64> {
+        match self.shared.pending_data(id) {
+            Some(data) => Ok(data.len() as u64),
+            None => self.shared.storage.item_size(id).await,
+        }
+    }
+
+    /// Pending items are not included.
+    async fn estimate_cost(&self) -> Result<crate::StorageCost> {
+        self.shared.storage.estimate_cost().await
+    }
+
+    /// Stops the background flush, writes all pending items, and then shuts down the wrapped storage.
+    /// Saving again restarts the background flush.
+    async fn shutdown(&self) -> Result<()> {
+        self.stop_flusher().await;
+        self.shared.flush().await?;
+        self.shared.storage.shutdown().await
+    }
+
+    async fn display_lock(&self, id: &ITEM::ID) -> Result<String> {
+        self.shared.storage.display_lock(id).await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_highest_seen_id(&self) -> Option<ITEM::ID> {
+        self.shared.storage.metadata_highest_seen_id().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_item_count(&self) -> Option<u64> {
+        self.shared.storage.metadata_item_count().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_total_bytes(&self) -> Option<u64> {
+        self.shared.storage.metadata_total_bytes().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_last_write(&self) -> Option<DateTime<Utc>> {
+        self.shared.storage.metadata_last_write().await
+    }
+
+    #[cfg(feature = "metadata")]
+    async fn metadata_lock_stats(&self) -> crate::LockStats {
+        self.shared.storage.metadata_lock_stats().await
+    }
+
+    /// Discards all pending items, pending items of new ids are not in the report.
+    #[cfg(feature = "wipe")]
+    async fn wipe(
+        &self,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        let _writing = self.shared.writing.lock().await;
+        let report = self.shared.storage.wipe(options, progress, cancel).await?;
+        if !options.is_dry_run() {
+            self.shared.pending.lock().expect("can lock").clear();
+        }
+
+        Ok(report)
+    }
+
+    /// Discards all pending items, like [Storage::wipe].
+    #[cfg(feature = "wipe")]
+    async fn wipe_matching(
+        &self,
+        filter: &crate::IdFilter,
+        options: &crate::WipeOptions,
+        progress: Option<&dyn crate::ProgressSink>,
+        cancel: Option<&tokio_util::sync::CancellationToken>,
+    ) -> Result<crate::WipeReport> {
+        let _writing = self.shared.writing.lock().await;
+        let report = self
+            .shared
+            .storage
+            .wipe_matching(filter, options, progress, cancel)
+            .await?;
+        if !options.is_dry_run() {
+            self.shared.pending.lock().expect("can lock").clear();
+        }
+
+        Ok(report)
+    }
+
+    /// Pending writes of deleted items are dropped.
+    #[cfg(feature = "wipe")]
+    async fn delete_many(
+        &self,
+        ids: &[ITEM::ID],
+        options: &crate::DeleteOptions,
+    ) -> Result<Vec<crate::DeleteOutcome>> {
+        let _writing = self.shared.writing.lock().await;
+        let outcomes = self.shared.storage.delete_many(ids, options).await?;
+        for (id, outcome) in ids.iter().zip(&outcomes) {
+            if *outcome == crate::DeleteOutcome::Deleted {
+                self.shared.take_pending(id);
+            }
+        }
+
+        Ok(outcomes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_item::TestItem;
+    use crate::MockResponse;
+    use crate::Storage;
+    use crate::StorageMock;
+    use crate::StorageOperation;
+    use crate::StorageWithMiddleware;
+    use crate::StorageWriteBehind;
+    use color_eyre::eyre::eyre;
+    use color_eyre::Result;
+    use std::time::Duration;
+
+    fn operations(storage: &StorageWriteBehind<TestItem, StorageMock<TestItem>>) -> Vec<String> {
+        storage
+            .storage()
+            .calls()
+            .iter()
+            .map(|c| c.operation.to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn it_coalesces_saves() -> Result<()> {
+        let storage = StorageWriteBehind::new(StorageMock::<TestItem>::default());
+        let id = String::from("1");
+
+        let (lock, _) = storage.lock(&id, "tester").await?.success()?;
+        for count in 1..=3 {
+            storage.save(&id, &TestItem { count }, &lock).await?;
+        }
+        assert_eq!(TestItem { count: 3 }, storage.load(&id).await?);
+        assert_eq!(vec!["lock", "verify_lock"], operations(&storage));
+
+        storage.flush().await?;
+        assert_eq!(0, storage.pending_count());
+        storage.save(&id, &TestItem { count: 4 }, &lock).await?;
+        storage.save(&id, &TestItem { count: 5 }, &lock).await?;
+        storage.unlock(&id, lock).await?;
+        storage.shutdown().await?;
+        assert_eq!(
+            vec![
+                "lock",
+                "verify_lock",
+                "save",
+                "verify_lock",
+                "save",
+                "unlock"
+            ],
+            operations(&storage)
+        );
+
+        let mock = storage.into_inner()?;
+        assert_eq!(6, mock.calls().len());
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn it_flushes_on_interval_and_size() -> Result<()> {
+        let mut storage = StorageWriteBehind::new(StorageMock::<TestItem>::default());
+        storage.set_flush_interval(Duration::from_secs(10))?;
+        storage.set_max_pending(3)?;
+
+        let mut locks = Vec::new();
+        for id in ["a", "b"] {
+            let id = String::from(id);
+            let (lock, _) = storage.lock(&id, "tester").await?.success()?;
+            storage.save(&id, &TestItem { count: 1 }, &lock).await?;
+            locks.push(lock);
+        }
+        assert_eq!(2, storage.pending_count());
+        tokio::time::sleep(Duration::from_secs(11)).await;
+        assert_eq!(0, storage.pending_count());
+        assert!(storage.set_flush_interval(Duration::from_secs(1)).is_err());
+
+        for (id, lock) in ["a", "b"].iter().zip(locks.iter()) {
+            storage
+                .save(&id.to_string(), &TestItem { count: 2 }, lock)
+                .await?;
+        }
+        let id = String::from("c");
+        let (lock, _) = storage.lock(&id, "tester").await?.success()?;
+        storage.save(&id, &TestItem { count: 2 }, &lock).await?;
+        assert_eq!(0, storage.pending_count());
+        let saves = storage
+            .storage()
+            .calls()
+            .iter()
+            .filter(|c| c.operation == StorageOperation::Save)
+            .count();
+        assert_eq!(5, saves);
+
+        Ok(())
+    }
+    #[tokio::test]
+    async fn it_flushes_on_shutdown_of_the_stack() -> Result<()> {
+        let storage =
+            StorageWithMiddleware::new(StorageWriteBehind::new(StorageMock::<TestItem>::default()));
+        let id = String::from("1");
+        let (lock, _) = storage.lock(&id, "tester").await?.success()?;
+        storage.save(&id, &TestItem { count: 1 }, &lock).await?;
+        assert_eq!(1, storage.storage().pending_count());
+
+        storage.shutdown().await?;
+        assert_eq!(0, storage.storage().pending_count());
+        assert!(operations(storage.storage()).contains(&String::from("save")));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_fails_flushes_dropping_writes_of_lost_locks() -> Result<()> {
+        let storage = StorageWriteBehind::new(StorageMock::<TestItem>::default());
+        let id = String::from("1");
+        let (lock, _) = storage.lock(&id, "tester").await?.success()?;
+        storage.save(&id, &TestItem { count: 1 }, &lock).await?;
+
+        let mock = storage.storage();
+        mock.script(
+            StorageOperation::Save,
+            MockResponse::Err(eyre!("Lock invalid!")),
+        );
+        mock.script(StorageOperation::VerifyLock, MockResponse::Bool(false));
+        assert!(storage.flush().await.is_err());
+        assert_eq!(0, storage.pending_count());
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn it_keeps_newer_saves_while_dropping_writes_of_lost_locks() -> Result<()> {
+        let storage = StorageWriteBehind::new(StorageMock::<TestItem>::default());
+        let id = String::from("1");
+        let (lock, _) = storage.lock(&id, "tester").await?.success()?;
+        storage.save(&id, &TestItem { count: 1 }, &lock).await?;
+
+        let mock = storage.storage();
+        mock.script_delayed(
+            StorageOperation::Save,
+            MockResponse::Err(eyre!("Lock invalid!")),
+            Some(Duration::from_millis(50)),
+        );
+        // the newer save verifies its lock first, the failing flush afterwards
+        mock.script(StorageOperation::VerifyLock, MockResponse::Bool(true));
+        mock.script(StorageOperation::VerifyLock, MockResponse::Bool(false));
+        let newer_save = async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            let (lock, _) = storage.lock(&id, "tester").await?.success()?;
+            storage.save(&id, &TestItem { count: 2 }, &lock).await
+        };
+        let (flushed, saved) = tokio::join!(storage.flush(), newer_save);
+        assert!(flushed.is_err());
+        saved?;
+        assert_eq!(1, storage.pending_count());
+        assert_eq!(TestItem { count: 2 }, storage.load(&id).await?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_drops_pending_writes_of_lost_locks_on_force_unlock() -> Result<()> {
+        let storage = StorageWriteBehind::new(StorageMock::<TestItem>::default());
+        let id = String::from("1");
+        let (lock, _) = storage.lock(&id, "tester").await?.success()?;
+        storage.save(&id, &TestItem { count: 1 }, &lock).await?;
+
+        storage
+            .storage()
+            .script(StorageOperation::VerifyLock, MockResponse::Bool(false));
+        storage.force_unlock(&id).await?;
+        assert_eq!(0, storage.pending_count());
+        assert_eq!(
+            vec!["lock", "verify_lock", "verify_lock", "force_unlock"],
+            operations(&storage)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_zero_max_pending() {
+        let mut storage = StorageWriteBehind::new(StorageMock::<TestItem>::default());
+        assert!(storage.set_max_pending(0).is_err());
+        assert!(storage.set_max_pending(1).is_ok());
+    }
+}
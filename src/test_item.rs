@@ -0,0 +1,59 @@
+//! The items shared by the tests.
+
+use crate::StorageId;
+use crate::StorageItem;
+use color_eyre::Result;
+use serde::Deserialize;
+use serde::Serialize;
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct TestItem {
+    pub(crate) count: u32,
+}
+
+impl StorageItem for TestItem {
+    type ID = String;
+
+    fn serialize(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(&self)?)
+    }
+    fn deserialize(data: &[u8]) -> Result<Self> {
+        Ok(serde_json::from_slice(data)?)
+    }
+}
+
+/// Only alphanumeric ids are valid, but constructing one doesn't check
+#[derive(Default, Debug, Clone, PartialEq, PartialOrd)]
+pub(crate) struct StrictId(String);
+
+impl std::fmt::Display for StrictId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl StorageId for StrictId {
+    fn from_string(id: &str) -> Result<Self> {
+        Ok(Self(id.to_string()))
+    }
+    fn generate_new(_previous: Option<&Self>) -> Self {
+        Self(nanoid::nanoid!(21, &nanoid::alphabet::SAFE[2..]))
+    }
+    fn is_valid_format(id: &str) -> bool {
+        !id.is_empty() && id.bytes().all(|b| b.is_ascii_alphanumeric())
+    }
+}
+
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub(crate) struct StrictItem {}
+
+impl StorageItem for StrictItem {
+    type ID = StrictId;
+
+    fn serialize(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(&self)?)
+    }
+    fn deserialize(data: &[u8]) -> Result<Self> {
+        Ok(serde_json::from_slice(data)?)
+    }
+}
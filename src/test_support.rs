@@ -0,0 +1,351 @@
+//! Checking backends against the same expectations, and DynamoDB Local or LocalStack to check DynamoDB against.
+
+use crate::LockResult;
+use crate::Storage;
+use crate::StorageDynamoDb;
+use crate::StorageError;
+use crate::StorageItem;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use color_eyre::eyre::WrapErr;
+use std::collections::HashSet;
+use std::net::TcpStream;
+use std::net::ToSocketAddrs;
+use std::process::Command;
+use std::time::Duration;
+
+/// Overrides the endpoints [DynamoDbLocal::detect] probes, e.g. `http://dynamodb:8000` in CI.
+pub const DYNAMODB_ENDPOINT_ENV: &str = "OML_STORAGE_DYNAMODB_ENDPOINT";
+/// The default ports of DynamoDB Local and LocalStack.
+const DEFAULT_ENDPOINTS: &[&str] = &["http://localhost:8000", "http://localhost:4566"];
+const DOCKER_IMAGE: &str = "amazon/dynamodb-local";
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(250);
+const START_TIMEOUT: Duration = Duration::from_secs(30);
+/// Used as lock holder.
+const WHO: &str = "conformance";
+const OTHER_WHO: &str = "conformance-other";
+
+/// A local DynamoDB, e.g. DynamoDB Local or LocalStack, to run tests against.
+///
+/// Every test should use its own [DynamoDbLocal::table], so tests can run in parallel.
+/// Containers started via [DynamoDbLocal::start] are removed on drop.
+#[derive(Debug)]
+pub struct DynamoDbLocal {
+    endpoint: String,
+    container: Option<String>,
+}
+
+/// Whether something accepts connections at the host and port of `endpoint`.
+async fn is_listening(endpoint: &str) -> bool {
+    let address = endpoint
+        .trim_start_matches("http://")
+        .trim_start_matches("https://")
+        .split('/')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    tokio::task::spawn_blocking(move || {
+        address
+            .to_socket_addrs()
+            .into_iter()
+            .flatten()
+            .any(|a| TcpStream::connect_timeout(&a, CONNECT_TIMEOUT).is_ok())
+    })
+    .await
+    .unwrap_or(false)
+}
+
+impl DynamoDbLocal {
+    /// The endpoint from [DYNAMODB_ENDPOINT_ENV], or the default port of DynamoDB Local or LocalStack,
+    /// that accepts connections, `None` if nothing runs, e.g. to skip tests.
+    pub async fn detect() -> Option<Self> {
+        if let Ok(endpoint) = std::env::var(DYNAMODB_ENDPOINT_ENV) {
+            return Some(Self {
+                endpoint,
+                container: None,
+            });
+        }
+        for endpoint in DEFAULT_ENDPOINTS {
+            if is_listening(endpoint).await {
+                return Some(Self {
+                    endpoint: endpoint.to_string(),
+                    container: None,
+                });
+            }
+        }
+
+        None
+    }
+
+    /// Starts DynamoDB Local via `docker` on `port` and waits until it accepts connections.
+    pub async fn start(port: u16) -> Result<Self> {
+        let output = Command::new("docker")
+            .args(["run", "--detach", "--rm", "--publish"])
+            .arg(format!("{port}:8000"))
+            .arg(DOCKER_IMAGE)
+            .output()
+            .wrap_err("Can't run docker")?;
+        if !output.status.success() {
+            return Err(eyre!(
+                "Can't start {DOCKER_IMAGE} -> {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        let local = Self {
+            endpoint: format!("http://localhost:{port}"),
+            container: Some(String::from_utf8_lossy(&output.stdout).trim().to_string()),
+        };
+        let started = tokio::time::Instant::now();
+        while !is_listening(&local.endpoint).await {
+            if started.elapsed() > START_TIMEOUT {
+                return Err(eyre!(
+                    "{DOCKER_IMAGE} didn't start within {START_TIMEOUT:?}"
+                ));
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        Ok(local)
+    }
+
+    /// [DynamoDbLocal::detect] or [DynamoDbLocal::start] on `port`.
+    pub async fn detect_or_start(port: u16) -> Result<Self> {
+        match Self::detect().await {
+            Some(local) => Ok(local),
+            None => Self::start(port).await,
+        }
+    }
+
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    /// A storage on a new table with a random name, with dummy credentials and its table created.
+    pub async fn table<ITEM: StorageItem + Send>(&self) -> Result<TestTable<ITEM>> {
+        let table_name = format!("oml-storage-test-{}", nanoid::nanoid!());
+        let mut storage = StorageDynamoDb::new(&table_name).await;
+        storage.set_endpoint_url(&self.endpoint)?;
+        storage.set_region("us-east-1")?;
+        storage.set_credentials("test", "test", None)?;
+        storage.ensure_storage_exists().await?;
+
+        Ok(TestTable { storage })
+    }
+}
+
+impl Drop for DynamoDbLocal {
+    fn drop(&mut self) {
+        if let Some(container) = &self.container {
+            if let Err(e) = Command::new("docker")
+                .args(["rm", "--force", container])
+                .output()
+            {
+                tracing::warn!("Can't remove container {container} -> {e:?}");
+            }
+        }
+    }
+}
+
+/// A [StorageDynamoDb] on its own table, see [DynamoDbLocal::table].
+///
+/// Note: Call [TestTable::delete] when done, tables are kept otherwise.
+#[derive(Debug)]
+pub struct TestTable<ITEM: StorageItem> {
+    storage: StorageDynamoDb<ITEM>,
+}
+
+impl<ITEM: StorageItem + Send> TestTable<ITEM> {
+    pub fn storage(&self) -> &StorageDynamoDb<ITEM> {
+        &self.storage
+    }
+
+    pub fn storage_mut(&mut self) -> &mut StorageDynamoDb<ITEM> {
+        &mut self.storage
+    }
+
+    /// Deletes the table.
+    pub async fn delete(self) -> Result<()> {
+        let client = self.storage.client().await?;
+        client
+            .delete_table()
+            .table_name(self.storage.table_name())
+            .send()
+            .await
+            .map_err(|e| eyre!("Can't delete table {} -> {e:?}", self.storage.table_name()))?;
+
+        Ok(())
+    }
+}
+
+/// Creates a new item, with the default item saved.
+async fn create_saved<ITEM, S>(storage: &S) -> Result<ITEM::ID>
+where
+    ITEM: StorageItem + Send,
+    S: Storage<ITEM> + ?Sized,
+{
+    let id = storage.create().await?;
+    let (lock, _) = storage.lock(&id, WHO).await?.success()?;
+    storage.save_and_unlock(&id, &ITEM::default(), lock).await?;
+
+    Ok(id)
+}
+
+fn ensure(check: bool, what: &str) -> Result<()> {
+    if !check {
+        return Err(eyre!("Expected {what}"));
+    }
+
+    Ok(())
+}
+
+/// Checks the behaviour every backend shares, on an existing, e.g. empty, storage.
+///
+/// Creates and keeps a few items with default content, and fails with the first unmet expectation.
+pub async fn run_conformance<ITEM, S>(storage: &S) -> Result<()>
+where
+    ITEM: StorageItem + Send,
+    S: Storage<ITEM> + ?Sized,
+{
+    let default_data = ITEM::default().serialize()?;
+
+    // save, and load
+    let id = create_saved(storage).await.wrap_err("save")?;
+    ensure(storage.exists(&id).await?, "saved item to exist")?;
+    let loaded = storage.load(&id).await.wrap_err("load")?;
+    ensure(
+        loaded.serialize()? == default_data,
+        "loaded item to match the saved one",
+    )?;
+
+    // missing items
+    let missing = storage.create().await?;
+    ensure(!storage.exists(&missing).await?, "new id to not exist")?;
+    let e = storage
+        .load(&missing)
+        .await
+        .err()
+        .ok_or_else(|| eyre!("Expected load of missing item to fail"))?;
+    ensure(
+        matches!(e.downcast_ref(), Some(StorageError::NotFound { .. })),
+        "StorageError::NotFound for missing item",
+    )?;
+
+    // locks
+    let (lock, _) = storage.lock(&id, WHO).await?.success().wrap_err("lock")?;
+    ensure(
+        storage.verify_lock(&id, &lock).await?,
+        "held lock to verify",
+    )?;
+    match storage.lock(&id, OTHER_WHO).await? {
+        LockResult::AlreadyLocked { who, .. } => ensure(who == WHO, "lock holder to be reported")?,
+        LockResult::Success { .. } => return Err(eyre!("Expected second lock to fail")),
+    }
+    let lock = storage
+        .handoff_lock(&id, lock, OTHER_WHO)
+        .await
+        .wrap_err("handoff_lock")?;
+    ensure(
+        lock.who() == OTHER_WHO,
+        "handed off lock to have the new holder",
+    )?;
+    storage.unlock(&id, lock).await.wrap_err("unlock")?;
+    let (lock, _) = storage.lock(&id, WHO).await?.success().wrap_err("relock")?;
+    let forced = storage.force_unlock(&id).await.wrap_err("force_unlock")?;
+    ensure(
+        forced.is_some_and(|forced| forced == lock),
+        "force_unlock to return the held lock",
+    )?;
+    ensure(
+        !storage.verify_lock(&id, &lock).await?,
+        "released lock to not verify",
+    )?;
+    ensure(
+        storage.save(&id, &ITEM::default(), &lock).await.is_err(),
+        "save with a released lock to fail",
+    )?;
+
+    // scans, and batches
+    let mut created = HashSet::from([id.to_string()]);
+    for _ in 0..4 {
+        created.insert(create_saved(storage).await?.to_string());
+    }
+    let mut scanned = HashSet::new();
+    let mut cursor = None;
+    loop {
+        let page = storage
+            .scan_ids(cursor.as_deref(), Some(2))
+            .await
+            .wrap_err("scan_ids")?;
+        ensure(page.items.len() <= 2, "pages to respect the limit")?;
+        scanned.extend(page.items.iter().map(|id| id.to_string()));
+        cursor = page.cursor;
+        if cursor.is_none() {
+            break;
+        }
+    }
+    ensure(created.is_subset(&scanned), "scans to find all items")?;
+    let loaded = storage
+        .load_many(&[id.clone(), missing.clone()])
+        .await
+        .wrap_err("load_many")?;
+    ensure(
+        matches!(loaded.as_slice(), [Some(_), None]),
+        "load_many to keep the order, with None for missing items",
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::run_conformance;
+    use super::DynamoDbLocal;
+    use crate::Storage;
+    use crate::StorageDisk;
+    use crate::StorageDiskPacked;
+    use crate::StorageItem;
+    use crate::StorageMemory;
+    use color_eyre::Result;
+    use std::env;
+    use std::path::Path;
+
+    #[derive(Default, Debug)]
+    struct TestItem;
+
+    impl StorageItem for TestItem {
+        type ID = String;
+
+        fn serialize(&self) -> Result<Vec<u8>> {
+            Ok(b"{}".to_vec())
+        }
+        fn deserialize(_data: &[u8]) -> Result<Self> {
+            Ok(Self)
+        }
+    }
+
+    #[tokio::test]
+    async fn it_runs_the_conformance_suite() -> Result<()> {
+        run_conformance(&StorageMemory::<TestItem>::default()).await?;
+
+        let mut path = env::current_dir()?;
+        path.push("data");
+        path.push("test_conformance");
+        let mut disk = StorageDisk::<TestItem>::new(&path, Path::new("test_item")).await;
+        disk.ensure_storage_exists().await?;
+        run_conformance(&disk).await?;
+
+        let mut packed = StorageDiskPacked::<TestItem>::new(&path.join("packed.log")).await;
+        packed.ensure_storage_exists().await?;
+        run_conformance(&packed).await?;
+
+        // only with DynamoDB Local, or LocalStack, running
+        let Some(local) = DynamoDbLocal::detect().await else {
+            return Ok(());
+        };
+        let table = local.table::<TestItem>().await?;
+        let result = run_conformance(table.storage()).await;
+        table.delete().await?;
+
+        result
+    }
+}
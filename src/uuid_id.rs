@@ -0,0 +1,106 @@
+use crate::StorageId;
+use chrono::DateTime;
+use chrono::Utc;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use uuid::Uuid;
+
+/// A UUID based id of the given version, stored in its hyphenated form.
+///
+/// Use [UuidV4Id] for random ids or [UuidV7Id] for time-ordered ids.
+/// Parsing only accepts UUIDs of the matching version.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UuidId<const VERSION: usize>(Uuid);
+
+/// Random UUIDs
+pub type UuidV4Id = UuidId<4>;
+/// Time-ordered UUIDs, new ids sort after older ones
+pub type UuidV7Id = UuidId<7>;
+
+impl<const VERSION: usize> UuidId<VERSION> {
+    pub fn uuid(&self) -> &Uuid {
+        &self.0
+    }
+}
+
+impl UuidV7Id {
+    /// The lowest id generated at `time`, e.g. as bound for [crate::Storage::scan_ids_between].
+    pub fn first_at(time: DateTime<Utc>) -> Self {
+        let ms = time.timestamp_millis().max(0) as u128;
+        // 48 bits milliseconds, version 7, variant 0b10, all random bits zero
+        Self(Uuid::from_u128(ms << 80 | 0x7 << 76 | 0b10 << 62))
+    }
+}
+
+impl<const VERSION: usize> TryFrom<Uuid> for UuidId<VERSION> {
+    type Error = color_eyre::eyre::Report;
+
+    fn try_from(uuid: Uuid) -> Result<Self> {
+        if uuid.get_version_num() != VERSION {
+            return Err(eyre!(
+                "Expected UUID version {VERSION}, got {}",
+                uuid.get_version_num()
+            ));
+        }
+
+        Ok(Self(uuid))
+    }
+}
+
+impl<const VERSION: usize> std::fmt::Display for UuidId<VERSION> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.hyphenated().fmt(f)
+    }
+}
+
+impl<const VERSION: usize> StorageId for UuidId<VERSION> {
+    fn from_string(id: &str) -> Result<Self> {
+        let uuid = Uuid::try_parse(id).map_err(|e| eyre!("Invalid UUID {id} -> {e}"))?;
+        Self::try_from(uuid)
+    }
+
+    fn generate_new(_previous: Option<&Self>) -> Self {
+        const {
+            assert!(
+                VERSION == 4 || VERSION == 7,
+                "Only UUID v4 and v7 are supported"
+            );
+        }
+        match VERSION {
+            4 => Self(Uuid::new_v4()),
+            _ => Self(Uuid::now_v7()),
+        }
+    }
+
+    fn is_valid_format(id: &str) -> bool {
+        Self::from_string(id).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::StorageId;
+    use crate::UuidV4Id;
+    use crate::UuidV7Id;
+
+    #[test]
+    fn it_generates_and_parses_uuid_ids() -> color_eyre::Result<()> {
+        let v4 = UuidV4Id::generate_new(None);
+        assert_eq!(v4, UuidV4Id::from_string(&v4.to_string())?);
+        assert!(UuidV4Id::is_valid_format(&v4.to_string()));
+        assert!(!UuidV7Id::is_valid_format(&v4.to_string()));
+        assert!(!UuidV4Id::is_valid_format("not-a-uuid"));
+
+        let first = UuidV7Id::generate_new(None);
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let second = UuidV7Id::generate_new(Some(&first));
+        assert!(first < second);
+        assert!(first.to_string() < second.to_string());
+        assert_eq!(36, second.to_string().len());
+        let first_at = UuidV7Id::first_at(chrono::Utc::now() - chrono::Duration::minutes(1));
+        assert!(UuidV7Id::is_valid_format(&first_at.to_string()));
+        assert!(first_at < first);
+
+        Ok(())
+    }
+}
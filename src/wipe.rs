@@ -0,0 +1,114 @@
+use crate::StorageLock;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use serde::Deserialize;
+use serde::Serialize;
+
+/// The confirmation [WipeOptions::Delete] needs.
+pub const WIPE_CONFIRMATION: &str = "Yes, I know what I am doing!";
+
+/// How many ids a [WipeReport] keeps as examples
+const SAMPLE_SIZE: usize = 10;
+
+/// How [crate::Storage::wipe] runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WipeOptions {
+    /// Only reports what would be removed, no confirmation needed
+    DryRun,
+    /// Removes everything, `confirmation` must be [WIPE_CONFIRMATION]
+    Delete { confirmation: String },
+}
+
+impl WipeOptions {
+    pub fn delete(confirmation: &str) -> Self {
+        WipeOptions::Delete {
+            confirmation: confirmation.to_string(),
+        }
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        matches!(self, WipeOptions::DryRun)
+    }
+
+    /// Fails unless this is a dry run or the confirmation is correct.
+    pub(crate) fn ensure_confirmed(&self) -> Result<()> {
+        match self {
+            WipeOptions::DryRun => Ok(()),
+            WipeOptions::Delete { confirmation } if confirmation == WIPE_CONFIRMATION => Ok(()),
+            WipeOptions::Delete { .. } => {
+                tracing::error!("Please confirm you know what you are doing");
+                Err(eyre!("Unconfirmed wipe attempt"))
+            }
+        }
+    }
+}
+
+/// What [crate::Storage::wipe] removed or would have removed for [WipeOptions::DryRun].
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WipeReport {
+    pub dry_run: bool,
+    /// Items only, aliases and blobs are removed with them, but not counted
+    pub count: usize,
+    /// The first few ids, to sanity check what a wipe hits
+    pub sample_ids: Vec<String>,
+}
+
+impl WipeReport {
+    pub(crate) fn new(options: &WipeOptions) -> Self {
+        Self {
+            dry_run: options.is_dry_run(),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn record(&mut self, id: &impl ToString) {
+        self.count += 1;
+        if self.sample_ids.len() < SAMPLE_SIZE {
+            self.sample_ids.push(id.to_string());
+        }
+    }
+}
+
+/// How [crate::Storage::delete_many] runs.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DeleteOptions {
+    force: bool,
+}
+
+impl DeleteOptions {
+    /// Deletes locked items too, instead of reporting them as [DeleteOutcome::Locked].
+    pub fn with_force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    pub fn force(&self) -> bool {
+        self.force
+    }
+
+    /// The outcome for an item held by `lock`, `None` if the item can be deleted.
+    pub(crate) fn blocked_by(&self, lock: Option<&StorageLock>) -> Option<DeleteOutcome> {
+        match lock {
+            Some(lock) if !self.force => Some(DeleteOutcome::Locked {
+                who: lock.who().to_string(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// What [crate::Storage::delete_many] did with one of the ids.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeleteOutcome {
+    Deleted,
+    /// Nothing was saved for the id, a lock without data is removed when forced
+    NotFound,
+    /// The item was kept, because `who` holds its lock
+    Locked {
+        who: String,
+    },
+    /// The backend failed, the item might have been deleted anyway
+    Failed {
+        message: String,
+    },
+}